@@ -9,17 +9,25 @@ import os
 import platform
 from importlib import reload
 import ctypes
+import struct
 import bmesh
 import mathutils
 
 # workaround for the "ImportError: attempted relative import with no known parent package" problem:
 DEV_MODE = False  # Set this to False for distribution
 HALLR_LIBRARY = None
+# the directory of the plugin libraries, next to this module
+PLUGIN_DIRECTORY = "plugins"
+# the loaded plugin libraries, kept referenced for the lifetime of the addon
+HALLR_PLUGINS = []
 
 
 class HallrException(Exception):
-    def __init__(self, message):
+    def __init__(self, message, code=None, detail=None):
         self.message = str(message)
+        # The numeric HallrErrorCode and the bare error message, when the error came from rust
+        self.code = code
+        self.detail = detail
 
 
 class Vector3(ctypes.Structure):
@@ -43,9 +51,81 @@ class GeometryOutput(ctypes.Structure):
                 ("matrices_count", ctypes.c_size_t)]
 
 
+class AttributeOutput(ctypes.Structure):
+    _fields_ = [("data", ctypes.POINTER(ctypes.c_uint8)),
+                ("size", ctypes.c_size_t)]
+
+
 class ProcessResult(ctypes.Structure):
     _fields_ = [("geometry", GeometryOutput),
-                ("map", StringMap)]
+                ("map", StringMap),
+                ("attributes", AttributeOutput)]
+
+
+class Vector3d(ctypes.Structure):
+    _fields_ = [("x", ctypes.c_double),
+                ("y", ctypes.c_double),
+                ("z", ctypes.c_double)]
+
+
+class GeometryOutputF64(ctypes.Structure):
+    _fields_ = [("vertices", ctypes.POINTER(Vector3d)),
+                ("vertex_count", ctypes.c_size_t),
+                ("indices", ctypes.POINTER(ctypes.c_size_t)),
+                ("indices_count", ctypes.c_size_t),
+                ("matrices", ctypes.POINTER(ctypes.c_double)),
+                ("matrices_count", ctypes.c_size_t)]
+
+
+class ProcessResultF64(ctypes.Structure):
+    _fields_ = [("geometry", GeometryOutputF64),
+                ("map", StringMap),
+                ("attributes", AttributeOutput)]
+
+
+# Models with coordinates larger than this are sent in double precision, the rust side then moves
+# them close to the origin before they are converted to single precision.
+DOUBLE_PRECISION_THRESHOLD = 10000.0
+# Meshes with more vertices than this are sent through a geometry stream, in chunks of
+# STREAM_CHUNK_SIZE vertices and indices, so that the whole mesh is never copied into one ctypes array.
+STREAM_THRESHOLD = 1000000
+STREAM_CHUNK_SIZE = 100000
+
+
+def encode_attributes(attributes):
+    """Pack a dict of named float lists into the binary attribute blob understood by rust"""
+    if not attributes:
+        return b""
+    blob = bytearray(b"HATR")
+    blob += struct.pack("<II", 1, len(attributes))
+    for name, values in attributes.items():
+        encoded_name = name.encode('utf-8')
+        blob += struct.pack("<I", len(encoded_name)) + encoded_name
+        blob += struct.pack(f"<I{len(values)}f", len(values), *values)
+    return bytes(blob)
+
+
+def decode_attributes(blob):
+    """Unpack the binary attribute blob returned by rust into a dict of named float lists"""
+    attributes = {}
+    if not blob:
+        return attributes
+    if blob[0:4] != b"HATR":
+        raise HallrException("Received an invalid attribute blob")
+    version, count = struct.unpack_from("<II", blob, 4)
+    if version != 1:
+        raise HallrException(f"Received an attribute blob of unsupported version: {version}")
+    position = 12
+    for _ in range(count):
+        (name_length,) = struct.unpack_from("<I", blob, position)
+        position += 4
+        name = blob[position:position + name_length].decode('utf-8')
+        position += name_length
+        (value_count,) = struct.unpack_from("<I", blob, position)
+        position += 4
+        attributes[name] = list(struct.unpack_from(f"<{value_count}f", blob, position))
+        position += 4 * value_count
+    return attributes
 
 
 def load_latest_dylib(prefix="libhallr_"):
@@ -88,16 +168,71 @@ def load_latest_dylib(prefix="libhallr_"):
     rust_lib.process_geometry.argtypes = [ctypes.POINTER(Vector3), ctypes.c_size_t,
                                           ctypes.POINTER(ctypes.c_size_t), ctypes.c_size_t,
                                           ctypes.POINTER(ctypes.c_float), ctypes.c_size_t,
-                                          ctypes.POINTER(StringMap)]
+                                          ctypes.POINTER(StringMap),
+                                          ctypes.c_char_p, ctypes.c_size_t]
 
     rust_lib.process_geometry.restype = ProcessResult
 
     rust_lib.free_process_results.argtypes = [ctypes.POINTER(ProcessResult)]
     rust_lib.free_process_results.restype = None
+
+    rust_lib.process_geometry_f64.argtypes = [ctypes.POINTER(Vector3d), ctypes.c_size_t,
+                                              ctypes.POINTER(ctypes.c_size_t), ctypes.c_size_t,
+                                              ctypes.POINTER(ctypes.c_double), ctypes.c_size_t,
+                                              ctypes.POINTER(StringMap),
+                                              ctypes.c_char_p, ctypes.c_size_t]
+    rust_lib.process_geometry_f64.restype = ProcessResultF64
+
+    rust_lib.free_process_results_f64.argtypes = [ctypes.POINTER(ProcessResultF64)]
+    rust_lib.free_process_results_f64.restype = None
+
+    rust_lib.begin_geometry_stream.argtypes = [ctypes.c_size_t, ctypes.c_size_t]
+    rust_lib.begin_geometry_stream.restype = ctypes.c_uint64
+    rust_lib.push_vertices.argtypes = [ctypes.c_uint64, ctypes.POINTER(Vector3), ctypes.c_size_t]
+    rust_lib.push_vertices.restype = ctypes.c_int32
+    rust_lib.push_indices.argtypes = [ctypes.c_uint64, ctypes.POINTER(ctypes.c_size_t), ctypes.c_size_t]
+    rust_lib.push_indices.restype = ctypes.c_int32
+    rust_lib.finish_and_process.argtypes = [ctypes.c_uint64,
+                                            ctypes.POINTER(ctypes.c_float), ctypes.c_size_t,
+                                            ctypes.POINTER(StringMap),
+                                            ctypes.c_char_p, ctypes.c_size_t]
+    rust_lib.finish_and_process.restype = ProcessResult
+    rust_lib.abort_geometry_stream.argtypes = [ctypes.c_uint64]
+    rust_lib.abort_geometry_stream.restype = ctypes.c_int32
+
+    rust_lib.hallr_list_commands.argtypes = []
+    rust_lib.hallr_list_commands.restype = StringMap
+    rust_lib.free_string_map.argtypes = [ctypes.POINTER(StringMap)]
+    rust_lib.free_string_map.restype = None
+    load_plugins(rust_lib)
     HALLR_LIBRARY = rust_lib
     return rust_lib
 
 
+def load_plugins(rust_lib):
+    """Loads every shared library of the plugins directory exporting hallr_register(register_fn), and calls it
+    with a pointer to hallr_register_command() of the rust library"""
+    directory = os.path.join(os.path.dirname(__file__), PLUGIN_DIRECTORY)
+    if not os.path.isdir(directory):
+        return
+    register_fn = ctypes.cast(rust_lib.hallr_register_command, ctypes.c_void_p)
+    for file_name in sorted(os.listdir(directory)):
+        if not file_name.endswith((".so", ".dylib", ".dll")):
+            continue
+        path = os.path.join(directory, file_name)
+        try:
+            plugin = ctypes.cdll.LoadLibrary(path)
+            register = plugin.hallr_register
+        except (OSError, AttributeError) as e:
+            print(f"Could not load the hallr plugin {path}: {e}")
+            continue
+        register.argtypes = [ctypes.c_void_p]
+        register.restype = ctypes.c_int32
+        if register(register_fn) != 0:
+            print(f"The hallr plugin {path} could not register its commands")
+        HALLR_PLUGINS.append(plugin)
+
+
 def ctypes_close_library(lib):
     if DEV_MODE:
         dlclose_func = ctypes.CDLL(None).dlclose
@@ -250,11 +385,10 @@ def handle_windows_line_modify_active_object(vertices, indices):
     active_obj.data.update()
 
 
-def unpack_model(options, raw_indices):
-    """Convert the received data into blender mesh edges, faces and world transform"""
+def unpack_indices(mesh_format, raw_indices):
+    """Convert the received indices into blender mesh edges and faces"""
     rv_edges = []
     rv_faces = []
-    mesh_format = options.get("mesh.format", None)
     if mesh_format == "line_windows":
         # Convert the indices to Blender's edge format
         # This mode assumes that the line is in the ".window(2)" format,
@@ -267,8 +401,35 @@ def unpack_model(options, raw_indices):
     elif mesh_format == "triangulated":
         # Assuming indices are [0, 1, 2, 2, 3, 4, ...], where each set of 3 is a triangle
         rv_faces = [tuple(raw_indices[i:i + 3]) for i in range(0, len(raw_indices), 3)]
+    elif mesh_format == "ngons":
+        # Each face is prefixed with its vertex count, i.e. [3, a, b, c, 4, d, e, f, g, ...]
+        i = 0
+        while i < len(raw_indices):
+            size = raw_indices[i]
+            rv_faces.append(tuple(raw_indices[i + 1:i + 1 + size]))
+            i += size + 1
     else:
-        raise HallrException("Unsupported mesh_format:" + mesh_format)
+        raise HallrException("Unsupported mesh_format:" + str(mesh_format))
+    return rv_edges, rv_faces
+
+
+def unpack_model(options, raw_indices):
+    """Convert the received data into blender mesh edges, faces and world transform.
+    The indices may be split into segments by the "first_index_model_N" keys, each segment can
+    override "mesh.format" with a "mesh.format_model_N" key.
+    When the result is in the local frame of the source object ("LOCAL_FRAME"), no world transform
+    is returned: the object keeps its own."""
+    rv_edges = []
+    rv_faces = []
+    model_counter = 0
+    while model_counter == 0 or ("first_index_model_" + str(model_counter)) in options:
+        index_start = int(options.get("first_index_model_" + str(model_counter), 0))
+        index_end = int(options.get("first_index_model_" + str(model_counter + 1), len(raw_indices)))
+        mesh_format = options.get("mesh.format_model_" + str(model_counter), options.get("mesh.format", None))
+        edges, faces = unpack_indices(mesh_format, raw_indices[index_start:index_end])
+        rv_edges += edges
+        rv_faces += faces
+        model_counter += 1
 
     # if pb_model.HasField("worldOrientation"):
     #    pbm = pb_model.worldOrientation
@@ -276,9 +437,112 @@ def unpack_model(options, raw_indices):
     #    mat[1][0], mat[1][1], mat[1][2], mat[1][3] = pbm.m10, pbm.m11, pbm.m12, pbm.m13
     #    mat[2][0], mat[2][1], mat[2][2], mat[2][3] = pbm.m20, pbm.m21, pbm.m22, pbm.m23
     #    mat[3][0], mat[3][1], mat[3][2], mat[3][3] = pbm.m30, pbm.m31, pbm.m32, pbm.m33
+    if options.get("LOCAL_FRAME", "false") == "true":
+        return rv_edges, rv_faces, None
     return rv_edges, rv_faces, mathutils.Matrix.Identity(4)
 
 
+def apply_vertex_colors(mesh, options):
+    """Store the "VERTEX_COLORS" (r,g,b,a per vertex) returned by rust as a color attribute.
+    The colors are either a binary attribute (a list of floats) or a comma separated string."""
+    raw_colors = options.get("VERTEX_COLORS")
+    if not raw_colors:
+        return
+    if isinstance(raw_colors, str):
+        colors = [float(c) for c in raw_colors.split(",")]
+    else:
+        colors = raw_colors
+    if len(colors) != 4 * len(mesh.vertices):
+        print("apply_vertex_colors() error: got", len(colors) // 4, "colors for", len(mesh.vertices), "vertices")
+        return
+    attribute = mesh.color_attributes.get("hallr_colors")
+    if attribute is None:
+        attribute = mesh.color_attributes.new(name="hallr_colors", type='FLOAT_COLOR', domain='POINT')
+    attribute.data.foreach_set("color", colors)
+    mesh.color_attributes.active_color = attribute
+    mesh.update()
+
+
+def get_shading_attributes(mesh):
+    """Return the vertex normals and the UVs of the active UV layer (the last loop of every vertex) as
+    the "NORMALS" and "UVS" binary attributes. Rust carries them over to the vertices of the result."""
+    rv = {"NORMALS": [c for v in mesh.vertices for c in v.normal]}
+    uv_layer = mesh.uv_layers.active
+    if uv_layer:
+        uvs = [0.0] * (2 * len(mesh.vertices))
+        for loop in mesh.loops:
+            uv = uv_layer.data[loop.index].uv
+            uvs[2 * loop.vertex_index] = uv.x
+            uvs[2 * loop.vertex_index + 1] = uv.y
+        rv["UVS"] = uvs
+    return rv
+
+
+def apply_shading(mesh, options):
+    """Store the "NORMALS" (x,y,z per vertex) returned by rust as custom normals, and the "UVS" (u,v
+    per vertex) as a UV layer."""
+    normals = options.get("NORMALS")
+    uvs = options.get("UVS")
+    if not normals and not uvs:
+        return
+    if normals and not isinstance(normals, str):
+        if len(normals) == 3 * len(mesh.vertices):
+            mesh.normals_split_custom_set_from_vertices(
+                [normals[i:i + 3] for i in range(0, len(normals), 3)])
+        else:
+            print("apply_shading() error: got", len(normals) // 3, "normals for", len(mesh.vertices), "vertices")
+    if uvs and not isinstance(uvs, str):
+        if len(uvs) == 2 * len(mesh.vertices):
+            uv_layer = mesh.uv_layers.get("hallr_uv") or mesh.uv_layers.new(name="hallr_uv")
+            for loop in mesh.loops:
+                uv_layer.data[loop.index].uv = uvs[2 * loop.vertex_index:2 * loop.vertex_index + 2]
+        else:
+            print("apply_shading() error: got", len(uvs) // 2, "UVs for", len(mesh.vertices), "vertices")
+    mesh.update()
+
+
+def new_bezier_curve_object(name, options, matrix_world):
+    """Create a curve object out of the "BEZIER_POINTS", "BEZIER_SEGMENTS" and "BEZIER_CYCLIC" binary
+    attributes returned by rust. Every curve is stored as its first point, followed by the two control
+    points and the end point of every segment. The last point of a cyclic curve is the first point."""
+    points = options.get("BEZIER_POINTS")
+    if not points:
+        return None
+    curve_data = bpy.data.curves.new(name=name, type='CURVE')
+    curve_data.dimensions = '3D'
+    position = 0
+    for segments, cyclic in zip(options["BEZIER_SEGMENTS"], options["BEZIER_CYCLIC"]):
+        segments = int(segments)
+        cyclic = cyclic > 0.5
+        coords = [tuple(points[position + 3 * i:position + 3 * i + 3]) for i in range(1 + 3 * segments)]
+        position += 3 * len(coords)
+        knot_count = segments if cyclic else segments + 1
+        spline = curve_data.splines.new('BEZIER')
+        spline.bezier_points.add(knot_count - 1)
+        for i, bezier_point in enumerate(spline.bezier_points):
+            bezier_point.handle_left_type = 'FREE'
+            bezier_point.handle_right_type = 'FREE'
+            bezier_point.co = coords[3 * i]
+            if i > 0:
+                bezier_point.handle_left = coords[3 * i - 1]
+            else:
+                bezier_point.handle_left = coords[-2] if cyclic else coords[0]
+            bezier_point.handle_right = coords[3 * i + 1] if i < segments else coords[3 * i]
+        spline.use_cyclic_u = cyclic
+    curve_obj = bpy.data.objects.new(name, curve_data)
+    curve_obj.matrix_world = matrix_world
+    bpy.context.collection.objects.link(curve_obj)
+    return curve_obj
+
+
+def error_code(options):
+    """Returns the numeric ERROR_CODE (see HallrErrorCode in lib.rs) of the returned map, or None"""
+    try:
+        return int(options["ERROR_CODE"])
+    except (KeyError, ValueError):
+        return None
+
+
 def handle_received_object_replace_active(active_object, options, ffi_vertices, ffi_indices):
     """Takes care of the raw ffi data received from rust, and create a blender mesh out of them"""
 
@@ -287,7 +551,7 @@ def handle_received_object_replace_active(active_object, options, ffi_vertices,
 
     for key, value in options.items():
         if key == "ERROR":
-            raise HallrException(str(value))
+            raise HallrException(str(value), error_code(options), options.get("ERROR_DETAIL"))
         if key == "REMOVE_DOUBLES" and value.lower() == "true":
             remove_doubles = True
         if key == "REMOVE_DOUBLES_THRESHOLD":
@@ -314,6 +578,8 @@ def handle_received_object_replace_active(active_object, options, ffi_vertices,
         bm.from_mesh(new_mesh)
         bpy.ops.object.mode_set(mode='OBJECT')
         bm.to_mesh(active_object.data)
+        apply_vertex_colors(active_object.data, options)
+        apply_shading(active_object.data, options)
         bpy.ops.object.mode_set(mode='EDIT')
 
         # print("active_object.update_from_editmode():", active_object.update_from_editmode())
@@ -469,11 +735,133 @@ def prepare_object_for_processing_direct(obj):
     return obj
 
 
-def call_rust(config: dict[str, str], active_obj, bounding_shape=None, only_selected_vertices=False):
+def add_unit_scale(config):
+    """ Send the unit scale of the scene, unless the operator already selected one.
+    The lengths of the config are given in scene units, the rust side scales them into meters and
+    the result back into scene units."""
+    if "UNIT_SCALE" not in config:
+        config["UNIT_SCALE"] = str(bpy.context.scene.unit_settings.scale_length)
+
+
+def stream_geometry(rust_lib, vertices, indices):
+    """Send the vertices and indices to a new geometry stream, chunk by chunk.
+    Returns the token of the stream."""
+    token = rust_lib.begin_geometry_stream(len(vertices), len(indices))
+    try:
+        for start in range(0, len(vertices), STREAM_CHUNK_SIZE):
+            chunk = vertices[start:start + STREAM_CHUNK_SIZE]
+            if rust_lib.push_vertices(token, (Vector3 * len(chunk))(*[Vector3(*v) for v in chunk]),
+                                      len(chunk)) != 0:
+                raise HallrException("The geometry stream was closed unexpectedly")
+        for start in range(0, len(indices), STREAM_CHUNK_SIZE):
+            chunk = indices[start:start + STREAM_CHUNK_SIZE]
+            if rust_lib.push_indices(token, (ctypes.c_size_t * len(chunk))(*chunk), len(chunk)) != 0:
+                raise HallrException("The geometry stream was closed unexpectedly")
+    except Exception:
+        rust_lib.abort_geometry_stream(token)
+        raise
+    return token
+
+
+def call_process_geometry(rust_lib, vertices, indices, matrices, config, attributes=None):
+    """Send the vertices (x, y, z tuples), indices, world matrices and config to rust.
+    Models with coordinates larger than DOUBLE_PRECISION_THRESHOLD are sent through
+    process_geometry_f64, so that they keep their detail. Meshes with more than STREAM_THRESHOLD
+    vertices are sent in chunks through a geometry stream.
+    Returns the vertices, the indices and the returned map, including the binary attributes."""
+    use_f64 = any(abs(c) > DOUBLE_PRECISION_THRESHOLD for v in vertices for c in v)
+    use_stream = not use_f64 and len(vertices) > STREAM_THRESHOLD
+    if use_f64:
+        print("hallr: large coordinates, using double precision")
+        vertex_type, float_type = Vector3d, ctypes.c_double
+        process, free = rust_lib.process_geometry_f64, rust_lib.free_process_results_f64
+    else:
+        vertex_type, float_type = Vector3, ctypes.c_float
+        process, free = rust_lib.process_geometry, rust_lib.free_process_results
+    matrices_ptr = (float_type * len(matrices))(*matrices)
+
+    # Convert the dictionary to two separate lists for keys and values
+    keys_list = list(config.keys())
+    values_list = list(config.values())
+    keys_array = (ctypes.c_char_p * len(keys_list))(*[k.encode('utf-8') for k in keys_list])
+    values_array = (ctypes.c_char_p * len(values_list))(*[v.encode('utf-8') for v in values_list])
+    map_data = StringMap(keys_array, values_array, len(keys_list))
+
+    attributes_blob = encode_attributes(attributes)
+    if use_stream:
+        print("hallr: large mesh, sending it in chunks")
+        token = stream_geometry(rust_lib, vertices, indices)
+        rust_result = rust_lib.finish_and_process(token, matrices_ptr, len(matrices), map_data,
+                                                  attributes_blob, len(attributes_blob))
+    else:
+        vertices_ptr = (vertex_type * len(vertices))(*[vertex_type(*v) for v in vertices])
+        indices_ptr = (ctypes.c_size_t * len(indices))(*indices)
+        rust_result = process(vertices_ptr, len(vertices), indices_ptr, len(indices), matrices_ptr,
+                              len(matrices), map_data, attributes_blob, len(attributes_blob))
+
+    output_vertices = [(vec.x, vec.y, vec.z) for vec in
+                       (rust_result.geometry.vertices[i] for i in range(rust_result.geometry.vertex_count))]
+    output_indices = [rust_result.geometry.indices[i] for i in range(rust_result.geometry.indices_count)]
+
+    output_map = {}
+    for i in range(rust_result.map.count):
+        key = ctypes.string_at(rust_result.map.keys[i]).decode('utf-8')
+        value = ctypes.string_at(rust_result.map.values[i]).decode('utf-8')
+        output_map[key] = value
+    if rust_result.attributes.size > 0:
+        output_map.update(decode_attributes(ctypes.string_at(rust_result.attributes.data,
+                                                             rust_result.attributes.size)))
+    # This frees the data owned by Rust
+    free(rust_result)
+    return output_vertices, output_indices, output_map
+
+
+def list_commands():
+    """Returns the commands known by the rust library, as a dict of the command name to a dict
+    with the "versions", the accepted "formats" of the first model (empty for any format) and the
+    "params", a list of dicts with the "key", "type", "required" and "range" (None or (min, max))
+    of every parameter. The commands of the plugins are listed without formats and parameters."""
+    rust_lib = load_latest_dylib()
+    string_map = rust_lib.hallr_list_commands()
+    output_map = {}
+    for i in range(string_map.count):
+        key = ctypes.string_at(string_map.keys[i]).decode('utf-8')
+        output_map[key] = ctypes.string_at(string_map.values[i]).decode('utf-8')
+    rust_lib.free_string_map(ctypes.byref(string_map))
+    ctypes_close_library(rust_lib)
+
+    def split(value):
+        return [v for v in value.split(",") if v]
+
+    plugins = split(output_map.get("PLUGINS", ""))
+    commands = {}
+    for version in split(output_map.get("COMMANDS", "")):
+        name, _, number = version.partition("/v")
+        if "formats." + name not in output_map and name not in plugins:
+            # the introspection command itself
+            continue
+        command = commands.setdefault(name, {"versions": [], "formats": split(output_map.get("formats." + name, "")),
+                                             "params": []})
+        command["versions"].append(int(number))
+    for name, command in commands.items():
+        for param in split(output_map.get("params." + name, "")):
+            fields = param.split(":")
+            value_range = None
+            if len(fields) > 3:
+                low, high = fields[3].split("..=")
+                value_range = (float(low), float(high))
+            command["params"].append({"key": fields[0], "type": fields[1], "required": fields[2] == "required",
+                                      "range": value_range})
+    return commands
+
+
+def call_rust(config: dict[str, str], active_obj, bounding_shape=None, only_selected_vertices=False,
+              attributes=None):
     # Load the Rust library
     # We load the .dylib and define argtypes for every invocation just to be able to update the lib without
     # restarting blender. This does not seem to work anymore, though
     rust_lib = load_latest_dylib()
+    add_unit_scale(config)
 
     # Prepare both objects for processing
     active_obj_to_process, active_obj_is_duplicated = prepare_object_for_processing(active_obj, "TempDuplicateActive")
@@ -487,20 +875,20 @@ def call_rust(config: dict[str, str], active_obj, bounding_shape=None, only_sele
 
     if only_selected_vertices:
         indices = []
-        vertices = [Vector3(v.co.x, v.co.y, v.co.z) for v in active_obj.data.vertices if v.select]
+        vertices = [(v.co.x, v.co.y, v.co.z) for v in active_obj.data.vertices if v.select]
     else:
         # 4. Gather triangle vertex indices
         indices = [vert_idx for face in active_obj_to_process.data.polygons for vert_idx in face.vertices]
 
         # 5. Convert the data to a ctypes-friendly format
-        vertices = [Vector3(v.co.x, v.co.y, v.co.z) for v in active_obj_to_process.data.vertices]
+        vertices = [(v.co.x, v.co.y, v.co.z) for v in active_obj_to_process.data.vertices]
 
     if bounding_shape:
 
         first_vertex_model_1 = len(vertices)
         first_index_model_1 = len(indices)
         # Appending vertices from the bounding shape
-        vertices += [Vector3(v.co.x, v.co.y, v.co.z) for v in bounding_obj_to_process.data.vertices]
+        vertices += [(v.co.x, v.co.y, v.co.z) for v in bounding_obj_to_process.data.vertices]
 
         config["first_vertex_model_1"] = str(first_vertex_model_1)
         config["first_index_model_1"] = str(first_index_model_1)
@@ -516,46 +904,18 @@ def call_rust(config: dict[str, str], active_obj, bounding_shape=None, only_sele
     if bounding_shape and bounding_obj_is_duplicated:
         cleanup_duplicated_object(bounding_obj_to_process)
 
-    # 6. Convert the data to a ctypes-friendly format
-    vertices_ptr = (Vector3 * len(vertices))(*vertices)
-    indices_ptr = (ctypes.c_size_t * len(indices))(*indices)
-
-    # Handle the world orientation
+    # 6. Handle the world orientation
     matrices = get_matrices(active_obj)
     if bounding_shape:
         matrices.extend(get_matrices(bounding_shape))
 
-    matrices_ptr = (ctypes.c_float * len(matrices))(*matrices)
-
-    # 7. Convert the dictionary to two separate lists for keys and values
-    keys_list = list(config.keys())
-    values_list = list(config.values())
-    keys_array = (ctypes.c_char_p * len(keys_list))(*[k.encode('utf-8') for k in keys_list])
-    values_array = (ctypes.c_char_p * len(values_list))(*[v.encode('utf-8') for v in values_list])
-    map_data = StringMap(keys_array, values_array, len(keys_list))
-
-    # 8. Make the call to rust
-    rust_result = rust_lib.process_geometry(vertices_ptr, len(vertices), indices_ptr, len(indices), matrices_ptr,
-                                            len(matrices), map_data)
-
-    print("python received: ", rust_result.geometry.vertex_count, "vertices",
-          rust_result.geometry.indices_count, "indices")
-    # 9. Handle the results
-    output_vertices = [(vec.x, vec.y, vec.z) for vec in
-                       (rust_result.geometry.vertices[i] for i in range(rust_result.geometry.vertex_count))]
-    output_indices = [rust_result.geometry.indices[i] for i in range(rust_result.geometry.indices_count)]
-
-    output_map = {}
-    for i in range(rust_result.map.count):
-        key = ctypes.string_at(rust_result.map.keys[i]).decode('utf-8')
-        value = ctypes.string_at(rust_result.map.values[i]).decode('utf-8')
-        output_map[key] = value
+    # 7. Make the call to rust, and free the rust memory
+    output_vertices, output_indices, output_map = call_process_geometry(rust_lib, vertices, indices, matrices,
+                                                                        config, attributes)
+    print("python received: ", len(output_vertices), "vertices", len(output_indices), "indices")
     print("python received: ", output_map)
 
-    # 10. Free rust memory
-    rust_lib.free_process_results(rust_result)
-
-    # 11. try to close the .dylib so that it is "fresh" for the next invocation.
+    # 8. try to close the .dylib so that it is "fresh" for the next invocation.
     # When running in release mode, this does nothing.
     ctypes_close_library(rust_lib)
 
@@ -571,20 +931,27 @@ def get_matrices(bpy_object):
             bm[3][0], bm[3][1], bm[3][2], bm[3][3]]
 
 
-def call_rust_direct(config, active_obj, use_line_chunks=False):
+def call_rust_direct(config, active_obj, use_line_chunks=False, attributes=None):
     """
     A simpler version of call_rust that only processes the active_object.
     When `expect_line_chunks` is set, the data will iterate over each edge(a,b) and use a list of
     indices in .chunks(2) format.
     If `expect_line_chunks` is not set, the code expect the mesh to be triangulated.
+    If config["LOCAL_FRAME"] is "true" the transformations are not applied, the vertices are sent in
+    the local frame of the object together with its world matrix.
+    `attributes` is an optional dict of named float lists, sent through the binary attribute channel.
+    The binary attributes returned by rust are added to the returned map, as lists of floats.
     """
 
     rust_lib = load_latest_dylib()
+    add_unit_scale(config)
 
-    active_obj_to_process = prepare_object_for_processing_direct(active_obj)
+    if config.get("LOCAL_FRAME", "false") == "true":
+        active_obj_to_process = active_obj
+    else:
+        active_obj_to_process = prepare_object_for_processing_direct(active_obj)
     # handle the vertices
-    vertices = [Vector3(v.co.x, v.co.y, v.co.z) for v in active_obj_to_process.data.vertices]
-    vertices_ptr = (Vector3 * len(vertices))(*vertices)
+    vertices = [(v.co.x, v.co.y, v.co.z) for v in active_obj_to_process.data.vertices]
 
     # Handle the indices
     if use_line_chunks:
@@ -603,34 +970,13 @@ def call_rust_direct(config, active_obj, use_line_chunks=False):
             indices.extend(face.vertices)
         if len(indices) == 0:
             raise HallrException("No polygons found, maybe the mesh is not fully triangulated?")
-    indices_ptr = (ctypes.c_size_t * len(indices))(*indices)
 
     # Handle the world orientation
     matrices = get_matrices(active_obj)
-    matrices_ptr = (ctypes.c_float * len(matrices))(*matrices)
 
-    # Handle the StringMap
-    keys_list = list(config.keys())
-    values_list = list(config.values())
-    keys_array = (ctypes.c_char_p * len(keys_list))(*[k.encode('utf-8') for k in keys_list])
-    values_array = (ctypes.c_char_p * len(values_list))(*[v.encode('utf-8') for v in values_list])
-    map_data = StringMap(keys_array, values_array, len(keys_list))
-
-    # This calls the rust library
-    rust_result = rust_lib.process_geometry(vertices_ptr, len(vertices), indices_ptr, len(indices), matrices_ptr,
-                                            len(matrices), map_data)
-
-    output_vertices = [(vec.x, vec.y, vec.z) for vec in
-                       (rust_result.geometry.vertices[i] for i in range(rust_result.geometry.vertex_count))]
-    output_indices = [rust_result.geometry.indices[i] for i in range(rust_result.geometry.indices_count)]
-
-    output_map = {}
-    for i in range(rust_result.map.count):
-        key = ctypes.string_at(rust_result.map.keys[i]).decode('utf-8')
-        value = ctypes.string_at(rust_result.map.values[i]).decode('utf-8')
-        output_map[key] = value
-    # This should free the data owned by Rust
-    rust_lib.free_process_results(rust_result)
+    # This calls the rust library, and frees the data owned by Rust
+    output_vertices, output_indices, output_map = call_process_geometry(rust_lib, vertices, indices, matrices,
+                                                                        config, attributes)
     # In development mode this tries to close the library, in release mode it does nothing
     ctypes_close_library(rust_lib)
 
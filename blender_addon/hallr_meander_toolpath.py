@@ -19,7 +19,29 @@ probes_props_items = [
 # Define the choices for the search pattern property
 patterns_props_items = [
     ("MEANDER", "Meander", "Meander scan pattern"),
-    ("TRIANGULATION", "Triangulation", "2d Delaunay Triangulation")
+    ("TRIANGULATION", "Triangulation", "2d Delaunay Triangulation"),
+    ("GRID", "Grid", "Raw grid of probe heights, e.g. for bed-leveling. Always uses the AABB bounds")
+]
+
+# Define the choices for the meander pass order property
+pass_order_props_items = [
+    ("ZIGZAG", "Zig-zag", "Alternate the direction of every pass"),
+    ("UNIDIRECTIONAL", "Unidirectional", "Cut every pass in the same direction, with a return move in between")
+]
+
+# Define the choices for the meander start corner property
+start_corner_props_items = [
+    ("MIN_X_MIN_Y", "Min X, Min Y", "Start at the min X, min Y corner"),
+    ("MAX_X_MIN_Y", "Max X, Min Y", "Start at the max X, min Y corner"),
+    ("MIN_X_MAX_Y", "Min X, Max Y", "Start at the min X, max Y corner"),
+    ("MAX_X_MAX_Y", "Max X, Max Y", "Start at the max X, max Y corner")
+]
+
+# Define the choices for the meander cut direction property
+direction_props_items = [
+    ("START_CORNER", "From start corner", "The pass direction is given by the start corner"),
+    ("CLIMB", "Climb", "Climb cutting, assuming a clockwise spindle"),
+    ("CONVENTIONAL", "Conventional", "Conventional cutting, assuming a clockwise spindle")
 ]
 
 # Define the choices for the search pattern property
@@ -76,6 +98,20 @@ class HALLR_PT_MeanderToolpath(bpy.types.Panel):
         layout.row(align=True).prop(settings, "step_props")
         layout.row(align=True).prop(settings, "minimum_z_props")
         layout.row(align=True).prop(settings, "pattern_props")
+        if settings.pattern_props == "TRIANGULATION":
+            layout.row(align=True).prop(settings, "enable_lattice_props")
+            if settings.enable_lattice_props:
+                layout.row(align=True).prop(settings, "step_x_props")
+                layout.row(align=True).prop(settings, "step_y_props")
+                layout.row(align=True).prop(settings, "lattice_angle_props")
+        if settings.pattern_props == "MEANDER":
+            layout.row(align=True).prop(settings, "start_corner_props")
+            layout.row(align=True).prop(settings, "pass_order_props")
+            if settings.pass_order_props == "UNIDIRECTIONAL":
+                layout.row(align=True).prop(settings, "direction_props")
+                layout.row(align=True).prop(settings, "enable_return_z_props")
+                if settings.enable_return_z_props:
+                    layout.row(align=True).prop(settings, "return_z_props")
 
         # Generate tool-path button
         if (settings.bounding_shape is not None and
@@ -176,6 +212,19 @@ class OBJECT_OT_GenerateMesh(bpy.types.Operator):
             if str(settings.probe_props) == "TAPERED_END":
                 config["probe_angle"] = str(settings.probe_angle_props)
 
+            if str(settings.pattern_props) == "TRIANGULATION" and settings.enable_lattice_props:
+                config["step_x"] = str(settings.step_x_props)
+                config["step_y"] = str(settings.step_y_props)
+                config["lattice_angle"] = str(settings.lattice_angle_props)
+            if str(settings.pattern_props) == "MEANDER":
+                config["start_corner"] = str(settings.start_corner_props)
+                config["pass_order"] = str(settings.pass_order_props)
+                if str(settings.pass_order_props) == "UNIDIRECTIONAL":
+                    if str(settings.direction_props) != "START_CORNER":
+                        config["direction"] = str(settings.direction_props)
+                    if settings.enable_return_z_props:
+                        config["return_z"] = str(settings.return_z_props)
+
             if settings.enable_adaptive_scan_props:
                 config["z_jump_threshold_multiplier"] = str(settings.z_jump_threshold_multiplier_props)
                 config["xy_sample_dist_multiplier"] = str(settings.xy_sample_dist_multiplier_props)
@@ -189,6 +238,9 @@ class OBJECT_OT_GenerateMesh(bpy.types.Operator):
             if config.get("ERROR"):
                 self.report({'ERROR'}, "" + config.get("ERROR"))
                 return {'CANCELLED'}
+            if config.get("WARNING"):
+                # e.g. samples clamped to minimum_z
+                self.report({'WARNING'}, config.get("WARNING"))
             # Check if the returned mesh format is triangulated
             if config.get("mesh.format") == "triangulated":
                 hallr_ffi_utils.handle_triangle_mesh(vertices, indices)
@@ -281,6 +333,61 @@ class MeanderToolpathSettings(bpy.types.PropertyGroup):
         items=patterns_props_items,
         default="MEANDER",
     )
+    enable_lattice_props: bpy.props.BoolProperty(
+        name="Regular lattice",
+        description="Scan a regular lattice with separate X and Y step sizes, and an orientation angle",
+    )
+    step_x_props: bpy.props.FloatProperty(
+        name="Step size X",
+        description="Define the step size of the lattice along its X axis",
+        default=0.5,
+        min=0.01,
+        max=10.0,
+    )
+    step_y_props: bpy.props.FloatProperty(
+        name="Step size Y",
+        description="Define the step size of the lattice along its Y axis",
+        default=0.5,
+        min=0.01,
+        max=10.0,
+    )
+    lattice_angle_props: bpy.props.FloatProperty(
+        name="Lattice angle",
+        description="Define the rotation of the lattice around the Z axis",
+        default=0.0,
+        min=math.radians(-180.0),
+        max=math.radians(180.0),
+        subtype='ANGLE',
+    )
+    pass_order_props: bpy.props.EnumProperty(
+        name="Pass order",
+        description="Choose how the meander passes are ordered",
+        items=pass_order_props_items,
+        default="ZIGZAG",
+    )
+    start_corner_props: bpy.props.EnumProperty(
+        name="Start corner",
+        description="Choose the corner where the first meander pass starts",
+        items=start_corner_props_items,
+        default="MIN_X_MIN_Y",
+    )
+    direction_props: bpy.props.EnumProperty(
+        name="Cut direction",
+        description="Choose climb or conventional cutting for unidirectional passes",
+        items=direction_props_items,
+        default="START_CORNER",
+    )
+    enable_return_z_props: bpy.props.BoolProperty(
+        name="Custom return height",
+        description="Set the height of the return moves, default is one step above the highest sample",
+    )
+    return_z_props: bpy.props.FloatProperty(
+        name="Return height",
+        description="Define the Z value of the return moves between unidirectional passes",
+        default=1.0,
+        min=-100.0,
+        max=100.0
+    )
 
 
 # Register classes and property group
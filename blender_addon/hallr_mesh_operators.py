@@ -447,6 +447,12 @@ class Hallr_Voronoi_Mesh(bpy.types.Operator):
         default=True
     )
 
+    local_frame_props: bpy.props.BoolProperty(
+        name="Local frame",
+        description="Generate the result in the local frame of the object, keeping its transformation",
+        default=False
+    )
+
     @classmethod
     def poll(cls, context):
         ob = context.active_object
@@ -465,6 +471,7 @@ class Hallr_Voronoi_Mesh(bpy.types.Operator):
         config = {"command": "voronoi_mesh",
                   "DISTANCE": str(self.distance_props),
                   "NEGATIVE_RADIUS": str(self.negative_radius_props).lower(),
+                  "LOCAL_FRAME": str(self.local_frame_props).lower(),
                   }
         # Call the Rust function
         vertices, indices, config_out = hallr_ffi_utils.call_rust_direct(config, obj, use_line_chunks=True)
@@ -476,6 +483,7 @@ class Hallr_Voronoi_Mesh(bpy.types.Operator):
         layout = self.layout
         layout.prop(self, "distance_props")
         layout.prop(self, "negative_radius_props")
+        layout.prop(self, "local_frame_props")
 
     def invoke(self, context, event):
         wm = context.window_manager
@@ -507,6 +515,32 @@ class Hallr_Voronoi_Diagram(bpy.types.Operator):
         default=True
     )
 
+    local_frame_props: bpy.props.BoolProperty(
+        name="Local frame",
+        description="Generate the result in the local frame of the object, keeping its transformation",
+        default=False
+    )
+
+    min_clearance_props: bpy.props.FloatProperty(
+        name="Min clearance",
+        description="Removes the edges whose closest input features are closer together than twice this "
+                    "distance. 0 disables the filter",
+        default=0.0,
+        min=0.0,
+        precision=6,
+        subtype='DISTANCE'
+    )
+
+    min_angle_props: bpy.props.FloatProperty(
+        name="Min separation angle",
+        description="Removes the edges whose closest input features are seen at a smaller angle than this. "
+                    "0 disables the filter",
+        default=0.0,
+        min=0.0,
+        max=180.0,
+        precision=2,
+    )
+
     @classmethod
     def poll(cls, context):
         ob = context.active_object
@@ -525,6 +559,9 @@ class Hallr_Voronoi_Diagram(bpy.types.Operator):
         config = {"command": "voronoi_diagram",
                   "DISTANCE": str(self.distance_props),
                   "KEEP_INPUT": str(self.keep_input_props).lower(),
+                  "LOCAL_FRAME": str(self.local_frame_props).lower(),
+                  "MEDIAL_AXIS_MIN_CLEARANCE": str(self.min_clearance_props),
+                  "MEDIAL_AXIS_MIN_ANGLE": str(self.min_angle_props),
                   }
         # Call the Rust function
         vertices, indices, config_out = hallr_ffi_utils.call_rust_direct(config, obj, use_line_chunks=True)
@@ -536,6 +573,9 @@ class Hallr_Voronoi_Diagram(bpy.types.Operator):
         layout = self.layout
         layout.prop(self, "distance_props")
         layout.prop(self, "keep_input_props")
+        layout.prop(self, "local_frame_props")
+        layout.prop(self, "min_clearance_props")
+        layout.prop(self, "min_angle_props")
 
     def invoke(self, context, event):
         wm = context.window_manager
@@ -564,6 +604,31 @@ class Hallr_SdfMesh25D(bpy.types.Operator):
         subtype='FACTOR'
     )
 
+    sdf_chunk_side_property: bpy.props.EnumProperty(
+        name="Chunk Size",
+        description="The side of each voxel chunk. Large smooth models benefit from bigger chunks, "
+                    "thin lattices prefer small ones",
+        items=[
+            ("8", "8", "8x8x8 voxels per chunk"),
+            ("14", "14", "14x14x14 voxels per chunk"),
+            ("30", "30", "30x30x30 voxels per chunk"),
+            ("62", "62", "62x62x62 voxels per chunk"),
+        ],
+        default="14",
+    )
+
+    debug_chunks_property: bpy.props.BoolProperty(
+        name="Debug Chunks",
+        description="Add the chunk boundaries as a wireframe and print per chunk timing statistics",
+        default=False
+    )
+
+    local_frame_props: bpy.props.BoolProperty(
+        name="Local frame",
+        description="Generate the result in the local frame of the object, keeping its transformation",
+        default=False
+    )
+
     @classmethod
     def poll(cls, context):
         ob = context.active_object
@@ -581,6 +646,9 @@ class Hallr_SdfMesh25D(bpy.types.Operator):
 
         config = {"command": "sdf_mesh_2_5",
                   "SDF_DIVISIONS": str(self.sdf_divisions_property),
+                  "SDF_CHUNK_SIDE": self.sdf_chunk_side_property,
+                  "DEBUG_CHUNKS": str(self.debug_chunks_property).lower(),
+                  "LOCAL_FRAME": str(self.local_frame_props).lower(),
                   }
         # Call the Rust function
         vertices, indices, config_out = hallr_ffi_utils.call_rust_direct(config, obj, use_line_chunks=True)
@@ -591,6 +659,9 @@ class Hallr_SdfMesh25D(bpy.types.Operator):
     def draw(self, context):
         layout = self.layout
         layout.prop(self, "sdf_divisions_property")
+        layout.prop(self, "sdf_chunk_side_property")
+        layout.prop(self, "debug_chunks_property")
+        layout.prop(self, "local_frame_props")
 
     def invoke(self, context, event):
         wm = context.window_manager
@@ -628,6 +699,31 @@ class Hallr_SdfMesh(bpy.types.Operator):
         subtype='PERCENTAGE'
     )
 
+    sdf_chunk_side_prop: bpy.props.EnumProperty(
+        name="Chunk Size",
+        description="The side of each voxel chunk. Large smooth models benefit from bigger chunks, "
+                    "thin lattices prefer small ones",
+        items=[
+            ("8", "8", "8x8x8 voxels per chunk"),
+            ("14", "14", "14x14x14 voxels per chunk"),
+            ("30", "30", "30x30x30 voxels per chunk"),
+            ("62", "62", "62x62x62 voxels per chunk"),
+        ],
+        default="14",
+    )
+
+    debug_chunks_prop: bpy.props.BoolProperty(
+        name="Debug Chunks",
+        description="Add the chunk boundaries as a wireframe and print per chunk timing statistics",
+        default=False
+    )
+
+    local_frame_props: bpy.props.BoolProperty(
+        name="Local frame",
+        description="Generate the result in the local frame of the object, keeping its transformation",
+        default=False
+    )
+
     @classmethod
     def poll(cls, context):
         ob = context.active_object
@@ -645,7 +741,10 @@ class Hallr_SdfMesh(bpy.types.Operator):
 
         config = {"command": "sdf_mesh",
                   "SDF_DIVISIONS": str(self.sdf_divisions_prop),
-                  "SDF_RADIUS_MULTIPLIER": str(self.sdf_radius_prop)
+                  "SDF_RADIUS_MULTIPLIER": str(self.sdf_radius_prop),
+                  "SDF_CHUNK_SIDE": self.sdf_chunk_side_prop,
+                  "DEBUG_CHUNKS": str(self.debug_chunks_prop).lower(),
+                  "LOCAL_FRAME": str(self.local_frame_props).lower(),
                   }
 
         # Call the Rust function
@@ -658,6 +757,9 @@ class Hallr_SdfMesh(bpy.types.Operator):
         layout = self.layout
         layout.prop(self, "sdf_divisions_prop")
         layout.prop(self, "sdf_radius_prop")
+        layout.prop(self, "sdf_chunk_side_prop")
+        layout.prop(self, "debug_chunks_prop")
+        layout.prop(self, "local_frame_props")
 
     def invoke(self, context, event):
         wm = context.window_manager
@@ -866,6 +968,21 @@ class Hallr_Centerline(bpy.types.Operator):
         default=True
     )
 
+    bezier_props: bpy.props.BoolProperty(
+        name="Bezier curves",
+        description="Also fit the centerline with Bezier curves, added as a new curve object",
+        default=False
+    )
+
+    bezier_tolerance_props: bpy.props.FloatProperty(
+        name="Bezier tolerance",
+        description="The max distance between the centerline and the Bezier curves",
+        default=0.01,
+        min=0.000001,
+        precision=6,
+        subtype='DISTANCE'
+    )
+
     @classmethod
     def poll(cls, context):
         ob = context.active_object
@@ -896,9 +1013,13 @@ class Hallr_Centerline(bpy.types.Operator):
                   "WELD"
                   : str(self.weld_props).lower(),
                   }
+        if self.bezier_props:
+            config["BEZIER_TOLERANCE"] = str(self.bezier_tolerance_props)
         # Call the Rust function
         vertices, indices, config_out = hallr_ffi_utils.call_rust_direct(config, obj, use_line_chunks=True)
         hallr_ffi_utils.handle_received_object_replace_active(obj, config_out, vertices, indices)
+        if self.bezier_props:
+            hallr_ffi_utils.new_bezier_curve_object(obj.name + "_centerline", config_out, obj.matrix_world)
 
         return {'FINISHED'}
 
@@ -917,6 +1038,9 @@ class Hallr_Centerline(bpy.types.Operator):
         if self.simplify_props:
             layout.prop(self, "distance_props")
         layout.prop(self, "simplify_props")
+        layout.prop(self, "bezier_props")
+        if self.bezier_props:
+            layout.prop(self, "bezier_tolerance_props")
 
 
 # menu containing all tools
@@ -31,9 +31,15 @@ class GeometryOutput(ctypes.Structure):
                 ("matrices_count", ctypes.c_size_t)]
 
 
+class AttributeOutput(ctypes.Structure):
+    _fields_ = [("data", ctypes.POINTER(ctypes.c_uint8)),
+                ("size", ctypes.c_size_t)]
+
+
 class ProcessResult(ctypes.Structure):
     _fields_ = [("geometry", GeometryOutput),
-                ("map", StringMap)]
+                ("map", StringMap),
+                ("attributes", AttributeOutput)]
 
 
 if __name__ == "__main__":
@@ -58,7 +64,8 @@ if __name__ == "__main__":
     rust_lib.process_geometry.argtypes = [ctypes.POINTER(Vector3), ctypes.c_size_t,
                                           ctypes.POINTER(ctypes.c_size_t), ctypes.c_size_t,
                                           ctypes.POINTER(ctypes.c_float), ctypes.c_size_t,
-                                          ctypes.POINTER(StringMap)]
+                                          ctypes.POINTER(StringMap),
+                                          ctypes.c_char_p, ctypes.c_size_t]
 
     rust_lib.process_geometry.restype = ProcessResult
     rust_lib.free_process_results.argtypes = [ctypes.POINTER(ProcessResult)]
@@ -86,7 +93,7 @@ if __name__ == "__main__":
     print("python: map_data.values:", map_data.values)
     print("python: map_data.count:", map_data.count)
     # 4. Make the call to rust
-    rust_result = rust_lib.process_geometry(vertices_ptr, len(vertices), indices_ptr, len(indices), matrices_ptr, len(matrices), map_data)
+    rust_result = rust_lib.process_geometry(vertices_ptr, len(vertices), indices_ptr, len(indices), matrices_ptr, len(matrices), map_data, None, 0)
 
     # 5. Handle the results
     output_vertices = [(vec.x, vec.y, vec.z) for vec in
@@ -25,9 +25,15 @@ struct GeometryOutput {
     size_t matrices_count;
 };
 
+struct AttributeOutput {
+    unsigned char* data;
+    size_t size;
+};
+
 struct ProcessResult {
     struct GeometryOutput geometry;
     struct StringMap map;
+    struct AttributeOutput attributes;
 };
 
 void free_process_results(struct ProcessResult* result) {
@@ -41,12 +47,14 @@ void free_process_results(struct ProcessResult* result) {
     free(result->geometry.vertices);
     free(result->geometry.indices);
     free(result->geometry.matrices);
+    free(result->attributes.data);
 }
 
 struct ProcessResult process_geometry(const struct Vector3* vertices, size_t vertex_count,
                                       const size_t* indices, size_t indices_count,
                                       const float* matrices, size_t matrices_count,
-                                      const struct StringMap* config) {
+                                      const struct StringMap* config,
+                                      const unsigned char* input_attributes, size_t attributes_size) {
     printf("C: Received config of size: %zu\n", config->count);
 
     struct ProcessResult result;
@@ -33,9 +33,14 @@ class GeometryOutput(ctypes.Structure):
                 ("matrices", ctypes.POINTER(ctypes.c_float)),
                 ("matrices_count", ctypes.c_size_t)]
 
+class AttributeOutput(ctypes.Structure):
+    _fields_ = [("data", ctypes.POINTER(ctypes.c_uint8)),
+                ("size", ctypes.c_size_t)]
+
 class ProcessResult(ctypes.Structure):
     _fields_ = [("geometry", GeometryOutput),
-                ("map", StringMap)]
+                ("map", StringMap),
+                ("attributes", AttributeOutput)]
 
 
 system = platform.system()
@@ -49,7 +54,8 @@ rust_lib = ctypes.cdll.LoadLibrary("./blender_addon_exported/lib/" + library_nam
 rust_lib.process_geometry.argtypes = [ctypes.POINTER(Vector3), ctypes.c_size_t,
                                       ctypes.POINTER(ctypes.c_size_t), ctypes.c_size_t,
                                       ctypes.POINTER(ctypes.c_float), ctypes.c_size_t,
-                                      ctypes.POINTER(StringMap)]
+                                      ctypes.POINTER(StringMap),
+                                      ctypes.c_char_p, ctypes.c_size_t]
 
 rust_lib.process_geometry.restype = ProcessResult
 rust_lib.free_process_results.argtypes = [ctypes.POINTER(ProcessResult)]
@@ -77,7 +83,7 @@ print("python: map_data.keys:", map_data.keys)
 print("python: map_data.values:", map_data.values)
 print("python: map_data.count:", map_data.count)
 # 4. Make the call to rust
-rust_result = rust_lib.process_geometry(vertices_ptr, len(vertices), indices_ptr, len(indices), matrices_ptr, len(matrices), map_data)
+rust_result = rust_lib.process_geometry(vertices_ptr, len(vertices), indices_ptr, len(indices), matrices_ptr, len(matrices), map_data, None, 0)
 
 # 5. Handle the results
 output_vertices = [(vec.x, vec.y, vec.z) for vec in
@@ -95,6 +101,7 @@ for i in range(rust_result.map.count):
 print("Python received:", rust_result.geometry.vertex_count, "vertices")
 print("Python received:", rust_result.geometry.indices_count, "indices")
 print("Python received:", rust_result.geometry.matrices_count, "matrices")
+print("Python received:", rust_result.attributes.size, "bytes of attributes")
 print("Python received:", output_map)
 
 rust_lib.free_process_results(rust_result)
\ No newline at end of file
@@ -0,0 +1,751 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! A typed Rust API of the commands, for using hallr as a normal library dependency.
+//!
+//! Every command has a parameter struct and a function, e.g. [`centerline()`] with
+//! [`CenterlineParams`]. Mandatory parameters are plain fields, optional parameters are `Option`
+//! fields where `None` selects the default of the command. The parameters are converted into the
+//! same string config the Blender addon sends, so both run exactly the same code path.
+//!
+//! ```no_run
+//! use hallr::{
+//!     api::{self, MeshFormat, MeshRef},
+//!     prelude::FFIVector3,
+//! };
+//!
+//! let vertices: [FFIVector3; 3] = [
+//!     (0.0, 0.0, 0.0).into(),
+//!     (1.0, 0.0, 0.0).into(),
+//!     (0.0, 1.0, 0.0).into(),
+//! ];
+//! let mesh = MeshRef::new(&vertices, &[0, 1, 1, 2, 2, 0], MeshFormat::LineChunks);
+//! let params = api::FilletParams {
+//!     fillet_radius: 0.1,
+//!     ..Default::default()
+//! };
+//! let result = api::fillet(&params, &[mesh])?;
+//! println!("{} vertices", result.vertices.len());
+//! # Ok::<(), hallr::HallrError>(())
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+pub use crate::command::{mesh_format::MeshFormat, SdfLattice};
+use crate::{command, ffi::FFIVector3, HallrError};
+use std::collections::{BTreeMap, HashMap};
+
+/// The string config of a command
+pub type Config = HashMap<String, String>;
+/// Named arrays of floats, the binary attribute channels of a command
+pub type Attributes = BTreeMap<String, Vec<f32>>;
+
+const IDENTITY_MATRIX: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+];
+
+/// An input mesh, borrowed from the caller. The indices refer to the vertices of this mesh.
+#[derive(Clone, Copy)]
+pub struct MeshRef<'a> {
+    pub vertices: &'a [FFIVector3],
+    pub indices: &'a [usize],
+    /// The world matrix of the mesh, row major: the translation is at the indices 3, 7 and 11
+    pub world_matrix: [f32; 16],
+    pub format: MeshFormat,
+}
+
+impl<'a> MeshRef<'a> {
+    /// A mesh with an identity world matrix
+    pub fn new(vertices: &'a [FFIVector3], indices: &'a [usize], format: MeshFormat) -> Self {
+        Self {
+            vertices,
+            indices,
+            world_matrix: IDENTITY_MATRIX,
+            format,
+        }
+    }
+
+    pub fn with_world_matrix(self, world_matrix: [f32; 16]) -> Self {
+        Self {
+            world_matrix,
+            ..self
+        }
+    }
+}
+
+/// The result of a command
+#[derive(Clone)]
+pub struct MeshOwned {
+    pub vertices: Vec<FFIVector3>,
+    pub indices: Vec<usize>,
+    /// The world matrix of the result, row major: the translation is at the indices 3, 7 and 11
+    pub world_matrix: Vec<f32>,
+    /// The returned config: the mesh format, the segments and the command specific values
+    pub config: Config,
+    pub attributes: Attributes,
+}
+
+impl MeshOwned {
+    /// Returns the mesh format of the result (of the first segment, if there are several)
+    pub fn format(&self) -> Result<MeshFormat, HallrError> {
+        self.config
+            .get("mesh.format")
+            .ok_or_else(|| HallrError::NoData("The result has no mesh.format".to_string()))?
+            .parse()
+    }
+}
+
+/// The type of a parameter, as reported by [`list_commands()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamType {
+    Float,
+    Int,
+    Bool,
+    String,
+    /// Comma separated numbers
+    FloatList,
+    /// One of the values
+    Enum(&'static [&'static str]),
+    /// Comma separated values
+    EnumList(&'static [&'static str]),
+}
+
+impl std::fmt::Display for ParamType {
+    /// `float`, `int`, `bool`, `string`, `float_list`, `enum(A|B)` or `enum_list(A|B)`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Float => write!(f, "float"),
+            Self::Int => write!(f, "int"),
+            Self::Bool => write!(f, "bool"),
+            Self::String => write!(f, "string"),
+            Self::FloatList => write!(f, "float_list"),
+            Self::Enum(values) => write!(f, "enum({})", values.join("|")),
+            Self::EnumList(values) => write!(f, "enum_list({})", values.join("|")),
+        }
+    }
+}
+
+/// A parameter of a command, as reported by [`list_commands()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamInfo {
+    /// The config key
+    pub key: &'static str,
+    pub param_type: ParamType,
+    /// False if the command has a default for the parameter
+    pub required: bool,
+    /// The bounds of the valid values, for the parameters limited to a range
+    pub range: Option<(f64, f64)>,
+}
+
+/// A command and its parameters, as reported by [`list_commands()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandInfo {
+    pub name: &'static str,
+    /// The supported versions, the last one is the default
+    pub versions: &'static [u32],
+    /// The accepted formats of the first input mesh, empty if the command accepts any format
+    pub formats: &'static [MeshFormat],
+    /// The command specific parameters. The global options (`UNIT_SCALE`, `POST`, ...) are
+    /// accepted by every command and not listed.
+    pub params: Vec<ParamInfo>,
+}
+
+/// A value that can be written into the string config
+pub trait OptionValue {
+    /// The type of the parameter
+    const TYPE: ParamType;
+    /// False for the optional parameters
+    const REQUIRED: bool = true;
+
+    /// Insert the value under `key`, nothing is inserted for absent optional values
+    fn write_option(&self, key: &str, config: &mut Config);
+}
+
+macro_rules! impl_option_value {
+    ($($t:ty => $param_type:ident),*) => {
+        $(
+            impl OptionValue for $t {
+                const TYPE: ParamType = ParamType::$param_type;
+
+                fn write_option(&self, key: &str, config: &mut Config) {
+                    let _ = config.insert(key.to_string(), self.to_string());
+                }
+            }
+        )*
+    };
+}
+impl_option_value!(
+    f32 => Float,
+    f64 => Float,
+    bool => Bool,
+    u32 => Int,
+    u64 => Int,
+    usize => Int,
+    String => String
+);
+
+impl<T: OptionValue> OptionValue for Option<T> {
+    const TYPE: ParamType = T::TYPE;
+    const REQUIRED: bool = false;
+
+    fn write_option(&self, key: &str, config: &mut Config) {
+        if let Some(value) = self {
+            value.write_option(key, config);
+        }
+    }
+}
+
+impl OptionValue for Vec<f32> {
+    const TYPE: ParamType = ParamType::FloatList;
+
+    fn write_option(&self, key: &str, config: &mut Config) {
+        let values: Vec<String> = self.iter().map(|v| v.to_string()).collect();
+        let _ = config.insert(key.to_string(), values.join(","));
+    }
+}
+
+/// The parameters of a command
+pub trait CommandParams {
+    /// The name of the command
+    const COMMAND: &'static str;
+    /// The accepted formats of the first input mesh, empty if the command accepts any format
+    const FORMATS: &'static [MeshFormat];
+    /// Insert the parameters into the command config
+    fn write_options(&self, config: &mut Config);
+    /// Describe the parameters
+    fn params() -> Vec<ParamInfo>;
+}
+
+/// Run the command of `params` on the `meshes`
+pub fn run<P: CommandParams>(params: &P, meshes: &[MeshRef<'_>]) -> Result<MeshOwned, HallrError> {
+    run_with_attributes(params, meshes, &Attributes::new())
+}
+
+/// Run the command of `params` on the `meshes`, with binary attribute channels as input (e.g.
+/// the scalars of `scalar_to_color`)
+pub fn run_with_attributes<P: CommandParams>(
+    params: &P,
+    meshes: &[MeshRef<'_>],
+    attributes: &Attributes,
+) -> Result<MeshOwned, HallrError> {
+    let mut config = Config::new();
+    params.write_options(&mut config);
+    run_config(P::COMMAND, config, meshes, attributes)
+}
+
+/// Run a command with a hand written config, for options without a typed parameter. The
+/// `command` and the mesh keys are inserted into the config.
+pub fn run_config(
+    command: &str,
+    mut config: Config,
+    meshes: &[MeshRef<'_>],
+    attributes: &Attributes,
+) -> Result<MeshOwned, HallrError> {
+    let first = meshes.first().ok_or_else(|| {
+        HallrError::InvalidInputData("At least one input mesh is required".to_string())
+    })?;
+    let _ = config.insert("command".to_string(), command.to_string());
+    let _ = config.insert("mesh.format".to_string(), first.format.to_string());
+
+    // the meshes are packed the same way as the addon does it
+    let mut vertices = Vec::<FFIVector3>::new();
+    let mut indices = Vec::<usize>::new();
+    let mut matrices = Vec::<f32>::new();
+    for (i, mesh) in meshes.iter().enumerate() {
+        if i > 0 {
+            let _ = config.insert(
+                format!("first_vertex_model_{}", i),
+                vertices.len().to_string(),
+            );
+            let _ = config.insert(
+                format!("first_index_model_{}", i),
+                indices.len().to_string(),
+            );
+        }
+        vertices.extend_from_slice(mesh.vertices);
+        indices.extend_from_slice(mesh.indices);
+        matrices.extend_from_slice(&mesh.world_matrix);
+    }
+    command::progress::clear_cancel();
+    let ((vertices, indices, world_matrix, config), attributes) =
+        command::process_command(&vertices, &indices, &matrices, attributes, config)?;
+    Ok(MeshOwned {
+        vertices,
+        indices,
+        world_matrix,
+        config,
+        attributes,
+    })
+}
+
+/// Returns the voxel and chunk lattice `sdf_mesh` would use for a skeleton with the bounding box
+/// `aabb_min`..`aabb_max`, without meshing anything. Use it to choose `sdf_divisions` or to warn
+/// before an expensive run, [`SdfLattice::vertex_estimate()`] gives a rough output size.
+pub fn sdf_mesh_lattice(
+    params: &SdfMeshParams,
+    aabb_min: FFIVector3,
+    aabb_max: FFIVector3,
+) -> Result<SdfLattice, HallrError> {
+    let mut config = Config::new();
+    params.write_options(&mut config);
+    command::sdf_mesh_lattice(&config, aabb_min, aabb_max)
+}
+
+/// Returns every command with its versions, the accepted mesh formats and the parameters, e.g.
+/// to generate a user interface or to validate a config before running the command
+pub fn list_commands() -> Vec<CommandInfo> {
+    let mut typed = typed_commands();
+    command::dispatch::builtins()
+        .map(|command| {
+            let name = command.name();
+            let versions = command.versions();
+            match typed.iter().position(|info| info.name == name) {
+                Some(index) => CommandInfo {
+                    versions,
+                    ..typed.swap_remove(index)
+                },
+                // every command is described by `command_params!`, the tests make sure of it
+                None => CommandInfo {
+                    name,
+                    versions,
+                    formats: &[],
+                    params: vec![],
+                },
+            }
+        })
+        .collect()
+}
+
+/// Declare enumerated option values
+macro_rules! option_enum {
+    ($(
+        $(#[$meta:meta])*
+        $name:ident { $($(#[$variant_meta:meta])* $variant:ident => $value:literal),* $(,)? }
+    )*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub enum $name {
+                $($(#[$variant_meta])* $variant),*
+            }
+
+            impl $name {
+                /// The config values of the variants
+                pub const VALUES: &'static [&'static str] = &[$($value),*];
+
+                pub fn as_str(&self) -> &'static str {
+                    match self {
+                        $(Self::$variant => $value),*
+                    }
+                }
+            }
+
+            /// The default is the first variant
+            impl Default for $name {
+                fn default() -> Self {
+                    [$(Self::$variant),*][0]
+                }
+            }
+
+            impl OptionValue for $name {
+                const TYPE: ParamType = ParamType::Enum(Self::VALUES);
+
+                fn write_option(&self, key: &str, config: &mut Config) {
+                    let _ = config.insert(key.to_string(), self.as_str().to_string());
+                }
+            }
+        )*
+    };
+}
+
+option_enum! {
+    /// The side of a closed curve
+    Side { Inside => "INSIDE", Outside => "OUTSIDE" }
+    /// The region of a triangulation or a scan
+    Bounds {
+        ConvexHull => "CONVEX_HULL",
+        Aabb => "AABB",
+        /// The edges of model 1 are constraints of the triangulation, not supported by the scans
+        Constrained => "CONSTRAINED",
+    }
+    ChamferTool { VBit => "V_BIT", Roundover => "ROUNDOVER" }
+    FilletCorners { All => "ALL", Internal => "INTERNAL", External => "EXTERNAL" }
+    HatchPattern {
+        Parallel => "PARALLEL",
+        Crosshatch => "CROSSHATCH",
+        Concentric => "CONCENTRIC",
+        Hilbert => "HILBERT",
+    }
+    FileMode { Load => "LOAD", Save => "SAVE" }
+    StlFormat { Binary => "BINARY", Ascii => "ASCII" }
+    ColorMap { Viridis => "VIRIDIS", Coolwarm => "COOLWARM" }
+    SdfBackend { Cpu => "cpu", Gpu => "gpu" }
+    SignMethod { Winding => "WINDING", Normal => "NORMAL" }
+    VoxelOutput { Cubes => "CUBES", Wireframe => "WIREFRAME" }
+    ProbeShape {
+        BallNose => "BALL_NOSE",
+        SquareEnd => "SQUARE_END",
+        TaperedEnd => "TAPERED_END",
+        Torus => "TORUS",
+        DragKnife => "DRAG_KNIFE",
+    }
+    ScanPattern { Meander => "MEANDER", Triangulation => "TRIANGULATION", Grid => "GRID" }
+    PassOrder { Zigzag => "ZIGZAG", Unidirectional => "UNIDIRECTIONAL" }
+    ScanOutput { Mesh => "MESH", Gcode => "GCODE" }
+    StartCorner {
+        MinXMinY => "MIN_X_MIN_Y",
+        MaxXMinY => "MAX_X_MIN_Y",
+        MinXMaxY => "MIN_X_MAX_Y",
+        MaxXMaxY => "MAX_X_MAX_Y",
+    }
+    CutDirection { Climb => "CLIMB", Conventional => "CONVENTIONAL" }
+    BooleanOperation {
+        Union => "UNION",
+        Difference => "DIFFERENCE",
+        Intersection => "INTERSECTION",
+    }
+    OffsetJoin { Miter => "MITER", Round => "ROUND", Bevel => "BEVEL" }
+    PocketPattern { Spiral => "SPIRAL", Zigzag => "ZIGZAG" }
+    /// The mapping of the input coordinates to the integer coordinates of the voronoi commands
+    VoronoiSnapping { Round => "ROUND", Floor => "FLOOR", Grid => "GRID" }
+}
+
+impl OptionValue for Vec<BooleanOperation> {
+    const TYPE: ParamType = ParamType::EnumList(BooleanOperation::VALUES);
+
+    fn write_option(&self, key: &str, config: &mut Config) {
+        let values: Vec<&str> = self.iter().map(|v| v.as_str()).collect();
+        let _ = config.insert(key.to_string(), values.join(","));
+    }
+}
+
+/// Declare the parameter struct and the function of commands, and the list of the commands
+macro_rules! command_params {
+    ($(
+        $(#[$meta:meta])*
+        $name:ident => $command:literal, fn $function:ident [$($format:ident),*] {
+            $(
+                $(#[$field_meta:meta])*
+                $field:ident: $field_type:ty => $key:literal $(in $min:literal..=$max:literal)?
+            ),* $(,)?
+        }
+    )*) => {
+        $(
+            #[doc = concat!("The parameters of the `", $command, "` command")]
+            $(#[$meta])*
+            #[derive(Debug, Clone, Default, PartialEq)]
+            pub struct $name {
+                $($(#[$field_meta])* pub $field: $field_type),*
+            }
+
+            impl CommandParams for $name {
+                const COMMAND: &'static str = $command;
+                const FORMATS: &'static [MeshFormat] = &[$(MeshFormat::$format),*];
+
+                #[allow(unused_variables)]
+                fn write_options(&self, config: &mut Config) {
+                    $(self.$field.write_option($key, config);)*
+                }
+
+                fn params() -> Vec<ParamInfo> {
+                    vec![$(ParamInfo {
+                        key: $key,
+                        param_type: <$field_type as OptionValue>::TYPE,
+                        required: <$field_type as OptionValue>::REQUIRED,
+                        range: None $(.or(Some(($min, $max))))?,
+                    }),*]
+                }
+            }
+
+            #[doc = concat!("Run the `", $command, "` command")]
+            pub fn $function(
+                params: &$name,
+                meshes: &[MeshRef<'_>],
+            ) -> Result<MeshOwned, HallrError> {
+                run(params, meshes)
+            }
+        )*
+
+        /// Describe the commands of the typed API, without their versions
+        fn typed_commands() -> Vec<CommandInfo> {
+            vec![$(CommandInfo {
+                name: $command,
+                versions: &[],
+                formats: $name::FORMATS,
+                params: $name::params(),
+            }),*]
+        }
+    };
+}
+
+command_params! {
+    /// Model 0 is the boundary, the surface is probed with a tool of model 1.
+    SurfaceScanParams => "surface_scan", fn surface_scan [Triangulated] {
+        probe: ProbeShape => "probe",
+        probe_radius: f32 => "probe_radius",
+        /// The angle of the tapered end probe, in degrees
+        probe_angle: Option<f32> => "probe_angle",
+        /// The corner radius of the torus probe, `probe_radius` is its major radius
+        probe_minor_radius: Option<f32> => "probe_minor_radius",
+        /// The grid spacing of the torus probe heights, defaults to half of the step
+        probe_sample_step: Option<f32> => "probe_sample_step",
+        /// The distance from the swivel axis of the drag knife to the blade tip
+        blade_offset: Option<f32> => "blade_offset",
+        minimum_z: f32 => "minimum_z",
+        step: f32 => "step",
+        pattern: ScanPattern => "pattern",
+        bounds: Bounds => "bounds",
+        floor_z: Option<f32> => "FLOOR_Z",
+        fail_on_clamp: Option<bool> => "FAIL_ON_CLAMP",
+        step_x: Option<f32> => "step_x",
+        step_y: Option<f32> => "step_y",
+        /// The rotation of the lattice of the triangulation pattern, in degrees
+        lattice_angle: Option<f32> => "lattice_angle",
+        /// Enables the adaptive search, together with the next two parameters
+        xy_sample_dist_multiplier: Option<f32> => "xy_sample_dist_multiplier",
+        z_jump_threshold_multiplier: Option<f32> => "z_jump_threshold_multiplier",
+        reduce_adaptive: Option<bool> => "reduce_adaptive",
+        pass_order: Option<PassOrder> => "pass_order",
+        start_corner: Option<StartCorner> => "start_corner",
+        direction: Option<CutDirection> => "direction",
+        return_z: Option<f32> => "return_z",
+        /// Enables the curvature adaptive stepover of the meander pattern
+        max_chord_error: Option<f32> => "max_chord_error",
+        /// The smallest adaptive stepover, defaults to a quarter of the step
+        min_step: Option<f32> => "min_step",
+        /// The index of the nominal CAD mesh, a model after the bounding shape
+        nominal_model: Option<usize> => "NOMINAL_MODEL",
+        /// Also export the meander toolpath as G-code
+        output: Option<ScanOutput> => "OUTPUT",
+        /// The G-code file, the program is returned in the config when not set
+        file_path: Option<String> => "FILE_PATH",
+        /// In mm/min
+        feed_rate: Option<f64> => "FEED_RATE",
+        /// In mm/min
+        plunge_rate: Option<f64> => "PLUNGE_RATE",
+        safe_z: Option<f64> => "SAFE_Z",
+        /// In RPM
+        spindle_speed: Option<f64> => "SPINDLE_SPEED",
+    }
+    ConvexHull2dParams => "convex_hull_2d", fn convex_hull_2d [] {}
+    SimplifyRdpParams => "simplify_rdp", fn simplify_rdp [LineChunks] {
+        simplify_distance: f32 => "simplify_distance",
+        simplify_3d: Option<bool> => "simplify_3d",
+    }
+    DelaunayTriangulation2dParams => "2d_delaunay_triangulation", fn delaunay_triangulation_2d [] {
+        bounds: Bounds => "bounds",
+        /// Remove the triangles outside of the `Bounds::Constrained` loops
+        remove_outside: Option<bool> => "REMOVE_OUTSIDE",
+    }
+    CenterlineParams => "centerline", fn centerline [LineChunks] {
+        /// The maximum angle of the input edges to the centerline, in degrees 0..=90
+        angle: f64 => "ANGLE" in 0.0..=90.0,
+        /// The discretization distance of curved edges, in percent of the longest axis
+        distance: f64 => "DISTANCE" in 0.001..=100.0,
+        remove_internals: Option<bool> => "REMOVE_INTERNALS",
+        max_voronoi_dimension: Option<f64> => "MAX_VORONOI_DIMENSION",
+        simplify: Option<bool> => "SIMPLIFY",
+        weld: Option<bool> => "WELD",
+        keep_input: Option<bool> => "KEEP_INPUT",
+        negative_radius: Option<bool> => "NEGATIVE_RADIUS",
+        bezier_tolerance: Option<f64> => "BEZIER_TOLERANCE",
+        /// Accept open polylines, replaced by corridors this far to both sides of them
+        corridor_offset: Option<f64> => "CORRIDOR_OFFSET",
+        /// Return a triangulated V-carve preview instead of the centerline edges
+        v_carve_mesh: Option<bool> => "V_CARVE_MESH",
+        voronoi_snapping: Option<VoronoiSnapping> => "VORONOI_SNAPPING",
+        /// The grid size of `VoronoiSnapping::Grid`, in model units
+        voronoi_snap_grid: Option<f64> => "VORONOI_SNAP_GRID",
+    }
+    Outline2dParams => "2d_outline", fn outline_2d [Triangulated] {}
+    KnifeIntersectParams => "knife_intersect", fn knife_intersect [LineChunks] {}
+    VoronoiMeshParams => "voronoi_mesh", fn voronoi_mesh [LineChunks] {
+        max_voronoi_dimension: Option<f64> => "MAX_VORONOI_DIMENSION",
+        distance: Option<f64> => "DISTANCE",
+        negative_radius: Option<bool> => "NEGATIVE_RADIUS",
+        local_frame: Option<bool> => "LOCAL_FRAME",
+        distance_texture_resolution: Option<usize> => "DISTANCE_TEXTURE_RESOLUTION",
+        distance_texture_path: Option<String> => "DISTANCE_TEXTURE_PATH",
+        /// Return the voronoi cell id of every triangle as a binary attribute
+        cell_ids: Option<bool> => "CELL_IDS",
+        voronoi_snapping: Option<VoronoiSnapping> => "VORONOI_SNAPPING",
+        /// The grid size of `VoronoiSnapping::Grid`, in model units
+        voronoi_snap_grid: Option<f64> => "VORONOI_SNAP_GRID",
+    }
+    VoronoiDiagramParams => "voronoi_diagram", fn voronoi_diagram [LineChunks] {
+        max_voronoi_dimension: Option<f64> => "MAX_VORONOI_DIMENSION",
+        distance: Option<f64> => "DISTANCE",
+        keep_input: Option<bool> => "KEEP_INPUT",
+        medial_axis_min_clearance: Option<f64> => "MEDIAL_AXIS_MIN_CLEARANCE",
+        /// In degrees
+        medial_axis_min_angle: Option<f64> => "MEDIAL_AXIS_MIN_ANGLE",
+        local_frame: Option<bool> => "LOCAL_FRAME",
+        /// Clip the diagram against model 1, a closed polygon
+        clip_boundary: Option<bool> => "CLIP_BOUNDARY",
+        voronoi_snapping: Option<VoronoiSnapping> => "VORONOI_SNAPPING",
+        /// The grid size of `VoronoiSnapping::Grid`, in model units
+        voronoi_snap_grid: Option<f64> => "VORONOI_SNAP_GRID",
+    }
+    SdfMesh25Params => "sdf_mesh_2_5", fn sdf_mesh_2_5 [LineChunks] {
+        sdf_divisions: f32 => "SDF_DIVISIONS",
+        sdf_chunk_side: Option<u32> => "SDF_CHUNK_SIDE",
+        debug_chunks: Option<bool> => "DEBUG_CHUNKS",
+        local_frame: Option<bool> => "LOCAL_FRAME",
+    }
+    SdfMeshParams => "sdf_mesh", fn sdf_mesh [LineChunks] {
+        /// The tube radius, in percent of the longest axis
+        sdf_radius_multiplier: f32 => "SDF_RADIUS_MULTIPLIER",
+        sdf_divisions: f32 => "SDF_DIVISIONS",
+        /// Overrides `sdf_divisions` along one axis, for anisotropic voxels
+        sdf_divisions_x: Option<f32> => "SDF_DIVISIONS_X",
+        sdf_divisions_y: Option<f32> => "SDF_DIVISIONS_Y",
+        sdf_divisions_z: Option<f32> => "SDF_DIVISIONS_Z",
+        sdf_chunk_side: Option<u32> => "SDF_CHUNK_SIDE",
+        sdf_backend: Option<SdfBackend> => "SDF_BACKEND",
+        multi_offsets: Option<Vec<f32>> => "MULTI_OFFSETS",
+        adaptive: Option<bool> => "ADAPTIVE",
+        debug_chunks: Option<bool> => "DEBUG_CHUNKS",
+        local_frame: Option<bool> => "LOCAL_FRAME",
+        /// Only return the predicted lattice and vertex estimate, nothing is meshed
+        dry_run: Option<bool> => "DRY_RUN",
+        /// Return the mesh in capped parts of at most this many vertices
+        split_max_vertices: Option<usize> => "SPLIT_MAX_VERTICES",
+    }
+    DiscretizeParams => "discretize", fn discretize [LineChunks] {
+        /// In percent of the longest axis
+        discretize_length: f32 => "discretize_length",
+    }
+    VisibilityPolygon2dParams => "visibility_polygon_2d", fn visibility_polygon_2d [LineChunks] {}
+    MinkowskiParams => "minkowski", fn minkowski [LineChunks] {}
+    ClipCurvesParams => "clip_curves", fn clip_curves [LineChunks] {
+        clip_mode: Option<Side> => "CLIP_MODE",
+    }
+    HatchParams => "hatch", fn hatch [LineChunks] {
+        hatch_spacing: f64 => "HATCH_SPACING",
+        hatch_pattern: HatchPattern => "HATCH_PATTERN",
+        /// In degrees
+        hatch_angle: Option<f64> => "HATCH_ANGLE",
+    }
+    OptimizePathParams => "optimize_path", fn optimize_path [LineChunks] {
+        allow_reverse: Option<bool> => "ALLOW_REVERSE",
+        time_budget_ms: Option<u64> => "TIME_BUDGET_MS",
+    }
+    CompareParams => "compare", fn compare [Triangulated] {
+        distance_channel: Option<bool> => "DISTANCE_CHANNEL",
+    }
+    ChamferParams => "chamfer", fn chamfer [LineChunks] {
+        chamfer_width: f64 => "CHAMFER_WIDTH",
+        chamfer_tool: ChamferTool => "CHAMFER_TOOL",
+        chamfer_side: Option<Side> => "CHAMFER_SIDE",
+        /// In degrees
+        v_bit_angle: Option<f64> => "V_BIT_ANGLE" in 0.0..=180.0,
+        tip_offset: Option<f64> => "TIP_OFFSET",
+        /// Mandatory for the roundover tool
+        roundover_radius: Option<f64> => "ROUNDOVER_RADIUS",
+        bearing_radius: Option<f64> => "BEARING_RADIUS",
+    }
+    SolidifyParams => "solidify", fn solidify [Triangulated] {
+        thickness: f64 => "THICKNESS",
+        solidify_offset: Option<f64> => "SOLIDIFY_OFFSET",
+    }
+    /// The scalars are read from the attribute channel `scalar_channel`, see
+    /// [`run_with_attributes()`]
+    ScalarToColorParams => "scalar_to_color", fn scalar_to_color [] {
+        color_map: ColorMap => "COLOR_MAP",
+        scalar_channel: Option<String> => "SCALAR_CHANNEL",
+        scalar_min: Option<f32> => "SCALAR_MIN",
+        scalar_max: Option<f32> => "SCALAR_MAX",
+    }
+    FilletParams => "fillet", fn fillet [LineChunks] {
+        fillet_radius: f64 => "FILLET_RADIUS",
+        fillet_corners: Option<FilletCorners> => "FILLET_CORNERS",
+        /// In degrees
+        fillet_max_angle: Option<f64> => "FILLET_MAX_ANGLE",
+    }
+    FeatureCheckParams => "feature_check", fn feature_check [LineChunks] {
+        tool_diameter: f64 => "TOOL_DIAMETER",
+        check_side: Option<Side> => "CHECK_SIDE",
+    }
+    SymmetryParams => "symmetry", fn symmetry [] {
+        symmetry_tolerance: Option<f64> => "SYMMETRY_TOLERANCE",
+        symmetry_min_score: Option<f64> => "SYMMETRY_MIN_SCORE",
+        symmetry_snap: Option<bool> => "SYMMETRY_SNAP",
+    }
+    ObjIoParams => "obj_io", fn obj_io [] {
+        obj_mode: FileMode => "OBJ_MODE",
+        obj_path: String => "OBJ_PATH",
+        obj_name: Option<String> => "OBJ_NAME",
+    }
+    StlIoParams => "stl_io", fn stl_io [] {
+        stl_mode: FileMode => "STL_MODE",
+        stl_path: String => "STL_PATH",
+        stl_format: Option<StlFormat> => "STL_FORMAT",
+        stl_scale: Option<f32> => "STL_SCALE",
+        stl_name: Option<String> => "STL_NAME",
+    }
+    VoxelPreviewParams => "voxel_preview", fn voxel_preview [Triangulated, LineChunks] {
+        voxel_divisions: Option<f64> => "VOXEL_DIVISIONS",
+        voxel_output: Option<VoxelOutput> => "VOXEL_OUTPUT",
+        /// Mandatory for line_chunks input, the tube radius in percent of the longest axis
+        sdf_radius_multiplier: Option<f64> => "SDF_RADIUS_MULTIPLIER",
+    }
+    SdfRemeshParams => "sdf_remesh", fn sdf_remesh [Triangulated] {
+        sdf_divisions: f64 => "SDF_DIVISIONS",
+        sdf_offset: Option<f64> => "SDF_OFFSET",
+        sdf_sign_method: Option<SignMethod> => "SDF_SIGN_METHOD",
+    }
+    FitPrimitivesParams => "fit_primitives", fn fit_primitives [Triangulated] {
+        /// In degrees
+        fit_angle: Option<f64> => "FIT_ANGLE" in 0.0..=180.0,
+        fit_min_faces: Option<usize> => "FIT_MIN_FACES",
+        fit_tolerance: Option<f64> => "FIT_TOLERANCE",
+    }
+    OrientOutlinesParams => "orient_outlines", fn orient_outlines [LineChunks] {}
+    SdfBooleanParams => "sdf_boolean", fn sdf_boolean [Triangulated] {
+        /// One operation per mesh after the first, applied left to right
+        operations: Vec<BooleanOperation> => "OPERATIONS",
+        sdf_divisions: f64 => "SDF_DIVISIONS",
+        sdf_sign_method: Option<SignMethod> => "SDF_SIGN_METHOD",
+    }
+    CentroidalRemeshParams => "centroidal_remesh", fn centroidal_remesh [LineChunks] {
+        cvt_edge_length: Option<f64> => "CVT_EDGE_LENGTH",
+        cvt_iterations: Option<usize> => "CVT_ITERATIONS",
+    }
+    Offset2dParams => "offset_2d", fn offset_2d [LineChunks] {
+        /// Positive distances grow the region, negative distances shrink it
+        offset_distance: f64 => "OFFSET_DISTANCE",
+        offset_join: Option<OffsetJoin> => "OFFSET_JOIN",
+        offset_miter_limit: Option<f64> => "OFFSET_MITER_LIMIT",
+        offset_arc_segments: Option<usize> => "OFFSET_ARC_SEGMENTS",
+        offset_count: Option<usize> => "OFFSET_COUNT",
+    }
+    PocketParams => "pocket", fn pocket [LineChunks] {
+        pocket_stepover: f64 => "POCKET_STEPOVER",
+        /// Below the top of the pocket
+        pocket_depth: f64 => "POCKET_DEPTH",
+        pocket_depth_per_pass: Option<f64> => "POCKET_DEPTH_PER_PASS",
+        pocket_tool_radius: Option<f64> => "POCKET_TOOL_RADIUS",
+        pocket_pattern: Option<PocketPattern> => "POCKET_PATTERN",
+        /// In degrees, for the zig-zag pattern
+        pocket_angle: Option<f64> => "POCKET_ANGLE",
+        pocket_return_z: Option<f64> => "POCKET_RETURN_Z",
+    }
+    MeshAnalyzeParams => "mesh_analyze", fn mesh_analyze [Triangulated] {
+        check_self_intersections: Option<bool> => "CHECK_SELF_INTERSECTIONS",
+    }
+    SelfIntersectParams => "self_intersect", fn self_intersect [Triangulated] {
+        /// Return the mesh split along the intersections instead of the intersection segments
+        split_faces: Option<bool> => "SPLIT_FACES",
+    }
+    ProjectParams => "project", fn project [] {
+        /// Default straight down
+        project_direction: Option<Vec<f32>> => "PROJECT_DIRECTION",
+        /// Project against the vertex normals instead of along the direction
+        project_along_normals: Option<bool> => "PROJECT_ALONG_NORMALS",
+        project_both_ways: Option<bool> => "PROJECT_BOTH_WAYS",
+        project_max_distance: Option<f64> => "PROJECT_MAX_DISTANCE",
+        project_offset: Option<f64> => "PROJECT_OFFSET",
+    }
+}
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{CommandParams, Config, MeshFormat, MeshRef, ParamType};
+use crate::{ffi::FFIVector3, HallrError};
+
+fn unit_square(z: f32) -> Vec<FFIVector3> {
+    vec![
+        (0.0, 0.0, z).into(),
+        (1.0, 0.0, z).into(),
+        (1.0, 1.0, z).into(),
+        (0.0, 1.0, z).into(),
+    ]
+}
+
+#[test]
+fn test_api_write_options() {
+    let params = super::SdfMeshParams {
+        sdf_radius_multiplier: 1.5,
+        sdf_divisions: 50.0,
+        multi_offsets: Some(vec![0.0, -0.25]),
+        sdf_backend: Some(super::SdfBackend::Cpu),
+        ..Default::default()
+    };
+    let mut config = Config::new();
+    params.write_options(&mut config);
+    assert_eq!("sdf_mesh", super::SdfMeshParams::COMMAND);
+    assert_eq!(
+        Some(&"1.5".to_string()),
+        config.get("SDF_RADIUS_MULTIPLIER")
+    );
+    assert_eq!(Some(&"50".to_string()), config.get("SDF_DIVISIONS"));
+    assert_eq!(Some(&"0,-0.25".to_string()), config.get("MULTI_OFFSETS"));
+    assert_eq!(Some(&"cpu".to_string()), config.get("SDF_BACKEND"));
+    // the absent optional parameters are left to the command defaults
+    assert!(!config.contains_key("SDF_CHUNK_SIDE"));
+    assert!(!config.contains_key("ADAPTIVE"));
+    assert_eq!(4, config.len());
+}
+
+#[test]
+fn test_api_compare() -> Result<(), HallrError> {
+    let indices = [0, 1, 2, 0, 2, 3];
+    let (measured, reference) = (unit_square(0.5), unit_square(0.0));
+    let result = super::compare(
+        &super::CompareParams::default(),
+        &[
+            MeshRef::new(&measured, &indices, MeshFormat::Triangulated),
+            MeshRef::new(&reference, &indices, MeshFormat::Triangulated),
+        ],
+    )?;
+    assert_eq!(MeshFormat::Triangulated, result.format()?);
+    let max: f64 = result.config.get("DISTANCE_MAX").unwrap().parse().unwrap();
+    assert!((max - 0.5).abs() < 1e-6);
+    assert!(result.attributes.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_api_errors() {
+    // no input mesh
+    assert!(super::symmetry(&super::SymmetryParams::default(), &[]).is_err());
+    // a mandatory parameter is validated by the command
+    let vertices = unit_square(0.0);
+    let mesh = MeshRef::new(&vertices, &[0, 1, 1, 2, 2, 3, 3, 0], MeshFormat::LineChunks);
+    let params = super::FilletParams {
+        fillet_radius: -1.0,
+        ..Default::default()
+    };
+    assert!(super::fillet(&params, &[mesh]).is_err());
+}
+
+#[test]
+fn test_api_list_commands() {
+    let commands = super::list_commands();
+    // every command but the introspection itself
+    assert_eq!(crate::command::dispatch::builtins().count(), commands.len());
+    let sdf_boolean = commands.iter().find(|c| c.name == "sdf_boolean").unwrap();
+    assert_eq!(&[1], sdf_boolean.versions);
+    assert_eq!(&[MeshFormat::Triangulated], sdf_boolean.formats);
+    let operations = &sdf_boolean.params[0];
+    assert_eq!("OPERATIONS", operations.key);
+    assert!(operations.required);
+    assert_eq!(
+        ParamType::EnumList(&["UNION", "DIFFERENCE", "INTERSECTION"]),
+        operations.param_type
+    );
+    let fit_angle = super::FitPrimitivesParams::params()
+        .into_iter()
+        .find(|p| p.key == "FIT_ANGLE")
+        .unwrap();
+    assert_eq!(ParamType::Float, fit_angle.param_type);
+    assert!(!fit_angle.required);
+    assert_eq!(Some((0.0, 180.0)), fit_angle.range);
+}
+
+#[test]
+fn test_api_command_tables() {
+    // the registered commands, the versioned names and the typed descriptions cover the same
+    // commands
+    let mut builtins: Vec<&str> = crate::command::dispatch::builtins()
+        .map(|command| command.name())
+        .collect();
+    let mut versioned: Vec<&str> = crate::command::registry::commands()
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| *name != crate::command::registry::LIST_COMMANDS)
+        .collect();
+    let mut typed: Vec<&str> = super::typed_commands()
+        .into_iter()
+        .map(|info| info.name)
+        .collect();
+    builtins.sort_unstable();
+    versioned.sort_unstable();
+    typed.sort_unstable();
+    assert_eq!(builtins, typed);
+    // the commands of the plugins registered by other tests are versioned too
+    versioned.retain(|name| builtins.contains(name));
+    assert_eq!(builtins, versioned);
+}
@@ -4,22 +4,73 @@
 
 //! This module contains the execution of the implemented commands.
 
+pub(crate) mod attributes;
 mod cmd_2d_outline;
 mod cmd_centerline;
+mod cmd_centroidal_remesh;
+mod cmd_chamfer;
+mod cmd_clip_curves;
+mod cmd_compare;
 mod cmd_convex_hull_2d;
 mod cmd_delaunay_triangulation_2d;
 mod cmd_discretize;
+mod cmd_feature_check;
+mod cmd_fillet;
+mod cmd_fit_primitives;
+mod cmd_hatch;
 mod cmd_knife_intersect;
+mod cmd_mesh_analyze;
+mod cmd_minkowski;
+mod cmd_obj_io;
+mod cmd_offset_2d;
+mod cmd_optimize_path;
+mod cmd_orient_outlines;
+mod cmd_pocket;
+mod cmd_project;
+mod cmd_scalar_to_color;
+mod cmd_sdf_boolean;
 mod cmd_sdf_mesh;
 mod cmd_sdf_mesh_2_5;
+mod cmd_sdf_remesh;
+mod cmd_self_intersect;
 mod cmd_simplify_rdp;
+mod cmd_solidify;
+mod cmd_stl_io;
 pub mod cmd_surface_scan;
+mod cmd_symmetry;
+mod cmd_visibility_polygon_2d;
 mod cmd_voronoi_diagram;
 mod cmd_voronoi_mesh;
+mod cmd_voxel_preview;
 mod create_test;
+pub(crate) mod dispatch;
+#[cfg(test)]
+mod fixtures;
+mod gcode;
 mod impls;
+pub(crate) mod mesh_format;
+mod non_finite;
+pub(crate) mod origin_shift;
+mod output_stats;
+pub(crate) mod plugin;
+mod post_process;
+pub(crate) mod progress;
+mod quality_report;
+pub(crate) mod registry;
+pub(crate) mod result_cache;
+mod session;
+mod shading;
+mod unit_scale;
+mod weld;
 
-use crate::{ffi::FFIVector3, prelude::*};
+pub(crate) use cmd_sdf_mesh::sdf_mesh_lattice;
+pub use cmd_sdf_mesh::SdfLattice;
+
+use crate::{
+    ffi::FFIVector3,
+    logging::{self, LOG_LEVEL_KEY},
+    prelude::*,
+};
 use std::collections::HashMap;
 use vector_traits::{approx::ulps_eq, glam::Vec3A, GenericVector3};
 
@@ -30,6 +81,11 @@ const DEFAULT_MAX_VORONOI_DIMENSION: f32 = 200000.0;
 /// AABB axis of the object.
 const DEFAULT_VORONOI_DISCRETE_DISTANCE: f32 = 0.0001;
 
+/// The option used by the generator commands (sdf meshes, voronoi mesh and diagram) to return the
+/// result in the local frame of the input model. The input vertices are then expected in that
+/// local frame, and the world orientation of the input model is returned with the result.
+const LOCAL_FRAME_KEY: &str = "LOCAL_FRAME";
+
 type ConfigType = HashMap<String, String>;
 
 const IDENTITY_MATRIX: [f32; 16] = [
@@ -88,6 +144,16 @@ impl<'a> Model<'a> {
     pub fn has_identity_orientation(&self) -> bool {
         Self::is_identity_matrix(self.world_orientation)
     }
+
+    /// Returns the world orientation of a generator command result: the world orientation of
+    /// this model when `local_frame` is set, otherwise identity (the result is in world space).
+    pub fn output_orientation(&self, local_frame: bool) -> Result<[f32; 16], HallrError> {
+        if local_frame {
+            self.copy_world_orientation()
+        } else {
+            Ok(IDENTITY_MATRIX)
+        }
+    }
 }
 
 /// An owned variant of `Model`
@@ -214,39 +280,143 @@ pub fn collect_models<'a, T: GenericVector3>(
 
 /// This is the main FFI entry point, once the FFI module has sorted out all the messy c_ptr types
 /// it will forward all request here.
+/// The decoded binary attribute channels of the input are passed in `attributes`, the channels
+/// produced by the command are returned next to the result.
 pub(crate) fn process_command(
     vertices: &[FFIVector3],
     indices: &[usize],
     matrix: &[f32],
-    config: ConfigType,
-) -> Result<CommandResult, HallrError> {
+    attributes: &attributes::Attributes,
+    mut config: ConfigType,
+) -> Result<(CommandResult, attributes::Attributes), HallrError> {
     // the type we use for the internal processing
     type T = Vec3A;
 
+    // aliases and versioned names are resolved here, the commands only see the canonical name
+    let (command, version) = registry::resolve(config.get_mandatory_option("command")?)?;
+    if command == registry::LIST_COMMANDS {
+        return Ok((registry::list_commands(), attributes::Attributes::new()));
+    }
+    let _ = config.insert("command".to_string(), command.to_string());
+    let _ = config.insert(
+        registry::COMMAND_VERSION_KEY.to_string(),
+        version.to_string(),
+    );
+
+    logging::set_command_level(config.get_parsed_option(LOG_LEVEL_KEY)?);
     validate_input_data::<T>(vertices, indices, &config)?;
+
+    let cache_size_mb = config
+        .get_parsed_option::<usize>(result_cache::CACHE_SIZE_MB_KEY)?
+        .unwrap_or(0);
+    // the cache does not store the attribute channels, so it is bypassed when they are used, and
+    // the results with output channels are not stored. The commands with side effects always run.
+    let cache_key =
+        if cache_size_mb > 0 && attributes.is_empty() && !result_cache::has_side_effects(&config) {
+            let key = result_cache::hash_input(vertices, indices, matrix, &config);
+            if let Some(mut rv) = result_cache::lookup(key) {
+                let _ =
+                    rv.3.insert(result_cache::CACHE_HIT_KEY.to_string(), "true".to_string());
+                info!("Rust: returning a cached result");
+                return Ok((rv, attributes::Attributes::new()));
+            }
+            Some(key)
+        } else {
+            None
+        };
+
+    // the session is recorded with the input as it was received
+    let recording = config
+        .does_option_exist(session::RECORD_SESSION_DIR_KEY)?
+        .then(|| (vertices, indices, config.clone()));
+
+    // the shading channels are sized to the input as it was received
+    let (input_vertices, input_indices) = (vertices, indices);
+
+    // NaN, Inf and denormal vertices are handled here, once for every command
+    let sanitized = non_finite::sanitize_input(vertices, indices, &mut config)?;
+    let (vertices, indices) = match &sanitized.data {
+        Some((vertices, indices)) => (vertices.as_slice(), indices.as_slice()),
+        None => (vertices, indices),
+    };
+
+    // the input and the length parameters are scaled into the canonical unit, once for every
+    // command
+    let unit_scale = unit_scale::unit_scale(&config)?;
+    let scaled = unit_scale
+        .map(|scale| unit_scale::scale_input(vertices, matrix, &mut config, scale))
+        .transpose()?;
+    let (vertices, command_matrix) = match &scaled {
+        Some((vertices, matrix)) => (vertices.as_slice(), matrix.as_slice()),
+        None => (vertices, matrix),
+    };
+
+    let output_format =
+        config.get_parsed_option::<mesh_format::MeshFormat>(mesh_format::OUTPUT_FORMAT_KEY)?;
+    let report_quality = config
+        .get_mandatory_parsed_option::<bool>(quality_report::QUALITY_REPORT_KEY, Some(false))?;
+    let weld_in_rust =
+        config.get_mandatory_parsed_option::<bool>(weld::WELD_IN_RUST_KEY, Some(false))?;
+    let post_steps = config
+        .get_parsed_option::<String>(post_process::POST_KEY)?
+        .map(|steps| post_process::parse_steps(&steps))
+        .transpose()?;
+    let shading_config = config.clone();
+    let (mut rv, mut output_attributes) =
+        dispatch_command(vertices, indices, command_matrix, attributes, config)?;
+    if let Some(scale) = unit_scale {
+        unit_scale::scale_output(&mut rv, scale)?;
+    }
+    sanitized.report(&mut rv.3);
+    if weld_in_rust {
+        weld::weld_result(&mut rv)?;
+    }
+    shading::transfer_shading(
+        input_vertices,
+        input_indices,
+        &shading_config,
+        attributes,
+        &rv,
+        &mut output_attributes,
+    )?;
+    if let Some(steps) = post_steps {
+        post_process::apply(&mut rv, &steps, matrix)?;
+    }
+    if let Some(output_format) = output_format {
+        mesh_format::convert_result(&mut rv, output_format)?;
+    }
+    output_stats::add_stats(&mut rv)?;
+    if report_quality {
+        quality_report::add_quality_report(&mut rv)?;
+    }
+    if let Some((vertices, indices, config)) = recording {
+        match session::record(vertices, indices, matrix, attributes, &config, &rv) {
+            Ok(path) => info!("Rust: recorded the session in {}", path),
+            Err(err) => info!("Rust: could not record the session: {}", err),
+        }
+    }
+    if let Some(key) = cache_key.filter(|_| output_attributes.is_empty()) {
+        result_cache::store(key, &rv, cache_size_mb);
+    }
+    Ok((rv, output_attributes))
+}
+
+/// Run the command named by the "command" config key.
+/// Returns the result together with the binary attribute channels produced by the command.
+fn dispatch_command(
+    vertices: &[FFIVector3],
+    indices: &[usize],
+    matrix: &[f32],
+    attributes: &attributes::Attributes,
+    config: ConfigType,
+) -> Result<(CommandResult, attributes::Attributes), HallrError> {
+    // the type we use for the internal processing
+    type T = Vec3A;
+
     let models = collect_models::<T>(vertices, indices, matrix, &config)?;
 
     if false {
         create_test::process_command(&config, &models)?
     }
-    Ok(match config.get_mandatory_option("command")? {
-        "surface_scan" => cmd_surface_scan::process_command::<T>(config, models)?,
-        "convex_hull_2d" => cmd_convex_hull_2d::process_command::<T>(config, models)?,
-        "simplify_rdp" => cmd_simplify_rdp::process_command::<T>(config, models)?,
-        "2d_delaunay_triangulation" => {
-            cmd_delaunay_triangulation_2d::process_command::<T>(config, models)?
-        }
-        "centerline" => cmd_centerline::process_command::<T>(config, models)?,
-        "2d_outline" => cmd_2d_outline::process_command::<T>(config, models)?,
-        "knife_intersect" => cmd_knife_intersect::process_command::<T>(config, models)?,
-        "voronoi_mesh" => cmd_voronoi_mesh::process_command(config, models)?,
-        "voronoi_diagram" => cmd_voronoi_diagram::process_command(config, models)?,
-        "sdf_mesh_2_5" => cmd_sdf_mesh_2_5::process_command(config, models)?,
-        "sdf_mesh" => cmd_sdf_mesh::process_command(config, models)?,
-        "discretize" => cmd_discretize::process_command(config, models)?,
-        illegal_command => Err(HallrError::InvalidParameter(format!(
-            "Invalid command:{}",
-            illegal_command
-        )))?,
-    })
+    dispatch::dispatch_command(config, models, attributes)
 }
@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! The binary attribute channel.
+//!
+//! Large numeric side channels (weights, radii, selections, colors...) can be passed to and from
+//! `process_geometry()` as named arrays of `f32`, instead of being stringified into the config.
+//! The channels are packed into one blob, all the values are little endian:
+//! * the magic bytes `HATR`
+//! * the format version, a `u32` (1)
+//! * the number of channels, a `u32`
+//! * for every channel: the length of the name in bytes (`u32`), the UTF-8 name, the number of
+//!   values (`u32`) and the values (`f32`)
+//!
+//! An empty blob is the same as no channels at all.
+
+#[cfg(test)]
+mod tests;
+
+use crate::HallrError;
+use std::collections::BTreeMap;
+
+/// Named arrays of floats, sorted by name so that the encoding is deterministic
+pub(crate) type Attributes = BTreeMap<String, Vec<f32>>;
+
+const MAGIC: &[u8; 4] = b"HATR";
+const VERSION: u32 = 1;
+
+/// Reads the little endian numbers of the blob, and keeps track of the position
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], HallrError> {
+        let rv = self
+            .position
+            .checked_add(count)
+            .and_then(|end| self.data.get(self.position..end))
+            .ok_or_else(|| {
+                HallrError::InvalidInputData(format!(
+                    "The attribute blob is truncated at byte {}",
+                    self.position
+                ))
+            })?;
+        self.position += count;
+        Ok(rv)
+    }
+
+    fn u32(&mut self) -> Result<u32, HallrError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// Decode the attribute blob
+pub(crate) fn decode(blob: &[u8]) -> Result<Attributes, HallrError> {
+    let mut rv = Attributes::new();
+    if blob.is_empty() {
+        return Ok(rv);
+    }
+    let mut reader = Reader {
+        data: blob,
+        position: 0,
+    };
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(HallrError::InvalidInputData(
+            "The attribute blob does not start with a valid header".to_string(),
+        ));
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        return Err(HallrError::InvalidInputData(format!(
+            "Unsupported attribute blob version: {}",
+            version
+        )));
+    }
+    let count = reader.u32()?;
+    for _ in 0..count {
+        let name_length = reader.u32()? as usize;
+        let name = std::str::from_utf8(reader.bytes(name_length)?)
+            .map_err(|_| {
+                HallrError::InvalidInputData(
+                    "An attribute name of the blob is not valid UTF-8".to_string(),
+                )
+            })?
+            .to_string();
+        let value_count = reader.u32()? as usize;
+        let values = reader
+            .bytes(value_count.checked_mul(4).ok_or_else(|| {
+                HallrError::InvalidInputData(format!("The attribute {} is too large", name))
+            })?)?
+            .chunks_exact(4)
+            .map(|v| f32::from_le_bytes([v[0], v[1], v[2], v[3]]))
+            .collect();
+        if rv.insert(name.clone(), values).is_some() {
+            return Err(HallrError::InvalidInputData(format!(
+                "The attribute {} is defined more than once",
+                name
+            )));
+        }
+    }
+    if reader.position != blob.len() {
+        return Err(HallrError::InvalidInputData(format!(
+            "The attribute blob has {} trailing bytes",
+            blob.len() - reader.position
+        )));
+    }
+    Ok(rv)
+}
+
+/// Encode the attributes into a blob. No channels at all are encoded as an empty blob.
+pub(crate) fn encode(attributes: &Attributes) -> Vec<u8> {
+    let mut rv = Vec::new();
+    if attributes.is_empty() {
+        return rv;
+    }
+    rv.extend_from_slice(MAGIC);
+    rv.extend_from_slice(&VERSION.to_le_bytes());
+    rv.extend_from_slice(&(attributes.len() as u32).to_le_bytes());
+    for (name, values) in attributes.iter() {
+        rv.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        rv.extend_from_slice(name.as_bytes());
+        rv.extend_from_slice(&(values.len() as u32).to_le_bytes());
+        for v in values.iter() {
+            rv.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    rv
+}
@@ -0,0 +1,50 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{decode, encode, Attributes};
+use crate::HallrError;
+
+#[test]
+fn test_attributes_roundtrip() -> Result<(), HallrError> {
+    let mut attributes = Attributes::new();
+    let _ = attributes.insert("weights".to_string(), vec![0.0, 0.5, 1.0, f32::NAN]);
+    let _ = attributes.insert("radius".to_string(), vec![]);
+    let _ = attributes.insert("ö".to_string(), vec![-1.5e-8]);
+    let blob = encode(&attributes);
+    assert_eq!(b"HATR", &blob[0..4]);
+    let decoded = decode(&blob)?;
+    assert_eq!(3, decoded.len());
+    assert_eq!(attributes["radius"], decoded["radius"]);
+    assert_eq!(attributes["ö"], decoded["ö"]);
+    // NaN is not equal to itself, compare the bits
+    assert!(attributes["weights"]
+        .iter()
+        .zip(decoded["weights"].iter())
+        .all(|(a, b)| a.to_bits() == b.to_bits()));
+
+    assert!(encode(&Attributes::new()).is_empty());
+    assert!(decode(&[])?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_attributes_invalid() {
+    let mut attributes = Attributes::new();
+    let _ = attributes.insert("weights".to_string(), vec![0.0, 0.5, 1.0]);
+    let blob = encode(&attributes);
+    // truncated
+    assert!(decode(&blob[..blob.len() - 1]).is_err());
+    // trailing data
+    let mut longer = blob.clone();
+    longer.push(0);
+    assert!(decode(&longer).is_err());
+    // bad magic
+    let mut bad = blob.clone();
+    bad[0] = b'X';
+    assert!(decode(&bad).is_err());
+    // unknown version
+    let mut bad = blob;
+    bad[4] = 2;
+    assert!(decode(&bad).is_err());
+}
@@ -56,7 +56,7 @@ where
             ))
         })?;
 
-    println!("2d_outline: data was in plane:{:?} aabb:{:?}", plane, aabb);
+    debug!("2d_outline: data was in plane:{:?} aabb:{:?}", plane, aabb);
 
     for face in model.indices.chunks(3) {
         for (v0, v1) in face.iter().chain(face.first()).tuple_windows::<(_, _)>() {
@@ -77,9 +77,9 @@ where
         }
     }
 
-    println!("Input vertices : {:?}", model.vertices.len());
-    println!("Input internal edges: {:?}", internal_edges.len());
-    println!("Input all edges: {:?}", all_edges.len());
+    debug!("Input vertices : {:?}", model.vertices.len());
+    debug!("Input internal edges: {:?}", internal_edges.len());
+    debug!("Input all edges: {:?}", all_edges.len());
     /*println!("Vertices: ");
     for (n, v) in obj.vertices.iter().enumerate() {
         println!("#{}, {:?}", n, v);
@@ -146,8 +146,8 @@ where
         // v0 and v1 now contains the translated vertex indices.
         rv_lines.push((v0, v1));
     }
-    println!("Output edges: {:?}", rv_lines.len());
-    println!("Output vertices: {:?}", rv_vertices.len());
+    debug!("Output edges: {:?}", rv_lines.len());
+    debug!("Output vertices: {:?}", rv_vertices.len());
 
     Ok((rv_lines, rv_vertices))
 }
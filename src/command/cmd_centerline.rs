@@ -2,8 +2,17 @@
 // Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
 // This file is part of the hallr crate.
 
-use super::{ConfigType, Model, Options, OwnedModel};
-use crate::{ffi::FFIVector3, utils, HallrError};
+use super::{
+    attributes::Attributes, cmd_hatch, cmd_offset_2d, ConfigType, Model, Options, OwnedModel,
+};
+use crate::{
+    ffi::FFIVector3,
+    utils::{
+        self,
+        voronoi_utils::{self, DegenerateInputCount, IntegerMapping, VoronoiSnapping},
+    },
+    HallrError,
+};
 use boostvoronoi as BV;
 use boostvoronoi::OutputType;
 use centerline::{HasMatrix4, Matrix4};
@@ -17,15 +26,25 @@ use rayon::{
     iter::ParallelIterator,
     prelude::{IntoParallelIterator, IntoParallelRefIterator},
 };
+use std::f64::consts::FRAC_PI_4;
 use vector_traits::{
     approx::{AbsDiffEq, UlpsEq},
-    num_traits::{real::Real, AsPrimitive, NumCast},
+    glam::{dvec2, DVec2, DVec3},
+    num_traits::{real::Real, AsPrimitive, NumCast, ToPrimitive},
     GenericScalar, GenericVector2, GenericVector3, HasXY, HasXYZ,
 };
 
 #[cfg(test)]
 mod tests;
 
+/// Turns sharper than this (radians) split the centerline into separate Bezier curves
+const BEZIER_CORNER_ANGLE: f64 = FRAC_PI_4;
+/// The max number of Newton-Raphson reparameterizations before a Bezier segment is split
+const BEZIER_MAX_ITERATIONS: usize = 4;
+
+/// The start point, the two control points and the end point of a cubic Bezier segment
+pub(crate) type CubicBezier = [DVec3; 4];
+
 #[inline(always)]
 /// make a key from v0 and v1, lowest index will always be first
 fn make_edge_key(v0: usize, v1: usize) -> (usize, usize) {
@@ -40,7 +59,15 @@ fn make_edge_key(v0: usize, v1: usize) -> (usize, usize) {
 #[allow(clippy::type_complexity)]
 fn parse_input<T: GenericVector3>(
     model: &Model<'_>,
-) -> Result<(ahash::AHashSet<(usize, usize)>, Vec<T>, Aabb3<T>), HallrError>
+) -> Result<
+    (
+        ahash::AHashSet<(usize, usize)>,
+        Vec<T>,
+        Aabb3<T>,
+        DegenerateInputCount,
+    ),
+    HallrError,
+>
 where
     FFIVector3: ConvertTo<T>,
 {
@@ -58,19 +85,26 @@ where
                 aabbe_d.x(), aabbe_d.y(), aabbe_d.z(),aabbe_c.x(), aabbe_c.y(), aabbe_c.z()
             ))
         })?;
-    println!(
+    debug!(
         "Centerline op: data was in plane:{:?} aabb:{:?}",
         plane, aabb
     );
     //println!("vertices:{:?}", model.vertices);
     //println!("indices:{:?}", model.indices);
     let mut edge_set = ahash::AHashSet::<(usize, usize)>::default();
+    let mut degenerate_count = DegenerateInputCount::default();
 
     for edge in model.indices.chunks(2) {
         let v0 = edge[0];
         let v1 = edge[1];
+        if v0 == v1 || model.vertices[v0] == model.vertices[v1] {
+            degenerate_count.zero_length_segments += 1;
+            continue;
+        }
         let key = make_edge_key(v0, v1);
-        let _ = edge_set.insert(key);
+        if !edge_set.insert(key) {
+            degenerate_count.duplicated_segments += 1;
+        }
     }
     let mut converted_vertices = Vec::<T>::with_capacity(model.vertices.len());
     for p in model.vertices.iter() {
@@ -86,7 +120,71 @@ where
         }
     }
 
-    Ok((edge_set, converted_vertices, aabb))
+    Ok((edge_set, converted_vertices, aabb, degenerate_count))
+}
+
+/// Replace the open polylines of the line_chunks model with closed corridors: the polylines
+/// offset by `offset` to both sides, cut square at the end points and rounded at the outer
+/// corners. Closed loops are kept as they are. The model must be in the z=0 plane.
+/// Returns the new vertices and indices, and the number of corridors.
+fn open_polyline_corridors(
+    model: &Model<'_>,
+    offset: f64,
+) -> Result<(Vec<FFIVector3>, Vec<usize>, usize), HallrError> {
+    if let Some(v) = model.vertices.iter().find(|v| v.z != 0.0) {
+        return Err(HallrError::InvalidInputData(format!(
+            "CORRIDOR_OFFSET requires input in the XY plane ({},{},{})",
+            v.x, v.y, v.z
+        )));
+    }
+    let join = cmd_offset_2d::Join {
+        join_type: cmd_offset_2d::JoinType::Round,
+        miter_limit: 2.0,
+        arc_segments: 32,
+    };
+    let segments: Vec<(u64, u64)> = model
+        .indices
+        .chunks_exact(2)
+        .map(|e| (e[0] as u64, e[1] as u64))
+        .collect();
+    let point = |i: u64| {
+        let v = model.vertices[i as usize];
+        dvec2(v.x as f64, v.y as f64)
+    };
+    let mut vertices = Vec::<FFIVector3>::new();
+    let mut indices = Vec::<usize>::new();
+    let mut corridors = 0;
+    let mut add_loop = |points: &[DVec2]| {
+        let first = vertices.len();
+        vertices.extend(
+            points
+                .iter()
+                .map(|p| FFIVector3::new(p.x as f32, p.y as f32, 0.0)),
+        );
+        for i in 0..points.len() {
+            indices.push(first + i);
+            indices.push(first + (i + 1) % points.len());
+        }
+    };
+    for polyline in cmd_hatch::chain_segments(&segments) {
+        let mut points: Vec<DVec2> = polyline.iter().map(|i| point(*i)).collect();
+        points.dedup();
+        if points.len() < 2 {
+            continue;
+        }
+        if polyline.first() == polyline.last() {
+            let _ = points.pop();
+            add_loop(&points);
+            continue;
+        }
+        // the polyline followed forward and back is a loop with both sides of the polyline to
+        // the right of its edges
+        let mut doubled = points.clone();
+        doubled.extend(points.iter().rev().skip(1).take(points.len() - 2));
+        add_loop(&cmd_offset_2d::offset_polygon(&doubled, offset, join));
+        corridors += 1;
+    }
+    Ok((vertices, indices, corridors))
 }
 
 /// Build the return model
@@ -101,7 +199,7 @@ fn build_output_model<T: GenericVector3>(
     inverted_transform: T::Matrix4Type,
     cmd_arg_negative_radius: bool,
     cmd_arg_keep_input: bool,
-) -> Result<OwnedModel, HallrError>
+) -> Result<(OwnedModel, Vec<(usize, usize)>), HallrError>
 where
     T: HasMatrix4 + ConvertTo<FFIVector3>,
     T::Scalar: OutputType,
@@ -125,6 +223,8 @@ where
         / 4;
 
     let mut output_model_edges = Vec::<(u32, u32)>::with_capacity(estimated_capacity);
+    // the edges of the output model that are not input edges
+    let mut centerline_edges = Vec::<(usize, usize)>::with_capacity(estimated_capacity);
 
     // map between vertex and vertex index
     let mut v_map = utils::VertexDeduplicator3D::<T>::default();
@@ -172,13 +272,14 @@ where
             let v1_index = v_map.get_index_or_insert(v1)?;
 
             if v0_index == v1_index {
-                println!(
+                debug!(
                     "v0_index==v1_index, but v0!=v1 v0:{:?} v1:{:?} v0_index:{:?} v1_index:{:?}",
                     v0, v1, v0_index, v1_index
                 );
                 continue;
             }
             output_model_edges.push((v0_index, v1_index));
+            centerline_edges.push((v0_index as usize, v1_index as usize));
         }
 
         // draw the concatenated line strings of the voronoi output
@@ -206,6 +307,7 @@ where
                 .chain(Some(v1_index).into_iter());
             for p in vertex_index_iterator.tuple_windows::<(_, _)>() {
                 output_model_edges.push((p.0, p.1));
+                centerline_edges.push((p.0 as usize, p.1 as usize));
             }
         }
     }
@@ -217,7 +319,7 @@ where
             output_pb_model_indices.push(a as usize);
             output_pb_model_indices.push(b as usize);
         } else {
-            println!("Something is wrong wanted to add edge {} to {}", a, b);
+            debug!("Something is wrong wanted to add edge {} to {}", a, b);
         }
     }
     //println!("Resulting centerline model:{:?}", output_pb_model_indices);
@@ -243,18 +345,338 @@ where
             .collect()
     };
 
-    Ok(OwnedModel {
-        world_orientation: OwnedModel::identity_matrix(),
-        //name: input_pb_model.name.clone(),
-        vertices: output_model_vertices,
-        indices: output_pb_model_indices,
-    })
+    Ok((
+        OwnedModel {
+            world_orientation: OwnedModel::identity_matrix(),
+            //name: input_pb_model.name.clone(),
+            vertices: output_model_vertices,
+            indices: output_pb_model_indices,
+        },
+        centerline_edges
+            .into_iter()
+            .filter(|(a, b)| a != b)
+            .collect(),
+    ))
+}
+
+/// Joins the edges into polylines, split at the junctions and at the turns sharper than
+/// `BEZIER_CORNER_ANGLE`. Returns the polylines and whether they are closed loops (the closing
+/// point is not repeated).
+pub(crate) fn split_into_polylines(
+    points: &[DVec3],
+    edges: &[(usize, usize)],
+) -> Vec<(Vec<DVec3>, bool)> {
+    let mut degree = vec![0_usize; points.len()];
+    for (a, b) in edges.iter() {
+        degree[*a] += 1;
+        degree[*b] += 1;
+    }
+    let is_split_point = |a: u64, p: u64, b: u64| {
+        let (u, v) = (
+            points[p as usize] - points[a as usize],
+            points[b as usize] - points[p as usize],
+        );
+        degree[p as usize] != 2
+            || (u.length_squared() > 0.0
+                && v.length_squared() > 0.0
+                && u.angle_between(v) > BEZIER_CORNER_ANGLE)
+    };
+    let positions = |chain: &[u64]| {
+        let mut rv = Vec::<DVec3>::with_capacity(chain.len());
+        for id in chain.iter() {
+            let p = points[*id as usize];
+            if rv.last() != Some(&p) {
+                rv.push(p);
+            }
+        }
+        rv
+    };
+
+    let segments: Vec<(u64, u64)> = edges.iter().map(|(a, b)| (*a as u64, *b as u64)).collect();
+    let mut rv = Vec::<(Vec<DVec3>, bool)>::new();
+    for mut chain in cmd_hatch::chain_segments(&segments) {
+        if chain.len() > 3 && chain.first() == chain.last() {
+            let _ = chain.pop();
+            let n = chain.len();
+            let Some(k) = (0..n)
+                .find(|k| is_split_point(chain[(k + n - 1) % n], chain[*k], chain[(k + 1) % n]))
+            else {
+                rv.push((positions(&chain), true));
+                continue;
+            };
+            // open the loop at a split point
+            chain.rotate_left(k);
+            chain.push(chain[0]);
+        }
+        let mut start = 0;
+        for k in 1..chain.len() - 1 {
+            if is_split_point(chain[k - 1], chain[k], chain[k + 1]) {
+                rv.push((positions(&chain[start..=k]), false));
+                start = k;
+            }
+        }
+        rv.push((positions(&chain[start..]), false));
+    }
+    rv.into_iter()
+        .filter(|(polyline, closed)| polyline.len() >= if *closed { 3 } else { 2 })
+        .collect()
+}
+
+fn bezier_point(bezier: &CubicBezier, t: f64) -> DVec3 {
+    let s = 1.0 - t;
+    bezier[0] * (s * s * s)
+        + bezier[1] * (3.0 * s * s * t)
+        + bezier[2] * (3.0 * s * t * t)
+        + bezier[3] * (t * t * t)
+}
+
+/// The least squares fit of a Bezier segment to the `points` at the parameters `u`, with the
+/// direction of the control points given by `tangent0` and `tangent1` (pointing into the curve)
+fn generate_bezier(points: &[DVec3], u: &[f64], tangent0: DVec3, tangent1: DVec3) -> CubicBezier {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let (mut c00, mut c01, mut c11, mut x0, mut x1) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (p, &t) in points.iter().zip(u.iter()) {
+        let s = 1.0 - t;
+        let a0 = tangent0 * (3.0 * s * s * t);
+        let a1 = tangent1 * (3.0 * s * t * t);
+        let residual =
+            *p - (first * (s * s * s + 3.0 * s * s * t) + last * (3.0 * s * t * t + t * t * t));
+        c00 += a0.dot(a0);
+        c01 += a0.dot(a1);
+        c11 += a1.dot(a1);
+        x0 += a0.dot(residual);
+        x1 += a1.dot(residual);
+    }
+    let determinant = c00 * c11 - c01 * c01;
+    let (mut alpha0, mut alpha1) = if determinant.abs() > f64::EPSILON {
+        (
+            (x0 * c11 - x1 * c01) / determinant,
+            (c00 * x1 - c01 * x0) / determinant,
+        )
+    } else {
+        (0.0, 0.0)
+    };
+    // fall back to the heuristic of Wu and Barsky when the fit is degenerate
+    let length = first.distance(last);
+    if alpha0 < 1e-6 * length || alpha1 < 1e-6 * length {
+        alpha0 = length / 3.0;
+        alpha1 = alpha0;
+    }
+    [
+        first,
+        first + tangent0 * alpha0,
+        last + tangent1 * alpha1,
+        last,
+    ]
+}
+
+/// One Newton-Raphson step improving the parameter `t` of the point `p` on the Bezier segment
+fn reparameterize(bezier: &CubicBezier, p: DVec3, t: f64) -> f64 {
+    let s = 1.0 - t;
+    let d = bezier_point(bezier, t) - p;
+    let d1 = ((bezier[1] - bezier[0]) * (s * s)
+        + (bezier[2] - bezier[1]) * (2.0 * s * t)
+        + (bezier[3] - bezier[2]) * (t * t))
+        * 3.0;
+    let d2 = ((bezier[2] - bezier[1] * 2.0 + bezier[0]) * s
+        + (bezier[3] - bezier[2] * 2.0 + bezier[1]) * t)
+        * 6.0;
+    let denominator = d1.dot(d1) + d.dot(d2);
+    if denominator.abs() > f64::EPSILON {
+        (t - d.dot(d1) / denominator).clamp(0.0, 1.0)
+    } else {
+        t
+    }
+}
+
+/// Returns the largest distance between the `points` and the Bezier segment, and the index of
+/// that point
+fn max_error(points: &[DVec3], bezier: &CubicBezier, u: &[f64]) -> (f64, usize) {
+    let mut rv = (0.0, points.len() / 2);
+    for i in 1..points.len() - 1 {
+        let error = bezier_point(bezier, u[i]).distance(points[i]);
+        if error > rv.0 {
+            rv = (error, i);
+        }
+    }
+    rv
+}
+
+/// Fit the `points` with Bezier segments, recursively split where the error exceeds `tolerance`
+/// (P. J. Schneider, An Algorithm for Automatically Fitting Digitized Curves, Graphics Gems 1990)
+fn fit_cubic(
+    points: &[DVec3],
+    tangent0: DVec3,
+    tangent1: DVec3,
+    tolerance: f64,
+    rv: &mut Vec<CubicBezier>,
+) {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    if points.len() == 2 {
+        let third = first.distance(last) / 3.0;
+        rv.push([
+            first,
+            first + tangent0 * third,
+            last + tangent1 * third,
+            last,
+        ]);
+        return;
+    }
+    // chord length parameterization
+    let mut u = Vec::<f64>::with_capacity(points.len());
+    let mut length = 0.0;
+    u.push(0.0);
+    for p in points.windows(2) {
+        length += p[0].distance(p[1]);
+        u.push(length);
+    }
+    u.iter_mut().for_each(|t| *t /= length);
+
+    let mut bezier = generate_bezier(points, &u, tangent0, tangent1);
+    let (mut error, mut split) = max_error(points, &bezier, &u);
+    if error <= tolerance {
+        rv.push(bezier);
+        return;
+    }
+    if error <= 4.0 * tolerance {
+        for _ in 0..BEZIER_MAX_ITERATIONS {
+            u = points
+                .iter()
+                .zip(u.iter())
+                .map(|(p, t)| reparameterize(&bezier, *p, *t))
+                .collect();
+            bezier = generate_bezier(points, &u, tangent0, tangent1);
+            (error, split) = max_error(points, &bezier, &u);
+            if error <= tolerance {
+                rv.push(bezier);
+                return;
+            }
+        }
+    }
+    let mut center = (points[split - 1] - points[split + 1]).normalize_or_zero();
+    if center == DVec3::ZERO {
+        center = (points[split] - points[split + 1]).normalize_or_zero();
+    }
+    fit_cubic(&points[..=split], tangent0, center, tolerance, rv);
+    fit_cubic(&points[split..], -center, tangent1, tolerance, rv);
+}
+
+/// Fit a polyline with Bezier segments, no point further away than `tolerance` from the curve.
+/// The curve of a closed polyline returns to the first point.
+pub(crate) fn fit_bezier(polyline: &[DVec3], closed: bool, tolerance: f64) -> Vec<CubicBezier> {
+    let mut rv = Vec::<CubicBezier>::new();
+    let n = polyline.len();
+    if closed {
+        let mut points = polyline.to_vec();
+        points.push(polyline[0]);
+        let tangent = (polyline[1] - polyline[n - 1]).normalize_or_zero();
+        fit_cubic(&points, tangent, -tangent, tolerance, &mut rv);
+    } else {
+        let tangent0 = (polyline[1] - polyline[0]).normalize_or_zero();
+        let tangent1 = (polyline[n - 2] - polyline[n - 1]).normalize_or_zero();
+        fit_cubic(polyline, tangent0, tangent1, tolerance, &mut rv);
+    }
+    rv
+}
+
+/// Fit the centerline edges with Bezier curves, and return them in the output attributes:
+/// * `BEZIER_POINTS`: the x,y,z values of the curves, for every curve the first point followed by
+///   the two control points and the end point of every segment
+/// * `BEZIER_SEGMENTS`: the number of segments of every curve
+/// * `BEZIER_CYCLIC`: 1.0 for the curves that are closed loops (their last point is the first
+///   point), otherwise 0.0
+///
+/// Returns the number of curves
+fn add_bezier_curves(
+    vertices: &[FFIVector3],
+    centerline_edges: &[(usize, usize)],
+    tolerance: f64,
+    output_attributes: &mut Attributes,
+) -> usize {
+    let points: Vec<DVec3> = vertices
+        .iter()
+        .map(|v| DVec3::new(v.x as f64, v.y as f64, v.z as f64))
+        .collect();
+    let mut bezier_points = Vec::<f32>::new();
+    let mut bezier_segments = Vec::<f32>::new();
+    let mut bezier_cyclic = Vec::<f32>::new();
+    for (polyline, closed) in split_into_polylines(&points, centerline_edges) {
+        let curve = fit_bezier(&polyline, closed, tolerance);
+        let curve_points = Some(curve[0][0]).into_iter().chain(
+            curve
+                .iter()
+                .flat_map(|segment| segment[1..].iter().copied()),
+        );
+        for p in curve_points {
+            bezier_points.extend([p.x as f32, p.y as f32, p.z as f32]);
+        }
+        bezier_segments.push(curve.len() as f32);
+        bezier_cyclic.push(if closed { 1.0 } else { 0.0 });
+    }
+    let curve_count = bezier_segments.len();
+    let _ = output_attributes.insert("BEZIER_POINTS".to_string(), bezier_points);
+    let _ = output_attributes.insert("BEZIER_SEGMENTS".to_string(), bezier_segments);
+    let _ = output_attributes.insert("BEZIER_CYCLIC".to_string(), bezier_cyclic);
+    curve_count
+}
+
+/// A V-carve preview of the centerline: every edge becomes a roof of four triangles, with the
+/// ridge along the edge at the depth of the centerline vertices and the eaves on the input plane
+/// at `plane_z`, one radius to both sides of the edge. The triangles face upwards, the vertices
+/// are not shared between the edges.
+fn v_carve_mesh(
+    vertices: &[FFIVector3],
+    centerline_edges: &[(usize, usize)],
+    plane_z: f32,
+) -> (Vec<FFIVector3>, Vec<usize>) {
+    let mut rv_vertices = Vec::<FFIVector3>::with_capacity(centerline_edges.len() * 6);
+    let mut rv_indices = Vec::<usize>::with_capacity(centerline_edges.len() * 12);
+    for (i0, i1) in centerline_edges.iter() {
+        let (a, b) = (vertices[*i0], vertices[*i1]);
+        let direction = dvec2((b.x - a.x) as f64, (b.y - a.y) as f64).normalize_or_zero();
+        if direction == DVec2::ZERO {
+            continue;
+        }
+        // the left hand normal
+        let normal = direction.perp();
+        let side = |v: FFIVector3, sign: f64| {
+            let offset = normal * sign * (v.z - plane_z).abs() as f64;
+            FFIVector3::new(v.x + offset.x as f32, v.y + offset.y as f32, plane_z)
+        };
+        let first = rv_vertices.len();
+        rv_vertices.extend([
+            a,
+            b,
+            side(a, 1.0),
+            side(b, 1.0),
+            side(a, -1.0),
+            side(b, -1.0),
+        ]);
+        // counter-clockwise as seen from above
+        for triangle in [[0, 1, 3], [0, 3, 2], [4, 5, 1], [4, 1, 0]] {
+            rv_indices.extend(triangle.iter().map(|i| first + i));
+        }
+    }
+    (rv_vertices, rv_indices)
 }
 
 /// Run the centerline command
+/// With the `BEZIER_TOLERANCE` option (in model units) the centerline is also fitted with cubic
+/// Bezier curves, returned in the binary attributes (see `add_bezier_curves`). The number of
+/// curves is returned as `BEZIER_CURVES`.
+/// The input is mapped to integer coordinates as set by `VORONOI_SNAPPING` (see
+/// `VoronoiSnapping`), the effective resolution is returned as `VORONOI_GRID_RESOLUTION`.
+/// With `CORRIDOR_OFFSET` (in model units) the input may also contain open polylines in the XY
+/// plane. Every open polyline is replaced by the closed corridor `CORRIDOR_OFFSET` to both sides
+/// of it, and the centerline of the corridors approximates the medial axis of the strokes. The
+/// polylines should neither branch nor cross. The number of corridors is returned as `CORRIDORS`.
+/// With `V_CARVE_MESH` the centerline is returned as a triangulated V-carve preview (see
+/// `v_carve_mesh`) instead of line chunks, the input is then never kept. This requires input in
+/// the XY plane.
 pub(crate) fn process_command<T: GenericVector3>(
     config: ConfigType,
     models: Vec<Model<'_>>,
+    output_attributes: &mut Attributes,
 ) -> Result<super::CommandResult, HallrError>
 where
     T: ConvertTo<FFIVector3> + HasMatrix4,
@@ -315,6 +737,29 @@ where
     let cmd_arg_negative_radius = config
         .get_parsed_option::<bool>("NEGATIVE_RADIUS")?
         .unwrap_or(true);
+    let cmd_arg_bezier_tolerance = config.get_parsed_option::<f64>("BEZIER_TOLERANCE")?;
+    if let Some(tolerance) = cmd_arg_bezier_tolerance {
+        if !tolerance.is_finite() || tolerance <= 0.0 {
+            return Err(HallrError::InvalidParameter(format!(
+                "BEZIER_TOLERANCE must be a positive number :({})",
+                tolerance
+            )));
+        }
+    }
+
+    let cmd_arg_snapping = VoronoiSnapping::from_config(&config)?;
+    let cmd_arg_corridor_offset = config.get_parsed_option::<f64>("CORRIDOR_OFFSET")?;
+    let cmd_arg_v_carve_mesh = config
+        .get_parsed_option::<bool>("V_CARVE_MESH")?
+        .unwrap_or(false);
+    if let Some(offset) = cmd_arg_corridor_offset {
+        if !offset.is_finite() || offset <= 0.0 {
+            return Err(HallrError::InvalidParameter(format!(
+                "CORRIDOR_OFFSET must be a positive number :({})",
+                offset
+            )));
+        }
+    }
 
     let mesh_format = config.get_mandatory_option("mesh.format")?;
     if mesh_format.ne("line_chunks") {
@@ -347,26 +792,29 @@ where
     // Can also be described as cos(angle) between edge and segment.
     let dot_limit = cmd_arg_angle.to_radians().cos().abs();
 
-    println!("cmd_centerline got command");
-    println!("model.vertices:{:?}", model.vertices.len());
-    println!("model.indices:{:?}", model.indices.len());
-    println!(
+    debug!("cmd_centerline got command");
+    debug!("model.vertices:{:?}", model.vertices.len());
+    debug!("model.indices:{:?}", model.indices.len());
+    debug!(
         "model.world_orientation:{:?}:{}",
         model.world_orientation,
         model.has_identity_orientation()
     );
-    println!("ANGLE:{:?}°, dot_limit:{:?}", cmd_arg_angle, dot_limit);
-    println!("REMOVE_INTERNALS:{:?}", cmd_arg_remove_internals);
-    println!("SIMPLIFY:{:?}", cmd_arg_simplify);
-    println!(
+    debug!("ANGLE:{:?}°, dot_limit:{:?}", cmd_arg_angle, dot_limit);
+    debug!("REMOVE_INTERNALS:{:?}", cmd_arg_remove_internals);
+    debug!("SIMPLIFY:{:?}", cmd_arg_simplify);
+    debug!(
         "KEEP_INPUT:{:?}, WELD:{:?}",
         cmd_arg_keep_input, cmd_arg_weld
     );
-    println!("DISTANCE:{:?}%", cmd_arg_discrete_distance);
-    println!("NEGATIVE_RADIUS:{:?}", cmd_arg_negative_radius);
-    println!("MAX_VORONOI_DIMENSION:{:?}", cmd_arg_max_voronoi_dimension);
-    println!("max_distance:{:?}", max_distance);
-    println!();
+    debug!("DISTANCE:{:?}%", cmd_arg_discrete_distance);
+    debug!("NEGATIVE_RADIUS:{:?}", cmd_arg_negative_radius);
+    debug!("BEZIER_TOLERANCE:{:?}", cmd_arg_bezier_tolerance);
+    debug!("MAX_VORONOI_DIMENSION:{:?}", cmd_arg_max_voronoi_dimension);
+    debug!("VORONOI_SNAPPING:{:?}", cmd_arg_snapping);
+    debug!("CORRIDOR_OFFSET:{:?}", cmd_arg_corridor_offset);
+    debug!("V_CARVE_MESH:{:?}", cmd_arg_v_carve_mesh);
+    debug!("max_distance:{:?}", max_distance);
 
     //let mut obj = Obj::<FFIVector3>::new("cmd_centerline");
     //println!("rust: vertices.len():{}", vertices.len());
@@ -378,17 +826,49 @@ where
     //println!("Vertices:{:?}", vertices);
     //println!("Indices:{:?}", indices);
 
-    let (edges, vertices, total_aabb) = parse_input(model)?;
+    // Input data in a plane like z=c is translated into a plane crossing origin, the offset is
+    // restored on the output vertices.
+    let plane_offset = utils::axis_aligned_plane_offset(model.vertices);
+    let translated_vertices = utils::translate_vertices(model.vertices, plane_offset);
+    let translated_model = Model {
+        world_orientation: model.world_orientation,
+        vertices: &translated_vertices,
+        indices: model.indices,
+    };
+    if plane_offset != FFIVector3::default() {
+        debug!("Centerline op: input translated by {:?}", plane_offset);
+    }
+    let (corridor_vertices, corridor_indices, corridor_count) = match cmd_arg_corridor_offset {
+        Some(offset) => open_polyline_corridors(&translated_model, offset)?,
+        None => (Vec::default(), Vec::default(), 0),
+    };
+    let translated_model = if cmd_arg_corridor_offset.is_some() {
+        Model {
+            world_orientation: model.world_orientation,
+            vertices: &corridor_vertices,
+            indices: &corridor_indices,
+        }
+    } else {
+        translated_model
+    };
+
+    let (edges, vertices, total_aabb, mut degenerate_count) = parse_input(&translated_model)?;
     //println!("edge set: {:?}", edges);
     //println!("-> divide_into_shapes");
     let lines = centerline::divide_into_shapes(edges, vertices)?;
     //println!("-> get_transform_relaxed");
-    let (_plane, transform, _voronoi_input_aabb) = centerline::get_transform_relaxed(
+    let (plane, transform, _voronoi_input_aabb) = centerline::get_transform_relaxed(
         total_aabb,
         cmd_arg_max_voronoi_dimension,
         T::Scalar::default_epsilon(),
         T::Scalar::default_max_ulps(),
     )?;
+    if cmd_arg_v_carve_mesh && plane != Plane::XY {
+        return Err(HallrError::InvalidInputData(format!(
+            "V_CARVE_MESH requires input in the XY plane :({:?})",
+            plane
+        )));
+    }
 
     let inverted_transform = transform.safe_inverse().ok_or(HallrError::InternalError(
         "Could not generate the inverse matrix.".to_string(),
@@ -408,13 +888,17 @@ where
             xc.copy_to_2d(Plane::XY)
         })
         .collect();
+    let mapping = IntegerMapping::new::<T>(cmd_arg_snapping, &transform);
     {
-        // round the floats to nearest int
-        let round_float = |v: <T as GenericVector3>::Vector2| -> <T as GenericVector3>::Vector2 {
-            <T as GenericVector3>::Vector2::new_2d(v.x().round(), v.y().round())
+        // snap the floats to integers, before the hulls are calculated
+        let snap = |value: T::Scalar| -> T::Scalar {
+            NumCast::from(mapping.snap(value.to_f64().unwrap_or_default())).unwrap()
+        };
+        let snap_float = |v: <T as GenericVector3>::Vector2| -> <T as GenericVector3>::Vector2 {
+            <T as GenericVector3>::Vector2::new_2d(snap(v.x()), snap(v.y()))
         };
         for r in lines_as_2d.iter_mut() {
-            r.apply(&round_float);
+            r.apply(&snap_float);
         }
     }
     //for s in lines_as_2d.iter() {
@@ -453,6 +937,9 @@ where
                     ))
                 }
             }
+            // rounding to integers may have created zero length or duplicated segments
+            let (_, segments, shape_degenerate_count) =
+                voronoi_utils::remove_degenerate_input(Vec::default(), segments);
             let mut c = centerline::Centerline::<i64, T>::with_segments(segments);
             if let Err(centerline_error) = c.build_voronoi() {
                 return Err(centerline_error.into());
@@ -485,17 +972,24 @@ where
                         .collect(),
                 );
             }
-            Ok((shape, c))
+            Ok((shape, c, shape_degenerate_count))
         })
         .collect::<Result<
             Vec<(
                 centerline::LineStringSet2<T::Vector2>,
                 centerline::Centerline<i64, T>,
+                DegenerateInputCount,
             )>,
             HallrError,
-        >>()?;
+        >>()?
+        .into_iter()
+        .map(|(shape, c, shape_degenerate_count)| {
+            degenerate_count += shape_degenerate_count;
+            (shape, c)
+        })
+        .collect();
     //println!("<-build_voronoi");
-    let model = build_output_model(
+    let (mut model, centerline_edges) = build_output_model(
         &config,
         shapes,
         cmd_arg_weld,
@@ -503,6 +997,9 @@ where
         cmd_arg_negative_radius,
         cmd_arg_keep_input,
     )?;
+    for v in model.vertices.iter_mut() {
+        *v = *v + plane_offset;
+    }
 
     //println!("result vertices:{:?}", obj.vertices);
     //println!("result edges:{:?}", obj.lines.first());
@@ -511,7 +1008,28 @@ where
     if cmd_arg_weld {
         let _ = return_config.insert("REMOVE_DOUBLES".to_string(), "true".to_string());
     }
-    println!(
+    degenerate_count.report("centerline", &mut return_config);
+    mapping.report(&mut return_config);
+    if cmd_arg_corridor_offset.is_some() {
+        let _ = return_config.insert("CORRIDORS".to_string(), corridor_count.to_string());
+    }
+    if let Some(tolerance) = cmd_arg_bezier_tolerance {
+        let curve_count = add_bezier_curves(
+            &model.vertices,
+            &centerline_edges,
+            tolerance,
+            output_attributes,
+        );
+        let _ = return_config.insert("BEZIER_CURVES".to_string(), curve_count.to_string());
+    }
+    if cmd_arg_v_carve_mesh {
+        let (vertices, indices) = v_carve_mesh(&model.vertices, &centerline_edges, plane_offset.z);
+        model.vertices = vertices;
+        model.indices = indices;
+        let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+        let _ = return_config.insert("REMOVE_DOUBLES".to_string(), "true".to_string());
+    }
+    info!(
         "centerline operation returning {} vertices, {} indices",
         model.vertices.len(),
         model.indices.len()
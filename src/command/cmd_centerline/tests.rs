@@ -3,10 +3,10 @@
 // This file is part of the hallr crate.
 
 use crate::{
-    command::{ConfigType, Model, OwnedModel},
+    command::{attributes::Attributes, ConfigType, Model, OwnedModel},
     HallrError,
 };
-use vector_traits::glam::Vec3;
+use vector_traits::glam::{DVec3, Vec3};
 
 #[test]
 fn test_centerline_1() -> Result<(), HallrError> {
@@ -38,7 +38,7 @@ fn test_centerline_1() -> Result<(), HallrError> {
         vertices: &owned_model_0.vertices,
     };
     let models = vec![model_0];
-    let result = super::process_command::<Vec3>(config, models)?;
+    let result = super::process_command::<Vec3>(config, models, &mut Attributes::new())?;
     assert_eq!(7, result.0.len()); // vertices
     assert_eq!(18, result.1.len()); // indices
     Ok(())
@@ -74,7 +74,7 @@ fn test_centerline_2() -> Result<(), HallrError> {
         vertices: &owned_model_0.vertices,
     };
     let models = vec![model_0];
-    let result = super::process_command::<Vec3>(config, models)?;
+    let result = super::process_command::<Vec3>(config, models, &mut Attributes::new())?;
     assert_eq!(7, result.0.len()); // vertices
     assert_eq!(10, result.1.len()); // indices
     Ok(())
@@ -112,8 +112,215 @@ fn test_centerline_3() -> Result<(), HallrError> {
         vertices: &owned_model_0.vertices,
     };
     let models = vec![model_0];
-    let result = super::process_command::<Vec3>(config, models)?;
+    let result = super::process_command::<Vec3>(config, models, &mut Attributes::new())?;
     assert_eq!(21, result.0.len()); // vertices
     assert_eq!(44, result.1.len()); // indices
     Ok(())
 }
+
+#[test]
+fn test_centerline_z_offset() -> Result<(), HallrError> {
+    // same as test_centerline_3 but also in the z=2 plane, the output must be the same, only
+    // offset by z=2
+    let run = |z: f32| {
+        let mut config = ConfigType::default();
+        let _ = config.insert("REMOVE_INTERNALS".to_string(), "true".to_string());
+        let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+        let _ = config.insert("command".to_string(), "centerline".to_string());
+        let _ = config.insert("NEGATIVE_RADIUS".to_string(), "true".to_string());
+        let _ = config.insert("first_index_model_0".to_string(), "0".to_string());
+        let _ = config.insert("ANGLE".to_string(), "89.00000133828577".to_string());
+        let _ = config.insert("KEEP_INPUT".to_string(), "true".to_string());
+        let _ = config.insert("DISTANCE".to_string(), "0.004999999888241291".to_string());
+        let _ = config.insert("SIMPLIFY".to_string(), "true".to_string());
+        let _ = config.insert("WELD".to_string(), "true".to_string());
+
+        let owned_model_0 = OwnedModel {
+            world_orientation: OwnedModel::identity_matrix(),
+            vertices: vec![
+                (-1.49995, -0.7411614, z).into(),
+                (-0.39808625, 0.6156829, z).into(),
+                (1.3165288, -0.969334, z).into(),
+                (-0.08538532, -0.12297079, z).into(),
+                (0.09803593, 1.5797875, z).into(),
+            ],
+            indices: vec![0, 1, 2, 4, 1, 4, 3, 2, 3, 0],
+        };
+        super::process_command::<Vec3>(
+            config,
+            vec![owned_model_0.as_model()],
+            &mut Attributes::new(),
+        )
+    };
+    let result_0 = run(0.0)?;
+    let result_2 = run(2.0)?;
+    assert_eq!(21, result_2.0.len()); // vertices
+    assert_eq!(result_0.1, result_2.1);
+    for (v0, v2) in result_0.0.iter().zip(result_2.0.iter()) {
+        assert!((v0.x - v2.x).abs() < 1e-5, "{:?} {:?}", v0, v2);
+        assert!((v0.y - v2.y).abs() < 1e-5, "{:?} {:?}", v0, v2);
+        assert!((v0.z + 2.0 - v2.z).abs() < 1e-5, "{:?} {:?}", v0, v2);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_centerline_4() -> Result<(), HallrError> {
+    // a quarter circle is fitted with a single segment, its end points are kept
+    let arc: Vec<DVec3> = (0..=32)
+        .map(|i| {
+            let (sin, cos) = (std::f64::consts::FRAC_PI_2 * i as f64 / 32.0).sin_cos();
+            DVec3::new(cos, sin, -0.5)
+        })
+        .collect();
+    let curve = super::fit_bezier(&arc, false, 0.01);
+    assert_eq!(1, curve.len());
+    assert_eq!(arc[0], curve[0][0]);
+    assert_eq!(arc[32], curve[0][3]);
+
+    // a tighter tolerance needs more segments
+    assert!(super::fit_bezier(&arc, false, 1e-6).len() > 1);
+
+    // a T junction and a closed square
+    let points = vec![
+        DVec3::new(0.0, 0.0, 0.0),
+        DVec3::new(1.0, 0.0, 0.0),
+        DVec3::new(2.0, 0.0, 0.0),
+        DVec3::new(1.0, 1.0, 0.0),
+        DVec3::new(5.0, 0.0, 0.0),
+        DVec3::new(6.0, 0.0, 0.0),
+        DVec3::new(6.0, 1.0, 0.0),
+        DVec3::new(5.0, 1.0, 0.0),
+    ];
+    let edges = vec![(0, 1), (1, 2), (1, 3), (4, 5), (5, 6), (6, 7), (7, 4)];
+    let polylines = super::split_into_polylines(&points, &edges);
+    // the three branches of the T, and the four sides of the square split at the corners
+    assert_eq!(7, polylines.len());
+    assert!(polylines
+        .iter()
+        .all(|(polyline, closed)| polyline.len() == 2 && !closed));
+    Ok(())
+}
+
+#[test]
+fn test_centerline_5() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("KEEP_INPUT".to_string(), "true".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "centerline".to_string());
+    let _ = config.insert("DISTANCE".to_string(), "0.004999999888241291".to_string());
+    let _ = config.insert("ANGLE".to_string(), "89.00000133828577".to_string());
+    let _ = config.insert("BEZIER_TOLERANCE".to_string(), "0.001".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.8870333, -0.39229375, 0.010461569).into(),
+            (-0.3180092, -2.0773406, 0.010461569).into(),
+            (2.680789, 0.5384001, 0.010461569).into(),
+            (-0.4052546, 2.4733071, 0.010461569).into(),
+        ],
+        indices: vec![0, 3, 0, 1, 2, 1, 3, 2],
+    };
+    let models = vec![owned_model_0.as_model()];
+    let mut output_attributes = Attributes::new();
+    let result = super::process_command::<Vec3>(config, models, &mut output_attributes)?;
+    let curves: usize = result.3.get("BEZIER_CURVES").unwrap().parse().unwrap();
+    assert!(curves > 0);
+    let segments = output_attributes.get("BEZIER_SEGMENTS").unwrap();
+    assert_eq!(curves, segments.len());
+    assert_eq!(
+        curves,
+        output_attributes.get("BEZIER_CYCLIC").unwrap().len()
+    );
+    // every curve has a start point, and three points per segment
+    let point_count: f32 = segments.iter().map(|s| 1.0 + 3.0 * s).sum();
+    assert_eq!(
+        3 * point_count as usize,
+        output_attributes.get("BEZIER_POINTS").unwrap().len()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_centerline_open_polyline() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("KEEP_INPUT".to_string(), "false".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "centerline".to_string());
+    let _ = config.insert("DISTANCE".to_string(), "0.1".to_string());
+    let _ = config.insert("ANGLE".to_string(), "45.0".to_string());
+    let _ = config.insert("CORRIDOR_OFFSET".to_string(), "1.0".to_string());
+
+    // an open L shaped stroke
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (10.0, 0.0, 0.0).into(),
+            (10.0, 10.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2],
+    };
+    let result = super::process_command::<Vec3>(
+        config.clone(),
+        vec![owned_model_0.as_model()],
+        &mut Attributes::new(),
+    )?;
+    assert_eq!("1", result.3.get("CORRIDORS").unwrap());
+    assert!(!result.1.is_empty());
+    // the centerline follows the stroke, one offset from the corridor sides
+    assert!(result
+        .0
+        .iter()
+        .any(|v| v.y.abs() < 1e-3 && v.x > 2.0 && v.x < 8.0 && (v.z + 1.0).abs() < 1e-3));
+    assert!(result
+        .0
+        .iter()
+        .any(|v| (v.x - 10.0).abs() < 1e-3 && v.y > 2.0 && v.y < 8.0));
+
+    let _ = config.insert("CORRIDOR_OFFSET".to_string(), "-1.0".to_string());
+    assert!(super::process_command::<Vec3>(
+        config,
+        vec![owned_model_0.as_model()],
+        &mut Attributes::new()
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_centerline_v_carve_mesh() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "centerline".to_string());
+    let _ = config.insert("DISTANCE".to_string(), "0.004999999888241291".to_string());
+    let _ = config.insert("ANGLE".to_string(), "89.00000133828577".to_string());
+    let _ = config.insert("V_CARVE_MESH".to_string(), "true".to_string());
+
+    let plane_z = 0.010461569;
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.8870333, -0.39229375, plane_z).into(),
+            (-0.3180092, -2.0773406, plane_z).into(),
+            (2.680789, 0.5384001, plane_z).into(),
+            (-0.4052546, 2.4733071, plane_z).into(),
+        ],
+        indices: vec![0, 3, 0, 1, 2, 1, 3, 2],
+    };
+    let result = super::process_command::<Vec3>(
+        config,
+        vec![owned_model_0.as_model()],
+        &mut Attributes::new(),
+    )?;
+    assert_eq!("triangulated", result.3.get("mesh.format").unwrap());
+    assert!(!result.1.is_empty());
+    assert_eq!(0, result.1.len() % 3);
+    assert!(result.1.iter().all(|i| *i < result.0.len()));
+    // the eaves are on the input plane, and the ridges below it
+    assert!(result.0.iter().all(|v| v.z <= plane_z + 1e-6));
+    assert!(result.0.iter().any(|v| (v.z - plane_z).abs() < 1e-6));
+    assert!(result.0.iter().any(|v| v.z < plane_z - 0.1));
+    Ok(())
+}
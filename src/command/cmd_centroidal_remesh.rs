@@ -0,0 +1,323 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{cmd_clip_curves, cmd_hatch, cmd_orient_outlines, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use vector_traits::glam::{dvec2, DVec2};
+
+#[cfg(test)]
+mod tests;
+
+/// The largest number of generated points
+const MAX_POINTS: usize = 100_000;
+/// Interior points closer to the outline than this fraction of the edge length are dropped
+const MIN_BOUNDARY_DISTANCE: f64 = 0.5;
+
+/// A triangle of the Delaunay triangulation, and its circumcircle
+#[derive(Clone, Copy)]
+struct Triangle {
+    vertices: [usize; 3],
+    center: DVec2,
+    radius_squared: f64,
+}
+
+impl Triangle {
+    /// A counter-clockwise triangle of the points `a`, `b` and `c`
+    fn new(points: &[DVec2], a: usize, b: usize, c: usize) -> Self {
+        let (pa, pb, pc) = (points[a], points[b], points[c]);
+        let vertices = if (pb - pa).perp_dot(pc - pa) < 0.0 {
+            [a, c, b]
+        } else {
+            [a, b, c]
+        };
+        let center = circumcenter(pa, pb, pc);
+        Self {
+            vertices,
+            center,
+            radius_squared: center.distance_squared(pa),
+        }
+    }
+}
+
+/// Returns the center of the circle passing through `a`, `b` and `c`
+fn circumcenter(a: DVec2, b: DVec2, c: DVec2) -> DVec2 {
+    let (ab, ac) = (b - a, c - a);
+    let d = 2.0 * ab.perp_dot(ac);
+    if d == 0.0 {
+        // degenerate, the centroid keeps the triangle usable
+        return (a + b + c) / 3.0;
+    }
+    a + dvec2(
+        ac.y * ab.length_squared() - ab.y * ac.length_squared(),
+        ab.x * ac.length_squared() - ac.x * ab.length_squared(),
+    ) / d
+}
+
+/// Returns the counter-clockwise Delaunay triangulation of the points, using the Bowyer-Watson
+/// algorithm
+pub(crate) fn delaunay(points: &[DVec2]) -> Vec<[usize; 3]> {
+    if points.len() < 3 {
+        return Vec::new();
+    }
+    let (min, max) = points.iter().fold(
+        (DVec2::splat(f64::MAX), DVec2::splat(f64::MIN)),
+        |(min, max), p| (min.min(*p), max.max(*p)),
+    );
+    let center = (min + max) * 0.5;
+    let size = (max - min).max_element().max(f64::EPSILON) * 20.0;
+    // the super triangle encloses all the points, its vertices are appended after them
+    let n = points.len();
+    let mut all_points = points.to_vec();
+    all_points.push(center + dvec2(-size, -size));
+    all_points.push(center + dvec2(size, -size));
+    all_points.push(center + dvec2(0.0, size));
+    let mut triangles = vec![Triangle::new(&all_points, n, n + 1, n + 2)];
+
+    let mut edges = Vec::<(usize, usize)>::new();
+    for (i, p) in points.iter().enumerate() {
+        edges.clear();
+        let mut kept = Vec::with_capacity(triangles.len() + 2);
+        for t in triangles.drain(..) {
+            if t.center.distance_squared(*p) < t.radius_squared {
+                for (a, b) in [
+                    (t.vertices[0], t.vertices[1]),
+                    (t.vertices[1], t.vertices[2]),
+                    (t.vertices[2], t.vertices[0]),
+                ] {
+                    edges.push((a.min(b), a.max(b)));
+                }
+            } else {
+                kept.push(t);
+            }
+        }
+        // the edges shared by two removed triangles are inside of the cavity, the others are
+        // connected to the new point
+        edges.sort_unstable();
+        let mut j = 0;
+        while j < edges.len() {
+            if j + 1 < edges.len() && edges[j] == edges[j + 1] {
+                j += 2;
+                continue;
+            }
+            kept.push(Triangle::new(&all_points, edges[j].0, edges[j].1, i));
+            j += 1;
+        }
+        triangles = kept;
+    }
+    triangles
+        .into_iter()
+        .filter(|t| t.vertices.iter().all(|v| *v < n))
+        .map(|t| t.vertices)
+        .collect()
+}
+
+/// Returns the distance from `p` to the closest edge of the region
+fn boundary_distance(p: DVec2, region: &[(DVec2, DVec2)]) -> f64 {
+    region
+        .iter()
+        .map(|(a, b)| cmd_hatch::distance_to_segment(p, *a, *b))
+        .fold(f64::MAX, f64::min)
+}
+
+/// Returns the triangles of the region, the triangles with a centroid outside of the region are
+/// removed
+fn triangulate_region(points: &[DVec2], region: &[(DVec2, DVec2)]) -> Vec<[usize; 3]> {
+    delaunay(points)
+        .into_iter()
+        .filter(|t| {
+            let centroid = (points[t[0]] + points[t[1]] + points[t[2]]) / 3.0;
+            cmd_clip_curves::is_inside_region(centroid, region)
+        })
+        .collect()
+}
+
+/// Returns the centroid of the polygon, or None if it has no area
+fn polygon_centroid(polygon: &[DVec2]) -> Option<DVec2> {
+    let mut area = 0.0;
+    let mut centroid = DVec2::ZERO;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        let cross = a.perp_dot(b);
+        area += cross;
+        centroid += (*a + b) * cross;
+    }
+    if area.abs() <= f64::EPSILON {
+        return None;
+    }
+    Some(centroid / (3.0 * area))
+}
+
+/// Move every point after `fixed` towards the centroid of its Voronoi cell (one Lloyd iteration).
+/// The cell is the polygon of the circumcenters of the triangles around the point. Moves that
+/// would bring a point outside of the region, or too close to its outline, are skipped.
+fn lloyd_iteration(
+    points: &mut [DVec2],
+    fixed: usize,
+    triangles: &[[usize; 3]],
+    region: &[(DVec2, DVec2)],
+    min_boundary_distance: f64,
+) {
+    let mut cells = vec![Vec::<DVec2>::new(); points.len()];
+    for t in triangles.iter() {
+        let center = circumcenter(points[t[0]], points[t[1]], points[t[2]]);
+        for v in t.iter() {
+            cells[*v].push(center);
+        }
+    }
+    for (i, mut cell) in cells.into_iter().enumerate().skip(fixed) {
+        if cell.len() < 3 {
+            continue;
+        }
+        let p = points[i];
+        cell.sort_by(|a, b| {
+            let angle = |c: &DVec2| (c.y - p.y).atan2(c.x - p.x);
+            angle(a).total_cmp(&angle(b))
+        });
+        if let Some(centroid) = polygon_centroid(&cell) {
+            if cmd_clip_curves::is_inside_region(centroid, region)
+                && boundary_distance(centroid, region) >= min_boundary_distance
+            {
+                points[i] = centroid;
+            }
+        }
+    }
+}
+
+/// Run the centroidal_remesh command
+/// Model 0 is one or more closed loops in the line_chunks format, in the XY plane. Loops inside
+/// of other loops are holes. The outline is resampled at `CVT_EDGE_LENGTH` (in model units,
+/// default 1/20 of the longest side of the bounding box), the inside is filled with a hexagonal
+/// grid of points, and `CVT_ITERATIONS` (default 10) Lloyd iterations move the inner points
+/// towards the centroids of their Voronoi cells. The Delaunay triangulation of the points is
+/// returned, without the triangles outside of the region. The boundary points are never moved,
+/// and they are dense enough for the Delaunay edges to follow the outline.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let model = &models[0];
+    if let Some(index) = model.indices.iter().find(|i| **i >= model.vertices.len()) {
+        return Err(HallrError::InvalidInputData(format!(
+            "The index {} is out of bounds",
+            index
+        )));
+    }
+    let loops = cmd_orient_outlines::classify_loops(model.vertices, model.indices)?;
+    if loops.is_empty() {
+        return Err(HallrError::NoData("The outline has no edges".to_string()));
+    }
+    let point = |i: usize| dvec2(model.vertices[i].x as f64, model.vertices[i].y as f64);
+    let region: Vec<(DVec2, DVec2)> = loops
+        .iter()
+        .flat_map(|l| {
+            (0..l.indices.len()).map(move |i| (l.indices[i], l.indices[(i + 1) % l.indices.len()]))
+        })
+        .map(|(a, b)| (point(a), point(b)))
+        .collect();
+    let (min, max) = region.iter().fold(
+        (DVec2::splat(f64::MAX), DVec2::splat(f64::MIN)),
+        |(min, max), (a, b)| (min.min(a.min(*b)), max.max(a.max(*b))),
+    );
+    if (max - min).min_element() <= 0.0 {
+        return Err(HallrError::InvalidInputData(
+            "The outline has no area".to_string(),
+        ));
+    }
+    let edge_length = config.get_mandatory_parsed_option::<f64>(
+        "CVT_EDGE_LENGTH",
+        Some((max - min).max_element() / 20.0),
+    )?;
+    if !edge_length.is_finite() || edge_length <= 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "CVT_EDGE_LENGTH must be a positive number :({})",
+            edge_length
+        )));
+    }
+    let iterations = config.get_mandatory_parsed_option::<usize>("CVT_ITERATIONS", Some(10))?;
+    let row_spacing = edge_length * 3.0_f64.sqrt() * 0.5;
+    let estimate = ((max - min).x / edge_length + 2.0) * ((max - min).y / row_spacing + 2.0);
+    if estimate > MAX_POINTS as f64 {
+        return Err(HallrError::InvalidParameter(format!(
+            "The remesh would need about {} points, the maximum is {}. Increase CVT_EDGE_LENGTH.",
+            estimate as usize, MAX_POINTS
+        )));
+    }
+
+    // the resampled outline, these points are never moved
+    let mut points = Vec::<DVec2>::new();
+    for (a, b) in region.iter() {
+        let steps = (a.distance(*b) / edge_length).ceil().max(1.0) as usize;
+        points.extend((0..steps).map(|s| a.lerp(*b, s as f64 / steps as f64)));
+    }
+    let fixed = points.len();
+    // a hexagonal grid of interior points
+    let min_boundary_distance = edge_length * MIN_BOUNDARY_DISTANCE;
+    let mut y = min.y + row_spacing * 0.5;
+    let mut row = 0;
+    while y < max.y {
+        let mut x = min.x + if row % 2 == 0 { 0.0 } else { edge_length * 0.5 };
+        while x < max.x {
+            let p = dvec2(x, y);
+            if cmd_clip_curves::is_inside_region(p, &region)
+                && boundary_distance(p, &region) >= min_boundary_distance
+            {
+                points.push(p);
+            }
+            x += edge_length;
+        }
+        y += row_spacing;
+        row += 1;
+    }
+
+    for _ in 0..iterations {
+        let triangles = triangulate_region(&points, &region);
+        lloyd_iteration(
+            &mut points,
+            fixed,
+            &triangles,
+            &region,
+            min_boundary_distance,
+        );
+    }
+    let triangles = triangulate_region(&points, &region);
+    if triangles.is_empty() {
+        return Err(HallrError::NoData(
+            "The remesh did not produce any triangles".to_string(),
+        ));
+    }
+
+    // the triangles are returned in the plane of the input
+    let z = model.vertices.iter().map(|v| v.z as f64).sum::<f64>() / model.vertices.len() as f64;
+    let output_vertices: Vec<FFIVector3> = points
+        .iter()
+        .map(|p| FFIVector3::new(p.x as f32, p.y as f32, z as f32))
+        .collect();
+    let output_indices: Vec<usize> = triangles.into_iter().flatten().collect();
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = return_config.insert("CVT_EDGE_LENGTH".to_string(), edge_length.to_string());
+    info!(
+        "centroidal_remesh operation returning {} vertices, {} triangles",
+        output_vertices.len(),
+        output_indices.len() / 3
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
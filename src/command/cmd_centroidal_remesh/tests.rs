@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, quality_report, ConfigType},
+    ffi::FFIVector3,
+    HallrError,
+};
+
+/// Returns the smallest and the summed signed area of the triangles, in the XY plane
+fn triangle_areas(vertices: &[FFIVector3], indices: &[usize]) -> (f64, f64) {
+    indices
+        .chunks_exact(3)
+        .map(|t| {
+            let (a, b, c) = (&vertices[t[0]], &vertices[t[1]], &vertices[t[2]]);
+            (((b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)) * 0.5) as f64
+        })
+        .fold((f64::MAX, 0.0), |(min, sum), area| {
+            (min.min(area), sum + area)
+        })
+}
+
+#[test]
+fn test_centroidal_remesh_1() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "centroidal_remesh".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("CVT_EDGE_LENGTH".to_string(), "1.0".to_string());
+    let _ = config.insert("CVT_ITERATIONS".to_string(), "5".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [10.0, 10.0], 2.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!("triangulated", result.3.get("mesh.format").unwrap());
+    // the interior is filled with roughly one vertex per 0.87 square units
+    assert!(
+        result.0.len() > 100 && result.0.len() < 160,
+        "{}",
+        result.0.len()
+    );
+    assert!(result.0.iter().all(|v| v.z == 2.0));
+    let (min_area, area) = triangle_areas(&result.0, &result.1);
+    assert!(min_area > 0.0, "{}", min_area);
+    assert!((area - 100.0).abs() < 1e-3, "{}", area);
+    let report = quality_report::quality_report(&result.0, &result.1);
+    assert_eq!(0, report.slivers);
+    Ok(())
+}
+
+#[test]
+fn test_centroidal_remesh_2() -> Result<(), HallrError> {
+    // a square with a square hole
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "centroidal_remesh".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("CVT_EDGE_LENGTH".to_string(), "1.0".to_string());
+    let _ = config.insert("CVT_ITERATIONS".to_string(), "5".to_string());
+
+    let mut owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [10.0, 10.0], 2.0);
+    fixtures::add_rectangle_outline(&mut owned_model_0, [3.0, 3.0], [7.0, 7.0], 2.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    let (min_area, area) = triangle_areas(&result.0, &result.1);
+    assert!(min_area > 0.0, "{}", min_area);
+    assert!((area - 84.0).abs() < 1e-3, "{}", area);
+    // no triangle is inside of the hole
+    assert!(result.1.chunks_exact(3).all(|t| {
+        let x = (result.0[t[0]].x + result.0[t[1]].x + result.0[t[2]].x) / 3.0;
+        let y = (result.0[t[0]].y + result.0[t[1]].y + result.0[t[2]].y) / 3.0;
+        !(x > 3.0 && x < 7.0 && y > 3.0 && y < 7.0)
+    }));
+    Ok(())
+}
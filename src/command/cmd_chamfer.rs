@@ -0,0 +1,190 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{cmd_clip_curves, cmd_hatch, cmd_optimize_path, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use std::f64::consts::PI;
+use vector_traits::glam::{dvec2, DVec2};
+
+#[cfg(test)]
+mod tests;
+
+/// Offsets smaller than this are treated as zero, the outline is then used as it is
+const EPSILON: f64 = 1e-9;
+
+/// Returns the (depth, offset) of the tip of a V-bit with the `included_angle` (radians), cutting
+/// a chamfer `width` wide. The tip runs `tip_offset` away from the edge, so that it does not rub
+/// against the wall.
+pub(crate) fn v_bit_placement(
+    width: f64,
+    included_angle: f64,
+    tip_offset: f64,
+) -> Result<(f64, f64), HallrError> {
+    if included_angle.is_nan() || included_angle <= 0.0 || included_angle >= PI {
+        return Err(HallrError::InvalidParameter(
+            "V_BIT_ANGLE must be in the 0..180 degree range".to_string(),
+        ));
+    }
+    Ok((
+        (width + tip_offset) / (included_angle * 0.5).tan(),
+        tip_offset,
+    ))
+}
+
+/// Returns the (depth, offset) of the bottom of a roundover bit with the `radius`, cutting a
+/// roundover `width` wide. The bit is guided by a bearing (or pilot) of `bearing_radius`.
+/// A width smaller than the radius is cut by raising the bit, leaving a partial roundover.
+pub(crate) fn roundover_placement(
+    width: f64,
+    radius: f64,
+    bearing_radius: f64,
+) -> Result<(f64, f64), HallrError> {
+    if !radius.is_finite() || radius <= 0.0 || width > radius {
+        return Err(HallrError::InvalidParameter(format!(
+            "The roundover width ({}) can not be larger than the radius ({})",
+            width, radius
+        )));
+    }
+    Ok(((width * (2.0 * radius - width)).sqrt(), bearing_radius))
+}
+
+/// Returns the outline of the model as polylines, as it is
+fn outline_polylines(model: &Model<'_>) -> Vec<Vec<DVec2>> {
+    let segments: Vec<(u64, u64)> = model
+        .indices
+        .chunks_exact(2)
+        .filter(|edge| edge[0] != edge[1])
+        .map(|edge| (edge[0] as u64, edge[1] as u64))
+        .collect();
+    cmd_hatch::chain_segments(&segments)
+        .iter()
+        .map(|chain| {
+            chain
+                .iter()
+                .map(|i| {
+                    let v = model.vertices[*i as usize];
+                    dvec2(v.x as f64, v.y as f64)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Run the chamfer command
+/// Model 0 contains one or more closed planar outlines (in the line_chunks format), holes are
+/// handled with the even-odd rule. The toolpath cutting a chamfer (`CHAMFER_TOOL=V_BIT`) or a
+/// roundover (`CHAMFER_TOOL=ROUNDOVER`) of `CHAMFER_WIDTH` along the outline is returned in the
+/// line_chunks format, at the required depth below the Z coordinate of the first vertex.
+/// The tool runs outside the region, or inside it with `CHAMFER_SIDE=INSIDE`. The depth and the
+/// offset of the tool path are returned as `CHAMFER_DEPTH` and `CHAMFER_OFFSET`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let width = config.get_mandatory_parsed_option::<f64>("CHAMFER_WIDTH", None)?;
+    if !width.is_finite() || width <= 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "CHAMFER_WIDTH must be a positive number :({})",
+            width
+        )));
+    }
+    let (depth, offset) = match config.get_mandatory_option("CHAMFER_TOOL")? {
+        "V_BIT" => v_bit_placement(
+            width,
+            config
+                .get_mandatory_parsed_option::<f64>("V_BIT_ANGLE", Some(90.0))?
+                .to_radians(),
+            config.get_mandatory_parsed_option::<f64>("TIP_OFFSET", Some(0.0))?,
+        )?,
+        "ROUNDOVER" => roundover_placement(
+            width,
+            config.get_mandatory_parsed_option::<f64>("ROUNDOVER_RADIUS", None)?,
+            config.get_mandatory_parsed_option::<f64>("BEARING_RADIUS", Some(0.0))?,
+        )?,
+        tool => {
+            return Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"CHAMFER_TOOL\" parameter",
+                tool
+            )))
+        }
+    };
+    if !offset.is_finite() || offset < 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "The tool offset must be zero or positive :({})",
+            offset
+        )));
+    }
+    // the distance field is positive inside the region
+    let level = match config
+        .get_mandatory_parsed_option::<String>("CHAMFER_SIDE", Some("OUTSIDE".to_string()))?
+        .as_str()
+    {
+        "OUTSIDE" => -offset,
+        "INSIDE" => offset,
+        side => {
+            return Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"CHAMFER_SIDE\" parameter",
+                side
+            )))
+        }
+    };
+
+    let model = &models[0];
+    let top_z = model.vertices.first().map_or(0.0, |v| v.z as f64);
+    let region = cmd_clip_curves::parse_region(model)?;
+    let polylines = if offset < EPSILON {
+        outline_polylines(model)
+    } else {
+        cmd_hatch::DistanceField::new(&region, offset * 0.25, offset * 2.0).iso_lines(level)
+    };
+    if polylines.is_empty() {
+        return Err(HallrError::NoData(
+            "The chamfer tool path is empty, the offset is too large for the region".to_string(),
+        ));
+    }
+    let (polylines, _) = cmd_optimize_path::order_polylines(polylines, |p| p.extend(0.0), false);
+
+    let z = (top_z - depth) as f32;
+    let mut output_vertices = Vec::<FFIVector3>::new();
+    let mut output_indices = Vec::<usize>::new();
+    for polyline in polylines {
+        let first_index = output_vertices.len();
+        for (i, v) in polyline.iter().enumerate() {
+            output_vertices.push(FFIVector3::new(v.x as f32, v.y as f32, z));
+            if i > 0 {
+                output_indices.push(first_index + i - 1);
+                output_indices.push(first_index + i);
+            }
+        }
+    }
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = return_config.insert("CHAMFER_DEPTH".to_string(), depth.to_string());
+    let _ = return_config.insert("CHAMFER_OFFSET".to_string(), offset.to_string());
+    info!(
+        "chamfer operation returning {} vertices, {} indices, depth:{} offset:{}",
+        output_vertices.len(),
+        output_indices.len(),
+        depth,
+        offset
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType},
+    HallrError,
+};
+
+#[test]
+fn test_chamfer_placement() -> Result<(), HallrError> {
+    let (depth, offset) = super::v_bit_placement(0.5, 90.0_f64.to_radians(), 0.0)?;
+    assert!((depth - 0.5).abs() < 1e-9);
+    assert_eq!(0.0, offset);
+    let (depth, offset) = super::v_bit_placement(0.5, 60.0_f64.to_radians(), 0.1)?;
+    assert!((depth - 0.6 * 3.0_f64.sqrt()).abs() < 1e-9);
+    assert_eq!(0.1, offset);
+    assert!(super::v_bit_placement(0.5, 180.0_f64.to_radians(), 0.0).is_err());
+
+    // a full roundover is cut at the depth of the radius
+    let (depth, offset) = super::roundover_placement(0.25, 0.25, 0.1)?;
+    assert!((depth - 0.25).abs() < 1e-9);
+    assert_eq!(0.1, offset);
+    let (depth, _) = super::roundover_placement(0.1, 0.25, 0.0)?;
+    assert!((depth - 0.2).abs() < 1e-9);
+    assert!(super::roundover_placement(0.3, 0.25, 0.0).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_chamfer_1() -> Result<(), HallrError> {
+    // the tip runs along the outline itself
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "chamfer".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("CHAMFER_TOOL".to_string(), "V_BIT".to_string());
+    let _ = config.insert("CHAMFER_WIDTH".to_string(), "0.25".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 1.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(5, result.0.len()); // vertices
+    assert_eq!(8, result.1.len()); // indices
+    assert!(result.0.iter().all(|v| (v.z - 0.75).abs() < 1e-6));
+    Ok(())
+}
+
+#[test]
+fn test_chamfer_2() -> Result<(), HallrError> {
+    // a bearing guided roundover, the tool path runs outside the square
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "chamfer".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("CHAMFER_TOOL".to_string(), "ROUNDOVER".to_string());
+    let _ = config.insert("CHAMFER_WIDTH".to_string(), "0.2".to_string());
+    let _ = config.insert("ROUNDOVER_RADIUS".to_string(), "0.2".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 1.0);
+    let models = vec![owned_model_0.as_model()];
+    let mut bearing_config = config.clone();
+    let _ = bearing_config.insert("BEARING_RADIUS".to_string(), "0.1".to_string());
+    let result = super::process_command(bearing_config, models)?;
+    assert!(!result.0.is_empty());
+    assert!(result.0.iter().all(|v| (v.z - 0.8).abs() < 1e-6));
+    // every point is about the bearing radius away from the square
+    assert!(result.0.iter().all(|v| {
+        let dx = (-v.x).max(v.x - 1.0).max(0.0);
+        let dy = (-v.y).max(v.y - 1.0).max(0.0);
+        ((dx * dx + dy * dy).sqrt() - 0.1).abs() < 0.02
+    }));
+
+    // the width is larger than the radius
+    let models = vec![owned_model_0.as_model()];
+    let _ = config.insert("CHAMFER_WIDTH".to_string(), "0.3".to_string());
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
@@ -0,0 +1,204 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use ahash::AHashMap;
+use vector_traits::glam::{dvec2, DVec2};
+
+#[cfg(test)]
+mod tests;
+
+/// Parameter tolerance used when merging split points along a segment
+const T_EPSILON: f64 = 1e-9;
+
+/// Returns true if `p` is inside the region, using the even-odd rule.
+/// The region is a soup of edges forming one or more closed loops, so holes are supported.
+pub(crate) fn is_inside_region(p: DVec2, region: &[(DVec2, DVec2)]) -> bool {
+    let mut inside = false;
+    for (a, b) in region.iter() {
+        if (a.y > p.y) != (b.y > p.y) {
+            let x = a.x + (p.y - a.y) * (b.x - a.x) / (b.y - a.y);
+            if p.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Clips the segment a->b against the region. Returns the parameter ranges (along a->b, in the
+/// 0..=1 range) of the pieces that are inside the region, or outside it if `keep_inside` is false.
+pub(crate) fn clip_segment(
+    a: DVec2,
+    b: DVec2,
+    region: &[(DVec2, DVec2)],
+    keep_inside: bool,
+) -> Vec<(f64, f64)> {
+    let ab = b - a;
+    let mut splits = vec![0.0, 1.0];
+    for (p, q) in region.iter() {
+        let s = *q - *p;
+        let denominator = ab.perp_dot(s);
+        if denominator.abs() < f64::EPSILON {
+            // parallel
+            continue;
+        }
+        let pa = *p - a;
+        let t = pa.perp_dot(s) / denominator;
+        let u = pa.perp_dot(ab) / denominator;
+        if t > 0.0 && t < 1.0 && (0.0..=1.0).contains(&u) {
+            splits.push(t);
+        }
+    }
+    splits.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+    splits.dedup_by(|a, b| (*a - *b).abs() <= T_EPSILON);
+
+    let mut rv = Vec::<(f64, f64)>::new();
+    for window in splits.windows(2) {
+        let (t0, t1) = (window[0], window[1]);
+        if is_inside_region(a + ab * ((t0 + t1) * 0.5), region) != keep_inside {
+            continue;
+        }
+        match rv.last_mut() {
+            // merge with the previous piece if they are connected
+            Some(last) if (last.1 - t0).abs() <= T_EPSILON => last.1 = t1,
+            _ => rv.push((t0, t1)),
+        }
+    }
+    rv
+}
+
+/// Parse a closed planar region, the edges of the model projected onto the XY plane
+pub(crate) fn parse_region(model: &Model<'_>) -> Result<Vec<(DVec2, DVec2)>, HallrError> {
+    let mut region = Vec::<(DVec2, DVec2)>::with_capacity(model.indices.len() / 2);
+    for edge in model.indices.chunks_exact(2) {
+        let v0 = model.vertices[edge[0]];
+        let v1 = model.vertices[edge[1]];
+        if !v0.x.is_finite() || !v0.y.is_finite() || !v1.x.is_finite() || !v1.y.is_finite() {
+            return Err(HallrError::InvalidInputData(
+                "Only valid coordinates are allowed".to_string(),
+            ));
+        }
+        if v0.x == v1.x && v0.y == v1.y {
+            // zero length segment
+            continue;
+        }
+        region.push((
+            dvec2(v0.x as f64, v0.y as f64),
+            dvec2(v1.x as f64, v1.y as f64),
+        ));
+    }
+    if region.len() < 3 {
+        return Err(HallrError::InvalidInputData(
+            "The region must be a closed loop of at least three edges".to_string(),
+        ));
+    }
+    Ok(region)
+}
+
+/// Run the clip_curves command
+/// Model 0 contains the curves and model 1 the closed clipping region, both in the line_chunks
+/// format. The curves are split where they cross the region boundary and only the pieces inside
+/// (or outside, with `CLIP_MODE=OUTSIDE`) the region are kept. The test is done in the XY plane,
+/// the Z coordinate of the curves is interpolated.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.len() < 2 {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires two input models: the curves and the clipping region"
+                .to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let keep_inside = match config
+        .get_mandatory_parsed_option::<String>("CLIP_MODE", Some("INSIDE".to_string()))?
+        .as_str()
+    {
+        "INSIDE" => true,
+        "OUTSIDE" => false,
+        mode => {
+            return Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"CLIP_MODE\" parameter",
+                mode
+            )))
+        }
+    };
+    let curves = &models[0];
+    let region = parse_region(&models[1])?;
+    debug!(
+        "clip_curves: {} curve edges, {} region edges, keep inside:{}",
+        curves.indices.len() / 2,
+        region.len(),
+        keep_inside
+    );
+
+    let mut output_vertices = Vec::<FFIVector3>::new();
+    let mut output_indices = Vec::<usize>::new();
+    // the original vertices are reused, so that the kept parts of a polyline stay connected
+    let mut vertex_map = AHashMap::<usize, usize>::new();
+
+    for edge in curves.indices.chunks_exact(2) {
+        let (i0, i1) = (edge[0], edge[1]);
+        let (v0, v1) = (curves.vertices[i0], curves.vertices[i1]);
+        if !v0.x.is_finite() || !v0.y.is_finite() || !v1.x.is_finite() || !v1.y.is_finite() {
+            return Err(HallrError::InvalidInputData(
+                "Only valid coordinates are allowed".to_string(),
+            ));
+        }
+        let a = dvec2(v0.x as f64, v0.y as f64);
+        let b = dvec2(v1.x as f64, v1.y as f64);
+        if a == b {
+            // zero length segment
+            continue;
+        }
+        for (t0, t1) in clip_segment(a, b, &region, keep_inside) {
+            for t in [t0, t1] {
+                let original = if t <= T_EPSILON {
+                    Some(i0)
+                } else if t >= 1.0 - T_EPSILON {
+                    Some(i1)
+                } else {
+                    None
+                };
+                let index = if let Some(original) = original {
+                    *vertex_map.entry(original).or_insert_with(|| {
+                        output_vertices.push(curves.vertices[original]);
+                        output_vertices.len() - 1
+                    })
+                } else {
+                    let t = t as f32;
+                    output_vertices.push(FFIVector3::new(
+                        v0.x + (v1.x - v0.x) * t,
+                        v0.y + (v1.y - v0.y) * t,
+                        v0.z + (v1.z - v0.z) * t,
+                    ));
+                    output_vertices.len() - 1
+                };
+                output_indices.push(index);
+            }
+        }
+    }
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    info!(
+        "clip_curves operation returning {} vertices, {} indices",
+        output_vertices.len(),
+        output_indices.len()
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        curves.world_orientation.to_vec(),
+        return_config,
+    ))
+}
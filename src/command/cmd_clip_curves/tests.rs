@@ -0,0 +1,100 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType, OwnedModel},
+    HallrError,
+};
+
+#[test]
+fn test_clip_curves_1() -> Result<(), HallrError> {
+    // a line crossing the square, keep the inside
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "clip_curves".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(-1.0, 0.5, 0.0).into(), (2.0, 0.5, 3.0).into()],
+        indices: vec![0, 1],
+    };
+    let owned_model_1 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 0.0);
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(2, result.0.len()); // vertices
+    assert_eq!(2, result.1.len()); // indices
+    assert_eq!(0.0, result.0[0].x);
+    assert_eq!(1.0, result.0[0].z);
+    assert_eq!(1.0, result.0[1].x);
+    assert_eq!(2.0, result.0[1].z);
+    Ok(())
+}
+
+#[test]
+fn test_clip_curves_2() -> Result<(), HallrError> {
+    // a line crossing the square, keep the outside
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "clip_curves".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("CLIP_MODE".to_string(), "OUTSIDE".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(-1.0, 0.5, 0.0).into(), (2.0, 0.5, 0.0).into()],
+        indices: vec![0, 1],
+    };
+    let owned_model_1 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 0.0);
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(4, result.0.len()); // vertices
+    assert_eq!(4, result.1.len()); // indices
+    Ok(())
+}
+
+#[test]
+fn test_clip_curves_3() -> Result<(), HallrError> {
+    // a polyline entering and leaving the square, the kept parts stay connected
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "clip_curves".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.0, 0.5, 0.0).into(),
+            (0.5, 0.5, 0.0).into(),
+            (0.5, 2.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2],
+    };
+    let owned_model_1 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 0.0);
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(3, result.0.len()); // vertices
+    assert_eq!(vec![0, 1, 1, 2], result.1); // indices
+    Ok(())
+}
+
+#[test]
+fn test_clip_curves_4() -> Result<(), HallrError> {
+    // invalid clip mode
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "clip_curves".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("CLIP_MODE".to_string(), "BOTH".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(-1.0, 0.5, 0.0).into(), (2.0, 0.5, 0.0).into()],
+        indices: vec![0, 1],
+    };
+    let owned_model_1 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 0.0);
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
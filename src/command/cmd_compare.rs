@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{cmd_scalar_to_color, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, utils::bvh::Bvh, HallrError};
+use rayon::prelude::*;
+use std::time;
+use vector_traits::glam::{dvec3, DVec3};
+
+#[cfg(test)]
+mod tests;
+
+/// Run the compare command
+/// Model 0 is the measured mesh and model 1 is the reference mesh, both triangulated.
+/// The distance from every vertex of model 0 to the closest point of model 1 is calculated.
+/// Model 0 is returned unchanged, together with the `DISTANCE_MAX`, `DISTANCE_MEAN` and
+/// `DISTANCE_RMS` statistics. With `DISTANCE_CHANNEL=true` the per-vertex distances are returned
+/// as a comma separated list in `DISTANCES`, in vertex order. With a `COLOR_MAP` the distances
+/// are also returned as `VERTEX_COLORS`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.len() < 2 {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires two input models: the measured and the reference mesh"
+                .to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("triangulated") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'triangulated' format".to_string(),
+        ));
+    }
+    let distance_channel =
+        config.get_mandatory_parsed_option::<bool>("DISTANCE_CHANNEL", Some(false))?;
+    let measured = &models[0];
+    let reference = &models[1];
+    if measured.vertices.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "The measured mesh has no vertices".to_string(),
+        ));
+    }
+    let to_dvec3 = |v: &FFIVector3| dvec3(v.x as f64, v.y as f64, v.z as f64);
+    let triangles: Vec<[DVec3; 3]> = reference
+        .indices
+        .chunks_exact(3)
+        .map(|t| {
+            [
+                to_dvec3(&reference.vertices[t[0]]),
+                to_dvec3(&reference.vertices[t[1]]),
+                to_dvec3(&reference.vertices[t[2]]),
+            ]
+        })
+        .collect();
+    if triangles.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "The reference mesh has no triangles".to_string(),
+        ));
+    }
+    debug!(
+        "compare: {} vertices against {} triangles",
+        measured.vertices.len(),
+        triangles.len()
+    );
+
+    let now = time::Instant::now();
+    let bvh = Bvh::new(triangles);
+    let distances: Vec<f64> = measured
+        .vertices
+        .par_iter()
+        .map(|v| bvh.distance(to_dvec3(v)).unwrap())
+        .collect();
+    debug!("compare: duration:{:?}", now.elapsed());
+
+    let max = distances.iter().copied().fold(0.0, f64::max);
+    let mean = distances.iter().sum::<f64>() / distances.len() as f64;
+    let rms = (distances.iter().map(|d| d * d).sum::<f64>() / distances.len() as f64).sqrt();
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = return_config.insert("DISTANCE_MAX".to_string(), max.to_string());
+    let _ = return_config.insert("DISTANCE_MEAN".to_string(), mean.to_string());
+    let _ = return_config.insert("DISTANCE_RMS".to_string(), rms.to_string());
+    cmd_scalar_to_color::add_vertex_colors(
+        &config,
+        &distances.iter().map(|d| *d as f32).collect::<Vec<_>>(),
+        &mut return_config,
+    )?;
+    if distance_channel {
+        let _ = return_config.insert(
+            "DISTANCES".to_string(),
+            distances
+                .iter()
+                .map(|d| (*d as f32).to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+    }
+    info!(
+        "compare operation returning {} vertices, max:{} mean:{} rms:{}",
+        measured.vertices.len(),
+        max,
+        mean,
+        rms
+    );
+    Ok((
+        measured.vertices.to_vec(),
+        measured.indices.to_vec(),
+        measured.world_orientation.to_vec(),
+        return_config,
+    ))
+}
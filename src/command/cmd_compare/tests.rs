@@ -0,0 +1,50 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType, OwnedModel},
+    HallrError,
+};
+
+#[test]
+fn test_compare_1() -> Result<(), HallrError> {
+    // the measured square is 0.5 above the reference square
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "compare".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("DISTANCE_CHANNEL".to_string(), "true".to_string());
+
+    let owned_model_0 = fixtures::rectangle_faces([0.0, 0.0], [1.0, 1.0], 0.5);
+    let owned_model_1 = fixtures::rectangle_faces([0.0, 0.0], [1.0, 1.0], 0.0);
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(4, result.0.len()); // vertices
+    assert_eq!(6, result.1.len()); // indices
+    let stat = |key: &str| result.3.get(key).unwrap().parse::<f64>().unwrap();
+    assert!((stat("DISTANCE_MAX") - 0.5).abs() < 1e-6);
+    assert!((stat("DISTANCE_MEAN") - 0.5).abs() < 1e-6);
+    assert!((stat("DISTANCE_RMS") - 0.5).abs() < 1e-6);
+    assert_eq!(4, result.3.get("DISTANCES").unwrap().split(',').count());
+    Ok(())
+}
+
+#[test]
+fn test_compare_2() -> Result<(), HallrError> {
+    // the reference model has no triangles
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "compare".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+
+    let owned_model_0 = fixtures::rectangle_faces([0.0, 0.0], [1.0, 1.0], 0.5);
+    let owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(0.0, 0.0, 0.0).into()],
+        indices: vec![],
+    };
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
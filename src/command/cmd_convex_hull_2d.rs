@@ -39,7 +39,7 @@ where
     rv_model.close_loop();
     let mut config = ConfigType::new();
     let _ = config.insert("mesh.format".to_string(), "line_windows".to_string());
-    println!(
+    info!(
         "convex_hull_2d operation returning {} vertices",
         rv_model.indices.len()
     );
@@ -2,17 +2,25 @@
 // Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
 // This file is part of the hallr crate.
 
-use super::{ConfigType, Model, Options};
+use super::{cmd_centroidal_remesh, cmd_clip_curves, ConfigType, Model, Options};
 use crate::prelude::*;
+use ahash::{AHashMap, AHashSet};
 use hronn::prelude::{triangulate_vertices, ConvertTo};
 
 use krakel::PointTrait;
 use linestring::linestring_2d::{convex_hull, Aabb2};
-use vector_traits::{num_traits::AsPrimitive, GenericVector3, HasXY};
+use vector_traits::{
+    glam::{DVec2, DVec3},
+    num_traits::AsPrimitive,
+    GenericVector3, HasXY,
+};
 
 #[cfg(test)]
 mod tests;
 
+/// The largest number of times the missing constraint edges are split
+const MAX_SPLIT_ROUNDS: usize = 32;
+
 fn aabb_delaunay_triangulation_2d<T: GenericVector3>(
     _config: ConfigType,
     models: Vec<Model<'_>>,
@@ -70,8 +78,8 @@ where
     // do not limit us to a line bound, - yet
     //let bounding_indices =
     //    crate::collision::continuous_loop_from_unordered_edges(bounding_indices)?;
-    println!("bounding_indices {:?}", bounding_shape.indices.len());
-    println!("bounding_vertices {:?}", bounding_shape.vertices.len());
+    debug!("bounding_indices {:?}", bounding_shape.indices.len());
+    debug!("bounding_vertices {:?}", bounding_shape.vertices.len());
 
     let convex_hull: Vec<T::Vector2> = {
         // strip the Z coordinate off the bounding shape
@@ -95,6 +103,147 @@ where
     ))
 }
 
+/// Returns the index of the point at the XY position of `v`, adding it if it is new
+fn add_point(points: &mut Vec<DVec3>, lookup: &mut AHashMap<(u64, u64), usize>, v: DVec3) -> usize {
+    *lookup
+        .entry((v.x.to_bits(), v.y.to_bits()))
+        .or_insert_with(|| {
+            points.push(v);
+            points.len() - 1
+        })
+}
+
+/// The Delaunay triangulation of the points of model 0 and model 1, where every edge of model 1
+/// is a constraint that must be in the triangulation. Constraint edges missing from the
+/// triangulation are split at their mid points until all the pieces are Delaunay edges (a
+/// conforming triangulation). With `REMOVE_OUTSIDE` the triangles outside of the constraint
+/// loops (even-odd rule, so inner loops are holes) are removed. The number of inserted points is
+/// returned as `CONSTRAINT_SPLITS`.
+fn constrained_delaunay_triangulation_2d(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    let model = &models[0];
+    let constraint_model = &models[1];
+    let cmd_arg_remove_outside = config
+        .get_parsed_option::<bool>("REMOVE_OUTSIDE")?
+        .unwrap_or(false);
+
+    let mut points = Vec::<DVec3>::new();
+    let mut lookup = AHashMap::<(u64, u64), usize>::new();
+    for v in model
+        .vertices
+        .iter()
+        .chain(constraint_model.vertices.iter())
+    {
+        if !v.x.is_finite() || !v.y.is_finite() || !v.z.is_finite() {
+            return Err(HallrError::InvalidInputData(format!(
+                "Only valid coordinates are allowed ({},{},{})",
+                v.x, v.y, v.z
+            )));
+        }
+    }
+    for v in model.vertices.iter() {
+        let _ = add_point(&mut points, &mut lookup, v.to());
+    }
+    let mut constraints = Vec::<(usize, usize)>::new();
+    for edge in constraint_model.indices.chunks_exact(2) {
+        let a = add_point(
+            &mut points,
+            &mut lookup,
+            constraint_model.vertices[edge[0]].to(),
+        );
+        let b = add_point(
+            &mut points,
+            &mut lookup,
+            constraint_model.vertices[edge[1]].to(),
+        );
+        if a != b {
+            constraints.push((a.min(b), a.max(b)));
+        }
+    }
+    constraints.sort_unstable();
+    constraints.dedup();
+    if constraints.is_empty() {
+        return Err(HallrError::NoData(
+            "The constraint model has no edges".to_string(),
+        ));
+    }
+
+    let first_split_point = points.len();
+    let mut triangles = Vec::<[usize; 3]>::new();
+    for round in 0..=MAX_SPLIT_ROUNDS {
+        let points_2d: Vec<DVec2> = points.iter().map(|p| p.truncate()).collect();
+        triangles = cmd_centroidal_remesh::delaunay(&points_2d);
+        let edges: AHashSet<(usize, usize)> = triangles
+            .iter()
+            .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect();
+        if constraints.iter().all(|c| edges.contains(c)) {
+            break;
+        }
+        if round == MAX_SPLIT_ROUNDS {
+            return Err(HallrError::InvalidInputData(
+                "Could not recover the constraint edges, they must not cross each other"
+                    .to_string(),
+            ));
+        }
+        let mut split_constraints = Vec::<(usize, usize)>::with_capacity(constraints.len());
+        for (a, b) in constraints.iter().copied() {
+            if edges.contains(&(a, b)) {
+                split_constraints.push((a, b));
+                continue;
+            }
+            let mid_point = (points[a] + points[b]) * 0.5;
+            let m = add_point(&mut points, &mut lookup, mid_point);
+            if m == a || m == b {
+                return Err(HallrError::InvalidInputData(
+                    "Could not recover the constraint edges, they are too short".to_string(),
+                ));
+            }
+            split_constraints.push((a.min(m), a.max(m)));
+            split_constraints.push((m.min(b), m.max(b)));
+        }
+        constraints = split_constraints;
+    }
+
+    if cmd_arg_remove_outside {
+        let region: Vec<(DVec2, DVec2)> = constraints
+            .iter()
+            .map(|(a, b)| (points[*a].truncate(), points[*b].truncate()))
+            .collect();
+        triangles.retain(|t| {
+            let centroid = (points[t[0]] + points[t[1]] + points[t[2]]).truncate() / 3.0;
+            cmd_clip_curves::is_inside_region(centroid, &region)
+        });
+    }
+    // only the used points are returned
+    let mut remap = vec![usize::MAX; points.len()];
+    let mut vertices = Vec::<FFIVector3>::new();
+    let mut indices = Vec::<usize>::with_capacity(triangles.len() * 3);
+    for index in triangles.iter().flatten() {
+        if remap[*index] == usize::MAX {
+            remap[*index] = vertices.len();
+            vertices.push(points[*index].to());
+        }
+        indices.push(remap[*index]);
+    }
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = return_config.insert(
+        "CONSTRAINT_SPLITS".to_string(),
+        (points.len() - first_split_point).to_string(),
+    );
+    Ok((
+        vertices,
+        indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
+
 pub(crate) fn process_command<T: GenericVector3>(
     config: ConfigType,
     models: Vec<Model<'_>>,
@@ -115,6 +264,7 @@ where
     match config.get_mandatory_option("bounds")? {
         "CONVEX_HULL" => convex_hull_delaunay_triangulation_2d::<T>(config, models),
         "AABB" => aabb_delaunay_triangulation_2d::<T>(config, models),
+        "CONSTRAINED" => constrained_delaunay_triangulation_2d(config, models),
         bounds => Err(HallrError::InvalidParameter(format!(
             "{} is not a valid \"bounds\" parameter",
             bounds
@@ -115,3 +115,64 @@ fn test_2d_delaunay_triangulation_2() -> Result<(), HallrError> {
     assert_eq!(87, result.1.len()); // indices
     Ok(())
 }
+
+#[test]
+fn test_2d_delaunay_triangulation_constrained() -> Result<(), HallrError> {
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (2.0, 2.0, 0.0).into(),
+            (8.0, 3.0, 0.0).into(),
+            (5.0, 5.0, 0.0).into(),
+            (3.0, 8.0, 0.0).into(),
+            // close to the hole, the Delaunay edges would cross its outline
+            (5.0, 3.9, 0.0).into(),
+        ],
+        indices: vec![],
+    };
+    // a square with a square hole
+    let mut owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![],
+        indices: vec![],
+    };
+    for (min, max) in [(0.0, 10.0), (4.0, 6.0)] {
+        let first = owned_model_1.vertices.len();
+        for (x, y) in [(min, min), (max, min), (max, max), (min, max)] {
+            owned_model_1.vertices.push((x, y, 0.0).into());
+        }
+        for i in 0..4 {
+            owned_model_1.indices.push(first + i);
+            owned_model_1.indices.push(first + (i + 1) % 4);
+        }
+    }
+    let area = |vertices: &[crate::ffi::FFIVector3], indices: &[usize]| -> f32 {
+        indices
+            .chunks_exact(3)
+            .map(|t| {
+                let (a, b, c) = (vertices[t[0]], vertices[t[1]], vertices[t[2]]);
+                0.5 * ((b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x))
+            })
+            .sum()
+    };
+
+    for (remove_outside, expected_area) in [(false, 100.0), (true, 96.0)] {
+        let mut config = ConfigType::default();
+        let _ = config.insert("bounds".to_string(), "CONSTRAINED".to_string());
+        let _ = config.insert("REMOVE_OUTSIDE".to_string(), remove_outside.to_string());
+        let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+        let result = super::process_command::<Vec3>(config, models)?;
+        assert_eq!("triangulated", result.3.get("mesh.format").unwrap());
+        let splits: usize = result.3.get("CONSTRAINT_SPLITS").unwrap().parse().unwrap();
+        assert!(splits > 0);
+        // the triangles are counter-clockwise, and cover the region exactly
+        let total = area(&result.0, &result.1);
+        assert!((total - expected_area).abs() < 1e-3, "{}", total);
+        // the point in the hole is not used
+        assert_eq!(
+            !remove_outside,
+            result.0.iter().any(|v| v.x == 5.0 && v.y == 5.0)
+        );
+    }
+    Ok(())
+}
@@ -85,7 +85,7 @@ pub(crate) fn build_output_model(
     }
 
     if verbose {
-        println!(
+        debug!(
             "Vertex return model packaging duration: {:?}",
             now.elapsed()
         );
@@ -130,7 +130,7 @@ pub(crate) fn process_command(
     // we already tested a_command.models.len()
     let input_model = &models[0];
 
-    println!(
+    debug!(
         "model.vertices:{:?}, cmd_arg_discretize_length_multiplier:{}",
         input_model.vertices.len(),
         cmd_arg_discretize_length_multiplier
@@ -140,7 +140,7 @@ pub(crate) fn process_command(
     let mut return_config = ConfigType::new();
     let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
     //let _ = return_config.insert("REMOVE_DOUBLES".to_string(), "true".to_string());
-    println!(
+    info!(
         "SDF mesh operation returning {} vertices, {} indices",
         output_model.vertices.len(),
         output_model.indices.len()
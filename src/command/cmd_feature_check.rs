@@ -0,0 +1,219 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{cmd_clip_curves, cmd_hatch::DistanceField, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use std::f64::consts::TAU;
+use vector_traits::glam::{dvec2, DVec2};
+
+#[cfg(test)]
+mod tests;
+
+/// The number of distance samples per tool radius
+const SAMPLES_PER_RADIUS: f64 = 10.0;
+/// The number of segments of the circles marking the features
+const MARKER_SEGMENTS: usize = 24;
+/// Features deeper than this fraction of the tool radius are reported as narrow features
+/// (slots, gaps), the others as corners with a too small radius. The residual of a corner of
+/// 60 degrees or more is never deeper than half the radius.
+const NARROW_FRACTION: f64 = 0.5;
+
+/// A part of the region the tool can not reach
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Feature {
+    /// The most inscribed point of the feature
+    pub(crate) location: DVec2,
+    /// Twice the distance from `location` to the outline, an estimate of the local width
+    pub(crate) width: f64,
+}
+
+/// Find the parts of the `field` a round tool of `radius` can not reach. `side` is 1.0 when the
+/// tool works inside the region, -1.0 when it works outside of it.
+/// The reachable area is the union of the tool discs centered at every point at least `radius`
+/// away from the outline. The unreachable samples are grouped into connected features.
+pub(crate) fn find_features(field: &DistanceField, radius: f64, side: f64) -> Vec<Feature> {
+    let (nx, ny) = field.size();
+    let step = field.step();
+    let depth = |i: usize, j: usize| side * field.value(i, j);
+    let is_center = |i: usize, j: usize| depth(i, j) >= radius;
+
+    let mut covered = vec![false; nx * ny];
+    // the discs of the inner tool centers are covered by the discs of the border centers, and by
+    // the centers themselves
+    let reach = radius + step;
+    let stamp = (reach / step).ceil() as isize;
+    for j in 0..ny {
+        for i in 0..nx {
+            if !is_center(i, j) {
+                continue;
+            }
+            covered[j * nx + i] = true;
+            let is_border = (i == 0 || !is_center(i - 1, j))
+                || (i + 1 == nx || !is_center(i + 1, j))
+                || (j == 0 || !is_center(i, j - 1))
+                || (j + 1 == ny || !is_center(i, j + 1));
+            if !is_border {
+                continue;
+            }
+            for dj in -stamp..=stamp {
+                for di in -stamp..=stamp {
+                    let (ci, cj) = (i as isize + di, j as isize + dj);
+                    if ci < 0 || cj < 0 || ci >= nx as isize || cj >= ny as isize {
+                        continue;
+                    }
+                    if ((di * di + dj * dj) as f64).sqrt() * step <= reach {
+                        covered[cj as usize * nx + ci as usize] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    // samples closer to the outline than one step are within the sampling error
+    let is_missed = |index: usize| !covered[index] && depth(index % nx, index / nx) > step;
+    let mut visited = vec![false; nx * ny];
+    let mut rv = Vec::<Feature>::new();
+    for start in 0..nx * ny {
+        if visited[start] || !is_missed(start) {
+            continue;
+        }
+        visited[start] = true;
+        let mut stack = vec![start];
+        let mut deepest = (start, f64::MIN);
+        while let Some(index) = stack.pop() {
+            let (i, j) = (index % nx, index / nx);
+            let d = depth(i, j);
+            if d > deepest.1 {
+                deepest = (index, d);
+            }
+            let neighbours = [
+                if i > 0 { Some(index - 1) } else { None },
+                if i + 1 < nx { Some(index + 1) } else { None },
+                if j > 0 { Some(index - nx) } else { None },
+                if j + 1 < ny { Some(index + nx) } else { None },
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if !visited[neighbour] && is_missed(neighbour) {
+                    visited[neighbour] = true;
+                    stack.push(neighbour);
+                }
+            }
+        }
+        rv.push(Feature {
+            location: field.grid_point(deepest.0 % nx, deepest.0 / nx),
+            width: 2.0 * deepest.1,
+        });
+    }
+    rv
+}
+
+/// Run the feature_check command
+/// Model 0 contains one or more closed planar outlines (in the line_chunks format), holes are
+/// handled with the even-odd rule. The outlines are checked for features a tool of
+/// `TOOL_DIAMETER` can not cut: slots and gaps narrower than the tool, and corners with a radius
+/// smaller than the tool radius. The tool works inside the region (`CHECK_SIDE=INSIDE`, default)
+/// or outside of it (`CHECK_SIDE=OUTSIDE`).
+/// Every feature is marked with a circle of the tool diameter (line_chunks format). The counts are
+/// returned as `FEATURE_COUNT`, `NARROW_FEATURES` and `CORNER_FEATURES`, the locations and the
+/// estimated widths as comma separated lists in `FEATURE_LOCATIONS` (x,y pairs) and
+/// `FEATURE_WIDTHS`. The sampling distance used is returned as `FEATURE_CHECK_STEP`, features
+/// smaller than that can be missed.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let diameter = config.get_mandatory_parsed_option::<f64>("TOOL_DIAMETER", None)?;
+    if !diameter.is_finite() || diameter <= 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "TOOL_DIAMETER must be a positive number :({})",
+            diameter
+        )));
+    }
+    let side = match config
+        .get_mandatory_parsed_option::<String>("CHECK_SIDE", Some("INSIDE".to_string()))?
+        .as_str()
+    {
+        "INSIDE" => 1.0,
+        "OUTSIDE" => -1.0,
+        side => {
+            return Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"CHECK_SIDE\" parameter",
+                side
+            )))
+        }
+    };
+    let radius = diameter * 0.5;
+    let model = &models[0];
+    let z = model.vertices.first().map_or(0.0, |v| v.z);
+    let region = cmd_clip_curves::parse_region(model)?;
+    // outside of the region the tool must be able to pass around the outline
+    let field = DistanceField::new(&region, radius / SAMPLES_PER_RADIUS, 2.0 * diameter);
+    let features = find_features(&field, radius, side);
+
+    let mut output_vertices = Vec::<FFIVector3>::new();
+    let mut output_indices = Vec::<usize>::new();
+    for feature in features.iter() {
+        let first_index = output_vertices.len();
+        for i in 0..MARKER_SEGMENTS {
+            let (sin, cos) = (TAU * i as f64 / MARKER_SEGMENTS as f64).sin_cos();
+            let p = feature.location + dvec2(cos, sin) * radius;
+            output_vertices.push(FFIVector3::new(p.x as f32, p.y as f32, z));
+            output_indices.push(first_index + i);
+            output_indices.push(first_index + (i + 1) % MARKER_SEGMENTS);
+        }
+    }
+    let narrow = features
+        .iter()
+        .filter(|f| f.width * 0.5 >= NARROW_FRACTION * radius)
+        .count();
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = return_config.insert("FEATURE_COUNT".to_string(), features.len().to_string());
+    let _ = return_config.insert("FEATURE_CHECK_STEP".to_string(), field.step().to_string());
+    let _ = return_config.insert("NARROW_FEATURES".to_string(), narrow.to_string());
+    let _ = return_config.insert(
+        "CORNER_FEATURES".to_string(),
+        (features.len() - narrow).to_string(),
+    );
+    let _ = return_config.insert(
+        "FEATURE_LOCATIONS".to_string(),
+        features
+            .iter()
+            .map(|f| format!("{},{}", f.location.x, f.location.y))
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    let _ = return_config.insert(
+        "FEATURE_WIDTHS".to_string(),
+        features
+            .iter()
+            .map(|f| f.width.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    info!(
+        "feature_check operation found {} features ({} narrow) for a tool of diameter {}",
+        features.len(),
+        narrow,
+        diameter
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,67 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType},
+    HallrError,
+};
+
+#[test]
+fn test_feature_check_1() -> Result<(), HallrError> {
+    // a slot narrower than the tool
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "feature_check".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("TOOL_DIAMETER".to_string(), "3.0".to_string());
+    let _ = config.insert("CHECK_SIDE".to_string(), "INSIDE".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [10.0, 2.0], 0.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!("1", result.3.get("FEATURE_COUNT").unwrap());
+    assert_eq!("1", result.3.get("NARROW_FEATURES").unwrap());
+    let width: f64 = result.3.get("FEATURE_WIDTHS").unwrap().parse().unwrap();
+    assert!((width - 2.0).abs() < 0.2, "{}", width);
+    // one marker circle
+    assert_eq!(super::MARKER_SEGMENTS, result.0.len());
+    assert_eq!(2 * super::MARKER_SEGMENTS, result.1.len());
+    Ok(())
+}
+
+#[test]
+fn test_feature_check_2() -> Result<(), HallrError> {
+    // the tool fits, but can not cut the sharp corners of the pocket
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "feature_check".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("TOOL_DIAMETER".to_string(), "2.0".to_string());
+    let _ = config.insert("CHECK_SIDE".to_string(), "INSIDE".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [10.0, 10.0], 0.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert_eq!("4", result.3.get("FEATURE_COUNT").unwrap());
+    assert_eq!("4", result.3.get("CORNER_FEATURES").unwrap());
+    let locations: Vec<f64> = result
+        .3
+        .get("FEATURE_LOCATIONS")
+        .unwrap()
+        .split(',')
+        .map(|v| v.parse().unwrap())
+        .collect();
+    // every feature is close to a corner
+    assert!(locations.chunks_exact(2).all(|p| {
+        let dx = p[0].min(10.0 - p[0]);
+        let dy = p[1].min(10.0 - p[1]);
+        dx < 0.5 && dy < 0.5
+    }));
+
+    // cutting around the outside, the tool reaches everything
+    let _ = config.insert("CHECK_SIDE".to_string(), "OUTSIDE".to_string());
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!("0", result.3.get("FEATURE_COUNT").unwrap());
+    assert!(result.0.is_empty());
+    Ok(())
+}
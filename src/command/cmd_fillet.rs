@@ -0,0 +1,233 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{cmd_clip_curves, cmd_hatch, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use std::{f64::consts::PI, str::FromStr};
+use vector_traits::glam::{dvec2, DVec2};
+
+#[cfg(test)]
+mod tests;
+
+/// Corners closer to a straight line (or to a spike) than this angle are left as they are
+const ANGLE_EPSILON: f64 = 1e-6;
+/// Consecutive output vertices closer than this are merged
+const MERGE_DISTANCE: f32 = 1e-5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FilletCorners {
+    /// Corners where the material makes up the larger angle (concave corners)
+    Internal,
+    /// Corners where the material makes up the smaller angle (convex corners)
+    External,
+    All,
+}
+
+impl FromStr for FilletCorners {
+    type Err = HallrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "INTERNAL" => Ok(Self::Internal),
+            "EXTERNAL" => Ok(Self::External),
+            "ALL" => Ok(Self::All),
+            _ => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"FILLET_CORNERS\" parameter",
+                s
+            ))),
+        }
+    }
+}
+
+/// Returns the arc replacing the corner `p`, going from the edge towards `a` to the edge towards
+/// `b`. The arc is approximated by segments spanning at most `max_angle` (radians).
+/// The radius is reduced when the tangent points would not fit on half of the adjacent edges, so
+/// that neighbouring fillets never overlap. The returned flag is set when that happened.
+/// Returns None for straight and degenerate corners.
+pub(crate) fn fillet_corner(
+    a: DVec2,
+    p: DVec2,
+    b: DVec2,
+    radius: f64,
+    max_angle: f64,
+) -> Option<(Vec<DVec2>, bool)> {
+    let (length_a, length_b) = (a.distance(p), b.distance(p));
+    if length_a <= 0.0 || length_b <= 0.0 {
+        return None;
+    }
+    let u = (a - p) / length_a;
+    let v = (b - p) / length_b;
+    // the angle between the two edges, PI for a straight line
+    let angle = u.dot(v).clamp(-1.0, 1.0).acos();
+    if angle < ANGLE_EPSILON || angle > PI - ANGLE_EPSILON {
+        return None;
+    }
+    let half_tan = (angle * 0.5).tan();
+    let limit = 0.5 * length_a.min(length_b);
+    let mut tangent_distance = radius / half_tan;
+    let clamped = tangent_distance > limit;
+    if clamped {
+        tangent_distance = limit;
+    }
+    let radius = tangent_distance * half_tan;
+    let center = p + (u + v).normalize() * (radius / (angle * 0.5).sin());
+    let start = p + u * tangent_distance - center;
+    let end = p + v * tangent_distance - center;
+    let sweep = (PI - angle) * start.perp_dot(end).signum();
+    let segments = ((sweep.abs() / max_angle).ceil() as usize).max(1);
+    let arc = (0..=segments)
+        .map(|i| {
+            let (sin, cos) = (sweep * i as f64 / segments as f64).sin_cos();
+            center + dvec2(start.x * cos - start.y * sin, start.x * sin + start.y * cos)
+        })
+        .collect();
+    Some((arc, clamped))
+}
+
+fn same_xy(a: &FFIVector3, b: &FFIVector3) -> bool {
+    (a.x - b.x).abs() < MERGE_DISTANCE && (a.y - b.y).abs() < MERGE_DISTANCE
+}
+
+/// Returns true if the material of the region fills the smaller angle of the corner `p`
+fn is_external_corner(a: DVec2, p: DVec2, b: DVec2, region: &[(DVec2, DVec2)]) -> bool {
+    let bisector = ((a - p).normalize() + (b - p).normalize()).normalize();
+    let probe = 1e-4 * a.distance(p).min(b.distance(p));
+    cmd_clip_curves::is_inside_region(p + bisector * probe, region)
+}
+
+/// Run the fillet command
+/// Model 0 contains one or more closed planar outlines (in the line_chunks format), holes are
+/// handled with the even-odd rule. The corners selected by `FILLET_CORNERS` (INTERNAL, EXTERNAL or
+/// ALL) are replaced with arcs of `FILLET_RADIUS`, approximated by segments spanning at most
+/// `FILLET_MAX_ANGLE` degrees (default 10). The radius is reduced where it would not fit on the
+/// adjacent edges, the number of such corners is returned as `FILLET_CLAMPED`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let radius = config.get_mandatory_parsed_option::<f64>("FILLET_RADIUS", None)?;
+    if !radius.is_finite() || radius <= 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "FILLET_RADIUS must be a positive number :({})",
+            radius
+        )));
+    }
+    let corners = config
+        .get_mandatory_parsed_option::<FilletCorners>("FILLET_CORNERS", Some(FilletCorners::All))?;
+    let max_angle = config.get_mandatory_parsed_option::<f64>("FILLET_MAX_ANGLE", Some(10.0))?;
+    if !max_angle.is_finite() || max_angle <= 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "FILLET_MAX_ANGLE must be a positive number :({})",
+            max_angle
+        )));
+    }
+    let max_angle = max_angle.to_radians();
+
+    let model = &models[0];
+    let region = cmd_clip_curves::parse_region(model)?;
+    let segments: Vec<(u64, u64)> = model
+        .indices
+        .chunks_exact(2)
+        .filter(|edge| edge[0] != edge[1])
+        .map(|edge| (edge[0] as u64, edge[1] as u64))
+        .collect();
+    let loops = cmd_hatch::chain_segments(&segments);
+    if loops.iter().any(|chain| chain.first() != chain.last()) {
+        return Err(HallrError::InvalidInputData(
+            "The outlines must be closed loops".to_string(),
+        ));
+    }
+
+    let mut output_vertices = Vec::<FFIVector3>::new();
+    let mut output_indices = Vec::<usize>::new();
+    let mut filleted = 0_usize;
+    let mut clamped = 0_usize;
+    for chain in loops {
+        // the closing id is not repeated
+        let corner_vertices: Vec<FFIVector3> = chain[..chain.len() - 1]
+            .iter()
+            .map(|i| model.vertices[*i as usize])
+            .collect();
+        let points: Vec<DVec2> = corner_vertices
+            .iter()
+            .map(|v| dvec2(v.x as f64, v.y as f64))
+            .collect();
+        let n = points.len();
+        let first_index = output_vertices.len();
+        for i in 0..n {
+            let (a, p, b) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+            let z = corner_vertices[i].z;
+            let selected = match corners {
+                FilletCorners::All => true,
+                FilletCorners::External => is_external_corner(a, p, b, &region),
+                FilletCorners::Internal => !is_external_corner(a, p, b, &region),
+            };
+            let arc = if selected {
+                fillet_corner(a, p, b, radius, max_angle)
+            } else {
+                None
+            };
+            if let Some((arc, was_clamped)) = arc {
+                filleted += 1;
+                if was_clamped {
+                    clamped += 1;
+                }
+                for v in arc {
+                    let v = FFIVector3::new(v.x as f32, v.y as f32, z);
+                    // fillets clamped to half of the same edge meet at its mid point
+                    if output_vertices.len() == first_index
+                        || !same_xy(&v, output_vertices.last().unwrap())
+                    {
+                        output_vertices.push(v);
+                    }
+                }
+            } else {
+                output_vertices.push(corner_vertices[i]);
+            }
+        }
+        if output_vertices.len() - first_index > 1
+            && same_xy(
+                &output_vertices[first_index],
+                output_vertices.last().unwrap(),
+            )
+        {
+            let _ = output_vertices.pop();
+        }
+        let last_index = output_vertices.len() - 1;
+        for i in first_index..last_index {
+            output_indices.push(i);
+            output_indices.push(i + 1);
+        }
+        output_indices.push(last_index);
+        output_indices.push(first_index);
+    }
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = return_config.insert("FILLET_CLAMPED".to_string(), clamped.to_string());
+    info!(
+        "fillet operation returning {} vertices, {} indices, {} filleted corners ({} clamped)",
+        output_vertices.len(),
+        output_indices.len(),
+        filleted,
+        clamped
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
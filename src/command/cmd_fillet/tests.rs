@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType, OwnedModel},
+    HallrError,
+};
+
+/// A closed loop in the line_chunks format
+fn outline(points: &[(f32, f32)]) -> OwnedModel {
+    OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: points.iter().map(|(x, y)| (*x, *y, 1.0).into()).collect(),
+        indices: (0..points.len())
+            .flat_map(|i| [i, (i + 1) % points.len()])
+            .collect(),
+    }
+}
+
+/// An L shaped outline, with one internal corner at (1,1)
+fn l_shape() -> OwnedModel {
+    outline(&[
+        (0.0, 0.0),
+        (2.0, 0.0),
+        (2.0, 1.0),
+        (1.0, 1.0),
+        (1.0, 2.0),
+        (0.0, 2.0),
+    ])
+}
+
+#[test]
+fn test_fillet_1() -> Result<(), HallrError> {
+    // every corner of the square becomes a 90 degree arc of 10 points
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "fillet".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("FILLET_RADIUS".to_string(), "0.25".to_string());
+    let _ = config.insert("FILLET_CORNERS".to_string(), "ALL".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 1.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert_eq!(40, result.0.len()); // vertices
+    assert_eq!(80, result.1.len()); // indices
+    assert_eq!("0", result.3.get("FILLET_CLAMPED").unwrap());
+    assert!(result.0.iter().all(|v| v.z == 1.0));
+    // the first arc is centered at (0.25,0.25)
+    assert!(result.0[0..10].iter().all(|v| {
+        let (dx, dy) = (v.x - 0.25, v.y - 0.25);
+        ((dx * dx + dy * dy).sqrt() - 0.25).abs() < 1e-5
+    }));
+
+    // the square has no internal corners
+    let _ = config.insert("FILLET_CORNERS".to_string(), "INTERNAL".to_string());
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(4, result.0.len());
+    assert_eq!(8, result.1.len());
+    Ok(())
+}
+
+#[test]
+fn test_fillet_2() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "fillet".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("FILLET_RADIUS".to_string(), "0.25".to_string());
+    let _ = config.insert("FILLET_CORNERS".to_string(), "INTERNAL".to_string());
+
+    let owned_model_0 = l_shape();
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert_eq!(15, result.0.len());
+    assert_eq!(30, result.1.len());
+    // the internal arc is centered at (1.25,1.25)
+    assert!(result.0[3..13].iter().all(|v| {
+        let (dx, dy) = (v.x - 1.25, v.y - 1.25);
+        ((dx * dx + dy * dy).sqrt() - 0.25).abs() < 1e-5
+    }));
+
+    let _ = config.insert("FILLET_CORNERS".to_string(), "EXTERNAL".to_string());
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(51, result.0.len());
+    Ok(())
+}
+
+#[test]
+fn test_fillet_3() -> Result<(), HallrError> {
+    // the radius does not fit, it is reduced to half of the edge length
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "fillet".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("FILLET_RADIUS".to_string(), "1.0".to_string());
+    let _ = config.insert("FILLET_CORNERS".to_string(), "ALL".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 1.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert_eq!("4", result.3.get("FILLET_CLAMPED").unwrap());
+    assert!(result.0.iter().all(|v| {
+        let (dx, dy) = (v.x - 0.5, v.y - 0.5);
+        ((dx * dx + dy * dy).sqrt() - 0.5).abs() < 1e-5
+    }));
+
+    // open outlines are rejected
+    let mut owned_model_0 = owned_model_0;
+    owned_model_0.indices.truncate(6);
+    let models = vec![owned_model_0.as_model()];
+    let _ = config.insert("FILLET_RADIUS".to_string(), "0.1".to_string());
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
@@ -0,0 +1,446 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{
+    attributes::Attributes,
+    cmd_symmetry::{canonical, eigenvectors},
+    ConfigType, Model, Options,
+};
+use crate::HallrError;
+use ahash::AHashMap;
+use std::{collections::VecDeque, fmt::Write};
+use vector_traits::glam::{dvec3, DMat3, DVec3};
+
+#[cfg(test)]
+mod tests;
+
+/// The default tolerance, as a fraction of the diagonal of the bounding box
+const DEFAULT_TOLERANCE_FRACTION: f64 = 1e-2;
+/// The key of the per face segment attribute
+pub(crate) const FIT_SEGMENT_KEY: &str = "FIT_SEGMENT";
+
+/// A primitive shape fitted to a segment of the mesh
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Primitive {
+    /// The points `p` of the plane fulfill `normal.dot(p) == distance`, the normal points in the
+    /// same direction as the faces
+    Plane {
+        normal: DVec3,
+        distance: f64,
+    },
+    /// The axis starts at `origin` and runs `height` along `axis`, covering the segment
+    Cylinder {
+        origin: DVec3,
+        axis: DVec3,
+        radius: f64,
+        height: f64,
+    },
+    Sphere {
+        center: DVec3,
+        radius: f64,
+    },
+    /// No primitive fits the segment within the tolerance
+    Unfitted,
+}
+
+/// A set of connected faces, and the primitive fitted to them
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Segment {
+    pub(crate) faces: Vec<usize>,
+    pub(crate) primitive: Primitive,
+    /// The RMS distance between the sample points and the primitive
+    pub(crate) rms: Option<f64>,
+}
+
+/// Solve the linear least squares problem of the `rows` (coefficients, right hand side) through
+/// the normal equations, by Gaussian elimination with partial pivoting
+fn least_squares<const N: usize>(rows: impl Iterator<Item = ([f64; N], f64)>) -> Option<[f64; N]> {
+    let mut a = [[0.0; N]; N];
+    let mut b = [0.0; N];
+    for (row, rhs) in rows {
+        for ((a_row, b_value), ri) in a.iter_mut().zip(b.iter_mut()).zip(row.iter()) {
+            for (v, rj) in a_row.iter_mut().zip(row.iter()) {
+                *v += ri * rj;
+            }
+            *b_value += ri * rhs;
+        }
+    }
+    for col in 0..N {
+        let pivot = (col..N).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+        if a[pivot][col] == 0.0 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let (pivot_row, pivot_b) = (a[col], b[col]);
+        for (row, b_value) in a.iter_mut().zip(b.iter_mut()).skip(col + 1) {
+            let f = row[col] / pivot_row[col];
+            for (v, p) in row.iter_mut().zip(pivot_row.iter()).skip(col) {
+                *v -= f * p;
+            }
+            *b_value -= f * pivot_b;
+        }
+    }
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let sum: f64 = a[row]
+            .iter()
+            .zip(x.iter())
+            .skip(row + 1)
+            .map(|(a, x)| a * x)
+            .sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    x.iter().all(|v| v.is_finite()).then_some(x)
+}
+
+/// Returns the eigenvector of the smallest eigenvalue of the symmetric matrix `m`
+fn smallest_eigenvector(m: DMat3) -> DVec3 {
+    eigenvectors(m)
+        .into_iter()
+        .min_by(|a, b| a.dot(m * *a).total_cmp(&b.dot(m * *b)))
+        .unwrap()
+}
+
+/// Returns the RMS of the residuals
+fn rms(residuals: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = residuals.fold((0.0, 0_usize), |(s, c), r| (s + r * r, c + 1));
+    (sum / count.max(1) as f64).sqrt()
+}
+
+/// Fit a plane to the points, by principal component analysis. The normal is flipped to the side
+/// of `face_normal`.
+pub(crate) fn fit_plane(points: &[DVec3], face_normal: DVec3) -> Option<(Primitive, f64)> {
+    let mean = points.iter().copied().sum::<DVec3>() / points.len() as f64;
+    let covariance = points.iter().fold(DMat3::ZERO, |sum, p| {
+        let d = *p - mean;
+        sum + DMat3::from_cols(d * d.x, d * d.y, d * d.z)
+    });
+    let mut normal = smallest_eigenvector(covariance).try_normalize()?;
+    if normal.dot(face_normal) < 0.0 {
+        normal = -normal;
+    }
+    let distance = normal.dot(mean);
+    let rms = rms(points.iter().map(|p| normal.dot(*p) - distance));
+    Some((Primitive::Plane { normal, distance }, rms))
+}
+
+/// Fit a sphere to the points, with the algebraic least squares method
+pub(crate) fn fit_sphere(points: &[DVec3]) -> Option<(Primitive, f64)> {
+    let mean = points.iter().copied().sum::<DVec3>() / points.len() as f64;
+    // |p|^2 = 2c.p + d, where d = r^2 - |c|^2
+    let [x, y, z, d] = least_squares(points.iter().map(|p| {
+        let p = *p - mean;
+        ([2.0 * p.x, 2.0 * p.y, 2.0 * p.z, 1.0], p.length_squared())
+    }))?;
+    let c = dvec3(x, y, z);
+    let radius_squared = d + c.length_squared();
+    if radius_squared <= 0.0 {
+        return None;
+    }
+    let (center, radius) = (mean + c, radius_squared.sqrt());
+    let rms = rms(points.iter().map(|p| p.distance(center) - radius));
+    Some((Primitive::Sphere { center, radius }, rms))
+}
+
+/// Fit a cylinder to the points. The axis is the direction most perpendicular to the (area
+/// weighted) face normals, the radius and the axis position come from a circle fitted to the points
+/// projected along the axis.
+pub(crate) fn fit_cylinder(points: &[DVec3], face_normals: &[DVec3]) -> Option<(Primitive, f64)> {
+    let normal_covariance = face_normals.iter().fold(DMat3::ZERO, |sum, n| {
+        // the length of the normals is twice the face area, n*n^T is weighted by the area
+        let n = n.normalize_or_zero() * n.length().sqrt();
+        sum + DMat3::from_cols(n * n.x, n * n.y, n * n.z)
+    });
+    let axis = canonical(
+        smallest_eigenvector(normal_covariance).try_normalize()?,
+        0.0,
+    )
+    .0;
+    let u = axis.any_orthonormal_vector();
+    let w = axis.cross(u);
+    let mean = points.iter().copied().sum::<DVec3>() / points.len() as f64;
+    // |q|^2 = 2c.q + d in the plane perpendicular to the axis
+    let [cu, cw, d] = least_squares(points.iter().map(|p| {
+        let (pu, pw) = ((*p - mean).dot(u), (*p - mean).dot(w));
+        ([2.0 * pu, 2.0 * pw, 1.0], pu * pu + pw * pw)
+    }))?;
+    let radius_squared = d + cu * cu + cw * cw;
+    if radius_squared <= 0.0 {
+        return None;
+    }
+    let radius = radius_squared.sqrt();
+    let center = mean + u * cu + w * cw;
+    let (t_min, t_max) = points.iter().fold((f64::MAX, f64::MIN), |(min, max), p| {
+        let t = (*p - center).dot(axis);
+        (min.min(t), max.max(t))
+    });
+    let rms = rms(points.iter().map(|p| {
+        let d = *p - center;
+        (d - axis * d.dot(axis)).length() - radius
+    }));
+    Some((
+        Primitive::Cylinder {
+            origin: center + axis * t_min,
+            axis,
+            radius,
+            height: t_max - t_min,
+        },
+        rms,
+    ))
+}
+
+/// Split the triangles into segments of edge connected faces. Two neighbouring faces belong to
+/// the same segment when the angle between their normals is smaller than the angle of
+/// `min_cos` (the cosine of the angle).
+/// Returns the faces of every segment, in the order of their first face.
+pub(crate) fn segment_faces(
+    indices: &[usize],
+    face_normals: &[DVec3],
+    min_cos: f64,
+) -> Vec<Vec<usize>> {
+    let mut edge_faces = AHashMap::<(usize, usize), Vec<usize>>::default();
+    for (face, t) in indices.chunks_exact(3).enumerate() {
+        for (a, b) in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+            edge_faces
+                .entry((a.min(b), a.max(b)))
+                .or_default()
+                .push(face);
+        }
+    }
+    let normals: Vec<DVec3> = face_normals.iter().map(|n| n.normalize_or_zero()).collect();
+    let mut visited = vec![false; normals.len()];
+    let mut rv = Vec::<Vec<usize>>::new();
+    for seed in 0..normals.len() {
+        if visited[seed] {
+            continue;
+        }
+        visited[seed] = true;
+        let mut segment = Vec::<usize>::new();
+        let mut queue = VecDeque::from([seed]);
+        while let Some(face) = queue.pop_front() {
+            segment.push(face);
+            let t = &indices[face * 3..face * 3 + 3];
+            for (a, b) in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+                for neighbour in edge_faces[&(a.min(b), a.max(b))].iter() {
+                    if !visited[*neighbour] && normals[face].dot(normals[*neighbour]) >= min_cos {
+                        visited[*neighbour] = true;
+                        queue.push_back(*neighbour);
+                    }
+                }
+            }
+        }
+        segment.sort_unstable();
+        rv.push(segment);
+    }
+    rv
+}
+
+/// Fit the primitives to the faces of a segment. The sample points are the vertices and the
+/// centroids of the faces. A plane is preferred if it is within the `tolerance`, otherwise the best
+/// fitting cylinder or sphere within the tolerance is selected.
+pub(crate) fn fit_segment(
+    vertices: &[DVec3],
+    indices: &[usize],
+    face_normals: &[DVec3],
+    faces: &[usize],
+    tolerance: f64,
+) -> Segment {
+    let mut vertex_ids: Vec<usize> = faces
+        .iter()
+        .flat_map(|f| indices[f * 3..f * 3 + 3].iter().copied())
+        .collect();
+    vertex_ids.sort_unstable();
+    vertex_ids.dedup();
+    let points: Vec<DVec3> = vertex_ids
+        .iter()
+        .map(|i| vertices[*i])
+        .chain(faces.iter().map(|f| {
+            let t = &indices[f * 3..f * 3 + 3];
+            (vertices[t[0]] + vertices[t[1]] + vertices[t[2]]) / 3.0
+        }))
+        .collect();
+    let normals: Vec<DVec3> = faces.iter().map(|f| face_normals[*f]).collect();
+    let face_normal = normals.iter().copied().sum::<DVec3>();
+
+    let (primitive, rms) = match fit_plane(&points, face_normal) {
+        Some((plane, rms)) if rms <= tolerance => (plane, Some(rms)),
+        _ => fit_cylinder(&points, &normals)
+            .into_iter()
+            .chain(fit_sphere(&points))
+            .filter(|(_, rms)| *rms <= tolerance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or((Primitive::Unfitted, None), |(p, rms)| (p, Some(rms))),
+    };
+    Segment {
+        faces: faces.to_vec(),
+        primitive,
+        rms,
+    }
+}
+
+/// Formats the fitted segments as a JSON array
+pub(crate) fn segments_to_json(segments: &[Segment]) -> String {
+    let v = |v: &DVec3| format!("[{},{},{}]", v.x, v.y, v.z);
+    let mut rv = String::from("[");
+    for (i, segment) in segments.iter().enumerate() {
+        if i > 0 {
+            rv.push(',');
+        }
+        let _ = write!(
+            rv,
+            "{{\"segment\":{},\"faces\":{},\"rms\":{},",
+            i,
+            segment.faces.len(),
+            segment
+                .rms
+                .map_or_else(|| "null".to_string(), |r| r.to_string())
+        );
+        let _ = match &segment.primitive {
+            Primitive::Plane { normal, distance } => write!(
+                rv,
+                "\"type\":\"plane\",\"normal\":{},\"distance\":{}}}",
+                v(normal),
+                distance
+            ),
+            Primitive::Cylinder {
+                origin,
+                axis,
+                radius,
+                height,
+            } => write!(
+                rv,
+                "\"type\":\"cylinder\",\"origin\":{},\"axis\":{},\"radius\":{},\"height\":{}}}",
+                v(origin),
+                v(axis),
+                radius,
+                height
+            ),
+            Primitive::Sphere { center, radius } => write!(
+                rv,
+                "\"type\":\"sphere\",\"center\":{},\"radius\":{}}}",
+                v(center),
+                radius
+            ),
+            Primitive::Unfitted => write!(rv, "\"type\":\"none\"}}"),
+        };
+    }
+    rv.push(']');
+    rv
+}
+
+/// Run the fit_primitives command
+/// Model 0 is a triangulated mesh with shared vertices. The faces are split into segments of
+/// connected faces, where neighbouring face normals differ less than `FIT_ANGLE` degrees (default
+/// 30). A plane, a cylinder or a sphere is fitted to every segment with at least `FIT_MIN_FACES`
+/// faces (default 4), the fit must be within `FIT_TOLERANCE` RMS (default 1% of the diagonal of
+/// the bounding box). The segments are returned as a JSON array in `FIT_PRIMITIVES`, the number of
+/// segments as `FIT_SEGMENT_COUNT`, and the segment of every face in the `FIT_SEGMENT` attribute.
+/// The mesh is returned unchanged.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+    output_attributes: &mut Attributes,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("triangulated") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'triangulated' format".to_string(),
+        ));
+    }
+    let model = &models[0];
+    if model.indices.len() < 3 || model.indices.len() % 3 != 0 {
+        return Err(HallrError::InvalidInputData(
+            "The model has no triangles".to_string(),
+        ));
+    }
+    if let Some(index) = model.indices.iter().find(|i| **i >= model.vertices.len()) {
+        return Err(HallrError::InvalidInputData(format!(
+            "The index {} is out of bounds",
+            index
+        )));
+    }
+    let angle = config.get_mandatory_parsed_option::<f64>("FIT_ANGLE", Some(30.0))?;
+    if !(0.0..180.0).contains(&angle) {
+        return Err(HallrError::InvalidParameter(format!(
+            "FIT_ANGLE must be in the range 0..180 :({})",
+            angle
+        )));
+    }
+    let min_faces = config.get_mandatory_parsed_option::<usize>("FIT_MIN_FACES", Some(4))?;
+    let vertices: Vec<DVec3> = model
+        .vertices
+        .iter()
+        .map(|v| dvec3(v.x as f64, v.y as f64, v.z as f64))
+        .collect();
+    let (min, max) = vertices.iter().fold(
+        (DVec3::splat(f64::MAX), DVec3::splat(f64::MIN)),
+        |(min, max), v| (min.min(*v), max.max(*v)),
+    );
+    let tolerance = match config.get_parsed_option::<f64>("FIT_TOLERANCE")? {
+        Some(tolerance) => tolerance,
+        None => (max - min).length() * DEFAULT_TOLERANCE_FRACTION,
+    };
+    if !tolerance.is_finite() || tolerance <= 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "FIT_TOLERANCE must be a positive number :({})",
+            tolerance
+        )));
+    }
+
+    let face_normals: Vec<DVec3> = model
+        .indices
+        .chunks_exact(3)
+        .map(|t| (vertices[t[1]] - vertices[t[0]]).cross(vertices[t[2]] - vertices[t[0]]))
+        .collect();
+    let segments: Vec<Segment> =
+        segment_faces(model.indices, &face_normals, angle.to_radians().cos())
+            .into_iter()
+            .map(|faces| {
+                if faces.len() < min_faces {
+                    Segment {
+                        faces,
+                        primitive: Primitive::Unfitted,
+                        rms: None,
+                    }
+                } else {
+                    fit_segment(&vertices, model.indices, &face_normals, &faces, tolerance)
+                }
+            })
+            .collect();
+
+    let mut face_segments = vec![0.0_f32; face_normals.len()];
+    for (i, segment) in segments.iter().enumerate() {
+        for face in segment.faces.iter() {
+            face_segments[*face] = i as f32;
+        }
+    }
+    let _ = output_attributes.insert(FIT_SEGMENT_KEY.to_string(), face_segments);
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = return_config.insert("FIT_SEGMENT_COUNT".to_string(), segments.len().to_string());
+    let _ = return_config.insert("FIT_PRIMITIVES".to_string(), segments_to_json(&segments));
+    let _ = return_config.insert("FIT_TOLERANCE".to_string(), tolerance.to_string());
+    info!(
+        "fit_primitives operation found {} segments, {} fitted, tolerance:{}",
+        segments.len(),
+        segments
+            .iter()
+            .filter(|s| s.primitive != Primitive::Unfitted)
+            .count(),
+        tolerance
+    );
+    Ok((
+        model.vertices.to_vec(),
+        model.indices.to_vec(),
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{fit_segment, segment_faces, Primitive};
+use crate::{
+    command::{attributes::Attributes, ConfigType, OwnedModel},
+    HallrError,
+};
+use vector_traits::glam::{dvec3, DVec3};
+
+/// A closed cylinder of radius 1 along the z axis, from z=-1 to z=1. The side faces come first,
+/// then the bottom cap and the top cap.
+fn cylinder_model(sides: usize) -> OwnedModel {
+    let mut vertices = Vec::new();
+    for z in [-1.0, 1.0] {
+        for i in 0..sides {
+            let angle = i as f32 * std::f32::consts::TAU / sides as f32;
+            vertices.push((angle.cos(), angle.sin(), z).into());
+        }
+    }
+    vertices.push((0.0, 0.0, -1.0).into());
+    vertices.push((0.0, 0.0, 1.0).into());
+    let (bottom_center, top_center) = (2 * sides, 2 * sides + 1);
+    let mut indices = Vec::new();
+    for i in 0..sides {
+        let (a, b) = (i, (i + 1) % sides);
+        let (c, d) = (b + sides, a + sides);
+        indices.extend([a, b, c, a, c, d]);
+    }
+    for i in 0..sides {
+        indices.extend([bottom_center, (i + 1) % sides, i]);
+    }
+    for i in 0..sides {
+        indices.extend([top_center, i + sides, (i + 1) % sides + sides]);
+    }
+    OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices,
+        indices,
+    }
+}
+
+/// A UV sphere with the radius 2, centered at (1,0,0)
+fn sphere_vertices_and_indices(segments: usize, rings: usize) -> (Vec<DVec3>, Vec<usize>) {
+    let center = dvec3(1.0, 0.0, 0.0);
+    let mut vertices = vec![center + dvec3(0.0, 0.0, 2.0)];
+    for j in 1..rings {
+        let phi = j as f64 * std::f64::consts::PI / rings as f64;
+        for i in 0..segments {
+            let theta = i as f64 * std::f64::consts::TAU / segments as f64;
+            vertices.push(
+                center + dvec3(phi.sin() * theta.cos(), phi.sin() * theta.sin(), phi.cos()) * 2.0,
+            );
+        }
+    }
+    vertices.push(center - dvec3(0.0, 0.0, 2.0));
+    let ring = |j: usize, i: usize| 1 + (j - 1) * segments + i % segments;
+    let south = vertices.len() - 1;
+    let mut indices = Vec::new();
+    for i in 0..segments {
+        indices.extend([0, ring(1, i), ring(1, i + 1)]);
+    }
+    for j in 1..rings - 1 {
+        for i in 0..segments {
+            let (p, q, r, s) = (
+                ring(j, i),
+                ring(j + 1, i),
+                ring(j + 1, i + 1),
+                ring(j, i + 1),
+            );
+            indices.extend([p, q, r, p, r, s]);
+        }
+    }
+    for i in 0..segments {
+        indices.extend([south, ring(rings - 1, i + 1), ring(rings - 1, i)]);
+    }
+    (vertices, indices)
+}
+
+fn face_normals(vertices: &[DVec3], indices: &[usize]) -> Vec<DVec3> {
+    indices
+        .chunks_exact(3)
+        .map(|t| (vertices[t[1]] - vertices[t[0]]).cross(vertices[t[2]] - vertices[t[0]]))
+        .collect()
+}
+
+#[test]
+fn test_fit_primitives_1() -> Result<(), HallrError> {
+    let owned_model_0 = cylinder_model(24);
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "fit_primitives".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let models = vec![owned_model_0.as_model()];
+    let mut attributes = Attributes::new();
+    let result = super::process_command(config, models, &mut attributes)?;
+    assert_eq!("3", result.3.get("FIT_SEGMENT_COUNT").unwrap());
+    let primitives = result.3.get("FIT_PRIMITIVES").unwrap();
+    assert!(primitives.contains("\"type\":\"cylinder\""));
+    assert_eq!(2, primitives.matches("\"type\":\"plane\"").count());
+    // the side, the bottom cap and the top cap
+    let segments = attributes.get(super::FIT_SEGMENT_KEY).unwrap();
+    assert_eq!(owned_model_0.indices.len() / 3, segments.len());
+    assert!(segments[..48].iter().all(|s| *s == 0.0));
+    assert!(segments[48..72].iter().all(|s| *s == 1.0));
+    assert!(segments[72..].iter().all(|s| *s == 2.0));
+    // the mesh is returned unchanged
+    assert!(owned_model_0.vertices == result.0);
+    assert_eq!(owned_model_0.indices, result.1);
+
+    let vertices: Vec<DVec3> = owned_model_0
+        .vertices
+        .iter()
+        .map(|v| dvec3(v.x as f64, v.y as f64, v.z as f64))
+        .collect();
+    let normals = face_normals(&vertices, &owned_model_0.indices);
+    let faces = segment_faces(
+        &owned_model_0.indices,
+        &normals,
+        30.0_f64.to_radians().cos(),
+    );
+    assert_eq!(3, faces.len());
+    let side = fit_segment(&vertices, &owned_model_0.indices, &normals, &faces[0], 0.05);
+    let Primitive::Cylinder {
+        origin,
+        axis,
+        radius,
+        height,
+    } = side.primitive
+    else {
+        panic!("expected a cylinder, got {:?}", side.primitive);
+    };
+    assert!(axis.distance(DVec3::Z) < 1e-6);
+    assert!((radius - 1.0).abs() < 0.01);
+    assert!((height - 2.0).abs() < 1e-6);
+    assert!(origin.distance(dvec3(0.0, 0.0, -1.0)) < 0.01);
+    let bottom = fit_segment(&vertices, &owned_model_0.indices, &normals, &faces[1], 0.05);
+    assert_eq!(
+        Primitive::Plane {
+            normal: -DVec3::Z,
+            distance: 1.0
+        },
+        bottom.primitive
+    );
+    Ok(())
+}
+
+#[test]
+fn test_fit_primitives_2() {
+    let (vertices, indices) = sphere_vertices_and_indices(24, 12);
+    let normals = face_normals(&vertices, &indices);
+    let faces = segment_faces(&indices, &normals, 30.0_f64.to_radians().cos());
+    // the sphere is smooth enough to become a single segment
+    assert_eq!(1, faces.len());
+    let segment = fit_segment(&vertices, &indices, &normals, &faces[0], 0.1);
+    let Primitive::Sphere { center, radius } = segment.primitive else {
+        panic!("expected a sphere, got {:?}", segment.primitive);
+    };
+    assert!(center.distance(dvec3(1.0, 0.0, 0.0)) < 0.01);
+    assert!((radius - 2.0).abs() < 0.05);
+
+    // nothing fits within a tiny tolerance
+    let segment = fit_segment(&vertices, &indices, &normals, &faces[0], 1e-6);
+    assert_eq!(Primitive::Unfitted, segment.primitive);
+    assert_eq!(None, segment.rms);
+}
+
+#[test]
+fn test_fit_primitives_3() {
+    let owned_model_0 = cylinder_model(24);
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "fit_primitives".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("FIT_ANGLE".to_string(), "200".to_string());
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models, &mut Attributes::new()).is_err());
+}
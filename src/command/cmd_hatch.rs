@@ -0,0 +1,423 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{cmd_clip_curves, cmd_optimize_path, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use ahash::AHashMap;
+use rayon::prelude::*;
+use std::{collections::VecDeque, f64::consts::FRAC_PI_2, mem};
+use vector_traits::glam::{dvec2, DVec2};
+
+#[cfg(test)]
+mod tests;
+
+/// The maximum number of hatch lines of one direction
+const MAX_HATCH_LINES: usize = 100_000;
+/// The maximum order of the hilbert curve, order 10 is 1024*1024 points
+const MAX_HILBERT_ORDER: u32 = 10;
+/// The maximum number of samples along each axis of the concentric distance field
+const MAX_GRID_SIDE: usize = 512;
+/// Distance tolerance as a fraction of the hatch spacing
+const RELATIVE_TOLERANCE: f64 = 1e-9;
+
+/// Rotates `p` counter-clockwise around the origin
+fn rotate(p: DVec2, angle: f64) -> DVec2 {
+    let (sin, cos) = angle.sin_cos();
+    dvec2(cos * p.x - sin * p.y, sin * p.x + cos * p.y)
+}
+
+/// Returns the AABB of the region
+fn region_aabb(region: &[(DVec2, DVec2)]) -> (DVec2, DVec2) {
+    region.iter().fold(
+        (DVec2::splat(f64::MAX), DVec2::splat(f64::MIN)),
+        |(low, high), (a, b)| (low.min(*a).min(*b), high.max(*a).max(*b)),
+    )
+}
+
+/// Parallel lines at `angle` (radians), clipped to the region
+pub(crate) fn parallel_hatch(
+    region: &[(DVec2, DVec2)],
+    spacing: f64,
+    angle: f64,
+) -> Result<Vec<Vec<DVec2>>, HallrError> {
+    // rotate the region so that the hatch lines are horizontal
+    let rotated: Vec<(DVec2, DVec2)> = region
+        .iter()
+        .map(|(a, b)| (rotate(*a, -angle), rotate(*b, -angle)))
+        .collect();
+    let (low, high) = region_aabb(&rotated);
+    let line_count = ((high.y - low.y) / spacing) as usize + 1;
+    if line_count > MAX_HATCH_LINES {
+        return Err(HallrError::InvalidParameter(format!(
+            "The hatch spacing is too small, it would generate more than {} lines",
+            MAX_HATCH_LINES
+        )));
+    }
+    let mut rv = Vec::<Vec<DVec2>>::new();
+    for k in 0..line_count {
+        let y = low.y + spacing * (k as f64 + 0.5);
+        if y >= high.y {
+            break;
+        }
+        let a = dvec2(low.x - spacing, y);
+        let b = dvec2(high.x + spacing, y);
+        for (t0, t1) in cmd_clip_curves::clip_segment(a, b, &rotated, true) {
+            rv.push(vec![
+                rotate(a.lerp(b, t0), angle),
+                rotate(a.lerp(b, t1), angle),
+            ]);
+        }
+    }
+    Ok(rv)
+}
+
+/// Converts the distance `d` along a hilbert curve of side `n` into grid coordinates
+fn hilbert_d2xy(n: u32, d: u32) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while s < n {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+/// A hilbert curve covering the region, clipped to the region
+fn hilbert_hatch(region: &[(DVec2, DVec2)], spacing: f64) -> Result<Vec<Vec<DVec2>>, HallrError> {
+    let (low, high) = region_aabb(region);
+    let side = (high - low).max_element();
+    let order = (side / spacing).log2().ceil().max(1.0) as u32;
+    if order > MAX_HILBERT_ORDER {
+        return Err(HallrError::InvalidParameter(format!(
+            "The hatch spacing is too small, the hilbert curve would need an order above {}",
+            MAX_HILBERT_ORDER
+        )));
+    }
+    let n = 1_u32 << order;
+    let cell = side / n as f64;
+    let tolerance = spacing * RELATIVE_TOLERANCE;
+    let points: Vec<DVec2> = (0..n * n)
+        .map(|d| {
+            let (x, y) = hilbert_d2xy(n, d);
+            low + dvec2(x as f64 + 0.5, y as f64 + 0.5) * cell
+        })
+        .collect();
+
+    let mut rv = Vec::<Vec<DVec2>>::new();
+    let mut current = Vec::<DVec2>::new();
+    for edge in points.windows(2) {
+        let (a, b) = (edge[0], edge[1]);
+        for (t0, t1) in cmd_clip_curves::clip_segment(a, b, region, true) {
+            let (start, end) = (a.lerp(b, t0), a.lerp(b, t1));
+            if current
+                .last()
+                .is_some_and(|l| l.distance(start) <= tolerance)
+            {
+                current.push(end);
+            } else {
+                if current.len() > 1 {
+                    rv.push(current);
+                }
+                current = vec![start, end];
+            }
+        }
+    }
+    if current.len() > 1 {
+        rv.push(current);
+    }
+    Ok(rv)
+}
+
+/// Returns the distance from `p` to the segment a-b
+pub(crate) fn distance_to_segment(p: DVec2, a: DVec2, b: DVec2) -> f64 {
+    let ab = b - a;
+    let ab_dot = ab.dot(ab);
+    let t = if ab_dot > 0.0 {
+        ((p - a).dot(ab) / ab_dot).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + ab * t)
+}
+
+/// Joins segments sharing end points into polylines. The end points are given as ids, the
+/// polylines are returned as lists of ids. A closed loop starts and ends with the same id.
+pub(crate) fn chain_segments(segments: &[(u64, u64)]) -> Vec<Vec<u64>> {
+    let mut adjacency = AHashMap::<u64, Vec<usize>>::new();
+    for (i, (a, b)) in segments.iter().enumerate() {
+        adjacency.entry(*a).or_default().push(i);
+        adjacency.entry(*b).or_default().push(i);
+    }
+    let mut used = vec![false; segments.len()];
+    let next_id = |id: u64, used: &mut [bool]| -> Option<u64> {
+        let segment = *adjacency.get(&id)?.iter().find(|s| !used[**s])?;
+        used[segment] = true;
+        let (a, b) = segments[segment];
+        Some(if a == id { b } else { a })
+    };
+
+    let mut rv = Vec::<Vec<u64>>::new();
+    for (i, (a, b)) in segments.iter().enumerate() {
+        if used[i] {
+            continue;
+        }
+        used[i] = true;
+        let mut chain = VecDeque::from([*a, *b]);
+        while let Some(id) = next_id(*chain.back().unwrap(), &mut used) {
+            chain.push_back(id);
+        }
+        while let Some(id) = next_id(*chain.front().unwrap(), &mut used) {
+            chain.push_front(id);
+        }
+        rv.push(chain.into());
+    }
+    rv
+}
+
+/// The signed distance to the outline of a region, sampled on a regular grid. Positive inside
+/// the region.
+pub(crate) struct DistanceField {
+    low: DVec2,
+    step: f64,
+    nx: usize,
+    ny: usize,
+    values: Vec<f64>,
+}
+
+impl DistanceField {
+    /// Sample the distance field of the region at `min_step` intervals, or coarser if the grid
+    /// would get too large. The sampled area is the AABB of the region grown by `margin`.
+    pub(crate) fn new(region: &[(DVec2, DVec2)], min_step: f64, margin: f64) -> Self {
+        let (low, high) = region_aabb(region);
+        let size = high - low + DVec2::splat(2.0 * margin);
+        let step = min_step.max(size.max_element() / (MAX_GRID_SIDE - 3) as f64);
+        let low = low - DVec2::splat(margin + step);
+        let nx = (size.x / step).ceil() as usize + 3;
+        let ny = (size.y / step).ceil() as usize + 3;
+        let grid_point = |i: usize, j: usize| low + dvec2(i as f64, j as f64) * step;
+
+        let values: Vec<f64> = (0..nx * ny)
+            .into_par_iter()
+            .map(|index| {
+                let p = grid_point(index % nx, index / nx);
+                let distance = region
+                    .iter()
+                    .map(|(a, b)| distance_to_segment(p, *a, *b))
+                    .fold(f64::MAX, f64::min);
+                if cmd_clip_curves::is_inside_region(p, region) {
+                    distance
+                } else {
+                    -distance
+                }
+            })
+            .collect();
+        Self {
+            low,
+            step,
+            nx,
+            ny,
+            values,
+        }
+    }
+
+    /// The largest sampled distance
+    pub(crate) fn max_value(&self) -> f64 {
+        self.values.iter().copied().fold(f64::MIN, f64::max)
+    }
+
+    /// The number of samples along the X and Y axes
+    pub(crate) fn size(&self) -> (usize, usize) {
+        (self.nx, self.ny)
+    }
+
+    /// The distance between two samples
+    pub(crate) fn step(&self) -> f64 {
+        self.step
+    }
+
+    /// The sampled distance at the grid point
+    pub(crate) fn value(&self, i: usize, j: usize) -> f64 {
+        self.values[j * self.nx + i]
+    }
+
+    pub(crate) fn grid_point(&self, i: usize, j: usize) -> DVec2 {
+        self.low + dvec2(i as f64, j as f64) * self.step
+    }
+
+    /// The iso-lines of the field at `level`, traced with marching squares. Closed loops start
+    /// and end with the same point.
+    pub(crate) fn iso_lines(&self, level: f64) -> Vec<Vec<DVec2>> {
+        let (nx, ny) = (self.nx, self.ny);
+        let field = &self.values;
+        // the ids of the horizontal and vertical grid edges, the iso-line crossings are stored per
+        // edge
+        let horizontal_id = |i: usize, j: usize| 2 * (j * nx + i) as u64;
+        let vertical_id = |i: usize, j: usize| 2 * (j * nx + i) as u64 + 1;
+
+        let mut positions = AHashMap::<u64, DVec2>::new();
+        let mut segments = Vec::<(u64, u64)>::new();
+        let mut crossing = |id: u64, p: (usize, usize), q: (usize, usize)| -> u64 {
+            let _ = positions.entry(id).or_insert_with(|| {
+                let fp = field[p.1 * nx + p.0];
+                let fq = field[q.1 * nx + q.0];
+                let t = (level - fp) / (fq - fp);
+                self.grid_point(p.0, p.1).lerp(self.grid_point(q.0, q.1), t)
+            });
+            id
+        };
+        for j in 0..ny - 1 {
+            for i in 0..nx - 1 {
+                let corners = [
+                    field[j * nx + i],
+                    field[j * nx + i + 1],
+                    field[(j + 1) * nx + i + 1],
+                    field[(j + 1) * nx + i],
+                ];
+                let case = corners
+                    .iter()
+                    .enumerate()
+                    .fold(0, |case, (bit, v)| case | (((*v > level) as usize) << bit));
+                if case == 0 || case == 15 {
+                    continue;
+                }
+                // the crossings of the bottom, right, top and left edges of the cell
+                let mut edge = |e: usize| match e {
+                    0 => crossing(horizontal_id(i, j), (i, j), (i + 1, j)),
+                    1 => crossing(vertical_id(i + 1, j), (i + 1, j), (i + 1, j + 1)),
+                    2 => crossing(horizontal_id(i, j + 1), (i, j + 1), (i + 1, j + 1)),
+                    _ => crossing(vertical_id(i, j), (i, j), (i, j + 1)),
+                };
+                let pairs: &[(usize, usize)] = match case {
+                    1 | 14 => &[(3, 0)],
+                    2 | 13 => &[(0, 1)],
+                    3 | 12 => &[(3, 1)],
+                    4 | 11 => &[(1, 2)],
+                    5 => &[(3, 0), (1, 2)],
+                    6 | 9 => &[(0, 2)],
+                    7 | 8 => &[(3, 2)],
+                    _ => &[(0, 1), (2, 3)],
+                };
+                for (e0, e1) in pairs.iter() {
+                    segments.push((edge(*e0), edge(*e1)));
+                }
+            }
+        }
+        chain_segments(&segments)
+            .into_iter()
+            .map(|chain| chain.iter().map(|id| positions[id]).collect())
+            .collect()
+    }
+}
+
+/// Concentric offsets of the region outline, spaced `spacing` apart.
+/// The offsets are the iso-lines of a sampled distance field, traced with marching squares.
+fn concentric_hatch(region: &[(DVec2, DVec2)], spacing: f64) -> Vec<Vec<DVec2>> {
+    let field = DistanceField::new(region, spacing * 0.25, 0.0);
+    let max_distance = field.max_value();
+
+    let mut rv = Vec::<Vec<DVec2>>::new();
+    let mut level = spacing;
+    while level < max_distance {
+        rv.extend(field.iso_lines(level));
+        level += spacing;
+    }
+    rv
+}
+
+/// Run the hatch command
+/// Model 0 contains one or more closed outlines (in the line_chunks format), holes are handled
+/// with the even-odd rule. The region is filled with the `HATCH_PATTERN` (PARALLEL, CROSSHATCH,
+/// CONCENTRIC or HILBERT) at `HATCH_SPACING` distance and `HATCH_ANGLE` degrees. The result is
+/// ordered to minimize the pen-up travel and is placed at the Z coordinate of the first vertex.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let spacing = config.get_mandatory_parsed_option::<f64>("HATCH_SPACING", None)?;
+    if !spacing.is_finite() || spacing <= 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "HATCH_SPACING must be a positive number :({})",
+            spacing
+        )));
+    }
+    let angle = config
+        .get_mandatory_parsed_option::<f64>("HATCH_ANGLE", Some(0.0))?
+        .to_radians();
+    let pattern = config.get_mandatory_option("HATCH_PATTERN")?;
+
+    let model = &models[0];
+    let z = model.vertices.first().map_or(0.0, |v| v.z);
+    let region = cmd_clip_curves::parse_region(model)?;
+
+    let polylines = match pattern {
+        "PARALLEL" => parallel_hatch(&region, spacing, angle)?,
+        "CROSSHATCH" => {
+            let mut rv = parallel_hatch(&region, spacing, angle)?;
+            rv.append(&mut parallel_hatch(&region, spacing, angle + FRAC_PI_2)?);
+            rv
+        }
+        "CONCENTRIC" => concentric_hatch(&region, spacing),
+        "HILBERT" => hilbert_hatch(&region, spacing)?,
+        pattern => {
+            return Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"HATCH_PATTERN\" parameter",
+                pattern
+            )))
+        }
+    };
+    let (polylines, travel) =
+        cmd_optimize_path::order_polylines(polylines, |p| p.extend(0.0), true);
+
+    let mut output_vertices = Vec::<FFIVector3>::new();
+    let mut output_indices = Vec::<usize>::new();
+    for polyline in polylines {
+        let first_index = output_vertices.len();
+        for (i, v) in polyline.iter().enumerate() {
+            output_vertices.push(FFIVector3::new(v.x as f32, v.y as f32, z));
+            if i > 0 {
+                output_indices.push(first_index + i - 1);
+                output_indices.push(first_index + i);
+            }
+        }
+    }
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = return_config.insert("PEN_UP_TRAVEL".to_string(), travel.to_string());
+    info!(
+        "hatch operation returning {} vertices, {} indices, pen-up travel:{}",
+        output_vertices.len(),
+        output_indices.len(),
+        travel
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
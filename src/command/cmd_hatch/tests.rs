@@ -0,0 +1,103 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType},
+    HallrError,
+};
+
+#[test]
+fn test_hatch_1() -> Result<(), HallrError> {
+    // four horizontal lines, connected in a zig-zag
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "hatch".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("HATCH_PATTERN".to_string(), "PARALLEL".to_string());
+    let _ = config.insert("HATCH_SPACING".to_string(), "0.25".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 1.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(8, result.0.len()); // vertices
+    assert_eq!(8, result.1.len()); // indices
+    assert!(result.0.iter().all(|v| v.z == 1.0));
+    let travel: f64 = result.3.get("PEN_UP_TRAVEL").unwrap().parse().unwrap();
+    assert!((travel - 0.75).abs() < 1e-6);
+    Ok(())
+}
+
+#[test]
+fn test_hatch_2() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "hatch".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("HATCH_PATTERN".to_string(), "CROSSHATCH".to_string());
+    let _ = config.insert("HATCH_SPACING".to_string(), "0.25".to_string());
+    let _ = config.insert("HATCH_ANGLE".to_string(), "30".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 1.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert!(!result.0.is_empty());
+    assert_eq!(result.0.len(), result.1.len()); // one edge per line
+    assert!(result
+        .0
+        .iter()
+        .all(|v| (-1e-5..=1.00001).contains(&v.x) && (-1e-5..=1.00001).contains(&v.y)));
+    Ok(())
+}
+
+#[test]
+fn test_hatch_3() -> Result<(), HallrError> {
+    // a single continuous hilbert curve of order 2
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "hatch".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("HATCH_PATTERN".to_string(), "HILBERT".to_string());
+    let _ = config.insert("HATCH_SPACING".to_string(), "0.25".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 1.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(16, result.0.len()); // vertices
+    assert_eq!(30, result.1.len()); // indices
+    Ok(())
+}
+
+#[test]
+fn test_hatch_4() -> Result<(), HallrError> {
+    // concentric offsets of a square stay inside the square
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "hatch".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("HATCH_PATTERN".to_string(), "CONCENTRIC".to_string());
+    let _ = config.insert("HATCH_SPACING".to_string(), "1.0".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [4.0, 4.0], 1.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert!(!result.0.is_empty());
+    assert!(result
+        .0
+        .iter()
+        .all(|v| (0.99..=3.01).contains(&v.x) && (0.99..=3.01).contains(&v.y)));
+    Ok(())
+}
+
+#[test]
+fn test_hatch_5() {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "hatch".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("HATCH_PATTERN".to_string(), "SPIRAL".to_string());
+    let _ = config.insert("HATCH_SPACING".to_string(), "0.25".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 1.0);
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config.clone(), models).is_err());
+    let _ = config.insert("HATCH_PATTERN".to_string(), "PARALLEL".to_string());
+    let _ = config.insert("HATCH_SPACING".to_string(), "-1.0".to_string());
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models).is_err());
+}
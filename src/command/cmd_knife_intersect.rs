@@ -42,7 +42,7 @@ where
     if plane != Plane::XY {
         return Err(HallrError::InvalidInputData(format!("At the moment the knife intersect operation only supports input data in the XY plane. {:?}", plane)));
     }
-    println!(
+    debug!(
         "knife_intersect: data was in plane:{:?} aabb:{:?}",
         plane, aabb
     );
@@ -63,7 +63,7 @@ where
         .chunks(2)
         .map(|i| (i[0], i[1]))
         .collect();
-    println!("Input edges : {:?}", input_edges.len());
+    debug!("Input edges : {:?}", input_edges.len());
 
     // this map contains a map from `edge_id` ->  `SmallVec<new intersecting vertices id>`
     let mut edge_split = ahash::AHashMap::<usize, smallvec::SmallVec<[usize; 1]>>::default();
@@ -75,7 +75,7 @@ where
                 .with_edges(input_edges.iter())?
                 .compute()?;
         if intersection_iter.len() == 0 {
-            println!("No intersections detected!!");
+            debug!("No intersections detected!!");
         }
         for (splitting_vertex_index, affected_edges) in intersection_iter {
             let splitting_vertex = updated_vertices_list[splitting_vertex_index];
@@ -193,7 +193,7 @@ where
                 .to_string(),
         ));
     }
-    println!(
+    debug!(
         "knife_intersect receiving {} vertices, {} indices, {} edges",
         input_model.vertices.len(),
         input_model.indices.len(),
@@ -204,7 +204,7 @@ where
 
     let mut config = ConfigType::new();
     let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
-    println!(
+    info!(
         "knife_intersect returning {} vertices, {} indices, {} edges",
         rv_model.vertices.len(),
         rv_model.indices.len(),
@@ -0,0 +1,292 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{ConfigType, Model, Options};
+use crate::{
+    utils::bvh::{segment_triangle_intersection, triangle_bounds, Bvh},
+    HallrError,
+};
+use ahash::AHashMap;
+use vector_traits::glam::DVec3;
+
+#[cfg(test)]
+mod tests;
+
+/// Triangles with an area smaller than this fraction of the squared bounding box diagonal are
+/// degenerate
+const DEGENERATE_AREA_FRACTION: f64 = 1e-12;
+
+/// The quality metrics of a triangle mesh
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct MeshReport {
+    /// The vertices used by the faces
+    pub(crate) vertices: usize,
+    pub(crate) faces: usize,
+    pub(crate) edges: usize,
+    /// Edges used by one face
+    pub(crate) boundary_edges: usize,
+    /// Edges used by more than two faces
+    pub(crate) non_manifold_edges: usize,
+    /// Vertices where the faces around the vertex do not form one fan
+    pub(crate) non_manifold_vertices: usize,
+    /// Edges shared by two faces using it in the same direction, the faces are wound differently
+    pub(crate) inconsistent_edges: usize,
+    /// Faces with a repeated vertex or without area
+    pub(crate) degenerate_faces: usize,
+    /// The number of intersecting face pairs, None if not checked
+    pub(crate) self_intersections: Option<usize>,
+    /// The number of edge connected parts
+    pub(crate) components: usize,
+    pub(crate) euler_characteristic: i64,
+    pub(crate) surface_area: f64,
+    /// The signed volume, only meaningful for closed meshes
+    pub(crate) volume: f64,
+}
+
+impl MeshReport {
+    /// A closed, consistently wound, two-manifold mesh
+    pub(crate) fn is_watertight(&self) -> bool {
+        self.faces > 0
+            && self.boundary_edges == 0
+            && self.non_manifold_edges == 0
+            && self.non_manifold_vertices == 0
+            && self.inconsistent_edges == 0
+    }
+}
+
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+fn union(parent: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parent, a), find(parent, b));
+    if a != b {
+        parent[a.max(b)] = a.min(b);
+    }
+}
+
+/// Returns the number of vertices where the faces around the vertex are not one fan of faces
+/// connected by their shared edges
+fn count_non_manifold_vertices(vertex_count: usize, triangles: &[[usize; 3]]) -> usize {
+    let mut vertex_faces = vec![Vec::<usize>::new(); vertex_count];
+    for (f, t) in triangles.iter().enumerate() {
+        for v in t.iter() {
+            vertex_faces[*v].push(f);
+        }
+    }
+    let mut rv = 0;
+    for (v, faces) in vertex_faces.iter().enumerate() {
+        if faces.len() < 2 {
+            continue;
+        }
+        // two faces around v are connected if they share one more vertex
+        let mut parent: Vec<usize> = (0..faces.len()).collect();
+        let mut first_face_of = AHashMap::<usize, usize>::new();
+        for (i, f) in faces.iter().enumerate() {
+            for other in triangles[*f].iter().filter(|o| **o != v) {
+                match first_face_of.get(other) {
+                    Some(j) => union(&mut parent, i, *j),
+                    None => {
+                        let _ = first_face_of.insert(*other, i);
+                    }
+                }
+            }
+        }
+        if (0..faces.len()).any(|i| find(&mut parent, i) != 0) {
+            rv += 1;
+        }
+    }
+    rv
+}
+
+/// Returns true if an edge of one triangle crosses the other triangle
+fn triangles_intersect(a: &[DVec3; 3], b: &[DVec3; 3]) -> bool {
+    (0..3).any(|i| segment_triangle_intersection(a[i], a[(i + 1) % 3], b).is_some())
+        || (0..3).any(|i| segment_triangle_intersection(b[i], b[(i + 1) % 3], a).is_some())
+}
+
+/// Returns the number of intersecting pairs of triangles. Triangles sharing a vertex are not
+/// tested, and neither are coplanar overlaps detected.
+fn count_self_intersections(vertices: &[DVec3], triangles: &[[usize; 3]]) -> usize {
+    let corners: Vec<[DVec3; 3]> = triangles
+        .iter()
+        .map(|t| [vertices[t[0]], vertices[t[1]], vertices[t[2]]])
+        .collect();
+    let bvh = Bvh::new(corners.clone());
+    let mut rv = 0;
+    for (i, triangle) in triangles.iter().enumerate() {
+        let (min, max) = triangle_bounds(&corners[i]);
+        for j in bvh.overlapping(min, max) {
+            if j <= i || triangle.iter().any(|v| triangles[j].contains(v)) {
+                continue;
+            }
+            if triangles_intersect(&corners[i], &corners[j]) {
+                rv += 1;
+            }
+        }
+    }
+    rv
+}
+
+/// Analyze the triangles. The self intersections are only counted if `check_self_intersections`
+/// is set.
+pub(crate) fn analyze(
+    vertices: &[DVec3],
+    triangles: &[[usize; 3]],
+    check_self_intersections: bool,
+) -> MeshReport {
+    let mut rv = MeshReport {
+        faces: triangles.len(),
+        ..MeshReport::default()
+    };
+    let (low, high) = vertices.iter().fold(
+        (DVec3::splat(f64::MAX), DVec3::splat(f64::MIN)),
+        |(low, high), v| (low.min(*v), high.max(*v)),
+    );
+    let min_area = DEGENERATE_AREA_FRACTION * (high - low).length_squared();
+
+    // the faces with three distinct vertices, with and without area
+    let mut valid = Vec::<[usize; 3]>::with_capacity(triangles.len());
+    let mut solid = Vec::<[usize; 3]>::with_capacity(triangles.len());
+    for t in triangles.iter() {
+        if t[0] == t[1] || t[1] == t[2] || t[2] == t[0] {
+            rv.degenerate_faces += 1;
+            continue;
+        }
+        let (a, b, c) = (vertices[t[0]], vertices[t[1]], vertices[t[2]]);
+        let cross = (b - a).cross(c - a);
+        let area = 0.5 * cross.length();
+        rv.surface_area += area;
+        rv.volume += a.dot(cross) / 6.0;
+        valid.push(*t);
+        if area <= min_area {
+            rv.degenerate_faces += 1;
+        } else {
+            solid.push(*t);
+        }
+    }
+
+    // the number of uses of every edge, and how many of them go from the lower to the higher index
+    let mut edges = AHashMap::<(usize, usize), (usize, usize)>::new();
+    let mut parent: Vec<usize> = (0..vertices.len()).collect();
+    let mut used = vec![false; vertices.len()];
+    for t in valid.iter() {
+        for i in 0..3 {
+            let (a, b) = (t[i], t[(i + 1) % 3]);
+            let entry = edges.entry((a.min(b), a.max(b))).or_default();
+            entry.0 += 1;
+            if a < b {
+                entry.1 += 1;
+            }
+            union(&mut parent, a, b);
+            used[a] = true;
+        }
+    }
+    rv.edges = edges.len();
+    for (count, forward) in edges.values() {
+        match *count {
+            1 => rv.boundary_edges += 1,
+            2 if *forward != 1 => rv.inconsistent_edges += 1,
+            2 => (),
+            _ => rv.non_manifold_edges += 1,
+        }
+    }
+    rv.vertices = used.iter().filter(|u| **u).count();
+    rv.components = (0..vertices.len())
+        .filter(|v| used[*v] && find(&mut parent, *v) == *v)
+        .count();
+    rv.non_manifold_vertices = count_non_manifold_vertices(vertices.len(), &valid);
+    rv.euler_characteristic = rv.vertices as i64 - rv.edges as i64 + valid.len() as i64;
+    if check_self_intersections {
+        rv.self_intersections = Some(count_self_intersections(vertices, &solid));
+    }
+    rv
+}
+
+/// Run the mesh_analyze command
+/// Model 0 is a triangulated mesh, it is returned unchanged. The quality of the mesh is returned
+/// as `VERTEX_COUNT` (the used vertices), `FACE_COUNT`, `EDGE_COUNT`, `BOUNDARY_EDGES`,
+/// `NON_MANIFOLD_EDGES`, `NON_MANIFOLD_VERTICES`, `INCONSISTENT_EDGES` (edges between faces with
+/// different winding), `DEGENERATE_FACES`, `COMPONENTS`, `EULER_CHARACTERISTIC`, `SURFACE_AREA`,
+/// `VOLUME` (signed, only meaningful for closed meshes) and `WATERTIGHT`. Unless
+/// `CHECK_SELF_INTERSECTIONS=false` the number of intersecting face pairs is returned as
+/// `SELF_INTERSECTIONS`, faces sharing a vertex and coplanar overlaps are not counted.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("triangulated") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'triangulated' format".to_string(),
+        ));
+    }
+    let check_self_intersections =
+        config.get_mandatory_parsed_option::<bool>("CHECK_SELF_INTERSECTIONS", Some(true))?;
+    let model = &models[0];
+    if model.indices.len() % 3 != 0 {
+        return Err(HallrError::InvalidInputData(
+            "The number of indices is not a multiple of three".to_string(),
+        ));
+    }
+    if let Some(index) = model.indices.iter().find(|i| **i >= model.vertices.len()) {
+        return Err(HallrError::InvalidInputData(format!(
+            "The index {} is out of bounds",
+            index
+        )));
+    }
+    let vertices: Vec<DVec3> = model
+        .vertices
+        .iter()
+        .map(|v| DVec3::new(v.x as f64, v.y as f64, v.z as f64))
+        .collect();
+    let triangles: Vec<[usize; 3]> = model
+        .indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+    let report = analyze(&vertices, &triangles, check_self_intersections);
+    debug!("mesh_analyze: {:?}", report);
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+    for (key, value) in [
+        ("VERTEX_COUNT", report.vertices),
+        ("FACE_COUNT", report.faces),
+        ("EDGE_COUNT", report.edges),
+        ("BOUNDARY_EDGES", report.boundary_edges),
+        ("NON_MANIFOLD_EDGES", report.non_manifold_edges),
+        ("NON_MANIFOLD_VERTICES", report.non_manifold_vertices),
+        ("INCONSISTENT_EDGES", report.inconsistent_edges),
+        ("DEGENERATE_FACES", report.degenerate_faces),
+        ("COMPONENTS", report.components),
+    ] {
+        let _ = return_config.insert(key.to_string(), value.to_string());
+    }
+    if let Some(intersections) = report.self_intersections {
+        let _ = return_config.insert("SELF_INTERSECTIONS".to_string(), intersections.to_string());
+    }
+    let _ = return_config.insert(
+        "EULER_CHARACTERISTIC".to_string(),
+        report.euler_characteristic.to_string(),
+    );
+    let _ = return_config.insert("SURFACE_AREA".to_string(), report.surface_area.to_string());
+    let _ = return_config.insert("VOLUME".to_string(), report.volume.to_string());
+    let _ = return_config.insert("WATERTIGHT".to_string(), report.is_watertight().to_string());
+    Ok((
+        model.vertices.to_vec(),
+        model.indices.to_vec(),
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
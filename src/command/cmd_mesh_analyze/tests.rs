@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{ConfigType, OwnedModel},
+    HallrError,
+};
+
+/// The closed unit cube, with outward facing triangles
+fn cube() -> OwnedModel {
+    let mut vertices = Vec::new();
+    for i in 0..8 {
+        vertices.push(((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32).into());
+    }
+    OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices,
+        indices: vec![
+            0, 2, 1, 1, 2, 3, // bottom
+            4, 5, 6, 5, 7, 6, // top
+            0, 1, 4, 1, 5, 4, // front
+            2, 6, 3, 3, 6, 7, // back
+            0, 4, 2, 2, 4, 6, // left
+            1, 3, 5, 3, 7, 5, // right
+        ],
+    }
+}
+
+#[test]
+fn test_mesh_analyze_cube() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "mesh_analyze".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+
+    let model = cube();
+    let result = super::process_command(config, vec![model.as_model()])?;
+    // the model is returned unchanged
+    assert_eq!(model.indices, result.1);
+    let value = |key: &str| result.3.get(key).unwrap().as_str();
+    assert_eq!("8", value("VERTEX_COUNT"));
+    assert_eq!("12", value("FACE_COUNT"));
+    assert_eq!("18", value("EDGE_COUNT"));
+    assert_eq!("0", value("BOUNDARY_EDGES"));
+    assert_eq!("0", value("NON_MANIFOLD_EDGES"));
+    assert_eq!("0", value("NON_MANIFOLD_VERTICES"));
+    assert_eq!("0", value("INCONSISTENT_EDGES"));
+    assert_eq!("0", value("DEGENERATE_FACES"));
+    assert_eq!("0", value("SELF_INTERSECTIONS"));
+    assert_eq!("1", value("COMPONENTS"));
+    assert_eq!("2", value("EULER_CHARACTERISTIC"));
+    assert_eq!("true", value("WATERTIGHT"));
+    let area: f64 = value("SURFACE_AREA").parse().unwrap();
+    let volume: f64 = value("VOLUME").parse().unwrap();
+    assert!((area - 6.0).abs() < 1e-9, "{}", area);
+    assert!((volume - 1.0).abs() < 1e-9, "{}", volume);
+    Ok(())
+}
+
+#[test]
+fn test_mesh_analyze_defects() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "mesh_analyze".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+
+    let mut model = cube();
+    // remove the last face, flip the first one and add a degenerate face
+    model.indices.truncate(33);
+    model.indices.swap(0, 1);
+    model.indices.extend([0, 0, 7]);
+    // a separate triangle piercing the cube
+    model.vertices.push((0.5, 0.5, -1.0).into());
+    model.vertices.push((0.6, 0.5, 2.0).into());
+    model.vertices.push((0.5, 0.6, 2.0).into());
+    model.indices.extend([8, 9, 10]);
+
+    let result = super::process_command(config.clone(), vec![model.as_model()])?;
+    let value = |key: &str| result.3.get(key).unwrap().as_str();
+    assert_eq!("false", value("WATERTIGHT"));
+    assert_eq!("1", value("DEGENERATE_FACES"));
+    assert_eq!("2", value("COMPONENTS"));
+    // the open triangle, and the three edges of the removed face
+    assert_eq!("6", value("BOUNDARY_EDGES"));
+    // the flipped face disagrees with all of its neighbours
+    assert_eq!("3", value("INCONSISTENT_EDGES"));
+    // the triangle crosses the top and the bottom of the cube
+    assert_eq!("2", value("SELF_INTERSECTIONS"));
+
+    let _ = config.insert("CHECK_SELF_INTERSECTIONS".to_string(), "false".to_string());
+    let result = super::process_command(config, vec![model.as_model()])?;
+    assert!(!result.3.contains_key("SELF_INTERSECTIONS"));
+    Ok(())
+}
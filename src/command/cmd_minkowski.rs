@@ -0,0 +1,371 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, utils, HallrError};
+use ahash::{AHashMap, AHashSet};
+use vector_traits::glam::{dvec2, DVec2};
+
+#[cfg(test)]
+mod tests;
+
+/// Distance tolerance as a fraction of the size of the result
+const RELATIVE_TOLERANCE: f64 = 1e-9;
+const DUMMY_VEC: [usize; 0] = [];
+
+/// Returns twice the signed area of the polygon, positive for counter-clockwise polygons
+fn signed_area_2(polygon: &[DVec2]) -> f64 {
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| a.perp_dot(*b))
+        .sum()
+}
+
+/// Returns the polygon in counter-clockwise order
+fn to_ccw(mut polygon: Vec<DVec2>) -> Vec<DVec2> {
+    if signed_area_2(&polygon) < 0.0 {
+        polygon.reverse();
+    }
+    polygon
+}
+
+/// Removes collinear and duplicated points from a closed polygon
+fn remove_collinear(polygon: Vec<DVec2>, epsilon: f64) -> Vec<DVec2> {
+    let is_collinear =
+        |a: DVec2, b: DVec2, c: DVec2| -> bool { (b - a).perp_dot(c - b).abs() <= epsilon };
+    let mut rv = Vec::<DVec2>::with_capacity(polygon.len());
+    for p in polygon {
+        rv.push(p);
+        while rv.len() >= 3 && is_collinear(rv[rv.len() - 3], rv[rv.len() - 2], rv[rv.len() - 1]) {
+            let _ = rv.remove(rv.len() - 2);
+        }
+    }
+    while rv.len() >= 3 && is_collinear(rv[rv.len() - 2], rv[rv.len() - 1], rv[0]) {
+        let _ = rv.pop();
+    }
+    while rv.len() >= 3 && is_collinear(rv[rv.len() - 1], rv[0], rv[1]) {
+        let _ = rv.remove(0);
+    }
+    rv
+}
+
+/// Returns true if the counter-clockwise polygon is convex
+fn is_convex(polygon: &[DVec2]) -> bool {
+    let n = polygon.len();
+    (0..n).all(|i| {
+        let a = polygon[i];
+        let b = polygon[(i + 1) % n];
+        let c = polygon[(i + 2) % n];
+        (b - a).perp_dot(c - b) >= 0.0
+    })
+}
+
+/// Returns the index of the bottom-most (then left-most) point
+fn lowest_point(polygon: &[DVec2]) -> usize {
+    polygon
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| {
+            a.y.partial_cmp(&b.y)
+                .unwrap()
+                .then(a.x.partial_cmp(&b.x).unwrap())
+        })
+        .map_or(0, |(i, _)| i)
+}
+
+/// The Minkowski sum of two convex counter-clockwise polygons, O(n+m).
+/// The edges of the two polygons are merged in polar angle order.
+fn convex_minkowski_sum(a: &[DVec2], b: &[DVec2]) -> Vec<DVec2> {
+    let (n, m) = (a.len(), b.len());
+    let (ia, ib) = (lowest_point(a), lowest_point(b));
+    let va = |i: usize| a[(ia + i) % n];
+    let vb = |j: usize| b[(ib + j) % m];
+    let mut rv = Vec::<DVec2>::with_capacity(n + m);
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        rv.push(va(i) + vb(j));
+        let cross = (va(i + 1) - va(i)).perp_dot(vb(j + 1) - vb(j));
+        if j >= m || (i < n && cross > 0.0) {
+            i += 1;
+        } else if i >= n || cross < 0.0 {
+            j += 1;
+        } else {
+            i += 1;
+            j += 1;
+        }
+    }
+    rv
+}
+
+/// Split a simple counter-clockwise polygon into convex pieces (triangles).
+/// Convex polygons are returned as they are.
+fn convex_decomposition(polygon: Vec<DVec2>) -> Result<Vec<Vec<DVec2>>, HallrError> {
+    if is_convex(&polygon) {
+        return Ok(vec![polygon]);
+    }
+    let flattened_coords: Vec<f64> = polygon.iter().flat_map(|v| [v.x, v.y]).collect();
+    let triangulation = earcutr::earcut(&flattened_coords, &DUMMY_VEC, 2)?;
+    Ok(triangulation
+        .chunks_exact(3)
+        .map(|t| to_ccw(vec![polygon[t[0]], polygon[t[1]], polygon[t[2]]]))
+        .collect())
+}
+
+/// Returns true if `point` is inside, or on the border of, the convex counter-clockwise polygon
+fn is_inside_convex(polygon: &[DVec2], point: DVec2, epsilon: f64) -> bool {
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .all(|(a, b)| (*b - *a).normalize().perp_dot(point - *a) >= -epsilon)
+}
+
+/// Split every segment where it intersects, or touches, any other segment
+fn split_segments(segments: &[(DVec2, DVec2)], epsilon: f64) -> Vec<(DVec2, DVec2)> {
+    let mut rv = Vec::<(DVec2, DVec2)>::with_capacity(segments.len() * 2);
+    for (i, (p, q)) in segments.iter().enumerate() {
+        let d = *q - *p;
+        let length = d.length();
+        let mut split_points = vec![0.0, 1.0];
+        for (j, (r, s)) in segments.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            // end points of the other segment touching this segment
+            for point in [*r, *s] {
+                let t = (point - *p).dot(d) / (length * length);
+                if t > 0.0 && t < 1.0 && d.perp_dot(point - *p).abs() / length <= epsilon {
+                    split_points.push(t);
+                }
+            }
+            let e = *s - *r;
+            let denominator = d.perp_dot(e);
+            if denominator.abs() > f64::EPSILON {
+                let rp = *r - *p;
+                let t = rp.perp_dot(e) / denominator;
+                let u = rp.perp_dot(d) / denominator;
+                if t > 0.0 && t < 1.0 && (0.0..=1.0).contains(&u) {
+                    split_points.push(t);
+                }
+            }
+        }
+        split_points.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+        for t in split_points.windows(2) {
+            if (t[1] - t[0]) * length > epsilon {
+                rv.push((*p + d * t[0], *p + d * t[1]));
+            }
+        }
+    }
+    rv
+}
+
+/// Calculates the outline of the union of a set of convex counter-clockwise polygons.
+/// Returns a list of closed loops.
+fn union_of_convex(pieces: &[Vec<DVec2>], scale: f64) -> Vec<Vec<DVec2>> {
+    let epsilon = scale * RELATIVE_TOLERANCE;
+    let segments: Vec<(DVec2, DVec2)> = pieces
+        .iter()
+        .flat_map(|piece| {
+            piece
+                .iter()
+                .zip(piece.iter().cycle().skip(1))
+                .map(|(a, b)| (*a, *b))
+        })
+        .collect();
+
+    // vertex de-duplication on a quantized grid
+    let grid = epsilon * 100.0;
+    let mut vertex_map = AHashMap::<(i64, i64), usize>::new();
+    let mut vertices = Vec::<DVec2>::new();
+    let mut vertex_index = |v: DVec2| -> usize {
+        let key = ((v.x / grid).round() as i64, (v.y / grid).round() as i64);
+        *vertex_map.entry(key).or_insert_with(|| {
+            vertices.push(v);
+            vertices.len() - 1
+        })
+    };
+
+    // keep the segments that does not have any piece immediately on their right hand side
+    let mut edge_set = AHashSet::<(usize, usize)>::new();
+    let mut edges = Vec::<(usize, usize)>::new();
+    for (p, q) in split_segments(&segments, epsilon) {
+        let d = q - p;
+        let outside_normal = dvec2(d.y, -d.x).normalize();
+        let probe = (p + q) / 2.0 + outside_normal * scale * 1e-6;
+        if pieces
+            .iter()
+            .any(|piece| is_inside_convex(piece, probe, epsilon))
+        {
+            continue;
+        }
+        let edge = (vertex_index(p), vertex_index(q));
+        if edge.0 != edge.1 && edge_set.insert(edge) {
+            edges.push(edge);
+        }
+    }
+
+    // chain the edges into loops
+    let mut next = AHashMap::<usize, Vec<usize>>::new();
+    for (a, b) in edges.iter() {
+        next.entry(*a).or_default().push(*b);
+    }
+    let mut used = AHashSet::<(usize, usize)>::new();
+    let mut loops = Vec::<Vec<DVec2>>::new();
+    for edge in edges.iter() {
+        if used.contains(edge) {
+            continue;
+        }
+        let mut current = *edge;
+        let mut a_loop = vec![vertices[current.0]];
+        while used.insert(current) {
+            if current.1 == edge.0 {
+                break;
+            }
+            a_loop.push(vertices[current.1]);
+            match next
+                .get(&current.1)
+                .and_then(|n| n.iter().find(|b| !used.contains(&(current.1, **b))))
+            {
+                Some(b) => current = (current.1, *b),
+                None => break,
+            }
+        }
+        let a_loop = remove_collinear(a_loop, epsilon);
+        if a_loop.len() >= 3 {
+            loops.push(a_loop);
+        }
+    }
+    loops
+}
+
+/// Reads the model as a single closed polygon, projected onto the XY plane
+fn parse_polygon(model: &Model<'_>, name: &str) -> Result<Vec<DVec2>, HallrError> {
+    if model.indices.len() < 6 {
+        return Err(HallrError::InvalidInputData(format!(
+            "The {} model must be a closed loop of at least three edges",
+            name
+        )));
+    }
+    let mut indices = utils::reconstruct_from_unordered_edges(model.indices)?;
+    if indices.first() != indices.last() {
+        return Err(HallrError::InvalidInputData(format!(
+            "The {} model must be a closed loop",
+            name
+        )));
+    }
+    let _ = indices.pop();
+    let mut polygon = Vec::<DVec2>::with_capacity(indices.len());
+    for i in indices {
+        let v = model.vertices[i];
+        if !v.x.is_finite() || !v.y.is_finite() {
+            return Err(HallrError::InvalidInputData(format!(
+                "Only valid coordinates are allowed ({},{},{})",
+                v.x, v.y, v.z
+            )));
+        }
+        polygon.push(dvec2(v.x as f64, v.y as f64));
+    }
+    Ok(to_ccw(polygon))
+}
+
+/// Calculates the Minkowski sum of two simple polygons, returns a list of closed loops.
+/// The first loop of the returned list is not necessarily the outer loop.
+pub(crate) fn minkowski_sum(a: Vec<DVec2>, b: Vec<DVec2>) -> Result<Vec<Vec<DVec2>>, HallrError> {
+    let scale = {
+        let (low, high) = a.iter().chain(b.iter()).fold(
+            (DVec2::splat(f64::MAX), DVec2::splat(f64::MIN)),
+            |(low, high), v| (low.min(*v), high.max(*v)),
+        );
+        (high - low).max_element().max(f64::EPSILON)
+    };
+    let epsilon = scale * RELATIVE_TOLERANCE;
+    let a = remove_collinear(to_ccw(a), epsilon);
+    let b = remove_collinear(to_ccw(b), epsilon);
+    if a.len() < 3 || b.len() < 3 {
+        return Err(HallrError::InvalidInputData(
+            "The input polygons must have a non-zero area".to_string(),
+        ));
+    }
+
+    if is_convex(&a) && is_convex(&b) {
+        // fast path
+        return Ok(vec![remove_collinear(
+            convex_minkowski_sum(&a, &b),
+            epsilon,
+        )]);
+    }
+    // general path: the union of the sums of every pair of convex pieces
+    let pieces_a = convex_decomposition(a)?;
+    let pieces_b = convex_decomposition(b)?;
+    let pieces: Vec<Vec<DVec2>> = pieces_a
+        .iter()
+        .flat_map(|pa| {
+            pieces_b
+                .iter()
+                .map(move |pb| remove_collinear(convex_minkowski_sum(pa, pb), epsilon))
+        })
+        .filter(|piece| piece.len() >= 3)
+        .collect();
+    debug!(
+        "minkowski: general path, {}*{} convex pieces",
+        pieces_a.len(),
+        pieces_b.len()
+    );
+    Ok(union_of_convex(&pieces, scale * 2.0))
+}
+
+/// Run the minkowski command
+/// Model 0 and model 1 are closed planar polygons (in the line_chunks format), the result is the
+/// Minkowski sum of the two, placed at the Z coordinate of the first vertex of model 0.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.len() < 2 {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires two input models".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let z = models[0].vertices.first().map_or(0.0, |v| v.z);
+    let a = parse_polygon(&models[0], "first")?;
+    let b = parse_polygon(&models[1], "second")?;
+    debug!(
+        "minkowski: received polygons of {} and {} vertices",
+        a.len(),
+        b.len()
+    );
+
+    let loops = minkowski_sum(a, b)?;
+
+    let mut output_vertices = Vec::<FFIVector3>::new();
+    let mut output_indices = Vec::<usize>::new();
+    for a_loop in loops {
+        let first_index = output_vertices.len();
+        for (i, v) in a_loop.iter().enumerate() {
+            output_vertices.push(FFIVector3::new(v.x as f32, v.y as f32, z));
+            output_indices.push(first_index + i);
+            output_indices.push(first_index + (i + 1) % a_loop.len());
+        }
+    }
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    info!(
+        "minkowski operation returning {} vertices, {} indices",
+        output_vertices.len(),
+        output_indices.len()
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        models[0].world_orientation.to_vec(),
+        return_config,
+    ))
+}
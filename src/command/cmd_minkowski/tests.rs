@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType, OwnedModel},
+    HallrError,
+};
+
+#[test]
+fn test_minkowski_1() -> Result<(), HallrError> {
+    // convex fast path: unit square + square of side 1 = square of side 2
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "minkowski".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [1.0, 1.0], 1.0);
+    let owned_model_1 = fixtures::rectangle_outline([-0.5, -0.5], [0.5, 0.5], 0.0);
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(4, result.0.len()); // vertices
+    assert_eq!(8, result.1.len()); // indices
+    assert!(result.0.iter().all(|v| v.z == 1.0));
+    assert!(result
+        .0
+        .iter()
+        .all(|v| (v.x == -0.5 || v.x == 1.5) && (v.y == -0.5 || v.y == 1.5)));
+    Ok(())
+}
+
+#[test]
+fn test_minkowski_2() -> Result<(), HallrError> {
+    // general path: an L shape grown by a small square is still an L shape
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "minkowski".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (2.0, 0.0, 0.0).into(),
+            (2.0, 1.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (1.0, 2.0, 0.0).into(),
+            (0.0, 2.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 0],
+    };
+    let owned_model_1 = fixtures::rectangle_outline([-0.1, -0.1], [0.1, 0.1], 0.0);
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(6, result.0.len()); // vertices
+    assert_eq!(12, result.1.len()); // indices
+    Ok(())
+}
+
+#[test]
+fn test_minkowski_3() -> Result<(), HallrError> {
+    // general path: a large enough square closes the slot of the U shape
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "minkowski".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (3.0, 0.0, 0.0).into(),
+            (3.0, 3.0, 0.0).into(),
+            (2.0, 3.0, 0.0).into(),
+            (2.0, 1.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (1.0, 3.0, 0.0).into(),
+            (0.0, 3.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 0],
+    };
+    let owned_model_1 = fixtures::rectangle_outline([-0.6, -0.6], [0.6, 0.6], 0.0);
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(4, result.0.len()); // vertices
+    assert_eq!(8, result.1.len()); // indices
+    Ok(())
+}
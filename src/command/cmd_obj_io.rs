@@ -0,0 +1,256 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{mesh_format::MeshFormat, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use std::{fmt::Write, fs, str::FromStr};
+
+#[cfg(test)]
+mod tests;
+
+/// Read from, or write to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileMode {
+    Load,
+    Save,
+}
+
+impl FromStr for FileMode {
+    type Err = HallrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "LOAD" => Ok(Self::Load),
+            "SAVE" => Ok(Self::Save),
+            _ => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid file mode, LOAD or SAVE",
+                s
+            ))),
+        }
+    }
+}
+
+/// Resolve a (1-based, or negative and relative) OBJ index of a vertex element
+fn parse_index(
+    element: &str,
+    vertex_count: usize,
+    line_number: usize,
+) -> Result<usize, HallrError> {
+    let invalid = || {
+        HallrError::InvalidInputData(format!(
+            "Invalid vertex reference \"{}\" at line {}",
+            element, line_number
+        ))
+    };
+    // only the vertex part of v/vt/vn is used
+    let index = element
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .parse::<i64>()
+        .map_err(|_| invalid())?;
+    let index = if index < 0 {
+        vertex_count as i64 + index
+    } else {
+        index - 1
+    };
+    if index < 0 || index >= vertex_count as i64 {
+        return Err(invalid());
+    }
+    Ok(index as usize)
+}
+
+/// Parse the vertices, faces and lines of a Wavefront OBJ text. All the objects and groups are
+/// merged. If there are faces they are returned in the ngons format and the lines are ignored,
+/// otherwise the lines are returned in the line_chunks format.
+/// Returns the vertices, the indices, the format and the number of ignored lines.
+pub(crate) fn parse_obj(
+    text: &str,
+) -> Result<(Vec<FFIVector3>, Vec<usize>, MeshFormat, usize), HallrError> {
+    let mut vertices = Vec::<FFIVector3>::new();
+    let mut faces = Vec::<usize>::new();
+    let mut edges = Vec::<usize>::new();
+    let mut line_count = 0_usize;
+    for (line_number, line) in text.lines().enumerate() {
+        let line_number = line_number + 1;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let coordinates = tokens
+                    .take(3)
+                    .map(|t| t.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()
+                    .filter(|c| c.len() == 3)
+                    .ok_or_else(|| {
+                        HallrError::InvalidInputData(format!(
+                            "Invalid vertex at line {}",
+                            line_number
+                        ))
+                    })?;
+                vertices.push(FFIVector3::new(
+                    coordinates[0],
+                    coordinates[1],
+                    coordinates[2],
+                ));
+            }
+            Some("f") => {
+                let face = tokens
+                    .map(|t| parse_index(t, vertices.len(), line_number))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if face.len() < 3 {
+                    return Err(HallrError::InvalidInputData(format!(
+                        "A face needs at least three vertices, line {}",
+                        line_number
+                    )));
+                }
+                faces.push(face.len());
+                faces.extend(face);
+            }
+            Some("l") => {
+                let polyline = tokens
+                    .map(|t| parse_index(t, vertices.len(), line_number))
+                    .collect::<Result<Vec<_>, _>>()?;
+                for edge in polyline.windows(2) {
+                    edges.extend(edge);
+                }
+                line_count += 1;
+            }
+            // normals, texture coordinates, groups, materials etc. are ignored
+            _ => (),
+        }
+    }
+    if !faces.is_empty() {
+        Ok((vertices, faces, MeshFormat::Ngons, line_count))
+    } else if !edges.is_empty() {
+        Ok((vertices, edges, MeshFormat::LineChunks, 0))
+    } else {
+        Err(HallrError::NoData(
+            "The OBJ data contains no faces or lines".to_string(),
+        ))
+    }
+}
+
+/// Format the model as a Wavefront OBJ text, the indices are interpreted in the `format`
+pub(crate) fn to_obj(
+    name: &str,
+    vertices: &[FFIVector3],
+    indices: &[usize],
+    format: MeshFormat,
+) -> Result<String, HallrError> {
+    let mut rv = String::new();
+    let _ = writeln!(rv, "# hallr\no {}", name);
+    for v in vertices.iter() {
+        let _ = writeln!(rv, "v {} {} {}", v.x, v.y, v.z);
+    }
+    let mut write_element = |kind: &str, element: &[usize]| -> Result<(), HallrError> {
+        if let Some(index) = element.iter().find(|i| **i >= vertices.len()) {
+            return Err(HallrError::InvalidInputData(format!(
+                "The index {} is out of bounds",
+                index
+            )));
+        }
+        rv.push_str(kind);
+        for i in element.iter() {
+            let _ = write!(rv, " {}", i + 1);
+        }
+        rv.push('\n');
+        Ok(())
+    };
+    match format {
+        MeshFormat::Triangulated => {
+            for triangle in indices.chunks_exact(3) {
+                write_element("f", triangle)?;
+            }
+        }
+        MeshFormat::LineChunks => {
+            for edge in indices.chunks_exact(2) {
+                write_element("l", edge)?;
+            }
+        }
+        MeshFormat::LineWindows => {
+            if indices.len() > 1 {
+                write_element("l", indices)?;
+            }
+        }
+        MeshFormat::Ngons => {
+            let mut rest = indices;
+            while let Some((count, tail)) = rest.split_first() {
+                if *count > tail.len() {
+                    return Err(HallrError::InvalidInputData(
+                        "The ngons data is truncated".to_string(),
+                    ));
+                }
+                write_element("f", &tail[..*count])?;
+                rest = &tail[*count..];
+            }
+        }
+    }
+    Ok(rv)
+}
+
+/// Run the obj_io command
+/// With `OBJ_MODE=LOAD` the Wavefront OBJ file at `OBJ_PATH` is returned as a mesh, with the world
+/// matrix of model 0 (model 0 itself is not used, it can be empty). Faces are returned in the
+/// ngons format, files without faces return their lines in the line_chunks format.
+/// With `OBJ_MODE=SAVE` model 0 is written to `OBJ_PATH` and returned unchanged, the vertices are
+/// written as they are (without the world matrix).
+/// The vertex and index counts are returned as `OBJ_VERTICES` and `OBJ_INDICES`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mode = config.get_mandatory_parsed_option::<FileMode>("OBJ_MODE", None)?;
+    let path = config.get_mandatory_option("OBJ_PATH")?;
+    let model = &models[0];
+
+    let mut return_config = ConfigType::new();
+    let (vertices, indices) = match mode {
+        FileMode::Load => {
+            let text = fs::read_to_string(path).map_err(|e| {
+                HallrError::InvalidParameter(format!("Could not read {}: {}", path, e))
+            })?;
+            let (vertices, indices, format, ignored_lines) = parse_obj(&text)?;
+            let _ = return_config.insert("mesh.format".to_string(), format.to_string());
+            if ignored_lines > 0 {
+                let _ = return_config.insert(
+                    "WARNING".to_string(),
+                    format!("{} lines of the OBJ file were ignored", ignored_lines),
+                );
+            }
+            (vertices, indices)
+        }
+        FileMode::Save => {
+            let format = config.get_mandatory_parsed_option::<MeshFormat>("mesh.format", None)?;
+            let name = config
+                .get_mandatory_parsed_option::<String>("OBJ_NAME", Some("hallr".to_string()))?;
+            let text = to_obj(&name, model.vertices, model.indices, format)?;
+            fs::write(path, text).map_err(|e| {
+                HallrError::InternalError(format!("Could not write {}: {}", path, e))
+            })?;
+            let _ = return_config.insert("mesh.format".to_string(), format.to_string());
+            (model.vertices.to_vec(), model.indices.to_vec())
+        }
+    };
+    let _ = return_config.insert("OBJ_VERTICES".to_string(), vertices.len().to_string());
+    let _ = return_config.insert("OBJ_INDICES".to_string(), indices.len().to_string());
+    debug!(
+        "obj_io operation {:?} {}: {} vertices, {} indices",
+        mode,
+        path,
+        vertices.len(),
+        indices.len()
+    );
+    Ok((
+        vertices,
+        indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::MeshFormat;
+use crate::{
+    command::{ConfigType, OwnedModel},
+    HallrError,
+};
+
+#[test]
+fn test_obj_io_parse() -> Result<(), HallrError> {
+    let text = "# a quad and a triangle\n\
+                o test\n\
+                v 0 0 0\n\
+                v 1 0 0\n\
+                v 1 1 0\n\
+                v 0 1 0.5\n\
+                vn 0 0 1\n\
+                f 1//1 2//1 3//1 4//1\n\
+                f -4/1 -3 -1\n\
+                l 1 3\n";
+    let (vertices, indices, format, ignored_lines) = super::parse_obj(text)?;
+    assert_eq!(4, vertices.len());
+    assert_eq!(0.5, vertices[3].z);
+    assert_eq!(MeshFormat::Ngons, format);
+    assert_eq!(vec![4, 0, 1, 2, 3, 3, 0, 1, 3], indices);
+    assert_eq!(1, ignored_lines);
+
+    let (_, indices, format, _) = super::parse_obj("v 0 0 0\nv 1 0 0\nv 1 1 0\nl 1 2 3\n")?;
+    assert_eq!(MeshFormat::LineChunks, format);
+    assert_eq!(vec![0, 1, 1, 2], indices);
+
+    assert!(super::parse_obj("v 0 0 0\nv 1 0 0\nf 1 2 3\n").is_err());
+    assert!(super::parse_obj("v 0 0 0\n").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_obj_io_round_trip() -> Result<(), HallrError> {
+    let path = std::env::temp_dir().join(format!("hallr_obj_test_{}.obj", std::process::id()));
+    let path = path.to_str().unwrap();
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "obj_io".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("OBJ_MODE".to_string(), "SAVE".to_string());
+    let _ = config.insert("OBJ_PATH".to_string(), path.to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (1.0, 0.0, 0.25).into(),
+            (1.0, 1.0, 0.0).into(),
+            (0.0, 1.0, -0.5).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 2, 3],
+    };
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert_eq!(owned_model_0.indices, result.1);
+    assert_eq!("4", result.3.get("OBJ_VERTICES").unwrap());
+
+    let empty_model = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: Vec::new(),
+        indices: Vec::new(),
+    };
+    let _ = config.insert("OBJ_MODE".to_string(), "LOAD".to_string());
+    let models = vec![empty_model.as_model()];
+    let result = super::process_command(config.clone(), models);
+    let _ = std::fs::remove_file(path);
+    let result = result?;
+    assert!(owned_model_0.vertices == result.0);
+    assert_eq!(vec![3, 0, 1, 2, 3, 0, 2, 3], result.1);
+    assert_eq!("ngons", result.3.get("mesh.format").unwrap());
+
+    // the file is gone
+    let models = vec![empty_model.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
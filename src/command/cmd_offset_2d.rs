@@ -0,0 +1,280 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{
+    cmd_hatch,
+    cmd_orient_outlines::{self, signed_area},
+    ConfigType, Model, Options,
+};
+use crate::{ffi::FFIVector3, HallrError};
+use std::{f64::consts::TAU, str::FromStr};
+use vector_traits::glam::{dvec2, DVec2};
+
+#[cfg(test)]
+mod tests;
+
+/// The largest number of offsets generated by one command
+const MAX_OFFSET_COUNT: usize = 1000;
+
+/// How the offset edges are connected at the outer corners
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JoinType {
+    /// The offset edges are extended until they meet, limited by the miter limit
+    Miter,
+    /// The corner is rounded with an arc around the original vertex
+    Round,
+    /// The ends of the offset edges are connected directly
+    Bevel,
+}
+
+impl FromStr for JoinType {
+    type Err = HallrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "MITER" => Ok(Self::Miter),
+            "ROUND" => Ok(Self::Round),
+            "BEVEL" => Ok(Self::Bevel),
+            _ => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"OFFSET_JOIN\" parameter",
+                s
+            ))),
+        }
+    }
+}
+
+/// The join parameters of an offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Join {
+    pub(crate) join_type: JoinType,
+    /// The longest miter, as a multiple of the offset distance
+    pub(crate) miter_limit: f64,
+    /// The number of segments of a full circle, for round joins
+    pub(crate) arc_segments: usize,
+}
+
+/// Returns the polygon offset by `distance` to the right of its edges: outwards for a
+/// counter-clockwise polygon, and inwards for a clockwise one. Inner corners are always mitered,
+/// the outer corners are joined as selected by `join`. Self intersections of the result are not
+/// resolved.
+pub(crate) fn offset_polygon(polygon: &[DVec2], distance: f64, join: Join) -> Vec<DVec2> {
+    let n = polygon.len();
+    let mut rv = Vec::<DVec2>::with_capacity(n);
+    for (i, p) in polygon.iter().enumerate() {
+        let d0 = (*p - polygon[(i + n - 1) % n]).normalize_or_zero();
+        let d1 = (polygon[(i + 1) % n] - *p).normalize_or_zero();
+        if d0 == DVec2::ZERO || d1 == DVec2::ZERO {
+            // a repeated vertex
+            continue;
+        }
+        let n0 = dvec2(d0.y, -d0.x);
+        let n1 = dvec2(d1.y, -d1.x);
+        let (a, b) = (*p + n0 * distance, *p + n1 * distance);
+        let cos = n0.dot(n1);
+        let turn = d0.perp_dot(d1);
+        if turn.abs() <= f64::EPSILON && cos > 0.0 {
+            // collinear edges
+            rv.push(a);
+            continue;
+        }
+        // the intersection of the offset edges, None if the edges turn back on each other
+        let miter = if 1.0 + cos > 1e-9 {
+            Some(*p + (n0 + n1) * (distance / (1.0 + cos)))
+        } else {
+            None
+        };
+        if turn * distance <= 0.0 {
+            // an inner corner, the offset edges overlap
+            match miter {
+                Some(m) => rv.push(m),
+                None => rv.extend([a, b]),
+            }
+            continue;
+        }
+        match join.join_type {
+            JoinType::Miter => match miter {
+                Some(m) if m.distance(*p) <= join.miter_limit * distance.abs() => rv.push(m),
+                _ => rv.extend([a, b]),
+            },
+            JoinType::Bevel => rv.extend([a, b]),
+            JoinType::Round => {
+                let angle = n0.perp_dot(n1).atan2(cos);
+                let steps = ((angle.abs() * join.arc_segments as f64 / TAU).ceil() as usize).max(1);
+                rv.extend((0..=steps).map(|s| {
+                    *p + DVec2::from_angle(angle * s as f64 / steps as f64).rotate(n0) * distance
+                }));
+            }
+        }
+    }
+    rv
+}
+
+/// Returns true if the offset of the loop `original` (with the signed area `original_area`) is
+/// usable. Growing loops always are. Shrinking loops must keep their orientation, get smaller and
+/// stay at least the offset distance away from the original loop: otherwise the loop has
+/// collapsed, or folded over itself.
+fn is_valid_offset(
+    original: &[DVec2],
+    original_area: f64,
+    offset: &[DVec2],
+    distance: f64,
+) -> bool {
+    if offset.len() < 3 {
+        return false;
+    }
+    if original_area * distance > 0.0 {
+        return true;
+    }
+    let area = signed_area(offset);
+    if area * original_area <= 0.0 || area.abs() >= original_area.abs() {
+        return false;
+    }
+    let min_distance = distance.abs() * (1.0 - 1e-6);
+    offset.iter().all(|p| {
+        (0..original.len()).all(|i| {
+            let (a, b) = (original[i], original[(i + 1) % original.len()]);
+            cmd_hatch::distance_to_segment(*p, a, b) >= min_distance
+        })
+    })
+}
+
+/// Run the offset_2d command
+/// Model 0 is one or more closed loops in the line_chunks format, in the XY plane. Loops inside
+/// of other loops are holes. The region is offset by `OFFSET_DISTANCE` (in model units): positive
+/// distances grow the region, negative distances shrink it. The outer corners are joined with
+/// `OFFSET_JOIN`: MITER (limited to `OFFSET_MITER_LIMIT` times the distance, default 2.0, beyond
+/// that the corner is beveled), ROUND (default, `OFFSET_ARC_SEGMENTS` segments per full circle,
+/// default 32) or BEVEL. With `OFFSET_COUNT` (default 1) the region is offset repeatedly, by
+/// 1, 2, .. times the distance, until every loop has collapsed.
+/// Loops collapsing completely are removed, their number is returned as `OFFSET_COLLAPSED`. The
+/// offset loops are returned in the line_chunks format, outer loops counter-clockwise and holes
+/// clockwise, and their number as `OFFSET_LOOPS`. Self intersections of the offset loops, or
+/// overlaps between loops, are not resolved.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let model = &models[0];
+    if let Some(index) = model.indices.iter().find(|i| **i >= model.vertices.len()) {
+        return Err(HallrError::InvalidInputData(format!(
+            "The index {} is out of bounds",
+            index
+        )));
+    }
+    let distance = config.get_mandatory_parsed_option::<f64>("OFFSET_DISTANCE", None)?;
+    if !distance.is_finite() || distance == 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "OFFSET_DISTANCE must be a non-zero number :({})",
+            distance
+        )));
+    }
+    let join = Join {
+        join_type: config
+            .get_mandatory_parsed_option::<JoinType>("OFFSET_JOIN", Some(JoinType::Round))?,
+        miter_limit: config.get_mandatory_parsed_option::<f64>("OFFSET_MITER_LIMIT", Some(2.0))?,
+        arc_segments: config
+            .get_mandatory_parsed_option::<usize>("OFFSET_ARC_SEGMENTS", Some(32))?,
+    };
+    if !join.miter_limit.is_finite() || join.miter_limit < 1.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "OFFSET_MITER_LIMIT must be at least 1.0 :({})",
+            join.miter_limit
+        )));
+    }
+    if join.arc_segments < 4 {
+        return Err(HallrError::InvalidParameter(format!(
+            "OFFSET_ARC_SEGMENTS must be at least 4 :({})",
+            join.arc_segments
+        )));
+    }
+    let count = config.get_mandatory_parsed_option::<usize>("OFFSET_COUNT", Some(1))?;
+    if !(1..=MAX_OFFSET_COUNT).contains(&count) {
+        return Err(HallrError::InvalidParameter(format!(
+            "The valid range of OFFSET_COUNT is [1..{}] :({})",
+            MAX_OFFSET_COUNT, count
+        )));
+    }
+
+    let mut loops = cmd_orient_outlines::classify_loops(model.vertices, model.indices)?;
+    if loops.is_empty() {
+        return Err(HallrError::NoData("The outline has no edges".to_string()));
+    }
+    let _ = cmd_orient_outlines::normalize_winding(&mut loops);
+    // the polygon and the mean Z of every loop
+    let polygons: Vec<(Vec<DVec2>, f32)> = loops
+        .iter()
+        .map(|l| {
+            let polygon = l
+                .indices
+                .iter()
+                .map(|i| dvec2(model.vertices[*i].x as f64, model.vertices[*i].y as f64))
+                .collect();
+            let z = l.indices.iter().map(|i| model.vertices[*i].z).sum::<f32>()
+                / l.indices.len() as f32;
+            (polygon, z)
+        })
+        .collect();
+
+    let mut output_vertices = Vec::<FFIVector3>::new();
+    let mut output_indices = Vec::<usize>::new();
+    let mut offset_loops = 0_usize;
+    let mut collapsed = 0_usize;
+    for step in 1..=count {
+        let step_distance = distance * step as f64;
+        let mut survivors = 0_usize;
+        for (l, (polygon, z)) in loops.iter().zip(polygons.iter()) {
+            let offset = offset_polygon(polygon, step_distance, join);
+            if !is_valid_offset(polygon, l.signed_area, &offset, step_distance) {
+                collapsed += 1;
+                continue;
+            }
+            let first = output_vertices.len();
+            output_vertices.extend(
+                offset
+                    .iter()
+                    .map(|p| FFIVector3::new(p.x as f32, p.y as f32, *z)),
+            );
+            for i in 0..offset.len() {
+                output_indices.push(first + i);
+                output_indices.push(first + (i + 1) % offset.len());
+            }
+            survivors += 1;
+        }
+        offset_loops += survivors;
+        if survivors == 0 {
+            break;
+        }
+    }
+    if output_indices.is_empty() {
+        return Err(HallrError::NoData(
+            "Every loop collapsed at the offset distance".to_string(),
+        ));
+    }
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = return_config.insert("OFFSET_LOOPS".to_string(), offset_loops.to_string());
+    let _ = return_config.insert("OFFSET_COLLAPSED".to_string(), collapsed.to_string());
+    info!(
+        "offset_2d operation returning {} loops, {} collapsed",
+        offset_loops, collapsed
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType},
+    ffi::FFIVector3,
+    HallrError,
+};
+
+/// Returns the summed signed area of the loops in the line_chunks format
+fn area(vertices: &[FFIVector3], indices: &[usize]) -> f64 {
+    indices
+        .chunks_exact(2)
+        .map(|e| {
+            let (a, b) = (&vertices[e[0]], &vertices[e[1]]);
+            (a.x as f64 * b.y as f64 - a.y as f64 * b.x as f64) * 0.5
+        })
+        .sum()
+}
+
+#[test]
+fn test_offset_2d_1() -> Result<(), HallrError> {
+    // the joins of the outer corners
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "offset_2d".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("OFFSET_DISTANCE".to_string(), "1.0".to_string());
+    let _ = config.insert("OFFSET_JOIN".to_string(), "MITER".to_string());
+    let _ = config.insert("OFFSET_COUNT".to_string(), "1".to_string());
+
+    let model = fixtures::rectangle_outline([0.0, 0.0], [10.0, 10.0], 1.0);
+    for (join, expected_vertices, expected_area) in [
+        ("MITER", 4, 144.0),
+        ("BEVEL", 8, 142.0),
+        ("ROUND", 36, 143.1214),
+    ] {
+        let _ = config.insert("OFFSET_JOIN".to_string(), join.to_string());
+        let models = vec![model.as_model()];
+        let result = super::process_command(config.clone(), models)?;
+        assert_eq!(expected_vertices, result.0.len(), "{}", join);
+        assert_eq!(2 * expected_vertices, result.1.len(), "{}", join);
+        let area = area(&result.0, &result.1);
+        assert!((area - expected_area).abs() < 1e-3, "{} {}", join, area);
+        assert!(result.0.iter().all(|v| v.z == 1.0));
+        assert_eq!("1", result.3.get("OFFSET_LOOPS").unwrap());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_offset_2d_2() -> Result<(), HallrError> {
+    // repeated insets, the third one collapses
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "offset_2d".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("OFFSET_DISTANCE".to_string(), "-2.0".to_string());
+    let _ = config.insert("OFFSET_JOIN".to_string(), "ROUND".to_string());
+    let _ = config.insert("OFFSET_COUNT".to_string(), "5".to_string());
+
+    let model = fixtures::rectangle_outline([0.0, 0.0], [10.0, 10.0], 1.0);
+    let models = vec![model.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert_eq!("2", result.3.get("OFFSET_LOOPS").unwrap());
+    assert_eq!("1", result.3.get("OFFSET_COLLAPSED").unwrap());
+    // 6x6 and 2x2, the inner corners are mitered
+    assert_eq!(8, result.0.len());
+    let area = area(&result.0, &result.1);
+    assert!((area - 40.0).abs() < 1e-3, "{}", area);
+
+    // a single inset that collapses, and no offset at all
+    let _ = config.insert("OFFSET_COUNT".to_string(), "1".to_string());
+    let _ = config.insert("OFFSET_DISTANCE".to_string(), "-6.0".to_string());
+    let models = vec![model.as_model()];
+    assert!(super::process_command(config.clone(), models).is_err());
+    let _ = config.insert("OFFSET_DISTANCE".to_string(), "0.0".to_string());
+    let models = vec![model.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_offset_2d_3() -> Result<(), HallrError> {
+    // a square with a square hole, the hole shrinks when the region grows
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "offset_2d".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("OFFSET_DISTANCE".to_string(), "1.0".to_string());
+    let _ = config.insert("OFFSET_JOIN".to_string(), "MITER".to_string());
+    let _ = config.insert("OFFSET_COUNT".to_string(), "1".to_string());
+
+    let mut model = fixtures::rectangle_outline([0.0, 0.0], [10.0, 10.0], 1.0);
+    fixtures::add_rectangle_outline(&mut model, [3.0, 3.0], [7.0, 7.0], 1.0);
+    let models = vec![model.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert_eq!("2", result.3.get("OFFSET_LOOPS").unwrap());
+    let area = area(&result.0, &result.1);
+    assert!((area - 140.0).abs() < 1e-3, "{}", area);
+
+    // the hole collapses, the outer loop remains
+    let _ = config.insert("OFFSET_DISTANCE".to_string(), "2.5".to_string());
+    let models = vec![model.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!("1", result.3.get("OFFSET_LOOPS").unwrap());
+    assert_eq!("1", result.3.get("OFFSET_COLLAPSED").unwrap());
+    Ok(())
+}
@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{cmd_hatch, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use std::time;
+use vector_traits::glam::{dvec3, DVec3};
+
+#[cfg(test)]
+mod tests;
+
+/// The default time budget of the 2-opt improvement, in milliseconds
+const DEFAULT_TIME_BUDGET_MS: u64 = 1000;
+
+/// Returns the total travel (pen-up/rapid) distance between the polylines, in the given order
+pub(crate) fn travel_distance<T, F: Fn(&T) -> DVec3>(polylines: &[Vec<T>], position: F) -> f64 {
+    polylines
+        .windows(2)
+        .map(|w| position(w[0].last().unwrap()).distance(position(&w[1][0])))
+        .sum()
+}
+
+/// Orders the polylines with a greedy nearest neighbour search, so that the travel between them
+/// is minimized. If `allow_reverse` is set, the polylines may also be reversed.
+/// The first polyline is kept as the starting point.
+/// Returns the ordered polylines and the total travel distance.
+pub(crate) fn order_polylines<T, F: Fn(&T) -> DVec3>(
+    mut polylines: Vec<Vec<T>>,
+    position: F,
+    allow_reverse: bool,
+) -> (Vec<Vec<T>>, f64) {
+    if polylines.is_empty() {
+        return (polylines, 0.0);
+    }
+    let mut rv = Vec::<Vec<T>>::with_capacity(polylines.len());
+    rv.push(polylines.swap_remove(0));
+    let mut travel = 0.0;
+    while !polylines.is_empty() {
+        let pen = position(rv.last().unwrap().last().unwrap());
+        let (index, reverse, distance) = polylines
+            .iter()
+            .enumerate()
+            .flat_map(|(i, p)| {
+                [
+                    Some((i, false, pen.distance(position(&p[0])))),
+                    allow_reverse.then(|| (i, true, pen.distance(position(&p[p.len() - 1])))),
+                ]
+            })
+            .flatten()
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap())
+            .unwrap();
+        let mut next = polylines.swap_remove(index);
+        if reverse {
+            next.reverse();
+        }
+        travel += distance;
+        rv.push(next);
+    }
+    (rv, travel)
+}
+
+/// Improves the order of the polylines with 2-opt moves (reversing a run of polylines, and every
+/// polyline in it) until no improving move is found or the time budget is spent.
+/// The first polyline is kept as the starting point.
+/// Returns the total travel distance after the improvement.
+pub(crate) fn two_opt<T, F: Fn(&T) -> DVec3>(
+    polylines: &mut [Vec<T>],
+    position: F,
+    time_budget: time::Duration,
+) -> f64 {
+    let start_time = time::Instant::now();
+    let start = |p: &Vec<T>| position(&p[0]);
+    let end = |p: &Vec<T>| position(&p[p.len() - 1]);
+    let n = polylines.len();
+    let mut improved = true;
+    'outer: while improved {
+        improved = false;
+        for i in 1..n {
+            if start_time.elapsed() > time_budget {
+                break 'outer;
+            }
+            for j in i..n {
+                // the travel edges before position i and after position j are replaced
+                let before = end(&polylines[i - 1]);
+                let mut delta =
+                    before.distance(end(&polylines[j])) - before.distance(start(&polylines[i]));
+                if j + 1 < n {
+                    let after = start(&polylines[j + 1]);
+                    delta +=
+                        start(&polylines[i]).distance(after) - end(&polylines[j]).distance(after);
+                }
+                if delta < -f64::EPSILON {
+                    polylines[i..=j].reverse();
+                    for p in polylines[i..=j].iter_mut() {
+                        p.reverse();
+                    }
+                    improved = true;
+                }
+            }
+        }
+    }
+    travel_distance(polylines, position)
+}
+
+/// Run the optimize_path command
+/// Model 0 contains the paths (in the line_chunks format). The edges are joined into polylines,
+/// the polylines are then reordered (and reversed, unless `ALLOW_REVERSE=false`) to minimize
+/// the pen-up/rapid travel between them. The result is one continuous path in the line_windows
+/// format, the travel distances before and after are returned as `TRAVEL_BEFORE` and
+/// `TRAVEL_AFTER`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let allow_reverse = config.get_mandatory_parsed_option::<bool>("ALLOW_REVERSE", Some(true))?;
+    let time_budget = time::Duration::from_millis(
+        config
+            .get_mandatory_parsed_option::<u64>("TIME_BUDGET_MS", Some(DEFAULT_TIME_BUDGET_MS))?,
+    );
+
+    let model = &models[0];
+    let segments: Vec<(u64, u64)> = model
+        .indices
+        .chunks_exact(2)
+        .filter(|edge| edge[0] != edge[1])
+        .map(|edge| (edge[0] as u64, edge[1] as u64))
+        .collect();
+    let polylines = cmd_hatch::chain_segments(&segments);
+    let position = |i: &u64| {
+        let v = model.vertices[*i as usize];
+        dvec3(v.x as f64, v.y as f64, v.z as f64)
+    };
+    let travel_before = travel_distance(&polylines, position);
+    debug!(
+        "optimize_path: {} polylines, travel before:{}",
+        polylines.len(),
+        travel_before
+    );
+
+    let now = time::Instant::now();
+    let (mut polylines, mut travel_after) = order_polylines(polylines, position, allow_reverse);
+    if allow_reverse {
+        travel_after = two_opt(&mut polylines, position, time_budget);
+    }
+    debug!(
+        "optimize_path: travel after:{}, duration:{:?}",
+        travel_after,
+        now.elapsed()
+    );
+
+    let output_vertices: Vec<FFIVector3> = polylines
+        .iter()
+        .flatten()
+        .map(|i| model.vertices[*i as usize])
+        .collect();
+    let output_indices: Vec<usize> = (0..output_vertices.len()).collect();
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line_windows".to_string());
+    let _ = return_config.insert("TRAVEL_BEFORE".to_string(), travel_before.to_string());
+    let _ = return_config.insert("TRAVEL_AFTER".to_string(), travel_after.to_string());
+    info!(
+        "optimize_path operation returning {} vertices",
+        output_vertices.len()
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
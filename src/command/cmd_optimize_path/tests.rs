@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{ConfigType, OwnedModel},
+    HallrError,
+};
+use std::time;
+use vector_traits::glam::{dvec2, DVec2};
+
+#[test]
+fn test_order_polylines() {
+    let polylines = vec![
+        vec![dvec2(0.0, 0.0), dvec2(1.0, 0.0)],
+        vec![dvec2(0.0, 2.0), dvec2(1.0, 2.0)],
+        vec![dvec2(1.0, 1.0), dvec2(0.0, 1.0)],
+    ];
+    let (ordered, travel) = super::order_polylines(polylines, |p: &DVec2| p.extend(0.0), true);
+    assert_eq!(dvec2(1.0, 1.0), ordered[1][0]);
+    assert_eq!(dvec2(0.0, 2.0), ordered[2][0]);
+    assert!((travel - 2.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_two_opt() {
+    // the last two polylines are visited in the wrong direction
+    let mut polylines = vec![
+        vec![dvec2(0.0, 0.0), dvec2(1.0, 0.0)],
+        vec![dvec2(2.0, 0.0), dvec2(3.0, 0.0)],
+        vec![dvec2(5.0, 0.0), dvec2(4.0, 0.0)],
+    ];
+    let before = super::travel_distance(&polylines, |p: &DVec2| p.extend(0.0));
+    let after = super::two_opt(
+        &mut polylines,
+        |p: &DVec2| p.extend(0.0),
+        time::Duration::from_secs(1),
+    );
+    assert!((before - 3.0).abs() < 1e-9);
+    assert!((after - 2.0).abs() < 1e-9);
+    assert_eq!(dvec2(4.0, 0.0), polylines[2][0]);
+}
+
+#[test]
+fn test_optimize_path_1() -> Result<(), HallrError> {
+    // three separate edges, given in a bad order
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "optimize_path".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (1.0, 0.0, 0.0).into(),
+            (0.0, 2.0, 0.0).into(),
+            (1.0, 2.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (0.0, 1.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 2, 3, 4, 5],
+    };
+
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(6, result.0.len()); // vertices
+    assert_eq!(6, result.1.len()); // indices
+    let before: f64 = result.3.get("TRAVEL_BEFORE").unwrap().parse().unwrap();
+    let after: f64 = result.3.get("TRAVEL_AFTER").unwrap().parse().unwrap();
+    assert!(after < before);
+    assert!((after - 2.0).abs() < 1e-6);
+    Ok(())
+}
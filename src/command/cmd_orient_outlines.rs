@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{cmd_clip_curves, cmd_hatch, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use vector_traits::glam::{dvec2, DVec2};
+
+#[cfg(test)]
+mod tests;
+
+/// A closed loop of an outline, and its place in the nesting hierarchy
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct OutlineLoop {
+    /// The vertex indices of the loop, the first index is not repeated at the end
+    pub(crate) indices: Vec<usize>,
+    /// Positive for counter-clockwise loops
+    pub(crate) signed_area: f64,
+    /// The smallest loop containing this loop
+    pub(crate) parent: Option<usize>,
+    /// The number of loops containing this loop
+    pub(crate) depth: usize,
+}
+
+impl OutlineLoop {
+    /// Loops inside of an odd number of other loops are holes
+    pub(crate) fn is_hole(&self) -> bool {
+        self.depth % 2 == 1
+    }
+}
+
+/// Returns the signed area of the polygon, positive for counter-clockwise polygons
+pub(crate) fn signed_area(polygon: &[DVec2]) -> f64 {
+    let mut rv = 0.0;
+    for (i, a) in polygon.iter().enumerate() {
+        rv += a.perp_dot(polygon[(i + 1) % polygon.len()]);
+    }
+    rv * 0.5
+}
+
+/// Split the line_chunks edges into closed loops (in the XY plane), and find their nesting: the
+/// parent of a loop is the smallest loop containing it.
+pub(crate) fn classify_loops(
+    vertices: &[FFIVector3],
+    indices: &[usize],
+) -> Result<Vec<OutlineLoop>, HallrError> {
+    let segments: Vec<(u64, u64)> = indices
+        .chunks_exact(2)
+        .filter(|edge| edge[0] != edge[1])
+        .map(|edge| (edge[0] as u64, edge[1] as u64))
+        .collect();
+    let point = |i: usize| dvec2(vertices[i].x as f64, vertices[i].y as f64);
+
+    let mut loops = Vec::<OutlineLoop>::new();
+    for chain in cmd_hatch::chain_segments(&segments) {
+        if chain.len() < 4 || chain.first() != chain.last() {
+            return Err(HallrError::InvalidInputData(format!(
+                "The outline must consist of closed loops, found an open chain starting at vertex {}",
+                chain[0]
+            )));
+        }
+        let indices: Vec<usize> = chain[..chain.len() - 1]
+            .iter()
+            .map(|i| *i as usize)
+            .collect();
+        let polygon: Vec<DVec2> = indices.iter().map(|i| point(*i)).collect();
+        loops.push(OutlineLoop {
+            signed_area: signed_area(&polygon),
+            indices,
+            parent: None,
+            depth: 0,
+        });
+    }
+
+    let edges: Vec<Vec<(DVec2, DVec2)>> = loops
+        .iter()
+        .map(|l| {
+            (0..l.indices.len())
+                .map(|i| {
+                    (
+                        point(l.indices[i]),
+                        point(l.indices[(i + 1) % l.indices.len()]),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    for i in 0..loops.len() {
+        let p = point(loops[i].indices[0]);
+        let containing: Vec<usize> = (0..loops.len())
+            .filter(|j| *j != i && cmd_clip_curves::is_inside_region(p, &edges[*j]))
+            .collect();
+        loops[i].depth = containing.len();
+        loops[i].parent = containing.into_iter().min_by(|a, b| {
+            loops[*a]
+                .signed_area
+                .abs()
+                .total_cmp(&loops[*b].signed_area.abs())
+        });
+    }
+    Ok(loops)
+}
+
+/// Reverse the loops that are not oriented as required: outer loops counter-clockwise, holes
+/// clockwise. Returns the number of reversed loops.
+pub(crate) fn normalize_winding(loops: &mut [OutlineLoop]) -> usize {
+    let mut reversed = 0;
+    for l in loops.iter_mut() {
+        if l.is_hole() == (l.signed_area > 0.0) {
+            l.indices.reverse();
+            l.signed_area = -l.signed_area;
+            reversed += 1;
+        }
+    }
+    reversed
+}
+
+/// Run the orient_outlines command
+/// Model 0 is one or more closed loops in the line_chunks format, in the XY plane. The loops are
+/// classified by their nesting: loops inside of an even number of other loops are outer loops,
+/// the others are holes. The loops are returned in the line_chunks format (with the input
+/// vertices), outer loops counter-clockwise and holes clockwise.
+/// The number of loops and holes are returned as `OUTLINE_LOOPS` and `OUTLINE_HOLES`, the number
+/// of reversed loops as `OUTLINE_REVERSED`, and the parent of every loop (in the output order,
+/// -1 for top level loops) as `OUTLINE_PARENTS`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let model = &models[0];
+    if let Some(index) = model.indices.iter().find(|i| **i >= model.vertices.len()) {
+        return Err(HallrError::InvalidInputData(format!(
+            "The index {} is out of bounds",
+            index
+        )));
+    }
+    let mut loops = classify_loops(model.vertices, model.indices)?;
+    if loops.is_empty() {
+        return Err(HallrError::NoData("The outline has no edges".to_string()));
+    }
+    let reversed = normalize_winding(&mut loops);
+
+    let mut output_indices = Vec::<usize>::with_capacity(model.indices.len());
+    for l in loops.iter() {
+        for i in 0..l.indices.len() {
+            output_indices.push(l.indices[i]);
+            output_indices.push(l.indices[(i + 1) % l.indices.len()]);
+        }
+    }
+    let holes = loops.iter().filter(|l| l.is_hole()).count();
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = return_config.insert("OUTLINE_LOOPS".to_string(), loops.len().to_string());
+    let _ = return_config.insert("OUTLINE_HOLES".to_string(), holes.to_string());
+    let _ = return_config.insert("OUTLINE_REVERSED".to_string(), reversed.to_string());
+    let _ = return_config.insert(
+        "OUTLINE_PARENTS".to_string(),
+        loops
+            .iter()
+            .map(|l| l.parent.map_or_else(|| "-1".to_string(), |p| p.to_string()))
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    info!(
+        "orient_outlines operation returning {} loops, {} holes, {} reversed",
+        loops.len(),
+        holes,
+        reversed
+    );
+    Ok((
+        model.vertices.to_vec(),
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{ConfigType, OwnedModel},
+    HallrError,
+};
+use vector_traits::glam::{dvec2, DVec2};
+
+/// Three nested squares: the outer square is clockwise, the hole and the island inside of the
+/// hole are counter-clockwise
+fn nested_squares() -> OwnedModel {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for (half_side, clockwise) in [(3.0, true), (2.0, false), (1.0, false)] {
+        let first = vertices.len();
+        let mut corners = vec![
+            (-half_side, -half_side),
+            (half_side, -half_side),
+            (half_side, half_side),
+            (-half_side, half_side),
+        ];
+        if clockwise {
+            corners.reverse();
+        }
+        for (i, (x, y)) in corners.into_iter().enumerate() {
+            vertices.push((x, y, 0.0).into());
+            indices.push(first + i);
+            indices.push(first + (i + 1) % 4);
+        }
+    }
+    OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices,
+        indices,
+    }
+}
+
+#[test]
+fn test_orient_outlines_1() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "orient_outlines".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+
+    let owned_model_0 = nested_squares();
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(12, result.0.len()); // vertices
+    assert_eq!(24, result.1.len()); // indices
+    assert_eq!("3", result.3.get("OUTLINE_LOOPS").unwrap());
+    assert_eq!("1", result.3.get("OUTLINE_HOLES").unwrap());
+    assert_eq!("2", result.3.get("OUTLINE_REVERSED").unwrap());
+    assert_eq!("-1,0,1", result.3.get("OUTLINE_PARENTS").unwrap());
+
+    // outer, hole, island
+    for (l, expect_ccw) in result.1.chunks_exact(8).zip([true, false, true]) {
+        let polygon: Vec<DVec2> = l
+            .chunks_exact(2)
+            .map(|e| dvec2(result.0[e[0]].x as f64, result.0[e[0]].y as f64))
+            .collect();
+        assert_eq!(expect_ccw, super::signed_area(&polygon) > 0.0);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_orient_outlines_2() -> Result<(), HallrError> {
+    // an open chain can not be oriented
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "orient_outlines".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (1.0, 0.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2],
+    };
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
@@ -0,0 +1,402 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{
+    cmd_clip_curves,
+    cmd_hatch::{self, DistanceField},
+    cmd_orient_outlines::{self, signed_area},
+    ConfigType, Model, Options,
+};
+use crate::{ffi::FFIVector3, HallrError};
+use vector_traits::glam::{dvec2, DVec2};
+
+#[cfg(test)]
+mod tests;
+
+/// The largest number of depth levels
+const MAX_LEVELS: usize = 1000;
+/// A link between two passes may be at most this many stepovers long, longer moves are retracts
+const MAX_LINK_STEPOVERS: f64 = 2.0;
+/// Distance tolerance of the link tests, as a fraction of the longest link
+const LINK_TOLERANCE: f64 = 1e-6;
+
+/// One continuous cut of a clearing level
+#[derive(Debug, Clone)]
+struct Pass {
+    points: Vec<DVec2>,
+    /// Closed passes return to their first point, which is not repeated in `points`
+    closed: bool,
+}
+
+impl Pass {
+    /// A closed pass, cut counter-clockwise. A repeated end point is removed.
+    fn closed(mut points: Vec<DVec2>) -> Self {
+        if points.len() > 1 && points.first() == points.last() {
+            let _ = points.pop();
+        }
+        if signed_area(&points) < 0.0 {
+            points.reverse();
+        }
+        Self {
+            points,
+            closed: true,
+        }
+    }
+
+    fn open(points: Vec<DVec2>) -> Self {
+        Self {
+            points,
+            closed: false,
+        }
+    }
+
+    fn start(&self) -> DVec2 {
+        self.points[0]
+    }
+
+    /// The end of the cut, closed passes end where they started
+    fn end(&self) -> DVec2 {
+        if self.closed {
+            self.points[0]
+        } else {
+            *self.points.last().unwrap()
+        }
+    }
+
+    /// Returns the distance from `p` to the closest possible start of the pass, and where that
+    /// start is: the vertex of a closed pass, or 0 / 1 for the start / end of an open one
+    fn closest_start(&self, p: DVec2) -> (f64, usize) {
+        if self.closed {
+            self.points
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (v.distance_squared(p), i))
+                .fold((f64::MAX, 0), |a, b| if b.0 < a.0 { b } else { a })
+        } else {
+            let first = self.points[0].distance_squared(p);
+            let last = self.points.last().unwrap().distance_squared(p);
+            if last < first {
+                (last, 1)
+            } else {
+                (first, 0)
+            }
+        }
+    }
+
+    /// Make `start` (as returned by `closest_start()`) the start of the pass
+    fn set_start(&mut self, start: usize) {
+        if self.closed {
+            self.points.rotate_left(start);
+        } else if start == 1 {
+            self.points.reverse();
+        }
+    }
+
+    /// The points of the cut, closed passes repeat the first point at the end
+    fn cut(&self) -> impl Iterator<Item = &DVec2> {
+        self.points
+            .iter()
+            .chain(self.closed.then_some(&self.points[0]))
+    }
+}
+
+/// Order the passes with a greedy nearest neighbour search, starting at `from`. Closed passes may
+/// start at any of their vertices, open passes may be reversed.
+fn order_passes(mut passes: Vec<Pass>, from: DVec2) -> Vec<Pass> {
+    let mut rv = Vec::<Pass>::with_capacity(passes.len());
+    let mut end = from;
+    while !passes.is_empty() {
+        let (index, (_, start)) = passes
+            .iter()
+            .map(|pass| pass.closest_start(end))
+            .enumerate()
+            .fold(
+                (0, (f64::MAX, 0)),
+                |a, b| if b.1 .0 < a.1 .0 { b } else { a },
+            );
+        let mut pass = passes.swap_remove(index);
+        pass.set_start(start);
+        end = pass.end();
+        rv.push(pass);
+    }
+    rv
+}
+
+/// Returns true if the straight move from `a` to `b` can be cut without retracting: it is short,
+/// it does not cross the outline of the cleared region and it is not outside of it
+fn is_valid_link(a: DVec2, b: DVec2, clear_region: &[(DVec2, DVec2)], max_link: f64) -> bool {
+    let length = a.distance(b);
+    if length > max_link {
+        return false;
+    }
+    let tolerance = max_link * LINK_TOLERANCE;
+    if length <= tolerance {
+        return true;
+    }
+    let ab = b - a;
+    let crosses = clear_region.iter().any(|(p, q)| {
+        let s = *q - *p;
+        let denominator = ab.perp_dot(s);
+        if denominator.abs() < f64::EPSILON {
+            // parallel, a link along the outline is fine
+            return false;
+        }
+        let pa = *p - a;
+        let t = pa.perp_dot(s) / denominator;
+        let u = pa.perp_dot(ab) / denominator;
+        t * length > tolerance && (1.0 - t) * length > tolerance && (0.0..=1.0).contains(&u)
+    });
+    if crosses {
+        return false;
+    }
+    let middle = (a + b) * 0.5;
+    cmd_clip_curves::is_inside_region(middle, clear_region)
+        || clear_region
+            .iter()
+            .any(|(p, q)| cmd_hatch::distance_to_segment(middle, *p, *q) <= tolerance)
+}
+
+/// Join the ordered passes into continuous cuts, a new cut is started wherever the link to the
+/// next pass is not valid
+fn link_passes(passes: &[Pass], clear_region: &[(DVec2, DVec2)], max_link: f64) -> Vec<Vec<DVec2>> {
+    let mut rv = Vec::<Vec<DVec2>>::new();
+    for pass in passes.iter() {
+        match rv.last_mut() {
+            Some(cut)
+                if is_valid_link(*cut.last().unwrap(), pass.start(), clear_region, max_link) =>
+            {
+                cut.extend(pass.cut().copied())
+            }
+            _ => rv.push(pass.cut().copied().collect()),
+        }
+    }
+    rv
+}
+
+/// Returns the edges of closed loops, as a region
+fn loops_to_region(loops: &[Pass]) -> Vec<(DVec2, DVec2)> {
+    loops
+        .iter()
+        .flat_map(|l| {
+            (0..l.points.len()).map(move |i| (l.points[i], l.points[(i + 1) % l.points.len()]))
+        })
+        .collect()
+}
+
+/// Returns the Z coordinates of the clearing levels, from the top down
+fn depth_levels(top_z: f64, depth: f64, depth_per_pass: f64) -> Result<Vec<f64>, HallrError> {
+    let count = (depth / depth_per_pass - 1e-9).ceil().max(1.0);
+    if count > MAX_LEVELS as f64 {
+        return Err(HallrError::InvalidParameter(format!(
+            "POCKET_DEPTH_PER_PASS is too small, it would generate more than {} levels",
+            MAX_LEVELS
+        )));
+    }
+    Ok((1..=count as usize)
+        .map(|level| top_z - (level as f64 * depth_per_pass).min(depth))
+        .collect())
+}
+
+/// Reads a positive length option
+fn positive_option(
+    config: &ConfigType,
+    key: &str,
+    default: Option<f64>,
+) -> Result<f64, HallrError> {
+    let value = config.get_mandatory_parsed_option::<f64>(key, default)?;
+    if !value.is_finite() || value <= 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "{} must be a positive number :({})",
+            key, value
+        )));
+    }
+    Ok(value)
+}
+
+/// Run the pocket command
+/// Model 0 is the pocket boundary and its islands as closed loops in the line_chunks format, in
+/// the XY plane at the top of the pocket (the Z of the first vertex). The area inside of the
+/// boundary and outside of the islands is cleared with a tool of `POCKET_TOOL_RADIUS` (default 0),
+/// the tool center keeps that distance from the outline.
+/// `POCKET_PATTERN=SPIRAL` (default) cuts contour parallel loops `POCKET_STEPOVER` apart, from the
+/// outline inwards, and links each loop to the next one where the move stays inside of the pocket.
+/// `POCKET_PATTERN=ZIGZAG` cuts parallel lines at `POCKET_ANGLE` degrees, linked into zig-zags,
+/// followed by one contour pass along the outline.
+/// The pattern is repeated at every `POCKET_DEPTH_PER_PASS` (default the full depth) down to
+/// `POCKET_DEPTH` below the top. The result is one continuous toolpath in the line format,
+/// retracting to `POCKET_RETURN_Z` (default one stepover above the top) between the cuts.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let stepover = positive_option(&config, "POCKET_STEPOVER", None)?;
+    let depth = positive_option(&config, "POCKET_DEPTH", None)?;
+    let depth_per_pass = positive_option(&config, "POCKET_DEPTH_PER_PASS", Some(depth))?;
+    let tool_radius = config.get_mandatory_parsed_option::<f64>("POCKET_TOOL_RADIUS", Some(0.0))?;
+    if !tool_radius.is_finite() || tool_radius < 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "POCKET_TOOL_RADIUS must not be negative :({})",
+            tool_radius
+        )));
+    }
+    let angle = config
+        .get_mandatory_parsed_option::<f64>("POCKET_ANGLE", Some(0.0))?
+        .to_radians();
+    let zigzag = match config
+        .get_mandatory_parsed_option::<String>("POCKET_PATTERN", Some("SPIRAL".to_string()))?
+        .as_str()
+    {
+        "SPIRAL" => false,
+        "ZIGZAG" => true,
+        pattern => {
+            return Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"POCKET_PATTERN\" parameter",
+                pattern
+            )))
+        }
+    };
+
+    let model = &models[0];
+    if let Some(index) = model.indices.iter().find(|i| **i >= model.vertices.len()) {
+        return Err(HallrError::InvalidInputData(format!(
+            "The index {} is out of bounds",
+            index
+        )));
+    }
+    let top_z = model.vertices.first().map_or(0.0, |v| v.z as f64);
+    let return_z =
+        config.get_mandatory_parsed_option::<f64>("POCKET_RETURN_Z", Some(top_z + stepover))?;
+    if return_z <= top_z {
+        return Err(HallrError::InvalidParameter(format!(
+            "POCKET_RETURN_Z must be above the top of the pocket ({}) :({})",
+            top_z, return_z
+        )));
+    }
+    let levels = depth_levels(top_z, depth, depth_per_pass)?;
+    let region = cmd_clip_curves::parse_region(model)?;
+
+    // the distance field is only needed for the inset outline and the spiral loops
+    let field =
+        (tool_radius > 0.0 || !zigzag).then(|| DistanceField::new(&region, stepover * 0.25, 0.0));
+    // the outline of the area the tool center may reach
+    let boundary_loops: Vec<Vec<DVec2>> = match field.as_ref() {
+        Some(field) if tool_radius > 0.0 => field.iso_lines(tool_radius),
+        _ => cmd_orient_outlines::classify_loops(model.vertices, model.indices)?
+            .into_iter()
+            .map(|l| {
+                l.indices
+                    .iter()
+                    .map(|i| dvec2(model.vertices[*i].x as f64, model.vertices[*i].y as f64))
+                    .collect()
+            })
+            .collect(),
+    };
+    let boundary: Vec<Pass> = boundary_loops
+        .into_iter()
+        .filter(|points| points.len() > 2)
+        .map(Pass::closed)
+        .collect();
+    if boundary.is_empty() {
+        return Err(HallrError::NoData(
+            "The tool does not fit inside of the pocket".to_string(),
+        ));
+    }
+    let clear_region = loops_to_region(&boundary);
+
+    let passes = match field {
+        Some(field) if !zigzag => {
+            let mut loops = Vec::<Pass>::new();
+            let max_distance = field.max_value();
+            let mut level = tool_radius + stepover;
+            while level < max_distance {
+                loops.extend(
+                    field
+                        .iso_lines(level)
+                        .into_iter()
+                        .filter(|points| points.len() > 2)
+                        .map(Pass::closed),
+                );
+                level += stepover;
+            }
+            // from the outline inwards
+            let from = boundary[0].start();
+            let mut passes = order_passes(boundary, from);
+            let from = passes.last().unwrap().end();
+            passes.extend(order_passes(loops, from));
+            passes
+        }
+        _ => {
+            let lines: Vec<Pass> = cmd_hatch::parallel_hatch(&clear_region, stepover, angle)?
+                .into_iter()
+                .map(Pass::open)
+                .collect();
+            let mut passes = match lines.first() {
+                Some(line) => {
+                    let from = line.start();
+                    order_passes(lines, from)
+                }
+                None => Vec::new(),
+            };
+            // the contour pass removes the scallops left by the lines
+            let from = passes.last().map_or(boundary[0].start(), |pass| pass.end());
+            passes.extend(order_passes(boundary, from));
+            passes
+        }
+    };
+    let cuts = link_passes(&passes, &clear_region, stepover * MAX_LINK_STEPOVERS);
+
+    // every level repeats the same cuts, with retracts in between
+    let mut output_vertices = Vec::<FFIVector3>::new();
+    let mut cut_length = 0.0;
+    for z in levels.iter() {
+        for cut in cuts.iter() {
+            output_vertices.push(FFIVector3::new(
+                cut[0].x as f32,
+                cut[0].y as f32,
+                return_z as f32,
+            ));
+            output_vertices.extend(
+                cut.iter()
+                    .map(|p| FFIVector3::new(p.x as f32, p.y as f32, *z as f32)),
+            );
+            let last = cut.last().unwrap();
+            output_vertices.push(FFIVector3::new(
+                last.x as f32,
+                last.y as f32,
+                return_z as f32,
+            ));
+            cut_length += cut.windows(2).map(|w| w[0].distance(w[1])).sum::<f64>();
+        }
+    }
+    let output_indices: Vec<usize> = (0..output_vertices.len()).collect();
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line".to_string());
+    let _ = return_config.insert("POCKET_LEVELS".to_string(), levels.len().to_string());
+    let _ = return_config.insert("POCKET_CUTS_PER_LEVEL".to_string(), cuts.len().to_string());
+    let _ = return_config.insert("POCKET_CUT_LENGTH".to_string(), cut_length.to_string());
+    info!(
+        "pocket operation returning {} vertices, {} levels, {} cuts per level",
+        output_vertices.len(),
+        levels.len(),
+        cuts.len()
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,123 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType},
+    HallrError,
+};
+
+#[test]
+fn test_pocket_zigzag() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "pocket".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("POCKET_STEPOVER".to_string(), "1.0".to_string());
+    let _ = config.insert("POCKET_DEPTH".to_string(), "2.0".to_string());
+    let _ = config.insert("POCKET_DEPTH_PER_PASS".to_string(), "1.0".to_string());
+    let _ = config.insert("POCKET_PATTERN".to_string(), "ZIGZAG".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [10.0, 10.0], 0.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!("line", result.3.get("mesh.format").unwrap());
+    assert_eq!("2", result.3.get("POCKET_LEVELS").unwrap());
+    // ten lines linked into one zig-zag, and the contour pass
+    assert_eq!("1", result.3.get("POCKET_CUTS_PER_LEVEL").unwrap());
+    let cut_length: f64 = result.3.get("POCKET_CUT_LENGTH").unwrap().parse().unwrap();
+    assert!((cut_length - 2.0 * 149.5).abs() < 1e-6, "{}", cut_length);
+    assert_eq!((0..result.0.len()).collect::<Vec<_>>(), result.1);
+    // every level starts and ends at the return height, one stepover above the top
+    assert_eq!(1.0, result.0[0].z);
+    assert_eq!(1.0, result.0.last().unwrap().z);
+    assert!(result.0.iter().all(|v| [1.0, -1.0, -2.0].contains(&v.z)));
+    assert_eq!(4, result.0.iter().filter(|v| v.z == 1.0).count());
+    Ok(())
+}
+
+#[test]
+fn test_pocket_spiral() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "pocket".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("POCKET_STEPOVER".to_string(), "1.0".to_string());
+    let _ = config.insert("POCKET_DEPTH".to_string(), "2.0".to_string());
+    let _ = config.insert("POCKET_DEPTH_PER_PASS".to_string(), "1.0".to_string());
+    let _ = config.insert("POCKET_PATTERN".to_string(), "SPIRAL".to_string());
+    let _ = config.insert("POCKET_TOOL_RADIUS".to_string(), "0.5".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [10.0, 10.0], 0.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!("2", result.3.get("POCKET_LEVELS").unwrap());
+    // the nested loops are linked into one cut
+    assert_eq!("1", result.3.get("POCKET_CUTS_PER_LEVEL").unwrap());
+    // the tool center keeps the tool radius from the outline
+    assert!(result
+        .0
+        .iter()
+        .filter(|v| v.z < 0.0)
+        .all(|v| v.x > 0.45 && v.x < 9.55 && v.y > 0.45 && v.y < 9.55));
+    // the last loops are close to the center
+    assert!(result
+        .0
+        .iter()
+        .any(|v| v.z < 0.0 && (v.x - 5.0).abs() < 1.0 && (v.y - 5.0).abs() < 1.0));
+    Ok(())
+}
+
+#[test]
+fn test_pocket_island() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "pocket".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("POCKET_STEPOVER".to_string(), "1.0".to_string());
+    let _ = config.insert("POCKET_DEPTH".to_string(), "2.0".to_string());
+    let _ = config.insert("POCKET_DEPTH_PER_PASS".to_string(), "1.0".to_string());
+    let _ = config.insert("POCKET_PATTERN".to_string(), "ZIGZAG".to_string());
+
+    let mut owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [10.0, 10.0], 0.0);
+    fixtures::add_rectangle_outline(&mut owned_model_0, [4.0, 4.0], [6.0, 6.0], 0.0);
+    for pattern in ["ZIGZAG", "SPIRAL"] {
+        let _ = config.insert("POCKET_PATTERN".to_string(), pattern.to_string());
+        let models = vec![owned_model_0.as_model()];
+        let result = super::process_command(config.clone(), models)?;
+        // no cut enters the island
+        for edge in result.0.windows(2).filter(|w| w[0].z < 0.0 && w[1].z < 0.0) {
+            for t in [0.0, 0.25, 0.5, 0.75, 1.0] {
+                let x = edge[0].x + (edge[1].x - edge[0].x) * t;
+                let y = edge[0].y + (edge[1].y - edge[0].y) * t;
+                assert!(
+                    !(x > 4.01 && x < 5.99 && y > 4.01 && y < 5.99),
+                    "{} ({},{})",
+                    pattern,
+                    x,
+                    y
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_pocket_errors() {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "pocket".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("POCKET_STEPOVER".to_string(), "1.0".to_string());
+    let _ = config.insert("POCKET_DEPTH".to_string(), "2.0".to_string());
+    let _ = config.insert("POCKET_DEPTH_PER_PASS".to_string(), "1.0".to_string());
+    let _ = config.insert("POCKET_PATTERN".to_string(), "ZIGZAG".to_string());
+
+    let owned_model_0 = fixtures::rectangle_outline([0.0, 0.0], [10.0, 10.0], 0.0);
+    let mut stepover_config = config.clone();
+    let _ = stepover_config.insert("POCKET_STEPOVER".to_string(), "0".to_string());
+    assert!(super::process_command(stepover_config, vec![owned_model_0.as_model()]).is_err());
+    // the tool does not fit in the pocket
+    let _ = config.insert("POCKET_TOOL_RADIUS".to_string(), "6".to_string());
+    assert!(matches!(
+        super::process_command(config, vec![owned_model_0.as_model()]),
+        Err(HallrError::NoData(_))
+    ));
+}
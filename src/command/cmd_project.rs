@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{attributes::Attributes, cmd_solidify, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, utils::bvh::Bvh, HallrError};
+use rayon::prelude::*;
+use vector_traits::glam::{dvec3, DVec3};
+
+#[cfg(test)]
+mod tests;
+
+/// The per-vertex attribute holding 1.0 for projected vertices and 0.0 for misses
+pub(crate) const PROJECT_HIT_KEY: &str = "PROJECT_HIT";
+
+/// Parse the `PROJECT_DIRECTION` as a normalized vector, default straight down
+fn parse_direction(config: &ConfigType) -> Result<DVec3, HallrError> {
+    let Some(value) = config.get("PROJECT_DIRECTION") else {
+        return Ok(DVec3::NEG_Z);
+    };
+    let components = value
+        .split(',')
+        .map(|c| c.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            HallrError::InvalidParameter(format!("Could not parse PROJECT_DIRECTION: {}", e))
+        })?;
+    let direction = match components[..] {
+        [x, y, z] => dvec3(x, y, z).normalize_or_zero(),
+        _ => DVec3::ZERO,
+    };
+    if direction == DVec3::ZERO || !direction.is_finite() {
+        return Err(HallrError::InvalidParameter(format!(
+            "PROJECT_DIRECTION must be three comma separated numbers, not all zero :({})",
+            value
+        )));
+    }
+    Ok(direction)
+}
+
+/// Run the project command
+/// The vertices of model 0 are projected onto the triangulated mesh of model 1, along
+/// `PROJECT_DIRECTION` (three comma separated numbers, default `0,0,-1`), or with
+/// `PROJECT_ALONG_NORMALS=true` against the vertex normals of model 0, which must then be
+/// triangulated. With `PROJECT_BOTH_WAYS=true` the closest hit in either direction is used. Hits
+/// further away than `PROJECT_MAX_DISTANCE` are ignored, and the projected vertices are moved back
+/// `PROJECT_OFFSET` along the ray (default 0). Vertices without a hit are left in place.
+/// Model 0 is returned with the projected vertices, the hits are flagged in the `PROJECT_HIT`
+/// attribute and counted in `PROJECT_HITS` and `PROJECT_MISSES`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+    output_attributes: &mut Attributes,
+) -> Result<super::CommandResult, HallrError> {
+    if models.len() < 2 {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires two input models: the projected model and the target mesh"
+                .to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    let along_normals =
+        config.get_mandatory_parsed_option::<bool>("PROJECT_ALONG_NORMALS", Some(false))?;
+    if along_normals && mesh_format.ne("triangulated") {
+        return Err(HallrError::InvalidInputData(
+            "PROJECT_ALONG_NORMALS requires model 0 in the 'triangulated' format".to_string(),
+        ));
+    }
+    let both_ways = config.get_mandatory_parsed_option::<bool>("PROJECT_BOTH_WAYS", Some(false))?;
+    let max_distance = config
+        .get_parsed_option::<f64>("PROJECT_MAX_DISTANCE")?
+        .unwrap_or(f64::MAX);
+    let offset = config.get_mandatory_parsed_option::<f64>("PROJECT_OFFSET", Some(0.0))?;
+    if max_distance.is_nan() || max_distance <= 0.0 || !offset.is_finite() {
+        return Err(HallrError::InvalidParameter(format!(
+            "PROJECT_MAX_DISTANCE must be positive and PROJECT_OFFSET finite :({},{})",
+            max_distance, offset
+        )));
+    }
+    let direction = parse_direction(&config)?;
+
+    let model = &models[0];
+    let target = &models[1];
+    if target.indices.len() % 3 != 0 || target.indices.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "The target mesh must be triangulated".to_string(),
+        ));
+    }
+    let to_dvec3 = |v: &FFIVector3| dvec3(v.x as f64, v.y as f64, v.z as f64);
+    let vertices: Vec<DVec3> = model.vertices.iter().map(to_dvec3).collect();
+    let directions: Vec<DVec3> = if along_normals {
+        cmd_solidify::vertex_normals(&vertices, model.indices)
+            .into_iter()
+            .map(|n| -n)
+            .collect()
+    } else {
+        vec![direction; vertices.len()]
+    };
+    let bvh = Bvh::new(
+        target
+            .indices
+            .chunks_exact(3)
+            .map(|t| {
+                [
+                    to_dvec3(&target.vertices[t[0]]),
+                    to_dvec3(&target.vertices[t[1]]),
+                    to_dvec3(&target.vertices[t[2]]),
+                ]
+            })
+            .collect(),
+    );
+
+    // the distance to the closest hit of every vertex, negative if behind it
+    let hits: Vec<Option<f64>> = vertices
+        .par_iter()
+        .zip(directions.par_iter())
+        .map(|(v, d)| {
+            if *d == DVec3::ZERO {
+                return None;
+            }
+            let forward = bvh.raycast(*v, *d, max_distance);
+            let backward = if both_ways {
+                bvh.raycast(*v, -*d, max_distance).map(|hit| -hit)
+            } else {
+                None
+            };
+            match (forward, backward) {
+                (Some(f), Some(b)) => Some(if f <= -b { f } else { b }),
+                (f, b) => f.or(b),
+            }
+        })
+        .collect();
+
+    let mut output_vertices = Vec::<FFIVector3>::with_capacity(vertices.len());
+    let mut flags = Vec::<f32>::with_capacity(vertices.len());
+    for ((v, d), hit) in model
+        .vertices
+        .iter()
+        .zip(directions.iter())
+        .zip(hits.iter())
+    {
+        match hit {
+            Some(distance) => {
+                // the offset is kept on the side the vertex came from
+                let p = to_dvec3(v) + *d * (*distance - offset.copysign(*distance));
+                output_vertices.push(FFIVector3::new(p.x as f32, p.y as f32, p.z as f32));
+                flags.push(1.0);
+            }
+            None => {
+                output_vertices.push(*v);
+                flags.push(0.0);
+            }
+        }
+    }
+    let hit_count = hits.iter().filter(|h| h.is_some()).count();
+    info!(
+        "project: {} of {} vertices projected",
+        hit_count,
+        vertices.len()
+    );
+    let _ = output_attributes.insert(PROJECT_HIT_KEY.to_string(), flags);
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), mesh_format.to_string());
+    let _ = return_config.insert("PROJECT_HITS".to_string(), hit_count.to_string());
+    let _ = return_config.insert(
+        "PROJECT_MISSES".to_string(),
+        (vertices.len() - hit_count).to_string(),
+    );
+    Ok((
+        output_vertices,
+        model.indices.to_vec(),
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
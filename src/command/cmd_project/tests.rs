@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{attributes::Attributes, fixtures, ConfigType, OwnedModel},
+    HallrError,
+};
+
+#[test]
+fn test_project_direction() -> Result<(), HallrError> {
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.25, 0.5, 2.0).into(),
+            (0.5, 0.5, 1.0).into(),
+            (2.0, 0.5, 1.0).into(),
+            (0.5, 0.25, -1.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3],
+    };
+    let owned_model_1 = fixtures::rectangle_faces([0.0, 0.0], [1.0, 1.0], 0.0);
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "project".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("PROJECT_OFFSET".to_string(), "0.1".to_string());
+    let mut attributes = Attributes::new();
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config.clone(), models, &mut attributes)?;
+    assert_eq!("line_chunks", result.3.get("mesh.format").unwrap());
+    assert_eq!(owned_model_0.indices, result.1);
+    // straight down: the vertex below the square and the one beside it are not moved
+    assert_eq!("2", result.3.get("PROJECT_HITS").unwrap());
+    assert_eq!("2", result.3.get("PROJECT_MISSES").unwrap());
+    assert_eq!(
+        &vec![1.0, 1.0, 0.0, 0.0],
+        attributes.get(super::PROJECT_HIT_KEY).unwrap()
+    );
+    let z: Vec<f32> = result.0.iter().map(|v| v.z).collect();
+    assert!(
+        (z[0] - 0.1).abs() < 1e-6 && (z[1] - 0.1).abs() < 1e-6,
+        "{:?}",
+        z
+    );
+    assert_eq!(vec![1.0, -1.0], z[2..]);
+
+    // both ways, the offset keeps the side of the vertex
+    let _ = config.insert("PROJECT_BOTH_WAYS".to_string(), "true".to_string());
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config.clone(), models, &mut attributes)?;
+    assert_eq!("3", result.3.get("PROJECT_HITS").unwrap());
+    assert!((result.0[3].z + 0.1).abs() < 1e-6, "{:?}", result.0[3]);
+
+    // sideways, with a maximum distance
+    let _ = config.insert("PROJECT_DIRECTION".to_string(), "-1,0,0".to_string());
+    let _ = config.insert("PROJECT_OFFSET".to_string(), "0".to_string());
+    let _ = config.insert("PROJECT_MAX_DISTANCE".to_string(), "0.6".to_string());
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.5, 0.25, -0.25).into(),
+            (0.5, 0.5, -1.0).into(),
+            (1.0, 0.25, -0.25).into(),
+        ],
+        indices: vec![0, 1, 1, 2],
+    };
+    let owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (0.0, 1.0, 0.0).into(),
+            (0.0, 0.0, -1.0).into(),
+        ],
+        indices: vec![0, 1, 2],
+    };
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config, models, &mut attributes)?;
+    assert_eq!("1", result.3.get("PROJECT_HITS").unwrap());
+    assert_eq!(0.0, result.0[0].x);
+    // outside of the triangle, and too far away
+    assert_eq!(0.5, result.0[1].x);
+    assert_eq!(1.0, result.0[2].x);
+    Ok(())
+}
+
+#[test]
+fn test_project_along_normals() -> Result<(), HallrError> {
+    // an upward facing triangle above the square
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.2, 0.2, 2.0).into(),
+            (0.8, 0.2, 2.0).into(),
+            (0.2, 0.8, 2.0).into(),
+        ],
+        indices: vec![0, 1, 2],
+    };
+    let owned_model_1 = fixtures::rectangle_faces([0.0, 0.0], [1.0, 1.0], 0.0);
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "project".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("PROJECT_ALONG_NORMALS".to_string(), "true".to_string());
+    // the direction is not used along the normals
+    let _ = config.insert("PROJECT_DIRECTION".to_string(), "0,0,1".to_string());
+    let mut attributes = Attributes::new();
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config, models, &mut attributes)?;
+    assert_eq!("3", result.3.get("PROJECT_HITS").unwrap());
+    assert!(result.0.iter().all(|v| v.z.abs() < 1e-6), "{:?}", result.0);
+    assert_eq!(0.8, result.0[1].x);
+    Ok(())
+}
+
+#[test]
+fn test_project_errors() {
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(0.5, 0.5, 1.0).into(), (0.5, 0.5, 2.0).into()],
+        indices: vec![0, 1],
+    };
+    let owned_model_1 = fixtures::rectangle_faces([0.0, 0.0], [1.0, 1.0], 0.0);
+    let models = || vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "project".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    // the target model is missing
+    let models_0 = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config.clone(), models_0, &mut Attributes::new()).is_err());
+
+    let mut direction_config = config.clone();
+    let _ = direction_config.insert("PROJECT_DIRECTION".to_string(), "0,0,0".to_string());
+    assert!(super::process_command(direction_config, models(), &mut Attributes::new()).is_err());
+    let mut direction_config = config.clone();
+    let _ = direction_config.insert("PROJECT_DIRECTION".to_string(), "0,1".to_string());
+    assert!(super::process_command(direction_config, models(), &mut Attributes::new()).is_err());
+    // the normals require triangles
+    let _ = config.insert("PROJECT_ALONG_NORMALS".to_string(), "true".to_string());
+    assert!(super::process_command(config, models(), &mut Attributes::new()).is_err());
+}
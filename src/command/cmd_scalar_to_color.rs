@@ -0,0 +1,237 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{attributes::Attributes, ConfigType, Model, Options};
+use crate::HallrError;
+use std::str::FromStr;
+
+#[cfg(test)]
+mod tests;
+
+/// The key of the returned vertex colors, a comma separated list of r,g,b,a values
+pub(crate) const VERTEX_COLORS_KEY: &str = "VERTEX_COLORS";
+/// The color of scalars that are not finite
+const NAN_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+/// Piecewise linear approximation of the matplotlib viridis color map
+const VIRIDIS: [(f32, [f32; 3]); 11] = [
+    (0.0, [0.267004, 0.004874, 0.329415]),
+    (0.125, [0.282327, 0.140926, 0.457517]),
+    (0.25, [0.253935, 0.265254, 0.529983]),
+    (0.375, [0.206756, 0.371758, 0.553117]),
+    (0.5, [0.163625, 0.471133, 0.558148]),
+    (0.625, [0.127568, 0.566949, 0.550556]),
+    (0.75, [0.134692, 0.658636, 0.517649]),
+    (0.8125, [0.266941, 0.748751, 0.440573]),
+    (0.875, [0.477504, 0.821444, 0.318195]),
+    (0.9375, [0.845561, 0.887322, 0.099702]),
+    (1.0, [0.993248, 0.906157, 0.143936]),
+];
+
+/// Piecewise linear approximation of the Moreland cool to warm diverging color map
+const COOLWARM: [(f32, [f32; 3]); 5] = [
+    (0.0, [0.229806, 0.298718, 0.753683]),
+    (0.25, [0.552011, 0.690364, 0.995452]),
+    (0.5, [0.865003, 0.865003, 0.865003]),
+    (0.75, [0.957614, 0.603220, 0.481865]),
+    (1.0, [0.705673, 0.015556, 0.150233]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ColorMap {
+    Viridis,
+    Coolwarm,
+}
+
+impl FromStr for ColorMap {
+    type Err = HallrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "VIRIDIS" => Ok(Self::Viridis),
+            "COOLWARM" => Ok(Self::Coolwarm),
+            _ => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"COLOR_MAP\" parameter",
+                s
+            ))),
+        }
+    }
+}
+
+impl ColorMap {
+    /// Returns the RGBA color of `t`, in the 0..=1 range (values outside are clamped)
+    pub(crate) fn color(&self, t: f32) -> [f32; 4] {
+        if !t.is_finite() {
+            return NAN_COLOR;
+        }
+        let table: &[(f32, [f32; 3])] = match self {
+            Self::Viridis => &VIRIDIS,
+            Self::Coolwarm => &COOLWARM,
+        };
+        let t = t.clamp(0.0, 1.0);
+        let upper = table
+            .iter()
+            .position(|(position, _)| *position >= t)
+            .unwrap_or(table.len() - 1)
+            .max(1);
+        let (p0, c0) = table[upper - 1];
+        let (p1, c1) = table[upper];
+        let f = (t - p0) / (p1 - p0);
+        [
+            c0[0] * (1.0 - f) + c1[0] * f,
+            c0[1] * (1.0 - f) + c1[1] * f,
+            c0[2] * (1.0 - f) + c1[2] * f,
+            1.0,
+        ]
+    }
+}
+
+/// Map the scalars to colors over the `min`..`max` range, that defaults to the range of the finite
+/// scalars.
+/// Returns the colors and the range used.
+pub(crate) fn scalars_to_colors(
+    scalars: &[f32],
+    color_map: ColorMap,
+    min: Option<f32>,
+    max: Option<f32>,
+) -> (Vec<[f32; 4]>, f32, f32) {
+    let (data_min, data_max) = scalars
+        .iter()
+        .filter(|s| s.is_finite())
+        .fold((f32::MAX, f32::MIN), |(low, high), s| {
+            (low.min(*s), high.max(*s))
+        });
+    let min = min.unwrap_or(if data_min <= data_max { data_min } else { 0.0 });
+    let max = max.unwrap_or(if data_min <= data_max { data_max } else { 1.0 });
+    let span = max - min;
+    let colors = scalars
+        .iter()
+        .map(|s| {
+            if span.abs() > f32::EPSILON {
+                color_map.color((s - min) / span)
+            } else if s.is_finite() {
+                color_map.color(0.5)
+            } else {
+                NAN_COLOR
+            }
+        })
+        .collect();
+    (colors, min, max)
+}
+
+/// Encode the colors as a comma separated list of r,g,b,a values
+pub(crate) fn encode_colors(colors: &[[f32; 4]]) -> String {
+    colors
+        .iter()
+        .flatten()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Map the scalars to colors, using the `COLOR_MAP`, `SCALAR_MIN` and `SCALAR_MAX` options, and
+/// insert them into the returned config as `VERTEX_COLORS`. Nothing is done if there is no
+/// `COLOR_MAP` option.
+pub(crate) fn add_vertex_colors(
+    config: &ConfigType,
+    scalars: &[f32],
+    return_config: &mut ConfigType,
+) -> Result<(), HallrError> {
+    let Some(color_map) = config.get_parsed_option::<ColorMap>("COLOR_MAP")? else {
+        return Ok(());
+    };
+    let (colors, min, max) = scalars_to_colors(
+        scalars,
+        color_map,
+        config.get_parsed_option::<f32>("SCALAR_MIN")?,
+        config.get_parsed_option::<f32>("SCALAR_MAX")?,
+    );
+    let _ = return_config.insert(VERTEX_COLORS_KEY.to_string(), encode_colors(&colors));
+    let _ = return_config.insert("SCALAR_MIN".to_string(), min.to_string());
+    let _ = return_config.insert("SCALAR_MAX".to_string(), max.to_string());
+    Ok(())
+}
+
+/// Run the scalar_to_color command
+/// Model 0 is returned unchanged, together with the per-vertex colors of a scalar channel (one
+/// value per vertex), named by `SCALAR_CHANNEL` (default `SCALARS`). The channel is read from the
+/// binary attributes, or else from the option with that name as a comma separated list of values.
+/// The scalars are mapped with the `COLOR_MAP` (VIRIDIS or COOLWARM) over the
+/// `SCALAR_MIN`..`SCALAR_MAX` range, that defaults to the range of the data. The colors are
+/// returned as `VERTEX_COLORS`, r,g,b,a values: as a binary attribute if the scalars were binary,
+/// otherwise as a comma separated list.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+    attributes: &Attributes,
+    output_attributes: &mut Attributes,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let color_map = config.get_mandatory_parsed_option::<ColorMap>("COLOR_MAP", None)?;
+    let channel = config
+        .get_mandatory_parsed_option::<String>("SCALAR_CHANNEL", Some("SCALARS".to_string()))?;
+    let model = &models[0];
+    let binary_scalars = attributes.get(&channel);
+    let scalars = if let Some(scalars) = binary_scalars {
+        scalars.clone()
+    } else {
+        config
+            .get_mandatory_option(&channel)?
+            .split(',')
+            .map(|s| {
+                s.trim().parse::<f32>().map_err(|_| {
+                    HallrError::InvalidParameter(format!(
+                        "Could not parse \"{}\" of the {} channel",
+                        s, channel
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    if scalars.len() != model.vertices.len() {
+        return Err(HallrError::InvalidInputData(format!(
+            "The {} channel has {} values, the model has {} vertices",
+            channel,
+            scalars.len(),
+            model.vertices.len()
+        )));
+    }
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert(
+        "mesh.format".to_string(),
+        config.get_mandatory_option("mesh.format")?.to_string(),
+    );
+    if binary_scalars.is_some() {
+        let (colors, min, max) = scalars_to_colors(
+            &scalars,
+            color_map,
+            config.get_parsed_option::<f32>("SCALAR_MIN")?,
+            config.get_parsed_option::<f32>("SCALAR_MAX")?,
+        );
+        let _ = output_attributes.insert(
+            VERTEX_COLORS_KEY.to_string(),
+            colors.into_iter().flatten().collect(),
+        );
+        let _ = return_config.insert("SCALAR_MIN".to_string(), min.to_string());
+        let _ = return_config.insert("SCALAR_MAX".to_string(), max.to_string());
+    } else {
+        add_vertex_colors(&config, &scalars, &mut return_config)?;
+    }
+    info!(
+        "scalar_to_color operation returning {} vertices, {} colors",
+        model.vertices.len(),
+        scalars.len()
+    );
+    Ok((
+        model.vertices.to_vec(),
+        model.indices.to_vec(),
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::ColorMap;
+use crate::{
+    command::{attributes::Attributes, ConfigType, OwnedModel},
+    HallrError,
+};
+
+#[test]
+fn test_color_map() {
+    assert_eq!(
+        [0.267004, 0.004874, 0.329415, 1.0],
+        ColorMap::Viridis.color(0.0)
+    );
+    assert_eq!(
+        [0.993248, 0.906157, 0.143936, 1.0],
+        ColorMap::Viridis.color(1.0)
+    );
+    // values outside the range are clamped
+    assert_eq!(ColorMap::Viridis.color(1.0), ColorMap::Viridis.color(7.0));
+    assert_eq!(
+        ColorMap::Coolwarm.color(0.0),
+        ColorMap::Coolwarm.color(-1.0)
+    );
+    let middle = ColorMap::Coolwarm.color(0.5);
+    assert!((middle[0] - 0.865003).abs() < 1e-6);
+    assert_eq!(super::NAN_COLOR, ColorMap::Coolwarm.color(f32::NAN));
+}
+
+#[test]
+fn test_scalars_to_colors() {
+    let (colors, min, max) =
+        super::scalars_to_colors(&[1.0, 3.0, f32::NAN], ColorMap::Viridis, None, None);
+    assert_eq!(1.0, min);
+    assert_eq!(3.0, max);
+    assert_eq!(ColorMap::Viridis.color(0.0), colors[0]);
+    assert_eq!(ColorMap::Viridis.color(1.0), colors[1]);
+    assert_eq!(super::NAN_COLOR, colors[2]);
+
+    let (colors, min, max) =
+        super::scalars_to_colors(&[1.0, 3.0], ColorMap::Viridis, Some(0.0), Some(2.0));
+    assert_eq!((0.0, 2.0), (min, max));
+    assert_eq!(ColorMap::Viridis.color(0.5), colors[0]);
+    assert_eq!(ColorMap::Viridis.color(1.0), colors[1]);
+}
+
+#[test]
+fn test_scalar_to_color_1() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "scalar_to_color".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("COLOR_MAP".to_string(), "COOLWARM".to_string());
+    let _ = config.insert("SCALAR_CHANNEL".to_string(), "CURVATURE".to_string());
+    let _ = config.insert("CURVATURE".to_string(), "-1.0, 0.0, 1.0".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (1.0, 0.0, 0.0).into(),
+            (2.0, 0.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2],
+    };
+    let models = vec![owned_model_0.as_model()];
+    let mut output_attributes = Attributes::new();
+    let result = super::process_command(
+        config.clone(),
+        models,
+        &Attributes::new(),
+        &mut output_attributes,
+    )?;
+    assert!(output_attributes.is_empty());
+    assert_eq!(3, result.0.len()); // vertices
+    assert_eq!(4, result.1.len()); // indices
+    let colors: Vec<f32> = result
+        .3
+        .get(super::VERTEX_COLORS_KEY)
+        .unwrap()
+        .split(',')
+        .map(|c| c.parse().unwrap())
+        .collect();
+    assert_eq!(3 * 4, colors.len());
+    assert_eq!(
+        ColorMap::Coolwarm.color(0.5).to_vec(),
+        colors[4..8].to_vec()
+    );
+
+    // one value is missing
+    let _ = config.insert("CURVATURE".to_string(), "-1.0, 0.0".to_string());
+    let models = vec![owned_model_0.as_model()];
+    assert!(
+        super::process_command(config, models, &Attributes::new(), &mut Attributes::new()).is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_scalar_to_color_2() -> Result<(), HallrError> {
+    // the scalars are read from, and the colors returned in, the binary attributes
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "scalar_to_color".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("COLOR_MAP".to_string(), "VIRIDIS".to_string());
+    let mut attributes = Attributes::new();
+    let _ = attributes.insert("SCALARS".to_string(), vec![2.0, 4.0]);
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(0.0, 0.0, 0.0).into(), (1.0, 0.0, 0.0).into()],
+        indices: vec![0, 1],
+    };
+    let models = vec![owned_model_0.as_model()];
+    let mut output_attributes = Attributes::new();
+    let result = super::process_command(config, models, &attributes, &mut output_attributes)?;
+    assert!(result.3.get(super::VERTEX_COLORS_KEY).is_none());
+    assert_eq!("2", result.3.get("SCALAR_MIN").unwrap());
+    assert_eq!("4", result.3.get("SCALAR_MAX").unwrap());
+    let colors = output_attributes.get(super::VERTEX_COLORS_KEY).unwrap();
+    assert_eq!(2 * 4, colors.len());
+    assert_eq!(ColorMap::Viridis.color(1.0).to_vec(), colors[4..8].to_vec());
+    Ok(())
+}
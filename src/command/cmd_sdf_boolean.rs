@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{
+    cmd_sdf_remesh::{self, Lattice, SignMethod},
+    progress, ConfigType, Model, Options,
+};
+use crate::HallrError;
+use rayon::prelude::*;
+use std::{str::FromStr, time};
+use vector_traits::glam::DVec3;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BooleanOperation {
+    Union,
+    Difference,
+    Intersection,
+}
+
+impl FromStr for BooleanOperation {
+    type Err = HallrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "UNION" => Ok(Self::Union),
+            "DIFFERENCE" => Ok(Self::Difference),
+            "INTERSECTION" => Ok(Self::Intersection),
+            _ => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"OPERATIONS\" parameter",
+                s
+            ))),
+        }
+    }
+}
+
+impl BooleanOperation {
+    /// Combine the signed distances of the accumulated solid `a` and the next solid `b`
+    pub(crate) fn apply(self, a: f32, b: f32) -> f32 {
+        match self {
+            Self::Union => a.min(b),
+            Self::Difference => a.max(-b),
+            Self::Intersection => a.max(b),
+        }
+    }
+}
+
+/// Run the sdf_boolean command
+/// Two or more closed triangulated models, in the same coordinate system, are combined left to
+/// right by `OPERATIONS`: one comma separated UNION, DIFFERENCE or INTERSECTION per model after
+/// model 0, e.g. "UNION,UNION,DIFFERENCE" for four models. The signed distance fields of the
+/// models are sampled on a common lattice of `SDF_DIVISIONS` voxels along the longest side of
+/// the combined bounding box, combined, and the result is meshed with surface nets. The inside of
+/// every model is decided by `SDF_SIGN_METHOD`, as in sdf_remesh. The voxel size is returned as
+/// `SDF_VOXEL_SIZE`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.len() < 2 {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires at least two input models".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("triangulated") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'triangulated' format".to_string(),
+        ));
+    }
+    let operations = config
+        .get_mandatory_option("OPERATIONS")?
+        .split(',')
+        .map(|operation| operation.trim().parse::<BooleanOperation>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if operations.len() != models.len() - 1 {
+        return Err(HallrError::InvalidParameter(format!(
+            "OPERATIONS must contain one operation per model after the first: expected {}, got {}",
+            models.len() - 1,
+            operations.len()
+        )));
+    }
+    let divisions = config.get_mandatory_parsed_option::<f64>("SDF_DIVISIONS", None)?;
+    if !divisions.is_finite() || divisions < 1.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "SDF_DIVISIONS must be at least 1 :({})",
+            divisions
+        )));
+    }
+    let sign_method = config
+        .get_mandatory_parsed_option::<SignMethod>("SDF_SIGN_METHOD", Some(SignMethod::Winding))?;
+
+    let solids: Vec<Vec<[DVec3; 3]>> = models.iter().map(cmd_sdf_remesh::model_triangles).collect();
+    if let Some(empty) = solids.iter().position(|triangles| triangles.is_empty()) {
+        return Err(HallrError::InvalidInputData(format!(
+            "Model {} has no triangles",
+            empty
+        )));
+    }
+    let (min, max) = solids.iter().flatten().flatten().fold(
+        (DVec3::splat(f64::MAX), DVec3::splat(f64::MIN)),
+        |(min, max), v| (min.min(*v), max.max(*v)),
+    );
+    let lattice = Lattice::new(min, max, divisions, 0.0)?;
+    debug!(
+        "sdf_boolean: {} models, lattice {:?}, voxel size {}",
+        solids.len(),
+        lattice.shape.as_array(),
+        lattice.voxel_size
+    );
+
+    let now = time::Instant::now();
+    let mut solids = solids.into_iter();
+    let mut sdf = lattice.signed_distance_field(solids.next().unwrap(), sign_method);
+    for (triangles, operation) in solids.zip(operations.iter()) {
+        progress::check_cancelled()?;
+        let next = lattice.signed_distance_field(triangles, sign_method);
+        sdf.par_iter_mut()
+            .zip(next.par_iter())
+            .for_each(|(a, b)| *a = operation.apply(*a, *b));
+    }
+    progress::check_cancelled()?;
+    debug!("sdf_boolean: SDF duration:{:?}", now.elapsed());
+    let (output_vertices, output_indices) = lattice.surface_nets(&sdf);
+    if output_indices.is_empty() {
+        return Err(HallrError::NoData(
+            "The result of the boolean operations is empty".to_string(),
+        ));
+    }
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = return_config.insert("SDF_VOXEL_SIZE".to_string(), lattice.voxel_size.to_string());
+    info!(
+        "sdf_boolean operation returning {} vertices, {} indices",
+        output_vertices.len(),
+        output_indices.len()
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        models[0].world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType},
+    HallrError,
+};
+
+#[test]
+fn test_sdf_boolean_1() -> Result<(), HallrError> {
+    // two overlapping cubes, the second one is shifted 1.0 along X
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "sdf_boolean".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "12".to_string());
+
+    let owned_model_0 = fixtures::cuboid([-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]);
+    let owned_model_1 = fixtures::cuboid([0.0, -1.0, -1.0], [2.0, 1.0, 1.0]);
+    for (operation, expected_min_x, expected_max_x) in [
+        ("UNION", -1.0, 2.0),
+        ("DIFFERENCE", -1.0, 0.0),
+        ("INTERSECTION", 0.0, 1.0),
+    ] {
+        let _ = config.insert("OPERATIONS".to_string(), operation.to_string());
+        let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+        let result = super::process_command(config.clone(), models)?;
+        assert!(!result.1.is_empty(), "{}", operation);
+        assert_eq!(0, result.1.len() % 3);
+        let min_x = result.0.iter().map(|v| v.x).fold(f32::MAX, f32::min);
+        let max_x = result.0.iter().map(|v| v.x).fold(f32::MIN, f32::max);
+        // the voxel size is 0.25
+        assert!(
+            (min_x - expected_min_x).abs() < 0.3,
+            "{} {}",
+            operation,
+            min_x
+        );
+        assert!(
+            (max_x - expected_max_x).abs() < 0.3,
+            "{} {}",
+            operation,
+            max_x
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_sdf_boolean_2() -> Result<(), HallrError> {
+    // three models are combined left to right: (cube_0 UNION cube_1) DIFFERENCE cube_2
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "sdf_boolean".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "12".to_string());
+    let _ = config.insert("OPERATIONS".to_string(), "UNION, DIFFERENCE".to_string());
+
+    let owned_model_0 = fixtures::cuboid([-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]);
+    let owned_model_1 = fixtures::cuboid([1.0, -1.0, -1.0], [3.0, 1.0, 1.0]);
+    let owned_model_2 = fixtures::cuboid([2.0, -1.0, -1.0], [4.0, 1.0, 1.0]);
+    let models = vec![
+        owned_model_0.as_model(),
+        owned_model_1.as_model(),
+        owned_model_2.as_model(),
+    ];
+    let result = super::process_command(config.clone(), models)?;
+    let min_x = result.0.iter().map(|v| v.x).fold(f32::MAX, f32::min);
+    let max_x = result.0.iter().map(|v| v.x).fold(f32::MIN, f32::max);
+    // the voxel size is 5/12
+    assert!((min_x + 1.0).abs() < 0.5, "{}", min_x);
+    assert!((max_x - 2.0).abs() < 0.5, "{}", max_x);
+
+    // one operation per model after the first
+    let _ = config.insert("OPERATIONS".to_string(), "UNION,UNION".to_string());
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    assert!(super::process_command(config.clone(), models).is_err());
+    let _ = config.insert("OPERATIONS".to_string(), "XOR".to_string());
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
@@ -2,28 +2,39 @@
 // Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
 // This file is part of the hallr crate.
 
+#[cfg(feature = "wgpu_sdf")]
+mod gpu;
 #[cfg(test)]
 mod tests;
 
 use crate::{
-    command::{ConfigType, Model, Options, OwnedModel},
+    command::{
+        progress::{self, Progress},
+        ConfigType, Model, Options, OwnedModel,
+    },
     ffi::FFIVector3,
     HallrError,
 };
-use fast_surface_nets::{ndshape::ConstShape, surface_nets, SurfaceNetsBuffer};
+use fast_surface_nets::{
+    ndshape::{ConstShape, ConstShape3u32},
+    surface_nets, SurfaceNetsBuffer,
+};
 use ilattice::{glam as iglam, prelude::Extent};
+use itertools::izip;
 use rayon::prelude::*;
-use std::time;
-
-// The un-padded chunk side, it will become 16*16*16
-const UN_PADDED_CHUNK_SIDE: u32 = 14_u32;
-type PaddedChunkShape = fast_surface_nets::ndshape::ConstShape3u32<
-    { UN_PADDED_CHUNK_SIDE + 2 },
-    { UN_PADDED_CHUNK_SIDE + 2 },
-    { UN_PADDED_CHUNK_SIDE + 2 },
->;
+use std::{borrow::Cow, fmt::Write, time};
+
+// The default un-padded chunk side, it will become 16*16*16
+const DEFAULT_UN_PADDED_CHUNK_SIDE: u32 = 14_u32;
+/// The selectable un-padded chunk sides, each one maps to a padded chunk shape of (side+2)^3
+pub(crate) const UN_PADDED_CHUNK_SIDE_PRESETS: [u32; 4] = [8, 14, 30, 62];
+type PaddedChunkShape<const PADDED_CHUNK_SIDE: u32> =
+    ConstShape3u32<PADDED_CHUNK_SIDE, PADDED_CHUNK_SIDE, PADDED_CHUNK_SIDE>;
 const DEFAULT_SDF_VALUE: f32 = 999.0;
 type Extent3i = Extent<iglam::IVec3>;
+/// A chunk that generated geometry: the padded chunk minimum (in voxel scale), the surface nets
+/// output and the time spent on the chunk (not measured by the GPU backend)
+pub(crate) type SdfChunk = (iglam::Vec3A, SurfaceNetsBuffer, Option<time::Duration>);
 
 /// returns an AABB (not padded by radius)
 #[allow(clippy::type_complexity)]
@@ -52,199 +63,845 @@ fn parse_input(model: &Model<'_>) -> Result<Extent<iglam::Vec3A>, HallrError> {
     Ok(aabb)
 }
 
-/// Build the chunk lattice and spawn off thread tasks for each chunk
+/// Reads the `SDF_CHUNK_SIDE` option: the un-padded side of the voxel chunks.
+/// Large smooth models benefit from bigger chunks, while thin lattices prefer small ones.
+pub(crate) fn parse_chunk_side(config: &ConfigType) -> Result<u32, HallrError> {
+    let chunk_side = config
+        .get_mandatory_parsed_option::<u32>("SDF_CHUNK_SIDE", Some(DEFAULT_UN_PADDED_CHUNK_SIDE))?;
+    if !UN_PADDED_CHUNK_SIDE_PRESETS.contains(&chunk_side) {
+        return Err(HallrError::InvalidParameter(format!(
+            "The valid values of SDF_CHUNK_SIDE are {:?} :({})",
+            UN_PADDED_CHUNK_SIDE_PRESETS, chunk_side
+        )));
+    }
+    Ok(chunk_side)
+}
+
+/// The voxel and chunk lattice of an sdf_mesh run, it only depends on the bounding box of the
+/// skeleton and the options. Use it to predict the cost of a run before meshing.
+#[derive(Clone, Debug)]
+pub struct SdfLattice {
+    /// The longest side of the (un-padded) skeleton bounding box
+    pub max_dimension: f32,
+    /// The number of voxels per model unit, along the X, Y and Z axis
+    pub scale: [f32; 3],
+    /// The tube radius of every shell, in model units
+    pub radii: Vec<f32>,
+    /// The un-padded side of the chunks, in voxels
+    pub chunk_side: u32,
+    /// The chunk coordinates covering the bounding box, padded with the radius and one voxel
+    pub(crate) chunks_extent: Extent3i,
+}
+
+impl SdfLattice {
+    /// Build the lattice of the skeleton bounding box. `radius_multiplier` is the fraction of the
+    /// longest side used as tube radius, `radius_offsets` (in model units) are added to it, one
+    /// shell per offset. `divisions` is the number of voxels along the longest side, per axis.
+    pub(crate) fn new(
+        unpadded_aabb: Extent<iglam::Vec3A>,
+        radius_multiplier: f32,
+        radius_offsets: &[f32],
+        divisions: [f32; 3],
+        chunk_side: u32,
+    ) -> Result<Self, HallrError> {
+        let max_dimension = {
+            let dimensions = unpadded_aabb.shape;
+            dimensions.x.max(dimensions.y).max(dimensions.z)
+        };
+
+        let base_radius = max_dimension * radius_multiplier; // unscaled
+        let radii: Vec<f32> = radius_offsets.iter().map(|o| base_radius + o).collect();
+        if radii.iter().any(|r| !r.is_finite() || *r <= 0.0) {
+            return Err(HallrError::InvalidParameter(format!(
+                "The offsets must keep the tube radius ({}) positive :({:?})",
+                base_radius, radius_offsets
+            )));
+        }
+        // the SDF is evaluated with the largest radius, the other shells are found inside of it
+        let radius = radii.iter().copied().fold(f32::MIN, f32::max);
+        let scale = iglam::Vec3A::from(divisions) / max_dimension;
+        // Add the radius padding around the aabb
+        let aabb = unpadded_aabb.padded(radius);
+        let chunks_extent = {
+            // pad with the radius + one voxel
+            let chunk_scale = scale / (chunk_side as f32);
+            Extent::from_min_and_shape(aabb.minimum * chunk_scale, aabb.shape * chunk_scale)
+                .padded(1.0 / (chunk_side as f32))
+                .containing_integer_extent()
+        };
+        Ok(Self {
+            max_dimension,
+            scale: scale.into(),
+            radii,
+            chunk_side,
+            chunks_extent,
+        })
+    }
+
+    /// The largest side of a voxel, in model units
+    pub fn voxel_size(&self) -> f32 {
+        self.voxel_sizes().into_iter().fold(0.0, f32::max)
+    }
+
+    /// The sides of a voxel along the X, Y and Z axis, in model units
+    pub fn voxel_sizes(&self) -> [f32; 3] {
+        self.scale.map(|s| 1.0 / s)
+    }
+
+    /// The number of voxels per model unit along the finest axis. The SDF is evaluated in this
+    /// unit, so that the distances are the same along every axis.
+    pub(crate) fn max_scale(&self) -> f32 {
+        self.scale.into_iter().fold(0.0, f32::max)
+    }
+
+    /// Converts voxel coordinates into the isotropic space of `max_scale()`, it is one along the
+    /// finest axis(es) and larger along the others
+    pub(crate) fn stretch(&self) -> iglam::Vec3A {
+        self.max_scale() / iglam::Vec3A::from(self.scale)
+    }
+
+    /// The largest tube radius, in model units
+    pub fn radius(&self) -> f32 {
+        self.radii.iter().copied().fold(f32::MIN, f32::max)
+    }
+
+    /// The number of chunks along the X, Y and Z axis
+    pub fn chunk_grid(&self) -> [u32; 3] {
+        let shape = self.chunks_extent.shape;
+        [shape.x as u32, shape.y as u32, shape.z as u32]
+    }
+
+    /// The number of chunks of the full lattice, `ADAPTIVE=true` usually evaluates far fewer
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_grid().iter().map(|s| *s as usize).product()
+    }
+
+    /// A rough estimate of the number of output vertices, summed over the shells.
+    /// `skeleton_length` is the total length of the skeleton edges. The surface nets produce
+    /// about one vertex per voxel face of the surface, and a tube thinner than a voxel is still
+    /// about one voxel wide. Anisotropic voxels are counted as cubes of the same volume.
+    pub fn vertex_estimate(&self, skeleton_length: f32) -> usize {
+        let voxel_size = self.voxel_sizes().iter().product::<f32>().cbrt();
+        self.radii
+            .iter()
+            .map(|r| {
+                let area = std::f32::consts::TAU * r.max(voxel_size) * skeleton_length;
+                (area / (voxel_size * voxel_size)) as usize
+            })
+            .sum()
+    }
+}
+
+/// Reads the lattice options of sdf_mesh: `SDF_RADIUS_MULTIPLIER` (in percent), `SDF_DIVISIONS`,
+/// `SDF_DIVISIONS_X`, `SDF_DIVISIONS_Y`, `SDF_DIVISIONS_Z` (defaults to `SDF_DIVISIONS`),
+/// `SDF_CHUNK_SIDE` and `MULTI_OFFSETS`, and builds the lattice of the skeleton bounding box
+pub(crate) fn parse_lattice(
+    config: &ConfigType,
+    unpadded_aabb: Extent<iglam::Vec3A>,
+) -> Result<SdfLattice, HallrError> {
+    let cmd_arg_sdf_radius_multiplier =
+        config.get_mandatory_parsed_option::<f32>("SDF_RADIUS_MULTIPLIER", None)? / 100.0;
+
+    let divisions = |key: &str, default: Option<f32>| -> Result<f32, HallrError> {
+        let divisions: f32 = config.get_mandatory_parsed_option(key, default)?;
+        if !(9.9..600.1).contains(&divisions) {
+            return Err(HallrError::InvalidInputData(format!(
+                "The valid range of {} is [{}..{}[% :({})",
+                key, 10, 600, divisions
+            )));
+        }
+        Ok(divisions)
+    };
+    let cmd_arg_sdf_divisions = divisions("SDF_DIVISIONS", None)?;
+    // the voxels may be finer along some axis, e.g. along Z for thin reliefs
+    let cmd_arg_sdf_axis_divisions = [
+        divisions("SDF_DIVISIONS_X", Some(cmd_arg_sdf_divisions))?,
+        divisions("SDF_DIVISIONS_Y", Some(cmd_arg_sdf_divisions))?,
+        divisions("SDF_DIVISIONS_Z", Some(cmd_arg_sdf_divisions))?,
+    ];
+
+    let cmd_arg_sdf_chunk_side = parse_chunk_side(config)?;
+    let cmd_arg_multi_offsets = match config.get_parsed_option::<String>("MULTI_OFFSETS")? {
+        Some(offsets) => offsets
+            .split(',')
+            .map(|s| {
+                s.trim().parse::<f32>().map_err(|_| {
+                    HallrError::InvalidParameter(format!(
+                        "Could not parse \"{}\" of MULTI_OFFSETS",
+                        s
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![0.0],
+    };
+    SdfLattice::new(
+        unpadded_aabb,
+        cmd_arg_sdf_radius_multiplier,
+        &cmd_arg_multi_offsets,
+        cmd_arg_sdf_axis_divisions,
+        cmd_arg_sdf_chunk_side,
+    )
+}
+
+/// Returns the lattice sdf_mesh would use for a skeleton with the bounding box `min`..`max`, the
+/// lattice options are read from the sdf_mesh `config`
+pub(crate) fn sdf_mesh_lattice(
+    config: &ConfigType,
+    min: FFIVector3,
+    max: FFIVector3,
+) -> Result<SdfLattice, HallrError> {
+    let (min, max) = (
+        iglam::vec3a(min.x, min.y, min.z),
+        iglam::vec3a(max.x, max.y, max.z),
+    );
+    if !min.is_finite() || !max.is_finite() || max.cmplt(min).any() {
+        return Err(HallrError::InvalidInputData(format!(
+            "The bounding box is not valid: {:?}..{:?}",
+            min, max
+        )));
+    }
+    parse_lattice(config, Extent::from_min_and_shape(min, max - min))
+}
+
+/// Returns the summed length of the edges of a line_chunks model
+fn skeleton_length(model: &Model<'_>) -> f32 {
+    model
+        .indices
+        .chunks_exact(2)
+        .map(|edge| {
+            let (a, b) = (&model.vertices[edge[0]], &model.vertices[edge[1]]);
+            iglam::vec3a(b.x - a.x, b.y - a.y, b.z - a.z).length()
+        })
+        .sum()
+}
+
+/// Spawn off thread tasks for each chunk of the lattice.
+/// One surface (shell) is generated for every radius of the lattice, the SDF is only evaluated
+/// once. Returns the chunks of every shell.
+/// With a `part` (in chunk coordinates) only the chunks of the part are evaluated, and the SDF is
+/// intersected with the box of the part so that the surfaces are capped at the part boundary.
 fn build_voxel(
-    radius_multiplier: f32,
-    divisions: f32,
+    lattice: &SdfLattice,
     vertices: &[FFIVector3],
     indices: &[usize],
-    unpadded_aabb: Extent<iglam::Vec3A>,
+    use_gpu: bool,
+    adaptive: bool,
+    part: Option<Extent3i>,
     verbose: bool,
-) -> Result<
-    (
-        f32, // voxel_size
-        Vec<(iglam::Vec3A /* offset */, SurfaceNetsBuffer)>,
-    ),
-    HallrError,
-> {
-    let max_dimension = {
-        let dimensions = unpadded_aabb.shape;
-        dimensions.x.max(dimensions.y).max(dimensions.z)
-    };
-
-    let radius = max_dimension * radius_multiplier; // unscaled
-    let scale = divisions / max_dimension;
-    // Add the radius padding around the aabb
-    let aabb = unpadded_aabb.padded(radius);
+) -> Result<Vec<Vec<SdfChunk>>, HallrError> {
+    let radii = &lattice.radii;
+    let radius = lattice.radius();
+    // the SDF is evaluated in voxels of the finest axis, `stretch` maps voxel coordinates to it
+    let scale = lattice.max_scale();
+    let stretch = lattice.stretch();
+    let chunks_extent = part.unwrap_or(lattice.chunks_extent);
+    let un_padded_chunk_side = lattice.chunk_side;
+    // The cut planes of the part are halfway between two voxels. A chunk only meshes the voxel
+    // edges starting from its first padding voxel up to its second to last voxel, so the minimum
+    // cut is between the last voxel of the previous chunk and the first voxel of the part, and
+    // the maximum cut is one voxel further inside, between the last two voxels of the part.
+    let clip = part.map(|part| {
+        let side = un_padded_chunk_side as i32;
+        (
+            (part.minimum * side).as_vec3a() - 0.5,
+            ((part.minimum + part.shape) * side).as_vec3a() - 1.5,
+        )
+    });
 
     if verbose {
-        println!(
-            "Voxelizing using tube radius. {} = {}*{}*{}",
-            radius, max_dimension, radius_multiplier, scale
+        debug!(
+            "Voxelizing using tube radius. {} (scale factor={:?})",
+            radius, lattice.scale
         );
 
-        println!(
-            "Voxelizing using divisions = {}, max dimension = {}, scale factor={} (max_dimension*scale={})",
-            divisions,
-            max_dimension,
+        debug!(
+            "Voxelizing using max dimension = {}, scale factor={} (max_dimension*scale={})",
+            lattice.max_dimension,
             scale,
-            max_dimension * scale
+            lattice.max_dimension * scale
         );
-        println!();
     }
     let vertices: Vec<iglam::Vec3A> = vertices
         .iter()
         .map(|v| iglam::Vec3A::new(v.x, v.y, v.z) * scale)
         .collect();
 
-    let chunks_extent = {
-        // pad with the radius + one voxel
-        (aabb * (scale / (UN_PADDED_CHUNK_SIDE as f32)))
-            .padded(1.0 / (UN_PADDED_CHUNK_SIDE as f32))
-            .containing_integer_extent()
-    };
-
     let now = time::Instant::now();
 
-    let sdf_chunks: Vec<_> = {
+    let sdf_chunks: Vec<Vec<SdfChunk>> = if use_gpu {
+        if radii.len() > 1 {
+            return Err(HallrError::InvalidParameter(
+                "MULTI_OFFSETS is not supported by SDF_BACKEND=gpu".to_string(),
+            ));
+        }
+        if adaptive {
+            return Err(HallrError::InvalidParameter(
+                "ADAPTIVE is not supported by SDF_BACKEND=gpu".to_string(),
+            ));
+        }
+        if part.is_some() {
+            return Err(HallrError::InvalidParameter(
+                "SPLIT_MAX_VERTICES is not supported by SDF_BACKEND=gpu".to_string(),
+            ));
+        }
+        if stretch != iglam::Vec3A::ONE {
+            return Err(HallrError::InvalidParameter(
+                "SDF_DIVISIONS_X/Y/Z are not supported by SDF_BACKEND=gpu".to_string(),
+            ));
+        }
+        vec![generate_sdf_chunks_on_gpu(
+            chunks_extent,
+            un_padded_chunk_side,
+            &vertices,
+            indices,
+            radius * scale,
+        )?]
+    } else {
+        let shell_offsets: Vec<f32> = radii.iter().map(|r| (r - radius) * scale).collect();
         let radius = radius * scale;
-        let unpadded_chunk_shape = iglam::IVec3::splat(UN_PADDED_CHUNK_SIDE as i32);
+        let unpadded_chunk_shape = iglam::IVec3::splat(un_padded_chunk_side as i32);
+        // the chunks to evaluate, and the edges that may affect them
+        let chunks: Vec<(iglam::IVec3, Cow<'_, [usize]>)> = if adaptive {
+            let root_size = (chunks_extent.shape.max_element() as u32).next_power_of_two() as i32;
+            // the chunks inside of a part are needed for its caps
+            let min_offset = if part.is_some() {
+                f32::NEG_INFINITY
+            } else {
+                shell_offsets.iter().copied().fold(0.0, f32::min)
+            };
+            let (part_min, part_max) = (
+                chunks_extent.minimum,
+                chunks_extent.minimum + chunks_extent.shape,
+            );
+            let chunks: Vec<_> = octree_chunks(
+                chunks_extent.minimum,
+                root_size,
+                un_padded_chunk_side as i32,
+                &vertices,
+                indices,
+                radius,
+                min_offset,
+                stretch,
+            )
+            .into_iter()
+            // the octree root may reach outside of the part
+            .filter(|(p, _)| p.cmpge(part_min).all() && p.cmplt(part_max).all())
+            .map(|(p, indices)| (p, Cow::Owned(indices)))
+            .collect();
+            if verbose {
+                debug!(
+                    "Adaptive octree kept {} of {} chunks",
+                    chunks.len(),
+                    chunks_extent.shape.x * chunks_extent.shape.y * chunks_extent.shape.z
+                );
+            }
+            chunks
+        } else {
+            chunks_extent
+                .iter3()
+                .map(|p| (p, Cow::Borrowed(indices)))
+                .collect()
+        };
+        let chunk_progress = Progress::new(chunks.len());
         // Spawn off thread tasks creating and processing chunks.
-        chunks_extent
-            .iter3()
-            .par_bridge()
-            .filter_map(move |p| {
+        let shell_chunks: Vec<(usize, SdfChunk)> = chunks
+            .into_par_iter()
+            .flat_map(|(p, indices)| {
+                // the remaining chunks are skipped once the command is cancelled
+                if progress::is_cancelled() {
+                    return Vec::new();
+                }
                 let unpadded_chunk_extent =
                     Extent3i::from_min_and_shape(p * unpadded_chunk_shape, unpadded_chunk_shape);
+                let chunk_start = time::Instant::now();
 
-                generate_and_process_sdf_chunk(unpadded_chunk_extent, &vertices, indices, radius)
+                // map the chunk side onto a const-generic chunk shape
+                let chunk = match un_padded_chunk_side {
+                    8 => generate_and_process_sdf_chunk::<10>(
+                        unpadded_chunk_extent,
+                        &vertices,
+                        &indices,
+                        radius,
+                        &shell_offsets,
+                        stretch,
+                        clip,
+                    ),
+                    30 => generate_and_process_sdf_chunk::<32>(
+                        unpadded_chunk_extent,
+                        &vertices,
+                        &indices,
+                        radius,
+                        &shell_offsets,
+                        stretch,
+                        clip,
+                    ),
+                    62 => generate_and_process_sdf_chunk::<64>(
+                        unpadded_chunk_extent,
+                        &vertices,
+                        &indices,
+                        radius,
+                        &shell_offsets,
+                        stretch,
+                        clip,
+                    ),
+                    _ => generate_and_process_sdf_chunk::<16>(
+                        unpadded_chunk_extent,
+                        &vertices,
+                        &indices,
+                        radius,
+                        &shell_offsets,
+                        stretch,
+                        clip,
+                    ),
+                };
+                let duration = chunk_start.elapsed();
+                chunk_progress.tick();
+                chunk
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (shell, offset, buffer))| {
+                        // the evaluation is shared by the shells, its time is only counted once
+                        (shell, (offset, buffer, (i == 0).then_some(duration)))
+                    })
+                    .collect::<Vec<_>>()
             })
-            .collect()
+            .collect();
+        progress::check_cancelled()?;
+        let mut rv: Vec<Vec<SdfChunk>> = radii.iter().map(|_| Vec::new()).collect();
+        for (shell, chunk) in shell_chunks {
+            rv[shell].push(chunk);
+        }
+        rv
     };
 
     if verbose {
-        println!(
+        debug!(
             "process_chunks() duration: {:?} generated {} chunks",
             now.elapsed(),
-            sdf_chunks.len()
+            sdf_chunks.iter().map(|shell| shell.len()).sum::<usize>()
         );
     }
 
-    Ok((1.0 / scale, sdf_chunks))
+    Ok(sdf_chunks)
+}
+
+/// Mesh the lattice in parts of at most `max_vertices` vertices. A part that is too large is cut in
+/// two along its longest side (in chunks), at the chunk boundary that balances the vertices best.
+/// The parts are capped at the cut planes, so every part is a closed surface.
+/// Returns the chunks of every part, ordered along the cuts.
+fn build_parts(
+    lattice: &SdfLattice,
+    vertices: &[FFIVector3],
+    indices: &[usize],
+    adaptive: bool,
+    max_vertices: usize,
+) -> Result<Vec<Vec<SdfChunk>>, HallrError> {
+    let mut rv = Vec::new();
+    let mut parts = vec![lattice.chunks_extent];
+    while let Some(part) = parts.pop() {
+        let mesh = build_voxel(
+            lattice,
+            vertices,
+            indices,
+            false,
+            adaptive,
+            Some(part),
+            false,
+        )?
+        .pop()
+        .unwrap_or_default();
+        let vertex_count = mesh.iter().map(|c| c.1.positions.len()).sum::<usize>();
+        if vertex_count == 0 {
+            continue;
+        }
+        if vertex_count <= max_vertices {
+            rv.push(mesh);
+            continue;
+        }
+        let (a, b) = split_part(part, &mesh, lattice.chunk_side).ok_or_else(|| {
+            HallrError::InvalidParameter(format!(
+                "A single chunk has {} vertices, more than SPLIT_MAX_VERTICES :({})",
+                vertex_count, max_vertices
+            ))
+        })?;
+        // the parts are popped in order
+        parts.push(b);
+        parts.push(a);
+    }
+    debug!("SDF mesh split into {} parts", rv.len());
+    Ok(rv)
+}
+
+/// Cut the `part` (in chunk coordinates) in two along its longest side, at the chunk boundary
+/// that divides the vertices of `mesh` most evenly. Returns None if the part is a single chunk.
+fn split_part(
+    part: Extent3i,
+    mesh: &[SdfChunk],
+    un_padded_chunk_side: u32,
+) -> Option<(Extent3i, Extent3i)> {
+    let axis = (0..3)
+        .filter(|axis| part.shape[*axis] > 1)
+        .max_by_key(|axis| part.shape[*axis])?;
+    // the vertex count of every layer of chunks along the axis
+    let mut layers = vec![0_usize; part.shape[axis] as usize];
+    for (padded_minimum, buffer, _) in mesh {
+        // the un-padded chunk starts one voxel inside the padded chunk
+        let chunk = ((padded_minimum[axis] + 1.0) / un_padded_chunk_side as f32).round() as i32;
+        if let Some(layer) = layers.get_mut((chunk - part.minimum[axis]) as usize) {
+            *layer += buffer.positions.len();
+        }
+    }
+    let total: usize = layers.iter().sum();
+    let (cut, _) = (1..layers.len())
+        .scan(0_usize, |below, i| {
+            *below += layers[i - 1];
+            Some((i as i32, below.abs_diff(total - *below)))
+        })
+        .min_by_key(|(_, imbalance)| *imbalance)?;
+    let mut a = part;
+    a.shape[axis] = cut;
+    let mut b = part;
+    b.minimum[axis] += cut;
+    b.shape[axis] -= cut;
+    Some((a, b))
+}
+
+/// Returns the distance from `p` to the line segment `a`-`b`
+fn segment_distance(p: iglam::Vec3A, a: iglam::Vec3A, b: iglam::Vec3A) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let ba_dot = ba.dot(ba);
+    let h = if ba_dot > 0.0 {
+        (pa.dot(ba) / ba_dot).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (pa - ba * h).length()
+}
+
+/// Recursively subdivide the octree node at `node_min` (in chunk coordinates, `node_size` chunks
+/// per side) and return the chunks that may contain a surface, together with the edges that can
+/// affect them.
+/// The SDF changes at most by one per voxel, so every value inside of the node is within half of
+/// the node diagonal from the value at the node center. Nodes where that range can not reach any
+/// of the shells (the offsets are in `min_offset..=0.0`) are dropped, and so are the edges that
+/// can never be the closest one inside of the node.
+/// The `vertices` and the distances are in the stretched (isotropic) voxel scale.
+#[allow(clippy::too_many_arguments)]
+fn octree_chunks(
+    node_min: iglam::IVec3,
+    node_size: i32,
+    un_padded_chunk_side: i32,
+    vertices: &[iglam::Vec3A],
+    indices: &[usize],
+    thickness: f32,
+    min_offset: f32,
+    stretch: iglam::Vec3A,
+) -> Vec<(iglam::IVec3, Vec<usize>)> {
+    // the padded extent of the node, in stretched voxel scale
+    let minimum = (node_min * un_padded_chunk_side - iglam::IVec3::ONE).as_vec3a() * stretch;
+    let maximum =
+        ((node_min + iglam::IVec3::splat(node_size)) * un_padded_chunk_side).as_vec3a() * stretch;
+    let center = (minimum + maximum) * 0.5;
+    let half_diagonal = (maximum - minimum).length() * 0.5;
+
+    let distances: Vec<f32> = indices
+        .chunks_exact(2)
+        .map(|edge| segment_distance(center, vertices[edge[0]], vertices[edge[1]]) - thickness)
+        .collect();
+    let closest = distances.iter().copied().fold(f32::MAX, f32::min);
+    if closest - half_diagonal > 0.0 || closest + half_diagonal <= min_offset {
+        // the node is either entirely outside or entirely inside of every shell
+        return Vec::default();
+    }
+    let indices: Vec<usize> = indices
+        .chunks_exact(2)
+        .zip(distances.iter())
+        .filter(|(_, d)| **d - half_diagonal <= closest + half_diagonal)
+        .flat_map(|(edge, _)| edge.iter().copied())
+        .collect();
+    if node_size <= 1 {
+        return vec![(node_min, indices)];
+    }
+    let half_size = node_size / 2;
+    (0..8_i32)
+        .into_par_iter()
+        .flat_map(|corner| {
+            let child_min = node_min
+                + iglam::ivec3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1) * half_size;
+            octree_chunks(
+                child_min,
+                half_size,
+                un_padded_chunk_side,
+                vertices,
+                &indices,
+                thickness,
+                min_offset,
+                stretch,
+            )
+        })
+        .collect()
+}
+
+/// The capsules affecting a chunk, stored in a structure-of-arrays layout so that the inner
+/// voxel loop can stream through the primitives while evaluating four voxels at a time.
+#[derive(Default)]
+struct CapsuleSoA {
+    ax: Vec<f32>,
+    ay: Vec<f32>,
+    az: Vec<f32>,
+    bax: Vec<f32>,
+    bay: Vec<f32>,
+    baz: Vec<f32>,
+    /// 1/dot(ba,ba), zero for zero length capsules
+    inv_ba_dot: Vec<f32>,
+}
+
+impl CapsuleSoA {
+    fn push(&mut self, a: iglam::Vec3A, b: iglam::Vec3A) {
+        let ba = b - a;
+        let ba_dot = ba.dot(ba);
+        self.ax.push(a.x);
+        self.ay.push(a.y);
+        self.az.push(a.z);
+        self.bax.push(ba.x);
+        self.bay.push(ba.y);
+        self.baz.push(ba.z);
+        self.inv_ba_dot
+            .push(if ba_dot > 0.0 { 1.0 / ba_dot } else { 0.0 });
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ax.is_empty()
+    }
+
+    /// Returns the squared distance to the closest capsule axis for four voxels in a row.
+    /// The voxels share the `py` and `pz` coordinates, `px` holds the four x coordinates.
+    #[inline]
+    fn min_distance_squared_x4(&self, px: iglam::Vec4, py: f32, pz: f32) -> iglam::Vec4 {
+        let mut rv = iglam::Vec4::splat(f32::MAX);
+        for (ax, ay, az, bax, bay, baz, inv_ba_dot) in izip!(
+            self.ax.iter(),
+            self.ay.iter(),
+            self.az.iter(),
+            self.bax.iter(),
+            self.bay.iter(),
+            self.baz.iter(),
+            self.inv_ba_dot.iter()
+        ) {
+            // This is the sdf formula of a capsule, minus the thickness
+            let pa_x = px - iglam::Vec4::splat(*ax);
+            let pa_y = py - *ay;
+            let pa_z = pz - *az;
+            let t = (pa_x * *bax + iglam::Vec4::splat(pa_y * *bay + pa_z * *baz)) * *inv_ba_dot;
+            let h = t.max(iglam::Vec4::ZERO).min(iglam::Vec4::ONE);
+            let dx = pa_x - h * *bax;
+            let dy = iglam::Vec4::splat(pa_y) - h * *bay;
+            let dz = iglam::Vec4::splat(pa_z) - h * *baz;
+            rv = rv.min(dx * dx + dy * dy + dz * dz);
+        }
+        rv
+    }
 }
 
-/// Generate the data of a single chunk
-fn generate_and_process_sdf_chunk(
+/// Generate the data of a single chunk, `PADDED_CHUNK_SIDE` is the un-padded chunk side + 2.
+/// The surface of a shell is where the SDF value equals its offset (the offsets are <= 0, in voxel
+/// scale). Returns the shell index, the chunk minimum and the surface of every shell crossing the
+/// chunk.
+/// The `vertices` and the SDF values are in the stretched voxel scale, a voxel coordinate times
+/// `stretch`. The shells are intersected with the `clip` box (minimum and maximum, in voxel
+/// scale) when there is one.
+fn generate_and_process_sdf_chunk<const PADDED_CHUNK_SIDE: u32>(
     unpadded_chunk_extent: Extent3i,
     vertices: &[iglam::Vec3A],
     indices: &[usize],
     thickness: f32,
-) -> Option<(iglam::Vec3A, SurfaceNetsBuffer)> {
+    shell_offsets: &[f32],
+    stretch: iglam::Vec3A,
+    clip: Option<(iglam::Vec3A, iglam::Vec3A)>,
+) -> Vec<(usize, iglam::Vec3A, SurfaceNetsBuffer)> {
     // the origin of this chunk, in voxel scale
     let padded_chunk_extent = unpadded_chunk_extent.padded(1);
 
     // filter out the edges that does not affect this chunk
-    let filtered_edges: Vec<_> = indices
-        .par_chunks_exact(2)
-        .filter_map(|edge| {
-            let (e0, e1) = (edge[0], edge[1]);
-
-            let tube_extent = Extent::from_min_and_lub(
-                vertices[e0].min(vertices[e1]) - iglam::Vec3A::splat(thickness),
-                vertices[e0].max(vertices[e1]) + iglam::Vec3A::splat(thickness),
-            )
-            .containing_integer_extent();
-            if !padded_chunk_extent.intersection(&tube_extent).is_empty() {
-                // The AABB of the edge tube intersected this chunk - keep it
-                Some((e0, e1))
-            } else {
-                None
-            }
-        })
-        .collect();
+    let mut capsules = CapsuleSoA::default();
+    for edge in indices.chunks_exact(2) {
+        let (v0, v1) = (vertices[edge[0]], vertices[edge[1]]);
 
-    #[cfg(not(feature = "display_sdf_chunks"))]
-    if filtered_edges.is_empty() {
-        // no tubes intersected this chunk
-        return None;
+        let tube_extent = Extent::from_min_and_lub(
+            (v0.min(v1) - iglam::Vec3A::splat(thickness)) / stretch,
+            (v0.max(v1) + iglam::Vec3A::splat(thickness)) / stretch,
+        )
+        .containing_integer_extent();
+        if !padded_chunk_extent.intersection(&tube_extent).is_empty() {
+            // The AABB of the edge tube intersected this chunk - keep it
+            capsules.push(v0, v1);
+        }
     }
 
-    let mut array = { [DEFAULT_SDF_VALUE; PaddedChunkShape::SIZE as usize] };
-
-    #[cfg(feature = "display_sdf_chunks")]
-    // The corners of the un-padded chunk extent
-    let corners: Vec<_> = unpadded_chunk_extent
-        .corners3()
-        .iter()
-        .map(|p| p.as_vec3a())
-        .collect();
+    if capsules.is_empty() {
+        // no tubes intersected this chunk
+        return Vec::default();
+    }
 
-    let mut some_neg_or_zero_found = false;
-    let mut some_pos_found = false;
+    let mut array = vec![DEFAULT_SDF_VALUE; PaddedChunkShape::<PADDED_CHUNK_SIDE>::SIZE as usize];
 
-    for pwo in padded_chunk_extent.iter3() {
-        let v = {
-            let p = pwo - unpadded_chunk_extent.minimum + 1;
-            &mut array[PaddedChunkShape::linearize([p.x as u32, p.y as u32, p.z as u32]) as usize]
-        };
-        let pwo = pwo.as_vec3a();
-        // Point With Offset from the un-padded extent minimum
-        #[cfg(feature = "display_sdf_chunks")]
-        {
-            // todo: this could probably be optimized with PaddedChunkShape::linearize(corner_pos)
-            let mut x = *v;
-            for c in corners.iter() {
-                x = x.min(c.distance(pwo) - 1.);
+    let minimum = padded_chunk_extent.minimum;
+    let lane_offsets = iglam::Vec4::new(0.0, 1.0, 2.0, 3.0);
+    for z in 0..PADDED_CHUNK_SIDE {
+        let pz = (minimum.z + z as i32) as f32 * stretch.z;
+        for y in 0..PADDED_CHUNK_SIDE {
+            let py = (minimum.y + y as i32) as f32 * stretch.y;
+            // x is the fastest moving axis, so a row of voxels is continuous in the array
+            let row = PaddedChunkShape::<PADDED_CHUNK_SIDE>::linearize([0, y, z]) as usize;
+            // evaluate four voxels at a time
+            for x in (0..PADDED_CHUNK_SIDE).step_by(4) {
+                let px =
+                    (iglam::Vec4::splat((minimum.x + x as i32) as f32) + lane_offsets) * stretch.x;
+                let distances_squared = capsules.min_distance_squared_x4(px, py, pz).to_array();
+                let lanes = (PADDED_CHUNK_SIDE - x).min(4) as usize;
+                for (lane, distance_squared) in distances_squared.iter().enumerate().take(lanes) {
+                    let v = &mut array[row + x as usize + lane];
+                    *v = (*v).min(distance_squared.sqrt() - thickness);
+                }
             }
-            *v = (*v).min(x);
-        }
-        for (from_v, to_v) in filtered_edges
-            .iter()
-            .map(|(e0, e1)| (vertices[*e0], vertices[*e1]))
-        {
-            // This is the sdf formula of a capsule
-            let pa = pwo - from_v;
-            let ba = to_v - from_v;
-            let t = pa.dot(ba) / ba.dot(ba);
-            let h = t.clamp(0.0, 1.0);
-            *v = (*v).min((pa - (ba * h)).length() - thickness);
-        }
-        if *v > 0.0 {
-            some_pos_found = true;
-        } else {
-            some_neg_or_zero_found = true;
         }
     }
-    if some_pos_found && some_neg_or_zero_found {
-        // A combination of positive and negative surfaces found - process this chunk
-        let mut sn_buffer = SurfaceNetsBuffer::default();
-
-        // do the voxel_size multiplication later, vertices pos. needs to match extent.
-        surface_nets(
-            &array,
-            &PaddedChunkShape {},
-            [0; 3],
-            [UN_PADDED_CHUNK_SIDE + 1; 3],
-            &mut sn_buffer,
-        );
+    // the SDF of the clip box, positive outside of it
+    let clip_array: Option<Vec<f32>> = clip.map(|(lo, hi)| {
+        (0..PaddedChunkShape::<PADDED_CHUNK_SIDE>::SIZE)
+            .map(|i| {
+                let [x, y, z] = PaddedChunkShape::<PADDED_CHUNK_SIDE>::delinearize(i);
+                let p = minimum.as_vec3a() + iglam::vec3a(x as f32, y as f32, z as f32);
+                ((lo - p).max(p - hi) * stretch).max_element()
+            })
+            .collect()
+    });
+    shell_offsets
+        .iter()
+        .enumerate()
+        .filter_map(|(shell, offset)| {
+            let array: Cow<'_, [f32]> = match &clip_array {
+                None if *offset == 0.0 => Cow::Borrowed(&array),
+                None => Cow::Owned(array.iter().map(|v| v - offset).collect()),
+                Some(clip_array) => Cow::Owned(
+                    array
+                        .iter()
+                        .zip(clip_array.iter())
+                        .map(|(v, c)| (v - offset).max(*c))
+                        .collect(),
+                ),
+            };
+            let some_pos_found = array.iter().any(|v| *v > 0.0);
+            let some_neg_or_zero_found = array.iter().any(|v| *v <= 0.0);
+            if some_pos_found && some_neg_or_zero_found {
+                // A combination of positive and negative surfaces found - process this chunk
+                process_sdf_chunk::<PADDED_CHUNK_SIDE>(&array, padded_chunk_extent.minimum)
+                    .map(|(minimum, buffer)| (shell, minimum, buffer))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
 
-        if sn_buffer.positions.is_empty() {
-            // No vertices were generated by this chunk, ignore it
-            None
-        } else {
-            Some((padded_chunk_extent.minimum.as_vec3a(), sn_buffer))
-        }
-    } else {
+/// Run surface nets on the SDF values of a single padded chunk
+fn process_sdf_chunk<const PADDED_CHUNK_SIDE: u32>(
+    array: &[f32],
+    padded_chunk_minimum: iglam::IVec3,
+) -> Option<(iglam::Vec3A, SurfaceNetsBuffer)> {
+    let mut sn_buffer = SurfaceNetsBuffer::default();
+
+    // do the voxel_size multiplication later, vertices pos. needs to match extent.
+    surface_nets(
+        array,
+        &PaddedChunkShape::<PADDED_CHUNK_SIDE> {},
+        [0; 3],
+        [PADDED_CHUNK_SIDE - 1; 3],
+        &mut sn_buffer,
+    );
+
+    if sn_buffer.positions.is_empty() {
+        // No vertices were generated by this chunk, ignore it
         None
+    } else {
+        Some((padded_chunk_minimum.as_vec3a(), sn_buffer))
     }
 }
 
-/// Build the return model
+/// Evaluate the SDF on the GPU, and then run surface nets on the returned chunks
+#[cfg(feature = "wgpu_sdf")]
+fn generate_sdf_chunks_on_gpu(
+    chunks_extent: Extent3i,
+    un_padded_chunk_side: u32,
+    vertices: &[iglam::Vec3A],
+    indices: &[usize],
+    thickness: f32,
+) -> Result<Vec<SdfChunk>, HallrError> {
+    let capsules: Vec<_> = indices
+        .chunks_exact(2)
+        .map(|edge| (vertices[edge[0]], vertices[edge[1]]))
+        .collect();
+    let tube_extents: Vec<Extent3i> = capsules
+        .iter()
+        .map(|(v0, v1)| {
+            Extent::from_min_and_lub(
+                v0.min(*v1) - iglam::Vec3A::splat(thickness),
+                v0.max(*v1) + iglam::Vec3A::splat(thickness),
+            )
+            .containing_integer_extent()
+        })
+        .collect();
+    let unpadded_chunk_shape = iglam::IVec3::splat(un_padded_chunk_side as i32);
+    // only send the chunks that intersects with at least one tube
+    let chunk_mins: Vec<iglam::IVec3> = chunks_extent
+        .iter3()
+        .filter_map(|p| {
+            let padded_chunk_extent =
+                Extent3i::from_min_and_shape(p * unpadded_chunk_shape, unpadded_chunk_shape)
+                    .padded(1);
+            tube_extents
+                .iter()
+                .any(|e| !padded_chunk_extent.intersection(e).is_empty())
+                .then_some(padded_chunk_extent.minimum)
+        })
+        .collect();
+
+    let sdf_chunks =
+        gpu::evaluate_capsule_chunks(&capsules, thickness, &chunk_mins, un_padded_chunk_side + 2)?;
+    Ok(sdf_chunks
+        .into_par_iter()
+        .filter_map(|(minimum, array)| match un_padded_chunk_side {
+            8 => process_sdf_chunk::<10>(&array, minimum),
+            30 => process_sdf_chunk::<32>(&array, minimum),
+            62 => process_sdf_chunk::<64>(&array, minimum),
+            _ => process_sdf_chunk::<16>(&array, minimum),
+        })
+        .map(|(offset, buffer)| (offset, buffer, None))
+        .collect())
+}
+
+/// Placeholder used when the crate is built without the `wgpu_sdf` feature
+#[cfg(not(feature = "wgpu_sdf"))]
+fn generate_sdf_chunks_on_gpu(
+    _chunks_extent: Extent3i,
+    _un_padded_chunk_side: u32,
+    _vertices: &[iglam::Vec3A],
+    _indices: &[usize],
+    _thickness: f32,
+) -> Result<Vec<SdfChunk>, HallrError> {
+    Err(HallrError::InvalidParameter(
+        "SDF_BACKEND=gpu requires a build with the 'wgpu_sdf' feature".to_string(),
+    ))
+}
+
+/// Build the return model, `voxel_size` is the side of a voxel along each axis
 pub(crate) fn build_output_model(
     //pb_model_name: String,
     //pb_world: Option<PB_Matrix4x432>,
-    voxel_size: f32,
-    mesh_buffers: Vec<(iglam::Vec3A, SurfaceNetsBuffer)>,
+    voxel_size: iglam::Vec3A,
+    mesh_buffers: Vec<SdfChunk>,
     verbose: bool,
 ) -> Result<OwnedModel, HallrError> {
     let now = time::Instant::now();
@@ -271,7 +928,7 @@ pub(crate) fn build_output_model(
         )
     };
 
-    for (vertex_offset, mesh_buffer) in mesh_buffers.iter() {
+    for (vertex_offset, mesh_buffer, _) in mesh_buffers.iter() {
         // each chunk starts counting vertices from zero
         let indices_offset = vertices.len() as u32;
 
@@ -279,9 +936,9 @@ pub(crate) fn build_output_model(
 
         for pv in mesh_buffer.positions.iter() {
             vertices.push(FFIVector3 {
-                x: (voxel_size * (pv[0] + vertex_offset.x)),
-                y: (voxel_size * (pv[1] + vertex_offset.y)),
-                z: (voxel_size * (pv[2] + vertex_offset.z)),
+                x: (voxel_size.x * (pv[0] + vertex_offset.x)),
+                y: (voxel_size.y * (pv[1] + vertex_offset.y)),
+                z: (voxel_size.z * (pv[2] + vertex_offset.z)),
             });
         }
 
@@ -291,7 +948,7 @@ pub(crate) fn build_output_model(
     }
 
     if verbose {
-        println!(
+        debug!(
             "Vertex return model packaging duration: {:?}",
             now.elapsed()
         );
@@ -304,7 +961,178 @@ pub(crate) fn build_output_model(
     })
 }
 
-/// Run the voronoi_mesh command
+/// The edges of a box, the corner index bits are (x, y, z)
+const BOX_EDGES: [usize; 24] = [
+    0, 1, 1, 3, 3, 2, 2, 0, 4, 5, 5, 7, 7, 6, 6, 4, 0, 4, 1, 5, 2, 6, 3, 7,
+];
+
+/// The per chunk information returned when `DEBUG_CHUNKS=true`
+pub(crate) struct ChunkStats {
+    /// the padded chunk minimum, in voxel scale
+    padded_minimum: iglam::Vec3A,
+    vertices: usize,
+    faces: usize,
+    duration: Option<time::Duration>,
+}
+
+/// Collect the debug information of the chunks, this must be done before the chunks are
+/// consumed by `build_output_model()`
+pub(crate) fn chunk_stats(chunks: &[SdfChunk]) -> Vec<ChunkStats> {
+    chunks
+        .iter()
+        .map(|(padded_minimum, buffer, duration)| ChunkStats {
+            padded_minimum: *padded_minimum,
+            vertices: buffer.positions.len(),
+            faces: buffer.indices.len() / 3,
+            duration: *duration,
+        })
+        .collect()
+}
+
+/// Append the wireframe boxes of the chunk boundaries to the output model as a separate
+/// line_chunks segment (number `segment`), and insert the per chunk statistics (as JSON) into the
+/// return config. `voxel_size` is the side of a voxel along each axis.
+pub(crate) fn add_debug_chunks(
+    voxel_size: iglam::Vec3A,
+    un_padded_chunk_side: u32,
+    stats: &[ChunkStats],
+    segment: usize,
+    output_model: &mut OwnedModel,
+    return_config: &mut ConfigType,
+) {
+    let _ = return_config.insert(
+        format!("first_vertex_model_{}", segment),
+        output_model.vertices.len().to_string(),
+    );
+    let _ = return_config.insert(
+        format!("first_index_model_{}", segment),
+        output_model.indices.len().to_string(),
+    );
+    let _ = return_config.insert(
+        format!("mesh.format_model_{}", segment),
+        "line_chunks".to_string(),
+    );
+
+    let side = un_padded_chunk_side as f32;
+    let mut json = String::from("[");
+    for (i, chunk) in stats.iter().enumerate() {
+        // the un-padded chunk starts one voxel inside the padded chunk
+        let minimum = chunk.padded_minimum + iglam::Vec3A::ONE;
+        let first_corner = output_model.vertices.len();
+        for corner in 0..8 {
+            let p = minimum
+                + iglam::vec3a(
+                    (corner & 1) as f32 * side,
+                    ((corner >> 1) & 1) as f32 * side,
+                    ((corner >> 2) & 1) as f32 * side,
+                );
+            let p = p * voxel_size;
+            output_model.vertices.push(FFIVector3::new(p.x, p.y, p.z));
+        }
+        output_model
+            .indices
+            .extend(BOX_EDGES.iter().map(|e| first_corner + *e));
+
+        if i > 0 {
+            json.push(',');
+        }
+        let _ = write!(
+            json,
+            "{{\"min\":[{},{},{}],\"vertices\":{},\"faces\":{},\"micros\":{}}}",
+            minimum.x * voxel_size.x,
+            minimum.y * voxel_size.y,
+            minimum.z * voxel_size.z,
+            chunk.vertices,
+            chunk.faces,
+            chunk
+                .duration
+                .map_or_else(|| "null".to_string(), |d| d.as_micros().to_string())
+        );
+    }
+    json.push(']');
+    let _ = return_config.insert("DEBUG_CHUNKS_STATS".to_string(), json);
+
+    if let Some(slowest) = stats.iter().filter_map(|c| c.duration).max() {
+        let total: time::Duration = stats.iter().filter_map(|c| c.duration).sum();
+        debug!(
+            "DEBUG_CHUNKS: {} chunks, total chunk time:{:?}, slowest chunk:{:?}",
+            stats.len(),
+            total,
+            slowest
+        );
+    }
+}
+
+/// Returns the predicted size of an sdf_mesh run, without any geometry
+fn dry_run(
+    lattice: &SdfLattice,
+    model: &Model<'_>,
+    local_frame: bool,
+) -> Result<super::CommandResult, HallrError> {
+    let grid = lattice.chunk_grid();
+    let vertex_estimate = lattice.vertex_estimate(skeleton_length(model));
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = return_config.insert(
+        "SDF_CHUNK_GRID".to_string(),
+        format!("{},{},{}", grid[0], grid[1], grid[2]),
+    );
+    let _ = return_config.insert(
+        "SDF_CHUNK_COUNT".to_string(),
+        lattice.chunk_count().to_string(),
+    );
+    let _ = return_config.insert("SDF_CHUNK_SIDE".to_string(), lattice.chunk_side.to_string());
+    let _ = return_config.insert(
+        "SDF_VOXEL_SIZE".to_string(),
+        lattice.voxel_size().to_string(),
+    );
+    for (key, size) in ["SDF_VOXEL_SIZE_X", "SDF_VOXEL_SIZE_Y", "SDF_VOXEL_SIZE_Z"]
+        .into_iter()
+        .zip(lattice.voxel_sizes())
+    {
+        let _ = return_config.insert(key.to_string(), size.to_string());
+    }
+    let _ = return_config.insert(
+        "SDF_VERTEX_ESTIMATE".to_string(),
+        vertex_estimate.to_string(),
+    );
+    if local_frame {
+        let _ = return_config.insert(super::LOCAL_FRAME_KEY.to_string(), "true".to_string());
+    }
+    debug!(
+        "SDF mesh dry run: chunk grid:{:?}, chunks:{}, voxel size:{:?}, estimated vertices:{}",
+        grid,
+        lattice.chunk_count(),
+        lattice.voxel_sizes(),
+        vertex_estimate
+    );
+    Ok((
+        Vec::new(),
+        Vec::new(),
+        model.output_orientation(local_frame)?.to_vec(),
+        return_config,
+    ))
+}
+
+/// Run the sdf_mesh command
+/// With `MULTI_OFFSETS=d1,d2,...` one shell is returned per offset, at the tube radius plus that
+/// offset (in model units). The shells are separate output segments, the SDF is only evaluated
+/// once. The number of shells is returned as `SHELL_COUNT`.
+/// With `ADAPTIVE=true` the chunks are selected by an octree subdivision of the lattice, only the
+/// chunks that may contain a surface are evaluated. This saves a lot of time on sparse skeletons
+/// with large `SDF_DIVISIONS`, the resulting mesh is the same.
+/// With `SDF_DIVISIONS_X`, `SDF_DIVISIONS_Y` or `SDF_DIVISIONS_Z` the voxels are anisotropic: the
+/// number of voxels along the longest side is set per axis, e.g. a finer Z for thin reliefs.
+/// This is not supported by `SDF_BACKEND=gpu`.
+/// With `SPLIT_MAX_VERTICES=n` the mesh is returned in spatially coherent parts of at most n
+/// vertices, e.g. for printing a large model in pieces. The lattice is cut in two at chunk
+/// boundaries until every part is small enough, and each part is capped at its cut planes.
+/// The parts are separate output segments, their number is returned as `PART_COUNT`.
+/// With `DRY_RUN=true` nothing is meshed, the lattice is returned as `SDF_CHUNK_GRID` (chunks along
+/// X,Y,Z), `SDF_CHUNK_COUNT`, `SDF_VOXEL_SIZE` (the largest voxel side), `SDF_VOXEL_SIZE_X/Y/Z` and
+/// a rough `SDF_VERTEX_ESTIMATE`.
+/// A meshing run returns the same `SDF_CHUNK_COUNT` (every chunk of the lattice), and the number of
+/// chunks that produced a surface as `SDF_SURFACE_CHUNK_COUNT`.
 pub(crate) fn process_command(
     config: ConfigType,
     models: Vec<Model<'_>>,
@@ -321,41 +1149,150 @@ pub(crate) fn process_command(
         ));
     }
 
-    let cmd_arg_sdf_radius_multiplier =
-        config.get_mandatory_parsed_option::<f32>("SDF_RADIUS_MULTIPLIER", None)? / 100.0;
+    let cmd_arg_debug_chunks =
+        config.get_mandatory_parsed_option::<bool>("DEBUG_CHUNKS", Some(false))?;
+    let cmd_arg_local_frame =
+        config.get_mandatory_parsed_option::<bool>(super::LOCAL_FRAME_KEY, Some(false))?;
+    let cmd_arg_adaptive = config.get_mandatory_parsed_option::<bool>("ADAPTIVE", Some(false))?;
+    let cmd_arg_use_gpu = match config
+        .get_mandatory_parsed_option::<String>("SDF_BACKEND", Some("cpu".to_string()))?
+        .as_str()
+    {
+        "cpu" => false,
+        "gpu" => true,
+        backend => {
+            return Err(HallrError::InvalidParameter(format!(
+                "The valid values of SDF_BACKEND are \"cpu\" and \"gpu\" :({})",
+                backend
+            )))
+        }
+    };
 
-    let cmd_arg_sdf_divisions: f32 = config.get_mandatory_parsed_option("SDF_DIVISIONS", None)?;
-    if !(9.9..600.1).contains(&cmd_arg_sdf_divisions) {
-        return Err(HallrError::InvalidInputData(format!(
-            "The valid range of SDF_DIVISIONS is [{}..{}[% :({})",
-            10, 600, cmd_arg_sdf_divisions
-        )));
+    let cmd_arg_dry_run = config.get_mandatory_parsed_option::<bool>("DRY_RUN", Some(false))?;
+    let cmd_arg_split_max_vertices = config.get_parsed_option::<usize>("SPLIT_MAX_VERTICES")?;
+    if cmd_arg_split_max_vertices == Some(0) {
+        return Err(HallrError::InvalidParameter(
+            "SPLIT_MAX_VERTICES must be positive".to_string(),
+        ));
     }
 
     // we already tested a_command.models.len()
     let input_model = &models[0];
 
-    println!("model.vertices:{:?}, ", input_model.vertices.len());
+    debug!("model.vertices:{:?}, ", input_model.vertices.len());
 
     let aabb = parse_input(input_model)?;
-    let (voxel_size, mesh) = build_voxel(
-        cmd_arg_sdf_radius_multiplier,
-        cmd_arg_sdf_divisions,
-        input_model.vertices,
-        input_model.indices,
-        aabb,
-        true,
-    )?;
-
-    let output_model = build_output_model(voxel_size, mesh, true)?;
+    let lattice = parse_lattice(&config, aabb)?;
+    let cmd_arg_sdf_chunk_side = lattice.chunk_side;
+    if cmd_arg_dry_run {
+        return dry_run(&lattice, input_model, cmd_arg_local_frame);
+    }
+    let voxel_size = iglam::Vec3A::from(lattice.voxel_sizes());
+    let shell_count = lattice.radii.len();
+    // the output segments, one per shell or one per part
+    let segments = if let Some(max_vertices) = cmd_arg_split_max_vertices {
+        if cmd_arg_use_gpu || shell_count > 1 {
+            return Err(HallrError::InvalidParameter(
+                "SPLIT_MAX_VERTICES can not be combined with SDF_BACKEND=gpu or MULTI_OFFSETS"
+                    .to_string(),
+            ));
+        }
+        build_parts(
+            &lattice,
+            input_model.vertices,
+            input_model.indices,
+            cmd_arg_adaptive,
+            max_vertices,
+        )?
+    } else {
+        build_voxel(
+            &lattice,
+            input_model.vertices,
+            input_model.indices,
+            cmd_arg_use_gpu,
+            cmd_arg_adaptive,
+            None,
+            true,
+        )?
+    };
+    // the chunks with a surface, summed over the shells and parts
+    let surface_chunk_count = segments.iter().map(|segment| segment.len()).sum::<usize>();
+    let debug_chunks = cmd_arg_debug_chunks.then(|| {
+        segments
+            .iter()
+            .flat_map(|segment| chunk_stats(segment))
+            .collect::<Vec<_>>()
+    });
+    let segment_count = segments.len();
 
     let mut return_config = ConfigType::new();
     let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
     let _ = return_config.insert("REMOVE_DOUBLES".to_string(), "true".to_string());
-    println!(
-        "SDF mesh operation returning {} vertices, {} indices",
+    let mut output_model = OwnedModel {
+        world_orientation: input_model.output_orientation(cmd_arg_local_frame)?,
+        vertices: Vec::default(),
+        indices: Vec::default(),
+    };
+    for (segment, mesh) in segments.into_iter().enumerate() {
+        let segment_model = build_output_model(voxel_size, mesh, true)?;
+        let first_vertex = output_model.vertices.len();
+        if segment > 0 {
+            // every shell (or part) is a separate segment
+            let _ = return_config.insert(
+                format!("first_vertex_model_{}", segment),
+                first_vertex.to_string(),
+            );
+            let _ = return_config.insert(
+                format!("first_index_model_{}", segment),
+                output_model.indices.len().to_string(),
+            );
+            let _ = return_config.insert(
+                format!("mesh.format_model_{}", segment),
+                "triangulated".to_string(),
+            );
+        }
+        output_model.vertices.extend(segment_model.vertices);
+        output_model
+            .indices
+            .extend(segment_model.indices.into_iter().map(|i| i + first_vertex));
+    }
+    let _ = return_config.insert("SHELL_COUNT".to_string(), shell_count.to_string());
+    if cmd_arg_split_max_vertices.is_some() {
+        let _ = return_config.insert("PART_COUNT".to_string(), segment_count.to_string());
+    }
+    if let Some(debug_chunks) = debug_chunks {
+        add_debug_chunks(
+            voxel_size,
+            cmd_arg_sdf_chunk_side,
+            &debug_chunks,
+            segment_count,
+            &mut output_model,
+            &mut return_config,
+        );
+    }
+    let _ = return_config.insert(
+        "SDF_CHUNK_SIDE".to_string(),
+        cmd_arg_sdf_chunk_side.to_string(),
+    );
+    let _ = return_config.insert(
+        "SDF_CHUNK_COUNT".to_string(),
+        lattice.chunk_count().to_string(),
+    );
+    let _ = return_config.insert(
+        "SDF_SURFACE_CHUNK_COUNT".to_string(),
+        surface_chunk_count.to_string(),
+    );
+    if cmd_arg_local_frame {
+        let _ = return_config.insert(super::LOCAL_FRAME_KEY.to_string(), "true".to_string());
+    }
+    info!(
+        "SDF mesh operation returning {} vertices, {} indices, chunk side:{}, chunks:{} ({} with \
+         a surface)",
         output_model.vertices.len(),
-        output_model.indices.len()
+        output_model.indices.len(),
+        cmd_arg_sdf_chunk_side,
+        lattice.chunk_count(),
+        surface_chunk_count
     );
     Ok((
         output_model.vertices,
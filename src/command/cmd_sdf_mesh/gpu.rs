@@ -0,0 +1,302 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! A wgpu compute path for the capsule SDF evaluation.
+//! The SDF of each chunk is evaluated on the GPU, only the chunks containing a surface crossing
+//! are copied back to the CPU. The surface nets step is still done on the CPU.
+
+use crate::HallrError;
+use ilattice::glam as iglam;
+use std::{mem, sync::mpsc};
+use wgpu::util::DeviceExt;
+
+/// The voxel evaluation shader, `999.0` is the same as `DEFAULT_SDF_VALUE`
+const SDF_CAPSULE_SHADER: &str = r#"
+struct Params {
+    side: u32,
+    chunk_count: u32,
+    capsule_count: u32,
+    thickness: f32,
+};
+
+struct Capsule {
+    a: vec4<f32>,
+    b: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> capsules: array<Capsule>;
+@group(0) @binding(2) var<storage, read> chunk_mins: array<vec4<i32>>;
+@group(0) @binding(3) var<storage, read_write> sdf: array<f32>;
+@group(0) @binding(4) var<storage, read_write> flags: array<atomic<u32>>;
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    let voxels = params.side * params.side * params.side;
+    let index = id.x + id.y * groups.x * 64u;
+    if (index >= params.chunk_count * voxels) {
+        return;
+    }
+    let chunk = index / voxels;
+    let local = index % voxels;
+    // same order as ConstShape3u32::linearize(): x is the fastest moving axis
+    let offset = vec3<i32>(
+        i32(local % params.side),
+        i32((local / params.side) % params.side),
+        i32(local / (params.side * params.side)),
+    );
+    let p = vec3<f32>(chunk_mins[chunk].xyz + offset);
+    var value = 999.0;
+    for (var i = 0u; i < params.capsule_count; i = i + 1u) {
+        // This is the sdf formula of a capsule
+        let a = capsules[i].a.xyz;
+        let ba = capsules[i].b.xyz - a;
+        let pa = p - a;
+        let ba_dot = dot(ba, ba);
+        let h = select(0.0, clamp(dot(pa, ba) / ba_dot, 0.0, 1.0), ba_dot > 0.0);
+        value = min(value, length(pa - ba * h) - params.thickness);
+    }
+    sdf[index] = value;
+    if (value > 0.0) {
+        atomicOr(&flags[chunk], 1u);
+    } else {
+        atomicOr(&flags[chunk], 2u);
+    }
+}
+"#;
+
+/// Both positive and negative (or zero) SDF values were found in the chunk
+const SURFACE_CROSSING: u32 = 3;
+const WORKGROUP_SIZE: u32 = 64;
+
+struct GpuContext {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    limits: wgpu::Limits,
+}
+
+impl GpuContext {
+    fn new() -> Result<Self, HallrError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        }))
+        .ok_or_else(|| HallrError::InternalError("No suitable GPU adapter found".to_string()))?;
+        let limits = adapter.limits();
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("hallr sdf"),
+                features: wgpu::Features::empty(),
+                limits: limits.clone(),
+            },
+            None,
+        ))
+        .map_err(|e| HallrError::InternalError(format!("Could not open the GPU device: {}", e)))?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sdf capsule shader"),
+            source: wgpu::ShaderSource::Wgsl(SDF_CAPSULE_SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("sdf capsule pipeline"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+        });
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+            limits,
+        })
+    }
+
+    /// Copy `size` bytes from each of the `ranges` of `source` into a mappable buffer and read
+    /// it back.
+    fn read_back<T: bytemuck::Pod>(
+        &self,
+        source: &wgpu::Buffer,
+        ranges: &[wgpu::BufferAddress],
+        size: wgpu::BufferAddress,
+    ) -> Result<Vec<T>, HallrError> {
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size: size * ranges.len() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for (i, offset) in ranges.iter().enumerate() {
+            encoder.copy_buffer_to_buffer(
+                source,
+                *offset,
+                &staging,
+                i as wgpu::BufferAddress * size,
+                size,
+            );
+        }
+        let _ = self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        let _ = self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|e| HallrError::InternalError(format!("GPU read back failed: {}", e)))?
+            .map_err(|e| HallrError::InternalError(format!("GPU read back failed: {}", e)))?;
+        let rv = bytemuck::cast_slice::<u8, T>(&slice.get_mapped_range()).to_vec();
+        staging.unmap();
+        Ok(rv)
+    }
+}
+
+/// Evaluate the capsule SDF of every chunk on the GPU.
+/// `chunk_mins` are the minimum corners of the padded chunks, `padded_chunk_side` is the side
+/// of the padded chunk. The capsules are given in voxel scale.
+/// Only the chunks with a surface crossing are returned, the voxel order matches
+/// `ConstShape3u32::linearize()`.
+pub(super) fn evaluate_capsule_chunks(
+    capsules: &[(iglam::Vec3A, iglam::Vec3A)],
+    thickness: f32,
+    chunk_mins: &[iglam::IVec3],
+    padded_chunk_side: u32,
+) -> Result<Vec<(iglam::IVec3, Vec<f32>)>, HallrError> {
+    if capsules.is_empty() || chunk_mins.is_empty() {
+        return Ok(Vec::default());
+    }
+    let context = GpuContext::new()?;
+
+    let voxels_per_chunk = padded_chunk_side.pow(3);
+    let chunk_bytes = (voxels_per_chunk as usize * mem::size_of::<f32>()) as u64;
+    let max_binding = (context.limits.max_storage_buffer_binding_size as u64)
+        .min(context.limits.max_buffer_size)
+        .min(1 << 26);
+    let batch_size = ((max_binding / chunk_bytes) as usize).max(1);
+
+    let capsule_data: Vec<[f32; 8]> = capsules
+        .iter()
+        .map(|(a, b)| [a.x, a.y, a.z, 0.0, b.x, b.y, b.z, 0.0])
+        .collect();
+    let capsule_buffer = context
+        .device
+        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("capsules"),
+            contents: bytemuck::cast_slice(&capsule_data),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+    let bind_group_layout = context.pipeline.get_bind_group_layout(0);
+
+    let mut rv = Vec::<(iglam::IVec3, Vec<f32>)>::new();
+    for batch in chunk_mins.chunks(batch_size) {
+        let params: [u32; 4] = [
+            padded_chunk_side,
+            batch.len() as u32,
+            capsules.len() as u32,
+            thickness.to_bits(),
+        ];
+        let params_buffer = context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("params"),
+                contents: bytemuck::cast_slice(&params),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let min_data: Vec<[i32; 4]> = batch.iter().map(|m| [m.x, m.y, m.z, 0]).collect();
+        let min_buffer = context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("chunk mins"),
+                contents: bytemuck::cast_slice(&min_data),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let sdf_buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sdf"),
+            size: chunk_bytes * batch.len() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let flag_data = vec![0_u32; batch.len()];
+        let flag_buffer = context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("flags"),
+                contents: bytemuck::cast_slice(&flag_data),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            });
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: capsule_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: min_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: sdf_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: flag_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+        let workgroups = (voxels_per_chunk * batch.len() as u32).div_ceil(WORKGROUP_SIZE);
+        let workgroups_x = workgroups.min(context.limits.max_compute_workgroups_per_dimension);
+        let workgroups_y = workgroups.div_ceil(workgroups_x);
+        let mut encoder = context
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&context.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(workgroups_x, workgroups_y, 1);
+        }
+        let _ = context.queue.submit(Some(encoder.finish()));
+
+        // read back the flags first, then only the chunks with a surface crossing
+        let flags: Vec<u32> = context.read_back(&flag_buffer, &[0], 4 * batch.len() as u64)?;
+        let crossing: Vec<usize> = flags
+            .iter()
+            .enumerate()
+            .filter(|(_, flag)| **flag == SURFACE_CROSSING)
+            .map(|(i, _)| i)
+            .collect();
+        if crossing.is_empty() {
+            continue;
+        }
+        let offsets: Vec<u64> = crossing.iter().map(|i| *i as u64 * chunk_bytes).collect();
+        let data: Vec<f32> = context.read_back(&sdf_buffer, &offsets, chunk_bytes)?;
+        for (n, i) in crossing.iter().enumerate() {
+            let range = n * voxels_per_chunk as usize..(n + 1) * voxels_per_chunk as usize;
+            rv.push((batch[*i], data[range].to_vec()));
+        }
+    }
+    Ok(rv)
+}
@@ -33,3 +33,400 @@ fn test_sdf_mesh_1() -> Result<(), HallrError> {
     assert_eq!(3888, result.1.len()); // indices
     Ok(())
 }
+
+#[test]
+fn test_sdf_mesh_2() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "sdf_mesh".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "50".to_string());
+    let _ = config.insert("SDF_RADIUS_MULTIPLIER".to_string(), "1.0".to_string());
+    let _ = config.insert("SDF_CHUNK_SIDE".to_string(), "30".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (1.203918, 1.203918, 1.0).into(),
+            (-1.805877, 0.74801874, 0.0).into(),
+            (0.0, -1.7025971, 0.0).into(),
+            (-0.36410117, 0.33949375, -1.0).into(),
+            (0.25582898, -0.17708552, 0.0).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 1, 2],
+    };
+
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert!(!result.0.is_empty()); // vertices
+    assert_eq!(0, result.1.len() % 3); // indices
+    assert_eq!(Some(&"30".to_string()), result.3.get("SDF_CHUNK_SIDE"));
+    assert!(result.3.contains_key("SDF_CHUNK_COUNT"));
+    Ok(())
+}
+
+#[test]
+fn test_sdf_mesh_3() {
+    // 16 is not one of the chunk side presets
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "sdf_mesh".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "50".to_string());
+    let _ = config.insert("SDF_RADIUS_MULTIPLIER".to_string(), "1.0".to_string());
+    let _ = config.insert("SDF_CHUNK_SIDE".to_string(), "16".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(0.0, 0.0, 0.0).into(), (1.0, 0.0, 0.0).into()],
+        indices: vec![0, 1],
+    };
+
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models).is_err());
+}
+
+#[test]
+fn test_sdf_mesh_debug_chunks() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "sdf_mesh".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "50".to_string());
+    let _ = config.insert("SDF_RADIUS_MULTIPLIER".to_string(), "1.0".to_string());
+    let _ = config.insert("DEBUG_CHUNKS".to_string(), "true".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (1.203918, 1.203918, 1.0).into(),
+            (-1.805877, 0.74801874, 0.0).into(),
+            (0.0, -1.7025971, 0.0).into(),
+            (-0.36410117, 0.33949375, -1.0).into(),
+            (0.25582898, -0.17708552, 0.0).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 1, 2],
+    };
+
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    let chunk_count: usize = result.3["SDF_SURFACE_CHUNK_COUNT"].parse().unwrap();
+    // the mesh is the same as in test_sdf_mesh_1, followed by one wireframe box per meshed chunk
+    assert_eq!(
+        Some(&"973".to_string()),
+        result.3.get("first_vertex_model_1")
+    );
+    assert_eq!(
+        Some(&"3888".to_string()),
+        result.3.get("first_index_model_1")
+    );
+    assert_eq!(973 + 8 * chunk_count, result.0.len()); // vertices
+    assert_eq!(3888 + 24 * chunk_count, result.1.len()); // indices
+    assert!(result.3.contains_key("DEBUG_CHUNKS_STATS"));
+    Ok(())
+}
+
+#[test]
+fn test_sdf_mesh_multi_offsets() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "sdf_mesh".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "50".to_string());
+    let _ = config.insert("SDF_RADIUS_MULTIPLIER".to_string(), "1.0".to_string());
+    let _ = config.insert("MULTI_OFFSETS".to_string(), "0.0, -0.2".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (1.203918, 1.203918, 1.0).into(),
+            (-1.805877, 0.74801874, 0.0).into(),
+            (0.0, -1.7025971, 0.0).into(),
+            (-0.36410117, 0.33949375, -1.0).into(),
+            (0.25582898, -0.17708552, 0.0).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 1, 2],
+    };
+
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(Some(&"2".to_string()), result.3.get("SHELL_COUNT"));
+    // the first shell is the same mesh as in test_sdf_mesh_1
+    assert_eq!(
+        Some(&"973".to_string()),
+        result.3.get("first_vertex_model_1")
+    );
+    assert_eq!(
+        Some(&"3888".to_string()),
+        result.3.get("first_index_model_1")
+    );
+    assert!(result.0.len() > 973); // vertices
+    assert_eq!(0, result.1.len() % 3); // indices
+    assert!(result.1[3888..]
+        .iter()
+        .all(|i| *i >= 973 && *i < result.0.len()));
+
+    // the offsets must keep the radius positive
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "sdf_mesh".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "50".to_string());
+    let _ = config.insert("SDF_RADIUS_MULTIPLIER".to_string(), "1.0".to_string());
+    let _ = config.insert("MULTI_OFFSETS".to_string(), "0.0,-100.0".to_string());
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_sdf_mesh_adaptive() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "sdf_mesh".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "50".to_string());
+    let _ = config.insert("SDF_RADIUS_MULTIPLIER".to_string(), "1.0".to_string());
+    let _ = config.insert("SDF_CHUNK_SIDE".to_string(), "8".to_string());
+    let _ = config.insert("ADAPTIVE".to_string(), "true".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (1.203918, 1.203918, 1.0).into(),
+            (-1.805877, 0.74801874, 0.0).into(),
+            (0.0, -1.7025971, 0.0).into(),
+            (-0.36410117, 0.33949375, -1.0).into(),
+            (0.25582898, -0.17708552, 0.0).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 1, 2],
+    };
+
+    let models = vec![owned_model_0.as_model()];
+    let adaptive = super::process_command(config.clone(), models)?;
+    let _ = config.insert("ADAPTIVE".to_string(), "false".to_string());
+    let models = vec![owned_model_0.as_model()];
+    let uniform = super::process_command(config, models)?;
+    // the octree only skips chunks without a surface, the mesh is the same
+    assert!(!adaptive.0.is_empty());
+    assert_eq!(uniform.0.len(), adaptive.0.len()); // vertices
+    assert_eq!(uniform.1.len(), adaptive.1.len()); // indices
+    assert_eq!(
+        uniform.3.get("SDF_SURFACE_CHUNK_COUNT"),
+        adaptive.3.get("SDF_SURFACE_CHUNK_COUNT")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_sdf_mesh_dry_run() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "sdf_mesh".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "50".to_string());
+    let _ = config.insert("SDF_RADIUS_MULTIPLIER".to_string(), "1.0".to_string());
+    let _ = config.insert("DRY_RUN".to_string(), "true".to_string());
+
+    // the same skeleton as test_sdf_mesh_1, it meshes into 973 vertices
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (1.203918, 1.203918, 1.0).into(),
+            (-1.805877, 0.74801874, 0.0).into(),
+            (0.0, -1.7025971, 0.0).into(),
+            (-0.36410117, 0.33949375, -1.0).into(),
+            (0.25582898, -0.17708552, 0.0).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 1, 2],
+    };
+
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert!(result.0.is_empty());
+    assert!(result.1.is_empty());
+    assert_eq!(OwnedModel::identity_matrix().to_vec(), result.2);
+    let grid: Vec<usize> = result.3["SDF_CHUNK_GRID"]
+        .split(',')
+        .map(|s| s.parse().unwrap())
+        .collect();
+    assert_eq!(3, grid.len());
+    let chunk_count: usize = result.3["SDF_CHUNK_COUNT"].parse().unwrap();
+    assert_eq!(grid.iter().product::<usize>(), chunk_count);
+    let voxel_size: f32 = result.3["SDF_VOXEL_SIZE"].parse().unwrap();
+    assert!(
+        (voxel_size - 3.009795 / 50.0).abs() < 1e-5,
+        "{}",
+        voxel_size
+    );
+    let estimate: usize = result.3["SDF_VERTEX_ESTIMATE"].parse().unwrap();
+    assert!(estimate > 973 / 2 && estimate < 973 * 2, "{}", estimate);
+
+    // the lattice can also be predicted from the bounding box alone
+    let lattice = super::sdf_mesh_lattice(
+        &config,
+        (-1.805877, -1.7025971, -1.0).into(),
+        (1.203918, 1.203918, 1.0).into(),
+    )?;
+    assert_eq!(chunk_count, lattice.chunk_count());
+    assert_eq!(voxel_size, lattice.voxel_size());
+
+    // the meshing reports the same lattice
+    let _ = config.insert("DRY_RUN".to_string(), "false".to_string());
+    let result = super::process_command(config.clone(), vec![owned_model_0.as_model()])?;
+    assert_eq!(chunk_count.to_string(), result.3["SDF_CHUNK_COUNT"]);
+    let surface_chunk_count: usize = result.3["SDF_SURFACE_CHUNK_COUNT"].parse().unwrap();
+    assert!(surface_chunk_count > 0 && surface_chunk_count <= chunk_count);
+    let _ = config.insert("DRY_RUN".to_string(), "true".to_string());
+
+    // the dry run returns the same world matrix as the meshing
+    let mut world_orientation = OwnedModel::identity_matrix();
+    world_orientation[3] = 5.0;
+    let owned_model_1 = OwnedModel {
+        world_orientation,
+        ..owned_model_0
+    };
+    let result = super::process_command(config.clone(), vec![owned_model_1.as_model()])?;
+    assert_eq!(OwnedModel::identity_matrix().to_vec(), result.2);
+    let _ = config.insert("LOCAL_FRAME".to_string(), "true".to_string());
+    let result = super::process_command(config, vec![owned_model_1.as_model()])?;
+    assert_eq!(world_orientation.to_vec(), result.2);
+    assert_eq!("true", result.3.get("LOCAL_FRAME").unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_sdf_mesh_anisotropic() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "sdf_mesh".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "40".to_string());
+    let _ = config.insert("SDF_DIVISIONS_Z".to_string(), "120".to_string());
+    let _ = config.insert("SDF_RADIUS_MULTIPLIER".to_string(), "10.0".to_string());
+
+    // a single tube along X, with a radius of 0.4
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(0.0, 0.0, 0.0).into(), (4.0, 0.0, 0.0).into()],
+        indices: vec![0, 1],
+    };
+    let lattice = super::sdf_mesh_lattice(&config, (0.0, 0.0, 0.0).into(), (4.0, 0.0, 0.0).into())?;
+    let sizes = lattice.voxel_sizes();
+    assert!((sizes[0] - 0.1).abs() < 1e-6 && (sizes[1] - 0.1).abs() < 1e-6);
+    assert!((sizes[2] - 0.1 / 3.0).abs() < 1e-6);
+    assert_eq!(sizes[0], lattice.voxel_size());
+    let mut isotropic_config = config.clone();
+    let _ = isotropic_config.remove("SDF_DIVISIONS_Z");
+    let isotropic = super::sdf_mesh_lattice(
+        &isotropic_config,
+        (0.0, 0.0, 0.0).into(),
+        (4.0, 0.0, 0.0).into(),
+    )?;
+    let (grid, isotropic_grid) = (lattice.chunk_grid(), isotropic.chunk_grid());
+    assert_eq!(grid[0..2], isotropic_grid[0..2]);
+    assert!(grid[2] > isotropic_grid[2]);
+
+    for adaptive in ["false", "true"] {
+        let _ = config.insert("ADAPTIVE".to_string(), adaptive.to_string());
+        let models = vec![owned_model_0.as_model()];
+        let result = super::process_command(config.clone(), models)?;
+        assert!(!result.0.is_empty());
+        // the surface is at the tube radius, within a (large) voxel
+        for v in result.0.iter().filter(|v| v.x > 0.0 && v.x < 4.0) {
+            let r = (v.y * v.y + v.z * v.z).sqrt();
+            assert!((r - 0.4).abs() < 0.1, "{:?} {}", v, r);
+        }
+        // the top of the tube is resolved by the finer Z voxels
+        let z_max = result.0.iter().map(|v| v.z).fold(f32::MIN, f32::max);
+        assert!((z_max - 0.4).abs() < 0.05, "{}", z_max);
+    }
+
+    // the per axis divisions have the same range as SDF_DIVISIONS
+    let _ = config.insert("SDF_DIVISIONS_Y".to_string(), "1000".to_string());
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_sdf_mesh_split() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("command".to_string(), "sdf_mesh".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "40".to_string());
+    let _ = config.insert("SDF_RADIUS_MULTIPLIER".to_string(), "10.0".to_string());
+    let _ = config.insert("SDF_CHUNK_SIDE".to_string(), "8".to_string());
+
+    // a single tube along X, with a radius of 0.4
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(0.0, 0.0, 0.0).into(), (4.0, 0.0, 0.0).into()],
+        indices: vec![0, 1],
+    };
+    let models = vec![owned_model_0.as_model()];
+    let whole = super::process_command(config.clone(), models)?;
+    // a tube is never cut, so it has no vertices close to its axis
+    let is_cap = |v: &crate::ffi::FFIVector3| {
+        v.x > 0.5 && v.x < 3.5 && (v.y * v.y + v.z * v.z).sqrt() < 0.25
+    };
+    assert!(!whole.0.iter().any(is_cap));
+
+    let max_vertices = whole.0.len() / 2;
+    let _ = config.insert("SPLIT_MAX_VERTICES".to_string(), max_vertices.to_string());
+    for adaptive in ["false", "true"] {
+        let _ = config.insert("ADAPTIVE".to_string(), adaptive.to_string());
+        let models = vec![owned_model_0.as_model()];
+        let result = super::process_command(config.clone(), models)?;
+        let part_count: usize = result.3["PART_COUNT"].parse().unwrap();
+        assert!(part_count >= 2, "{}", part_count);
+        let (mut first_vertices, mut first_indices) = (vec![0], vec![0]);
+        for part in 1..part_count {
+            first_vertices.push(
+                result.3[&format!("first_vertex_model_{}", part)]
+                    .parse()
+                    .unwrap(),
+            );
+            first_indices.push(
+                result.3[&format!("first_index_model_{}", part)]
+                    .parse()
+                    .unwrap(),
+            );
+        }
+        first_vertices.push(result.0.len());
+        first_indices.push(result.1.len());
+        for (range, index_range) in first_vertices.windows(2).zip(first_indices.windows(2)) {
+            assert!(range[1] > range[0]);
+            assert!(range[1] - range[0] <= max_vertices);
+            // every part is watertight: with the chunk boundaries welded, each directed edge has
+            // its reverse in the same part
+            let (map, _) = crate::command::weld::weld_map(&result.0[range[0]..range[1]], 0.0001);
+            let mut edges = std::collections::HashMap::<(usize, usize), i32>::new();
+            for t in result.1[index_range[0]..index_range[1]].chunks_exact(3) {
+                let t = [
+                    map[t[0] - range[0]],
+                    map[t[1] - range[0]],
+                    map[t[2] - range[0]],
+                ];
+                if t[0] == t[1] || t[1] == t[2] || t[2] == t[0] {
+                    continue;
+                }
+                for (a, b) in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+                    *edges.entry((a, b)).or_default() += 1;
+                }
+            }
+            assert!(!edges.is_empty());
+            for ((a, b), count) in edges.iter() {
+                assert_eq!(
+                    Some(count),
+                    edges.get(&(*b, *a)),
+                    "adaptive:{} the edge {}-{} is open",
+                    adaptive,
+                    a,
+                    b
+                );
+            }
+        }
+        // the parts are capped at the cuts
+        assert!(result.0.iter().any(is_cap));
+        assert_eq!(0, result.1.len() % 3);
+    }
+
+    // a single chunk can not be split
+    let _ = config.insert("SPLIT_MAX_VERTICES".to_string(), "1".to_string());
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
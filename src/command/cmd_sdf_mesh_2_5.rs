@@ -6,11 +6,18 @@
 mod tests;
 
 use crate::{
-    command::{ConfigType, Model, Options, OwnedModel},
+    command::{
+        cmd_sdf_mesh::{self, SdfChunk},
+        progress::{self, Progress},
+        ConfigType, Model, Options, OwnedModel,
+    },
     ffi::FFIVector3,
     HallrError,
 };
-use fast_surface_nets::{ndshape::ConstShape, surface_nets, SurfaceNetsBuffer};
+use fast_surface_nets::{
+    ndshape::{ConstShape, ConstShape3u32},
+    surface_nets, SurfaceNetsBuffer,
+};
 use ilattice::{
     glam as iglam,
     prelude::{Extent, Vector2},
@@ -19,13 +26,9 @@ use linestring::linestring_3d::Plane;
 use rayon::prelude::*;
 use std::{borrow::Borrow, time};
 
-// The un-padded chunk side, it will become 16*16*16
-const UN_PADDED_CHUNK_SIDE: u32 = 14_u32;
-type PaddedChunkShape = fast_surface_nets::ndshape::ConstShape3u32<
-    { UN_PADDED_CHUNK_SIDE + 2 },
-    { UN_PADDED_CHUNK_SIDE + 2 },
-    { UN_PADDED_CHUNK_SIDE + 2 },
->;
+// The padded chunk shape, the un-padded chunk side is PADDED_CHUNK_SIDE - 2
+type PaddedChunkShape<const PADDED_CHUNK_SIDE: u32> =
+    ConstShape3u32<PADDED_CHUNK_SIDE, PADDED_CHUNK_SIDE, PADDED_CHUNK_SIDE>;
 const DEFAULT_SDF_VALUE: f32 = 999.0;
 type Extent3i = Extent<iglam::IVec3>;
 
@@ -91,9 +94,9 @@ struct RoundedCone {
     m: iglam::Affine3A,
 }
 
-/// Generate the data of a single chunk.
+/// Generate the data of a single chunk, `PADDED_CHUNK_SIDE` is the un-padded chunk side + 2
 /// This code is run in a single thread
-fn generate_and_process_sdf_chunk(
+fn generate_and_process_sdf_chunk<const PADDED_CHUNK_SIDE: u32>(
     un_padded_chunk_extent: Extent3i,
     rounded_cones: &[(RoundedCone, Extent3i)],
 ) -> Option<(iglam::Vec3A, SurfaceNetsBuffer)> {
@@ -113,21 +116,12 @@ fn generate_and_process_sdf_chunk(
         })
         .collect();
 
-    #[cfg(not(feature = "display_sdf_chunks"))]
     if filtered_cones.is_empty() {
         // no tubes intersected this chunk
         return None;
     }
 
-    let mut array = { [DEFAULT_SDF_VALUE; PaddedChunkShape::SIZE as usize] };
-
-    #[cfg(feature = "display_sdf_chunks")]
-    // The corners of the un-padded chunk extent
-    let corners: Vec<_> = un_padded_chunk_extent
-        .corners3()
-        .iter()
-        .map(|p| p.as_vec3a())
-        .collect();
+    let mut array = vec![DEFAULT_SDF_VALUE; PaddedChunkShape::<PADDED_CHUNK_SIDE>::SIZE as usize];
 
     let mut some_neg_or_zero_found = false;
     let mut some_pos_found = false;
@@ -135,20 +129,12 @@ fn generate_and_process_sdf_chunk(
     for pwo in padded_chunk_extent.iter3() {
         let v = {
             let p = pwo - un_padded_chunk_extent.minimum + 1;
-            &mut array[PaddedChunkShape::linearize([p.x as u32, p.y as u32, p.z as u32]) as usize]
+            &mut array[PaddedChunkShape::<PADDED_CHUNK_SIDE>::linearize([
+                p.x as u32, p.y as u32, p.z as u32,
+            ]) as usize]
         };
         // Point With Offset from the un-padded extent minimum
         let pwo = pwo.as_vec3a();
-
-        #[cfg(feature = "display_sdf_chunks")]
-        {
-            // todo: this could probably be optimized with PaddedChunkShape::linearize(corner_pos)
-            let mut x = *v;
-            for c in corners.iter() {
-                x = x.min(c.distance(pwo) - 1.);
-            }
-            *v = (*v).min(x);
-        }
         for index in filtered_cones.iter() {
             let cone = &rounded_cones[*index as usize].0;
             let pwo = cone.m.transform_point3a(pwo);
@@ -177,10 +163,10 @@ fn generate_and_process_sdf_chunk(
 
         // do the voxel_size multiplication later, vertices pos. needs to match extent.
         surface_nets(
-            &array,
-            &PaddedChunkShape {},
+            &array[..],
+            &PaddedChunkShape::<PADDED_CHUNK_SIDE> {},
             [0; 3],
-            [UN_PADDED_CHUNK_SIDE + 1; 3],
+            [PADDED_CHUNK_SIDE - 1; 3],
             &mut sn_buffer,
         );
 
@@ -202,11 +188,13 @@ fn build_voxel(
     vertices: Vec<(iglam::Vec2, f32)>,
     indices: &[usize],
     aabb: Extent<iglam::Vec3A>,
+    un_padded_chunk_side: u32,
     verbose: bool,
 ) -> Result<
     (
-        f32, // voxel_size
-        Vec<(iglam::Vec3A, SurfaceNetsBuffer)>,
+        f32,   // voxel_size
+        usize, // the number of chunks in the lattice
+        Vec<SdfChunk>,
     ),
     HallrError,
 > {
@@ -220,16 +208,15 @@ fn build_voxel(
     let scale = divisions / max_dimension;
 
     if verbose {
-        println!(
+        debug!(
             "Voxelizing using divisions = {}, max dimension = {}, scale factor={} (max_dimension*scale={})",
             divisions,
             max_dimension,
             scale,
             max_dimension * scale
         );
-        println!();
     }
-    println!("indices.len():{:?}", indices.len());
+    debug!("indices.len():{:?}", indices.len());
 
     let rounded_cones: Vec<(RoundedCone, Extent3i)> = indices
         .par_chunks_exact(2)
@@ -273,15 +260,18 @@ fn build_voxel(
 
     let chunks_extent = {
         // pad with the radius + one voxel
-        (aabb * (scale / (UN_PADDED_CHUNK_SIDE as f32)))
-            .padded(1.0 / (UN_PADDED_CHUNK_SIDE as f32))
+        (aabb * (scale / (un_padded_chunk_side as f32)))
+            .padded(1.0 / (un_padded_chunk_side as f32))
             .containing_integer_extent()
     };
-    println!("chunks_extent:{:?}", chunks_extent);
+    debug!("chunks_extent:{:?}", chunks_extent);
     let now = time::Instant::now();
 
+    let lattice_chunk_count =
+        (chunks_extent.shape.x * chunks_extent.shape.y * chunks_extent.shape.z) as usize;
     let sdf_chunks: Vec<_> = {
-        let un_padded_chunk_shape = iglam::IVec3::splat(UN_PADDED_CHUNK_SIDE as i32);
+        let un_padded_chunk_shape = iglam::IVec3::splat(un_padded_chunk_side as i32);
+        let chunk_progress = Progress::new(lattice_chunk_count);
         // Spawn off thread tasks creating and processing chunks.
         // Could also do:
         // (min.x..max.x).into_par_iter().flat_map(|x|
@@ -291,21 +281,43 @@ fn build_voxel(
             .iter3()
             .par_bridge()
             .filter_map(move |p| {
+                // the remaining chunks are skipped once the command is cancelled
+                if progress::is_cancelled() {
+                    return None;
+                }
                 let un_padded_chunk_extent =
                     Extent3i::from_min_and_shape(p * un_padded_chunk_shape, un_padded_chunk_shape);
-
-                generate_and_process_sdf_chunk(un_padded_chunk_extent, &rounded_cones)
+                let chunk_start = time::Instant::now();
+
+                // map the chunk side onto a const-generic chunk shape
+                let chunk = match un_padded_chunk_side {
+                    8 => {
+                        generate_and_process_sdf_chunk::<10>(un_padded_chunk_extent, &rounded_cones)
+                    }
+                    30 => {
+                        generate_and_process_sdf_chunk::<32>(un_padded_chunk_extent, &rounded_cones)
+                    }
+                    62 => {
+                        generate_and_process_sdf_chunk::<64>(un_padded_chunk_extent, &rounded_cones)
+                    }
+                    _ => {
+                        generate_and_process_sdf_chunk::<16>(un_padded_chunk_extent, &rounded_cones)
+                    }
+                };
+                chunk_progress.tick();
+                chunk.map(|(offset, buffer)| (offset, buffer, Some(chunk_start.elapsed())))
             })
             .collect()
     };
+    progress::check_cancelled()?;
     if verbose {
-        println!(
+        debug!(
             "process_chunks() duration: {:?} generated {} chunks",
             now.elapsed(),
             sdf_chunks.len()
         );
     }
-    Ok((1.0 / scale, sdf_chunks))
+    Ok((1.0 / scale, lattice_chunk_count, sdf_chunks))
 }
 
 /// Build the return model
@@ -313,7 +325,7 @@ pub(crate) fn build_output_model(
     //pb_model_name: String,
     //pb_world: Option<PB_Matrix4x432>,
     voxel_size: f32,
-    mesh_buffers: Vec<(iglam::Vec3A, SurfaceNetsBuffer)>,
+    mesh_buffers: Vec<SdfChunk>,
     cmd_arg_radius_axis: Plane,
     verbose: bool,
 ) -> Result<OwnedModel, HallrError> {
@@ -339,7 +351,7 @@ pub(crate) fn build_output_model(
         )
     };
 
-    for (vertex_offset, mesh_buffer) in mesh_buffers.iter() {
+    for (vertex_offset, mesh_buffer, _) in mesh_buffers.iter() {
         // each chunk starts counting vertices from zero
         let indices_offset = vertices.len() as u32;
 
@@ -385,7 +397,7 @@ pub(crate) fn build_output_model(
     }
 
     if verbose {
-        println!(
+        debug!(
             "Vertex return model packaging duration: {:?}",
             now.elapsed()
         );
@@ -423,30 +435,67 @@ pub(crate) fn process_command(
         )));
     }
 
+    let cmd_arg_sdf_chunk_side = cmd_sdf_mesh::parse_chunk_side(&config)?;
+    let cmd_arg_debug_chunks =
+        config.get_mandatory_parsed_option::<bool>("DEBUG_CHUNKS", Some(false))?;
+    let cmd_arg_local_frame =
+        config.get_mandatory_parsed_option::<bool>(super::LOCAL_FRAME_KEY, Some(false))?;
+
     // we already tested a_command.models.len()
     let input_model = &models[0];
 
-    println!("model.vertices:{:?}, ", input_model.vertices.len());
+    debug!("model.vertices:{:?}, ", input_model.vertices.len());
 
     let plane = Plane::XY;
     let (vertices, aabb) = parse_input(input_model, plane)?;
-    let (voxel_size, mesh) = build_voxel(
+    let (voxel_size, chunk_count, mesh) = build_voxel(
         cmd_arg_sdf_divisions,
         vertices,
         input_model.indices,
         aabb,
+        cmd_arg_sdf_chunk_side,
         true,
     )?;
+    let surface_chunk_count = mesh.len();
+    let debug_chunks = cmd_arg_debug_chunks.then(|| cmd_sdf_mesh::chunk_stats(&mesh));
 
-    let output_model = build_output_model(voxel_size, mesh, plane, true)?;
+    let mut output_model = build_output_model(voxel_size, mesh, plane, true)?;
+    output_model.world_orientation = input_model.output_orientation(cmd_arg_local_frame)?;
 
     let mut return_config = ConfigType::new();
     let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
     let _ = return_config.insert("REMOVE_DOUBLES".to_string(), "true".to_string());
-    println!(
-        "sdf mesh 2.5d operation returning {} vertices, {} indices",
+    if let Some(debug_chunks) = debug_chunks {
+        // the plane is always XY, so the chunks are not swizzled
+        cmd_sdf_mesh::add_debug_chunks(
+            iglam::Vec3A::splat(voxel_size),
+            cmd_arg_sdf_chunk_side,
+            &debug_chunks,
+            1,
+            &mut output_model,
+            &mut return_config,
+        );
+    }
+    let _ = return_config.insert(
+        "SDF_CHUNK_SIDE".to_string(),
+        cmd_arg_sdf_chunk_side.to_string(),
+    );
+    let _ = return_config.insert("SDF_CHUNK_COUNT".to_string(), chunk_count.to_string());
+    let _ = return_config.insert(
+        "SDF_SURFACE_CHUNK_COUNT".to_string(),
+        surface_chunk_count.to_string(),
+    );
+    if cmd_arg_local_frame {
+        let _ = return_config.insert(super::LOCAL_FRAME_KEY.to_string(), "true".to_string());
+    }
+    info!(
+        "sdf mesh 2.5d operation returning {} vertices, {} indices, chunk side:{}, chunks:{} ({} \
+         with a surface)",
         output_model.vertices.len(),
-        output_model.indices.len()
+        output_model.indices.len(),
+        cmd_arg_sdf_chunk_side,
+        chunk_count,
+        surface_chunk_count
     );
     Ok((
         output_model.vertices,
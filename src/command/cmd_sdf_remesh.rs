@@ -0,0 +1,335 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{progress, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, utils::bvh::Bvh, HallrError};
+use fast_surface_nets::{
+    ndshape::{RuntimeShape, Shape},
+    surface_nets, SurfaceNetsBuffer,
+};
+use rayon::prelude::*;
+use std::{f64::consts::PI, str::FromStr, time};
+use vector_traits::glam::{dvec3, DVec3};
+
+#[cfg(test)]
+mod tests;
+
+/// The largest number of SDF samples of the lattice
+const MAX_SAMPLES: usize = 1 << 25;
+/// The width of the band around the surface where the sign is evaluated, in voxels
+const SIGN_BAND: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SignMethod {
+    /// The generalized winding number, robust against small holes and flipped faces
+    Winding,
+    /// The side of the closest triangle, fast but requires consistent face orientation
+    Normal,
+}
+
+impl FromStr for SignMethod {
+    type Err = HallrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "WINDING" => Ok(Self::Winding),
+            "NORMAL" => Ok(Self::Normal),
+            _ => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"SDF_SIGN_METHOD\" parameter",
+                s
+            ))),
+        }
+    }
+}
+
+/// Returns the generalized winding number of the triangles around `p`, 1.0 inside of a closed
+/// mesh and 0.0 outside of it. From "Robust Inside-Outside Segmentation using Generalized Winding
+/// Numbers" by Jacobson et al., using the solid angle formula of Van Oosterom and Strackee.
+pub(crate) fn winding_number(p: DVec3, triangles: &[[DVec3; 3]]) -> f64 {
+    triangles
+        .iter()
+        .map(|t| {
+            let (a, b, c) = (t[0] - p, t[1] - p, t[2] - p);
+            let (la, lb, lc) = (a.length(), b.length(), c.length());
+            let numerator = a.dot(b.cross(c));
+            let denominator = la * lb * lc + a.dot(b) * lc + b.dot(c) * la + c.dot(a) * lb;
+            2.0 * numerator.atan2(denominator)
+        })
+        .sum::<f64>()
+        / (4.0 * PI)
+}
+
+/// Returns the signed distance field of the triangles, sampled on the `shape` lattice starting at
+/// `origin`, with the spacing `voxel_size`. The values are negative inside of the mesh.
+/// The sign is only evaluated in a narrow band around the surface: the samples further away are
+/// outside if they connect to the border of the lattice without passing the band, and inside
+/// otherwise.
+pub(crate) fn signed_distance_field(
+    triangles: Vec<[DVec3; 3]>,
+    origin: DVec3,
+    voxel_size: f64,
+    shape: &RuntimeShape<u32, 3>,
+    sign_method: SignMethod,
+) -> Vec<f32> {
+    let band = SIGN_BAND * voxel_size;
+    let sample_count = shape.size() as usize;
+    let position = |i: usize| {
+        let [x, y, z] = shape.delinearize(i as u32);
+        origin + dvec3(x as f64, y as f64, z as f64) * voxel_size
+    };
+    let bvh = Bvh::new(triangles.clone());
+    // the unsigned distance, and the sign of the samples inside of the band
+    let samples: Vec<(f64, Option<bool>)> = (0..sample_count)
+        .into_par_iter()
+        .map(|i| {
+            // the remaining samples are skipped once the command is cancelled
+            if progress::is_cancelled() {
+                return (0.0, None);
+            }
+            let p = position(i);
+            let (closest, triangle) = bvh.closest(p).unwrap();
+            let distance = closest.distance(p);
+            if distance > band {
+                return (distance, None);
+            }
+            let inside = match sign_method {
+                // the absolute value makes the sign independent of the face orientation
+                SignMethod::Winding => winding_number(p, &triangles).abs() > 0.5,
+                SignMethod::Normal => {
+                    let normal = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]);
+                    (p - closest).dot(normal) < 0.0
+                }
+            };
+            (distance, Some(inside))
+        })
+        .collect();
+
+    // flood fill the samples outside of the band from the border of the lattice
+    let [sx, sy, sz] = shape.as_array();
+    let mut outside = vec![false; sample_count];
+    let mut stack: Vec<usize> = (0..sample_count)
+        .filter(|i| {
+            let [x, y, z] = shape.delinearize(*i as u32);
+            x == 0 || y == 0 || z == 0 || x == sx - 1 || y == sy - 1 || z == sz - 1
+        })
+        .filter(|i| samples[*i].1.is_none())
+        .collect();
+    for i in stack.iter() {
+        outside[*i] = true;
+    }
+    while let Some(i) = stack.pop() {
+        let [x, y, z] = shape.delinearize(i as u32).map(|c| c as i64);
+        for [dx, dy, dz] in [
+            [-1, 0, 0],
+            [1, 0, 0],
+            [0, -1, 0],
+            [0, 1, 0],
+            [0, 0, -1],
+            [0, 0, 1],
+        ] {
+            let n = [x + dx, y + dy, z + dz];
+            if n.iter()
+                .zip([sx, sy, sz])
+                .any(|(c, s)| *c < 0 || *c >= s as i64)
+            {
+                continue;
+            }
+            let n = shape.linearize(n.map(|c| c as u32)) as usize;
+            if !outside[n] && samples[n].1.is_none() {
+                outside[n] = true;
+                stack.push(n);
+            }
+        }
+    }
+    samples
+        .iter()
+        .zip(outside.iter())
+        .map(|((distance, inside), outside)| {
+            let inside = inside.unwrap_or(!outside);
+            (if inside { -distance } else { *distance }) as f32
+        })
+        .collect()
+}
+
+/// Returns the triangles of a triangulated model
+pub(crate) fn model_triangles(model: &Model<'_>) -> Vec<[DVec3; 3]> {
+    let to_dvec3 = |v: &FFIVector3| dvec3(v.x as f64, v.y as f64, v.z as f64);
+    model
+        .indices
+        .chunks_exact(3)
+        .map(|t| {
+            [
+                to_dvec3(&model.vertices[t[0]]),
+                to_dvec3(&model.vertices[t[1]]),
+                to_dvec3(&model.vertices[t[2]]),
+            ]
+        })
+        .collect()
+}
+
+/// The sample lattice of a signed distance field
+pub(crate) struct Lattice {
+    pub(crate) origin: DVec3,
+    pub(crate) voxel_size: f64,
+    pub(crate) shape: RuntimeShape<u32, 3>,
+}
+
+impl Lattice {
+    /// A lattice with `divisions` voxels along the longest side of the `min`..`max` bounds. The
+    /// lattice is padded so that its border is outside of the surface grown by `offset`.
+    pub(crate) fn new(
+        min: DVec3,
+        max: DVec3,
+        divisions: f64,
+        offset: f64,
+    ) -> Result<Self, HallrError> {
+        let max_dimension = (max - min).max_element();
+        if max_dimension <= 0.0 {
+            return Err(HallrError::InvalidInputData(
+                "The mesh has no extent".to_string(),
+            ));
+        }
+        let voxel_size = max_dimension / divisions;
+        // the border of the lattice must be outside of the offset surface and of the sign band
+        let padding = offset.max(0.0) + (SIGN_BAND + 2.0) * voxel_size;
+        let origin = min - padding;
+        let extent = ((max + padding - origin) / voxel_size).ceil() + 1.0;
+        let sample_count = extent.x * extent.y * extent.z;
+        if sample_count > MAX_SAMPLES as f64 {
+            return Err(HallrError::InvalidParameter(format!(
+                "The lattice would need {} samples, the maximum is {}. Reduce SDF_DIVISIONS.",
+                sample_count, MAX_SAMPLES
+            )));
+        }
+        Ok(Self {
+            origin,
+            voxel_size,
+            shape: RuntimeShape::<u32, 3>::new([extent.x as u32, extent.y as u32, extent.z as u32]),
+        })
+    }
+
+    /// Returns the signed distance field of the triangles, sampled on the lattice
+    pub(crate) fn signed_distance_field(
+        &self,
+        triangles: Vec<[DVec3; 3]>,
+        sign_method: SignMethod,
+    ) -> Vec<f32> {
+        signed_distance_field(
+            triangles,
+            self.origin,
+            self.voxel_size,
+            &self.shape,
+            sign_method,
+        )
+    }
+
+    /// Mesh the zero level of the `sdf` samples with surface nets, in the triangulated format
+    pub(crate) fn surface_nets(&self, sdf: &[f32]) -> (Vec<FFIVector3>, Vec<usize>) {
+        let mut buffer = SurfaceNetsBuffer::default();
+        let [sx, sy, sz] = self.shape.as_array();
+        surface_nets(
+            sdf,
+            &self.shape,
+            [0; 3],
+            [sx - 1, sy - 1, sz - 1],
+            &mut buffer,
+        );
+        let vertices = buffer
+            .positions
+            .iter()
+            .map(|p| {
+                let v =
+                    self.origin + dvec3(p[0] as f64, p[1] as f64, p[2] as f64) * self.voxel_size;
+                FFIVector3::new(v.x as f32, v.y as f32, v.z as f32)
+            })
+            .collect();
+        (
+            vertices,
+            buffer.indices.iter().map(|i| *i as usize).collect(),
+        )
+    }
+}
+
+/// Run the sdf_remesh command
+/// Model 0 is a closed triangulated mesh. Its signed distance field is sampled on a lattice of
+/// `SDF_DIVISIONS` voxels along the longest side of the bounding box, and the surface at
+/// `SDF_OFFSET` (in model units, default 0.0, positive grows the mesh) is re-meshed with surface
+/// nets. The inside is decided by `SDF_SIGN_METHOD`: WINDING (default), the generalized winding
+/// number, or NORMAL, the orientation of the closest triangle. The voxel size is returned as
+/// `SDF_VOXEL_SIZE`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("triangulated") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'triangulated' format".to_string(),
+        ));
+    }
+    let divisions = config.get_mandatory_parsed_option::<f64>("SDF_DIVISIONS", None)?;
+    if !divisions.is_finite() || divisions < 1.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "SDF_DIVISIONS must be at least 1 :({})",
+            divisions
+        )));
+    }
+    let offset = config.get_mandatory_parsed_option::<f64>("SDF_OFFSET", Some(0.0))?;
+    if !offset.is_finite() {
+        return Err(HallrError::InvalidParameter(format!(
+            "SDF_OFFSET must be a finite number :({})",
+            offset
+        )));
+    }
+    let sign_method = config
+        .get_mandatory_parsed_option::<SignMethod>("SDF_SIGN_METHOD", Some(SignMethod::Winding))?;
+    let model = &models[0];
+    let triangles = model_triangles(model);
+    if triangles.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "The mesh has no triangles".to_string(),
+        ));
+    }
+    let (min, max) = triangles.iter().flatten().fold(
+        (DVec3::splat(f64::MAX), DVec3::splat(f64::MIN)),
+        |(min, max), v| (min.min(*v), max.max(*v)),
+    );
+    let lattice = Lattice::new(min, max, divisions, offset)?;
+    let voxel_size = lattice.voxel_size;
+    debug!(
+        "sdf_remesh: {} triangles, lattice {:?}, voxel size {}",
+        triangles.len(),
+        lattice.shape.as_array(),
+        voxel_size
+    );
+
+    let now = time::Instant::now();
+    let mut sdf = lattice.signed_distance_field(triangles, sign_method);
+    progress::check_cancelled()?;
+    if offset != 0.0 {
+        sdf.iter_mut().for_each(|v| *v -= offset as f32);
+    }
+    debug!("sdf_remesh: SDF duration:{:?}", now.elapsed());
+    let (output_vertices, output_indices) = lattice.surface_nets(&sdf);
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = return_config.insert("SDF_VOXEL_SIZE".to_string(), voxel_size.to_string());
+    info!(
+        "sdf_remesh operation returning {} vertices, {} indices",
+        output_vertices.len(),
+        output_indices.len()
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
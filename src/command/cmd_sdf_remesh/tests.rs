@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType},
+    HallrError,
+};
+
+#[test]
+fn test_sdf_remesh_1() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "sdf_remesh".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "10".to_string());
+    let _ = config.insert("SDF_SIGN_METHOD".to_string(), "WINDING".to_string());
+    let _ = config.insert("SDF_OFFSET".to_string(), "0.0".to_string());
+
+    let owned_model_0 = fixtures::cuboid([-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]);
+    for sign_method in ["WINDING", "NORMAL"] {
+        let _ = config.insert("SDF_SIGN_METHOD".to_string(), sign_method.to_string());
+        let models = vec![owned_model_0.as_model()];
+        let result = super::process_command(config.clone(), models)?;
+        assert!(!result.0.is_empty(), "{}", sign_method);
+        assert_eq!(0, result.1.len() % 3);
+        assert_eq!("0.2", result.3.get("SDF_VOXEL_SIZE").unwrap());
+        // the corners are rounded by the voxels, but nothing is outside of the cube
+        let max_x = result.0.iter().map(|v| v.x).fold(f32::MIN, f32::max);
+        assert!(max_x > 0.9 && max_x < 1.01, "{} {}", sign_method, max_x);
+        assert!(result
+            .0
+            .iter()
+            .all(|v| [v.x, v.y, v.z].iter().all(|c| c.abs() < 1.01)));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_sdf_remesh_2() -> Result<(), HallrError> {
+    // a grown cube, from flipped triangles
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "sdf_remesh".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("SDF_DIVISIONS".to_string(), "10".to_string());
+    let _ = config.insert("SDF_SIGN_METHOD".to_string(), "WINDING".to_string());
+    let _ = config.insert("SDF_OFFSET".to_string(), "0.4".to_string());
+
+    let mut owned_model_0 = fixtures::cuboid([-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]);
+    for triangle in owned_model_0.indices.chunks_exact_mut(3) {
+        triangle.swap(1, 2);
+    }
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    let max_x = result.0.iter().map(|v| v.x).fold(f32::MIN, f32::max);
+    assert!(max_x > 1.3 && max_x < 1.45, "{}", max_x);
+
+    let _ = config.insert("SDF_SIGN_METHOD".to_string(), "PARITY".to_string());
+    let _ = config.insert("SDF_OFFSET".to_string(), "0.0".to_string());
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
@@ -0,0 +1,420 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{ConfigType, Model, Options};
+use crate::{
+    ffi::FFIVector3,
+    utils::bvh::{segment_triangle_intersection, triangle_bounds, Bvh},
+    HallrError,
+};
+use ahash::AHashMap;
+use vector_traits::glam::{DVec2, DVec3};
+
+#[cfg(test)]
+mod tests;
+
+/// Intersection points closer than this fraction of the edge length to one end of the edge are
+/// snapped to that vertex
+const VERTEX_SNAP_FRACTION: f64 = 1e-9;
+
+/// Points closer than this fraction of the face size to an edge of the face are on the edge
+const ON_EDGE_FRACTION: f64 = 1e-9;
+
+/// The maximum number of edge flips used to recover one intersection segment, per triangle of the
+/// split face
+const MAX_FLIPS_PER_TRIANGLE: usize = 16;
+
+/// The intersections between the faces of one mesh
+#[derive(Default)]
+struct Intersections {
+    /// The vertices of the mesh followed by the intersection points
+    points: Vec<DVec3>,
+    /// The intersection segments
+    segments: Vec<(usize, usize)>,
+    /// The intersection points inside of the mesh edges, with their position along the edge
+    edge_points: AHashMap<(usize, usize), Vec<(f64, usize)>>,
+    /// The intersection points inside of the faces
+    face_points: AHashMap<usize, Vec<usize>>,
+    /// The intersection segments of every face
+    face_segments: AHashMap<usize, Vec<(usize, usize)>>,
+    /// The point where an edge crosses a face
+    crossings: AHashMap<((usize, usize), usize), usize>,
+    intersecting_pairs: usize,
+}
+
+impl Intersections {
+    /// Returns the point where the mesh `edge` crosses the `face`, if it does. Points inside of
+    /// the edge are shared by all the faces of that edge.
+    fn crossing(
+        &mut self,
+        edge: (usize, usize),
+        face: usize,
+        triangle: &[DVec3; 3],
+    ) -> Option<usize> {
+        if let Some(point) = self.crossings.get(&(edge, face)) {
+            return Some(*point);
+        }
+        let (p, q) = (self.points[edge.0], self.points[edge.1]);
+        let t = segment_triangle_intersection(p, q, triangle)?;
+        let point = if t <= VERTEX_SNAP_FRACTION {
+            edge.0
+        } else if t >= 1.0 - VERTEX_SNAP_FRACTION {
+            edge.1
+        } else {
+            self.points.push(p.lerp(q, t));
+            let point = self.points.len() - 1;
+            self.edge_points.entry(edge).or_default().push((t, point));
+            point
+        };
+        let face_points = self.face_points.entry(face).or_default();
+        if !face_points.contains(&point) {
+            face_points.push(point);
+        }
+        let _ = self.crossings.insert((edge, face), point);
+        Some(point)
+    }
+}
+
+/// Finds the intersection segments between all the pairs of faces not sharing a vertex
+fn find_intersections(vertices: &[DVec3], triangles: &[[usize; 3]]) -> Intersections {
+    let corners: Vec<[DVec3; 3]> = triangles
+        .iter()
+        .map(|t| [vertices[t[0]], vertices[t[1]], vertices[t[2]]])
+        .collect();
+    let bvh = Bvh::new(corners.clone());
+    let mut rv = Intersections {
+        points: vertices.to_vec(),
+        ..Intersections::default()
+    };
+    for (i, triangle) in triangles.iter().enumerate() {
+        let (min, max) = triangle_bounds(&corners[i]);
+        for j in bvh.overlapping(min, max) {
+            if j <= i || triangle.iter().any(|v| triangles[j].contains(v)) {
+                continue;
+            }
+            // the edges of one face crossing the other face are the ends of the segment
+            let mut ends = Vec::<usize>::with_capacity(2);
+            for (edge_face, other_face) in [(i, j), (j, i)] {
+                let t = triangles[edge_face];
+                for k in 0..3 {
+                    let (a, b) = (t[k], t[(k + 1) % 3]);
+                    if let Some(point) =
+                        rv.crossing((a.min(b), a.max(b)), other_face, &corners[other_face])
+                    {
+                        if !ends.contains(&point) {
+                            ends.push(point);
+                        }
+                    }
+                }
+            }
+            if ends.len() < 2 {
+                continue;
+            }
+            // more than two ends only happens for touching faces, keep the longest segment
+            let mut segment = (ends[0], ends[1]);
+            for (n, a) in ends.iter().enumerate() {
+                for b in ends.iter().skip(n + 1) {
+                    if rv.points[*a].distance_squared(rv.points[*b])
+                        > rv.points[segment.0].distance_squared(rv.points[segment.1])
+                    {
+                        segment = (*a, *b);
+                    }
+                }
+            }
+            rv.intersecting_pairs += 1;
+            rv.segments.push(segment);
+            for face in [i, j] {
+                rv.face_segments.entry(face).or_default().push(segment);
+            }
+        }
+    }
+    rv
+}
+
+/// Returns twice the signed area of the triangle `a`,`b`,`c`
+fn orient(a: DVec2, b: DVec2, c: DVec2) -> f64 {
+    (b - a).perp_dot(c - a)
+}
+
+/// Returns true if the segments `a`-`b` and `c`-`d` cross at a point inside of both
+fn segments_cross(a: DVec2, b: DVec2, c: DVec2, d: DVec2) -> bool {
+    orient(a, b, c) * orient(a, b, d) < 0.0 && orient(c, d, a) * orient(c, d, b) < 0.0
+}
+
+/// Returns the position in the triangle where the edge `a`-`b` starts, in either direction, or
+/// None if the triangle does not have the edge
+fn edge_position(t: &[usize; 3], a: usize, b: usize) -> Option<usize> {
+    (0..3).find(|i| {
+        let (u, v) = (t[*i], t[(i + 1) % 3]);
+        (u == a && v == b) || (u == b && v == a)
+    })
+}
+
+/// Splits the triangles with the edge `a`-`b` at `point`
+fn split_edge(triangles: &mut Vec<[usize; 3]>, a: usize, b: usize, point: usize) {
+    for k in 0..triangles.len() {
+        if let Some(i) = edge_position(&triangles[k], a, b) {
+            let t = triangles[k];
+            let (u, v, w) = (t[i], t[(i + 1) % 3], t[(i + 2) % 3]);
+            triangles[k] = [u, point, w];
+            triangles.push([point, v, w]);
+        }
+    }
+}
+
+/// Inserts the point into the triangle containing it, returns false if no triangle does
+fn insert_point(
+    triangles: &mut Vec<[usize; 3]>,
+    point: usize,
+    position: &impl Fn(usize) -> DVec2,
+    tolerance: f64,
+) -> bool {
+    if triangles.iter().flatten().any(|v| *v == point) {
+        return true;
+    }
+    let p = position(point);
+    for k in 0..triangles.len() {
+        let t = triangles[k];
+        // the distances from p to the edges of the triangle, positive inside
+        let distances: Vec<f64> = (0..3)
+            .map(|i| {
+                let (a, b) = (position(t[i]), position(t[(i + 1) % 3]));
+                orient(a, b, p) / a.distance(b)
+            })
+            .collect();
+        if distances.iter().any(|d| *d < -tolerance) {
+            continue;
+        }
+        match (0..3).find(|i| distances[*i] <= tolerance) {
+            Some(i) => split_edge(triangles, t[i], t[(i + 1) % 3], point),
+            None => {
+                triangles[k] = [t[0], t[1], point];
+                triangles.push([t[1], t[2], point]);
+                triangles.push([t[2], t[0], point]);
+            }
+        }
+        return true;
+    }
+    false
+}
+
+/// Flips the edges crossing `a`-`b` until `a`-`b` is an edge of the triangles. The `fixed` edges
+/// are never flipped. Returns false if the edge could not be recovered.
+fn recover_edge(
+    triangles: &mut [[usize; 3]],
+    a: usize,
+    b: usize,
+    position: &impl Fn(usize) -> DVec2,
+    fixed: &[(usize, usize)],
+) -> bool {
+    let is_fixed = |u: usize, v: usize| fixed.contains(&(u.min(v), u.max(v)));
+    let (pa, pb) = (position(a), position(b));
+    for _ in 0..MAX_FLIPS_PER_TRIANGLE * triangles.len() {
+        if triangles.iter().any(|t| edge_position(t, a, b).is_some()) {
+            return true;
+        }
+        let mut flip = None;
+        'search: for k in 0..triangles.len() {
+            for i in 0..3 {
+                let t = triangles[k];
+                let (u, v, w1) = (t[i], t[(i + 1) % 3], t[(i + 2) % 3]);
+                if is_fixed(u, v) || !segments_cross(position(u), position(v), pa, pb) {
+                    continue;
+                }
+                // the triangle on the other side of the edge
+                let Some((m, j)) = triangles
+                    .iter()
+                    .enumerate()
+                    .filter(|(m, _)| *m != k)
+                    .find_map(|(m, o)| edge_position(o, u, v).map(|j| (m, j)))
+                else {
+                    continue;
+                };
+                let w2 = triangles[m][(j + 2) % 3];
+                // only the diagonal of a convex quadrilateral can be flipped
+                if segments_cross(position(w1), position(w2), position(u), position(v)) {
+                    flip = Some((k, m, [u, w2, w1], [w2, v, w1]));
+                    break 'search;
+                }
+            }
+        }
+        match flip {
+            Some((k, m, first, second)) => {
+                triangles[k] = first;
+                triangles[m] = second;
+            }
+            None => return false,
+        }
+    }
+    triangles.iter().any(|t| edge_position(t, a, b).is_some())
+}
+
+/// Returns the faces split along the intersection segments, and the number of segments that
+/// could not be made into edges
+fn split_faces(triangles: &[[usize; 3]], intersections: &Intersections) -> (Vec<usize>, usize) {
+    let points = &intersections.points;
+    let mut indices = Vec::<usize>::with_capacity(triangles.len() * 3);
+    let mut unresolved = 0;
+    for (f, t) in triangles.iter().enumerate() {
+        let edge_points: Vec<(usize, usize, &Vec<(f64, usize)>)> = (0..3)
+            .filter_map(|i| {
+                let (a, b) = (t[i], t[(i + 1) % 3]);
+                intersections
+                    .edge_points
+                    .get(&(a.min(b), a.max(b)))
+                    .map(|p| (a, b, p))
+            })
+            .collect();
+        let face_points = intersections.face_points.get(&f);
+        let normal = (points[t[1]] - points[t[0]]).cross(points[t[2]] - points[t[0]]);
+        if (edge_points.is_empty() && face_points.is_none()) || normal.length_squared() == 0.0 {
+            indices.extend(t.iter());
+            continue;
+        }
+        // project the face on the axis plane it is the most parallel to, keeping the winding
+        let n = normal.abs();
+        let axis = if n.x >= n.y && n.x >= n.z {
+            0
+        } else if n.y >= n.z {
+            1
+        } else {
+            2
+        };
+        let flip = normal[axis] < 0.0;
+        let position = |i: usize| {
+            let p = points[i];
+            let v = match axis {
+                0 => DVec2::new(p.y, p.z),
+                1 => DVec2::new(p.z, p.x),
+                _ => DVec2::new(p.x, p.y),
+            };
+            if flip {
+                DVec2::new(v.y, v.x)
+            } else {
+                v
+            }
+        };
+        let tolerance = ON_EDGE_FRACTION
+            * (0..3)
+                .map(|i| position(t[i]).distance(position(t[(i + 1) % 3])))
+                .fold(0.0, f64::max);
+
+        let mut local = vec![*t];
+        for (a, b, edge_points) in edge_points {
+            // the points along the edge from a, the positions are stored from the lower index
+            let mut sorted: Vec<(f64, usize)> = edge_points
+                .iter()
+                .map(|(t, p)| (if a < b { *t } else { 1.0 - *t }, *p))
+                .collect();
+            sorted.sort_unstable_by(|x, y| x.0.total_cmp(&y.0));
+            let mut start = a;
+            for (_, point) in sorted {
+                split_edge(&mut local, start, b, point);
+                start = point;
+            }
+        }
+        for point in face_points.into_iter().flatten() {
+            let _ = insert_point(&mut local, *point, &position, tolerance);
+        }
+        let mut fixed = Vec::<(usize, usize)>::new();
+        for (a, b) in intersections.face_segments.get(&f).into_iter().flatten() {
+            if recover_edge(&mut local, *a, *b, &position, &fixed) {
+                fixed.push(((*a).min(*b), (*a).max(*b)));
+            } else {
+                unresolved += 1;
+            }
+        }
+        indices.extend(local.iter().flatten());
+    }
+    (indices, unresolved)
+}
+
+/// Run the self_intersect command
+/// Model 0 is a triangulated mesh. The intersections between faces not sharing a vertex are
+/// returned as line chunks, or with `SPLIT_FACES=true` the mesh is returned with the faces split
+/// so that the intersection segments are edges. The number of intersecting face pairs is returned
+/// as `INTERSECTING_PAIRS`, and when splitting, the number of segments that could not be made into
+/// edges (where intersection curves cross inside of a face) as `UNRESOLVED_SEGMENTS`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("triangulated") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'triangulated' format".to_string(),
+        ));
+    }
+    let split = config.get_mandatory_parsed_option::<bool>("SPLIT_FACES", Some(false))?;
+    let model = &models[0];
+    if model.indices.len() % 3 != 0 {
+        return Err(HallrError::InvalidInputData(
+            "The number of indices is not a multiple of three".to_string(),
+        ));
+    }
+    if let Some(index) = model.indices.iter().find(|i| **i >= model.vertices.len()) {
+        return Err(HallrError::InvalidInputData(format!(
+            "The index {} is out of bounds",
+            index
+        )));
+    }
+    let vertices: Vec<DVec3> = model
+        .vertices
+        .iter()
+        .map(|v| DVec3::new(v.x as f64, v.y as f64, v.z as f64))
+        .collect();
+    let triangles: Vec<[usize; 3]> = model
+        .indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+    let intersections = find_intersections(&vertices, &triangles);
+    info!(
+        "self_intersect found {} intersecting face pairs",
+        intersections.intersecting_pairs
+    );
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert(
+        "INTERSECTING_PAIRS".to_string(),
+        intersections.intersecting_pairs.to_string(),
+    );
+    let to_ffi = |p: &DVec3| FFIVector3::new(p.x as f32, p.y as f32, p.z as f32);
+    if split {
+        let (indices, unresolved) = split_faces(&triangles, &intersections);
+        let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+        let _ = return_config.insert("UNRESOLVED_SEGMENTS".to_string(), unresolved.to_string());
+        return Ok((
+            intersections.points.iter().map(to_ffi).collect(),
+            indices,
+            model.world_orientation.to_vec(),
+            return_config,
+        ));
+    }
+    // only the points of the segments are returned
+    let mut remap = AHashMap::<usize, usize>::new();
+    let mut vertices = Vec::<FFIVector3>::new();
+    let mut indices = Vec::<usize>::with_capacity(intersections.segments.len() * 2);
+    for (a, b) in intersections.segments.iter() {
+        for point in [*a, *b] {
+            let index = *remap.entry(point).or_insert_with(|| {
+                vertices.push(to_ffi(&intersections.points[point]));
+                vertices.len() - 1
+            });
+            indices.push(index);
+        }
+    }
+    let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    Ok((
+        vertices,
+        indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{cmd_mesh_analyze::analyze, ConfigType, OwnedModel},
+    ffi::FFIVector3,
+    HallrError,
+};
+use vector_traits::glam::DVec3;
+
+fn to_dvec3(v: &FFIVector3) -> DVec3 {
+    DVec3::new(v.x as f64, v.y as f64, v.z as f64)
+}
+
+/// Returns the sum of the (doubled) vector areas of the triangles
+fn vector_area(vertices: &[FFIVector3], indices: &[usize]) -> DVec3 {
+    indices
+        .chunks_exact(3)
+        .map(|t| {
+            let (a, b, c) = (
+                to_dvec3(&vertices[t[0]]),
+                to_dvec3(&vertices[t[1]]),
+                to_dvec3(&vertices[t[2]]),
+            );
+            (b - a).cross(c - a)
+        })
+        .sum()
+}
+
+fn has_edge(indices: &[usize], a: usize, b: usize) -> bool {
+    indices
+        .chunks_exact(3)
+        .any(|t| t.contains(&a) && t.contains(&b))
+}
+
+#[test]
+fn test_self_intersect_segments() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "self_intersect".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("SPLIT_FACES".to_string(), "false".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (4.0, 0.0, 0.0).into(),
+            (0.0, 4.0, 0.0).into(),
+            (1.0, 1.0, -1.0).into(),
+            (2.0, 1.0, 1.0).into(),
+            (1.0, 2.0, 1.0).into(),
+        ],
+        indices: vec![0, 1, 2, 3, 4, 5],
+    };
+    let result = super::process_command(config.clone(), vec![owned_model_0.as_model()])?;
+    assert_eq!("line_chunks", result.3.get("mesh.format").unwrap());
+    assert_eq!("1", result.3.get("INTERSECTING_PAIRS").unwrap());
+    assert_eq!(vec![0, 1], result.1);
+    let mut ends: Vec<(f32, f32, f32)> = result.0.iter().map(|v| (v.x, v.y, v.z)).collect();
+    ends.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(vec![(1.0, 1.5, 0.0), (1.5, 1.0, 0.0)], ends);
+
+    let _ = config.insert("SPLIT_FACES".to_string(), "true".to_string());
+    let result = super::process_command(config, vec![owned_model_0.as_model()])?;
+    assert_eq!("triangulated", result.3.get("mesh.format").unwrap());
+    assert_eq!("0", result.3.get("UNRESOLVED_SEGMENTS").unwrap());
+    assert_eq!(8, result.0.len());
+    // the first face is split into five triangles, the second into three
+    assert_eq!(8 * 3, result.1.len());
+    assert!(has_edge(&result.1[..15], 6, 7));
+    assert!(has_edge(&result.1[15..], 6, 7));
+    // the faces are split, not changed
+    let before = vector_area(&owned_model_0.vertices, &owned_model_0.indices);
+    let after = vector_area(&result.0, &result.1);
+    assert!(before.distance(after) < 1e-5, "{} {}", before, after);
+    Ok(())
+}
+
+#[test]
+fn test_self_intersect_shared_edge() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "self_intersect".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("SPLIT_FACES".to_string(), "false".to_string());
+
+    // a square crossed by a triangle, the intersection crosses the diagonal of the square
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (4.0, 0.0, 0.0).into(),
+            (4.0, 4.0, 0.0).into(),
+            (0.0, 4.0, 0.0).into(),
+            (0.0, 2.0, -1.0).into(),
+            (4.0, 2.0, -1.0).into(),
+            (2.0, 2.0, 1.0).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 2, 3, 4, 5, 6],
+    };
+    let result = super::process_command(config.clone(), vec![owned_model_0.as_model()])?;
+    assert_eq!("2", result.3.get("INTERSECTING_PAIRS").unwrap());
+    // the two segments share the point on the diagonal
+    assert_eq!(3, result.0.len());
+    assert_eq!(4, result.1.len());
+
+    let _ = config.insert("SPLIT_FACES".to_string(), "true".to_string());
+    let result = super::process_command(config, vec![owned_model_0.as_model()])?;
+    assert_eq!("0", result.3.get("UNRESOLVED_SEGMENTS").unwrap());
+    assert_eq!(10, result.0.len());
+    let before = vector_area(&owned_model_0.vertices, &owned_model_0.indices);
+    let after = vector_area(&result.0, &result.1);
+    assert!(before.distance(after) < 1e-5, "{} {}", before, after);
+    // no T-junctions: the outlines of the square and the triangle are the only open edges, and the
+    // two intersection segments are shared by four faces each
+    let vertices: Vec<DVec3> = result.0.iter().map(to_dvec3).collect();
+    let triangles: Vec<[usize; 3]> = result
+        .1
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+    let report = analyze(&vertices, &triangles, false);
+    assert_eq!(4 + 5, report.boundary_edges);
+    assert_eq!(2, report.non_manifold_edges);
+    assert_eq!(0, report.inconsistent_edges);
+    Ok(())
+}
+
+#[test]
+fn test_self_intersect_errors() {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "self_intersect".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("SPLIT_FACES".to_string(), "false".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(0.0, 0.0, 0.0).into(), (1.0, 0.0, 0.0).into()],
+        indices: vec![0, 1],
+    };
+    assert!(super::process_command(config.clone(), vec![owned_model_0.as_model()]).is_err());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    assert!(super::process_command(config, vec![owned_model_0.as_model()]).is_err());
+}
@@ -114,7 +114,7 @@ where
     let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
     let _ = config.insert("REMOVE_DOUBLES".to_string(), "false".to_string());
 
-    println!(
+    info!(
         "simplify_rdp operation returning {} vertices, {} indices",
         output_vertices.len(),
         output_indices.len()
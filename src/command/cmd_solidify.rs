@@ -0,0 +1,162 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use ahash::AHashMap;
+use vector_traits::glam::{dvec3, DVec3};
+
+#[cfg(test)]
+mod tests;
+
+/// Returns the angle weighted normal of every vertex. Vertices without any (non-degenerate)
+/// triangle get a zero normal.
+pub(crate) fn vertex_normals(vertices: &[DVec3], indices: &[usize]) -> Vec<DVec3> {
+    let mut normals = vec![DVec3::ZERO; vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let p = [
+            vertices[triangle[0]],
+            vertices[triangle[1]],
+            vertices[triangle[2]],
+        ];
+        let face_normal = (p[1] - p[0]).cross(p[2] - p[0]).normalize_or_zero();
+        if face_normal == DVec3::ZERO {
+            continue;
+        }
+        for corner in 0..3 {
+            let a = p[(corner + 1) % 3] - p[corner];
+            let b = p[(corner + 2) % 3] - p[corner];
+            normals[triangle[corner]] += face_normal * a.angle_between(b);
+        }
+    }
+    normals.iter().map(|n| n.normalize_or_zero()).collect()
+}
+
+/// Returns the boundary edges of the triangles, the edges only used by one triangle. The edges
+/// keep the direction they have in their triangle.
+pub(crate) fn boundary_edges(indices: &[usize]) -> Vec<(usize, usize)> {
+    let mut edges = AHashMap::<(usize, usize), (usize, usize, usize)>::new();
+    for triangle in indices.chunks_exact(3) {
+        for corner in 0..3 {
+            let a = triangle[corner];
+            let b = triangle[(corner + 1) % 3];
+            let entry = edges.entry((a.min(b), a.max(b))).or_insert((a, b, 0));
+            entry.2 += 1;
+        }
+    }
+    let mut rv: Vec<(usize, usize)> = edges
+        .values()
+        .filter(|(_, _, count)| *count == 1)
+        .map(|(a, b, _)| (*a, *b))
+        .collect();
+    // the hash map order is random, keep the output deterministic
+    rv.sort_unstable();
+    rv
+}
+
+/// Run the solidify command
+/// Model 0 is an open, triangulated surface. Every vertex is offset along its (angle weighted)
+/// normal, and the two resulting surfaces are stitched together at the open rims into a closed
+/// solid of `THICKNESS`. `SOLIDIFY_OFFSET` places the solid relative to the surface: -1 (default)
+/// behind the surface, 0 centered and 1 in front of it. With `THICKNESS=0` the result is a double
+/// sided surface, without any rim.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("triangulated") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'triangulated' format".to_string(),
+        ));
+    }
+    let thickness = config.get_mandatory_parsed_option::<f64>("THICKNESS", None)?;
+    if !thickness.is_finite() || thickness < 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "THICKNESS must be zero or a positive number :({})",
+            thickness
+        )));
+    }
+    let offset = config.get_mandatory_parsed_option::<f64>("SOLIDIFY_OFFSET", Some(-1.0))?;
+    if !(-1.0..=1.0).contains(&offset) {
+        return Err(HallrError::InvalidParameter(format!(
+            "SOLIDIFY_OFFSET must be in the -1..1 range :({})",
+            offset
+        )));
+    }
+    let model = &models[0];
+    if model.indices.len() % 3 != 0 {
+        return Err(HallrError::InvalidInputData(
+            "The number of indices is not a multiple of three".to_string(),
+        ));
+    }
+    if let Some(index) = model.indices.iter().find(|i| **i >= model.vertices.len()) {
+        return Err(HallrError::InvalidInputData(format!(
+            "The index {} is out of bounds",
+            index
+        )));
+    }
+
+    let vertices: Vec<DVec3> = model
+        .vertices
+        .iter()
+        .map(|v| dvec3(v.x as f64, v.y as f64, v.z as f64))
+        .collect();
+    let normals = vertex_normals(&vertices, model.indices);
+    let front_distance = thickness * (offset + 1.0) * 0.5;
+    let back_distance = thickness * (offset - 1.0) * 0.5;
+    let to_ffi = |v: DVec3| FFIVector3::new(v.x as f32, v.y as f32, v.z as f32);
+
+    // the front surface uses the vertex indices as they are, the back surface is shifted by n
+    let n = vertices.len();
+    let mut output_vertices = Vec::<FFIVector3>::with_capacity(2 * n);
+    output_vertices.extend(
+        vertices
+            .iter()
+            .zip(normals.iter())
+            .map(|(v, normal)| to_ffi(*v + *normal * front_distance)),
+    );
+    output_vertices.extend(
+        vertices
+            .iter()
+            .zip(normals.iter())
+            .map(|(v, normal)| to_ffi(*v + *normal * back_distance)),
+    );
+
+    let mut output_indices = Vec::<usize>::with_capacity(2 * model.indices.len());
+    output_indices.extend_from_slice(model.indices);
+    // the back surface faces the other way
+    for triangle in model.indices.chunks_exact(3) {
+        output_indices.extend([triangle[0] + n, triangle[2] + n, triangle[1] + n]);
+    }
+    let rim = if thickness > 0.0 {
+        boundary_edges(model.indices)
+    } else {
+        Vec::default()
+    };
+    for (a, b) in rim.iter() {
+        // the rim quad runs b->a along the front surface, and a->b along the back surface
+        output_indices.extend([*b, *a, *a + n, *b, *a + n, *b + n]);
+    }
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+    info!(
+        "solidify operation returning {} vertices, {} indices, {} rim edges",
+        output_vertices.len(),
+        output_indices.len(),
+        rim.len()
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType},
+    HallrError,
+};
+use ahash::AHashMap;
+
+#[test]
+fn test_solidify_boundary_edges() {
+    let edges = super::boundary_edges(&[0, 1, 2, 0, 2, 3]);
+    assert_eq!(vec![(0, 1), (1, 2), (2, 3), (3, 0)], edges);
+}
+
+#[test]
+fn test_solidify_1() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "solidify".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("THICKNESS".to_string(), "0.5".to_string());
+
+    let owned_model_0 = fixtures::rectangle_faces([0.0, 0.0], [1.0, 1.0], 0.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(8, result.0.len()); // vertices
+                                   // 2 front, 2 back and 4*2 rim triangles
+    assert_eq!(3 * 12, result.1.len()); // indices
+    assert!(result.0[..4].iter().all(|v| v.z == 0.0));
+    assert!(result.0[4..].iter().all(|v| (v.z + 0.5).abs() < 1e-6));
+
+    // the result is closed and consistently oriented: every directed edge is used exactly once,
+    // and so is its opposite
+    let mut directed_edges = AHashMap::<(usize, usize), usize>::new();
+    for triangle in result.1.chunks_exact(3) {
+        for corner in 0..3 {
+            *directed_edges
+                .entry((triangle[corner], triangle[(corner + 1) % 3]))
+                .or_default() += 1;
+        }
+    }
+    for ((a, b), count) in directed_edges.iter() {
+        assert_eq!(1, *count);
+        assert_eq!(Some(&1), directed_edges.get(&(*b, *a)));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_solidify_2() -> Result<(), HallrError> {
+    // a centered, double sided surface
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "solidify".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("THICKNESS".to_string(), "0.0".to_string());
+    let _ = config.insert("SOLIDIFY_OFFSET".to_string(), "0".to_string());
+
+    let owned_model_0 = fixtures::rectangle_faces([0.0, 0.0], [1.0, 1.0], 0.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert_eq!(8, result.0.len()); // vertices
+    assert_eq!(3 * 4, result.1.len()); // indices
+
+    let _ = config.remove("SOLIDIFY_OFFSET");
+    let _ = config.insert("THICKNESS".to_string(), "-1.0".to_string());
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
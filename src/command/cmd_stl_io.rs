@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{cmd_obj_io::FileMode, ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use std::{fmt::Write, fs, str::FromStr};
+use vector_traits::glam::{vec3, Vec3};
+
+#[cfg(test)]
+mod tests;
+
+/// The size of the binary STL header
+const HEADER_SIZE: usize = 80;
+/// The size of a binary STL triangle: normal, three vertices and the attribute byte count
+const TRIANGLE_SIZE: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StlFormat {
+    Ascii,
+    Binary,
+}
+
+impl FromStr for StlFormat {
+    type Err = HallrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ASCII" => Ok(Self::Ascii),
+            "BINARY" => Ok(Self::Binary),
+            _ => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"STL_FORMAT\" parameter",
+                s
+            ))),
+        }
+    }
+}
+
+/// Parse a binary or an ASCII STL file, the format is detected from the content.
+/// Every triangle gets three vertices of its own, the vertices are multiplied by `scale`.
+/// Returns the vertices and the format of the data.
+pub(crate) fn parse_stl(
+    data: &[u8],
+    scale: f32,
+) -> Result<(Vec<FFIVector3>, StlFormat), HallrError> {
+    // binary files may also start with "solid", the size is the reliable test
+    if data.len() >= HEADER_SIZE + 4 {
+        let count = u32::from_le_bytes([
+            data[HEADER_SIZE],
+            data[HEADER_SIZE + 1],
+            data[HEADER_SIZE + 2],
+            data[HEADER_SIZE + 3],
+        ]) as usize;
+        if count
+            .checked_mul(TRIANGLE_SIZE)
+            .is_some_and(|size| size + HEADER_SIZE + 4 == data.len())
+        {
+            let vertices = data[HEADER_SIZE + 4..]
+                .chunks_exact(TRIANGLE_SIZE)
+                .flat_map(|triangle| {
+                    // skip the normal
+                    triangle[12..48].chunks_exact(12).map(move |v| {
+                        let f = |i: usize| {
+                            f32::from_le_bytes([v[i], v[i + 1], v[i + 2], v[i + 3]]) * scale
+                        };
+                        FFIVector3::new(f(0), f(4), f(8))
+                    })
+                })
+                .collect();
+            return Ok((vertices, StlFormat::Binary));
+        }
+    }
+    let text = std::str::from_utf8(data).map_err(|_| {
+        HallrError::InvalidInputData(
+            "The STL data is neither valid binary nor ASCII STL".to_string(),
+        )
+    })?;
+    if !text.trim_start().starts_with("solid") {
+        return Err(HallrError::InvalidInputData(
+            "The STL data is neither valid binary nor ASCII STL".to_string(),
+        ));
+    }
+    let mut vertices = Vec::<FFIVector3>::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("vertex") {
+            continue;
+        }
+        let coordinates = tokens
+            .take(3)
+            .map(|t| t.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .filter(|c| c.len() == 3)
+            .ok_or_else(|| {
+                HallrError::InvalidInputData(format!("Invalid vertex at line {}", line_number + 1))
+            })?;
+        vertices.push(FFIVector3::new(
+            coordinates[0] * scale,
+            coordinates[1] * scale,
+            coordinates[2] * scale,
+        ));
+    }
+    if vertices.len() % 3 != 0 {
+        return Err(HallrError::InvalidInputData(format!(
+            "The ASCII STL data has {} vertices, not a multiple of three",
+            vertices.len()
+        )));
+    }
+    Ok((vertices, StlFormat::Ascii))
+}
+
+/// Format the triangles as STL data, the vertices are multiplied by `scale`
+pub(crate) fn to_stl(
+    name: &str,
+    vertices: &[FFIVector3],
+    indices: &[usize],
+    format: StlFormat,
+    scale: f32,
+) -> Result<Vec<u8>, HallrError> {
+    if indices.len() % 3 != 0 {
+        return Err(HallrError::InvalidInputData(
+            "The number of indices is not a multiple of three".to_string(),
+        ));
+    }
+    let triangles = indices
+        .chunks_exact(3)
+        .map(|t| {
+            let mut triangle = [Vec3::ZERO; 3];
+            for (corner, i) in triangle.iter_mut().zip(t.iter()) {
+                let v = vertices.get(*i).ok_or_else(|| {
+                    HallrError::InvalidInputData(format!("The index {} is out of bounds", i))
+                })?;
+                *corner = vec3(v.x, v.y, v.z) * scale;
+            }
+            Ok(triangle)
+        })
+        .collect::<Result<Vec<_>, HallrError>>()?;
+    let normal = |t: &[Vec3; 3]| (t[1] - t[0]).cross(t[2] - t[0]).normalize_or_zero();
+
+    Ok(match format {
+        StlFormat::Ascii => {
+            let mut rv = String::new();
+            let _ = writeln!(rv, "solid {}", name);
+            for t in triangles.iter() {
+                let n = normal(t);
+                let _ = writeln!(rv, "facet normal {} {} {}\nouter loop", n.x, n.y, n.z);
+                for v in t.iter() {
+                    let _ = writeln!(rv, "vertex {} {} {}", v.x, v.y, v.z);
+                }
+                rv.push_str("endloop\nendfacet\n");
+            }
+            let _ = writeln!(rv, "endsolid {}", name);
+            rv.into_bytes()
+        }
+        StlFormat::Binary => {
+            let mut rv =
+                Vec::<u8>::with_capacity(HEADER_SIZE + 4 + triangles.len() * TRIANGLE_SIZE);
+            let mut header = [0_u8; HEADER_SIZE];
+            // a binary header must not start with "solid"
+            for (h, c) in header.iter_mut().zip(format!("hallr {}", name).bytes()) {
+                *h = c;
+            }
+            rv.extend_from_slice(&header);
+            rv.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+            for t in triangles.iter() {
+                for v in std::iter::once(normal(t)).chain(t.iter().copied()) {
+                    for c in v.to_array() {
+                        rv.extend_from_slice(&c.to_le_bytes());
+                    }
+                }
+                rv.extend_from_slice(&[0, 0]);
+            }
+            rv
+        }
+    })
+}
+
+/// Run the stl_io command
+/// With `STL_MODE=LOAD` the binary or ASCII STL file at `STL_PATH` is returned as a triangulated
+/// mesh, with the world matrix of model 0 (model 0 itself is not used, it can be empty). Every
+/// triangle has vertices of its own, `REMOVE_DOUBLES` is requested from the addon. The detected
+/// format is returned as `STL_FORMAT`.
+/// With `STL_MODE=SAVE` the triangulated model 0 is written to `STL_PATH` in the `STL_FORMAT`
+/// (ASCII or BINARY, default BINARY) and returned unchanged.
+/// The coordinates read or written are multiplied by `STL_SCALE` (default 1.0), e.g. 1000 to
+/// write a model in meters as millimeters. The triangle count is returned as `STL_TRIANGLES`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let mode = config.get_mandatory_parsed_option::<FileMode>("STL_MODE", None)?;
+    let path = config.get_mandatory_option("STL_PATH")?;
+    let scale = config.get_mandatory_parsed_option::<f32>("STL_SCALE", Some(1.0))?;
+    if !scale.is_finite() || scale <= 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "STL_SCALE must be a positive number :({})",
+            scale
+        )));
+    }
+    let model = &models[0];
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let (vertices, indices, format) = match mode {
+        FileMode::Load => {
+            let data = fs::read(path).map_err(|e| {
+                HallrError::InvalidParameter(format!("Could not read {}: {}", path, e))
+            })?;
+            let (vertices, format) = parse_stl(&data, scale)?;
+            let indices = (0..vertices.len()).collect();
+            let _ = return_config.insert("REMOVE_DOUBLES".to_string(), "true".to_string());
+            (vertices, indices, format)
+        }
+        FileMode::Save => {
+            let mesh_format = config.get_mandatory_option("mesh.format")?;
+            if mesh_format.ne("triangulated") {
+                return Err(HallrError::InvalidInputData(
+                    "Model mesh data must be in the 'triangulated' format".to_string(),
+                ));
+            }
+            let format = config
+                .get_mandatory_parsed_option::<StlFormat>("STL_FORMAT", Some(StlFormat::Binary))?;
+            let name = config
+                .get_mandatory_parsed_option::<String>("STL_NAME", Some("hallr".to_string()))?;
+            let data = to_stl(&name, model.vertices, model.indices, format, scale)?;
+            fs::write(path, data).map_err(|e| {
+                HallrError::InternalError(format!("Could not write {}: {}", path, e))
+            })?;
+            (model.vertices.to_vec(), model.indices.to_vec(), format)
+        }
+    };
+    let _ = return_config.insert(
+        "STL_FORMAT".to_string(),
+        match format {
+            StlFormat::Ascii => "ASCII",
+            StlFormat::Binary => "BINARY",
+        }
+        .to_string(),
+    );
+    let _ = return_config.insert("STL_TRIANGLES".to_string(), (indices.len() / 3).to_string());
+    debug!(
+        "stl_io operation {:?} {} ({:?}): {} triangles",
+        mode,
+        path,
+        format,
+        indices.len() / 3
+    );
+    Ok((
+        vertices,
+        indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
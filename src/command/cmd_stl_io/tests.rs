@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::StlFormat;
+use crate::{
+    command::{ConfigType, OwnedModel},
+    HallrError,
+};
+
+fn quad() -> OwnedModel {
+    OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (1.0, 0.0, 0.25).into(),
+            (1.0, 1.0, 0.0).into(),
+            (0.0, 1.0, -0.5).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 2, 3],
+    }
+}
+
+#[test]
+fn test_stl_io_formats() -> Result<(), HallrError> {
+    let model = quad();
+    for format in [StlFormat::Ascii, StlFormat::Binary] {
+        let data = super::to_stl("quad", &model.vertices, &model.indices, format, 2.0)?;
+        let (vertices, detected) = super::parse_stl(&data, 0.5)?;
+        assert_eq!(format, detected);
+        assert_eq!(6, vertices.len());
+        for (v, i) in vertices.iter().zip(model.indices.iter()) {
+            let expected = model.vertices[*i];
+            assert!((v.x - expected.x).abs() < 1e-6);
+            assert!((v.y - expected.y).abs() < 1e-6);
+            assert!((v.z - expected.z).abs() < 1e-6);
+        }
+    }
+    let data = super::to_stl(
+        "quad",
+        &model.vertices,
+        &model.indices,
+        StlFormat::Binary,
+        1.0,
+    )?;
+    assert_eq!(84 + 2 * 50, data.len());
+    assert!(super::parse_stl(b"not an stl file", 1.0).is_err());
+    assert!(super::parse_stl(b"solid x\nvertex 0 0 0\nendsolid x\n", 1.0).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_stl_io_round_trip() -> Result<(), HallrError> {
+    let path = std::env::temp_dir().join(format!("hallr_stl_test_{}.stl", std::process::id()));
+    let path = path.to_str().unwrap();
+    let owned_model_0 = quad();
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "stl_io".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("STL_MODE".to_string(), "SAVE".to_string());
+    let _ = config.insert("STL_PATH".to_string(), path.to_string());
+    let _ = config.insert("STL_FORMAT".to_string(), "ASCII".to_string());
+    let _ = config.insert("STL_SCALE".to_string(), "1000".to_string());
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert_eq!("2", result.3.get("STL_TRIANGLES").unwrap());
+
+    let _ = config.insert("STL_MODE".to_string(), "LOAD".to_string());
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models);
+    let _ = std::fs::remove_file(path);
+    let result = result?;
+    assert_eq!("ASCII", result.3.get("STL_FORMAT").unwrap());
+    assert_eq!("true", result.3.get("REMOVE_DOUBLES").unwrap());
+    assert_eq!(6, result.0.len());
+    assert_eq!(vec![0, 1, 2, 3, 4, 5], result.1);
+    // scaled twice
+    assert_eq!(250000.0, result.0[1].z);
+    Ok(())
+}
@@ -2,7 +2,11 @@
 // Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
 // This file is part of the hallr crate.
 
-use super::{ConfigType, Model};
+use super::{
+    cmd_scalar_to_color,
+    gcode::{self, GcodeSettings},
+    progress, ConfigType, Model,
+};
 use hronn::{
     generate_aabb_then_convex_hull, generate_convex_hull_then_aabb,
     prelude::{
@@ -13,12 +17,433 @@ use hronn::{
     HronnError,
 };
 
-use crate::{command::Options, prelude::FFIVector3, HallrError};
+use crate::{command::Options, prelude::FFIVector3, utils::bvh::Bvh, HallrError};
 use krakel::PointTrait;
-use vector_traits::{num_traits::AsPrimitive, GenericVector3, HasXY};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+};
+use vector_traits::{
+    glam::{dvec3, DVec3, Mat4, Vec3},
+    num_traits::AsPrimitive,
+    GenericVector3, HasXY,
+};
 
 #[cfg(test)]
 mod tests;
+
+/// Samples closer than this to `minimum_z` are counted as clamped
+const CLAMP_EPSILON: f32 = 1e-5;
+
+/// Returns the world matrix of the model, the FFI matrix is packed in row major order
+fn world_matrix(model: &Model<'_>) -> Result<Mat4, HallrError> {
+    Ok(Mat4::from_cols_array(&model.copy_world_orientation()?).transpose())
+}
+
+/// Returns the matrix transforming world coordinates into the frame of the first target
+fn scan_frame_inverse(models: &[Model<'_>]) -> Result<Mat4, HallrError> {
+    let scan_frame = world_matrix(&models[0])?;
+    if scan_frame.determinant().abs() < f32::EPSILON {
+        return Err(HallrError::InvalidInputData(
+            "The world matrix of the first target mesh is not invertible".to_string(),
+        ));
+    }
+    Ok(scan_frame.inverse())
+}
+
+/// Merge the target meshes into one single mesh, in the coordinate frame of the first target.
+/// Model 1 is the bounding shape, so the targets are model 0 and model 2..n, except for the
+/// `nominal` model.
+/// If there is only one target the data is simply borrowed.
+#[allow(clippy::type_complexity)]
+fn merge_target_models<'a>(
+    models: &[Model<'a>],
+    nominal: Option<usize>,
+) -> Result<(Cow<'a, [FFIVector3]>, Cow<'a, [usize]>), HallrError> {
+    let model = &models[0];
+    if models.len() - usize::from(nominal.is_some()) <= 2 {
+        return Ok((Cow::Borrowed(model.vertices), Cow::Borrowed(model.indices)));
+    }
+    let scan_frame_inverse = scan_frame_inverse(models)?;
+
+    let mut vertices = model.vertices.to_vec();
+    let mut indices = model.indices.to_vec();
+    for (model_number, target) in models.iter().enumerate().skip(2) {
+        if Some(model_number) == nominal {
+            continue;
+        }
+        if target.indices.len() % 3 != 0 {
+            return Err(HallrError::InvalidInputData(format!(
+                "The target mesh of model {} is not triangulated",
+                model_number
+            )));
+        }
+        // world -> scan frame
+        let transform = scan_frame_inverse * world_matrix(target)?;
+        let vertex_offset = vertices.len();
+        vertices.extend(target.vertices.iter().map(|v| {
+            let v = transform.transform_point3(Vec3::new(v.x, v.y, v.z));
+            FFIVector3::new(v.x, v.y, v.z)
+        }));
+        indices.extend(target.indices.iter().map(|i| *i + vertex_offset));
+    }
+    debug!(
+        "surface_scan: merged {} target meshes into {} vertices and {} indices",
+        models.len() - 1 - usize::from(nominal.is_some()),
+        vertices.len(),
+        indices.len()
+    );
+    Ok((Cow::Owned(vertices), Cow::Owned(indices)))
+}
+
+/// How the passes of a meander toolpath are ordered, from the `pass_order`, `start_corner`,
+/// `direction` and `return_z` options.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MeanderOrder {
+    /// All passes are cut in the same direction, with a return move at `return_z` in between
+    unidirectional: bool,
+    /// The first pass starts at the min X (else max X) side
+    start_min_x: bool,
+    /// The first pass starts at the min Y (else max Y) side
+    start_min_y: bool,
+    /// Some(true) for climb, Some(false) for conventional cutting (clockwise spindle).
+    /// Overrides the pass direction given by the start corner.
+    climb: Option<bool>,
+    /// The height of the return moves, defaults to one step above the highest sample
+    return_z: Option<f32>,
+}
+
+impl MeanderOrder {
+    /// Parse the options, returns None if none of them are set (the hronn order is kept as is)
+    fn from_config(config: &ConfigType) -> Result<Option<Self>, HallrError> {
+        let keys = ["pass_order", "start_corner", "direction", "return_z"];
+        if !keys.iter().any(|key| config.contains_key(*key)) {
+            return Ok(None);
+        }
+        let unidirectional = match config
+            .get_mandatory_parsed_option::<String>("pass_order", Some("ZIGZAG".to_string()))?
+            .as_str()
+        {
+            "ZIGZAG" => false,
+            "UNIDIRECTIONAL" => true,
+            pass_order => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"pass_order\" parameter",
+                pass_order
+            )))?,
+        };
+        let (start_min_x, start_min_y) = match config
+            .get_mandatory_parsed_option::<String>("start_corner", Some("MIN_X_MIN_Y".to_string()))?
+            .as_str()
+        {
+            "MIN_X_MIN_Y" => (true, true),
+            "MAX_X_MIN_Y" => (false, true),
+            "MIN_X_MAX_Y" => (true, false),
+            "MAX_X_MAX_Y" => (false, false),
+            corner => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"start_corner\" parameter",
+                corner
+            )))?,
+        };
+        let climb = match config.get_parsed_option::<String>("direction")?.as_deref() {
+            None => None,
+            Some("CLIMB") => Some(true),
+            Some("CONVENTIONAL") => Some(false),
+            Some(direction) => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"direction\" parameter",
+                direction
+            )))?,
+        };
+        if climb.is_some() && !unidirectional {
+            return Err(HallrError::InvalidParameter(
+                "The \"direction\" parameter requires \"pass_order\"=UNIDIRECTIONAL".to_string(),
+            ));
+        }
+        Ok(Some(Self {
+            unidirectional,
+            start_min_x,
+            start_min_y,
+            climb,
+            return_z: config.get_parsed_option::<f32>("return_z")?,
+        }))
+    }
+}
+
+/// Returns the pass of every sample: the offset along the stepover axis rounded to whole `step`
+/// units. The pass axis is the axis giving the fewest passes, true if the passes run along X.
+fn pass_keys(samples: &[FFIVector3], step: f32) -> (bool, Vec<i64>) {
+    let keys = |along_x: bool| -> Vec<i64> {
+        samples
+            .iter()
+            .map(|v| ((if along_x { v.y } else { v.x }) / step).round() as i64)
+            .collect()
+    };
+    let count = |keys: &[i64]| keys.iter().collect::<BTreeSet<_>>().len();
+    let x_keys = keys(true);
+    let y_keys = keys(false);
+    if count(&x_keys) <= count(&y_keys) {
+        (true, x_keys)
+    } else {
+        (false, y_keys)
+    }
+}
+
+/// Split the samples of a meander line into passes (as grouped by `pass_keys()`), ordered along
+/// the stepover axis. The samples of every pass are sorted along the pass axis.
+/// Returns true if the passes run along X, and the passes.
+fn split_passes(samples: &[FFIVector3], step: f32) -> (bool, Vec<Vec<FFIVector3>>) {
+    let (along_x, keys) = pass_keys(samples, step);
+    let mut passes = BTreeMap::<i64, Vec<FFIVector3>>::new();
+    for (v, key) in samples.iter().zip(keys) {
+        passes.entry(key).or_default().push(*v);
+    }
+    let pass_coordinate = |v: &FFIVector3| if along_x { v.x } else { v.y };
+    let passes = passes
+        .into_values()
+        .map(|mut pass| {
+            pass.sort_by(|a, b| pass_coordinate(a).total_cmp(&pass_coordinate(b)));
+            pass
+        })
+        .collect();
+    (along_x, passes)
+}
+
+/// Returns the height of the pass at `position` along the pass axis, interpolated between the
+/// samples. None if the position is outside of the pass.
+fn pass_z_at(pass: &[FFIVector3], along_x: bool, position: f32) -> Option<f32> {
+    let pass_coordinate = |v: &FFIVector3| if along_x { v.x } else { v.y };
+    let i = pass.partition_point(|v| pass_coordinate(v) < position);
+    let b = pass.get(i)?;
+    if pass_coordinate(b) == position {
+        return Some(b.z);
+    }
+    let a = pass.get(i.checked_sub(1)?)?;
+    let t = (position - pass_coordinate(a)) / (pass_coordinate(b) - pass_coordinate(a));
+    Some(a.z + (b.z - a.z) * t)
+}
+
+/// Select the passes of a curvature adaptive meander from densely spaced `passes`. A pass is
+/// dropped when the surface between its neighbours is within `max_chord_error` (along Z) of the
+/// straight line between them. At most `max_stride - 1` passes are dropped in a row, the first
+/// and the last pass are always kept. Returns the indices of the kept passes.
+fn select_adaptive_passes(
+    passes: &[Vec<FFIVector3>],
+    along_x: bool,
+    max_chord_error: f32,
+    max_stride: usize,
+) -> Vec<usize> {
+    if passes.is_empty() {
+        return Vec::default();
+    }
+    let stepover_coordinate = |pass: &[FFIVector3]| {
+        pass.iter()
+            .map(|v| if along_x { v.y } else { v.x })
+            .sum::<f32>()
+            / pass.len() as f32
+    };
+    let pass_coordinate = |v: &FFIVector3| if along_x { v.x } else { v.y };
+    // true if every pass between `from` and `to` is within the chord error
+    let is_flat = |from: usize, to: usize| {
+        let (s0, s1) = (
+            stepover_coordinate(&passes[from]),
+            stepover_coordinate(&passes[to]),
+        );
+        (from + 1..to).all(|i| {
+            let t = (stepover_coordinate(&passes[i]) - s0) / (s1 - s0);
+            passes[i].iter().all(|v| {
+                let position = pass_coordinate(v);
+                match (
+                    pass_z_at(&passes[from], along_x, position),
+                    pass_z_at(&passes[to], along_x, position),
+                ) {
+                    (Some(z0), Some(z1)) => (v.z - (z0 + (z1 - z0) * t)).abs() <= max_chord_error,
+                    // the neighbours do not cover the sample, so it can not be dropped
+                    _ => false,
+                }
+            })
+        })
+    };
+    let last = passes.len() - 1;
+    let mut rv = vec![0];
+    let mut from = 0;
+    while from < last {
+        let mut to = from + 1;
+        while to < last && to + 1 - from <= max_stride.max(1) && is_flat(from, to + 1) {
+            to += 1;
+        }
+        rv.push(to);
+        from = to;
+    }
+    rv
+}
+
+/// Thin the dense meander line (scanned at `min_step`) into a curvature adaptive zig-zag: the step
+/// between the kept passes is at most `max_step`, and shrinks where the surface deviates more than
+/// `max_chord_error` from a straight line between two passes.
+/// Returns the new line, as a list of vertices in order.
+fn adaptive_meander(
+    vertices: &[FFIVector3],
+    line: &[usize],
+    min_step: f32,
+    max_step: f32,
+    max_chord_error: f32,
+) -> Result<Vec<FFIVector3>, HallrError> {
+    let samples: Vec<FFIVector3> = line
+        .iter()
+        .map(|i| {
+            vertices.get(*i).copied().ok_or_else(|| {
+                HallrError::InternalError("The meander line index is out of bounds".to_string())
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    let (along_x, passes) = split_passes(&samples, min_step);
+    let max_stride = (max_step / min_step).round() as usize;
+    let kept = select_adaptive_passes(&passes, along_x, max_chord_error, max_stride);
+    debug!(
+        "surface_scan: the adaptive stepover kept {} of {} passes",
+        kept.len(),
+        passes.len()
+    );
+    let mut rv = Vec::<FFIVector3>::with_capacity(samples.len());
+    for (n, i) in kept.into_iter().enumerate() {
+        if n % 2 == 0 {
+            rv.extend(passes[i].iter());
+        } else {
+            rv.extend(passes[i].iter().rev());
+        }
+    }
+    Ok(rv)
+}
+
+/// The kind of motion of a toolpath pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PassType {
+    /// Cutting along a scan line
+    Raster,
+    /// Moving between two scan lines, a step-over or a return move
+    Link,
+}
+
+impl PassType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Raster => "RASTER",
+            Self::Link => "LINK",
+        }
+    }
+}
+
+/// Group the edges of a meander line into passes. Edge `k` joins sample `k` and `k + 1`, an edge
+/// is a link if its samples are on different scan lines or if it only moves along Z (the ends of
+/// a return move). Returns the first edge and the type of every pass.
+fn meander_pass_groups(samples: &[FFIVector3], step: f32) -> Vec<(usize, PassType)> {
+    if step <= 0.0 {
+        return Vec::default();
+    }
+    let (_, keys) = pass_keys(samples, step);
+    let mut rv = Vec::<(usize, PassType)>::new();
+    for (edge, (pair, key_pair)) in samples.windows(2).zip(keys.windows(2)).enumerate() {
+        let vertical = pair[0].x == pair[1].x && pair[0].y == pair[1].y;
+        let pass_type = if key_pair[0] != key_pair[1] || vertical {
+            PassType::Link
+        } else {
+            PassType::Raster
+        };
+        if rv.last().map(|(_, t)| *t) != Some(pass_type) {
+            rv.push((edge, pass_type));
+        }
+    }
+    rv
+}
+
+/// Split the meander line into passes and reorder them. The samples are grouped into passes by
+/// rounding their offset along the stepover axis to whole `step` units. The pass axis is the axis
+/// giving the fewest passes.
+/// Returns the new line, as a list of vertices in order.
+fn reorder_meander_passes(
+    vertices: &[FFIVector3],
+    line: &[usize],
+    step: f32,
+    order: &MeanderOrder,
+) -> Result<Vec<FFIVector3>, HallrError> {
+    if step <= 0.0 {
+        return Err(HallrError::InvalidParameter(
+            "The step must be positive".to_string(),
+        ));
+    }
+    let samples: Vec<FFIVector3> = line
+        .iter()
+        .map(|i| {
+            vertices.get(*i).copied().ok_or_else(|| {
+                HallrError::InternalError("The meander line index is out of bounds".to_string())
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    if samples.is_empty() {
+        return Ok(samples);
+    }
+    let (along_x, mut passes) = split_passes(&samples, step);
+    let pass_coordinate = |v: &FFIVector3| if along_x { v.x } else { v.y };
+
+    // the stepover goes from the start corner towards the other side
+    let stepover_ascending = if along_x {
+        order.start_min_y
+    } else {
+        order.start_min_x
+    };
+    let first_pass_positive = match order.climb {
+        Some(climb) => {
+            // with a clockwise spindle the cut is a climb cut when the uncut material (in the
+            // stepover direction) is on the right hand side of the cut direction
+            let stepover = if stepover_ascending { 1.0_f32 } else { -1.0 };
+            // the right hand side of +X is -Y and the right hand side of +Y is +X
+            let positive_is_climb = if along_x {
+                -stepover > 0.0
+            } else {
+                stepover > 0.0
+            };
+            climb == positive_is_climb
+        }
+        None => {
+            if along_x {
+                order.start_min_x
+            } else {
+                order.start_min_y
+            }
+        }
+    };
+    let return_z = order
+        .return_z
+        .unwrap_or_else(|| samples.iter().map(|v| v.z).fold(f32::MIN, f32::max) + step);
+
+    if !stepover_ascending {
+        passes.reverse();
+    }
+    let mut rv = Vec::<FFIVector3>::with_capacity(samples.len() + 2 * passes.len());
+    for (n, mut pass) in passes.into_iter().enumerate() {
+        let positive = if order.unidirectional {
+            first_pass_positive
+        } else {
+            first_pass_positive == (n % 2 == 0)
+        };
+        pass.sort_by(|a, b| {
+            let ordering = pass_coordinate(a).total_cmp(&pass_coordinate(b));
+            if positive {
+                ordering
+            } else {
+                ordering.reverse()
+            }
+        });
+        if order.unidirectional && n > 0 {
+            let last = *rv.last().unwrap();
+            let first = pass[0];
+            rv.push(FFIVector3::new(last.x, last.y, return_z));
+            rv.push(FFIVector3::new(first.x, first.y, return_z));
+        }
+        rv.extend(pass);
+    }
+    Ok(rv)
+}
+
 fn do_meander_scan<T: GenericVector3>(
     config: ConfigType,
     bounding_vertices: &[FFIVector3],
@@ -59,6 +484,30 @@ where
     //println!("bounding_indices {:?}", bounding_indices.len());
     //println!("bounding_vertices {:?}", bounding_vertices.len());
 
+    let meander_order = MeanderOrder::from_config(&config)?;
+    // the adaptive stepover selects its passes from a dense scan at `min_step`
+    let max_chord_error = config.get_parsed_option::<f32>("max_chord_error")?;
+    let scan_step = if let Some(max_chord_error) = max_chord_error {
+        if !max_chord_error.is_finite() || max_chord_error <= 0.0 {
+            return Err(HallrError::InvalidParameter(format!(
+                "The \"max_chord_error\" parameter must be positive :({})",
+                max_chord_error
+            )));
+        }
+        let four: T::Scalar = 4_u32.as_();
+        let min_step =
+            config.get_mandatory_parsed_option::<T::Scalar>("min_step", Some(step / four))?;
+        let min_step_f: f32 = min_step.as_();
+        if !min_step_f.is_finite() || min_step_f <= 0.0 || min_step > step {
+            return Err(HallrError::InvalidParameter(
+                "The \"min_step\" parameter must be positive and not larger than \"step\""
+                    .to_string(),
+            ));
+        }
+        min_step
+    } else {
+        step
+    };
     let (aabb, convex_hull) = match config.get_mandatory_option("bounds")? {
         "CONVEX_HULL" => generate_convex_hull_then_aabb(bounding_vertices),
         "AABB" => generate_aabb_then_convex_hull(bounding_vertices),
@@ -68,7 +517,7 @@ where
         ))),
     }?;
 
-    let mut results = MeanderPattern::<T, FFIVector3>::new(aabb, convex_hull, step)?
+    let mut results = MeanderPattern::<T, FFIVector3>::new(aabb, convex_hull, scan_step)?
         .search(mesh_analyzer, &search_config)?
         .get_line_data()?;
     let mut return_config = ConfigType::new();
@@ -76,8 +525,459 @@ where
     let _ = return_config.insert("mesh.format".to_string(), "line".to_string());
 
     let indices = results.lines.pop().unwrap_or_else(Vec::default);
+    let (vertices, indices) = if let Some(max_chord_error) = max_chord_error {
+        let vertices = adaptive_meander(
+            &results.vertices,
+            &indices,
+            scan_step.as_(),
+            step.as_(),
+            max_chord_error,
+        )?;
+        let indices = (0..vertices.len()).collect();
+        (vertices, indices)
+    } else {
+        (results.vertices, indices)
+    };
+
+    let (vertices, indices) = if let Some(order) = meander_order {
+        let vertices = reorder_meander_passes(&vertices, &indices, scan_step.as_(), &order)?;
+        let indices = (0..vertices.len()).collect();
+        (vertices, indices)
+    } else {
+        (vertices, indices)
+    };
+    let samples: Vec<FFIVector3> = indices
+        .iter()
+        .filter_map(|i: &usize| vertices.get(*i).copied())
+        .collect();
+    let groups = meander_pass_groups(&samples, scan_step.as_());
+    // the drag knife compensation adds points, so the pass starts are moved along
+    let (vertices, indices, segment_starts) =
+        if config.get_mandatory_option("probe")? == "DRAG_KNIFE" {
+            let blade_offset = config.get_mandatory_parsed_option::<f32>("blade_offset", None)?;
+            let (path, starts) = drag_knife_path(&samples, blade_offset);
+            let indices = (0..path.len()).collect();
+            (path, indices, Some(starts))
+        } else {
+            (vertices, indices, None)
+        };
+    let _ = return_config.insert(
+        "PASS_STARTS".to_string(),
+        groups
+            .iter()
+            .map(|(edge, _)| {
+                segment_starts
+                    .as_ref()
+                    .map_or(*edge, |starts| starts[*edge])
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    let _ = return_config.insert(
+        "PASS_TYPES".to_string(),
+        groups
+            .iter()
+            .map(|(_, pass_type)| pass_type.as_str())
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    let _ = return_config.insert(
+        "PASS_COUNT".to_string(),
+        groups
+            .iter()
+            .filter(|(_, pass_type)| *pass_type == PassType::Raster)
+            .count()
+            .to_string(),
+    );
+    Ok((vertices, indices, return_config))
+}
 
-    Ok((results.vertices, indices, return_config))
+/// Arrange the probe samples into a regular grid of `step` spacing, in row major order
+/// (x is the fastest moving axis). Cells without a sample are set to `missing_z`.
+/// Returns the grid vertices, the grid edges (in the line_chunks format), the number of rows and
+/// columns and the number of missing cells.
+fn samples_to_grid(
+    samples: &[FFIVector3],
+    step: f32,
+    missing_z: f32,
+) -> (Vec<FFIVector3>, Vec<usize>, usize, usize, usize) {
+    if samples.is_empty() || step <= 0.0 {
+        return (Vec::default(), Vec::default(), 0, 0, 0);
+    }
+    let (min_x, min_y, max_x, max_y) = samples.iter().fold(
+        (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+        |(min_x, min_y, max_x, max_y), v| {
+            (
+                min_x.min(v.x),
+                min_y.min(v.y),
+                max_x.max(v.x),
+                max_y.max(v.y),
+            )
+        },
+    );
+    let columns = ((max_x - min_x) / step).round() as usize + 1;
+    let rows = ((max_y - min_y) / step).round() as usize + 1;
+
+    let mut heights = vec![None::<f32>; rows * columns];
+    for v in samples.iter() {
+        let column = ((v.x - min_x) / step).round() as usize;
+        let row = ((v.y - min_y) / step).round() as usize;
+        let cell = &mut heights[row * columns + column];
+        // the probe rests on the highest contact
+        *cell = Some(cell.map_or(v.z, |z| z.max(v.z)));
+    }
+    let missing = heights.iter().filter(|z| z.is_none()).count();
+
+    let mut vertices = Vec::<FFIVector3>::with_capacity(rows * columns);
+    for row in 0..rows {
+        for column in 0..columns {
+            vertices.push(FFIVector3::new(
+                min_x + column as f32 * step,
+                min_y + row as f32 * step,
+                heights[row * columns + column].unwrap_or(missing_z),
+            ));
+        }
+    }
+    let mut indices = Vec::<usize>::with_capacity(4 * rows * columns);
+    for row in 0..rows {
+        for column in 0..columns {
+            let i = row * columns + column;
+            if column + 1 < columns {
+                indices.push(i);
+                indices.push(i + 1);
+            }
+            if row + 1 < rows {
+                indices.push(i);
+                indices.push(i + columns);
+            }
+        }
+    }
+    (vertices, indices, rows, columns, missing)
+}
+
+/// Probe the surface on a regular grid and return the raw heights, e.g. for bed-leveling
+/// (surface compensation) in CNC controllers. The grid always covers the AABB of the bounding
+/// shape, the grid layout is described by the `grid.*` keys of the returned config.
+fn do_grid_scan<T: GenericVector3>(
+    bounding_vertices: &[FFIVector3],
+    mesh_analyzer: &MeshAnalyzer<'_, T, FFIVector3>,
+    probe: &dyn Probe<T, FFIVector3>,
+    minimum_z: T::Scalar,
+    step: T::Scalar,
+) -> Result<(Vec<FFIVector3>, Vec<usize>, ConfigType), HallrError>
+where
+    T::Vector2: PointTrait<PScalar = T::Scalar>,
+    T: ConvertTo<FFIVector3>,
+    FFIVector3: ConvertTo<T>,
+    u32: AsPrimitive<<FFIVector3 as HasXY>::Scalar>,
+    u32: AsPrimitive<T::Scalar>,
+    T::Scalar: AsPrimitive<<FFIVector3 as HasXY>::Scalar>,
+{
+    let grid_step: f32 = step.as_();
+    let (vertices, indices, rows, columns, missing) =
+        scan_grid::<T>(bounding_vertices, mesh_analyzer, probe, minimum_z, step)?;
+    if vertices.is_empty() {
+        return Err(HallrError::NoData(
+            "The grid scan did not produce any samples".to_string(),
+        ));
+    }
+    if missing > 0 {
+        debug!(
+            "surface_scan: {} grid cells had no sample, they were set to minimum_z",
+            missing
+        );
+    }
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = return_config.insert("grid.rows".to_string(), rows.to_string());
+    let _ = return_config.insert("grid.columns".to_string(), columns.to_string());
+    let _ = return_config.insert("grid.min_x".to_string(), vertices[0].x.to_string());
+    let _ = return_config.insert("grid.min_y".to_string(), vertices[0].y.to_string());
+    let _ = return_config.insert("grid.step".to_string(), grid_step.to_string());
+    let _ = return_config.insert("grid.missing".to_string(), missing.to_string());
+    debug!(
+        "surface_scan: grid scan returned {} rows and {} columns",
+        rows, columns
+    );
+    Ok((vertices, indices, return_config))
+}
+
+/// Probe the surface on a regular grid covering the AABB of the bounding vertices, the cells
+/// without a sample are set to `minimum_z`. Returns the same as `samples_to_grid()`.
+fn scan_grid<T: GenericVector3>(
+    bounding_vertices: &[FFIVector3],
+    mesh_analyzer: &MeshAnalyzer<'_, T, FFIVector3>,
+    probe: &dyn Probe<T, FFIVector3>,
+    minimum_z: T::Scalar,
+    step: T::Scalar,
+) -> Result<(Vec<FFIVector3>, Vec<usize>, usize, usize, usize), HallrError>
+where
+    T::Vector2: PointTrait<PScalar = T::Scalar>,
+    T: ConvertTo<FFIVector3>,
+    FFIVector3: ConvertTo<T>,
+    u32: AsPrimitive<<FFIVector3 as HasXY>::Scalar>,
+    u32: AsPrimitive<T::Scalar>,
+    T::Scalar: AsPrimitive<<FFIVector3 as HasXY>::Scalar>,
+{
+    // adaptive sampling would break the grid, so it is never used here
+    let search_config = SearchPatternConfig::<T, FFIVector3>::new(probe, minimum_z);
+    let (aabb, convex_hull) = generate_aabb_then_convex_hull(bounding_vertices)?;
+
+    let results = MeanderPattern::<T, FFIVector3>::new(aabb, convex_hull, step)?
+        .search(mesh_analyzer, &search_config)?
+        .get_line_data()?;
+    Ok(samples_to_grid(
+        &results.vertices,
+        step.as_(),
+        minimum_z.as_(),
+    ))
+}
+
+/// Raise the vertices to the resting height of a bull-nose (torus) cutter. A torus is a flat disc
+/// of `major_radius` swept by a ball of the minor radius, so the cutter rests at the highest ball
+/// nose height within `major_radius` of its axis. `grid` holds those ball nose heights on a
+/// regular grid of `step` spacing (row major, `columns` wide), the vertices already hold the ball
+/// nose height at their own position.
+fn torus_heights(
+    vertices: &mut [FFIVector3],
+    grid: &[FFIVector3],
+    columns: usize,
+    step: f32,
+    major_radius: f32,
+) {
+    if grid.is_empty() || columns == 0 || step <= 0.0 {
+        return;
+    }
+    let origin = grid[0];
+    let rows = grid.len() / columns;
+    let reach = (major_radius / step).ceil() as i64;
+    let radius_squared = major_radius * major_radius + CLAMP_EPSILON;
+    for v in vertices.iter_mut() {
+        let column = ((v.x - origin.x) / step).round() as i64;
+        let row = ((v.y - origin.y) / step).round() as i64;
+        for r in (row - reach).max(0)..=(row + reach).min(rows as i64 - 1) {
+            for c in (column - reach).max(0)..=(column + reach).min(columns as i64 - 1) {
+                let q = &grid[r as usize * columns + c as usize];
+                let (dx, dy) = (q.x - v.x, q.y - v.y);
+                if dx * dx + dy * dy <= radius_squared && q.z > v.z {
+                    v.z = q.z;
+                }
+            }
+        }
+    }
+}
+
+/// The smallest change of direction that makes a drag knife swivel around its tip, in radians
+const DRAG_KNIFE_SWIVEL_ANGLE: f32 = 5.0 * std::f32::consts::PI / 180.0;
+/// The largest angle of one segment of a swivel arc, in radians
+const DRAG_KNIFE_ARC_STEP: f32 = 10.0 * std::f32::consts::PI / 180.0;
+
+/// Compensate a toolpath for the blade offset of a drag knife. The blade tip trails `offset`
+/// behind the swivel axis, so the axis is moved `offset` ahead of the tip along every segment, and
+/// around an arc centered on the tip wherever the direction changes more than
+/// `DRAG_KNIFE_SWIVEL_ANGLE`. The heights are those of the tip.
+/// Returns the compensated path, and the index in it of the start of every input segment.
+fn drag_knife_path(points: &[FFIVector3], offset: f32) -> (Vec<FFIVector3>, Vec<usize>) {
+    // the XY direction of every segment, segments without XY length keep the previous one
+    let mut directions = Vec::<Option<(f32, f32)>>::with_capacity(points.len());
+    for w in points.windows(2) {
+        let (dx, dy) = (w[1].x - w[0].x, w[1].y - w[0].y);
+        let length = (dx * dx + dy * dy).sqrt();
+        directions.push(if length > CLAMP_EPSILON {
+            Some((dx / length, dy / length))
+        } else {
+            directions.last().copied().flatten()
+        });
+    }
+    // the leading segments without a direction take the first one
+    let first = directions.iter().copied().flatten().next();
+    let Some(first) = first else {
+        return (points.to_vec(), (0..points.len()).collect());
+    };
+    let directions: Vec<(f32, f32)> = directions.into_iter().map(|d| d.unwrap_or(first)).collect();
+    let shifted = |p: &FFIVector3, d: (f32, f32)| {
+        FFIVector3::new(p.x + offset * d.0, p.y + offset * d.1, p.z)
+    };
+
+    let mut rv = Vec::<FFIVector3>::with_capacity(points.len() * 2);
+    let mut starts = Vec::<usize>::with_capacity(directions.len());
+    for (i, d) in directions.iter().enumerate() {
+        let p = &points[i];
+        let previous = directions[i.saturating_sub(1)];
+        if previous != *d {
+            rv.push(shifted(p, previous));
+            let (from, to) = (previous.1.atan2(previous.0), d.1.atan2(d.0));
+            // the signed turn, in -PI..PI
+            let turn = (to - from + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU)
+                - std::f32::consts::PI;
+            if turn.abs() > DRAG_KNIFE_SWIVEL_ANGLE {
+                let steps = (turn.abs() / DRAG_KNIFE_ARC_STEP).ceil() as usize;
+                for step in 1..steps {
+                    let angle = from + turn * step as f32 / steps as f32;
+                    rv.push(shifted(p, (angle.cos(), angle.sin())));
+                }
+            }
+        }
+        starts.push(rv.len());
+        rv.push(shifted(p, *d));
+    }
+    if let (Some(p), Some(d)) = (points.last(), directions.last()) {
+        rv.push(shifted(p, *d));
+    }
+    (rv, starts)
+}
+
+/// Raise the samples below `floor_z` to it. Returns the number of those samples, together with the
+/// samples the scan silently clamped to `minimum_z` (where the probe found nothing higher).
+fn clamp_samples(vertices: &mut [FFIVector3], minimum_z: f32, floor_z: Option<f32>) -> usize {
+    let mut rv = 0;
+    for v in vertices.iter_mut() {
+        if let Some(floor_z) = floor_z {
+            if v.z < floor_z {
+                v.z = floor_z;
+                rv += 1;
+                continue;
+            }
+        }
+        if v.z <= minimum_z + CLAMP_EPSILON {
+            rv += 1;
+        }
+    }
+    rv
+}
+
+/// Rotate the vertices `angle` radians around the Z axis
+fn rotate_z(vertices: &[FFIVector3], angle: f32) -> Vec<FFIVector3> {
+    let (sin, cos) = angle.sin_cos();
+    vertices
+        .iter()
+        .map(|v| FFIVector3::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos, v.z))
+        .collect()
+}
+
+/// Pick every `stride_x`:th column and every `stride_y`:th row of the grid (as returned by
+/// `samples_to_grid`, with NaN heights for the missing cells) and triangulate the resulting
+/// lattice. A lattice cell with one missing corner is covered by a single triangle.
+/// Returns the vertices and the triangulated indices.
+fn triangulate_lattice(
+    grid: &[FFIVector3],
+    rows: usize,
+    columns: usize,
+    stride_x: usize,
+    stride_y: usize,
+) -> (Vec<FFIVector3>, Vec<usize>) {
+    let lattice_rows: Vec<usize> = (0..rows).step_by(stride_y.max(1)).collect();
+    let lattice_columns: Vec<usize> = (0..columns).step_by(stride_x.max(1)).collect();
+    let width = lattice_columns.len();
+
+    let mut vertices = Vec::<FFIVector3>::with_capacity(lattice_rows.len() * width);
+    let mut lattice = vec![None::<usize>; lattice_rows.len() * width];
+    for (r, row) in lattice_rows.iter().enumerate() {
+        for (c, column) in lattice_columns.iter().enumerate() {
+            let v = grid[row * columns + column];
+            if !v.z.is_nan() {
+                lattice[r * width + c] = Some(vertices.len());
+                vertices.push(v);
+            }
+        }
+    }
+    let mut indices = Vec::<usize>::with_capacity(6 * vertices.len());
+    for row in 0..lattice_rows.len().saturating_sub(1) {
+        for column in 0..width.saturating_sub(1) {
+            let i = row * width + column;
+            // the corners of the cell, in counter clockwise order
+            let corners = (
+                lattice[i],
+                lattice[i + 1],
+                lattice[i + width + 1],
+                lattice[i + width],
+            );
+            match corners {
+                (Some(a), Some(b), Some(c), Some(d)) => indices.extend([a, b, c, a, c, d]),
+                (None, Some(b), Some(c), Some(d)) => indices.extend([b, c, d]),
+                (Some(a), None, Some(c), Some(d)) => indices.extend([a, c, d]),
+                (Some(a), Some(b), None, Some(d)) => indices.extend([a, b, d]),
+                (Some(a), Some(b), Some(c), None) => indices.extend([a, b, c]),
+                _ => (),
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+/// Probe the surface on a regular lattice with separate `step_x` and `step_y` spacing, and
+/// triangulate it. The surface is sampled at the smaller of the two steps, the larger step is
+/// rounded to a whole multiple of it. The effective steps are returned as `lattice.step_x` and
+/// `lattice.step_y`.
+/// The input is expected to already be rotated into the frame of the lattice.
+fn do_lattice_scan<T: GenericVector3>(
+    config: ConfigType,
+    bounding_vertices: &[FFIVector3],
+    mesh_analyzer: &MeshAnalyzer<'_, T, FFIVector3>,
+    probe: &dyn Probe<T, FFIVector3>,
+    minimum_z: T::Scalar,
+    step: T::Scalar,
+) -> Result<(Vec<FFIVector3>, Vec<usize>, ConfigType), HallrError>
+where
+    T::Vector2: PointTrait<PScalar = T::Scalar>,
+    T: ConvertTo<FFIVector3>,
+    FFIVector3: ConvertTo<T>,
+    u32: AsPrimitive<<FFIVector3 as HasXY>::Scalar>,
+    u32: AsPrimitive<T::Scalar>,
+    T::Scalar: AsPrimitive<<FFIVector3 as HasXY>::Scalar>,
+{
+    let step_x = config.get_mandatory_parsed_option::<T::Scalar>("step_x", Some(step))?;
+    let step_y = config.get_mandatory_parsed_option::<T::Scalar>("step_y", Some(step))?;
+    let sample_step = if step_x < step_y { step_x } else { step_y };
+    let sample_step_f: f32 = sample_step.as_();
+    if !sample_step_f.is_finite() || sample_step_f <= 0.0 {
+        return Err(HallrError::InvalidParameter(
+            "The \"step_x\" and \"step_y\" parameters must be positive".to_string(),
+        ));
+    }
+    let step_x_f: f32 = step_x.as_();
+    let step_y_f: f32 = step_y.as_();
+    let stride_x = ((step_x_f / sample_step_f).round() as usize).max(1);
+    let stride_y = ((step_y_f / sample_step_f).round() as usize).max(1);
+
+    let (aabb, convex_hull) = match config.get_mandatory_option("bounds")? {
+        "CONVEX_HULL" => generate_convex_hull_then_aabb(bounding_vertices),
+        "AABB" => generate_aabb_then_convex_hull(bounding_vertices),
+        bounds => Err(HronnError::InvalidParameter(format!(
+            "{} is not a valid \"bounds\" parameter",
+            bounds
+        ))),
+    }?;
+    // adaptive sampling would break the lattice, so it is never used here
+    let search_config = SearchPatternConfig::<T, FFIVector3>::new(probe, minimum_z);
+    let results = MeanderPattern::<T, FFIVector3>::new(aabb, convex_hull, sample_step)?
+        .search(mesh_analyzer, &search_config)?
+        .get_line_data()?;
+
+    let (grid, _, rows, columns, _) = samples_to_grid(&results.vertices, sample_step_f, f32::NAN);
+    let (vertices, indices) = triangulate_lattice(&grid, rows, columns, stride_x, stride_y);
+    if indices.is_empty() {
+        return Err(HallrError::NoData(
+            "The lattice scan did not produce any triangles".to_string(),
+        ));
+    }
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = return_config.insert(
+        "lattice.step_x".to_string(),
+        (stride_x as f32 * sample_step_f).to_string(),
+    );
+    let _ = return_config.insert(
+        "lattice.step_y".to_string(),
+        (stride_y as f32 * sample_step_f).to_string(),
+    );
+    debug!(
+        "surface_scan: lattice scan returned {} vertices and {} triangles",
+        vertices.len(),
+        indices.len() / 3
+    );
+    Ok((vertices, indices, return_config))
 }
 
 fn do_triangulation_scan<T: GenericVector3>(
@@ -131,6 +1031,64 @@ where
     Ok((results.vertices, results.indices, return_config))
 }
 
+/// Returns the triangles of the mesh in double precision
+fn frame_triangles(vertices: &[FFIVector3], indices: &[usize]) -> Vec<[DVec3; 3]> {
+    let to_dvec3 = |v: &FFIVector3| dvec3(v.x as f64, v.y as f64, v.z as f64);
+    indices
+        .chunks_exact(3)
+        .map(|t| {
+            [
+                to_dvec3(&vertices[t[0]]),
+                to_dvec3(&vertices[t[1]]),
+                to_dvec3(&vertices[t[2]]),
+            ]
+        })
+        .collect()
+}
+
+/// Returns, per toolpath point, the height of the scanned surface above the nominal surface at
+/// the XY position of the point. Positive values are excess material, negative values are
+/// missing material. Points where either surface is absent get NaN.
+fn surface_deviations(vertices: &[FFIVector3], scanned: &Bvh, nominal: &Bvh) -> Vec<f64> {
+    vertices
+        .iter()
+        .map(|v| {
+            let (x, y) = (v.x as f64, v.y as f64);
+            match (scanned.highest_z(x, y), nominal.highest_z(x, y)) {
+                (Some(scanned), Some(nominal)) => scanned - nominal,
+                _ => f64::NAN,
+            }
+        })
+        .collect()
+}
+
+/// Run the surface_scan command
+/// Samples where the probe found no surface above `minimum_z` are clamped to it. Samples below
+/// the optional `FLOOR_Z` (e.g. the top of the fixture) are raised to it. The number of clamped
+/// samples is returned as `CLAMPED_SAMPLES` and reported as a warning, or as an error if
+/// `FAIL_ON_CLAMP` is true.
+/// With `NOMINAL_MODEL` (the index of a model after the bounding shape) that model is not scanned,
+/// it is the nominal CAD mesh. The deviation of the scanned surface from it, straight below every
+/// toolpath point, is returned as `DEVIATIONS` (NaN where either surface is absent) with
+/// `DEVIATION_MIN`, `DEVIATION_MAX`, `DEVIATION_MEAN` and `DEVIATION_MISSING`, and as
+/// `VERTEX_COLORS` with a `COLOR_MAP`.
+/// The MEANDER toolpath is grouped into passes: `PASS_STARTS` is the first edge of every pass (edge
+/// `k` joins point `k` and `k + 1` of the line), `PASS_TYPES` tells if the pass is a `RASTER` cut
+/// or a `LINK` between two cuts, and `PASS_COUNT` is the number of raster passes.
+/// With `max_chord_error` the MEANDER stepover adapts to the surface: the surface is scanned at
+/// `min_step` (default a quarter of `step`), and the passes where the surface between the
+/// neighbouring passes is within `max_chord_error` of a straight line are dropped. The step between
+/// the passes stays between `min_step` and `step`.
+/// The TORUS probe is a bull-nose cutter, a ball of `probe_minor_radius` swept around the axis at
+/// `probe_radius` (the major radius, the cutter radius is the sum of the two). The surface is first
+/// scanned with the corner ball, then raised to the highest ball height within the major radius,
+/// sampled on a grid of `probe_sample_step` spacing (default half of `step`).
+/// The DRAG_KNIFE probe scans like a SQUARE_END, and the MEANDER toolpath is compensated for the
+/// `blade_offset` of the knife: the path is moved ahead of the blade tip, with a swivel arc at
+/// every corner. The heights are those of the tip.
+/// With `OUTPUT=GCODE` the MEANDER toolpath is also exported as G-code, in world coordinates, with
+/// the `FEED_RATE`, `PLUNGE_RATE`, `SAFE_Z` (default one step above the toolpath) and
+/// `SPINDLE_SPEED` options. The program is written to `FILE_PATH`, or returned as `GCODE`.
 pub(crate) fn process_command<T: GenericVector3>(
     config: ConfigType,
     models: Vec<Model<'_>>,
@@ -152,16 +1110,85 @@ where
     let world_matrix = model.world_orientation.to_vec();
     let bounding_shape = &models[1];
     let _bounding_shape_world_matrix = bounding_shape.world_orientation.to_vec();
-    // todo: actually use the matrices
+    // todo: actually use the matrices of the bounding shape
 
+    // a TRIANGULATION scan with separate x and y steps, or a rotated lattice, is a lattice scan
+    let lattice_scan = config.get_mandatory_option("pattern")? == "TRIANGULATION"
+        && ["step_x", "step_y", "lattice_angle"]
+            .iter()
+            .any(|key| config.contains_key(*key));
+    let gcode_output = match config
+        .get_mandatory_parsed_option::<String>("OUTPUT", Some("MESH".to_string()))?
+        .as_str()
+    {
+        "MESH" => false,
+        "GCODE" if config.get_mandatory_option("pattern")? == "MEANDER" => true,
+        "GCODE" => Err(HallrError::InvalidParameter(
+            "OUTPUT=GCODE requires a toolpath, use the MEANDER pattern".to_string(),
+        ))?,
+        output => Err(HallrError::InvalidParameter(format!(
+            "{} is not a valid \"OUTPUT\" parameter",
+            output
+        )))?,
+    };
+    let lattice_angle = if lattice_scan {
+        config.get_mandatory_parsed_option::<f32>("lattice_angle", Some(0.0))?
+    } else {
+        0.0
+    };
+
+    // an optional nominal (CAD) mesh, the deviation of the scanned surface from it is returned
+    let nominal = config.get_parsed_option::<usize>("NOMINAL_MODEL")?;
+    if let Some(nominal) = nominal {
+        if nominal < 2 || nominal >= models.len() {
+            return Err(HallrError::InvalidParameter(format!(
+                "NOMINAL_MODEL must be the index of a model after the bounding shape :({})",
+                nominal
+            )));
+        }
+    }
+
+    // model 0 and any model after the bounding shape, except the nominal mesh, is a target mesh
+    let (target_vertices, target_indices) = merge_target_models(&models, nominal)?;
+    // the surfaces are compared in the scan frame, before any lattice rotation
+    let deviation_surfaces = if let Some(nominal) = nominal {
+        let nominal_model = &models[nominal];
+        let transform = scan_frame_inverse(&models)? * self::world_matrix(nominal_model)?;
+        let nominal_vertices: Vec<FFIVector3> = nominal_model
+            .vertices
+            .iter()
+            .map(|v| {
+                let v = transform.transform_point3(Vec3::new(v.x, v.y, v.z));
+                FFIVector3::new(v.x, v.y, v.z)
+            })
+            .collect();
+        Some((
+            Bvh::new(frame_triangles(&target_vertices, &target_indices)),
+            Bvh::new(frame_triangles(&nominal_vertices, nominal_model.indices)),
+        ))
+    } else {
+        None
+    };
+    let bounding_indices = bounding_shape.indices;
+    let mut bounding_vertices = Cow::Borrowed(bounding_shape.vertices);
+    let target_vertices = if lattice_angle != 0.0 {
+        // the lattice is scanned in its own frame, and the result rotated back
+        bounding_vertices = Cow::Owned(rotate_z(bounding_shape.vertices, -lattice_angle));
+        Cow::Owned(rotate_z(&target_vertices, -lattice_angle))
+    } else {
+        target_vertices
+    };
     let mesh_analyzer = MeshAnalyzerBuilder::<T, FFIVector3>::default()
-        .load_from_ref(model.vertices, model.indices)?
+        .load_from_ref(&*target_vertices, &*target_indices)?
         .build()?;
-    let bounding_indices = bounding_shape.indices;
-    let bounding_vertices = bounding_shape.vertices;
+    let bounding_vertices = &*bounding_vertices;
+    // the scan itself runs inside hronn, so only the steps around it are reported
+    progress::report(10);
 
     let probe_radius = config.get_mandatory_parsed_option("probe_radius", None)?;
     let minimum_z = config.get_mandatory_parsed_option("minimum_z", None)?;
+    let floor_z = config.get_parsed_option::<f32>("FLOOR_Z")?;
+    let fail_on_clamp = config.get_mandatory_parsed_option::<bool>("FAIL_ON_CLAMP", Some(false))?;
     let step = config.get_mandatory_parsed_option("step", None)?;
     let probe: Box<dyn Probe<T, FFIVector3>> = match config.get_mandatory_option("probe")? {
         "SQUARE_END" => Box::new(SquareEndProbe::new(&mesh_analyzer, probe_radius)?),
@@ -170,6 +1197,26 @@ where
             let angle = config.get_mandatory_parsed_option("probe_angle", None)?;
             Box::new(TaperedProbe::new(&mesh_analyzer, probe_radius, angle)?)
         },
+        // the surface is scanned with the corner ball, the disc is added afterwards
+        "TORUS" => Box::new(BallNoseProbe::new(
+            &mesh_analyzer,
+            config.get_mandatory_parsed_option("probe_minor_radius", None)?,
+        )?),
+        "DRAG_KNIFE" => {
+            let blade_offset = config.get_mandatory_parsed_option::<f32>("blade_offset", None)?;
+            if !blade_offset.is_finite() || blade_offset <= 0.0 {
+                return Err(HallrError::InvalidParameter(format!(
+                    "The \"blade_offset\" parameter must be positive :({})",
+                    blade_offset
+                )));
+            }
+            if config.get_mandatory_option("pattern")? != "MEANDER" {
+                return Err(HallrError::InvalidParameter(
+                    "The DRAG_KNIFE probe requires a toolpath, use the MEANDER pattern".to_string(),
+                ));
+            }
+            Box::new(SquareEndProbe::new(&mesh_analyzer, probe_radius)?)
+        },
         probe_name => Err(HronnError::InvalidParameter(format!(
             "{} is not a valid \"probe\" parameter",
             probe_name
@@ -178,7 +1225,7 @@ where
 
     let rv = match config.get_mandatory_option("pattern")? {
         "MEANDER" => do_meander_scan::<T>(
-            config,
+            config.clone(),
             bounding_vertices,
             bounding_indices,
             &mesh_analyzer,
@@ -186,8 +1233,16 @@ where
             minimum_z,
             step,
         ),
+        "TRIANGULATION" if lattice_scan => do_lattice_scan::<T>(
+            config.clone(),
+            bounding_vertices,
+            &mesh_analyzer,
+            probe.as_ref(),
+            minimum_z,
+            step,
+        ),
         "TRIANGULATION" => do_triangulation_scan::<T>(
-            config,
+            config.clone(),
             bounding_vertices,
             bounding_indices,
             &mesh_analyzer,
@@ -195,11 +1250,135 @@ where
             minimum_z,
             step,
         ),
+        "GRID" => do_grid_scan::<T>(
+            bounding_vertices,
+            &mesh_analyzer,
+            probe.as_ref(),
+            minimum_z,
+            step,
+        ),
 
         pattern => Err(HallrError::InvalidParameter(format!(
             "{} is not a valid option for the \"probe\" parameter",
             pattern
         ))),
     }?;
-    Ok((rv.0, rv.1, world_matrix, rv.2))
+    let (mut vertices, indices, mut return_config) = rv;
+    if config.get_mandatory_option("probe")? == "TORUS" {
+        let major_radius: f32 = config.get_mandatory_parsed_option("probe_radius", None)?;
+        let two: T::Scalar = 2_u32.as_();
+        let torus_step = config
+            .get_mandatory_parsed_option::<T::Scalar>("probe_sample_step", Some(step / two))?;
+        let torus_step_f: f32 = torus_step.as_();
+        if !major_radius.is_finite()
+            || major_radius < 0.0
+            || !torus_step_f.is_finite()
+            || torus_step_f <= 0.0
+        {
+            return Err(HallrError::InvalidParameter(
+                "The TORUS probe needs a positive \"probe_radius\" and \"probe_sample_step\""
+                    .to_string(),
+            ));
+        }
+        // the ball nose heights around the bounding shape, as far as the disc reaches
+        let (min, max) = bounding_vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), v| {
+                let v = Vec3::new(v.x, v.y, 0.0);
+                (min.min(v), max.max(v))
+            },
+        );
+        let reach = major_radius + torus_step_f;
+        let padded_bounds: Vec<FFIVector3> = [
+            (min.x - reach, min.y - reach),
+            (max.x + reach, min.y - reach),
+            (max.x + reach, max.y + reach),
+            (min.x - reach, max.y + reach),
+        ]
+        .into_iter()
+        .map(|(x, y)| FFIVector3::new(x, y, 0.0))
+        .collect();
+        let (grid, _, _, columns, _) = scan_grid::<T>(
+            &padded_bounds,
+            &mesh_analyzer,
+            probe.as_ref(),
+            minimum_z,
+            torus_step,
+        )?;
+        torus_heights(&mut vertices, &grid, columns, torus_step_f, major_radius);
+    }
+    progress::report(100);
+    let clamped = clamp_samples(&mut vertices, minimum_z.as_(), floor_z);
+    let _ = return_config.insert("CLAMPED_SAMPLES".to_string(), clamped.to_string());
+    if clamped > 0 {
+        let warning = format!(
+            "surface_scan: {} of {} samples were clamped to minimum_z or FLOOR_Z, the probe may not have reached the surface",
+            clamped,
+            vertices.len()
+        );
+        if fail_on_clamp {
+            return Err(HallrError::InvalidInputData(warning));
+        }
+        warn!("Warning: {}", warning);
+        let _ = return_config.insert("WARNING".to_string(), warning);
+    }
+    let vertices = if lattice_angle != 0.0 {
+        rotate_z(&vertices, lattice_angle)
+    } else {
+        vertices
+    };
+    if let Some((scanned, nominal)) = deviation_surfaces {
+        let deviations = surface_deviations(&vertices, &scanned, &nominal);
+        let measured: Vec<f64> = deviations.iter().copied().filter(|d| d.is_finite()).collect();
+        let missing = deviations.len() - measured.len();
+        if let (Some(min), Some(max)) = (
+            measured.iter().copied().reduce(f64::min),
+            measured.iter().copied().reduce(f64::max),
+        ) {
+            let mean = measured.iter().sum::<f64>() / measured.len() as f64;
+            let _ = return_config.insert("DEVIATION_MIN".to_string(), min.to_string());
+            let _ = return_config.insert("DEVIATION_MAX".to_string(), max.to_string());
+            let _ = return_config.insert("DEVIATION_MEAN".to_string(), mean.to_string());
+        }
+        let _ = return_config.insert("DEVIATION_MISSING".to_string(), missing.to_string());
+        let _ = return_config.insert(
+            "DEVIATIONS".to_string(),
+            deviations
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        // the points without a deviation are colored as if they were on the nominal surface
+        cmd_scalar_to_color::add_vertex_colors(
+            &config,
+            &deviations
+                .iter()
+                .map(|d| if d.is_finite() { *d as f32 } else { 0.0 })
+                .collect::<Vec<_>>(),
+            &mut return_config,
+        )?;
+        debug!(
+            "surface_scan: deviation from the nominal mesh measured at {} points, {} missing",
+            measured.len(),
+            missing
+        );
+    }
+    if gcode_output {
+        // the program is written in world coordinates
+        let transform = self::world_matrix(model)?;
+        let toolpath: Vec<FFIVector3> = indices
+            .iter()
+            .filter_map(|i| vertices.get(*i))
+            .map(|v| {
+                let v = transform.transform_point3(Vec3::new(v.x, v.y, v.z));
+                FFIVector3::new(v.x, v.y, v.z)
+            })
+            .collect();
+        let step: f32 = config.get_mandatory_parsed_option("step", None)?;
+        let default_safe_z = toolpath.iter().map(|v| v.z).fold(f32::MIN, f32::max) + step;
+        let settings = GcodeSettings::from_config(&config, default_safe_z as f64)?;
+        gcode::export(&toolpath, &settings, &mut return_config)?;
+    }
+    Ok((vertices, indices, world_matrix, return_config))
 }
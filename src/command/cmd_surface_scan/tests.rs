@@ -4,6 +4,7 @@
 
 use crate::{
     command::{ConfigType, OwnedModel},
+    ffi::FFIVector3,
     HallrError,
 };
 use vector_traits::glam::Vec3;
@@ -54,6 +55,10 @@ fn test_surface_scan_1() -> Result<(), HallrError> {
     let result = super::process_command::<Vec3>(config, models)?;
     assert_eq!(35, result.0.len()); // vertices
     assert_eq!(35, result.1.len()); // indices
+    let starts = result.3.get("PASS_STARTS").unwrap().split(',').count();
+    let types: Vec<&str> = result.3.get("PASS_TYPES").unwrap().split(',').collect();
+    assert_eq!(starts, types.len());
+    assert!(types.windows(2).all(|w| w[0] != w[1]));
     Ok(())
 }
 
@@ -254,3 +259,628 @@ fn test_surface_scan_5() -> Result<(), HallrError> {
 
     Ok(())
 }
+
+#[test]
+fn test_surface_scan_6() -> Result<(), HallrError> {
+    // the same mesh as test_surface_scan_1, but split into two target models.
+    // The second target is moved +1 in X, and moved back by its world matrix.
+    let mut config = ConfigType::default();
+    let _ = config.insert("bounds".to_string(), "AABB".to_string());
+    let _ = config.insert("probe_radius".to_string(), "0.5".to_string());
+    let _ = config.insert("minimum_z".to_string(), "0.0".to_string());
+    let _ = config.insert("step".to_string(), "0.5".to_string());
+    let _ = config.insert("command".to_string(), "surface_scan".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("pattern".to_string(), "MEANDER".to_string());
+    let _ = config.insert("probe".to_string(), "BALL_NOSE".to_string());
+
+    let vertices: Vec<(f32, f32, f32)> = vec![
+        (-0.29610628, -1.7045903, -0.9548358),
+        (-0.18138881, -0.23321122, 0.5500126),
+        (-1.5054786, 0.84019524, -0.70687366),
+        (1.5054786, -0.84019524, -1.0391741),
+        (0.6572089, 0.07475242, 0.09592825),
+        (0.29610628, 1.7045903, -0.79121196),
+    ];
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vertices.iter().map(|v| (*v).into()).collect(),
+        indices: vec![1, 2, 0, 3, 1, 0, 5, 1, 4],
+    };
+
+    let owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.8112676, -0.21234381, 0.0).into(),
+            (-1.0113943, -0.9753443, 0.0).into(),
+            (1.0, -1.0, 0.0).into(),
+            (1.5378065, -0.20696306, 0.0).into(),
+            (1.0241334, 1.0380125, 0.0).into(),
+            (-0.13404018, 1.979902, 0.0).into(),
+            (-1.0, 1.0, 0.0).into(),
+            (-1.8112676, -0.21234381, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 0],
+    };
+
+    let mut world_orientation = OwnedModel::identity_matrix();
+    // the matrix is in row major order, this is the X translation
+    world_orientation[3] = -1.0;
+    let owned_model_2 = OwnedModel {
+        world_orientation,
+        vertices: vertices
+            .iter()
+            .map(|v| (v.0 + 1.0, v.1, v.2).into())
+            .collect(),
+        indices: vec![3, 4, 1, 5, 2, 1],
+    };
+
+    let models = vec![
+        owned_model_0.as_model(),
+        owned_model_1.as_model(),
+        owned_model_2.as_model(),
+    ];
+    let result = super::process_command::<Vec3>(config, models)?;
+    assert_eq!(35, result.0.len()); // vertices
+    assert_eq!(35, result.1.len()); // indices
+    Ok(())
+}
+
+#[test]
+fn test_surface_scan_grid_1() {
+    let samples: Vec<FFIVector3> = vec![
+        (0.0, 0.0, 1.0).into(),
+        (0.5, 0.0, 2.0).into(),
+        (1.0, 0.0, 3.0).into(),
+        (1.0, 0.5, 4.0).into(),
+        (0.5, 0.5, 5.0).into(),
+        (0.5, 0.5, 6.0).into(),
+    ];
+    let (vertices, indices, rows, columns, missing) = super::samples_to_grid(&samples, 0.5, -1.0);
+    assert_eq!(2, rows);
+    assert_eq!(3, columns);
+    assert_eq!(1, missing);
+    assert_eq!(6, vertices.len());
+    // 2*2 horizontal and 3 vertical edges
+    assert_eq!(14, indices.len());
+    assert_eq!(-1.0, vertices[3].z);
+    assert_eq!(6.0, vertices[4].z);
+    assert_eq!(4.0, vertices[5].z);
+}
+
+#[test]
+fn test_surface_scan_grid_2() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("bounds".to_string(), "AABB".to_string());
+    let _ = config.insert("probe_radius".to_string(), "0.5".to_string());
+    let _ = config.insert("minimum_z".to_string(), "0.0".to_string());
+    let _ = config.insert("first_index_model_1".to_string(), "15".to_string());
+    let _ = config.insert("step".to_string(), "0.5".to_string());
+    let _ = config.insert("command".to_string(), "surface_scan".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("pattern".to_string(), "GRID".to_string());
+    let _ = config.insert("first_vertex_model_1".to_string(), "6".to_string());
+    let _ = config.insert("probe".to_string(), "BALL_NOSE".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-0.29610628, -1.7045903, -0.9548358).into(),
+            (-0.18138881, -0.23321122, 0.5500126).into(),
+            (-1.5054786, 0.84019524, -0.70687366).into(),
+            (1.5054786, -0.84019524, -1.0391741).into(),
+            (0.6572089, 0.07475242, 0.09592825).into(),
+            (0.29610628, 1.7045903, -0.79121196).into(),
+        ],
+        indices: vec![1, 2, 0, 3, 1, 0, 5, 1, 4, 3, 4, 1, 5, 2, 1],
+    };
+
+    let owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.8112676, -0.21234381, 0.0).into(),
+            (-1.0113943, -0.9753443, 0.0).into(),
+            (1.0, -1.0, 0.0).into(),
+            (1.5378065, -0.20696306, 0.0).into(),
+            (1.0241334, 1.0380125, 0.0).into(),
+            (-0.13404018, 1.979902, 0.0).into(),
+            (-1.0, 1.0, 0.0).into(),
+            (-1.8112676, -0.21234381, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 0],
+    };
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command::<Vec3>(config, models)?;
+    let rows: usize = result.3.get("grid.rows").unwrap().parse().unwrap();
+    let columns: usize = result.3.get("grid.columns").unwrap().parse().unwrap();
+    assert_eq!("line_chunks", result.3.get("mesh.format").unwrap());
+    assert_eq!(rows * columns, result.0.len()); // vertices
+    assert_eq!(
+        2 * ((columns - 1) * rows + (rows - 1) * columns),
+        result.1.len()
+    ); // indices
+    Ok(())
+}
+
+/// A 3*2 sample line along X, in the hronn zig-zag order
+fn meander_samples() -> (Vec<FFIVector3>, Vec<usize>) {
+    let vertices: Vec<FFIVector3> = vec![
+        (0.0, 0.0, 1.0).into(),
+        (0.5, 0.0, 1.0).into(),
+        (1.0, 0.0, 1.0).into(),
+        (1.0, 0.5, 1.0).into(),
+        (0.5, 0.5, 1.0).into(),
+        (0.0, 0.5, 1.0).into(),
+    ];
+    let indices = (0..vertices.len()).collect();
+    (vertices, indices)
+}
+
+#[test]
+fn test_surface_scan_meander_order_1() -> Result<(), HallrError> {
+    let (vertices, indices) = meander_samples();
+    let mut config = ConfigType::default();
+    let _ = config.insert("start_corner".to_string(), "MAX_X_MIN_Y".to_string());
+    let order = super::MeanderOrder::from_config(&config)?.unwrap();
+    let result = super::reorder_meander_passes(&vertices, &indices, 0.5, &order)?;
+    let xy: Vec<(f32, f32)> = result.iter().map(|v| (v.x, v.y)).collect();
+    assert_eq!(
+        vec![
+            (1.0, 0.0),
+            (0.5, 0.0),
+            (0.0, 0.0),
+            (0.0, 0.5),
+            (0.5, 0.5),
+            (1.0, 0.5)
+        ],
+        xy
+    );
+    Ok(())
+}
+
+#[test]
+fn test_surface_scan_meander_order_2() -> Result<(), HallrError> {
+    let (vertices, indices) = meander_samples();
+    let mut config = ConfigType::default();
+    let _ = config.insert("pass_order".to_string(), "UNIDIRECTIONAL".to_string());
+    let _ = config.insert("direction".to_string(), "CLIMB".to_string());
+    let order = super::MeanderOrder::from_config(&config)?.unwrap();
+    let result = super::reorder_meander_passes(&vertices, &indices, 0.5, &order)?;
+    // stepping over towards +Y, a climb cut with a clockwise spindle moves towards -X
+    let xyz: Vec<(f32, f32, f32)> = result.iter().map(|v| (v.x, v.y, v.z)).collect();
+    assert_eq!(
+        vec![
+            (1.0, 0.0, 1.0),
+            (0.5, 0.0, 1.0),
+            (0.0, 0.0, 1.0),
+            (0.0, 0.0, 1.5),
+            (1.0, 0.5, 1.5),
+            (1.0, 0.5, 1.0),
+            (0.5, 0.5, 1.0),
+            (0.0, 0.5, 1.0)
+        ],
+        xyz
+    );
+    Ok(())
+}
+
+#[test]
+fn test_surface_scan_meander_passes() -> Result<(), HallrError> {
+    use super::PassType::{Link, Raster};
+    let (vertices, indices) = meander_samples();
+    assert_eq!(
+        vec![(0, Raster), (2, Link), (3, Raster)],
+        super::meander_pass_groups(&vertices, 0.5)
+    );
+    // the return move of a unidirectional toolpath is a single link
+    let mut config = ConfigType::default();
+    let _ = config.insert("pass_order".to_string(), "UNIDIRECTIONAL".to_string());
+    let order = super::MeanderOrder::from_config(&config)?.unwrap();
+    let result = super::reorder_meander_passes(&vertices, &indices, 0.5, &order)?;
+    assert_eq!(
+        vec![(0, Raster), (2, Link), (5, Raster)],
+        super::meander_pass_groups(&result, 0.5)
+    );
+    Ok(())
+}
+
+/// 33 passes along X, a quarter apart, with the height `z(y)`
+fn dense_passes(z: impl Fn(f32) -> f32) -> Vec<Vec<FFIVector3>> {
+    (0..33)
+        .map(|row| {
+            let y = row as f32 * 0.25;
+            (0..5)
+                .map(|column| FFIVector3::new(column as f32, y, z(y)))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_surface_scan_adaptive_passes() {
+    // a flat surface is scanned at the maximum stride
+    let passes = dense_passes(|y| 0.5 * y);
+    assert_eq!(
+        (0..=32).step_by(4).collect::<Vec<_>>(),
+        super::select_adaptive_passes(&passes, true, 0.1, 4)
+    );
+    // the last pass is kept even if it is closer than the stride
+    assert_eq!(
+        vec![0, 3, 6, 9, 12, 15, 18, 21, 24, 27, 30, 32],
+        super::select_adaptive_passes(&passes, true, 0.1, 3)
+    );
+    // the chord error of z=y² over three quarters is 0.125, over two quarters 0.0625
+    let passes = dense_passes(|y| y * y);
+    assert_eq!(
+        (0..=32).step_by(2).collect::<Vec<_>>(),
+        super::select_adaptive_passes(&passes, true, 0.1, 4)
+    );
+    // a tight tolerance keeps every pass
+    assert_eq!(
+        33,
+        super::select_adaptive_passes(&passes, true, 0.01, 4).len()
+    );
+    assert_eq!(Some(1.0), super::pass_z_at(&passes[4], true, 2.5));
+    assert_eq!(None, super::pass_z_at(&passes[4], true, 4.5));
+}
+
+#[test]
+fn test_surface_scan_torus_heights() {
+    // ball nose heights on a flat grid, with a single peak at (2.5,2.5)
+    let mut grid = Vec::<FFIVector3>::new();
+    for row in 0..11 {
+        for column in 0..11 {
+            let z = if row == 5 && column == 5 { 1.0 } else { 0.0 };
+            grid.push(FFIVector3::new(column as f32 * 0.5, row as f32 * 0.5, z));
+        }
+    }
+    let mut vertices = vec![
+        FFIVector3::new(3.5, 2.5, 0.0),
+        FFIVector3::new(4.1, 2.5, 0.0),
+        FFIVector3::new(2.5, 3.0, 2.0),
+        // outside of the grid
+        FFIVector3::new(-3.0, -3.0, 0.0),
+    ];
+    super::torus_heights(&mut vertices, &grid, 11, 0.5, 1.0);
+    // the disc rests on the peak
+    assert_eq!(1.0, vertices[0].z);
+    // the peak is out of reach
+    assert_eq!(0.0, vertices[1].z);
+    // the ball nose height at the vertex is kept if it is higher
+    assert_eq!(2.0, vertices[2].z);
+    assert_eq!(0.0, vertices[3].z);
+}
+
+#[test]
+fn test_surface_scan_torus() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("bounds".to_string(), "AABB".to_string());
+    let _ = config.insert("probe_radius".to_string(), "0.3".to_string());
+    let _ = config.insert("probe_minor_radius".to_string(), "0.2".to_string());
+    let _ = config.insert("minimum_z".to_string(), "-2.0".to_string());
+    let _ = config.insert("step".to_string(), "0.5".to_string());
+    let _ = config.insert("command".to_string(), "surface_scan".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("pattern".to_string(), "MEANDER".to_string());
+    let _ = config.insert("first_vertex_model_1".to_string(), "6".to_string());
+    let _ = config.insert("first_index_model_1".to_string(), "15".to_string());
+    let _ = config.insert("probe".to_string(), "TORUS".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-0.29610628, -1.7045903, -0.9548358).into(),
+            (-0.18138881, -0.23321122, 0.5500126).into(),
+            (-1.5054786, 0.84019524, -0.70687366).into(),
+            (1.5054786, -0.84019524, -1.0391741).into(),
+            (0.6572089, 0.07475242, 0.09592825).into(),
+            (0.29610628, 1.7045903, -0.79121196).into(),
+        ],
+        indices: vec![1, 2, 0, 3, 1, 0, 5, 1, 4, 3, 4, 1, 5, 2, 1],
+    };
+    let owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.0, -1.0, 0.0).into(),
+            (1.0, -1.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (-1.0, 1.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 0],
+    };
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let torus = super::process_command::<Vec3>(config.clone(), models)?;
+    // the corner ball alone never rests higher than the whole torus
+    let _ = config.insert("probe".to_string(), "BALL_NOSE".to_string());
+    let _ = config.insert("probe_radius".to_string(), "0.2".to_string());
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let ball = super::process_command::<Vec3>(config.clone(), models)?;
+    assert_eq!(ball.0.len(), torus.0.len());
+    for (b, t) in ball.0.iter().zip(torus.0.iter()) {
+        assert_eq!((b.x, b.y), (t.x, t.y));
+        assert!(t.z >= b.z, "{:?} {:?}", b, t);
+    }
+    assert!(ball.0.iter().zip(torus.0.iter()).any(|(b, t)| t.z > b.z));
+
+    // the drag knife compensation needs a toolpath
+    let _ = config.insert("probe".to_string(), "DRAG_KNIFE".to_string());
+    let _ = config.insert("blade_offset".to_string(), "0.25".to_string());
+    let _ = config.insert("pattern".to_string(), "TRIANGULATION".to_string());
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    assert!(super::process_command::<Vec3>(config, models).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_surface_scan_drag_knife() {
+    let points: Vec<FFIVector3> = vec![
+        (0.0, 0.0, -1.0).into(),
+        (10.0, 0.0, -1.0).into(),
+        (10.0, 10.0, -2.0).into(),
+    ];
+    let (path, starts) = super::drag_knife_path(&points, 1.0);
+    // the swivel axis leads the tip along the first segment
+    assert_eq!(FFIVector3::new(1.0, 0.0, -1.0), path[0]);
+    assert_eq!(FFIVector3::new(11.0, 0.0, -1.0), path[1]);
+    // a quarter turn around the tip, then the second segment
+    assert_eq!(2, starts.len());
+    let second = starts[1];
+    assert_eq!(path.len() - 2, second);
+    assert!(second > 3);
+    for v in &path[1..=second] {
+        let r = ((v.x - 10.0).powi(2) + v.y.powi(2)).sqrt();
+        assert!((r - 1.0).abs() < 1e-5, "{:?}", v);
+        assert_eq!(-1.0, v.z);
+    }
+    assert!((path[second].x - 10.0).abs() < 1e-5 && (path[second].y - 1.0).abs() < 1e-5);
+    assert_eq!(FFIVector3::new(10.0, 11.0, -2.0), *path.last().unwrap());
+
+    // a straight path is only shifted
+    let points: Vec<FFIVector3> = vec![
+        (0.0, 0.0, 0.0).into(),
+        (5.0, 0.0, 0.0).into(),
+        (10.0, 0.0, 0.0).into(),
+    ];
+    let (path, starts) = super::drag_knife_path(&points, 0.5);
+    assert_eq!(vec![0, 1], starts);
+    assert_eq!(
+        vec![0.5, 5.5, 10.5],
+        path.iter().map(|v| v.x).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_surface_scan_meander_order_3() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    assert!(super::MeanderOrder::from_config(&config)?.is_none());
+    // the direction can only be chosen for unidirectional passes
+    let _ = config.insert("direction".to_string(), "CONVENTIONAL".to_string());
+    assert!(super::MeanderOrder::from_config(&config).is_err());
+    let _ = config.insert("pass_order".to_string(), "UNIDIRECTIONAL".to_string());
+    let _ = config.insert("start_corner".to_string(), "MIN_Z".to_string());
+    assert!(super::MeanderOrder::from_config(&config).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_surface_scan_lattice_1() {
+    // a 3*3 grid with one missing corner
+    let mut grid: Vec<FFIVector3> = Vec::new();
+    for row in 0..3 {
+        for column in 0..3 {
+            grid.push(FFIVector3::new(column as f32, row as f32, 1.0));
+        }
+    }
+    grid[8].z = f32::NAN;
+    let (vertices, indices) = super::triangulate_lattice(&grid, 3, 3, 1, 1);
+    assert_eq!(8, vertices.len());
+    // three full cells and one cell with a single triangle
+    assert_eq!(3 * 7, indices.len());
+
+    // every other column
+    let (vertices, indices) = super::triangulate_lattice(&grid, 3, 3, 2, 1);
+    assert_eq!(5, vertices.len());
+    assert_eq!(3 * 3, indices.len());
+    assert!(vertices.iter().all(|v| v.x == 0.0 || v.x == 2.0));
+}
+
+#[test]
+fn test_surface_scan_lattice_2() {
+    let vertices = vec![FFIVector3::new(1.0, 0.0, 2.0)];
+    let rotated = super::rotate_z(&vertices, std::f32::consts::FRAC_PI_2);
+    assert!(rotated[0].x.abs() < 1e-6);
+    assert!((rotated[0].y - 1.0).abs() < 1e-6);
+    assert_eq!(2.0, rotated[0].z);
+    let restored = super::rotate_z(&rotated, -std::f32::consts::FRAC_PI_2);
+    assert!((restored[0].x - 1.0).abs() < 1e-6);
+    assert!(restored[0].y.abs() < 1e-6);
+}
+
+#[test]
+fn test_surface_scan_lattice_3() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("bounds".to_string(), "AABB".to_string());
+    let _ = config.insert("probe_radius".to_string(), "0.5".to_string());
+    let _ = config.insert("minimum_z".to_string(), "0.0".to_string());
+    let _ = config.insert("first_index_model_1".to_string(), "15".to_string());
+    let _ = config.insert("step".to_string(), "0.5".to_string());
+    let _ = config.insert("step_x".to_string(), "0.5".to_string());
+    let _ = config.insert("step_y".to_string(), "0.25".to_string());
+    let _ = config.insert("lattice_angle".to_string(), "0.3".to_string());
+    let _ = config.insert("command".to_string(), "surface_scan".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("pattern".to_string(), "TRIANGULATION".to_string());
+    let _ = config.insert("first_vertex_model_1".to_string(), "6".to_string());
+    let _ = config.insert("probe".to_string(), "BALL_NOSE".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-0.29610628, -1.7045903, -0.9548358).into(),
+            (-0.18138881, -0.23321122, 0.5500126).into(),
+            (-1.5054786, 0.84019524, -0.70687366).into(),
+            (1.5054786, -0.84019524, -1.0391741).into(),
+            (0.6572089, 0.07475242, 0.09592825).into(),
+            (0.29610628, 1.7045903, -0.79121196).into(),
+        ],
+        indices: vec![1, 2, 0, 3, 1, 0, 5, 1, 4, 3, 4, 1, 5, 2, 1],
+    };
+
+    let owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.8112676, -0.21234381, 0.0).into(),
+            (-1.0113943, -0.9753443, 0.0).into(),
+            (1.0, -1.0, 0.0).into(),
+            (1.5378065, -0.20696306, 0.0).into(),
+            (1.0241334, 1.0380125, 0.0).into(),
+            (-0.13404018, 1.979902, 0.0).into(),
+            (-1.0, 1.0, 0.0).into(),
+            (-1.8112676, -0.21234381, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 0],
+    };
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command::<Vec3>(config, models)?;
+    assert_eq!("triangulated", result.3.get("mesh.format").unwrap());
+    assert_eq!("0.5", result.3.get("lattice.step_x").unwrap());
+    assert_eq!("0.25", result.3.get("lattice.step_y").unwrap());
+    assert!(!result.1.is_empty());
+    assert_eq!(0, result.1.len() % 3);
+    Ok(())
+}
+
+#[test]
+fn test_surface_scan_clamp() -> Result<(), HallrError> {
+    let scan_config = |floor_z: Option<&str>, fail_on_clamp: bool| {
+        let mut config = ConfigType::default();
+        let _ = config.insert("bounds".to_string(), "AABB".to_string());
+        let _ = config.insert("probe_radius".to_string(), "0.5".to_string());
+        let _ = config.insert("minimum_z".to_string(), "0.0".to_string());
+        let _ = config.insert("step".to_string(), "0.5".to_string());
+        let _ = config.insert("command".to_string(), "surface_scan".to_string());
+        let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+        let _ = config.insert("pattern".to_string(), "MEANDER".to_string());
+        let _ = config.insert("probe".to_string(), "BALL_NOSE".to_string());
+        let _ = config.insert("FAIL_ON_CLAMP".to_string(), fail_on_clamp.to_string());
+        if let Some(floor_z) = floor_z {
+            let _ = config.insert("FLOOR_Z".to_string(), floor_z.to_string());
+        }
+        config
+    };
+    // the surface is partly below minimum_z
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-0.29610628, -1.7045903, -0.9548358).into(),
+            (-0.18138881, -0.23321122, 0.5500126).into(),
+            (-1.5054786, 0.84019524, -0.70687366).into(),
+            (1.5054786, -0.84019524, -1.0391741).into(),
+            (0.6572089, 0.07475242, 0.09592825).into(),
+            (0.29610628, 1.7045903, -0.79121196).into(),
+        ],
+        indices: vec![1, 2, 0, 3, 1, 0, 5, 1, 4, 3, 4, 1, 5, 2, 1],
+    };
+    let owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.0, -1.0, 0.0).into(),
+            (1.0, -1.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (-1.0, 1.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 0],
+    };
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command::<Vec3>(scan_config(None, false), models)?;
+    let clamped: usize = result.3.get("CLAMPED_SAMPLES").unwrap().parse().unwrap();
+    assert!(clamped > 0);
+    assert!(clamped < result.0.len());
+    assert!(result.3.contains_key("WARNING"));
+
+    // the floor raises more samples
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command::<Vec3>(scan_config(Some("0.3"), false), models)?;
+    assert!(result.0.iter().all(|v| v.z >= 0.3));
+    let floor_clamped: usize = result.3.get("CLAMPED_SAMPLES").unwrap().parse().unwrap();
+    assert!(floor_clamped >= clamped);
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    assert!(super::process_command::<Vec3>(scan_config(None, true), models).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_surface_scan_nominal() -> Result<(), HallrError> {
+    let square = |z: f32| OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-2.0, -2.0, z).into(),
+            (2.0, -2.0, z).into(),
+            (2.0, 2.0, z).into(),
+            (-2.0, 2.0, z).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 2, 3],
+    };
+    let scan_config = |nominal_model: &str| {
+        let mut config = ConfigType::default();
+        let _ = config.insert("bounds".to_string(), "AABB".to_string());
+        let _ = config.insert("probe_radius".to_string(), "0.5".to_string());
+        let _ = config.insert("minimum_z".to_string(), "0.0".to_string());
+        let _ = config.insert("step".to_string(), "0.5".to_string());
+        let _ = config.insert("command".to_string(), "surface_scan".to_string());
+        let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+        let _ = config.insert("pattern".to_string(), "MEANDER".to_string());
+        let _ = config.insert("probe".to_string(), "BALL_NOSE".to_string());
+        let _ = config.insert("NOMINAL_MODEL".to_string(), nominal_model.to_string());
+        config
+    };
+    // the scanned surface is 0.2 above the nominal surface
+    let owned_model_0 = square(1.0);
+    let owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.0, -1.0, 0.0).into(),
+            (1.0, -1.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (-1.0, 1.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 0],
+    };
+    let owned_model_2 = square(0.8);
+
+    let models = vec![
+        owned_model_0.as_model(),
+        owned_model_1.as_model(),
+        owned_model_2.as_model(),
+    ];
+    let result = super::process_command::<Vec3>(scan_config("2"), models)?;
+    assert!(!result.0.is_empty());
+    // the nominal mesh is not scanned, so the toolpath follows the scanned surface
+    assert!(result.0.iter().all(|v| (v.z - 1.0).abs() < 1e-4));
+    let deviations: Vec<f64> = result
+        .3
+        .get("DEVIATIONS")
+        .unwrap()
+        .split(',')
+        .map(|d| d.parse().unwrap())
+        .collect();
+    assert_eq!(result.0.len(), deviations.len());
+    assert!(deviations.iter().all(|d| (d - 0.2).abs() < 1e-4));
+    assert_eq!("0", result.3.get("DEVIATION_MISSING").unwrap());
+    let mean: f64 = result.3.get("DEVIATION_MEAN").unwrap().parse().unwrap();
+    assert!((mean - 0.2).abs() < 1e-4);
+
+    // the bounding shape can not be the nominal mesh
+    let models = vec![
+        owned_model_0.as_model(),
+        owned_model_1.as_model(),
+        owned_model_2.as_model(),
+    ];
+    assert!(super::process_command::<Vec3>(scan_config("1"), models).is_err());
+    Ok(())
+}
@@ -0,0 +1,398 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use ahash::AHashMap;
+use vector_traits::glam::{dvec3, DMat3, DVec3};
+
+#[cfg(test)]
+mod tests;
+
+/// The default tolerance, as a fraction of the diagonal of the bounding box
+const DEFAULT_TOLERANCE_FRACTION: f64 = 1e-3;
+/// The number of times a candidate plane is re-fitted to its matched vertex pairs
+const REFINE_ITERATIONS: usize = 5;
+/// The number of Jacobi sweeps of the eigen decomposition
+const JACOBI_SWEEPS: usize = 16;
+/// Planes with normals closer than this (cosine of the angle) are the same plane
+const SAME_NORMAL_COS: f64 = 0.9998;
+
+/// A mirror plane, the points `p` of the plane fulfill `normal.dot(p) == distance`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SymmetryPlane {
+    pub(crate) normal: DVec3,
+    pub(crate) distance: f64,
+    /// The fraction of the vertices with a mirrored vertex within the tolerance
+    pub(crate) score: f64,
+    /// The RMS distance between the mirrored vertices and their matches
+    pub(crate) rms: f64,
+}
+
+impl SymmetryPlane {
+    fn reflect(&self, p: DVec3) -> DVec3 {
+        p - self.normal * (2.0 * (self.normal.dot(p) - self.distance))
+    }
+}
+
+/// A uniform hash grid over the vertices, for nearest neighbour queries within a fixed radius
+struct VertexGrid<'a> {
+    cell_size: f64,
+    cells: AHashMap<(i64, i64, i64), Vec<usize>>,
+    vertices: &'a [DVec3],
+}
+
+impl<'a> VertexGrid<'a> {
+    fn new(vertices: &'a [DVec3], cell_size: f64) -> Self {
+        let mut cells = AHashMap::<(i64, i64, i64), Vec<usize>>::default();
+        for (i, v) in vertices.iter().enumerate() {
+            cells.entry(Self::cell(*v, cell_size)).or_default().push(i);
+        }
+        Self {
+            cell_size,
+            cells,
+            vertices,
+        }
+    }
+
+    fn cell(p: DVec3, cell_size: f64) -> (i64, i64, i64) {
+        let c = (p / cell_size).floor();
+        (c.x as i64, c.y as i64, c.z as i64)
+    }
+
+    /// Returns the index of the vertex closest to `p`, if it is within the cell size, and the
+    /// distance to it
+    fn closest(&self, p: DVec3) -> Option<(usize, f64)> {
+        let (cx, cy, cz) = Self::cell(p, self.cell_size);
+        let mut best: Option<(usize, f64)> = None;
+        for x in cx - 1..=cx + 1 {
+            for y in cy - 1..=cy + 1 {
+                for z in cz - 1..=cz + 1 {
+                    let Some(cell) = self.cells.get(&(x, y, z)) else {
+                        continue;
+                    };
+                    for i in cell.iter() {
+                        let d = self.vertices[*i].distance(p);
+                        if d <= self.cell_size && !best.is_some_and(|(_, b)| b <= d) {
+                            best = Some((*i, d));
+                        }
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Returns the eigenvectors of the symmetric matrix `m`, by the Jacobi method
+pub(crate) fn eigenvectors(m: DMat3) -> [DVec3; 3] {
+    let mut a = m.to_cols_array_2d();
+    let mut v = DMat3::IDENTITY.to_cols_array_2d();
+    for _ in 0..JACOBI_SWEEPS {
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-30 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for row in a.iter_mut() {
+                let (akp, akq) = (row[p], row[q]);
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            a[p] = std::array::from_fn(|k| c * row_p[k] - s * row_q[k]);
+            a[q] = std::array::from_fn(|k| s * row_p[k] + c * row_q[k]);
+            for row in v.iter_mut() {
+                let (vkp, vkq) = (row[p], row[q]);
+                row[p] = c * vkp - s * vkq;
+                row[q] = s * vkp + c * vkq;
+            }
+        }
+    }
+    // the eigenvectors are the columns of the accumulated rotation
+    [0, 1, 2].map(|i| dvec3(v[0][i], v[1][i], v[2][i]))
+}
+
+/// Flip the normal so that its largest component is positive, for a deterministic output
+pub(crate) fn canonical(normal: DVec3, distance: f64) -> (DVec3, f64) {
+    let largest = if normal.x.abs() >= normal.y.abs() && normal.x.abs() >= normal.z.abs() {
+        normal.x
+    } else if normal.y.abs() >= normal.z.abs() {
+        normal.y
+    } else {
+        normal.z
+    };
+    if largest < 0.0 {
+        (-normal, -distance)
+    } else {
+        (normal, distance)
+    }
+}
+
+/// Match every reflected vertex with its closest vertex. Returns the matches and the plane with
+/// its score and rms.
+fn score_plane(
+    grid: &VertexGrid<'_>,
+    normal: DVec3,
+    distance: f64,
+) -> (Vec<Option<usize>>, SymmetryPlane) {
+    let mut plane = SymmetryPlane {
+        normal,
+        distance,
+        score: 0.0,
+        rms: 0.0,
+    };
+    let mut sum_squared = 0.0;
+    let mut matched = 0_usize;
+    let matches: Vec<Option<usize>> = grid
+        .vertices
+        .iter()
+        .map(|v| {
+            grid.closest(plane.reflect(*v)).map(|(i, d)| {
+                matched += 1;
+                sum_squared += d * d;
+                i
+            })
+        })
+        .collect();
+    if matched > 0 {
+        plane.score = matched as f64 / grid.vertices.len() as f64;
+        plane.rms = (sum_squared / matched as f64).sqrt();
+    }
+    (matches, plane)
+}
+
+/// Re-fit the plane to the matched vertex pairs: the normal is the mean direction between the
+/// pairs, and the plane goes through the mean of their midpoints
+fn refine_plane(vertices: &[DVec3], matches: &[Option<usize>], normal: DVec3) -> (DVec3, f64) {
+    let mut direction = DVec3::ZERO;
+    let mut midpoint_sum = DVec3::ZERO;
+    let mut count = 0_usize;
+    for (v, m) in vertices.iter().zip(matches.iter()) {
+        let Some(m) = m else {
+            continue;
+        };
+        let delta = *v - vertices[*m];
+        direction += if delta.dot(normal) < 0.0 {
+            -delta
+        } else {
+            delta
+        };
+        midpoint_sum += (*v + vertices[*m]) * 0.5;
+        count += 1;
+    }
+    let normal = if direction.length_squared() > 0.0 {
+        direction.normalize()
+    } else {
+        normal
+    };
+    (normal, normal.dot(midpoint_sum / count.max(1) as f64))
+}
+
+/// Find the mirror planes of the vertices. The candidates are the planes through the centroid,
+/// perpendicular to the world axes and to the principal axes of the vertices. Every candidate is
+/// re-fitted to its matches, the planes with a score of at least `min_score` are returned, the
+/// best first.
+pub(crate) fn find_symmetry_planes(
+    vertices: &[DVec3],
+    tolerance: f64,
+    min_score: f64,
+) -> Vec<SymmetryPlane> {
+    if vertices.is_empty() {
+        return Vec::default();
+    }
+    let centroid = vertices.iter().copied().sum::<DVec3>() / vertices.len() as f64;
+    let covariance = vertices.iter().fold(DMat3::ZERO, |sum, v| {
+        let d = *v - centroid;
+        sum + DMat3::from_cols(d * d.x, d * d.y, d * d.z)
+    });
+    let grid = VertexGrid::new(vertices, tolerance);
+
+    let mut rv = Vec::<SymmetryPlane>::new();
+    for candidate in [DVec3::X, DVec3::Y, DVec3::Z]
+        .into_iter()
+        .chain(eigenvectors(covariance))
+    {
+        if !candidate.is_finite() || candidate.length_squared() < 0.5 {
+            continue;
+        }
+        let mut normal = candidate.normalize();
+        let mut distance = normal.dot(centroid);
+        let (mut matches, mut plane) = score_plane(&grid, normal, distance);
+        for _ in 0..REFINE_ITERATIONS {
+            if plane.score == 0.0 {
+                break;
+            }
+            (normal, distance) = refine_plane(vertices, &matches, normal);
+            let (new_matches, new_plane) = score_plane(&grid, normal, distance);
+            if new_plane.score < plane.score {
+                break;
+            }
+            (matches, plane) = (new_matches, new_plane);
+        }
+        if plane.score < min_score {
+            continue;
+        }
+        (plane.normal, plane.distance) = canonical(plane.normal, plane.distance);
+        let duplicate = rv.iter().any(|p| {
+            p.normal.dot(plane.normal) > SAME_NORMAL_COS
+                && (p.distance - plane.distance).abs() < tolerance
+        });
+        if !duplicate {
+            rv.push(plane);
+        }
+    }
+    rv.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap()
+            .then(a.rms.partial_cmp(&b.rms).unwrap())
+    });
+    rv
+}
+
+/// Move the vertices so that they become exactly symmetric over `plane`. Only mutually matched
+/// vertex pairs are moved: both are replaced with the mean of the vertex and the reflection of its
+/// match. Vertices matched to themselves are projected onto the plane.
+/// Returns the number of moved vertices.
+pub(crate) fn snap_to_plane(
+    vertices: &mut [DVec3],
+    plane: &SymmetryPlane,
+    tolerance: f64,
+) -> usize {
+    let matches = {
+        let grid = VertexGrid::new(vertices, tolerance);
+        score_plane(&grid, plane.normal, plane.distance).0
+    };
+    let original = vertices.to_vec();
+    let mut snapped = 0_usize;
+    for (i, m) in matches.iter().enumerate() {
+        let Some(m) = *m else {
+            continue;
+        };
+        if matches[m] != Some(i) {
+            continue;
+        }
+        let v = (original[i] + plane.reflect(original[m])) * 0.5;
+        if v != original[i] {
+            vertices[i] = v;
+            snapped += 1;
+        }
+    }
+    snapped
+}
+
+/// Run the symmetry command
+/// Detects the approximate mirror symmetry planes of the vertices of model 0 (any mesh format).
+/// A vertex is matched when its reflection is within `SYMMETRY_TOLERANCE` of another vertex
+/// (default 0.1% of the diagonal of the bounding box). The planes matching at least the
+/// `SYMMETRY_MIN_SCORE` fraction of the vertices (default 0.9) are returned, the best first, as
+/// `SYMMETRY_PLANES`: nx,ny,nz,d groups where `n.dot(p) == d` on the plane, in the coordinates of
+/// the input vertices. The scores and the RMS match distances are returned as `SYMMETRY_SCORES`
+/// and `SYMMETRY_RMS`, the number of planes as `SYMMETRY_PLANE_COUNT`.
+/// With `SYMMETRY_SNAP=true` the vertices are moved to be exactly symmetric over the best plane,
+/// the number of moved vertices is returned as `SNAPPED_VERTICES`. The indices are unchanged.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let model = &models[0];
+    if model.vertices.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "The model has no vertices".to_string(),
+        ));
+    }
+    let mut vertices: Vec<DVec3> = model
+        .vertices
+        .iter()
+        .map(|v| dvec3(v.x as f64, v.y as f64, v.z as f64))
+        .collect();
+    let (min, max) = vertices.iter().fold(
+        (DVec3::splat(f64::MAX), DVec3::splat(f64::MIN)),
+        |(min, max), v| (min.min(*v), max.max(*v)),
+    );
+    let default_tolerance = (max - min).length() * DEFAULT_TOLERANCE_FRACTION;
+    let tolerance = match config.get_parsed_option::<f64>("SYMMETRY_TOLERANCE")? {
+        Some(tolerance) => tolerance,
+        None => default_tolerance,
+    };
+    if !tolerance.is_finite() || tolerance <= 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "SYMMETRY_TOLERANCE must be a positive number :({})",
+            tolerance
+        )));
+    }
+    let min_score = config.get_mandatory_parsed_option::<f64>("SYMMETRY_MIN_SCORE", Some(0.9))?;
+    if !(0.0..=1.0).contains(&min_score) {
+        return Err(HallrError::InvalidParameter(format!(
+            "SYMMETRY_MIN_SCORE must be in the range 0..=1 :({})",
+            min_score
+        )));
+    }
+    let snap = config.get_mandatory_parsed_option::<bool>("SYMMETRY_SNAP", Some(false))?;
+
+    let planes = find_symmetry_planes(&vertices, tolerance, min_score);
+    let snapped = match (snap, planes.first()) {
+        (true, Some(plane)) => snap_to_plane(&mut vertices, plane, tolerance),
+        _ => 0,
+    };
+    let join = |values: Vec<f64>| {
+        values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert(
+        "mesh.format".to_string(),
+        config.get_mandatory_option("mesh.format")?.to_string(),
+    );
+    let _ = return_config.insert("SYMMETRY_PLANE_COUNT".to_string(), planes.len().to_string());
+    let _ = return_config.insert(
+        "SYMMETRY_PLANES".to_string(),
+        join(
+            planes
+                .iter()
+                .flat_map(|p| [p.normal.x, p.normal.y, p.normal.z, p.distance])
+                .collect(),
+        ),
+    );
+    let _ = return_config.insert(
+        "SYMMETRY_SCORES".to_string(),
+        join(planes.iter().map(|p| p.score).collect()),
+    );
+    let _ = return_config.insert(
+        "SYMMETRY_RMS".to_string(),
+        join(planes.iter().map(|p| p.rms).collect()),
+    );
+    let _ = return_config.insert("SYMMETRY_TOLERANCE".to_string(), tolerance.to_string());
+    if snap {
+        let _ = return_config.insert("SNAPPED_VERTICES".to_string(), snapped.to_string());
+    }
+    info!(
+        "symmetry operation found {} planes, snapped {} vertices, tolerance:{}",
+        planes.len(),
+        snapped,
+        tolerance
+    );
+    Ok((
+        vertices
+            .iter()
+            .map(|v| FFIVector3::new(v.x as f32, v.y as f32, v.z as f32))
+            .collect(),
+        model.indices.to_vec(),
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{ConfigType, OwnedModel},
+    HallrError,
+};
+
+/// A point cloud that is mirror symmetric over the x=1 plane, and nothing else. The first vertex
+/// of every pair is at x=1-dx, the second at x=1+dx. The last vertex is on the plane.
+fn mirrored_model() -> OwnedModel {
+    let half = [
+        (0.5, 0.0, 0.0),
+        (0.7, 1.0, 0.2),
+        (0.3, 2.5, 0.1),
+        (0.9, 0.4, 1.3),
+    ];
+    let mut vertices = Vec::new();
+    for (dx, y, z) in half {
+        vertices.push((1.0 - dx, y, z).into());
+        vertices.push((1.0 + dx, y, z).into());
+    }
+    vertices.push((1.0, 3.0, 0.7).into());
+    OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices,
+        indices: vec![0, 1, 2, 3, 4, 5, 6, 7, 8],
+    }
+}
+
+#[test]
+fn test_symmetry_1() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "symmetry".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+
+    let owned_model_0 = mirrored_model();
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!("1", result.3.get("SYMMETRY_PLANE_COUNT").unwrap());
+    let plane: Vec<f64> = result
+        .3
+        .get("SYMMETRY_PLANES")
+        .unwrap()
+        .split(',')
+        .map(|v| v.parse().unwrap())
+        .collect();
+    assert_eq!(4, plane.len());
+    assert!((plane[0] - 1.0).abs() < 1e-6);
+    assert!(plane[1].abs() < 1e-6);
+    assert!(plane[2].abs() < 1e-6);
+    assert!((plane[3] - 1.0).abs() < 1e-6);
+    assert_eq!("1", result.3.get("SYMMETRY_SCORES").unwrap());
+    // the model is returned unchanged
+    assert!(owned_model_0.vertices == result.0);
+    assert_eq!(owned_model_0.indices, result.1);
+    assert!(!result.3.contains_key("SNAPPED_VERTICES"));
+    Ok(())
+}
+
+#[test]
+fn test_symmetry_2() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "symmetry".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("SYMMETRY_TOLERANCE".to_string(), "0.01".to_string());
+    let _ = config.insert("SYMMETRY_SNAP".to_string(), "true".to_string());
+
+    let mut owned_model_0 = mirrored_model();
+    owned_model_0.vertices[3].x += 0.004;
+    owned_model_0.vertices[8].x += 0.002;
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert_eq!("1", result.3.get("SYMMETRY_PLANE_COUNT").unwrap());
+    assert_ne!("0", result.3.get("SNAPPED_VERTICES").unwrap());
+    let (a, b) = (result.0[2], result.0[3]);
+    assert!((a.x + b.x - 2.0).abs() < 1e-3);
+    assert_eq!(a.y, b.y);
+    assert_eq!(a.z, b.z);
+    assert!((result.0[8].x - 1.0).abs() < 1e-3);
+
+    // the tolerance must be positive
+    let _ = config.insert("SYMMETRY_TOLERANCE".to_string(), "-1.0".to_string());
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
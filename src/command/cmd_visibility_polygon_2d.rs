@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{ConfigType, Model, Options, OwnedModel};
+use crate::{ffi::FFIVector3, HallrError};
+use vector_traits::glam::{dvec2, DVec2};
+
+#[cfg(test)]
+mod tests;
+
+/// The angle (in radians) used to cast rays just before and just after each obstacle vertex
+const RAY_ANGLE_EPSILON: f64 = 1e-5;
+/// Distance tolerance as a fraction of the size of the scene
+const RELATIVE_TOLERANCE: f64 = 1e-6;
+
+/// Casts a ray from `origin` in the direction `dir` and returns the distance to the closest
+/// obstacle segment, if any.
+fn cast_ray(origin: DVec2, dir: DVec2, segments: &[(DVec2, DVec2)]) -> Option<f64> {
+    let mut closest: Option<f64> = None;
+    for (p, q) in segments.iter() {
+        let s = *q - *p;
+        let denominator = dir.perp_dot(s);
+        if denominator.abs() < f64::EPSILON {
+            // parallel
+            continue;
+        }
+        let po = *p - origin;
+        let t = po.perp_dot(s) / denominator;
+        let u = po.perp_dot(dir) / denominator;
+        if t >= 0.0 && (-f64::EPSILON..=1.0 + f64::EPSILON).contains(&u) {
+            closest = Some(closest.map_or(t, |c| c.min(t)));
+        }
+    }
+    closest
+}
+
+/// Returns true if `b` is redundant in the path a->b->c (collinear and pointing in the same
+/// direction)
+fn is_collinear(a: DVec2, b: DVec2, c: DVec2) -> bool {
+    let ab = b - a;
+    let bc = c - b;
+    ab.perp_dot(bc).abs() <= RELATIVE_TOLERANCE * ab.length() * bc.length() && ab.dot(bc) > 0.0
+}
+
+/// Computes the visibility polygon as seen from `observer`.
+/// The obstacles are surrounded by an implicit bounding box, so that every ray hits something.
+/// The result is a counter-clockwise loop of points, the first point is not repeated.
+fn visibility_polygon(
+    observer: DVec2,
+    mut segments: Vec<(DVec2, DVec2)>,
+) -> Result<Vec<DVec2>, HallrError> {
+    let (low, high) = segments
+        .iter()
+        .fold((observer, observer), |(low, high), s| {
+            (low.min(s.0).min(s.1), high.max(s.0).max(s.1))
+        });
+    let padding = (high - low).max_element() * 0.1 + 1.0;
+    let (low, high) = (low - DVec2::splat(padding), high + DVec2::splat(padding));
+    let scale = (high - low).max_element();
+    let tolerance = scale * RELATIVE_TOLERANCE;
+
+    let corners = [low, dvec2(high.x, low.y), high, dvec2(low.x, high.y)];
+    for i in 0..4 {
+        segments.push((corners[i], corners[(i + 1) % 4]));
+    }
+
+    let mut angles = Vec::<f64>::with_capacity(segments.len() * 6);
+    for p in segments.iter().flat_map(|s| [s.0, s.1]) {
+        let d = p - observer;
+        if d.length() <= tolerance {
+            continue;
+        }
+        let angle = d.y.atan2(d.x);
+        angles.push(angle - RAY_ANGLE_EPSILON);
+        angles.push(angle);
+        angles.push(angle + RAY_ANGLE_EPSILON);
+    }
+    angles.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let mut rv = Vec::<DVec2>::with_capacity(angles.len());
+    for angle in angles {
+        let dir = dvec2(angle.cos(), angle.sin());
+        let t = cast_ray(observer, dir, &segments).ok_or_else(|| {
+            HallrError::InternalError(format!(
+                "A ray from the observer did not hit anything, angle:{}",
+                angle
+            ))
+        })?;
+        let p = observer + dir * t;
+        if rv.last().is_some_and(|l| l.distance(p) <= tolerance) {
+            continue;
+        }
+        rv.push(p);
+        while rv.len() >= 3 && is_collinear(rv[rv.len() - 3], rv[rv.len() - 2], rv[rv.len() - 1]) {
+            let _ = rv.remove(rv.len() - 2);
+        }
+    }
+    // clean up the wrap-around point
+    if rv.len() > 1 && rv[0].distance(rv[rv.len() - 1]) <= tolerance {
+        let _ = rv.pop();
+    }
+    while rv.len() >= 3 && is_collinear(rv[rv.len() - 2], rv[rv.len() - 1], rv[0]) {
+        let _ = rv.pop();
+    }
+    while rv.len() >= 3 && is_collinear(rv[rv.len() - 1], rv[0], rv[1]) {
+        let _ = rv.remove(0);
+    }
+    if rv.len() < 3 {
+        return Err(HallrError::InternalError(
+            "The visibility polygon collapsed into less than three points".to_string(),
+        ));
+    }
+    Ok(rv)
+}
+
+/// Run the visibility_polygon_2d command
+/// Model 0 contains the obstacles (in the line_chunks format), the first vertex of model 1 is the
+/// observer. The data is projected onto the XY plane, the output is placed at the Z coordinate of
+/// the observer.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.len() < 2 {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires two input models: the obstacles and the observer".to_string(),
+        ));
+    }
+    let mesh_format = config.get_mandatory_option("mesh.format")?;
+    if mesh_format.ne("line_chunks") {
+        return Err(HallrError::InvalidInputData(
+            "Model mesh data must be in the 'line_chunks' format".to_string(),
+        ));
+    }
+    let obstacles = &models[0];
+    let observer = models[1]
+        .vertices
+        .first()
+        .ok_or_else(|| HallrError::NoData("The observer model was empty".to_string()))?;
+    if !observer.x.is_finite() || !observer.y.is_finite() || !observer.z.is_finite() {
+        return Err(HallrError::InvalidInputData(format!(
+            "Only valid coordinates are allowed ({},{},{})",
+            observer.x, observer.y, observer.z
+        )));
+    }
+
+    let mut segments = Vec::<(DVec2, DVec2)>::with_capacity(obstacles.indices.len() / 2 + 4);
+    for edge in obstacles.indices.chunks_exact(2) {
+        let v0 = obstacles.vertices[edge[0]];
+        let v1 = obstacles.vertices[edge[1]];
+        if !v0.x.is_finite() || !v0.y.is_finite() || !v1.x.is_finite() || !v1.y.is_finite() {
+            return Err(HallrError::InvalidInputData(
+                "Only valid coordinates are allowed".to_string(),
+            ));
+        }
+        if v0.x == v1.x && v0.y == v1.y {
+            // zero length segment
+            continue;
+        }
+        segments.push((
+            dvec2(v0.x as f64, v0.y as f64),
+            dvec2(v1.x as f64, v1.y as f64),
+        ));
+    }
+    debug!(
+        "visibility_polygon_2d: {} obstacle segments, observer:{:?}",
+        segments.len(),
+        observer
+    );
+
+    let polygon = visibility_polygon(dvec2(observer.x as f64, observer.y as f64), segments)?;
+
+    let mut output_model = OwnedModel::with_capacity(polygon.len(), polygon.len() + 1);
+    for p in polygon {
+        output_model.push(FFIVector3::new(p.x as f32, p.y as f32, observer.z));
+    }
+    output_model.close_loop();
+
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert("mesh.format".to_string(), "line_windows".to_string());
+    info!(
+        "visibility_polygon_2d operation returning {} vertices",
+        output_model.vertices.len()
+    );
+    Ok((
+        output_model.vertices,
+        output_model.indices,
+        obstacles.world_orientation.to_vec(),
+        return_config,
+    ))
+}
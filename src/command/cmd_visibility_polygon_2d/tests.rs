@@ -0,0 +1,71 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{ConfigType, OwnedModel},
+    HallrError,
+};
+
+#[test]
+fn test_visibility_polygon_2d_1() -> Result<(), HallrError> {
+    // the observer is inside a closed square, the visibility polygon is the square itself
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "visibility_polygon_2d".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.0, -1.0, 0.0).into(),
+            (1.0, -1.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (-1.0, 1.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 0],
+    };
+    let owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(0.0, 0.0, 0.5).into()],
+        indices: vec![],
+    };
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(4, result.0.len()); // vertices
+    assert_eq!(5, result.1.len()); // indices
+    assert!(result.0.iter().all(|v| v.z == 0.5));
+    Ok(())
+}
+
+#[test]
+fn test_visibility_polygon_2d_2() -> Result<(), HallrError> {
+    // a square room with a wall segment casting a shadow
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "visibility_polygon_2d".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-2.0, -2.0, 0.0).into(),
+            (2.0, -2.0, 0.0).into(),
+            (2.0, 2.0, 0.0).into(),
+            (-2.0, 2.0, 0.0).into(),
+            (-1.0, 1.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 0, 4, 5],
+    };
+    let owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(0.0, 0.0, 0.0).into()],
+        indices: vec![],
+    };
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(6, result.0.len()); // vertices
+    assert_eq!(7, result.1.len()); // indices
+    Ok(())
+}
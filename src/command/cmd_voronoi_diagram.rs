@@ -3,9 +3,13 @@
 // This file is part of the hallr crate.
 
 use crate::{
-    command::{ConfigType, Model, Options, OwnedModel},
+    command::{cmd_clip_curves, ConfigType, Model, Options, OwnedModel},
     ffi::FFIVector3,
-    utils::{voronoi_utils, GrowingVob},
+    utils::{
+        self,
+        voronoi_utils::{self, IntegerMapping, VoronoiSnapping},
+        GrowingVob,
+    },
     HallrError,
 };
 use boostvoronoi as BV;
@@ -14,23 +18,234 @@ use hronn::prelude::ConvertTo;
 use linestring::{linestring_2d::Aabb2, linestring_3d::Plane};
 use vector_traits::{
     approx::{AbsDiffEq, UlpsEq},
-    glam::Vec3A,
+    glam::{dvec2, vec2, DVec2, Vec2, Vec3A},
     num_traits::AsPrimitive,
     GenericVector2, GenericVector3, HasXY, HasXYZ,
 };
 #[cfg(test)]
 mod tests;
 
+/// The λ/θ pruning of the medial axis edges. Every voronoi edge is tested at its mid point, against
+/// the closest points of the input features (the feet).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct MedialAxisPruning {
+    /// Edges with feet closer together than twice this distance are removed (λ)
+    pub(crate) min_clearance: f64,
+    /// Edges with feet seen at a smaller angle than this (radians) are removed (θ)
+    pub(crate) min_angle: f64,
+}
+
+impl MedialAxisPruning {
+    fn is_active(&self) -> bool {
+        self.min_clearance > 0.0 || self.min_angle > 0.0
+    }
+}
+
+/// Returns the point of the segment `a`-`b` closest to `p`
+fn closest_point(p: DVec2, a: DVec2, b: DVec2) -> DVec2 {
+    let ab = b - a;
+    let length_squared = ab.length_squared();
+    if length_squared <= 0.0 {
+        return a;
+    }
+    a + ab * ((p - a).dot(ab) / length_squared).clamp(0.0, 1.0)
+}
+
+/// Returns the separation of the point `p`: half of the largest distance between its feet, and the
+/// largest angle (radians) between them as seen from `p`. The feet are the closest points of the
+/// input `sites`, sites within `tolerance` of the closest one are counted as equally close.
+/// A point with a single foot has no separation at all.
+pub(crate) fn separation(p: DVec2, sites: &[(DVec2, DVec2)], tolerance: f64) -> (f64, f64) {
+    let feet: Vec<DVec2> = sites
+        .iter()
+        .map(|(a, b)| closest_point(p, *a, *b))
+        .collect();
+    let clearance = feet.iter().map(|f| f.distance(p)).fold(f64::MAX, f64::min);
+    let feet: Vec<DVec2> = feet
+        .into_iter()
+        .filter(|f| f.distance(p) <= clearance + tolerance)
+        .collect();
+    let mut rv = (0.0_f64, 0.0_f64);
+    for (i, f0) in feet.iter().enumerate() {
+        for f1 in feet[i + 1..].iter() {
+            let (v0, v1) = (*f0 - p, *f1 - p);
+            rv.0 = rv.0.max(0.5 * f0.distance(*f1));
+            rv.1 = rv.1.max(v0.perp_dot(v1).atan2(v0.dot(v1)).abs());
+        }
+    }
+    rv
+}
+
+/// Removes the vertices not used by any of the edges, and renumbers the indices
+fn remove_unused_vertices(vertices: &mut Vec<Vec3A>, indices: &mut [usize]) {
+    let mut remap = vec![usize::MAX; vertices.len()];
+    let mut kept_vertices = Vec::<Vec3A>::new();
+    for index in indices.iter_mut() {
+        if remap[*index] == usize::MAX {
+            remap[*index] = kept_vertices.len();
+            kept_vertices.push(vertices[*index]);
+        }
+        *index = remap[*index];
+    }
+    *vertices = kept_vertices;
+}
+
+/// Removes the edges (in line_chunks format) failing the `pruning` test, and the vertices no longer
+/// in use. Only the first `tested_indices` indices are tested, the rest are kept as they are.
+/// Returns the number of removed edges.
+fn prune_medial_axis(
+    vertices: &mut Vec<Vec3A>,
+    indices: &mut Vec<usize>,
+    tested_indices: usize,
+    sites: &[(DVec2, DVec2)],
+    pruning: MedialAxisPruning,
+    tolerance: f64,
+) -> usize {
+    let edge_count = indices.len() / 2;
+    *indices = indices
+        .chunks_exact(2)
+        .enumerate()
+        .filter(|(i, edge)| {
+            if i * 2 >= tested_indices {
+                return true;
+            }
+            let (v0, v1) = (vertices[edge[0]], vertices[edge[1]]);
+            let mid_point = dvec2(
+                (v0.x as f64 + v1.x as f64) * 0.5,
+                (v0.y as f64 + v1.y as f64) * 0.5,
+            );
+            let (clearance, angle) = separation(mid_point, sites, tolerance);
+            clearance >= pruning.min_clearance && angle >= pruning.min_angle
+        })
+        .flat_map(|(_, edge)| [edge[0], edge[1]])
+        .collect();
+    remove_unused_vertices(vertices, indices);
+    edge_count - indices.len() / 2
+}
+
+/// Converts the infinite primary edges of the diagram into finite edges, long enough to reach
+/// `reach` (in voronoi coordinates) beyond `center`. An edge starting at a voronoi vertex becomes a
+/// ray from that vertex, an edge without any vertex becomes a line centered between its two sites.
+/// The edges are added to `edge_map`, just like the edges converted by `convert_edges()`.
+fn convert_infinite_edges(
+    helper: &voronoi_utils::DiagramHelperRo<Vec3A>,
+    dhrw: &mut voronoi_utils::DiagramHelperRw<Vec3A>,
+    edge_map: &mut ahash::AHashMap<usize, Vec<usize>>,
+    center: Vec2,
+    reach: f32,
+) -> Result<(), HallrError> {
+    let to_vec2 = |p: BV::Point<i64>| vec2(p.x as f32, p.y as f32);
+    for edge in helper.diagram.edges() {
+        let edge = edge.get();
+        let edge_id = edge.id();
+        if !edge.is_primary() || !helper.diagram.edge_is_infinite(edge_id)? {
+            continue;
+        }
+        let twin_id = edge.twin()?;
+        let vertex0 = match (edge.vertex0(), helper.diagram.edge_get_vertex1(edge_id)?) {
+            (Some(vertex0), None) => Some(vertex0),
+            (None, None) if edge_id.0 < twin_id.0 => None,
+            // the twin of a ray, or the other half of a line
+            _ => continue,
+        };
+        let cell_id = edge.cell()?;
+        let twin_cell_id = helper.diagram.edge_get_cell(twin_id)?;
+        let cell_contains_point = helper.diagram.get_cell(cell_id)?.get().contains_point();
+        let twin_cell_contains_point = helper
+            .diagram
+            .get_cell(twin_cell_id)?
+            .get()
+            .contains_point();
+        // the direction is chosen like in the clip_infinite_edge() example of boost voronoi
+        let (origin, direction) = if cell_contains_point && twin_cell_contains_point {
+            let p1 = to_vec2(helper.retrieve_point(cell_id)?);
+            let p2 = to_vec2(helper.retrieve_point(twin_cell_id)?);
+            ((p1 + p2) * 0.5, vec2(p1.y - p2.y, p2.x - p1.x))
+        } else if cell_contains_point || twin_cell_contains_point {
+            let (point_cell_id, segment_cell_id) = if cell_contains_point {
+                (cell_id, twin_cell_id)
+            } else {
+                (twin_cell_id, cell_id)
+            };
+            let point = helper.retrieve_point(point_cell_id)?;
+            let segment = helper.retrieve_segment(segment_cell_id)?;
+            let d = to_vec2(segment.end) - to_vec2(segment.start);
+            let origin_is_start = segment.start.x == point.x && segment.start.y == point.y;
+            let direction = if origin_is_start ^ cell_contains_point {
+                vec2(d.y, -d.x)
+            } else {
+                vec2(-d.y, d.x)
+            };
+            (to_vec2(point), direction)
+        } else {
+            // infinite edges can not be created by two segment sites
+            continue;
+        };
+        let direction = direction.normalize_or_zero();
+        if direction == Vec2::ZERO {
+            continue;
+        }
+        let start = if let Some(vertex0) = vertex0 {
+            let vertex0 = helper.diagram.vertex_get(vertex0)?.get();
+            vec2(vertex0.x(), vertex0.y())
+        } else {
+            origin - direction * (origin.distance(center) + reach)
+        };
+        let end = start + direction * (start.distance(center) + reach);
+        let v0 = dhrw.place_new_vertex_dup_check(Vec3A::new(start.x, start.y, 0.0))?;
+        let v1 = dhrw.place_new_vertex_dup_check(Vec3A::new(end.x, end.y, 0.0))?;
+        let _ = edge_map.insert(edge_id.0, vec![v0, v1]);
+    }
+    Ok(())
+}
+
+/// Clips the first `clipped_indices` indices (edges in line_chunks format) against the closed
+/// `region`. The parts outside of the region are removed, and the edges crossing its outline are
+/// trimmed at the crossing. The rest of the indices are kept as they are.
+fn clip_to_region(
+    vertices: &mut Vec<Vec3A>,
+    indices: &mut Vec<usize>,
+    clipped_indices: usize,
+    region: &[(DVec2, DVec2)],
+) {
+    let mut clipped = Vec::<usize>::with_capacity(indices.len());
+    for edge in indices[..clipped_indices].chunks_exact(2) {
+        let (v0, v1) = (vertices[edge[0]], vertices[edge[1]]);
+        let ranges = cmd_clip_curves::clip_segment(
+            dvec2(v0.x as f64, v0.y as f64),
+            dvec2(v1.x as f64, v1.y as f64),
+            region,
+            true,
+        );
+        for (t0, t1) in ranges {
+            for (t, index) in [(t0, edge[0]), (t1, edge[1])] {
+                if t <= 0.0 || t >= 1.0 {
+                    clipped.push(index);
+                } else {
+                    clipped.push(vertices.len());
+                    vertices.push(v0.lerp(v1, t as f32));
+                }
+            }
+        }
+    }
+    clipped.extend_from_slice(&indices[clipped_indices..]);
+    remove_unused_vertices(vertices, &mut clipped);
+    *indices = clipped;
+}
+
 #[allow(clippy::type_complexity)]
 fn parse_input<T: GenericVector3 + HasMatrix4>(
     input_model: &Model<'_>,
     cmd_arg_max_voronoi_dimension: T::Scalar,
+    snapping: VoronoiSnapping,
 ) -> Result<
     (
         Vec<BV::Point<i64>>,
         Vec<BV::Line<i64>>,
         Aabb2<T::Vector2>,
         T::Matrix4Type,
+        voronoi_utils::DegenerateInputCount,
+        IntegerMapping,
     ),
     HallrError,
 >
@@ -63,25 +278,25 @@ where
         "Could not calculate inverse matrix".to_string(),
     ))?;
 
-    println!(
+    debug!(
         "cmd_voronoi_diagram: data was in plane:{:?} aabb:{:?}",
         plane, aabb
     );
 
     //println!("input Lines:{:?}", input_model.vertices);
 
+    // boost voronoi only accepts integers as coordinates
+    let mapping = IntegerMapping::new::<T>(snapping, &transform);
     let mut vor_lines = Vec::<BV::Line<i64>>::with_capacity(input_model.indices.len() / 2);
     let vor_vertices: Vec<BV::Point<i64>> = input_model
         .vertices
         .iter()
         .map(|vertex| {
-            let p = transform
-                .transform_point3(T::new_3d(vertex.x.into(), vertex.y.into(), vertex.z.into()))
-                .to_2d();
-            BV::Point {
-                x: p.x().as_(),
-                y: p.y().as_(),
-            }
+            mapping.point(
+                transform
+                    .transform_point3(T::new_3d(vertex.x.into(), vertex.y.into(), vertex.z.into()))
+                    .to_2d(),
+            )
         })
         .collect();
     let mut used_vertices = vob::Vob::<u32>::fill_with_false(vor_vertices.len());
@@ -104,19 +319,44 @@ where
         .filter(|x| !used_vertices[x.0])
         .map(|x| x.1)
         .collect();
-    Ok((vor_vertices, vor_lines, vor_aabb, inverse_transform))
+    let (vor_vertices, vor_lines, degenerate_count) =
+        voronoi_utils::remove_degenerate_input(vor_vertices, vor_lines);
+    Ok((
+        vor_vertices,
+        vor_lines,
+        vor_aabb,
+        inverse_transform,
+        degenerate_count,
+        mapping,
+    ))
 }
 
 /// Runs boost cmd_voronoi_diagram over the input and generates to output model.
-/// Removes the external edges as we can't handle infinite length edges in blender.
+/// Removes the external edges as we can't handle infinite length edges in blender, unless there
+/// is a `clip_boundary`: then the infinite edges are converted into long finite edges and every
+/// voronoi edge is clipped against the boundary.
+/// The voronoi edges failing the `pruning` test are removed, the number of them is returned.
+#[allow(clippy::type_complexity)]
 pub(crate) fn compute_voronoi_diagram(
     input_model: &Model<'_>,
     cmd_arg_max_voronoi_dimension: f32,
     cmd_discretization_distance: f32,
     cmd_arg_keep_input: bool,
-) -> Result<(Vec<Vec3A>, Vec<usize>), HallrError> {
-    let (vor_vertices, vor_lines, vor_aabb2, inverted_transform) =
-        parse_input::<Vec3A>(input_model, cmd_arg_max_voronoi_dimension)?;
+    pruning: MedialAxisPruning,
+    clip_boundary: Option<&[(DVec2, DVec2)]>,
+    snapping: VoronoiSnapping,
+) -> Result<
+    (
+        Vec<Vec3A>,
+        Vec<usize>,
+        voronoi_utils::DegenerateInputCount,
+        usize,
+        IntegerMapping,
+    ),
+    HallrError,
+> {
+    let (vor_vertices, vor_lines, vor_aabb2, inverted_transform, degenerate_count, mapping) =
+        parse_input::<Vec3A>(input_model, cmd_arg_max_voronoi_dimension, snapping)?;
     let vor_diagram = {
         BV::Builder::<i64, f32>::default()
             .with_vertices(vor_vertices.iter())?
@@ -130,7 +370,19 @@ pub(crate) fn compute_voronoi_diagram(
         cmd_discretization_distance * max_dist.magnitude() / 100.0
     };
 
-    let reject_edges = voronoi_utils::reject_external_edges::<Vec3A>(&vor_diagram)?;
+    let reject_edges = if clip_boundary.is_some() {
+        // only the infinite edges are rejected, they are converted separately
+        let mut reject_edges = vob::Vob::<u32>::fill_with_false(vor_diagram.edges().len());
+        for edge in vor_diagram.edges() {
+            let edge_id = edge.get().id();
+            if vor_diagram.edge_is_infinite(edge_id)? {
+                let _ = reject_edges.set(edge_id.0, true);
+            }
+        }
+        reject_edges
+    } else {
+        voronoi_utils::reject_external_edges::<Vec3A>(&vor_diagram)?
+    };
     let internal_vertices =
         voronoi_utils::find_internal_vertices::<Vec3A>(&vor_diagram, &reject_edges)?;
     let diagram_helper = voronoi_utils::DiagramHelperRo::<Vec3A> {
@@ -142,13 +394,104 @@ pub(crate) fn compute_voronoi_diagram(
         inverted_transform,
     };
 
-    let (dhrw, mod_edges) = diagram_helper.convert_edges(discretization_distance)?;
-    let (indices, vertices) =
+    let (mut dhrw, mut mod_edges) = diagram_helper.convert_edges(discretization_distance)?;
+    if let Some(clip_boundary) = clip_boundary {
+        // the infinite edges must reach past the boundary, from anywhere in the diagram
+        let (low, high) = input_model
+            .vertices
+            .iter()
+            .map(|v| dvec2(v.x as f64, v.y as f64))
+            .fold((DVec2::MAX, DVec2::MIN), |(low, high), p| {
+                (low.min(p), high.max(p))
+            });
+        let (boundary_low, boundary_high) = clip_boundary
+            .iter()
+            .fold((low, high), |(low, high), (a, b)| {
+                (low.min(a.min(*b)), high.max(a.max(*b)))
+            });
+        let vor_low = vor_aabb2.low().unwrap();
+        let vor_high = vor_aabb2.high().unwrap();
+        let scale = if high.distance(low) > 0.0 {
+            (vor_high - vor_low).length() as f64 / high.distance(low)
+        } else {
+            1.0
+        };
+        convert_infinite_edges(
+            &diagram_helper,
+            &mut dhrw,
+            &mut mod_edges,
+            (vor_low + vor_high) * 0.5,
+            (boundary_high.distance(boundary_low) * scale) as f32,
+        )?;
+    }
+    let (mut indices, mut vertices) =
         diagram_helper.generate_voronoi_edges_from_cells(dhrw, mod_edges, cmd_arg_keep_input)?;
-    Ok((vertices, indices))
+    // the input segments are appended after the voronoi edges
+    let mut voronoi_indices = if cmd_arg_keep_input {
+        indices.len() - 2 * diagram_helper.segments.len()
+    } else {
+        indices.len()
+    };
+    if !pruning.is_active() {
+        if let Some(clip_boundary) = clip_boundary {
+            clip_to_region(&mut vertices, &mut indices, voronoi_indices, clip_boundary);
+        }
+        return Ok((vertices, indices, degenerate_count, 0, mapping));
+    }
+    let points: Vec<DVec2> = input_model
+        .vertices
+        .iter()
+        .map(|v| dvec2(v.x as f64, v.y as f64))
+        .collect();
+    let mut used_points = vec![false; points.len()];
+    let mut sites: Vec<(DVec2, DVec2)> = input_model
+        .indices
+        .chunks_exact(2)
+        .map(|edge| {
+            used_points[edge[0]] = true;
+            used_points[edge[1]] = true;
+            (points[edge[0]], points[edge[1]])
+        })
+        .collect();
+    sites.extend(
+        points
+            .iter()
+            .zip(used_points)
+            .filter(|(_, used)| !used)
+            .map(|(p, _)| (*p, *p)),
+    );
+    // the voronoi edges are discretized, and the input is snapped to an integer grid
+    let (low, high) = points
+        .iter()
+        .fold((DVec2::MAX, DVec2::MIN), |(low, high), p| {
+            (low.min(*p), high.max(*p))
+        });
+    let tolerance = high.distance(low) * (2.0 * cmd_discretization_distance as f64 / 100.0 + 1e-4);
+    let pruned = prune_medial_axis(
+        &mut vertices,
+        &mut indices,
+        voronoi_indices,
+        &sites,
+        pruning,
+        tolerance,
+    );
+    voronoi_indices -= 2 * pruned;
+    if let Some(clip_boundary) = clip_boundary {
+        clip_to_region(&mut vertices, &mut indices, voronoi_indices, clip_boundary);
+    }
+    Ok((vertices, indices, degenerate_count, pruned, mapping))
 }
 
-/// Run the voronoi_mesh command
+/// Run the voronoi_diagram command
+/// The medial axis edges can be pruned with `MEDIAL_AXIS_MIN_CLEARANCE` (λ, in model units) and
+/// `MEDIAL_AXIS_MIN_ANGLE` (θ, in degrees): edges whose closest input features are closer together
+/// than twice the clearance, or are seen at a smaller angle, are removed. Both default to 0 (no
+/// pruning). The number of removed edges is returned as `MEDIAL_AXIS_PRUNED`.
+/// With `CLIP_BOUNDARY` the external edges are kept, and the diagram is clipped against model 1:
+/// a closed polygon in the line_chunks format, in the same coordinate system as model 0. The
+/// edges crossing the boundary are trimmed at the crossing.
+/// The input is mapped to integer coordinates as set by `VORONOI_SNAPPING` (see
+/// `VoronoiSnapping`), the effective resolution is returned as `VORONOI_GRID_RESOLUTION`.
 pub(crate) fn process_command(
     config: ConfigType,
     models: Vec<Model<'_>>,
@@ -161,7 +504,15 @@ pub(crate) fn process_command(
         ));
     }
 
-    if models.len() > 1 {
+    let cmd_arg_clip_boundary =
+        config.get_mandatory_parsed_option::<bool>("CLIP_BOUNDARY", Some(false))?;
+    if cmd_arg_clip_boundary && models.len() != 2 {
+        return Err(HallrError::InvalidInputData(
+            "CLIP_BOUNDARY requires two input models: the input and the boundary polygon"
+                .to_string(),
+        ));
+    }
+    if !cmd_arg_clip_boundary && models.len() > 1 {
         return Err(HallrError::InvalidInputData(
             "This operation only supports one model as input".to_string(),
         ));
@@ -197,13 +548,33 @@ pub(crate) fn process_command(
     }
 
     let cmd_arg_keep_input = config.get_parsed_option("KEEP_INPUT")?.unwrap_or(false);
+    let pruning = MedialAxisPruning {
+        min_clearance: config
+            .get_mandatory_parsed_option::<f64>("MEDIAL_AXIS_MIN_CLEARANCE", Some(0.0))?,
+        min_angle: config
+            .get_mandatory_parsed_option::<f64>("MEDIAL_AXIS_MIN_ANGLE", Some(0.0))?
+            .to_radians(),
+    };
+    if !pruning.min_clearance.is_finite()
+        || pruning.min_clearance < 0.0
+        || !(0.0..=std::f64::consts::PI).contains(&pruning.min_angle)
+    {
+        return Err(HallrError::InvalidParameter(format!(
+            "MEDIAL_AXIS_MIN_CLEARANCE must be a positive number and MEDIAL_AXIS_MIN_ANGLE must be in [0..180] degrees :({}, {})",
+            pruning.min_clearance,
+            pruning.min_angle.to_degrees()
+        )));
+    }
+    let cmd_arg_local_frame =
+        config.get_mandatory_parsed_option::<bool>(super::LOCAL_FRAME_KEY, Some(false))?;
+    let cmd_arg_snapping = VoronoiSnapping::from_config(&config)?;
 
     // used for simplification and discretization distance
     let max_distance: Scalar =
         cmd_arg_max_voronoi_dimension * cmd_arg_discretization_distance / 100.0;
     // we already tested a_command.models.len()
     let input_model = &models[0];
-    if !input_model.has_identity_orientation() {
+    if !cmd_arg_local_frame && !input_model.has_identity_orientation() {
         return Err(HallrError::InvalidInputData(
             "The cmd_voronoi_diagram mesh operation currently requires identify world orientation"
                 .to_string(),
@@ -211,41 +582,68 @@ pub(crate) fn process_command(
     }
 
     // we already tested that there is only one model
-    println!();
-    println!("cmd_voronoi_mesh got command:");
+    debug!("cmd_voronoi_mesh got command:");
     //println!("model.name:{:?}, ", input_model.name);
-    println!("model.vertices:{:?}", input_model.vertices.len());
-    println!("model.indices:{:?}", input_model.indices.len());
-    println!(
+    debug!("model.vertices:{:?}", input_model.vertices.len());
+    debug!("model.indices:{:?}", input_model.indices.len());
+    debug!(
         "model.world_orientation:{:?}:{}",
         input_model.world_orientation,
         input_model.has_identity_orientation()
     );
-    println!("MAX_VORONOI_DIMENSION:{:?}", cmd_arg_max_voronoi_dimension);
-    println!(
+    debug!("MAX_VORONOI_DIMENSION:{:?}", cmd_arg_max_voronoi_dimension);
+    debug!(
         "VORONOI_DISCRETE_DISTANCE:{:?}%",
         cmd_arg_discretization_distance
     );
-    println!("KEEP_INPUT:{:?}", cmd_arg_keep_input);
-    println!("max_distance:{:?}", max_distance);
+    debug!("KEEP_INPUT:{:?}", cmd_arg_keep_input);
+    debug!("MEDIAL_AXIS_PRUNING:{:?}", pruning);
+    debug!("CLIP_BOUNDARY:{:?}", cmd_arg_clip_boundary);
+    debug!("LOCAL_FRAME:{:?}", cmd_arg_local_frame);
+    debug!("VORONOI_SNAPPING:{:?}", cmd_arg_snapping);
+    debug!("max_distance:{:?}", max_distance);
 
-    println!();
+    // Input data in a plane like z=c is translated into a plane crossing origin, the offset is
+    // restored on the output vertices.
+    let plane_offset = utils::axis_aligned_plane_offset(input_model.vertices);
+    let translated_vertices = utils::translate_vertices(input_model.vertices, plane_offset);
+    let translated_model = Model {
+        world_orientation: input_model.world_orientation,
+        vertices: &translated_vertices,
+        indices: input_model.indices,
+    };
+    let vec3a_offset: Vec3A = plane_offset.into();
+    // the boundary is moved along with the input
+    let clip_boundary = if cmd_arg_clip_boundary {
+        let offset = dvec2(vec3a_offset.x as f64, vec3a_offset.y as f64);
+        Some(
+            cmd_clip_curves::parse_region(&models[1])?
+                .into_iter()
+                .map(|(a, b)| (a - offset, b - offset))
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        None
+    };
 
     // do the actual operation
-    let (vertices, indices) = compute_voronoi_diagram(
-        input_model,
+    let (vertices, indices, degenerate_count, pruned, mapping) = compute_voronoi_diagram(
+        &translated_model,
         cmd_arg_max_voronoi_dimension,
         cmd_arg_discretization_distance,
         cmd_arg_keep_input,
+        pruning,
+        clip_boundary.as_deref(),
+        cmd_arg_snapping,
     )?;
     let output_model = OwnedModel {
-        world_orientation: Model::copy_world_orientation(input_model)?,
+        world_orientation: input_model.output_orientation(cmd_arg_local_frame)?,
         indices,
         vertices: vertices
             .into_iter()
             .map(|mut v: Vec3A| {
                 v.set_z(0.0);
-                v.to()
+                (v + vec3a_offset).to()
             })
             .collect(),
     };
@@ -253,8 +651,14 @@ pub(crate) fn process_command(
     let mut return_config = ConfigType::new();
     let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
     let _ = return_config.insert("REMOVE_DOUBLES".to_string(), "true".to_string());
+    degenerate_count.report("voronoi_diagram", &mut return_config);
+    mapping.report(&mut return_config);
+    let _ = return_config.insert("MEDIAL_AXIS_PRUNED".to_string(), pruned.to_string());
+    if cmd_arg_local_frame {
+        let _ = return_config.insert(super::LOCAL_FRAME_KEY.to_string(), "true".to_string());
+    }
 
-    println!(
+    info!(
         "cmd_voronoi_diagram mesh operation returning {} vertices, {} indices",
         output_model.vertices.len(),
         output_model.indices.len()
@@ -33,3 +33,190 @@ fn test_voronoi_diagram_1() -> Result<(), HallrError> {
     assert_eq!(32, result.1.len()); // indices
     Ok(())
 }
+
+#[test]
+fn test_voronoi_diagram_2() -> Result<(), HallrError> {
+    // same as test_voronoi_diagram_1 but in the z=2 plane
+    let mut config = ConfigType::default();
+    let _ = config.insert("DISTANCE".to_string(), "1.0".to_string());
+    let _ = config.insert("command".to_string(), "voronoi_diagram".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("KEEP_INPUT".to_string(), "false".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (1.203918, 1.203918, 2.0).into(),
+            (-1.805877, 0.74801874, 2.0).into(),
+            (0.0, -1.7025971, 2.0).into(),
+            (-0.36410117, 0.33949375, 2.0).into(),
+            (0.25582898, -0.17708552, 2.0).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 1, 2],
+    };
+
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!(18, result.0.len()); // vertices
+    assert_eq!(32, result.1.len()); // indices
+    assert!(result.0.iter().all(|v| v.z == 2.0));
+    Ok(())
+}
+
+#[test]
+fn test_voronoi_diagram_3() -> Result<(), HallrError> {
+    use vector_traits::glam::dvec2;
+    // the sides of a 4x1 rectangle
+    let corners = [
+        dvec2(0.0, 0.0),
+        dvec2(4.0, 0.0),
+        dvec2(4.0, 1.0),
+        dvec2(0.0, 1.0),
+    ];
+    let sites: Vec<_> = (0..4).map(|i| (corners[i], corners[(i + 1) % 4])).collect();
+    // the center line has two opposite feet
+    let (clearance, angle) = super::separation(dvec2(2.0, 0.5), &sites, 1e-6);
+    assert!((clearance - 0.5).abs() < 1e-9);
+    assert!((angle - std::f64::consts::PI).abs() < 1e-9);
+    // close to a side there is only one foot
+    assert_eq!((0.0, 0.0), super::separation(dvec2(2.0, 0.1), &sites, 1e-6));
+    Ok(())
+}
+
+#[test]
+fn test_voronoi_diagram_4() -> Result<(), HallrError> {
+    let voronoi_config = |clearance: &str| {
+        let mut config = ConfigType::default();
+        let _ = config.insert("DISTANCE".to_string(), "1.0".to_string());
+        let _ = config.insert("command".to_string(), "voronoi_diagram".to_string());
+        let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+        let _ = config.insert("KEEP_INPUT".to_string(), "false".to_string());
+        let _ = config.insert(
+            "MEDIAL_AXIS_MIN_CLEARANCE".to_string(),
+            clearance.to_string(),
+        );
+        config
+    };
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (4.0, 0.0, 0.0).into(),
+            (4.0, 1.0, 0.0).into(),
+            (0.0, 1.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 0],
+    };
+
+    let models = vec![owned_model_0.as_model()];
+    let unpruned = super::process_command(voronoi_config("0.0"), models)?;
+    assert_eq!("0", unpruned.3.get("MEDIAL_AXIS_PRUNED").unwrap());
+    assert!(!unpruned.1.is_empty());
+
+    // no feet are further apart than the diagonal of the rectangle
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(voronoi_config("10.0"), models)?;
+    assert!(result.0.is_empty());
+    assert!(result.1.is_empty());
+    assert_eq!(
+        (unpruned.1.len() / 2).to_string(),
+        *result.3.get("MEDIAL_AXIS_PRUNED").unwrap()
+    );
+
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(voronoi_config("-1.0"), models).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_voronoi_diagram_clip_boundary() -> Result<(), HallrError> {
+    // the input of test_voronoi_diagram_1, clipped against a 6x6 square
+    let mut config = ConfigType::default();
+    let _ = config.insert("DISTANCE".to_string(), "1.0".to_string());
+    let _ = config.insert("command".to_string(), "voronoi_diagram".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("KEEP_INPUT".to_string(), "false".to_string());
+    let _ = config.insert("CLIP_BOUNDARY".to_string(), "true".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (1.203918, 1.203918, 0.0).into(),
+            (-1.805877, 0.74801874, 0.0).into(),
+            (0.0, -1.7025971, 0.0).into(),
+            (-0.36410117, 0.33949375, 0.0).into(),
+            (0.25582898, -0.17708552, 0.0).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 1, 2],
+    };
+    let owned_model_1 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-3.0, -3.0, 0.0).into(),
+            (3.0, -3.0, 0.0).into(),
+            (3.0, 3.0, 0.0).into(),
+            (-3.0, 3.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 1, 2, 2, 3, 3, 0],
+    };
+
+    let models = vec![owned_model_0.as_model(), owned_model_1.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    // the external edges are kept, trimmed at the boundary
+    assert!(result.1.len() > 32, "{}", result.1.len());
+    assert_eq!(0, result.1.len() % 2);
+    assert!(result
+        .0
+        .iter()
+        .all(|v| v.x.abs() <= 3.0 + 1e-4 && v.y.abs() <= 3.0 + 1e-4));
+    assert!(result
+        .0
+        .iter()
+        .any(|v| (v.x.abs() - 3.0).abs() < 1e-4 || (v.y.abs() - 3.0).abs() < 1e-4));
+
+    // the boundary model is mandatory
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_voronoi_diagram_snapping() -> Result<(), HallrError> {
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (1.203918, 1.203918, 0.0).into(),
+            (-1.805877, 0.74801874, 0.0).into(),
+            (0.0, -1.7025971, 0.0).into(),
+            (-0.36410117, 0.33949375, 0.0).into(),
+            (0.25582898, -0.17708552, 0.0).into(),
+        ],
+        indices: vec![0, 1, 2, 0, 1, 2],
+    };
+    let mut config = ConfigType::default();
+    let _ = config.insert("DISTANCE".to_string(), "1.0".to_string());
+    let _ = config.insert("command".to_string(), "voronoi_diagram".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let result = super::process_command(config.clone(), vec![owned_model_0.as_model()])?;
+    assert_eq!("ROUND", result.3.get("VORONOI_SNAPPING").unwrap());
+    let resolution: f64 = result
+        .3
+        .get("VORONOI_GRID_RESOLUTION")
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(resolution > 0.0 && resolution < 1e-4, "{}", resolution);
+
+    let _ = config.insert("VORONOI_SNAPPING".to_string(), "GRID".to_string());
+    let _ = config.insert("VORONOI_SNAP_GRID".to_string(), "0.01".to_string());
+    let result = super::process_command(config, vec![owned_model_0.as_model()])?;
+    assert_eq!("GRID", result.3.get("VORONOI_SNAPPING").unwrap());
+    let resolution: f64 = result
+        .3
+        .get("VORONOI_GRID_RESOLUTION")
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((resolution - 0.01).abs() < 1e-4, "{}", resolution);
+    Ok(())
+}
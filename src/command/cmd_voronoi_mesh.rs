@@ -3,18 +3,24 @@
 // This file is part of the hallr crate.
 
 use crate::{
-    command::{ConfigType, Model, Options, OwnedModel},
+    command::{attributes::Attributes, progress, ConfigType, Model, Options, OwnedModel},
     ffi::FFIVector3,
-    utils::{voronoi_utils, GrowingVob},
+    utils::{
+        self,
+        voronoi_utils::{self, IntegerMapping, VoronoiSnapping},
+        GrowingVob,
+    },
     HallrError,
 };
 use boostvoronoi as BV;
 use centerline::{HasMatrix4, Matrix4};
 use hronn::prelude::ConvertTo;
+use itertools::Itertools;
 use linestring::{linestring_2d::Aabb2, linestring_3d::Plane};
+use std::{fs, io::Write};
 use vector_traits::{
     approx::{AbsDiffEq, UlpsEq},
-    glam::Vec3A,
+    glam::{vec2, Vec2, Vec3A},
     num_traits::AsPrimitive,
     GenericVector2, GenericVector3, HasXY,
 };
@@ -22,16 +28,24 @@ use vector_traits::{
 #[cfg(test)]
 mod tests;
 
+/// The key of the returned distance texture, in the binary attributes
+pub(crate) const DISTANCE_TEXTURE_KEY: &str = "DISTANCE_TEXTURE";
+/// The key of the per face cell id attribute
+pub(crate) const VORONOI_CELL_KEY: &str = "VORONOI_CELL";
+
 #[allow(clippy::type_complexity)]
 fn parse_input<T: GenericVector3 + HasMatrix4>(
     input_model: &Model<'_>,
     cmd_arg_max_voronoi_dimension: T::Scalar,
+    snapping: VoronoiSnapping,
 ) -> Result<
     (
         Vec<BV::Point<i64>>,
         Vec<BV::Line<i64>>,
         Aabb2<T::Vector2>,
         T::Matrix4Type,
+        voronoi_utils::DegenerateInputCount,
+        IntegerMapping,
     ),
     HallrError,
 >
@@ -64,22 +78,22 @@ where
         "Could not calculate inverse matrix".to_string(),
     ))?;
 
-    println!("voronoi: data was in plane:{:?} aabb:{:?}", plane, aabb);
+    debug!("voronoi: data was in plane:{:?} aabb:{:?}", plane, aabb);
 
     //println!("input Lines:{:?}", input_model.vertices);
 
+    // boost voronoi only accepts integers as coordinates
+    let mapping = IntegerMapping::new::<T>(snapping, &transform);
     let mut vor_lines = Vec::<BV::Line<i64>>::with_capacity(input_model.indices.len() / 2);
     let vor_vertices: Vec<BV::Point<i64>> = input_model
         .vertices
         .iter()
         .map(|vertex| {
-            let p = transform
-                .transform_point3(T::new_3d(vertex.x.into(), vertex.y.into(), vertex.z.into()))
-                .to_2d();
-            BV::Point {
-                x: p.x().as_(),
-                y: p.y().as_(),
-            }
+            mapping.point(
+                transform
+                    .transform_point3(T::new_3d(vertex.x.into(), vertex.y.into(), vertex.z.into()))
+                    .to_2d(),
+            )
         })
         .collect();
     let mut used_vertices = vob::Vob::<u32>::fill_with_false(vor_vertices.len());
@@ -102,24 +116,47 @@ where
         .filter(|x| !used_vertices[x.0])
         .map(|x| x.1)
         .collect();
-    Ok((vor_vertices, vor_lines, vor_aabb, inverse_transform))
+    let (vor_vertices, vor_lines, degenerate_count) =
+        voronoi_utils::remove_degenerate_input(vor_vertices, vor_lines);
+    Ok((
+        vor_vertices,
+        vor_lines,
+        vor_aabb,
+        inverse_transform,
+        degenerate_count,
+        mapping,
+    ))
 }
 
 /// Runs boost cmd_voronoi_diagram over the input and generates to output model.
 /// Removes the external edges as we can't handle infinite length edges in blender.
+/// The voronoi cell id of every triangle is returned along with the mesh.
 pub(crate) fn compute_voronoi_mesh(
     input_model: &Model<'_>,
     cmd_arg_max_voronoi_dimension: f32,
     cmd_discretization_distance: f32,
-) -> Result<(Vec<Vec3A>, Vec<usize>), HallrError> {
-    let (vor_vertices, vor_lines, vor_aabb2, inverted_transform) =
-        parse_input::<Vec3A>(input_model, cmd_arg_max_voronoi_dimension)?;
+    snapping: VoronoiSnapping,
+) -> Result<
+    (
+        Vec<Vec3A>,
+        Vec<usize>,
+        voronoi_utils::DegenerateInputCount,
+        IntegerMapping,
+        Vec<usize>,
+    ),
+    HallrError,
+> {
+    let (vor_vertices, vor_lines, vor_aabb2, inverted_transform, degenerate_count, mapping) =
+        parse_input::<Vec3A>(input_model, cmd_arg_max_voronoi_dimension, snapping)?;
+    // the work happens in a few opaque steps, so the progress is reported per step
+    progress::report(10);
     let vor_diagram = {
         BV::Builder::<i64, f32>::default()
             .with_vertices(vor_vertices.iter())?
             .with_segments(vor_lines.iter())?
             .build()?
     };
+    progress::report(40);
 
     let discretization_distance: f32 = {
         let max_dist: <Vec3A as GenericVector3>::Vector2 =
@@ -130,6 +167,7 @@ pub(crate) fn compute_voronoi_mesh(
     let reject_edges = voronoi_utils::reject_external_edges::<Vec3A>(&vor_diagram)?;
     let internal_vertices =
         voronoi_utils::find_internal_vertices::<Vec3A>(&vor_diagram, &reject_edges)?;
+    progress::report(50);
     let diagram_helper = voronoi_utils::DiagramHelperRo::<Vec3A> {
         vertices: vor_vertices,
         segments: vor_lines,
@@ -140,14 +178,127 @@ pub(crate) fn compute_voronoi_mesh(
     };
 
     let (dhrw, mod_edges) = diagram_helper.convert_edges(discretization_distance)?;
-    let (indices, vertices) = diagram_helper.generate_mesh_from_cells(dhrw, mod_edges)?;
-    Ok((vertices, indices))
+    progress::report(80);
+    let (indices, vertices, cell_ids) = diagram_helper.generate_mesh_from_cells(dhrw, mod_edges)?;
+    progress::report(100);
+    Ok((vertices, indices, degenerate_count, mapping, cell_ids))
+}
+
+/// The pixel grid of a distance texture, covering the XY bounds of the input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TextureGrid {
+    pub(crate) low: Vec2,
+    pub(crate) pixel_size: f32,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+}
+
+impl TextureGrid {
+    /// A grid with `resolution` pixels along the longest side of the `low`..`high` bounds
+    pub(crate) fn new(low: Vec2, high: Vec2, resolution: usize) -> Self {
+        let size = high - low;
+        let pixel_size = size.max_element() / resolution as f32;
+        let pixels = |length: f32| ((length / pixel_size).round() as usize).clamp(1, resolution);
+        Self {
+            low,
+            pixel_size,
+            width: pixels(size.x),
+            height: pixels(size.y),
+        }
+    }
+}
+
+/// Rasterize the height (the absolute Z value) of the triangles into the grid. The pixels are in
+/// rows starting at the lower left corner, the pixels outside the mesh are zero.
+pub(crate) fn rasterize_distance(
+    vertices: &[Vec3A],
+    indices: &[usize],
+    grid: &TextureGrid,
+) -> Vec<f32> {
+    let mut pixels = vec![0.0_f32; grid.width * grid.height];
+    // the pixel column (or row) of a coordinate along the axis, clamped to the grid
+    let to_pixel = |value: f32, low: f32, count: usize| -> usize {
+        (((value - low) / grid.pixel_size - 0.5).max(0.0) as usize).min(count - 1)
+    };
+    for triangle in indices.chunks_exact(3) {
+        let p = [
+            vertices[triangle[0]],
+            vertices[triangle[1]],
+            vertices[triangle[2]],
+        ];
+        let (a, b, c) = (p[0].truncate(), p[1].truncate(), p[2].truncate());
+        let area = (b - a).perp_dot(c - a);
+        if area.abs() <= f32::EPSILON {
+            continue;
+        }
+        let low = a.min(b).min(c);
+        let high = a.max(b).max(c);
+        for row in
+            to_pixel(low.y, grid.low.y, grid.height)..=to_pixel(high.y, grid.low.y, grid.height)
+        {
+            for column in
+                to_pixel(low.x, grid.low.x, grid.width)..=to_pixel(high.x, grid.low.x, grid.width)
+            {
+                let center =
+                    grid.low + vec2(column as f32 + 0.5, row as f32 + 0.5) * grid.pixel_size;
+                // the barycentric coordinates of the pixel center
+                let wa = (c - b).perp_dot(center - b) / area;
+                let wb = (a - c).perp_dot(center - c) / area;
+                let wc = 1.0 - wa - wb;
+                if wa >= 0.0 && wb >= 0.0 && wc >= 0.0 {
+                    pixels[row * grid.width + column] =
+                        (wa * p[0].z + wb * p[1].z + wc * p[2].z).abs();
+                }
+            }
+        }
+    }
+    pixels
+}
+
+/// Write the pixels (rows starting at the lower left corner) as a greyscale Radiance HDR image
+pub(crate) fn write_radiance_hdr(
+    path: &str,
+    pixels: &[f32],
+    grid: &TextureGrid,
+) -> Result<(), HallrError> {
+    let mut data = format!(
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        grid.height, grid.width
+    )
+    .into_bytes();
+    // the image is stored from the top row down, without run length encoding
+    for row in pixels.chunks_exact(grid.width).rev() {
+        for value in row {
+            if !value.is_finite() || *value < 1e-32 {
+                data.extend([0, 0, 0, 0]);
+            } else {
+                let exponent = value.log2().floor() as i32 + 1;
+                let mantissa = (value * 256.0 / 2.0_f32.powi(exponent)).min(255.0) as u8;
+                data.extend([mantissa, mantissa, mantissa, (exponent + 128) as u8]);
+            }
+        }
+    }
+    fs::File::create(path)
+        .and_then(|mut file| file.write_all(&data))
+        .map_err(|e| {
+            HallrError::InternalError(format!("Could not write the texture {}: {}", path, e))
+        })
 }
 
 /// Run the voronoi_mesh command
+/// With `DISTANCE_TEXTURE_RESOLUTION` set, the distance-to-sites field (the height of the mesh) is
+/// rasterized into an image of that many pixels along the longest side of the input, instead of
+/// returning the mesh. The image is written to `DISTANCE_TEXTURE_PATH` as a Radiance HDR file, or
+/// else returned as the `DISTANCE_TEXTURE` binary attribute. The input model is then returned
+/// unchanged.
+/// The input is mapped to integer coordinates as set by `VORONOI_SNAPPING` (see
+/// `VoronoiSnapping`), the effective resolution is returned as `VORONOI_GRID_RESOLUTION`.
+/// With `CELL_IDS` the voronoi cell id of every triangle (one cell per input site) is returned as
+/// the `VORONOI_CELL` binary attribute, and the number of meshed cells as `VORONOI_CELLS`.
 pub(crate) fn process_command(
     config: ConfigType,
     models: Vec<Model<'_>>,
+    output_attributes: &mut Attributes,
 ) -> Result<super::CommandResult, HallrError> {
     type Scalar = f32;
 
@@ -171,6 +322,22 @@ pub(crate) fn process_command(
     let cmd_arg_negative_radius = config
         .get_parsed_option::<bool>("NEGATIVE_RADIUS")?
         .unwrap_or(true);
+    let cmd_arg_local_frame =
+        config.get_mandatory_parsed_option::<bool>(super::LOCAL_FRAME_KEY, Some(false))?;
+    let cmd_arg_snapping = VoronoiSnapping::from_config(&config)?;
+    let cmd_arg_cell_ids = config
+        .get_parsed_option::<bool>("CELL_IDS")?
+        .unwrap_or(false);
+    let cmd_arg_texture_resolution =
+        config.get_parsed_option::<usize>("DISTANCE_TEXTURE_RESOLUTION")?;
+    if let Some(resolution) = cmd_arg_texture_resolution {
+        if !(1..=16384).contains(&resolution) {
+            return Err(HallrError::InvalidParameter(format!(
+                "The valid range of DISTANCE_TEXTURE_RESOLUTION is [1..16384] :({})",
+                resolution
+            )));
+        }
+    }
 
     if !(super::DEFAULT_MAX_VORONOI_DIMENSION as i64..100_000_000)
         .contains(&cmd_arg_max_voronoi_dimension.as_())
@@ -201,55 +368,139 @@ pub(crate) fn process_command(
         cmd_arg_max_voronoi_dimension * cmd_arg_discretization_distance / 100.0;
     // we already tested a_command.models.len()
     let input_model = &models[0];
-    if !input_model.has_identity_orientation() {
+    if !cmd_arg_local_frame && !input_model.has_identity_orientation() {
         return Err(HallrError::InvalidInputData(
             "The voronoi mesh operation currently requires identify world orientation".to_string(),
         ));
     }
 
     // we already tested that there is only one model
-    println!();
-    println!("cmd_voronoi_mesh got command:");
+    debug!("cmd_voronoi_mesh got command:");
     //println!("model.name:{:?}, ", input_model.name);
-    println!("model.vertices:{:?}", input_model.vertices.len());
-    println!("model.indices:{:?}", input_model.indices.len());
-    println!(
+    debug!("model.vertices:{:?}", input_model.vertices.len());
+    debug!("model.indices:{:?}", input_model.indices.len());
+    debug!(
         "model.world_orientation:{:?}:{}",
         input_model.world_orientation,
         input_model.has_identity_orientation()
     );
-    println!("MAX_VORONOI_DIMENSION:{:?}", cmd_arg_max_voronoi_dimension);
-    println!(
+    debug!("MAX_VORONOI_DIMENSION:{:?}", cmd_arg_max_voronoi_dimension);
+    debug!(
         "VORONOI_DISCRETE_DISTANCE:{:?}%",
         cmd_arg_discretization_distance
     );
-    println!("max_distance:{:?}", max_distance);
-    println!("NEGATIVE_RADIUS:{:?}", cmd_arg_negative_radius);
-    println!();
+    debug!("max_distance:{:?}", max_distance);
+    debug!("NEGATIVE_RADIUS:{:?}", cmd_arg_negative_radius);
+    debug!("LOCAL_FRAME:{:?}", cmd_arg_local_frame);
+    debug!("VORONOI_SNAPPING:{:?}", cmd_arg_snapping);
+    debug!("CELL_IDS:{:?}", cmd_arg_cell_ids);
+
+    // Input data in a plane like z=c is translated into a plane crossing origin, the offset is
+    // restored on the output vertices.
+    let plane_offset = utils::axis_aligned_plane_offset(input_model.vertices);
+    let translated_vertices = utils::translate_vertices(input_model.vertices, plane_offset);
+    let translated_model = Model {
+        world_orientation: input_model.world_orientation,
+        vertices: &translated_vertices,
+        indices: input_model.indices,
+    };
+    let vec3a_offset: Vec3A = plane_offset.into();
 
     // do the actual operation
-    let (vertices, indices) = compute_voronoi_mesh(
-        input_model,
+    let (vertices, indices, degenerate_count, mapping, cell_ids) = compute_voronoi_mesh(
+        &translated_model,
         cmd_arg_max_voronoi_dimension,
         cmd_arg_discretization_distance,
+        cmd_arg_snapping,
     )?;
+
+    if let Some(resolution) = cmd_arg_texture_resolution {
+        // the texture covers the XY bounds of the input
+        let (low, high) = input_model.vertices.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(low, high), v| (low.min(vec2(v.x, v.y)), high.max(vec2(v.x, v.y))),
+        );
+        if (high - low).max_element() <= 0.0 {
+            return Err(HallrError::InvalidInputData(
+                "The input has no extent, a distance texture can not be created".to_string(),
+            ));
+        }
+        let grid = TextureGrid::new(low, high, resolution);
+        let translated: Vec<Vec3A> = vertices
+            .iter()
+            .map(|v| Vec3A::new(v.x + vec3a_offset.x, v.y + vec3a_offset.y, v.z))
+            .collect();
+        let pixels = rasterize_distance(&translated, &indices, &grid);
+        let mut return_config = ConfigType::new();
+        let _ = return_config.insert(
+            "mesh.format".to_string(),
+            config.get_mandatory_option("mesh.format")?.to_string(),
+        );
+        let _ = return_config.insert("DISTANCE_TEXTURE_WIDTH".to_string(), grid.width.to_string());
+        let _ = return_config.insert(
+            "DISTANCE_TEXTURE_HEIGHT".to_string(),
+            grid.height.to_string(),
+        );
+        let _ = return_config.insert(
+            "DISTANCE_TEXTURE_BOUNDS".to_string(),
+            format!(
+                "{},{},{},{}",
+                grid.low.x,
+                grid.low.y,
+                grid.low.x + grid.width as f32 * grid.pixel_size,
+                grid.low.y + grid.height as f32 * grid.pixel_size
+            ),
+        );
+        degenerate_count.report("voronoi_mesh", &mut return_config);
+        mapping.report(&mut return_config);
+        if let Some(path) = config.get_parsed_option::<String>("DISTANCE_TEXTURE_PATH")? {
+            write_radiance_hdr(&path, &pixels, &grid)?;
+            let _ = return_config.insert("DISTANCE_TEXTURE_PATH".to_string(), path);
+        } else {
+            let _ = output_attributes.insert(DISTANCE_TEXTURE_KEY.to_string(), pixels);
+        }
+        info!(
+            "voronoi mesh operation returning a {}x{} distance texture",
+            grid.width, grid.height
+        );
+        return Ok((
+            input_model.vertices.to_vec(),
+            input_model.indices.to_vec(),
+            input_model.world_orientation.to_vec(),
+            return_config,
+        ));
+    }
     let output_model = OwnedModel {
-        world_orientation: Model::copy_world_orientation(input_model)?,
+        world_orientation: input_model.output_orientation(cmd_arg_local_frame)?,
         indices,
         vertices: if cmd_arg_negative_radius {
             // radius is interpreted as a negative Z value by default
-            vertices.into_iter().map(|v: Vec3A| v.to()).collect()
+            vertices
+                .into_iter()
+                .map(|v: Vec3A| (v + vec3a_offset).to())
+                .collect()
         } else {
             vertices
                 .into_iter()
-                .map(|v: Vec3A| Vec3A::new(v.x, v.y, v.z.abs()).to())
+                .map(|v: Vec3A| (Vec3A::new(v.x, v.y, v.z.abs()) + vec3a_offset).to())
                 .collect()
         },
     };
 
     let mut return_config = ConfigType::new();
     let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
-    println!(
+    degenerate_count.report("voronoi_mesh", &mut return_config);
+    mapping.report(&mut return_config);
+    if cmd_arg_cell_ids {
+        let cell_ids: Vec<f32> = cell_ids.into_iter().map(|id| id as f32).collect();
+        let cell_count = cell_ids.iter().copied().dedup().count();
+        let _ = return_config.insert("VORONOI_CELLS".to_string(), cell_count.to_string());
+        let _ = output_attributes.insert(VORONOI_CELL_KEY.to_string(), cell_ids);
+    }
+    if cmd_arg_local_frame {
+        let _ = return_config.insert(super::LOCAL_FRAME_KEY.to_string(), "true".to_string());
+    }
+    info!(
         "voronoi mesh operation returning {} vertices, {} indices",
         output_model.vertices.len(),
         output_model.indices.len()
@@ -3,7 +3,7 @@
 // This file is part of the hallr crate.
 
 use crate::{
-    command::{ConfigType, OwnedModel},
+    command::{attributes::Attributes, ConfigType, OwnedModel},
     HallrError,
 };
 
@@ -27,7 +27,7 @@ fn test_voronoi_mesh_1() -> Result<(), HallrError> {
     };
 
     let models = vec![owned_model_0.as_model()];
-    let result = super::process_command(config, models)?;
+    let result = super::process_command(config, models, &mut Attributes::new())?;
     assert_eq!(5, result.0.len()); // vertices
     assert_eq!(12, result.1.len()); // indices
     assert_eq!("triangulated", result.3.get("mesh.format").unwrap());
@@ -55,12 +55,52 @@ fn test_voronoi_mesh_2() -> Result<(), HallrError> {
     };
 
     let models = vec![owned_model_0.as_model()];
-    let result = super::process_command(config, models)?;
+    let result = super::process_command(config, models, &mut Attributes::new())?;
     assert_eq!(10, result.0.len()); // vertices
     assert_eq!(27, result.1.len()); // indices
     Ok(())
 }
 
+#[test]
+fn test_voronoi_mesh_z_offset() -> Result<(), HallrError> {
+    // same as test_voronoi_mesh_2 but also in the z=2 plane, the output must be the same, only
+    // offset by z=2
+    let run = |z: f32| {
+        let mut config = ConfigType::default();
+        let _ = config.insert("DISTANCE".to_string(), "0.2864788911621093".to_string());
+        let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+        let _ = config.insert("command".to_string(), "voronoi_mesh".to_string());
+
+        let owned_model_0 = OwnedModel {
+            world_orientation: OwnedModel::identity_matrix(),
+            vertices: vec![
+                (-1.3491066, -0.42415974, z).into(),
+                (0.42415974, -1.3491066, z).into(),
+                (-0.420259, 1.3558924, z).into(),
+                (1.3491066, 0.42415974, z).into(),
+                (1.3491066, 0.42415974, z).into(),
+                (1.1850299, 1.4086196, z).into(),
+            ],
+            indices: vec![2, 0, 0, 1, 1, 3, 3, 2, 3, 4, 4, 5, 5, 2],
+        };
+        super::process_command(
+            config,
+            vec![owned_model_0.as_model()],
+            &mut Attributes::new(),
+        )
+    };
+    let result_0 = run(0.0)?;
+    let result_2 = run(2.0)?;
+    assert_eq!(10, result_2.0.len()); // vertices
+    assert_eq!(result_0.1, result_2.1);
+    for (v0, v2) in result_0.0.iter().zip(result_2.0.iter()) {
+        assert!((v0.x - v2.x).abs() < 1e-5, "{:?} {:?}", v0, v2);
+        assert!((v0.y - v2.y).abs() < 1e-5, "{:?} {:?}", v0, v2);
+        assert!((v0.z + 2.0 - v2.z).abs() < 1e-5, "{:?} {:?}", v0, v2);
+    }
+    Ok(())
+}
+
 #[test]
 fn test_voronoi_mesh_3() -> Result<(), HallrError> {
     let mut config = ConfigType::default();
@@ -82,7 +122,7 @@ fn test_voronoi_mesh_3() -> Result<(), HallrError> {
     };
 
     let models = vec![owned_model_0.as_model()];
-    let result = super::process_command(config, models)?;
+    let result = super::process_command(config, models, &mut Attributes::new())?;
     assert_eq!(21, result.0.len()); // vertices
     assert_eq!(96, result.1.len()); // indices
     Ok(())
@@ -108,8 +148,136 @@ fn test_voronoi_mesh4() -> Result<(), HallrError> {
     };
 
     let models = vec![owned_model_0.as_model()];
-    let result = super::process_command(config, models)?;
+    let result = super::process_command(config, models, &mut Attributes::new())?;
     assert_eq!(20, result.0.len()); // vertices
     assert_eq!(87, result.1.len()); // indices
     Ok(())
 }
+
+#[test]
+fn test_voronoi_mesh_local_frame() -> Result<(), HallrError> {
+    // a translated model is only accepted in the local frame, the matrix is passed on
+    let mut world_orientation = OwnedModel::identity_matrix();
+    world_orientation[3] = 5.0;
+    let owned_model_0 = OwnedModel {
+        world_orientation,
+        vertices: vec![
+            (-1.3491066, -0.42415974, 0.0).into(),
+            (0.42415974, -1.3491066, 0.0).into(),
+            (-0.42415974, 1.3491066, 0.0).into(),
+            (1.3491066, 0.42415974, 0.0).into(),
+        ],
+        indices: vec![2, 0, 0, 1, 1, 3, 3, 2],
+    };
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "voronoi_mesh".to_string());
+    let _ = config.insert("DISTANCE".to_string(), "0.2864788911621093".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    assert!(super::process_command(
+        config.clone(),
+        vec![owned_model_0.as_model()],
+        &mut Attributes::new()
+    )
+    .is_err());
+
+    let _ = config.insert("LOCAL_FRAME".to_string(), "true".to_string());
+    let result = super::process_command(
+        config,
+        vec![owned_model_0.as_model()],
+        &mut Attributes::new(),
+    )?;
+    assert_eq!(5, result.0.len()); // vertices
+    assert_eq!(world_orientation.to_vec(), result.2);
+    assert_eq!("true", result.3.get("LOCAL_FRAME").unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_voronoi_mesh_distance_texture() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "voronoi_mesh".to_string());
+    let _ = config.insert("DISTANCE".to_string(), "0.2864788911621093".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("DISTANCE_TEXTURE_RESOLUTION".to_string(), "32".to_string());
+
+    // a square with sides of length 2, rotated around the origin
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.3491066, -0.42415974, 0.0).into(),
+            (0.42415974, -1.3491066, 0.0).into(),
+            (-0.42415974, 1.3491066, 0.0).into(),
+            (1.3491066, 0.42415974, 0.0).into(),
+        ],
+        indices: vec![2, 0, 0, 1, 1, 3, 3, 2],
+    };
+    let models = vec![owned_model_0.as_model()];
+    let mut output_attributes = Attributes::new();
+    let result = super::process_command(config, models, &mut output_attributes)?;
+    // the input is returned as it is
+    assert_eq!(4, result.0.len());
+    assert_eq!(8, result.1.len());
+    assert_eq!("32", result.3.get("DISTANCE_TEXTURE_WIDTH").unwrap());
+    assert_eq!("32", result.3.get("DISTANCE_TEXTURE_HEIGHT").unwrap());
+    let texture = output_attributes.get(super::DISTANCE_TEXTURE_KEY).unwrap();
+    assert_eq!(32 * 32, texture.len());
+    // the corners of the image are outside the square
+    assert_eq!(0.0, texture[0]);
+    // the center of the square is 1.0 away from the sides
+    let center = texture[16 * 32 + 16];
+    assert!(center > 0.9 && center <= 1.0, "{}", center);
+    assert!(texture.iter().all(|d| (0.0..=1.0 + 1e-5).contains(d)));
+    Ok(())
+}
+
+#[test]
+fn test_voronoi_mesh_write_hdr() -> Result<(), HallrError> {
+    let grid = super::TextureGrid::new((0.0, 0.0).into(), (2.0, 1.0).into(), 4);
+    assert_eq!((4, 2), (grid.width, grid.height));
+    let pixels = vec![0.0, 0.5, 1.0, 2.0, 3.0, 0.25, 0.125, 0.0];
+    let path = std::env::temp_dir().join(format!("hallr_texture_test_{}.hdr", std::process::id()));
+    super::write_radiance_hdr(path.to_str().unwrap(), &pixels, &grid)?;
+    let data = std::fs::read(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 4\n";
+    assert_eq!(header.as_slice(), &data[..header.len()]);
+    assert_eq!(header.len() + 8 * 4, data.len());
+    // the top row is written first, 3.0 = 192/256 * 2^2
+    assert_eq!(&[192, 192, 192, 130], &data[header.len()..header.len() + 4]);
+    Ok(())
+}
+
+#[test]
+fn test_voronoi_mesh_cell_ids() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "voronoi_mesh".to_string());
+    let _ = config.insert("DISTANCE".to_string(), "0.2864788911621093".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("CELL_IDS".to_string(), "true".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (-1.3491066, -0.42415974, 0.0).into(),
+            (0.42415974, -1.3491066, 0.0).into(),
+            (-0.42415974, 1.3491066, 0.0).into(),
+            (1.3491066, 0.42415974, 0.0).into(),
+        ],
+        indices: vec![2, 0, 0, 1, 1, 3, 3, 2],
+    };
+
+    let mut attributes = Attributes::new();
+    let result = super::process_command(config, vec![owned_model_0.as_model()], &mut attributes)?;
+    let cell_ids = attributes.get(super::VORONOI_CELL_KEY).unwrap();
+    // one cell id per triangle
+    assert_eq!(result.1.len() / 3, cell_ids.len());
+    let mut distinct = cell_ids.clone();
+    distinct.sort_by(f32::total_cmp);
+    distinct.dedup();
+    assert!(distinct.len() > 1);
+    assert_eq!(
+        distinct.len().to_string(),
+        *result.3.get("VORONOI_CELLS").unwrap()
+    );
+    Ok(())
+}
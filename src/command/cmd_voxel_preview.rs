@@ -0,0 +1,405 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{ConfigType, Model, Options};
+use crate::{ffi::FFIVector3, utils::bvh, HallrError};
+use ahash::{AHashMap, AHashSet};
+use std::str::FromStr;
+use vector_traits::glam::{dvec3, ivec3, DVec3, IVec3};
+
+#[cfg(test)]
+mod tests;
+
+/// The largest number of voxels of the preview lattice
+const MAX_VOXELS: usize = 1 << 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VoxelOutput {
+    /// The outer faces of the occupied voxels
+    Cubes,
+    /// The edges of the occupied voxels
+    Wireframe,
+}
+
+impl FromStr for VoxelOutput {
+    type Err = HallrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "CUBES" => Ok(Self::Cubes),
+            "WIREFRAME" => Ok(Self::Wireframe),
+            _ => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"VOXEL_OUTPUT\" parameter",
+                s
+            ))),
+        }
+    }
+}
+
+/// The occupied voxels of a lattice, voxel `(x,y,z)` spans `origin + (x,y,z)*size` to
+/// `origin + (x+1,y+1,z+1)*size`
+pub(crate) struct VoxelGrid {
+    pub(crate) origin: DVec3,
+    pub(crate) size: f64,
+    pub(crate) shape: IVec3,
+    pub(crate) occupied: Vec<bool>,
+}
+
+impl VoxelGrid {
+    fn new(min: DVec3, max: DVec3, size: f64) -> Result<Self, HallrError> {
+        let shape = ((max - min) / size).ceil().as_ivec3().max(IVec3::ONE);
+        let count = shape.x as usize * shape.y as usize * shape.z as usize;
+        if count > MAX_VOXELS {
+            return Err(HallrError::InvalidParameter(format!(
+                "The preview would need {} voxels, the maximum is {}",
+                count, MAX_VOXELS
+            )));
+        }
+        Ok(Self {
+            origin: min,
+            size,
+            shape,
+            occupied: vec![false; count],
+        })
+    }
+
+    fn index(&self, v: IVec3) -> usize {
+        (v.z as usize * self.shape.y as usize + v.y as usize) * self.shape.x as usize + v.x as usize
+    }
+
+    pub(crate) fn is_occupied(&self, v: IVec3) -> bool {
+        v.cmpge(IVec3::ZERO).all() && v.cmplt(self.shape).all() && self.occupied[self.index(v)]
+    }
+
+    fn center(&self, v: IVec3) -> DVec3 {
+        self.origin + (v.as_dvec3() + 0.5) * self.size
+    }
+
+    /// Mark the voxels of the `min..max` box whose center is within `margin` of the primitive
+    fn mark(
+        &mut self,
+        min: DVec3,
+        max: DVec3,
+        margin: f64,
+        closest_point: impl Fn(DVec3) -> DVec3,
+    ) {
+        let low = ((min - margin - self.origin) / self.size)
+            .floor()
+            .as_ivec3()
+            .max(IVec3::ZERO);
+        let high = ((max + margin - self.origin) / self.size)
+            .floor()
+            .as_ivec3()
+            .min(self.shape - 1);
+        for z in low.z..=high.z {
+            for y in low.y..=high.y {
+                for x in low.x..=high.x {
+                    let v = ivec3(x, y, z);
+                    let index = self.index(v);
+                    if self.occupied[index] {
+                        continue;
+                    }
+                    let center = self.center(v);
+                    if closest_point(center).distance(center) <= margin {
+                        self.occupied[index] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.occupied.iter().filter(|o| **o).count()
+    }
+}
+
+/// Voxelize the triangles of a mesh: a voxel is occupied if the surface passes within half of its
+/// diagonal from its center
+pub(crate) fn voxelize_triangles(
+    vertices: &[DVec3],
+    indices: &[usize],
+    size: f64,
+) -> Result<VoxelGrid, HallrError> {
+    let (min, max) = bounds(vertices);
+    let margin = size * 0.5 * 3.0_f64.sqrt();
+    let mut grid = VoxelGrid::new(min, max, size)?;
+    for t in indices.chunks_exact(3) {
+        let triangle = [vertices[t[0]], vertices[t[1]], vertices[t[2]]];
+        let (t_min, t_max) = bounds(&triangle);
+        grid.mark(t_min, t_max, margin, |p| {
+            bvh::closest_point_on_triangle(p, &triangle)
+        });
+    }
+    Ok(grid)
+}
+
+/// Voxelize the edges of a skeleton as tubes of `radius`: a voxel is occupied if its center is
+/// inside of a tube, as the SDF mesh would see it
+pub(crate) fn voxelize_edges(
+    vertices: &[DVec3],
+    indices: &[usize],
+    radius: f64,
+    size: f64,
+) -> Result<VoxelGrid, HallrError> {
+    let (min, max) = bounds(vertices);
+    let mut grid = VoxelGrid::new(min - radius, max + radius, size)?;
+    for e in indices.chunks_exact(2) {
+        let (a, b) = (vertices[e[0]], vertices[e[1]]);
+        let ab = b - a;
+        grid.mark(a.min(b), a.max(b), radius, |p| {
+            let t = if ab.length_squared() > 0.0 {
+                ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            a + ab * t
+        });
+    }
+    Ok(grid)
+}
+
+fn bounds(vertices: &[DVec3]) -> (DVec3, DVec3) {
+    vertices.iter().fold(
+        (DVec3::splat(f64::MAX), DVec3::splat(f64::MIN)),
+        |(min, max), v| (min.min(*v), max.max(*v)),
+    )
+}
+
+/// Returns the triangles of the voxel faces not shared with another occupied voxel.
+/// The vertices are not shared between the faces.
+fn cube_faces(grid: &VoxelGrid) -> (Vec<FFIVector3>, Vec<usize>) {
+    // the corners of the face in the +axis direction, counter clockwise seen from the outside
+    const FACES: [(IVec3, [IVec3; 4]); 6] = [
+        (
+            IVec3::X,
+            [
+                ivec3(1, 0, 0),
+                ivec3(1, 1, 0),
+                ivec3(1, 1, 1),
+                ivec3(1, 0, 1),
+            ],
+        ),
+        (
+            IVec3::NEG_X,
+            [
+                ivec3(0, 0, 0),
+                ivec3(0, 0, 1),
+                ivec3(0, 1, 1),
+                ivec3(0, 1, 0),
+            ],
+        ),
+        (
+            IVec3::Y,
+            [
+                ivec3(0, 1, 0),
+                ivec3(0, 1, 1),
+                ivec3(1, 1, 1),
+                ivec3(1, 1, 0),
+            ],
+        ),
+        (
+            IVec3::NEG_Y,
+            [
+                ivec3(0, 0, 0),
+                ivec3(1, 0, 0),
+                ivec3(1, 0, 1),
+                ivec3(0, 0, 1),
+            ],
+        ),
+        (
+            IVec3::Z,
+            [
+                ivec3(0, 0, 1),
+                ivec3(1, 0, 1),
+                ivec3(1, 1, 1),
+                ivec3(0, 1, 1),
+            ],
+        ),
+        (
+            IVec3::NEG_Z,
+            [
+                ivec3(0, 0, 0),
+                ivec3(0, 1, 0),
+                ivec3(1, 1, 0),
+                ivec3(1, 0, 0),
+            ],
+        ),
+    ];
+    let mut vertices = Vec::<FFIVector3>::new();
+    let mut indices = Vec::<usize>::new();
+    for_each_occupied(grid, |v| {
+        for (direction, corners) in FACES.iter() {
+            if grid.is_occupied(v + *direction) {
+                continue;
+            }
+            let first = vertices.len();
+            for corner in corners.iter() {
+                vertices.push(to_ffi(grid, v + *corner));
+            }
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    });
+    (vertices, indices)
+}
+
+/// Returns the unique edges of the occupied voxels, in the line_chunks format
+fn wireframe(grid: &VoxelGrid) -> (Vec<FFIVector3>, Vec<usize>) {
+    let mut edges = AHashSet::<(IVec3, IVec3)>::default();
+    for_each_occupied(grid, |v| {
+        for (axis, u, w) in [
+            (IVec3::X, IVec3::Y, IVec3::Z),
+            (IVec3::Y, IVec3::Z, IVec3::X),
+            (IVec3::Z, IVec3::X, IVec3::Y),
+        ] {
+            // the four edges along this axis
+            for offset in [IVec3::ZERO, u, w, u + w] {
+                let _ = edges.insert((v + offset, v + offset + axis));
+            }
+        }
+    });
+    let mut edges: Vec<_> = edges.into_iter().collect();
+    // the hash set iteration order is random, the output should not be
+    edges.sort_unstable_by_key(|(a, b)| (a.to_array(), b.to_array()));
+    let mut vertex_ids = AHashMap::<IVec3, usize>::default();
+    let mut vertices = Vec::<FFIVector3>::new();
+    let mut indices = Vec::<usize>::with_capacity(edges.len() * 2);
+    for (a, b) in edges {
+        for corner in [a, b] {
+            let id = *vertex_ids.entry(corner).or_insert_with(|| {
+                vertices.push(to_ffi(grid, corner));
+                vertices.len() - 1
+            });
+            indices.push(id);
+        }
+    }
+    (vertices, indices)
+}
+
+fn for_each_occupied(grid: &VoxelGrid, mut f: impl FnMut(IVec3)) {
+    for z in 0..grid.shape.z {
+        for y in 0..grid.shape.y {
+            for x in 0..grid.shape.x {
+                let v = ivec3(x, y, z);
+                if grid.occupied[grid.index(v)] {
+                    f(v);
+                }
+            }
+        }
+    }
+}
+
+fn to_ffi(grid: &VoxelGrid, corner: IVec3) -> FFIVector3 {
+    let p = grid.origin + corner.as_dvec3() * grid.size;
+    FFIVector3::new(p.x as f32, p.y as f32, p.z as f32)
+}
+
+/// Run the voxel_preview command
+/// Voxelizes model 0 at the resolution of `VOXEL_DIVISIONS` voxels along the longest side of the
+/// bounding box (the same voxel size as sdf_mesh with `SDF_DIVISIONS`). A triangulated model is
+/// voxelized by its surface. The edges of a line_chunks model (a skeleton) are voxelized as tubes
+/// with the radius `SDF_RADIUS_MULTIPLIER` percent of the longest side, like sdf_mesh does.
+/// `VOXEL_OUTPUT=CUBES` (default) returns the outer faces of the occupied voxels (triangulated),
+/// `VOXEL_OUTPUT=WIREFRAME` the voxel edges (line_chunks). The number of occupied voxels is
+/// returned as `VOXEL_COUNT`, the voxel size as `VOXEL_SIZE`.
+pub(crate) fn process_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+) -> Result<super::CommandResult, HallrError> {
+    if models.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "This operation requires one input model".to_string(),
+        ));
+    }
+    let divisions = config.get_mandatory_parsed_option::<f64>("VOXEL_DIVISIONS", Some(16.0))?;
+    if !divisions.is_finite() || divisions < 1.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "VOXEL_DIVISIONS must be at least 1 :({})",
+            divisions
+        )));
+    }
+    let output = config
+        .get_mandatory_parsed_option::<VoxelOutput>("VOXEL_OUTPUT", Some(VoxelOutput::Cubes))?;
+    let model = &models[0];
+    if model.vertices.is_empty() {
+        return Err(HallrError::InvalidInputData(
+            "Input vertex list was empty".to_string(),
+        ));
+    }
+    let vertices: Vec<DVec3> = model
+        .vertices
+        .iter()
+        .map(|v| dvec3(v.x as f64, v.y as f64, v.z as f64))
+        .collect();
+    if !vertices.iter().all(|v| v.is_finite()) {
+        return Err(HallrError::InvalidInputData(
+            "Only finite coordinates are allowed".to_string(),
+        ));
+    }
+    if model.indices.iter().any(|i| *i >= vertices.len()) {
+        return Err(HallrError::InvalidInputData(
+            "An index is out of bounds".to_string(),
+        ));
+    }
+    let (min, max) = bounds(&vertices);
+    let max_dimension = (max - min).max_element();
+    if max_dimension <= 0.0 {
+        return Err(HallrError::InvalidInputData(
+            "The model has no extent".to_string(),
+        ));
+    }
+    let size = max_dimension / divisions;
+
+    let grid = match config.get_mandatory_option("mesh.format")? {
+        "triangulated" => voxelize_triangles(&vertices, model.indices, size)?,
+        "line_chunks" => {
+            let radius =
+                config.get_mandatory_parsed_option::<f64>("SDF_RADIUS_MULTIPLIER", None)? / 100.0
+                    * max_dimension;
+            if !radius.is_finite() || radius <= 0.0 {
+                return Err(HallrError::InvalidParameter(format!(
+                    "SDF_RADIUS_MULTIPLIER must be a positive number :({})",
+                    radius
+                )));
+            }
+            voxelize_edges(&vertices, model.indices, radius, size)?
+        }
+        _ => {
+            return Err(HallrError::InvalidInputData(
+                "Model mesh data must be in the 'triangulated' or the 'line_chunks' format"
+                    .to_string(),
+            ))
+        }
+    };
+    let voxel_count = grid.count();
+    let (output_vertices, output_indices) = match output {
+        VoxelOutput::Cubes => cube_faces(&grid),
+        VoxelOutput::Wireframe => wireframe(&grid),
+    };
+
+    let mut return_config = ConfigType::new();
+    match output {
+        VoxelOutput::Cubes => {
+            let _ = return_config.insert("mesh.format".to_string(), "triangulated".to_string());
+            let _ = return_config.insert("REMOVE_DOUBLES".to_string(), "true".to_string());
+        }
+        VoxelOutput::Wireframe => {
+            let _ = return_config.insert("mesh.format".to_string(), "line_chunks".to_string());
+        }
+    }
+    let _ = return_config.insert("VOXEL_COUNT".to_string(), voxel_count.to_string());
+    let _ = return_config.insert("VOXEL_SIZE".to_string(), size.to_string());
+    info!(
+        "voxel_preview operation returning {} vertices, {} indices, {} voxels of size {}",
+        output_vertices.len(),
+        output_indices.len(),
+        voxel_count,
+        size
+    );
+    Ok((
+        output_vertices,
+        output_indices,
+        model.world_orientation.to_vec(),
+        return_config,
+    ))
+}
@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use crate::{
+    command::{fixtures, ConfigType, OwnedModel},
+    HallrError,
+};
+
+#[test]
+fn test_voxel_preview_1() -> Result<(), HallrError> {
+    // a flat unit square, voxelized into a 4*4*1 slab
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "voxel_preview".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("VOXEL_DIVISIONS".to_string(), "4".to_string());
+    let _ = config.insert("VOXEL_OUTPUT".to_string(), "CUBES".to_string());
+
+    let owned_model_0 = fixtures::rectangle_faces([0.0, 0.0], [1.0, 1.0], 0.0);
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config.clone(), models)?;
+    assert_eq!("16", result.3.get("VOXEL_COUNT").unwrap());
+    assert_eq!("0.25", result.3.get("VOXEL_SIZE").unwrap());
+    // top, bottom and the sides of the slab
+    assert_eq!(48 * 4, result.0.len());
+    assert_eq!(48 * 6, result.1.len());
+
+    let _ = config.insert("VOXEL_OUTPUT".to_string(), "WIREFRAME".to_string());
+    let models = vec![owned_model_0.as_model()];
+    let result = super::process_command(config, models)?;
+    assert_eq!("line_chunks", result.3.get("mesh.format").unwrap());
+    assert_eq!(5 * 5 * 2, result.0.len());
+    assert_eq!((40 + 40 + 25) * 2, result.1.len());
+    Ok(())
+}
+
+#[test]
+fn test_voxel_preview_2() -> Result<(), HallrError> {
+    // a skeleton edge with a tube radius of 0.25
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "voxel_preview".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("VOXEL_DIVISIONS".to_string(), "8".to_string());
+    let _ = config.insert("VOXEL_OUTPUT".to_string(), "CUBES".to_string());
+
+    let owned_model_0 = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![(0.0, 0.0, 0.0).into(), (2.0, 0.0, 0.0).into()],
+        indices: vec![0, 1],
+    };
+    let models = vec![owned_model_0.as_model()];
+    let mut radius_config = config.clone();
+    let _ = radius_config.insert("SDF_RADIUS_MULTIPLIER".to_string(), "12.5".to_string());
+    let result = super::process_command(radius_config, models)?;
+    assert_eq!("40", result.3.get("VOXEL_COUNT").unwrap());
+    assert!(result.0.iter().all(|v| v.x >= -0.25 && v.x <= 2.25));
+
+    // the radius is mandatory for skeletons
+    let models = vec![owned_model_0.as_model()];
+    assert!(super::process_command(config, models).is_err());
+    Ok(())
+}
@@ -0,0 +1,395 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! The commands behind a common trait.
+//!
+//! Every command implements [`HallrCommand`] and is registered in a map keyed by its canonical
+//! name. `dispatch_command()` looks the command up, lets it validate the config and the models,
+//! executes it and logs the time it took.
+//!
+//! The commands of the plugins are registered at runtime by `register()`, see the `plugin`
+//! module.
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    attributes::Attributes, cmd_2d_outline, cmd_centerline, cmd_centroidal_remesh, cmd_chamfer,
+    cmd_clip_curves, cmd_compare, cmd_convex_hull_2d, cmd_delaunay_triangulation_2d,
+    cmd_discretize, cmd_feature_check, cmd_fillet, cmd_fit_primitives, cmd_hatch,
+    cmd_knife_intersect, cmd_mesh_analyze, cmd_minkowski, cmd_obj_io, cmd_offset_2d,
+    cmd_optimize_path, cmd_orient_outlines, cmd_pocket, cmd_project, cmd_scalar_to_color,
+    cmd_sdf_boolean, cmd_sdf_mesh, cmd_sdf_mesh_2_5, cmd_sdf_remesh, cmd_self_intersect,
+    cmd_simplify_rdp, cmd_solidify, cmd_stl_io, cmd_surface_scan, cmd_symmetry,
+    cmd_visibility_polygon_2d, cmd_voronoi_diagram, cmd_voronoi_mesh, cmd_voxel_preview, registry,
+    CommandResult, ConfigType, Model, Options,
+};
+use crate::{api, HallrError};
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+use vector_traits::glam::Vec3A;
+
+/// The type we use for the internal processing
+type T = Vec3A;
+
+/// A command that can be run by `process_command()`
+pub(crate) trait HallrCommand: Send + Sync {
+    /// The canonical name of the command
+    fn name(&self) -> &'static str;
+
+    /// The supported versions of the command, the last one is the default
+    fn versions(&self) -> &'static [u32] {
+        &[1]
+    }
+
+    /// Check the config and the models before the command is executed
+    fn validate(&self, _config: &ConfigType, _models: &[Model<'_>]) -> Result<(), HallrError> {
+        Ok(())
+    }
+
+    /// Run the command. The binary attribute channels of the input are in `attributes`, the
+    /// channels produced by the command are inserted into `output_attributes`.
+    fn execute(
+        &self,
+        config: ConfigType,
+        models: Vec<Model<'_>>,
+        attributes: &Attributes,
+        output_attributes: &mut Attributes,
+    ) -> Result<CommandResult, HallrError>;
+}
+
+/// The signature shared by the built in commands
+type CommandFn = fn(
+    ConfigType,
+    Vec<Model<'_>>,
+    &Attributes,
+    &mut Attributes,
+) -> Result<CommandResult, HallrError>;
+
+/// A command of this crate
+struct Builtin {
+    name: &'static str,
+    versions: &'static [u32],
+    run: CommandFn,
+}
+
+impl HallrCommand for Builtin {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn versions(&self) -> &'static [u32] {
+        self.versions
+    }
+
+    /// Checks the parameters limited to a range, as described by [`api::list_commands()`]
+    fn validate(&self, config: &ConfigType, _models: &[Model<'_>]) -> Result<(), HallrError> {
+        for (key, min, max) in ranges().get(self.name).into_iter().flatten() {
+            if let Some(value) = config.get_parsed_option::<f64>(key)? {
+                if !(*min..=*max).contains(&value) {
+                    return Err(HallrError::InvalidParameter(format!(
+                        "{} must be in the range {}..={} :({})",
+                        key, min, max, value
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(
+        &self,
+        config: ConfigType,
+        models: Vec<Model<'_>>,
+        attributes: &Attributes,
+        output_attributes: &mut Attributes,
+    ) -> Result<CommandResult, HallrError> {
+        (self.run)(config, models, attributes, output_attributes)
+    }
+}
+
+/// The built in commands and their versions, the introspection command is handled by the
+/// `registry`
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "surface_scan",
+        versions: &[1],
+        run: |c, m, _, _| cmd_surface_scan::process_command::<T>(c, m),
+    },
+    Builtin {
+        name: "convex_hull_2d",
+        versions: &[1],
+        run: |c, m, _, _| cmd_convex_hull_2d::process_command::<T>(c, m),
+    },
+    Builtin {
+        name: "simplify_rdp",
+        versions: &[1],
+        run: |c, m, _, _| cmd_simplify_rdp::process_command::<T>(c, m),
+    },
+    Builtin {
+        name: "2d_delaunay_triangulation",
+        versions: &[1],
+        run: |c, m, _, _| cmd_delaunay_triangulation_2d::process_command::<T>(c, m),
+    },
+    Builtin {
+        name: "centerline",
+        versions: &[1],
+        run: |c, m, _, o| cmd_centerline::process_command::<T>(c, m, o),
+    },
+    Builtin {
+        name: "2d_outline",
+        versions: &[1],
+        run: |c, m, _, _| cmd_2d_outline::process_command::<T>(c, m),
+    },
+    Builtin {
+        name: "knife_intersect",
+        versions: &[1],
+        run: |c, m, _, _| cmd_knife_intersect::process_command::<T>(c, m),
+    },
+    Builtin {
+        name: "voronoi_mesh",
+        versions: &[1],
+        run: |c, m, _, o| cmd_voronoi_mesh::process_command(c, m, o),
+    },
+    Builtin {
+        name: "voronoi_diagram",
+        versions: &[1],
+        run: |c, m, _, _| cmd_voronoi_diagram::process_command(c, m),
+    },
+    Builtin {
+        name: "sdf_mesh_2_5",
+        versions: &[1],
+        run: |c, m, _, _| cmd_sdf_mesh_2_5::process_command(c, m),
+    },
+    Builtin {
+        name: "sdf_mesh",
+        versions: &[1],
+        run: |c, m, _, _| cmd_sdf_mesh::process_command(c, m),
+    },
+    Builtin {
+        name: "discretize",
+        versions: &[1],
+        run: |c, m, _, _| cmd_discretize::process_command(c, m),
+    },
+    Builtin {
+        name: "visibility_polygon_2d",
+        versions: &[1],
+        run: |c, m, _, _| cmd_visibility_polygon_2d::process_command(c, m),
+    },
+    Builtin {
+        name: "minkowski",
+        versions: &[1],
+        run: |c, m, _, _| cmd_minkowski::process_command(c, m),
+    },
+    Builtin {
+        name: "clip_curves",
+        versions: &[1],
+        run: |c, m, _, _| cmd_clip_curves::process_command(c, m),
+    },
+    Builtin {
+        name: "hatch",
+        versions: &[1],
+        run: |c, m, _, _| cmd_hatch::process_command(c, m),
+    },
+    Builtin {
+        name: "optimize_path",
+        versions: &[1],
+        run: |c, m, _, _| cmd_optimize_path::process_command(c, m),
+    },
+    Builtin {
+        name: "compare",
+        versions: &[1],
+        run: |c, m, _, _| cmd_compare::process_command(c, m),
+    },
+    Builtin {
+        name: "chamfer",
+        versions: &[1],
+        run: |c, m, _, _| cmd_chamfer::process_command(c, m),
+    },
+    Builtin {
+        name: "solidify",
+        versions: &[1],
+        run: |c, m, _, _| cmd_solidify::process_command(c, m),
+    },
+    Builtin {
+        name: "scalar_to_color",
+        versions: &[1],
+        run: |c, m, a, o| cmd_scalar_to_color::process_command(c, m, a, o),
+    },
+    Builtin {
+        name: "fillet",
+        versions: &[1],
+        run: |c, m, _, _| cmd_fillet::process_command(c, m),
+    },
+    Builtin {
+        name: "feature_check",
+        versions: &[1],
+        run: |c, m, _, _| cmd_feature_check::process_command(c, m),
+    },
+    Builtin {
+        name: "symmetry",
+        versions: &[1],
+        run: |c, m, _, _| cmd_symmetry::process_command(c, m),
+    },
+    Builtin {
+        name: "obj_io",
+        versions: &[1],
+        run: |c, m, _, _| cmd_obj_io::process_command(c, m),
+    },
+    Builtin {
+        name: "stl_io",
+        versions: &[1],
+        run: |c, m, _, _| cmd_stl_io::process_command(c, m),
+    },
+    Builtin {
+        name: "voxel_preview",
+        versions: &[1],
+        run: |c, m, _, _| cmd_voxel_preview::process_command(c, m),
+    },
+    Builtin {
+        name: "sdf_remesh",
+        versions: &[1],
+        run: |c, m, _, _| cmd_sdf_remesh::process_command(c, m),
+    },
+    Builtin {
+        name: "fit_primitives",
+        versions: &[1],
+        run: |c, m, _, o| cmd_fit_primitives::process_command(c, m, o),
+    },
+    Builtin {
+        name: "orient_outlines",
+        versions: &[1],
+        run: |c, m, _, _| cmd_orient_outlines::process_command(c, m),
+    },
+    Builtin {
+        name: "sdf_boolean",
+        versions: &[1],
+        run: |c, m, _, _| cmd_sdf_boolean::process_command(c, m),
+    },
+    Builtin {
+        name: "centroidal_remesh",
+        versions: &[1],
+        run: |c, m, _, _| cmd_centroidal_remesh::process_command(c, m),
+    },
+    Builtin {
+        name: "offset_2d",
+        versions: &[1],
+        run: |c, m, _, _| cmd_offset_2d::process_command(c, m),
+    },
+    Builtin {
+        name: "pocket",
+        versions: &[1],
+        run: |c, m, _, _| cmd_pocket::process_command(c, m),
+    },
+    Builtin {
+        name: "mesh_analyze",
+        versions: &[1],
+        run: |c, m, _, _| cmd_mesh_analyze::process_command(c, m),
+    },
+    Builtin {
+        name: "self_intersect",
+        versions: &[1],
+        run: |c, m, _, _| cmd_self_intersect::process_command(c, m),
+    },
+    Builtin {
+        name: "project",
+        versions: &[1],
+        run: |c, m, _, o| cmd_project::process_command(c, m, o),
+    },
+];
+
+/// The registered commands, keyed by their canonical name
+fn commands() -> &'static BTreeMap<&'static str, &'static dyn HallrCommand> {
+    static COMMANDS: OnceLock<BTreeMap<&'static str, &'static dyn HallrCommand>> = OnceLock::new();
+    COMMANDS.get_or_init(|| {
+        builtins()
+            .map(|command| (command.name(), command))
+            .collect()
+    })
+}
+
+/// The parameters limited to a range of every built in command, as `(key, min, max)`
+fn ranges() -> &'static BTreeMap<&'static str, Vec<(&'static str, f64, f64)>> {
+    static RANGES: OnceLock<BTreeMap<&'static str, Vec<(&'static str, f64, f64)>>> =
+        OnceLock::new();
+    RANGES.get_or_init(|| {
+        api::list_commands()
+            .into_iter()
+            .map(|info| {
+                let ranges = info
+                    .params
+                    .iter()
+                    .filter_map(|p| p.range.map(|(min, max)| (p.key, min, max)))
+                    .collect();
+                (info.name, ranges)
+            })
+            .collect()
+    })
+}
+
+/// Returns the built in commands, in the order of their registration
+pub(crate) fn builtins() -> impl Iterator<Item = &'static dyn HallrCommand> {
+    BUILTINS
+        .iter()
+        .map(|command| command as &'static dyn HallrCommand)
+}
+
+/// The commands registered by the plugins, keyed by their name
+static PLUGINS: Mutex<BTreeMap<&'static str, &'static dyn HallrCommand>> =
+    Mutex::new(BTreeMap::new());
+
+/// Register the command of a plugin. The name must not be taken by another command or alias, and
+/// it must not contain any of `/`, `,` and `=`, they are separators of `list_commands`.
+pub(crate) fn register(command: Box<dyn HallrCommand>) -> Result<(), HallrError> {
+    let name = command.name();
+    if name.is_empty() || name.contains(['/', ',', '=']) {
+        return Err(HallrError::InvalidParameter(format!(
+            "Invalid plugin command name:{}",
+            name
+        )));
+    }
+    let mut plugins = PLUGINS.lock().unwrap();
+    if registry::is_builtin(name) || plugins.contains_key(name) {
+        return Err(HallrError::InvalidParameter(format!(
+            "The command {} is already registered",
+            name
+        )));
+    }
+    // the plugins are registered for the lifetime of the process
+    let _ = plugins.insert(name, Box::leak(command));
+    info!("Rust: registered the plugin command {}", name);
+    Ok(())
+}
+
+/// Returns the commands registered by the plugins
+pub(crate) fn plugins() -> Vec<&'static dyn HallrCommand> {
+    PLUGINS.lock().unwrap().values().copied().collect()
+}
+
+/// Returns the command registered under the canonical `name`
+pub(crate) fn lookup(name: &str) -> Result<&'static dyn HallrCommand, HallrError> {
+    commands()
+        .get(name)
+        .copied()
+        .or_else(|| PLUGINS.lock().unwrap().get(name).copied())
+        .ok_or_else(|| HallrError::InvalidParameter(format!("Invalid command:{}", name)))
+}
+
+/// Validate and run the command named by the "command" config key
+pub(crate) fn dispatch_command(
+    config: ConfigType,
+    models: Vec<Model<'_>>,
+    attributes: &Attributes,
+) -> Result<(CommandResult, Attributes), HallrError> {
+    let command = lookup(config.get_mandatory_option("command")?)?;
+    command.validate(&config, &models)?;
+    let mut output_attributes = Attributes::new();
+    let start = Instant::now();
+    let rv = command.execute(config, models, attributes, &mut output_attributes)?;
+    info!("Rust: {} executed in {:?}", command.name(), start.elapsed());
+    Ok((rv, output_attributes))
+}
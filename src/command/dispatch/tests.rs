@@ -0,0 +1,38 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{builtins, commands, lookup};
+use crate::{
+    command::{registry, ConfigType},
+    HallrError,
+};
+
+#[test]
+fn test_dispatch_commands() {
+    // every built in command is registered under its name
+    assert_eq!(builtins().count(), commands().len());
+    for command in builtins() {
+        assert_eq!(command.name(), lookup(command.name()).unwrap().name());
+    }
+    assert!(lookup("no_such_command").is_err());
+    assert!(lookup(registry::LIST_COMMANDS).is_err());
+}
+
+#[test]
+fn test_dispatch_validate() -> Result<(), HallrError> {
+    let command = lookup("centerline")?;
+    let mut config = ConfigType::default();
+    let _ = config.insert("ANGLE".to_string(), "45.0".to_string());
+    let _ = config.insert("DISTANCE".to_string(), "0.1".to_string());
+    command.validate(&config, &[])?;
+
+    let _ = config.insert("ANGLE".to_string(), "91.0".to_string());
+    assert!(matches!(
+        command.validate(&config, &[]),
+        Err(HallrError::InvalidParameter(_))
+    ));
+    let _ = config.insert("ANGLE".to_string(), "not a number".to_string());
+    assert!(command.validate(&config, &[]).is_err());
+    Ok(())
+}
@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! The input geometry shared by the tests of the commands.
+
+use super::OwnedModel;
+
+/// Append the closed outline of the rectangle from `min` to `max` in the plane z=`z` to `model`,
+/// in the line_chunks format. The corners are in counter-clockwise order, starting at `min`.
+pub(crate) fn add_rectangle_outline(model: &mut OwnedModel, min: [f32; 2], max: [f32; 2], z: f32) {
+    let first = model.vertices.len();
+    for (x, y) in [
+        (min[0], min[1]),
+        (max[0], min[1]),
+        (max[0], max[1]),
+        (min[0], max[1]),
+    ] {
+        model.vertices.push((x, y, z).into());
+    }
+    for i in 0..4 {
+        model.indices.push(first + i);
+        model.indices.push(first + (i + 1) % 4);
+    }
+}
+
+/// The closed outline of the rectangle from `min` to `max` in the plane z=`z`, in the line_chunks
+/// format
+pub(crate) fn rectangle_outline(min: [f32; 2], max: [f32; 2], z: f32) -> OwnedModel {
+    let mut model = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: Vec::new(),
+        indices: Vec::new(),
+    };
+    add_rectangle_outline(&mut model, min, max, z);
+    model
+}
+
+/// The rectangle from `min` to `max` in the plane z=`z`, as two triangles facing +Z
+pub(crate) fn rectangle_faces(min: [f32; 2], max: [f32; 2], z: f32) -> OwnedModel {
+    let mut model = rectangle_outline(min, max, z);
+    model.indices = vec![0, 1, 2, 0, 2, 3];
+    model
+}
+
+/// The closed box from `min` to `max`, with outward facing triangles. Vertex `i` is at the
+/// maximum of the X, Y and Z axis when the bit 1, 2 and 4 of `i` is set.
+pub(crate) fn cuboid(min: [f32; 3], max: [f32; 3]) -> OwnedModel {
+    let quads = [
+        [0, 2, 3, 1],
+        [4, 5, 7, 6],
+        [0, 1, 5, 4],
+        [2, 6, 7, 3],
+        [0, 4, 6, 2],
+        [1, 3, 7, 5],
+    ];
+    OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: (0..8)
+            .map(|i| {
+                let c = |axis: usize| {
+                    if i & (1 << axis) != 0 {
+                        max[axis]
+                    } else {
+                        min[axis]
+                    }
+                };
+                (c(0), c(1), c(2)).into()
+            })
+            .collect(),
+        indices: quads
+            .iter()
+            .flat_map(|q| [q[0], q[1], q[2], q[0], q[2], q[3]])
+            .collect(),
+    }
+}
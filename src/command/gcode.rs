@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! RS-274 (G-code) export of toolpaths.
+//!
+//! The toolpath is written as one continuous cut in absolute millimeter coordinates: a rapid move
+//! to the safe height above the first point, a plunge at the plunge rate, the cut at the feed
+//! rate and a rapid retract to the safe height at the end.
+
+#[cfg(test)]
+mod tests;
+
+use super::{origin_shift::ORIGIN_SHIFT_KEY, unit_scale::UNIT_SCALE_KEY, ConfigType, Options};
+use crate::{ffi::FFIVector3, HallrError};
+use std::{fmt::Write, fs};
+
+/// The key of the returned G-code, when it is not written to a file
+pub(crate) const GCODE_KEY: &str = "GCODE";
+
+/// The machine settings of the exported program
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct GcodeSettings {
+    /// The cutting feed rate, in mm/min
+    pub(crate) feed_rate: f64,
+    /// The feed rate of the plunge to the first point, in mm/min
+    pub(crate) plunge_rate: f64,
+    /// The height of the rapid moves, in model units
+    pub(crate) safe_z: f64,
+    /// The spindle speed in RPM, the spindle commands are only written if set
+    pub(crate) spindle_speed: Option<f64>,
+    /// Model units to millimeters
+    pub(crate) scale: f64,
+    /// The offset added to the coordinates, in model units. The double precision entry point
+    /// moves the model by `-ORIGIN_SHIFT`, and the program is written at the original position.
+    pub(crate) origin: [f64; 3],
+    /// Write the program here instead of returning it
+    pub(crate) file_path: Option<String>,
+}
+
+impl GcodeSettings {
+    /// Parse `FEED_RATE`, `PLUNGE_RATE` (default the feed rate), `SAFE_Z` (default `default_safe_z`),
+    /// `SPINDLE_SPEED`, `FILE_PATH` and `ORIGIN_SHIFT`. The coordinates are converted from meters
+    /// to millimeters when a `UNIT_SCALE` is given, else they are written as they are.
+    pub(crate) fn from_config(
+        config: &ConfigType,
+        default_safe_z: f64,
+    ) -> Result<Self, HallrError> {
+        let rate = |key: &str, default: Option<f64>| -> Result<f64, HallrError> {
+            let value = config.get_mandatory_parsed_option::<f64>(key, default)?;
+            if !value.is_finite() || value <= 0.0 {
+                return Err(HallrError::InvalidParameter(format!(
+                    "{} must be a positive number :({})",
+                    key, value
+                )));
+            }
+            Ok(value)
+        };
+        let feed_rate = rate("FEED_RATE", None)?;
+        let plunge_rate = rate("PLUNGE_RATE", Some(feed_rate))?;
+        let spindle_speed = match config.get_parsed_option::<f64>("SPINDLE_SPEED")? {
+            Some(_) => Some(rate("SPINDLE_SPEED", None)?),
+            None => None,
+        };
+        let safe_z = config.get_mandatory_parsed_option::<f64>("SAFE_Z", Some(default_safe_z))?;
+        if !safe_z.is_finite() {
+            return Err(HallrError::InvalidParameter(format!(
+                "SAFE_Z must be a finite number :({})",
+                safe_z
+            )));
+        }
+        let scale = if config.contains_key(UNIT_SCALE_KEY) {
+            1000.0
+        } else {
+            1.0
+        };
+        let origin = match config.get(ORIGIN_SHIFT_KEY) {
+            Some(value) => {
+                let parsed = value
+                    .split(',')
+                    .map(|v| v.trim().parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>();
+                match parsed.as_deref() {
+                    Ok([x, y, z]) => [*x, *y, *z],
+                    _ => {
+                        return Err(HallrError::InvalidParameter(format!(
+                            "{} must be three comma separated numbers :({})",
+                            ORIGIN_SHIFT_KEY, value
+                        )))
+                    }
+                }
+            }
+            None => [0.0; 3],
+        };
+        Ok(Self {
+            feed_rate,
+            plunge_rate,
+            safe_z,
+            spindle_speed,
+            scale,
+            origin,
+            file_path: config.get_parsed_option::<String>("FILE_PATH")?,
+        })
+    }
+}
+
+/// Returns the toolpath as a G-code program
+pub(crate) fn to_gcode(
+    toolpath: &[FFIVector3],
+    settings: &GcodeSettings,
+) -> Result<String, HallrError> {
+    let first = toolpath
+        .first()
+        .ok_or_else(|| HallrError::NoData("The toolpath is empty".to_string()))?;
+    if let Some(v) = toolpath.iter().find(|v| v.z as f64 > settings.safe_z) {
+        return Err(HallrError::InvalidParameter(format!(
+            "SAFE_Z ({}) is below the toolpath point ({},{},{})",
+            settings.safe_z, v.x, v.y, v.z
+        )));
+    }
+    let s = settings.scale;
+    let [ox, oy, oz] = settings.origin;
+    let x = |value: f32| (value as f64 + ox) * s;
+    let y = |value: f32| (value as f64 + oy) * s;
+    let z = |value: f32| (value as f64 + oz) * s;
+    let safe_z = (settings.safe_z + oz) * s;
+    let mut rv = String::new();
+    let mut write_program = || -> std::fmt::Result {
+        writeln!(rv, "(hallr toolpath, {} points)", toolpath.len())?;
+        writeln!(rv, "G21")?;
+        writeln!(rv, "G90")?;
+        if let Some(speed) = settings.spindle_speed {
+            writeln!(rv, "M3 S{:.0}", speed)?;
+        }
+        writeln!(rv, "G0 Z{:.4}", safe_z)?;
+        writeln!(rv, "G0 X{:.4} Y{:.4}", x(first.x), y(first.y))?;
+        writeln!(rv, "G1 Z{:.4} F{:.1}", z(first.z), settings.plunge_rate)?;
+        for (i, v) in toolpath.iter().enumerate().skip(1) {
+            write!(rv, "G1 X{:.4} Y{:.4} Z{:.4}", x(v.x), y(v.y), z(v.z))?;
+            if i == 1 {
+                write!(rv, " F{:.1}", settings.feed_rate)?;
+            }
+            writeln!(rv)?;
+        }
+        writeln!(rv, "G0 Z{:.4}", safe_z)?;
+        if settings.spindle_speed.is_some() {
+            writeln!(rv, "M5")?;
+        }
+        writeln!(rv, "M2")
+    };
+    write_program().map_err(|e| HallrError::InternalError(e.to_string()))?;
+    Ok(rv)
+}
+
+/// Export the toolpath: write it to the `FILE_PATH` of the settings, or return it under
+/// `GCODE_KEY`. The number of written lines is returned as `GCODE_LINES`.
+pub(crate) fn export(
+    toolpath: &[FFIVector3],
+    settings: &GcodeSettings,
+    return_config: &mut ConfigType,
+) -> Result<(), HallrError> {
+    let program = to_gcode(toolpath, settings)?;
+    let _ = return_config.insert(
+        "GCODE_LINES".to_string(),
+        program.lines().count().to_string(),
+    );
+    match settings.file_path.as_deref() {
+        Some(path) => {
+            fs::write(path, &program).map_err(|e| {
+                HallrError::InternalError(format!("Could not write {}: {}", path, e))
+            })?;
+            info!("G-code written to {}", path);
+        }
+        None => {
+            let _ = return_config.insert(GCODE_KEY.to_string(), program);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{GcodeSettings, GCODE_KEY};
+use crate::{command::ConfigType, ffi::FFIVector3, HallrError};
+
+fn toolpath() -> Vec<FFIVector3> {
+    vec![
+        (0.0, 0.0, -1.0).into(),
+        (10.0, 0.0, -1.5).into(),
+        (10.0, 2.5, -1.0).into(),
+    ]
+}
+
+#[test]
+fn test_gcode_program() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("FEED_RATE".to_string(), "800".to_string());
+    let _ = config.insert("PLUNGE_RATE".to_string(), "200".to_string());
+    let _ = config.insert("SPINDLE_SPEED".to_string(), "12000".to_string());
+    let settings = GcodeSettings::from_config(&config, 5.0)?;
+    let program = super::to_gcode(&toolpath(), &settings)?;
+    let lines: Vec<&str> = program.lines().collect();
+    assert_eq!(
+        vec![
+            "(hallr toolpath, 3 points)",
+            "G21",
+            "G90",
+            "M3 S12000",
+            "G0 Z5.0000",
+            "G0 X0.0000 Y0.0000",
+            "G1 Z-1.0000 F200.0",
+            "G1 X10.0000 Y0.0000 Z-1.5000 F800.0",
+            "G1 X10.0000 Y2.5000 Z-1.0000",
+            "G0 Z5.0000",
+            "M5",
+            "M2",
+        ],
+        lines
+    );
+    Ok(())
+}
+
+#[test]
+fn test_gcode_export() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("FEED_RATE".to_string(), "800".to_string());
+    let _ = config.insert("SAFE_Z".to_string(), "0.002".to_string());
+    // the toolpath is in meters, the program in millimeters
+    let _ = config.insert("UNIT_SCALE".to_string(), "0.001".to_string());
+    let settings = GcodeSettings::from_config(&config, 0.0)?;
+    let toolpath: Vec<FFIVector3> = vec![(0.0, 0.001, 0.0).into(), (0.01, 0.001, 0.0).into()];
+    let mut return_config = ConfigType::new();
+    super::export(&toolpath, &settings, &mut return_config)?;
+    let program = return_config.get(GCODE_KEY).unwrap();
+    assert!(program.contains("G0 Z2.0000\n"), "{}", program);
+    assert!(
+        program.contains("G1 X10.0000 Y1.0000 Z0.0000 F800.0\n"),
+        "{}",
+        program
+    );
+    // no spindle commands without a spindle speed
+    assert!(!program.contains("M3"));
+    assert_eq!(
+        program.lines().count().to_string(),
+        *return_config.get("GCODE_LINES").unwrap()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_gcode_origin_shift() -> Result<(), HallrError> {
+    // the toolpath was moved by -ORIGIN_SHIFT, the program is written at the original position
+    let mut config = ConfigType::default();
+    let _ = config.insert("FEED_RATE".to_string(), "800".to_string());
+    let _ = config.insert("ORIGIN_SHIFT".to_string(), "1000,2000,-10".to_string());
+    let settings = GcodeSettings::from_config(&config, 5.0)?;
+    let program = super::to_gcode(&toolpath(), &settings)?;
+    assert!(program.contains("G0 Z-5.0000\n"), "{}", program);
+    assert!(
+        program.contains("G0 X1000.0000 Y2000.0000\n"),
+        "{}",
+        program
+    );
+    assert!(
+        program.contains("G1 X1010.0000 Y2000.0000 Z-11.5000 F800.0\n"),
+        "{}",
+        program
+    );
+    let _ = config.insert("ORIGIN_SHIFT".to_string(), "1000,2000".to_string());
+    assert!(GcodeSettings::from_config(&config, 5.0).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_gcode_errors() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    // the feed rate is mandatory
+    assert!(GcodeSettings::from_config(&config, 5.0).is_err());
+    let _ = config.insert("FEED_RATE".to_string(), "-1".to_string());
+    assert!(GcodeSettings::from_config(&config, 5.0).is_err());
+    let _ = config.insert("FEED_RATE".to_string(), "1000".to_string());
+    // the safe height must be above the whole toolpath
+    let settings = GcodeSettings::from_config(&config, -1.2)?;
+    assert!(super::to_gcode(&toolpath(), &settings).is_err());
+    assert!(super::to_gcode(&[], &settings).is_err());
+    Ok(())
+}
@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! The index encodings (`mesh.format`) of the mesh data exchanged with the addon, and
+//! conversions between them.
+
+#[cfg(test)]
+mod tests;
+
+use super::{CommandResult, Options};
+use crate::HallrError;
+use ahash::AHashSet;
+use std::{fmt, str::FromStr};
+
+/// The option used to request the result in a specific mesh format, the result is converted if
+/// the command returned something else.
+pub(crate) const OUTPUT_FORMAT_KEY: &str = "mesh.output_format";
+
+/// The encoding of the indices of a model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshFormat {
+    /// Every three indices form a triangle: `[a, b, c, d, e, f, ..]`
+    Triangulated,
+    /// Every two indices form an edge, as in `.chunks(2)`: `[a, b, c, d, ..]`
+    LineChunks,
+    /// The indices form one continuous line, as in `.windows(2)`: `[a, b, c, ..]`
+    LineWindows,
+    /// Faces of any size, each face is prefixed with its vertex count:
+    /// `[3, a, b, c, 4, d, e, f, g, ..]`
+    Ngons,
+}
+
+impl MeshFormat {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Triangulated => "triangulated",
+            Self::LineChunks => "line_chunks",
+            Self::LineWindows => "line_windows",
+            Self::Ngons => "ngons",
+        }
+    }
+}
+
+impl fmt::Display for MeshFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for MeshFormat {
+    type Err = HallrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "triangulated" => Ok(Self::Triangulated),
+            "line_chunks" => Ok(Self::LineChunks),
+            // "line" is the older name of the windows format
+            "line_windows" | "line" => Ok(Self::LineWindows),
+            "ngons" => Ok(Self::Ngons),
+            _ => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid mesh format",
+                s
+            ))),
+        }
+    }
+}
+
+/// Split n-gon encoded indices into faces
+pub(crate) fn split_ngons(indices: &[usize]) -> Result<Vec<&[usize]>, HallrError> {
+    let mut rv = Vec::new();
+    let mut rest = indices;
+    while let Some((&size, tail)) = rest.split_first() {
+        if size < 3 {
+            return Err(HallrError::InvalidInputData(format!(
+                "An n-gon face must have at least three vertices, found {}",
+                size
+            )));
+        }
+        if size > tail.len() {
+            return Err(HallrError::InvalidInputData(
+                "The n-gon indices are truncated".to_string(),
+            ));
+        }
+        let (face, tail) = tail.split_at(size);
+        rv.push(face);
+        rest = tail;
+    }
+    Ok(rv)
+}
+
+/// Encode faces as n-gon indices
+pub(crate) fn faces_to_ngons<F: AsRef<[usize]>>(faces: &[F]) -> Vec<usize> {
+    let mut rv = Vec::with_capacity(faces.iter().map(|f| f.as_ref().len() + 1).sum());
+    for face in faces.iter() {
+        rv.push(face.as_ref().len());
+        rv.extend_from_slice(face.as_ref());
+    }
+    rv
+}
+
+/// Returns the edges of the faces in the line_chunks format, edges shared between faces are only
+/// returned once.
+fn faces_to_line_chunks<'a>(faces: impl Iterator<Item = &'a [usize]>) -> Vec<usize> {
+    let mut seen = AHashSet::<(usize, usize)>::new();
+    let mut rv = Vec::new();
+    for face in faces {
+        for (i, a) in face.iter().enumerate() {
+            let b = face[(i + 1) % face.len()];
+            if seen.insert((*a.min(&b), *a.max(&b))) {
+                rv.push(*a);
+                rv.push(b);
+            }
+        }
+    }
+    rv
+}
+
+/// Convert the indices from one mesh format to another.
+/// Faces are triangulated as fans, so this is only correct for convex n-gons.
+/// Lines can not be converted into faces.
+pub(crate) fn convert(
+    indices: &[usize],
+    from: MeshFormat,
+    to: MeshFormat,
+) -> Result<Vec<usize>, HallrError> {
+    use MeshFormat::*;
+    Ok(match (from, to) {
+        (from, to) if from == to => indices.to_vec(),
+        (Triangulated, Ngons) => faces_to_ngons(&indices.chunks_exact(3).collect::<Vec<_>>()),
+        (Triangulated, LineChunks) => faces_to_line_chunks(indices.chunks_exact(3)),
+        (Ngons, Triangulated) => {
+            let mut rv = Vec::with_capacity(indices.len() * 3);
+            for face in split_ngons(indices)? {
+                for i in 1..face.len() - 1 {
+                    rv.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+                }
+            }
+            rv
+        }
+        (Ngons, LineChunks) => faces_to_line_chunks(split_ngons(indices)?.into_iter()),
+        (LineWindows, LineChunks) => indices.windows(2).flatten().copied().collect(),
+        (from, to) => {
+            return Err(HallrError::InvalidParameter(format!(
+                "Can not convert from the {} to the {} mesh format",
+                from, to
+            )))
+        }
+    })
+}
+
+/// Convert every segment of the result to the `to` mesh format. The segments are defined by the
+/// `first_index_model_N` keys, and each segment may have its own `mesh.format_model_N`.
+pub(crate) fn convert_result(result: &mut CommandResult, to: MeshFormat) -> Result<(), HallrError> {
+    let (_, indices, _, config) = result;
+    let format = config.get_mandatory_parsed_option::<MeshFormat>("mesh.format", None)?;
+    let mut segment_starts = vec![0_usize];
+    while let Some(start) =
+        config.get_parsed_option::<usize>(&format!("first_index_model_{}", segment_starts.len()))?
+    {
+        segment_starts.push(start);
+    }
+    let mut converted = Vec::<usize>::with_capacity(indices.len());
+    for (n, start) in segment_starts.iter().enumerate() {
+        let end = segment_starts.get(n + 1).copied().unwrap_or(indices.len());
+        let segment = indices.get(*start..end).ok_or_else(|| {
+            HallrError::InternalError(format!("The segment {} is out of bounds", n))
+        })?;
+        let segment_format = config
+            .get_parsed_option::<MeshFormat>(&format!("mesh.format_model_{}", n))?
+            .unwrap_or(format);
+        if n > 0 {
+            let _ = config.insert(
+                format!("first_index_model_{}", n),
+                converted.len().to_string(),
+            );
+        }
+        let _ = config.remove(&format!("mesh.format_model_{}", n));
+        converted.append(&mut convert(segment, segment_format, to)?);
+    }
+    *indices = converted;
+    let _ = config.insert("mesh.format".to_string(), to.to_string());
+    Ok(())
+}
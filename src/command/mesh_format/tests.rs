@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{convert, convert_result, split_ngons, MeshFormat};
+use crate::{
+    command::{output_stats, ConfigType},
+    ffi::FFIVector3,
+    HallrError,
+};
+
+#[test]
+fn test_mesh_format_parse() -> Result<(), HallrError> {
+    assert_eq!(MeshFormat::Ngons, "ngons".parse::<MeshFormat>()?);
+    assert_eq!(MeshFormat::LineWindows, "line".parse::<MeshFormat>()?);
+    assert_eq!("line_chunks", MeshFormat::LineChunks.to_string());
+    assert!("quads".parse::<MeshFormat>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_split_ngons() -> Result<(), HallrError> {
+    let faces = split_ngons(&[3, 0, 1, 2, 4, 0, 2, 3, 4])?;
+    assert_eq!(vec![&[0, 1, 2][..], &[0, 2, 3, 4][..]], faces);
+    assert!(split_ngons(&[4, 0, 1, 2]).is_err());
+    assert!(split_ngons(&[2, 0, 1]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_convert() -> Result<(), HallrError> {
+    // a square as one n-gon
+    let square = [4, 0, 1, 2, 3];
+    assert_eq!(
+        vec![0, 1, 2, 0, 2, 3],
+        convert(&square, MeshFormat::Ngons, MeshFormat::Triangulated)?
+    );
+    assert_eq!(
+        vec![0, 1, 1, 2, 2, 3, 3, 0],
+        convert(&square, MeshFormat::Ngons, MeshFormat::LineChunks)?
+    );
+    // the diagonal is shared by the two triangles
+    assert_eq!(
+        vec![0, 1, 1, 2, 2, 0, 2, 3, 3, 0],
+        convert(
+            &[0, 1, 2, 0, 2, 3],
+            MeshFormat::Triangulated,
+            MeshFormat::LineChunks
+        )?
+    );
+    assert_eq!(
+        vec![3, 0, 1, 2, 3, 0, 2, 3],
+        convert(
+            &[0, 1, 2, 0, 2, 3],
+            MeshFormat::Triangulated,
+            MeshFormat::Ngons
+        )?
+    );
+    assert_eq!(
+        vec![0, 1, 1, 2],
+        convert(&[0, 1, 2], MeshFormat::LineWindows, MeshFormat::LineChunks)?
+    );
+    assert!(convert(&[0, 1], MeshFormat::LineChunks, MeshFormat::Ngons).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_convert_result() -> Result<(), HallrError> {
+    // an n-gon segment followed by a line_chunks segment, converted to line_chunks
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "ngons".to_string());
+    let _ = config.insert("first_vertex_model_1".to_string(), "4".to_string());
+    let _ = config.insert("first_index_model_1".to_string(), "5".to_string());
+    let _ = config.insert("mesh.format_model_1".to_string(), "line_chunks".to_string());
+    let mut result = (
+        vec![FFIVector3::default(); 6],
+        vec![4, 0, 1, 2, 3, 4, 5],
+        vec![],
+        config,
+    );
+    let stats = output_stats::segment_stats(&result)?;
+    assert_eq!(1, stats[0].faces);
+    assert_eq!(1, stats[1].edges);
+
+    convert_result(&mut result, MeshFormat::LineChunks)?;
+    assert_eq!(vec![0, 1, 1, 2, 2, 3, 3, 0, 4, 5], result.1);
+    assert_eq!("line_chunks", result.3.get("mesh.format").unwrap());
+    assert_eq!("8", result.3.get("first_index_model_1").unwrap());
+    assert!(result.3.get("mesh.format_model_1").is_none());
+    Ok(())
+}
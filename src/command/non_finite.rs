@@ -0,0 +1,295 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! Central handling of non-finite (NaN, Inf) and denormal input vertices.
+//!
+//! Blender modifiers can produce NaN or infinite coordinates. Instead of letting every command
+//! deal with them, the input is checked once before the command runs. What happens to the
+//! affected vertices is selected with the `NON_FINITE_POLICY` option:
+//! * `ERROR` (default): the command is rejected.
+//! * `FILTER`: the vertices are removed, together with the faces and edges using them.
+//! * `CLAMP`: NaN coordinates are replaced with zero, infinite coordinates with the smallest or
+//!   largest finite value of that axis.
+//!
+//! Denormal coordinates are always flushed to zero.
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    mesh_format::{self, MeshFormat},
+    ConfigType, Options,
+};
+use crate::{ffi::FFIVector3, HallrError};
+use std::{slice, str::FromStr};
+
+/// The option selecting the policy
+pub(crate) const NON_FINITE_POLICY_KEY: &str = "NON_FINITE_POLICY";
+/// The key inserted into the returned config when non-finite vertices were found
+pub(crate) const NON_FINITE_VERTICES_KEY: &str = "NON_FINITE_VERTICES";
+/// The key inserted into the returned config when denormal coordinates were flushed to zero
+pub(crate) const DENORMAL_VERTICES_KEY: &str = "DENORMAL_VERTICES";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NonFinitePolicy {
+    Error,
+    Filter,
+    Clamp,
+}
+
+impl FromStr for NonFinitePolicy {
+    type Err = HallrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ERROR" => Ok(Self::Error),
+            "FILTER" => Ok(Self::Filter),
+            "CLAMP" => Ok(Self::Clamp),
+            _ => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"{}\" parameter",
+                s, NON_FINITE_POLICY_KEY
+            ))),
+        }
+    }
+}
+
+/// The number of affected vertices, and the sanitized copy of the input if anything was changed
+#[derive(Default)]
+pub(crate) struct Sanitized {
+    pub(crate) non_finite: usize,
+    pub(crate) denormal: usize,
+    pub(crate) data: Option<(Vec<FFIVector3>, Vec<usize>)>,
+}
+
+impl Sanitized {
+    /// Report the affected vertex counts in the returned config
+    pub(crate) fn report(&self, return_config: &mut ConfigType) {
+        if self.non_finite > 0 {
+            let _ = return_config.insert(
+                NON_FINITE_VERTICES_KEY.to_string(),
+                self.non_finite.to_string(),
+            );
+        }
+        if self.denormal > 0 {
+            let _ =
+                return_config.insert(DENORMAL_VERTICES_KEY.to_string(), self.denormal.to_string());
+        }
+    }
+}
+
+fn is_finite(v: &FFIVector3) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
+
+fn is_denormal(v: &FFIVector3) -> bool {
+    v.x.is_subnormal() || v.y.is_subnormal() || v.z.is_subnormal()
+}
+
+fn flush_denormal(value: f32) -> f32 {
+    if value.is_subnormal() {
+        0.0
+    } else {
+        value
+    }
+}
+
+/// Returns the (vertex, index) start offsets of the input models, as defined by the
+/// `first_vertex_model_N` and `first_index_model_N` keys.
+fn model_offsets(config: &ConfigType) -> Result<Vec<(usize, usize)>, HallrError> {
+    let mut rv = vec![(0, 0)];
+    while let Some(vertex_start) =
+        config.get_parsed_option::<usize>(&format!("first_vertex_model_{}", rv.len()))?
+    {
+        let index_start = config.get_mandatory_parsed_option::<usize>(
+            &format!("first_index_model_{}", rv.len()),
+            None,
+        )?;
+        rv.push((vertex_start, index_start));
+    }
+    Ok(rv)
+}
+
+/// Remove the `bad` vertices, and every face or edge using them. The indices of each model are
+/// relative to the first vertex of that model.
+fn filter(
+    vertices: &[FFIVector3],
+    indices: &[usize],
+    bad: &[bool],
+    config: &mut ConfigType,
+) -> Result<(Vec<FFIVector3>, Vec<usize>), HallrError> {
+    let format = config.get_parsed_option::<MeshFormat>("mesh.format")?;
+    let offsets = model_offsets(config)?;
+    let mut rv_vertices = Vec::<FFIVector3>::with_capacity(vertices.len());
+    let mut rv_indices = Vec::<usize>::with_capacity(indices.len());
+
+    for (n, (vertex_start, index_start)) in offsets.iter().enumerate() {
+        let (vertex_end, index_end) = offsets
+            .get(n + 1)
+            .copied()
+            .unwrap_or((vertices.len(), indices.len()));
+        let model_vertices = vertices.get(*vertex_start..vertex_end).ok_or_else(|| {
+            HallrError::InvalidInputData(format!("The vertices of model {} are out of bounds", n))
+        })?;
+        let model_indices = indices.get(*index_start..index_end).ok_or_else(|| {
+            HallrError::InvalidInputData(format!("The indices of model {} are out of bounds", n))
+        })?;
+        if n > 0 {
+            let _ = config.insert(
+                format!("first_vertex_model_{}", n),
+                rv_vertices.len().to_string(),
+            );
+            let _ = config.insert(
+                format!("first_index_model_{}", n),
+                rv_indices.len().to_string(),
+            );
+        }
+        // the new (model relative) index of each vertex, None if removed
+        let mut remap = Vec::<Option<usize>>::with_capacity(model_vertices.len());
+        let model_first_vertex = rv_vertices.len();
+        for (i, v) in model_vertices.iter().enumerate() {
+            if bad[vertex_start + i] {
+                remap.push(None);
+            } else {
+                remap.push(Some(rv_vertices.len() - model_first_vertex));
+                rv_vertices.push(*v);
+            }
+        }
+        // returns the remapped primitive, or None if any of its vertices was removed
+        let remap_primitive = |primitive: &[usize]| -> Result<Option<Vec<usize>>, HallrError> {
+            primitive
+                .iter()
+                .map(|i| {
+                    remap.get(*i).copied().ok_or_else(|| {
+                        HallrError::InvalidInputData(format!("The index {} is out of bounds", i))
+                    })
+                })
+                .collect()
+        };
+        match format {
+            Some(MeshFormat::Triangulated) => {
+                for triangle in model_indices.chunks_exact(3) {
+                    if let Some(triangle) = remap_primitive(triangle)? {
+                        rv_indices.extend(triangle);
+                    }
+                }
+            }
+            Some(MeshFormat::LineChunks) => {
+                for edge in model_indices.chunks_exact(2) {
+                    if let Some(edge) = remap_primitive(edge)? {
+                        rv_indices.extend(edge);
+                    }
+                }
+            }
+            Some(MeshFormat::Ngons) => {
+                for face in mesh_format::split_ngons(model_indices)? {
+                    if let Some(face) = remap_primitive(face)? {
+                        rv_indices.push(face.len());
+                        rv_indices.extend(face);
+                    }
+                }
+            }
+            // a continuous line is joined over the removed vertices, as is any other format
+            Some(MeshFormat::LineWindows) | None => {
+                for index in model_indices.iter() {
+                    if let Some(index) = remap_primitive(slice::from_ref(index))? {
+                        rv_indices.extend(index);
+                    }
+                }
+            }
+        }
+    }
+    Ok((rv_vertices, rv_indices))
+}
+
+/// Replace NaN coordinates with zero, and infinite coordinates with the smallest or largest
+/// finite value of that axis.
+fn clamp(vertices: &mut [FFIVector3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for v in vertices.iter() {
+        for (axis, value) in [v.x, v.y, v.z].into_iter().enumerate() {
+            if value.is_finite() {
+                min[axis] = min[axis].min(value);
+                max[axis] = max[axis].max(value);
+            }
+        }
+    }
+    let clamp_value = |value: f32, axis: usize| -> f32 {
+        if value.is_nan() {
+            0.0
+        } else if value == f32::INFINITY {
+            if max[axis] >= min[axis] {
+                max[axis]
+            } else {
+                0.0
+            }
+        } else if value == f32::NEG_INFINITY {
+            if max[axis] >= min[axis] {
+                min[axis]
+            } else {
+                0.0
+            }
+        } else {
+            value
+        }
+    };
+    for v in vertices.iter_mut() {
+        v.x = clamp_value(v.x, 0);
+        v.y = clamp_value(v.y, 1);
+        v.z = clamp_value(v.z, 2);
+    }
+}
+
+/// Check the input vertices for non-finite and denormal coordinates, and apply the
+/// `NON_FINITE_POLICY`. The config is updated if the model offsets changed.
+pub(crate) fn sanitize_input(
+    vertices: &[FFIVector3],
+    indices: &[usize],
+    config: &mut ConfigType,
+) -> Result<Sanitized, HallrError> {
+    let policy = config.get_mandatory_parsed_option::<NonFinitePolicy>(
+        NON_FINITE_POLICY_KEY,
+        Some(NonFinitePolicy::Error),
+    )?;
+    let non_finite = vertices.iter().filter(|v| !is_finite(v)).count();
+    let denormal = vertices.iter().filter(|v| is_denormal(v)).count();
+    if non_finite == 0 && denormal == 0 {
+        return Ok(Sanitized::default());
+    }
+    if non_finite > 0 && policy == NonFinitePolicy::Error {
+        return Err(HallrError::FloatNotFinite(format!(
+            "{} input vertices have NaN or infinite coordinates. Set \"{}\" to FILTER or CLAMP to \
+            process them anyway",
+            non_finite, NON_FINITE_POLICY_KEY
+        )));
+    }
+    info!(
+        "Rust: {} non-finite vertices ({:?}), {} denormal vertices",
+        non_finite, policy, denormal
+    );
+    let mut rv_vertices: Vec<FFIVector3> = vertices
+        .iter()
+        .map(|v| {
+            FFIVector3::new(
+                flush_denormal(v.x),
+                flush_denormal(v.y),
+                flush_denormal(v.z),
+            )
+        })
+        .collect();
+    let mut rv_indices = indices.to_vec();
+    if non_finite > 0 {
+        if policy == NonFinitePolicy::Filter {
+            let bad: Vec<bool> = vertices.iter().map(|v| !is_finite(v)).collect();
+            (rv_vertices, rv_indices) = filter(&rv_vertices, indices, &bad, config)?;
+        } else {
+            clamp(&mut rv_vertices);
+        }
+    }
+    Ok(Sanitized {
+        non_finite,
+        denormal,
+        data: Some((rv_vertices, rv_indices)),
+    })
+}
@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{sanitize_input, DENORMAL_VERTICES_KEY, NON_FINITE_VERTICES_KEY};
+use crate::{command::ConfigType, ffi::FFIVector3, HallrError};
+
+fn input() -> (Vec<FFIVector3>, Vec<usize>) {
+    (
+        vec![
+            (0.0, 0.0, 0.0).into(),
+            (1.0, 0.0, 0.0).into(),
+            (f32::NAN, 0.0, 0.0).into(),
+            (1.0, 1.0, f32::INFINITY).into(),
+            (0.0, 1.0, 0.0).into(),
+        ],
+        vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 0],
+    )
+}
+
+#[test]
+fn test_non_finite_error() {
+    let (vertices, indices) = input();
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    assert!(sanitize_input(&vertices, &indices, &mut config).is_err());
+}
+
+#[test]
+fn test_non_finite_filter() -> Result<(), HallrError> {
+    let (mut vertices, mut indices) = input();
+    // a second model
+    vertices.extend_from_slice(&[
+        FFIVector3::new(2.0, 2.0, 0.0),
+        FFIVector3::new(3.0, 2.0, 0.0),
+    ]);
+    indices.extend_from_slice(&[0, 1]);
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("NON_FINITE_POLICY".to_string(), "FILTER".to_string());
+    let _ = config.insert("first_vertex_model_1".to_string(), "5".to_string());
+    let _ = config.insert("first_index_model_1".to_string(), "10".to_string());
+    let sanitized = sanitize_input(&vertices, &indices, &mut config)?;
+    assert_eq!(2, sanitized.non_finite);
+    let (vertices, indices) = sanitized.data.as_ref().unwrap();
+    assert_eq!(5, vertices.len());
+    // only the edges 0-1 and 4-0 remain, 4 is now 2
+    assert_eq!(&vec![0, 1, 2, 0, 0, 1], indices);
+    assert_eq!("3", config.get("first_vertex_model_1").unwrap());
+    assert_eq!("4", config.get("first_index_model_1").unwrap());
+
+    let mut return_config = ConfigType::default();
+    sanitized.report(&mut return_config);
+    assert_eq!("2", return_config.get(NON_FINITE_VERTICES_KEY).unwrap());
+
+    // the indices can not be filtered without a valid mesh format
+    let (vertices, indices) = input();
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_strips".to_string());
+    let _ = config.insert("NON_FINITE_POLICY".to_string(), "FILTER".to_string());
+    assert!(sanitize_input(&vertices, &indices, &mut config).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_non_finite_clamp() -> Result<(), HallrError> {
+    let (vertices, indices) = input();
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("NON_FINITE_POLICY".to_string(), "CLAMP".to_string());
+    let sanitized = sanitize_input(&vertices, &indices, &mut config)?;
+    let (vertices, indices) = sanitized.data.as_ref().unwrap();
+    assert_eq!(5, vertices.len());
+    assert_eq!(10, indices.len());
+    assert_eq!(0.0, vertices[2].x);
+    // the largest finite z value is 0.0
+    assert_eq!(0.0, vertices[3].z);
+    Ok(())
+}
+
+#[test]
+fn test_non_finite_denormal() -> Result<(), HallrError> {
+    let vertices: Vec<FFIVector3> = vec![(f32::MIN_POSITIVE / 2.0, 1.0, 0.0).into()];
+    let mut config = ConfigType::default();
+    let sanitized = sanitize_input(&vertices, &[0], &mut config)?;
+    assert_eq!(0.0, sanitized.data.as_ref().unwrap().0[0].x);
+    let mut return_config = ConfigType::default();
+    sanitized.report(&mut return_config);
+    assert_eq!("1", return_config.get(DENORMAL_VERTICES_KEY).unwrap());
+    assert!(return_config.get(NON_FINITE_VERTICES_KEY).is_none());
+    Ok(())
+}
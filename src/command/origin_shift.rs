@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! The double precision entry point of the commands.
+//!
+//! The commands run in single precision, so the detail of a model far away from the origin is
+//! lost when its coordinates are converted. `process_geometry_f64` receives the coordinates in
+//! double precision, moves the whole scene by `-ORIGIN_SHIFT` (the center of the bounding box of
+//! the vertices) and converts it to single precision only then. The vertices, the matrices and the
+//! options holding absolute coordinates are moved, the result is moved back in double precision.
+//! The applied offset is returned as `ORIGIN_SHIFT`, and passed to the command in the config, so
+//! that exported coordinates (G-code) can be written unshifted.
+
+#[cfg(test)]
+mod tests;
+
+use super::{attributes::Attributes, CommandResult, ConfigType, Options};
+use crate::{
+    ffi::{FFIVector3, FFIVector3d},
+    HallrError,
+};
+
+/// The offset subtracted from the input, as three comma separated numbers
+pub(crate) const ORIGIN_SHIFT_KEY: &str = "ORIGIN_SHIFT";
+
+/// The options holding absolute coordinates, and their axis
+const POSITION_PARAMETERS: &[(&str, usize)] = &[
+    ("minimum_z", 2),
+    ("return_z", 2),
+    ("FLOOR_Z", 2),
+    ("POCKET_RETURN_Z", 2),
+    ("SAFE_Z", 2),
+];
+
+/// The returned keys holding absolute coordinates, and their axis
+const POSITION_RESULTS: &[(&str, usize)] = &[("grid.min_x", 0), ("grid.min_y", 1)];
+
+/// The result of `process_command_f64`
+pub(crate) type CommandResultF64 = (Vec<FFIVector3d>, Vec<usize>, Vec<f64>, ConfigType);
+
+/// Returns the center of the bounding box of the finite vertices, zero if there are none
+pub(crate) fn origin(vertices: &[FFIVector3d]) -> [f64; 3] {
+    let mut low = [f64::MAX; 3];
+    let mut high = [f64::MIN; 3];
+    for v in vertices
+        .iter()
+        .filter(|v| v.x.is_finite() && v.y.is_finite() && v.z.is_finite())
+    {
+        for (axis, value) in [v.x, v.y, v.z].into_iter().enumerate() {
+            low[axis] = low[axis].min(value);
+            high[axis] = high[axis].max(value);
+        }
+    }
+    if low[0] > high[0] {
+        return [0.0; 3];
+    }
+    [
+        (low[0] + high[0]) * 0.5,
+        (low[1] + high[1]) * 0.5,
+        (low[2] + high[2]) * 0.5,
+    ]
+}
+
+/// Moves the scene of the row major world matrices by `offset`: the matrix `M` becomes
+/// `T(offset) * M * T(-offset)`, so that the world coordinates of the moved vertices move too.
+/// Only the translations change.
+fn shift_matrices(matrices: &mut [f64], offset: [f64; 3]) {
+    for matrix in matrices.chunks_exact_mut(16) {
+        for row in 0..3 {
+            let linear: f64 = (0..3).map(|c| matrix[row * 4 + c] * offset[c]).sum();
+            matrix[row * 4 + 3] += offset[row] - linear;
+        }
+    }
+}
+
+/// Adds `offset` to the `keys` present in the config, on their axis
+fn shift_keys(
+    config: &mut ConfigType,
+    keys: &[(&str, usize)],
+    offset: [f64; 3],
+) -> Result<(), HallrError> {
+    for (key, axis) in keys.iter() {
+        if let Some(value) = config.get_parsed_option::<f64>(key)? {
+            let _ = config.insert(key.to_string(), (value + offset[*axis]).to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Runs the command on the double precision input, see the module documentation
+pub(crate) fn process_command_f64(
+    vertices: &[FFIVector3d],
+    indices: &[usize],
+    matrix: &[f64],
+    attributes: &Attributes,
+    mut config: ConfigType,
+) -> Result<(CommandResultF64, Attributes), HallrError> {
+    let origin = origin(vertices);
+    let negative = [-origin[0], -origin[1], -origin[2]];
+    info!("Rust: moving the input by {:?}", negative);
+    let shifted_vertices: Vec<FFIVector3> = vertices
+        .iter()
+        .map(|v| {
+            FFIVector3::new(
+                (v.x - origin[0]) as f32,
+                (v.y - origin[1]) as f32,
+                (v.z - origin[2]) as f32,
+            )
+        })
+        .collect();
+    let mut shifted_matrix = matrix.to_vec();
+    shift_matrices(&mut shifted_matrix, negative);
+    let shifted_matrix: Vec<f32> = shifted_matrix.into_iter().map(|m| m as f32).collect();
+    shift_keys(&mut config, POSITION_PARAMETERS, negative)?;
+    let origin_value = format!("{},{},{}", origin[0], origin[1], origin[2]);
+    let _ = config.insert(ORIGIN_SHIFT_KEY.to_string(), origin_value.clone());
+
+    let ((vertices, indices, matrix, mut config), output_attributes): (CommandResult, _) =
+        super::process_command(
+            &shifted_vertices,
+            indices,
+            &shifted_matrix,
+            attributes,
+            config,
+        )?;
+
+    let vertices = vertices
+        .into_iter()
+        .map(|v| {
+            FFIVector3d::new(
+                v.x as f64 + origin[0],
+                v.y as f64 + origin[1],
+                v.z as f64 + origin[2],
+            )
+        })
+        .collect();
+    let mut matrix: Vec<f64> = matrix.into_iter().map(|m| m as f64).collect();
+    shift_matrices(&mut matrix, origin);
+    shift_keys(&mut config, POSITION_RESULTS, origin)?;
+    let _ = config.insert(ORIGIN_SHIFT_KEY.to_string(), origin_value);
+    Ok(((vertices, indices, matrix, config), output_attributes))
+}
@@ -0,0 +1,85 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{origin, process_command_f64, shift_matrices, ORIGIN_SHIFT_KEY};
+use crate::{
+    command::{attributes::Attributes, ConfigType},
+    ffi::FFIVector3d,
+    HallrError,
+};
+
+#[test]
+fn test_origin() {
+    let vertices: Vec<FFIVector3d> = vec![
+        (1.0, -2.0, 10.0).into(),
+        (3.0, 2.0, 20.0).into(),
+        (f64::NAN, 100.0, 100.0).into(),
+    ];
+    assert_eq!([2.0, 0.0, 15.0], origin(&vertices));
+    assert_eq!([0.0; 3], origin(&[]));
+}
+
+/// Transform the point by the row major matrix
+fn transform(m: &[f64], p: [f64; 3]) -> [f64; 3] {
+    let row = |r: usize| m[r * 4] * p[0] + m[r * 4 + 1] * p[1] + m[r * 4 + 2] * p[2] + m[r * 4 + 3];
+    [row(0), row(1), row(2)]
+}
+
+#[test]
+fn test_shift_matrices() {
+    // a rotation of 90 degrees around z, and a translation, row by row
+    let matrix = vec![
+        0.0, -1.0, 0.0, 5.0, //
+        1.0, 0.0, 0.0, 6.0, //
+        0.0, 0.0, 1.0, 7.0, //
+        0.0, 0.0, 0.0, 1.0,
+    ];
+    let offset = [-1.0, -2.0, -3.0];
+    let mut shifted = matrix.clone();
+    shift_matrices(&mut shifted, offset);
+    // the moved local point ends up at the moved world position
+    let p = [1.0, 2.0, 3.0];
+    let world = transform(&matrix, p);
+    assert_eq!(
+        [world[0] - 1.0, world[1] - 2.0, world[2] - 3.0],
+        transform(&shifted, [p[0] - 1.0, p[1] - 2.0, p[2] - 3.0])
+    );
+    shift_matrices(&mut shifted, [1.0, 2.0, 3.0]);
+    assert_eq!(matrix, shifted);
+}
+
+#[test]
+fn test_process_command_f64() -> Result<(), HallrError> {
+    // a small triangle far away from the origin, f32 can not hold the detail
+    let vertices: Vec<FFIVector3d> = vec![
+        (1_000_000.0, 2_000_000.0, 0.0).into(),
+        (1_000_000.001, 2_000_000.0, 0.0).into(),
+        (1_000_000.0, 2_000_000.001, 0.0).into(),
+    ];
+    let mut matrix = vec![0.0; 16];
+    for i in [0, 5, 10, 15] {
+        matrix[i] = 1.0;
+    }
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "mesh_analyze".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("CHECK_SELF_INTERSECTIONS".to_string(), "false".to_string());
+    let ((output, indices, output_matrix, config), _) =
+        process_command_f64(&vertices, &[0, 1, 2], &matrix, &Attributes::new(), config)?;
+    assert_eq!(vec![0, 1, 2], indices);
+    assert_eq!(matrix, output_matrix);
+    for (a, b) in vertices.iter().zip(output.iter()) {
+        assert!((a.x - b.x).abs() < 1e-7 && (a.y - b.y).abs() < 1e-7 && a.z == b.z);
+    }
+    let shift: Vec<f64> = config
+        .get(ORIGIN_SHIFT_KEY)
+        .unwrap()
+        .split(',')
+        .map(|v| v.parse::<f64>().unwrap())
+        .collect();
+    assert!((shift[0] - 1_000_000.0005).abs() < 1e-7);
+    assert!((shift[1] - 2_000_000.0005).abs() < 1e-7);
+    assert_eq!(0.0, shift[2]);
+    Ok(())
+}
@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! Per segment statistics of the returned geometry, so that the addon does not have to parse the
+//! raw buffers just to display vertex, edge and face counts.
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    mesh_format::{self, MeshFormat},
+    CommandResult, Options,
+};
+use crate::HallrError;
+use std::{fmt::Write, ops::Range};
+
+/// The key of the statistics in the returned config
+pub(crate) const STATS_KEY: &str = "stats";
+
+/// The statistics of one output model segment
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct SegmentStats {
+    pub(crate) format: String,
+    pub(crate) vertices: usize,
+    pub(crate) indices: usize,
+    pub(crate) edges: usize,
+    pub(crate) faces: usize,
+}
+
+impl SegmentStats {
+    fn new(format: &str, vertices: usize, indices: &[usize]) -> Self {
+        let (edges, faces) = match format.parse::<MeshFormat>() {
+            Ok(MeshFormat::Triangulated) => (0, indices.len() / 3),
+            Ok(MeshFormat::LineChunks) => (indices.len() / 2, 0),
+            Ok(MeshFormat::LineWindows) => (indices.len().saturating_sub(1), 0),
+            Ok(MeshFormat::Ngons) => (0, mesh_format::split_ngons(indices).map_or(0, |f| f.len())),
+            Err(_) => (0, 0),
+        };
+        Self {
+            format: format.to_string(),
+            vertices,
+            indices: indices.len(),
+            edges,
+            faces,
+        }
+    }
+}
+
+/// Split the returned buffers into segments. The segments are defined by the
+/// `first_vertex_model_N` and `first_index_model_N` keys, the same way as the input models.
+/// If those keys are missing, the whole result is one segment.
+/// A segment may override the `mesh.format` of the result with a `mesh.format_model_N` key.
+/// Returns the format, the vertex range and the index range of every segment.
+pub(crate) fn segments(
+    result: &CommandResult,
+) -> Result<Vec<(String, Range<usize>, Range<usize>)>, HallrError> {
+    let (vertices, indices, _, config) = result;
+    let format = config.get_parsed_option::<String>("mesh.format")?;
+    let format = format.as_deref().unwrap_or("unknown");
+    let mut rv = Vec::new();
+    let mut model_counter = 0;
+    while model_counter == 0
+        || config.does_option_exist(&format!("first_vertex_model_{}", model_counter))?
+    {
+        let default = (model_counter == 0).then_some(0);
+        let vertex_start: usize = config.get_mandatory_parsed_option(
+            &format!("first_vertex_model_{}", model_counter),
+            default,
+        )?;
+        let index_start: usize = config.get_mandatory_parsed_option(
+            &format!("first_index_model_{}", model_counter),
+            default,
+        )?;
+        let vertex_end = config
+            .get_parsed_option(&format!("first_vertex_model_{}", model_counter + 1))?
+            .unwrap_or(vertices.len());
+        let index_end = config
+            .get_parsed_option(&format!("first_index_model_{}", model_counter + 1))?
+            .unwrap_or(indices.len());
+        let segment_format =
+            config.get_parsed_option::<String>(&format!("mesh.format_model_{}", model_counter))?;
+        rv.push((
+            segment_format.unwrap_or_else(|| format.to_string()),
+            vertex_start..vertex_end,
+            index_start..index_end,
+        ));
+        model_counter += 1;
+    }
+    Ok(rv)
+}
+
+/// Returns the statistics of every segment of the result
+pub(crate) fn segment_stats(result: &CommandResult) -> Result<Vec<SegmentStats>, HallrError> {
+    Ok(segments(result)?
+        .into_iter()
+        .map(|(format, vertices, indices)| {
+            SegmentStats::new(
+                &format,
+                vertices.end.saturating_sub(vertices.start),
+                result.1.get(indices).unwrap_or_default(),
+            )
+        })
+        .collect())
+}
+
+/// Formats the statistics as a JSON string
+pub(crate) fn stats_to_json(stats: &[SegmentStats]) -> String {
+    let mut rv = String::from("{\"segments\":[");
+    for (i, s) in stats.iter().enumerate() {
+        if i > 0 {
+            rv.push(',');
+        }
+        let _ = write!(
+            rv,
+            "{{\"format\":\"{}\",\"vertices\":{},\"indices\":{},\"edges\":{},\"faces\":{}}}",
+            s.format.replace('\\', "\\\\").replace('"', "\\\""),
+            s.vertices,
+            s.indices,
+            s.edges,
+            s.faces
+        );
+    }
+    rv.push_str("]}");
+    rv
+}
+
+/// Insert the segment statistics into the returned config
+pub(crate) fn add_stats(result: &mut CommandResult) -> Result<(), HallrError> {
+    let stats = stats_to_json(&segment_stats(result)?);
+    let _ = result.3.insert(STATS_KEY.to_string(), stats);
+    Ok(())
+}
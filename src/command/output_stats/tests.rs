@@ -0,0 +1,83 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{add_stats, segment_stats, SegmentStats, STATS_KEY};
+use crate::{command::ConfigType, ffi::FFIVector3, HallrError};
+
+#[test]
+fn test_output_stats_1() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let mut result = (
+        vec![FFIVector3::default(); 4],
+        vec![0, 1, 2, 0, 2, 3],
+        vec![],
+        config,
+    );
+    add_stats(&mut result)?;
+    let expected = concat!(
+        r#"{"segments":[{"format":"triangulated","#,
+        r#""vertices":4,"indices":6,"edges":0,"faces":2}]}"#
+    );
+    assert_eq!(Some(&expected.to_string()), result.3.get(STATS_KEY));
+    Ok(())
+}
+
+#[test]
+fn test_output_stats_2() -> Result<(), HallrError> {
+    // two line_chunks segments
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("first_vertex_model_1".to_string(), "3".to_string());
+    let _ = config.insert("first_index_model_1".to_string(), "4".to_string());
+    let result = (
+        vec![FFIVector3::default(); 5],
+        vec![0, 1, 1, 2, 3, 4],
+        vec![],
+        config,
+    );
+    let stats = segment_stats(&result)?;
+    assert_eq!(
+        vec![
+            SegmentStats {
+                format: "line_chunks".to_string(),
+                vertices: 3,
+                indices: 4,
+                edges: 2,
+                faces: 0
+            },
+            SegmentStats {
+                format: "line_chunks".to_string(),
+                vertices: 2,
+                indices: 2,
+                edges: 1,
+                faces: 0
+            }
+        ],
+        stats
+    );
+    Ok(())
+}
+
+#[test]
+fn test_output_stats_3() -> Result<(), HallrError> {
+    // a triangulated segment followed by a line_chunks segment
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("first_vertex_model_1".to_string(), "3".to_string());
+    let _ = config.insert("first_index_model_1".to_string(), "3".to_string());
+    let _ = config.insert("mesh.format_model_1".to_string(), "line_chunks".to_string());
+    let result = (
+        vec![FFIVector3::default(); 5],
+        vec![0, 1, 2, 3, 4],
+        vec![],
+        config,
+    );
+    let stats = segment_stats(&result)?;
+    assert_eq!(2, stats.len());
+    assert_eq!(1, stats[0].faces);
+    assert_eq!("line_chunks", stats[1].format);
+    assert_eq!(1, stats[1].edges);
+    Ok(())
+}
@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! Commands provided by plugins.
+//!
+//! A plugin registers a command at runtime through `hallr_register_command()`, with a C callback
+//! implementing it. The callback receives the input the way it was sent to hallr: all the models,
+//! their world matrices and the config, after the handling of the non-finite vertices and the
+//! `UNIT_SCALE`. It fills in the output `PluginGeometry`, which stays owned by the plugin: the
+//! output is copied and then handed to the release callback. The result of a plugin command is
+//! post-processed (`POST`, `WELD_IN_RUST`, the statistics) like the result of any other command.
+//!
+//! The Blender addon loads the plugins: every shared library of the plugins directory exporting
+//! `int32_t hallr_register(register_fn)` is called with a pointer to `hallr_register_command()`.
+
+#[cfg(test)]
+mod tests;
+
+use super::{attributes::Attributes, dispatch::HallrCommand, CommandResult, ConfigType, Model};
+use crate::{ffi::FFIVector3, HallrError};
+use std::{
+    ffi::{c_void, CStr, CString},
+    os::raw::c_char,
+    ptr, slice,
+};
+
+/// The geometry exchanged with a plugin command. The config is `config_count` pairs of
+/// null-terminated keys and values. A pointer may be null when its count is zero.
+#[repr(C)]
+pub struct PluginGeometry {
+    pub vertices: *const FFIVector3,
+    pub vertex_count: usize,
+    pub indices: *const usize,
+    pub indices_count: usize,
+    pub matrices: *const f32,
+    pub matrices_count: usize,
+    pub keys: *const *const c_char,
+    pub values: *const *const c_char,
+    pub config_count: usize,
+}
+
+impl PluginGeometry {
+    fn empty() -> Self {
+        Self {
+            vertices: ptr::null(),
+            vertex_count: 0,
+            indices: ptr::null(),
+            indices_count: 0,
+            matrices: ptr::null(),
+            matrices_count: 0,
+            keys: ptr::null(),
+            values: ptr::null(),
+            config_count: 0,
+        }
+    }
+}
+
+/// Runs the plugin command on `input` and fills in `output`, returns 0 on success. On failure
+/// the output config may hold an `ERROR` message.
+pub type PluginCommandFn = unsafe extern "C" fn(
+    input: *const PluginGeometry,
+    output: *mut PluginGeometry,
+    user_data: *mut c_void,
+) -> i32;
+
+/// Releases the output filled in by the `PluginCommandFn`, once it has been copied
+pub type PluginReleaseFn =
+    unsafe extern "C" fn(output: *mut PluginGeometry, user_data: *mut c_void);
+
+/// The opaque pointer passed back to the callbacks of the plugin
+struct UserData(*mut c_void);
+
+// Safety: the plugin is responsible for making its user data safe to use from any thread, the
+// commands may run on worker threads.
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+/// A command registered by a plugin
+pub(crate) struct Plugin {
+    name: &'static str,
+    command: PluginCommandFn,
+    release: Option<PluginReleaseFn>,
+    user_data: UserData,
+}
+
+impl Plugin {
+    pub(crate) fn new(
+        name: &str,
+        command: PluginCommandFn,
+        release: Option<PluginReleaseFn>,
+        user_data: *mut c_void,
+    ) -> Self {
+        Self {
+            // the plugins are registered for the lifetime of the process
+            name: Box::leak(name.to_string().into_boxed_str()),
+            command,
+            release,
+            user_data: UserData(user_data),
+        }
+    }
+}
+
+/// Returns the slice at `data`, which may be null when `len` is zero
+///
+/// # Safety
+/// `data` must point at `len` valid elements
+unsafe fn as_slice<'a, T>(data: *const T, len: usize, name: &str) -> Result<&'a [T], HallrError> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if data.is_null() {
+        return Err(HallrError::PluginError(format!(
+            "The plugin returned {} {} but a null pointer",
+            len, name
+        )));
+    }
+    Ok(slice::from_raw_parts(data, len))
+}
+
+/// Copies the output of the plugin
+///
+/// # Safety
+/// The pointers of `output` must be valid for their counts
+unsafe fn copy_output(output: &PluginGeometry) -> Result<CommandResult, HallrError> {
+    let keys = as_slice(output.keys, output.config_count, "config keys")?;
+    let values = as_slice(output.values, output.config_count, "config values")?;
+    let mut config = ConfigType::with_capacity(output.config_count);
+    for (key, value) in keys.iter().zip(values.iter()) {
+        if key.is_null() || value.is_null() {
+            return Err(HallrError::PluginError(
+                "The plugin returned a null config string".to_string(),
+            ));
+        }
+        let _ = config.insert(
+            CStr::from_ptr(*key).to_string_lossy().into_owned(),
+            CStr::from_ptr(*value).to_string_lossy().into_owned(),
+        );
+    }
+    Ok((
+        as_slice(output.vertices, output.vertex_count, "vertices")?.to_vec(),
+        as_slice(output.indices, output.indices_count, "indices")?.to_vec(),
+        as_slice(output.matrices, output.matrices_count, "matrix elements")?.to_vec(),
+        config,
+    ))
+}
+
+impl HallrCommand for Plugin {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn execute(
+        &self,
+        config: ConfigType,
+        models: Vec<Model<'_>>,
+        _attributes: &Attributes,
+        _output_attributes: &mut Attributes,
+    ) -> Result<CommandResult, HallrError> {
+        // the models are consecutive slices of the input, so they are sent as one buffer
+        let vertices: Vec<FFIVector3> = models
+            .iter()
+            .flat_map(|m| m.vertices.iter().copied())
+            .collect();
+        let indices: Vec<usize> = models
+            .iter()
+            .flat_map(|m| m.indices.iter().copied())
+            .collect();
+        let matrices: Vec<f32> = models
+            .iter()
+            .flat_map(|m| m.world_orientation.iter().copied())
+            .collect();
+        let to_c_string = |s: &String| {
+            CString::new(s.as_str()).map_err(|_| {
+                HallrError::InvalidParameter(format!("The config contains a null byte: {}", s))
+            })
+        };
+        let keys = config
+            .keys()
+            .map(to_c_string)
+            .collect::<Result<Vec<_>, _>>()?;
+        let values = config
+            .values()
+            .map(to_c_string)
+            .collect::<Result<Vec<_>, _>>()?;
+        let key_ptrs: Vec<*const c_char> = keys.iter().map(|k| k.as_ptr()).collect();
+        let value_ptrs: Vec<*const c_char> = values.iter().map(|v| v.as_ptr()).collect();
+        let input = PluginGeometry {
+            vertices: vertices.as_ptr(),
+            vertex_count: vertices.len(),
+            indices: indices.as_ptr(),
+            indices_count: indices.len(),
+            matrices: matrices.as_ptr(),
+            matrices_count: matrices.len(),
+            keys: key_ptrs.as_ptr(),
+            values: value_ptrs.as_ptr(),
+            config_count: key_ptrs.len(),
+        };
+        let mut output = PluginGeometry::empty();
+        // Safety: the input is valid during the call, the plugin fills in the output
+        let status = unsafe { (self.command)(&input, &mut output, self.user_data.0) };
+        // Safety: the plugin keeps the output valid until it is released
+        let rv = unsafe { copy_output(&output) };
+        if let Some(release) = self.release {
+            // Safety: the output was filled in by the plugin
+            unsafe { release(&mut output, self.user_data.0) };
+        }
+        if status != 0 {
+            let message = rv
+                .ok()
+                .and_then(|mut rv| rv.3.remove("ERROR"))
+                .unwrap_or_else(|| format!("The command failed with status {}", status));
+            return Err(HallrError::PluginError(format!(
+                "{}: {}",
+                self.name, message
+            )));
+        }
+        rv
+    }
+}
@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{Plugin, PluginGeometry};
+use crate::{
+    command::{attributes::Attributes, dispatch, registry, ConfigType, OwnedModel},
+    HallrError,
+};
+use std::{
+    ffi::{c_void, CString},
+    os::raw::c_char,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Returns the input as the output
+unsafe extern "C" fn echo(
+    input: *const PluginGeometry,
+    output: *mut PluginGeometry,
+    _user_data: *mut c_void,
+) -> i32 {
+    let input = &*input;
+    let output = &mut *output;
+    output.vertices = input.vertices;
+    output.vertex_count = input.vertex_count;
+    output.indices = input.indices;
+    output.indices_count = input.indices_count;
+    output.matrices = input.matrices;
+    output.matrices_count = input.matrices_count;
+    output.keys = input.keys;
+    output.values = input.values;
+    output.config_count = input.config_count;
+    0
+}
+
+/// The error message returned by `fail()`
+struct Failure {
+    keys: [*const c_char; 1],
+    values: [*const c_char; 1],
+}
+
+/// Fails with the error message of the `Failure` in the user data
+unsafe extern "C" fn fail(
+    _input: *const PluginGeometry,
+    output: *mut PluginGeometry,
+    user_data: *mut c_void,
+) -> i32 {
+    let failure = &*(user_data as *const Failure);
+    (*output).keys = failure.keys.as_ptr();
+    (*output).values = failure.values.as_ptr();
+    (*output).config_count = 1;
+    1
+}
+
+/// Counts the releases in the `AtomicUsize` of the user data
+unsafe extern "C" fn release(_output: *mut PluginGeometry, user_data: *mut c_void) {
+    let _ = (*(user_data as *const AtomicUsize)).fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+fn test_plugin_execute() -> Result<(), HallrError> {
+    static RELEASED: AtomicUsize = AtomicUsize::new(0);
+    let user_data = &RELEASED as *const AtomicUsize as *mut c_void;
+    dispatch::register(Box::new(Plugin::new(
+        "test_echo_plugin",
+        echo,
+        Some(release),
+        user_data,
+    )))?;
+    // the name is taken now, and so are the names of the built in commands
+    assert!(dispatch::register(Box::new(Plugin::new(
+        "test_echo_plugin",
+        echo,
+        None,
+        user_data
+    )))
+    .is_err());
+    assert!(
+        dispatch::register(Box::new(Plugin::new("centerline", echo, None, user_data))).is_err()
+    );
+    assert!(dispatch::register(Box::new(Plugin::new("a/v2", echo, None, user_data))).is_err());
+    assert_eq!(
+        ("test_echo_plugin", 1),
+        registry::resolve("test_echo_plugin/v1")?
+    );
+    assert!(registry::list_commands().3["PLUGINS"]
+        .split(',')
+        .any(|p| p == "test_echo_plugin"));
+
+    let model = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![
+            (0.0, 0.0, 0.0).into(),
+            (1.0, 0.0, 0.0).into(),
+            (0.0, 1.0, 0.0).into(),
+        ],
+        indices: vec![0, 1, 2],
+    };
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "test_echo_plugin".to_string());
+    let (result, _) =
+        dispatch::dispatch_command(config, vec![model.as_model()], &Attributes::new())?;
+    assert_eq!(model.vertices, result.0);
+    assert_eq!(model.indices, result.1);
+    assert_eq!(model.world_orientation.to_vec(), result.2);
+    assert_eq!("test_echo_plugin", result.3["command"]);
+    assert_eq!(1, RELEASED.load(Ordering::SeqCst));
+    Ok(())
+}
+
+#[test]
+fn test_plugin_error() -> Result<(), HallrError> {
+    // the failure outlives the test, the plugin can not be unregistered
+    let failure = Box::leak(Box::new(Failure {
+        keys: [CString::new("ERROR").unwrap().into_raw()],
+        values: [CString::new("no can do").unwrap().into_raw()],
+    }));
+    let user_data = failure as *mut Failure as *mut c_void;
+    dispatch::register(Box::new(Plugin::new(
+        "test_failing_plugin",
+        fail,
+        None,
+        user_data,
+    )))?;
+    let model = OwnedModel {
+        world_orientation: OwnedModel::identity_matrix(),
+        vertices: vec![],
+        indices: vec![],
+    };
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "test_failing_plugin".to_string());
+    match dispatch::dispatch_command(config, vec![model.as_model()], &Attributes::new()) {
+        Err(HallrError::PluginError(message)) => {
+            assert_eq!("test_failing_plugin: no can do", message)
+        }
+        _ => panic!("expected a plugin error"),
+    }
+    Ok(())
+}
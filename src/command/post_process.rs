@@ -0,0 +1,198 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! A post-processing pipeline applied to the result of any command.
+//!
+//! The steps are listed in the `POST` option, comma separated and applied in order, e.g.
+//! `POST="weld:0.001,flip_normals"`:
+//! * `weld` or `weld:<distance>` welds the vertices of every output segment, with the
+//!   `REMOVE_DOUBLES_THRESHOLD` of the command when no distance is given.
+//! * `triangulate` splits the n-gon faces into triangle fans.
+//! * `flip_normals` reverses the winding of the faces, lines are left as they are.
+//! * `to_local` transforms a world space result into the local frame of the first input model,
+//!   and returns it with that world matrix and `LOCAL_FRAME=true`.
+//!
+//! The steps run on the result in document units, after `REMOVE_DOUBLES` handling and before
+//! the `mesh.output_format` conversion.
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    mesh_format::{self, MeshFormat},
+    output_stats, weld, CommandResult, Model, Options, LOCAL_FRAME_KEY,
+};
+use crate::{ffi::FFIVector3, HallrError};
+use vector_traits::glam::{DMat4, DVec3};
+
+/// The option listing the post-processing steps
+pub(crate) const POST_KEY: &str = "POST";
+
+/// One step of the post-processing pipeline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Step {
+    /// Weld within the distance, or the threshold of the command
+    Weld(Option<f32>),
+    Triangulate,
+    FlipNormals,
+    ToLocal,
+}
+
+/// Parse the comma separated steps of the `POST` option
+pub(crate) fn parse_steps(value: &str) -> Result<Vec<Step>, HallrError> {
+    let mut rv = Vec::new();
+    for step in value.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let (name, argument) = match step.split_once(':') {
+            Some((name, argument)) => (name.trim(), Some(argument.trim())),
+            None => (step, None),
+        };
+        rv.push(match (name, argument) {
+            ("weld", None) => Step::Weld(None),
+            ("weld", Some(argument)) => {
+                let distance = argument.parse::<f32>().map_err(|e| {
+                    HallrError::InvalidParameter(format!(
+                        "Could not parse the weld distance of {}: {}",
+                        POST_KEY, e
+                    ))
+                })?;
+                if !distance.is_finite() || distance < 0.0 {
+                    return Err(HallrError::InvalidParameter(format!(
+                        "The weld distance must be a non-negative number :({})",
+                        argument
+                    )));
+                }
+                Step::Weld(Some(distance))
+            }
+            ("triangulate", None) => Step::Triangulate,
+            ("flip_normals", None) => Step::FlipNormals,
+            ("to_local", None) => Step::ToLocal,
+            _ => {
+                return Err(HallrError::InvalidParameter(format!(
+                    "Unknown {} step: {}",
+                    POST_KEY, step
+                )))
+            }
+        });
+    }
+    Ok(rv)
+}
+
+/// Replace the indices of every segment with the result of `f`, which also returns the new
+/// format of the segment
+fn map_segments(
+    result: &mut CommandResult,
+    f: impl Fn(MeshFormat, &[usize]) -> Result<(MeshFormat, Vec<usize>), HallrError>,
+) -> Result<(), HallrError> {
+    let mut indices = Vec::<usize>::with_capacity(result.1.len());
+    let mut formats = Vec::<MeshFormat>::new();
+    for (n, (format, _, index_range)) in output_stats::segments(result)?.into_iter().enumerate() {
+        if n > 0 {
+            let _ = result.3.insert(
+                format!("first_index_model_{}", n),
+                indices.len().to_string(),
+            );
+        }
+        let segment = result.1.get(index_range).ok_or_else(|| {
+            HallrError::InternalError(format!("The segment {} is out of bounds", n))
+        })?;
+        let (format, mut segment) = f(format.parse::<MeshFormat>()?, segment)?;
+        formats.push(format);
+        indices.append(&mut segment);
+    }
+    result.1 = indices;
+    // the format of the first segment is the default of the rest
+    if let Some(first) = formats.first() {
+        let _ = result
+            .3
+            .insert("mesh.format".to_string(), first.to_string());
+    }
+    for (n, format) in formats.iter().enumerate() {
+        let key = format!("mesh.format_model_{}", n);
+        if Some(format) == formats.first() {
+            let _ = result.3.remove(&key);
+        } else {
+            let _ = result.3.insert(key, format.to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Transform the world space result into the local frame of the `world_orientation` matrix.
+/// A result already returned with a world matrix of its own is left as it is.
+fn to_local(result: &mut CommandResult, world_orientation: &[f32]) -> Result<(), HallrError> {
+    if result.3.get(LOCAL_FRAME_KEY).map(|v| v.as_str()) == Some("true")
+        || !(result.2.is_empty() || Model::is_identity_matrix(&result.2))
+    {
+        return Ok(());
+    }
+    let matrix = world_orientation.get(0..16).ok_or_else(|| {
+        HallrError::InvalidInputData(
+            "The provided world orientation matrix was of the wrong size".to_string(),
+        )
+    })?;
+    let mut cols = [0.0_f64; 16];
+    for (c, m) in cols.iter_mut().zip(matrix.iter()) {
+        *c = *m as f64;
+    }
+    // the matrix is sent row by row
+    let world = DMat4::from_cols_array(&cols).transpose();
+    if world.determinant().abs() < f64::EPSILON {
+        return Err(HallrError::InvalidInputData(
+            "The world orientation matrix can not be inverted".to_string(),
+        ));
+    }
+    let inverse = world.inverse();
+    for v in result.0.iter_mut() {
+        let p = inverse.transform_point3(DVec3::new(v.x as f64, v.y as f64, v.z as f64));
+        *v = FFIVector3::new(p.x as f32, p.y as f32, p.z as f32);
+    }
+    result.2 = matrix.to_vec();
+    let _ = result
+        .3
+        .insert(LOCAL_FRAME_KEY.to_string(), "true".to_string());
+    Ok(())
+}
+
+/// Apply the steps to the result. `world_orientation` is the world matrix of the first input
+/// model.
+pub(crate) fn apply(
+    result: &mut CommandResult,
+    steps: &[Step],
+    world_orientation: &[f32],
+) -> Result<(), HallrError> {
+    for step in steps.iter() {
+        info!("Rust: post-processing {:?}", step);
+        match step {
+            Step::Weld(threshold) => weld::weld_segments(result, *threshold)?,
+            Step::Triangulate => map_segments(result, |format, indices| match format {
+                MeshFormat::Ngons => Ok((
+                    MeshFormat::Triangulated,
+                    mesh_format::convert(indices, format, MeshFormat::Triangulated)?,
+                )),
+                _ => Ok((format, indices.to_vec())),
+            })?,
+            Step::FlipNormals => map_segments(result, |format, indices| {
+                Ok((
+                    format,
+                    match format {
+                        MeshFormat::Triangulated => indices
+                            .chunks_exact(3)
+                            .flat_map(|t| [t[0], t[2], t[1]])
+                            .collect(),
+                        MeshFormat::Ngons => {
+                            let faces: Vec<Vec<usize>> = mesh_format::split_ngons(indices)?
+                                .into_iter()
+                                .map(|face| face.iter().rev().copied().collect())
+                                .collect();
+                            mesh_format::faces_to_ngons(&faces)
+                        }
+                        MeshFormat::LineChunks | MeshFormat::LineWindows => indices.to_vec(),
+                    },
+                ))
+            })?,
+            Step::ToLocal => to_local(result, world_orientation)?,
+        }
+    }
+    Ok(())
+}
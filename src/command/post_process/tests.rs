@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{apply, parse_steps, Step};
+use crate::{command::ConfigType, HallrError};
+
+#[test]
+fn test_parse_steps() -> Result<(), HallrError> {
+    assert_eq!(
+        vec![
+            Step::Weld(Some(0.001)),
+            Step::FlipNormals,
+            Step::Weld(None),
+            Step::Triangulate,
+            Step::ToLocal
+        ],
+        parse_steps("weld:0.001, flip_normals,weld,triangulate,to_local")?
+    );
+    assert!(parse_steps("")?.is_empty());
+    assert!(parse_steps("smooth").is_err());
+    assert!(parse_steps("weld:-1").is_err());
+    assert!(parse_steps("flip_normals:1").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_post_process_segments() -> Result<(), HallrError> {
+    // an ngons segment with a duplicated vertex, followed by a line_chunks segment
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "ngons".to_string());
+    let _ = config.insert("first_vertex_model_1".to_string(), "5".to_string());
+    let _ = config.insert("first_index_model_1".to_string(), "5".to_string());
+    let _ = config.insert("mesh.format_model_1".to_string(), "line_chunks".to_string());
+    let mut result = (
+        vec![
+            (0.0, 0.0, 0.0).into(),
+            (1.0, 0.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (0.0, 1.0, 0.0).into(),
+            (0.0, 0.0005, 0.0).into(),
+            // the line
+            (0.0, 0.0, 1.0).into(),
+            (1.0, 0.0, 1.0).into(),
+        ],
+        vec![4, 0, 1, 2, 3, 0, 1],
+        vec![],
+        config,
+    );
+    apply(
+        &mut result,
+        &parse_steps("weld:0.001,triangulate,flip_normals")?,
+        &[],
+    )?;
+    assert_eq!(4 + 2, result.0.len());
+    assert_eq!(vec![0, 2, 1, 0, 3, 2, 4, 5], result.1);
+    assert_eq!("triangulated", result.3.get("mesh.format").unwrap());
+    assert_eq!("line_chunks", result.3.get("mesh.format_model_1").unwrap());
+    assert_eq!("4", result.3.get("first_vertex_model_1").unwrap());
+    assert_eq!("6", result.3.get("first_index_model_1").unwrap());
+    assert_eq!("1", result.3.get("WELDED_VERTICES").unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_post_process_to_local() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let mut result = (
+        vec![(1.0, 2.0, 3.0).into(), (3.0, 2.0, 3.0).into()],
+        vec![0, 1],
+        vec![],
+        config,
+    );
+    // scaled by 2 and translated by (1,2,3), row by row
+    let world = [
+        2.0, 0.0, 0.0, 1.0, //
+        0.0, 2.0, 0.0, 2.0, //
+        0.0, 0.0, 2.0, 3.0, //
+        0.0, 0.0, 0.0, 1.0,
+    ];
+    apply(&mut result, &[Step::ToLocal], &world)?;
+    assert_eq!(
+        (0.0, 0.0, 0.0),
+        (result.0[0].x, result.0[0].y, result.0[0].z)
+    );
+    assert_eq!(
+        (1.0, 0.0, 0.0),
+        (result.0[1].x, result.0[1].y, result.0[1].z)
+    );
+    assert_eq!(world.to_vec(), result.2);
+    assert_eq!("true", result.3.get("LOCAL_FRAME").unwrap());
+
+    // a result already in the local frame is not transformed again
+    apply(&mut result, &[Step::ToLocal], &world)?;
+    assert_eq!(
+        (1.0, 0.0, 0.0),
+        (result.0[1].x, result.0[1].y, result.0[1].z)
+    );
+    Ok(())
+}
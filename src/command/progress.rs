@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! Progress reporting and cancellation of long running commands.
+//!
+//! The caller can register a callback with `hallr_set_progress_callback`, it is then invoked with
+//! the percent completed of the running command. The callback may be invoked from the rayon
+//! worker threads, so it must be thread safe (e.g. only store the value for the UI thread to
+//! read). Without a registered callback the reports are no-ops.
+//!
+//! `hallr_request_cancel` asks the running commands to stop, the heavy loops check the request
+//! cooperatively and the command returns `HallrError::Cancelled`. The request is cleared when
+//! the next command starts.
+
+#[cfg(test)]
+mod tests;
+
+use crate::HallrError;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    Mutex,
+};
+
+/// The signature of the progress callback, the argument is the percent completed (0..=100)
+pub type ProgressCallback = extern "C" fn(percent: u32);
+
+static PROGRESS_CALLBACK: Mutex<Option<ProgressCallback>> = Mutex::new(None);
+static CANCEL_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Register the progress callback, `None` removes it
+pub(crate) fn set_callback(callback: Option<ProgressCallback>) {
+    *PROGRESS_CALLBACK.lock().unwrap() = callback;
+}
+
+/// Report the percent completed to the registered callback, if any
+pub(crate) fn report(percent: u32) {
+    // the lock is not held while the callback runs
+    let callback = *PROGRESS_CALLBACK.lock().unwrap();
+    if let Some(callback) = callback {
+        callback(percent.min(100));
+    }
+}
+
+/// Ask the running commands to stop
+pub(crate) fn request_cancel() {
+    CANCEL_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Forget any earlier cancel request. Called by the entry points on the calling thread before a
+/// command is started, so that a request made as soon as the call returns is not lost.
+pub(crate) fn clear_cancel() {
+    CANCEL_REQUESTED.store(false, Ordering::Relaxed);
+}
+
+/// Returns true if the running commands should stop
+pub(crate) fn is_cancelled() -> bool {
+    CANCEL_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Returns `HallrError::Cancelled` if the running commands should stop
+pub(crate) fn check_cancelled() -> Result<(), HallrError> {
+    if is_cancelled() {
+        return Err(HallrError::Cancelled);
+    }
+    Ok(())
+}
+
+/// Counts the completed work items of a (possibly parallel) loop, and reports every new percent
+pub(crate) struct Progress {
+    total: usize,
+    done: AtomicUsize,
+    reported: AtomicU32,
+}
+
+impl Progress {
+    /// A progress counter of `total` work items
+    pub(crate) fn new(total: usize) -> Self {
+        Self {
+            total,
+            done: AtomicUsize::new(0),
+            reported: AtomicU32::new(0),
+        }
+    }
+
+    /// Count one completed item. Returns the percent completed if it has not been returned
+    /// before.
+    pub(crate) fn step(&self) -> Option<u32> {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        let percent = (done.min(self.total) * 100 / self.total.max(1)) as u32;
+        let previous = self.reported.fetch_max(percent, Ordering::Relaxed);
+        (percent > previous).then_some(percent)
+    }
+
+    /// Count one completed item, and report the percent completed when it changes
+    pub(crate) fn tick(&self) {
+        if let Some(percent) = self.step() {
+            report(percent);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::Progress;
+use std::sync::Mutex;
+
+static REPORTED: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+extern "C" fn record(percent: u32) {
+    REPORTED.lock().unwrap().push(percent);
+}
+
+#[test]
+fn test_progress_step() {
+    let progress = Progress::new(3);
+    assert_eq!(Some(33), progress.step());
+    assert_eq!(Some(66), progress.step());
+    assert_eq!(Some(100), progress.step());
+    // extra items never report more than 100%
+    assert_eq!(None, progress.step());
+
+    // only the first item of every percent is reported
+    let progress = Progress::new(1000);
+    let reported = (0..1000).filter_map(|_| progress.step()).count();
+    assert_eq!(100, reported);
+}
+
+#[test]
+fn test_progress_callback() {
+    // other tests may report while the callback is registered, so only look for our own value
+    super::set_callback(Some(record));
+    super::report(42);
+    super::set_callback(None);
+    assert!(REPORTED.lock().unwrap().contains(&42));
+}
@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! Triangle quality metrics of the returned geometry, requested with `QUALITY_REPORT=true`.
+//! The edge lengths and the aspect ratios of the triangles of every triangulated output segment
+//! are summarized, so that remesh and SDF settings can be checked without exporting the result
+//! into an analysis tool.
+
+#[cfg(test)]
+mod tests;
+
+use super::{output_stats, CommandResult};
+use crate::{ffi::FFIVector3, HallrError};
+use ahash::AHashSet;
+use std::fmt::Write;
+
+/// The option requesting the report
+pub(crate) const QUALITY_REPORT_KEY: &str = "QUALITY_REPORT";
+/// The key of the report in the returned config
+pub(crate) const QUALITY_KEY: &str = "quality";
+/// The number of buckets of the edge length histogram, spanning the min..max edge length
+const EDGE_LENGTH_BUCKETS: usize = 10;
+/// The lower limits of the aspect ratio buckets, the last bucket is unbounded
+const ASPECT_RATIO_BUCKETS: [f64; 6] = [1.0, 1.5, 2.0, 3.0, 5.0, 10.0];
+/// Triangles with a larger aspect ratio than this (or without area) are slivers
+const SLIVER_ASPECT_RATIO: f64 = 10.0;
+
+/// The quality metrics of a set of triangles
+#[derive(Debug, PartialEq)]
+pub(crate) struct QualityReport {
+    pub(crate) triangles: usize,
+    pub(crate) edges: usize,
+    pub(crate) edge_length_min: f64,
+    pub(crate) edge_length_max: f64,
+    pub(crate) edge_length_mean: f64,
+    pub(crate) edge_length_histogram: [usize; EDGE_LENGTH_BUCKETS],
+    pub(crate) aspect_ratio_histogram: [usize; ASPECT_RATIO_BUCKETS.len()],
+    pub(crate) slivers: usize,
+}
+
+/// Returns the aspect ratio of the triangle: the longest edge times the perimeter divided by the
+/// area, normalized so that an equilateral triangle is 1.0. Triangles without area are infinite.
+pub(crate) fn aspect_ratio(a: &FFIVector3, b: &FFIVector3, c: &FFIVector3) -> f64 {
+    let d = |p: &FFIVector3, q: &FFIVector3| {
+        let (x, y, z) = ((p.x - q.x) as f64, (p.y - q.y) as f64, (p.z - q.z) as f64);
+        (x * x + y * y + z * z).sqrt()
+    };
+    let (ab, bc, ca) = (d(a, b), d(b, c), d(c, a));
+    let s = (ab + bc + ca) * 0.5;
+    // Heron's formula
+    let area = (s * (s - ab) * (s - bc) * (s - ca)).max(0.0).sqrt();
+    if area <= f64::EPSILON * s * s {
+        return f64::INFINITY;
+    }
+    ab.max(bc).max(ca) * 2.0 * s / (4.0 * 3.0_f64.sqrt() * area)
+}
+
+/// Summarize the triangles of `indices` (in the triangulated format), the edges shared by
+/// triangles are only counted once
+pub(crate) fn quality_report(vertices: &[FFIVector3], indices: &[usize]) -> QualityReport {
+    let mut edges = AHashSet::<(usize, usize)>::default();
+    let mut rv = QualityReport {
+        triangles: 0,
+        edges: 0,
+        edge_length_min: 0.0,
+        edge_length_max: 0.0,
+        edge_length_mean: 0.0,
+        edge_length_histogram: [0; EDGE_LENGTH_BUCKETS],
+        aspect_ratio_histogram: [0; ASPECT_RATIO_BUCKETS.len()],
+        slivers: 0,
+    };
+    for t in indices.chunks_exact(3) {
+        if t.iter().any(|i| *i >= vertices.len()) {
+            continue;
+        }
+        rv.triangles += 1;
+        for (a, b) in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+            let _ = edges.insert((a.min(b), a.max(b)));
+        }
+        let ratio = aspect_ratio(&vertices[t[0]], &vertices[t[1]], &vertices[t[2]]);
+        if ratio > SLIVER_ASPECT_RATIO {
+            rv.slivers += 1;
+        }
+        let bucket = ASPECT_RATIO_BUCKETS
+            .iter()
+            .rposition(|limit| ratio >= *limit)
+            .unwrap_or(0);
+        rv.aspect_ratio_histogram[bucket] += 1;
+    }
+    let lengths: Vec<f64> = edges
+        .iter()
+        .map(|(a, b)| {
+            let (a, b) = (&vertices[*a], &vertices[*b]);
+            let (x, y, z) = ((a.x - b.x) as f64, (a.y - b.y) as f64, (a.z - b.z) as f64);
+            (x * x + y * y + z * z).sqrt()
+        })
+        .collect();
+    if !lengths.is_empty() {
+        rv.edges = lengths.len();
+        rv.edge_length_min = lengths.iter().copied().fold(f64::MAX, f64::min);
+        rv.edge_length_max = lengths.iter().copied().fold(0.0, f64::max);
+        rv.edge_length_mean = lengths.iter().sum::<f64>() / lengths.len() as f64;
+        let span = rv.edge_length_max - rv.edge_length_min;
+        for length in lengths {
+            let bucket = if span > 0.0 {
+                (((length - rv.edge_length_min) / span) * EDGE_LENGTH_BUCKETS as f64) as usize
+            } else {
+                0
+            };
+            rv.edge_length_histogram[bucket.min(EDGE_LENGTH_BUCKETS - 1)] += 1;
+        }
+    }
+    rv
+}
+
+/// Formats the report as a JSON string
+pub(crate) fn report_to_json(report: &QualityReport) -> String {
+    let list = |values: &mut dyn Iterator<Item = String>| values.collect::<Vec<_>>().join(",");
+    let mut rv = String::new();
+    let _ = write!(
+        rv,
+        "{{\"triangles\":{},\"edges\":{},\"edge_length\":{{\"min\":{},\"max\":{},\"mean\":{},\
+         \"histogram\":[{}]}},\"aspect_ratio\":{{\"buckets\":[{}],\"histogram\":[{}]}},\
+         \"slivers\":{}}}",
+        report.triangles,
+        report.edges,
+        report.edge_length_min,
+        report.edge_length_max,
+        report.edge_length_mean,
+        list(&mut report.edge_length_histogram.iter().map(|c| c.to_string())),
+        list(&mut ASPECT_RATIO_BUCKETS.iter().map(|b| b.to_string())),
+        list(&mut report.aspect_ratio_histogram.iter().map(|c| c.to_string())),
+        report.slivers
+    );
+    rv
+}
+
+/// Insert the quality report of the triangulated segments of the result into the returned config
+pub(crate) fn add_quality_report(result: &mut CommandResult) -> Result<(), HallrError> {
+    let mut triangles = Vec::<usize>::new();
+    for (format, _, indices) in output_stats::segments(result)? {
+        if format == "triangulated" {
+            triangles.extend_from_slice(result.1.get(indices).unwrap_or_default());
+        }
+    }
+    let report = report_to_json(&quality_report(&result.0, &triangles));
+    let _ = result.3.insert(QUALITY_KEY.to_string(), report);
+    Ok(())
+}
@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{add_quality_report, aspect_ratio, quality_report, QUALITY_KEY};
+use crate::{command::ConfigType, ffi::FFIVector3, HallrError};
+
+#[test]
+fn test_quality_report_1() {
+    let equilateral = [
+        FFIVector3::new(0.0, 0.0, 0.0),
+        FFIVector3::new(1.0, 0.0, 0.0),
+        FFIVector3::new(0.5, 0.75_f32.sqrt(), 0.0),
+    ];
+    assert!((aspect_ratio(&equilateral[0], &equilateral[1], &equilateral[2]) - 1.0).abs() < 1e-6);
+    let flat = [
+        FFIVector3::new(0.0, 0.0, 0.0),
+        FFIVector3::new(1.0, 0.0, 0.0),
+        FFIVector3::new(2.0, 0.0, 0.0),
+    ];
+    assert!(aspect_ratio(&flat[0], &flat[1], &flat[2]).is_infinite());
+
+    // a unit square and a sliver
+    let vertices = vec![
+        FFIVector3::new(0.0, 0.0, 0.0),
+        FFIVector3::new(1.0, 0.0, 0.0),
+        FFIVector3::new(1.0, 1.0, 0.0),
+        FFIVector3::new(0.0, 1.0, 0.0),
+        FFIVector3::new(2.0, 0.0, 0.0),
+        FFIVector3::new(4.0, 0.01, 0.0),
+    ];
+    let report = quality_report(&vertices, &[0, 1, 2, 0, 2, 3, 1, 4, 5]);
+    assert_eq!(3, report.triangles);
+    // the diagonal is shared
+    assert_eq!(8, report.edges);
+    assert_eq!(1, report.slivers);
+    assert_eq!(1.0, report.edge_length_min);
+    assert!((report.edge_length_max - 3.0).abs() < 1e-4);
+    assert_eq!(8, report.edge_length_histogram.iter().sum::<usize>());
+    // the right angled triangles have an aspect ratio of about 1.39
+    assert_eq!(2, report.aspect_ratio_histogram[0]);
+    assert_eq!(1, report.aspect_ratio_histogram[5]);
+}
+
+#[test]
+fn test_quality_report_2() -> Result<(), HallrError> {
+    // only the triangulated segment is reported
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("first_vertex_model_1".to_string(), "3".to_string());
+    let _ = config.insert("first_index_model_1".to_string(), "3".to_string());
+    let _ = config.insert("mesh.format_model_1".to_string(), "line_chunks".to_string());
+    let mut result = (
+        vec![
+            FFIVector3::new(0.0, 0.0, 0.0),
+            FFIVector3::new(1.0, 0.0, 0.0),
+            FFIVector3::new(0.0, 1.0, 0.0),
+            FFIVector3::new(5.0, 0.0, 0.0),
+            FFIVector3::new(15.0, 0.0, 0.0),
+        ],
+        vec![0, 1, 2, 3, 4],
+        vec![],
+        config,
+    );
+    add_quality_report(&mut result)?;
+    let report = result.3.get(QUALITY_KEY).unwrap();
+    assert!(report.starts_with(r#"{"triangles":1,"edges":3,"edge_length":{"min":1,"#));
+    assert!(report.ends_with(r#""slivers":0}"#));
+    Ok(())
+}
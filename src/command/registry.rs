@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! Versioned command names and command aliases.
+//!
+//! A command can be requested as `name` (the latest version) or as `name/vN`. Aliases are
+//! alternative names of a command, kept so that old scripts continue to work after a rename.
+//! The name is resolved once in `process_command()`: the commands only see the canonical name in
+//! the `command` key, and the selected version in `command.version`.
+//!
+//! The commands registered by plugins have the single version 1, and no aliases.
+//!
+//! The `list_commands` command returns the supported commands, versions and aliases.
+
+#[cfg(test)]
+mod tests;
+
+use super::{dispatch, CommandResult, ConfigType};
+use crate::{api, HallrError};
+
+/// The introspection command
+pub(crate) const LIST_COMMANDS: &str = "list_commands";
+/// The key of the selected command version, inserted into the config of the command
+pub(crate) const COMMAND_VERSION_KEY: &str = "command.version";
+
+/// The versions of the introspection command
+const LIST_COMMANDS_VERSIONS: &[u32] = &[1];
+
+/// The built in commands, the introspection command and the commands of the plugins, with their
+/// supported versions. The last version is the default.
+pub(crate) fn commands() -> Vec<(&'static str, &'static [u32])> {
+    dispatch::builtins()
+        .map(|command| (command.name(), command.versions()))
+        .chain([(LIST_COMMANDS, LIST_COMMANDS_VERSIONS)])
+        .chain(
+            dispatch::plugins()
+                .into_iter()
+                .map(|command| (command.name(), command.versions())),
+        )
+        .collect()
+}
+
+/// Alternative command names, and the command they refer to
+const ALIASES: &[(&str, &str)] = &[
+    ("delaunay_triangulation_2d", "2d_delaunay_triangulation"),
+    ("outline_2d", "2d_outline"),
+];
+
+/// Resolve a requested command name, `name` or `name/vN`, into the canonical command name and
+/// version
+pub(crate) fn resolve(requested: &str) -> Result<(&'static str, u32), HallrError> {
+    let (name, version) = match requested.split_once('/') {
+        Some((name, version)) => {
+            let version = version
+                .strip_prefix('v')
+                .and_then(|v| v.parse::<u32>().ok())
+                .ok_or_else(|| {
+                    HallrError::InvalidParameter(format!(
+                        "Invalid command version:{}, the format is name/vN",
+                        requested
+                    ))
+                })?;
+            (name, Some(version))
+        }
+        None => (requested, None),
+    };
+    let name = name_or_alias(name);
+    let (canonical, versions) = commands()
+        .into_iter()
+        .find(|(command, _)| *command == name)
+        .ok_or_else(|| HallrError::InvalidParameter(format!("Invalid command:{}", requested)))?;
+    let latest = *versions.last().unwrap();
+    match version {
+        None => Ok((canonical, latest)),
+        Some(version) if versions.contains(&version) => Ok((canonical, version)),
+        Some(version) => Err(HallrError::InvalidParameter(format!(
+            "Version {} of the {} command is not supported, the supported versions are: {}",
+            version,
+            canonical,
+            format_versions(canonical, versions)
+        ))),
+    }
+}
+
+/// Returns true if `name` is a command or an alias of this crate
+pub(crate) fn is_builtin(name: &str) -> bool {
+    name == LIST_COMMANDS
+        || dispatch::builtins().any(|command| command.name() == name)
+        || ALIASES.iter().any(|(alias, _)| *alias == name)
+}
+
+fn name_or_alias(name: &str) -> &str {
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, command)| command)
+}
+
+fn format_versions(name: &str, versions: &[u32]) -> String {
+    versions
+        .iter()
+        .map(|v| format!("{}/v{}", name, v))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Run the list_commands command
+/// Returns no geometry, the config contains `COMMANDS`: every supported command version as
+/// `name/vN`, `LATEST`: the default `name/vN` of every command and `ALIASES`: the aliases as
+/// `alias=name`. All the lists are comma separated. The commands registered by plugins are
+/// included, and listed by name in `PLUGINS`.
+///
+/// Every command is also described by `formats.<name>`, the accepted mesh formats of the first
+/// model (empty for any format), and `params.<name>`, its parameters as
+/// `KEY:type:required|optional[:min..=max]`, see `api::ParamType` for the types.
+pub(crate) fn list_commands() -> CommandResult {
+    let commands = commands();
+    let mut return_config = ConfigType::new();
+    let _ = return_config.insert(
+        "COMMANDS".to_string(),
+        commands
+            .iter()
+            .map(|(name, versions)| format_versions(name, versions))
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    let _ = return_config.insert(
+        "LATEST".to_string(),
+        commands
+            .iter()
+            .map(|(name, versions)| format!("{}/v{}", name, versions.last().unwrap()))
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    let _ = return_config.insert(
+        "PLUGINS".to_string(),
+        dispatch::plugins()
+            .iter()
+            .map(|command| command.name())
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    let _ = return_config.insert(
+        "ALIASES".to_string(),
+        ALIASES
+            .iter()
+            .map(|(alias, name)| format!("{}={}", alias, name))
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    for info in api::list_commands() {
+        let _ = return_config.insert(
+            format!("formats.{}", info.name),
+            info.formats
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        let _ = return_config.insert(
+            format!("params.{}", info.name),
+            info.params
+                .iter()
+                .map(|p| {
+                    let mut param = format!(
+                        "{}:{}:{}",
+                        p.key,
+                        p.param_type,
+                        if p.required { "required" } else { "optional" }
+                    );
+                    if let Some((min, max)) = p.range {
+                        param.push_str(&format!(":{}..={}", min, max));
+                    }
+                    param
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+    }
+    (vec![], vec![], vec![], return_config)
+}
@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{list_commands, resolve};
+use crate::{
+    command::{attributes::Attributes, ConfigType},
+    HallrError,
+};
+
+#[test]
+fn test_registry_resolve() -> Result<(), HallrError> {
+    assert_eq!(("centerline", 1), resolve("centerline")?);
+    assert_eq!(("centerline", 1), resolve("centerline/v1")?);
+    assert_eq!(
+        ("2d_delaunay_triangulation", 1),
+        resolve("delaunay_triangulation_2d/v1")?
+    );
+    assert_eq!(("2d_outline", 1), resolve("outline_2d")?);
+    // unknown versions and commands
+    assert!(resolve("centerline/v9").is_err());
+    assert!(resolve("centerline/1").is_err());
+    assert!(resolve("centerline/").is_err());
+    assert!(resolve("no_such_command").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_registry_list_commands() -> Result<(), HallrError> {
+    let (vertices, indices, _, config) = list_commands();
+    assert!(vertices.is_empty());
+    assert!(indices.is_empty());
+    let commands: Vec<&str> = config.get("COMMANDS").unwrap().split(',').collect();
+    assert!(commands.contains(&"surface_scan/v1"));
+    assert!(commands.contains(&"list_commands/v1"));
+    assert!(config
+        .get("ALIASES")
+        .unwrap()
+        .split(',')
+        .any(|a| a == "outline_2d=2d_outline"));
+    assert_eq!("line_chunks", config["formats.centerline"]);
+    assert_eq!("", config["formats.convex_hull_2d"]);
+    assert!(config["params.centerline"]
+        .split(',')
+        .any(|p| p == "ANGLE:float:required:0..=90"));
+    assert!(config["params.hatch"]
+        .split(',')
+        .any(|p| p == "HATCH_PATTERN:enum(PARALLEL|CROSSHATCH|CONCENTRIC|HILBERT):required"));
+    assert_eq!("", config["params.convex_hull_2d"]);
+
+    // the introspection does not need any geometry
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "list_commands".to_string());
+    let (result, _) = crate::command::process_command(&[], &[], &[], &Attributes::new(), config)?;
+    assert!(result.3.contains_key("LATEST"));
+    Ok(())
+}
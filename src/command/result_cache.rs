@@ -0,0 +1,262 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! An opt-in, in-process LRU cache of command results.
+//!
+//! The cache is keyed by a hash of the command name, the configuration and the raw input data.
+//! Every entry also stores a fingerprint of the input (the sizes and a second, independent hash)
+//! that is compared on a hit, so that a hash collision is a miss instead of the result of another
+//! input.
+//! It is only consulted when the caller sets `CACHE_SIZE_MB` to a non-zero value, so the default
+//! behaviour of the (otherwise stateless) API is unchanged. The commands reading or writing files
+//! (any `*_PATH` option) and the recorded sessions bypass the cache, they must run every time.
+
+#[cfg(test)]
+mod tests;
+
+use super::{session::RECORD_SESSION_DIR_KEY, CommandResult, ConfigType};
+use crate::ffi::FFIVector3;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    mem,
+    sync::Mutex,
+};
+
+/// The config key used to enable the cache, the value is the cache budget in megabytes.
+pub(crate) const CACHE_SIZE_MB_KEY: &str = "CACHE_SIZE_MB";
+/// The key inserted into the returned config when the result was fetched from the cache.
+pub(crate) const CACHE_HIT_KEY: &str = "CACHE_HIT";
+
+static RESULT_CACHE: Mutex<Option<ResultCache>> = Mutex::new(None);
+
+/// The identity of a command input, see `hash_input()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CacheKey {
+    /// The key of the entry
+    hash: u64,
+    /// Compared on a hit
+    fingerprint: Fingerprint,
+}
+
+impl CacheKey {
+    /// The 64 bit hash of the input
+    pub(crate) fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    vertex_count: usize,
+    index_count: usize,
+    matrix_count: usize,
+    config_count: usize,
+    /// A FNV-1a hash of the input, independent of the `DefaultHasher` of the key
+    hash: u64,
+}
+
+/// The 64 bit FNV-1a hash
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+struct CacheEntry {
+    fingerprint: Fingerprint,
+    result: CommandResult,
+    size: usize,
+    last_used: u64,
+}
+
+/// A size bounded least-recently-used cache of `CommandResult`
+#[derive(Default)]
+pub(crate) struct ResultCache {
+    entries: HashMap<u64, CacheEntry>,
+    /// the sum of the sizes of all the entries, in bytes
+    total_size: usize,
+    /// a monotonically increasing counter used to track the usage order
+    clock: u64,
+}
+
+impl ResultCache {
+    /// Returns a copy of the cached result, if any
+    pub(crate) fn get(&mut self, key: CacheKey) -> Option<CommandResult> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries
+            .get_mut(&key.hash)
+            .filter(|entry| entry.fingerprint == key.fingerprint)
+            .map(|entry| {
+                entry.last_used = clock;
+                entry.result.clone()
+            })
+    }
+
+    /// Stores the result and evicts the least recently used entries until the cache fits
+    /// inside `budget` bytes. Results larger than the budget are never stored.
+    pub(crate) fn insert(&mut self, key: CacheKey, result: &CommandResult, budget: usize) {
+        let size = result_size(result);
+        if size > budget {
+            return;
+        }
+        self.clock += 1;
+        if let Some(old) = self.entries.insert(
+            key.hash,
+            CacheEntry {
+                fingerprint: key.fingerprint,
+                result: result.clone(),
+                size,
+                last_used: self.clock,
+            },
+        ) {
+            self.total_size -= old.size;
+        }
+        self.total_size += size;
+        self.evict(budget);
+    }
+
+    /// Evict the least recently used entries until the cache fits inside `budget` bytes
+    pub(crate) fn evict(&mut self, budget: usize) {
+        while self.total_size > budget {
+            let lru_key = match self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key)
+            {
+                Some(key) => key,
+                None => break,
+            };
+            if let Some(entry) = self.entries.remove(&lru_key) {
+                self.total_size -= entry.size;
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn total_size(&self) -> usize {
+        self.total_size
+    }
+}
+
+/// Returns the approximate heap size of a `CommandResult`, in bytes
+pub(crate) fn result_size(result: &CommandResult) -> usize {
+    let (vertices, indices, matrices, config) = result;
+    vertices.len() * mem::size_of::<FFIVector3>()
+        + indices.len() * mem::size_of::<usize>()
+        + matrices.len() * mem::size_of::<f32>()
+        + config
+            .iter()
+            .map(|(k, v)| k.len() + v.len() + 2 * mem::size_of::<String>())
+            .sum::<usize>()
+}
+
+/// Feeds the command input, without the cache control keys, to the hasher
+fn hash_into<H: Hasher>(
+    hasher: &mut H,
+    vertices: &[FFIVector3],
+    indices: &[usize],
+    matrix: &[f32],
+    config: &ConfigType,
+) {
+    let mut keys: Vec<_> = config
+        .keys()
+        .filter(|k| k.as_str() != CACHE_SIZE_MB_KEY)
+        .collect();
+    keys.sort_unstable();
+    for key in keys {
+        key.hash(hasher);
+        config[key].hash(hasher);
+    }
+    vertices.len().hash(hasher);
+    for v in vertices {
+        v.x.to_bits().hash(hasher);
+        v.y.to_bits().hash(hasher);
+        v.z.to_bits().hash(hasher);
+    }
+    indices.hash(hasher);
+    matrix.len().hash(hasher);
+    for m in matrix {
+        m.to_bits().hash(hasher);
+    }
+}
+
+/// Calculates the key of the command input. The cache control keys are excluded so that changing
+/// the cache budget does not invalidate the stored results.
+pub(crate) fn hash_input(
+    vertices: &[FFIVector3],
+    indices: &[usize],
+    matrix: &[f32],
+    config: &ConfigType,
+) -> CacheKey {
+    let mut hasher = DefaultHasher::new();
+    hash_into(&mut hasher, vertices, indices, matrix, config);
+    let mut fnv = Fnv1a::default();
+    hash_into(&mut fnv, vertices, indices, matrix, config);
+    CacheKey {
+        hash: hasher.finish(),
+        fingerprint: Fingerprint {
+            vertex_count: vertices.len(),
+            index_count: indices.len(),
+            matrix_count: matrix.len(),
+            config_count: config.len() - usize::from(config.contains_key(CACHE_SIZE_MB_KEY)),
+            hash: fnv.finish(),
+        },
+    }
+}
+
+/// Returns true if running the command has side effects: it reads or writes a file, or the
+/// session is recorded. Such a command is never served from the cache.
+pub(crate) fn has_side_effects(config: &ConfigType) -> bool {
+    config
+        .keys()
+        .any(|key| key.ends_with("_PATH") || key == RECORD_SESSION_DIR_KEY)
+}
+
+/// Look up a previously stored result
+pub(crate) fn lookup(key: CacheKey) -> Option<CommandResult> {
+    RESULT_CACHE
+        .lock()
+        .ok()
+        .and_then(|mut cache| cache.as_mut().and_then(|c| c.get(key)))
+}
+
+/// Store a result, `budget_mb` is the total size of the cache in megabytes
+pub(crate) fn store(key: CacheKey, result: &CommandResult, budget_mb: usize) {
+    if let Ok(mut cache) = RESULT_CACHE.lock() {
+        cache.get_or_insert_with(ResultCache::default).insert(
+            key,
+            result,
+            budget_mb.saturating_mul(1024 * 1024),
+        );
+    }
+}
+
+/// Drop every cached result
+pub(crate) fn clear() {
+    if let Ok(mut cache) = RESULT_CACHE.lock() {
+        *cache = None;
+    }
+}
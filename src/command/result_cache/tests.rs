@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{hash_input, result_size, CacheKey, ResultCache, CACHE_HIT_KEY, CACHE_SIZE_MB_KEY};
+use crate::{
+    command::{attributes::Attributes, process_command, CommandResult, ConfigType},
+    ffi::FFIVector3,
+    HallrError,
+};
+
+/// The key of a single vertex input, `n` is in the config
+fn dummy_key(n: usize) -> CacheKey {
+    let mut config = ConfigType::new();
+    let _ = config.insert("n".to_string(), n.to_string());
+    hash_input(&[(1.0, 2.0, 3.0).into()], &[0], &[], &config)
+}
+
+fn dummy_result(vertex_count: usize) -> CommandResult {
+    (
+        vec![(1.0, 2.0, 3.0).into(); vertex_count],
+        (0..vertex_count).collect(),
+        vec![],
+        ConfigType::new(),
+    )
+}
+
+#[test]
+fn test_result_cache_1() {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "centerline".to_string());
+    let vertices = vec![(1.0, 2.0, 3.0).into(), (3.0, 2.0, 1.0).into()];
+    let key_a = hash_input(&vertices, &[0, 1], &[], &config);
+
+    // the cache size option should not affect the key
+    let _ = config.insert(CACHE_SIZE_MB_KEY.to_string(), "10".to_string());
+    assert_eq!(key_a, hash_input(&vertices, &[0, 1], &[], &config));
+
+    let _ = config.insert("ANGLE".to_string(), "89".to_string());
+    assert_ne!(key_a, hash_input(&vertices, &[0, 1], &[], &config));
+    assert_ne!(key_a, hash_input(&vertices, &[1, 0], &[], &config));
+}
+
+#[test]
+fn test_result_cache_2() {
+    let mut cache = ResultCache::default();
+    let size = result_size(&dummy_result(100));
+    // room for exactly two results
+    let budget = size * 2;
+
+    cache.insert(dummy_key(1), &dummy_result(100), budget);
+    cache.insert(dummy_key(2), &dummy_result(100), budget);
+    assert_eq!(2, cache.len());
+    assert_eq!(size * 2, cache.total_size());
+
+    // touch #1 so that #2 becomes the least recently used entry
+    assert!(cache.get(dummy_key(1)).is_some());
+    cache.insert(dummy_key(3), &dummy_result(100), budget);
+    assert_eq!(2, cache.len());
+    assert!(cache.get(dummy_key(2)).is_none());
+    assert!(cache.get(dummy_key(1)).is_some());
+    assert!(cache.get(dummy_key(3)).is_some());
+
+    // results larger than the budget are never stored
+    cache.insert(dummy_key(4), &dummy_result(1000), budget);
+    assert!(cache.get(dummy_key(4)).is_none());
+    assert_eq!(size * 2, cache.total_size());
+}
+
+#[test]
+fn test_result_cache_collision() {
+    let mut cache = ResultCache::default();
+    let key = dummy_key(1);
+    cache.insert(key, &dummy_result(10), 1024 * 1024);
+    assert!(cache.get(key).is_some());
+    // an input with the same hash but another fingerprint is a miss
+    let mut other = dummy_key(2);
+    other.hash = key.hash;
+    assert!(cache.get(other).is_none());
+}
+
+#[test]
+fn test_result_cache_output_attributes() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "voronoi_mesh".to_string());
+    let _ = config.insert("DISTANCE".to_string(), "0.2864788911621093".to_string());
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let _ = config.insert("CELL_IDS".to_string(), "true".to_string());
+    let _ = config.insert(CACHE_SIZE_MB_KEY.to_string(), "10".to_string());
+    let vertices: Vec<FFIVector3> = vec![
+        (-1.3491066, -0.42415974, 0.0).into(),
+        (0.42415974, -1.3491066, 0.0).into(),
+        (-0.42415974, 1.3491066, 0.0).into(),
+        (1.3491066, 0.42415974, 0.0).into(),
+    ];
+    let indices = vec![2, 0, 0, 1, 1, 3, 3, 2];
+    let mut matrix = vec![0.0; 16];
+    for i in [0, 5, 10, 15] {
+        matrix[i] = 1.0;
+    }
+    let (first, first_attributes) = process_command(
+        &vertices,
+        &indices,
+        &matrix,
+        &Attributes::new(),
+        config.clone(),
+    )?;
+    // the result with the cell ids is not cached, the second call returns the channel as well
+    let (second, second_attributes) =
+        process_command(&vertices, &indices, &matrix, &Attributes::new(), config)?;
+    assert!(!second.3.contains_key(CACHE_HIT_KEY));
+    assert_eq!(first.1, second.1);
+    assert!(first_attributes.contains_key("VORONOI_CELL"));
+    assert_eq!(first_attributes, second_attributes);
+    Ok(())
+}
+
+#[test]
+fn test_result_cache_side_effects() -> Result<(), HallrError> {
+    let path = std::env::temp_dir().join(format!("hallr_cache_test_{}.obj", std::process::id()));
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "obj_io".to_string());
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("OBJ_MODE".to_string(), "SAVE".to_string());
+    let _ = config.insert("OBJ_PATH".to_string(), path.to_str().unwrap().to_string());
+    let _ = config.insert(CACHE_SIZE_MB_KEY.to_string(), "10".to_string());
+    let vertices: Vec<FFIVector3> = vec![
+        (0.0, 0.0, 0.0).into(),
+        (1.0, 0.0, 0.0).into(),
+        (1.0, 1.0, 0.0).into(),
+    ];
+    let indices = vec![0, 1, 2];
+    let mut matrix = vec![0.0; 16];
+    for i in [0, 5, 10, 15] {
+        matrix[i] = 1.0;
+    }
+    let _ = process_command(
+        &vertices,
+        &indices,
+        &matrix,
+        &Attributes::new(),
+        config.clone(),
+    )?;
+    std::fs::remove_file(&path).unwrap();
+    // the second save writes the file again
+    let second = process_command(&vertices, &indices, &matrix, &Attributes::new(), config);
+    let written = path.exists();
+    let _ = std::fs::remove_file(&path);
+    let (second, _) = second?;
+    assert!(!second.3.contains_key(CACHE_HIT_KEY));
+    assert!(written);
+    Ok(())
+}
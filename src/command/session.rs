@@ -0,0 +1,363 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! Recording of command sessions, and the canonical output hash used to replay them as
+//! regression tests.
+//!
+//! When `RECORD_SESSION_DIR` is set, the raw input of the command (config, vertices, indices,
+//! matrices and binary attributes) is written to a `.session` file in that directory, together
+//! with a golden hash of the result. Sessions copied into the `corpus` directory of the crate are replayed by
+//! `cargo test --features corpus_tests`, and the output is compared against the golden hash.
+//!
+//! The hash is calculated on a canonical form of the output: the vertices are quantized to a grid
+//! of `RECORD_SESSION_QUANTUM` (default 1e-4) and the primitives are described by their quantized
+//! coordinates, sorted. So the vertex and primitive order, and small numerical noise, does not
+//! change the hash.
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    attributes::Attributes,
+    mesh_format::{self, MeshFormat},
+    result_cache, CommandResult, ConfigType, Options,
+};
+use crate::{ffi::FFIVector3, HallrError};
+use std::{fs, path::Path};
+
+/// The option used to record the session, the value is the output directory
+pub(crate) const RECORD_SESSION_DIR_KEY: &str = "RECORD_SESSION_DIR";
+/// The option setting the quantization grid of the golden hash
+pub(crate) const RECORD_SESSION_QUANTUM_KEY: &str = "RECORD_SESSION_QUANTUM";
+/// The default quantization grid of the golden hash
+const DEFAULT_QUANTUM: f32 = 1e-4;
+/// The first line of every session file
+const SESSION_HEADER: &str = "hallr-session 1";
+
+/// A recorded command: the raw input data and the expected output
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Session {
+    pub(crate) config: ConfigType,
+    pub(crate) vertices: Vec<FFIVector3>,
+    pub(crate) indices: Vec<usize>,
+    pub(crate) matrices: Vec<f32>,
+    pub(crate) attributes: Attributes,
+    /// The canonical hash of the output, and the quantization grid it was calculated with
+    pub(crate) golden: Option<(u64, f32)>,
+}
+
+/// The 64 bit FNV-1a hash. Unlike the std hashers, the output is stable between Rust releases.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_i64(&mut self, value: i64) {
+        self.write(&value.to_le_bytes());
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut rv = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => rv.push('\n'),
+                Some(c) => rv.push(c),
+                None => rv.push('\\'),
+            }
+        } else {
+            rv.push(c);
+        }
+    }
+    rv
+}
+
+fn parse_value<T: std::str::FromStr>(value: &str, line_number: usize) -> Result<T, HallrError> {
+    value.parse::<T>().map_err(|_| {
+        HallrError::InvalidInputData(format!(
+            "Could not parse \"{}\" on line {} of the session",
+            value, line_number
+        ))
+    })
+}
+
+impl Session {
+    /// Serialize the session into the line based text format
+    pub(crate) fn to_text(&self) -> String {
+        let mut rv = String::new();
+        rv.push_str(SESSION_HEADER);
+        rv.push('\n');
+        if let Some((hash, quantum)) = self.golden {
+            rv.push_str(&format!("golden {:016x} {}\n", hash, quantum));
+        }
+        let mut keys: Vec<_> = self.config.keys().collect();
+        keys.sort_unstable();
+        for key in keys {
+            rv.push_str(&format!(
+                "config {}={}\n",
+                escape(key),
+                escape(&self.config[key])
+            ));
+        }
+        for matrix in self.matrices.chunks(16) {
+            rv.push_str("matrix");
+            for m in matrix {
+                rv.push_str(&format!(" {}", m));
+            }
+            rv.push('\n');
+        }
+        for (name, values) in self.attributes.iter() {
+            rv.push_str(&format!("attribute {}=", escape(name)));
+            for (i, v) in values.iter().enumerate() {
+                if i > 0 {
+                    rv.push(' ');
+                }
+                rv.push_str(&v.to_string());
+            }
+            rv.push('\n');
+        }
+        for v in self.vertices.iter() {
+            rv.push_str(&format!("vertex {} {} {}\n", v.x, v.y, v.z));
+        }
+        rv.push_str("indices");
+        for i in self.indices.iter() {
+            rv.push_str(&format!(" {}", i));
+        }
+        rv.push('\n');
+        rv
+    }
+
+    /// Parse a session from the line based text format
+    pub(crate) fn from_text(text: &str) -> Result<Self, HallrError> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, line)| line.trim()) != Some(SESSION_HEADER) {
+            return Err(HallrError::InvalidInputData(
+                "The session does not start with a valid header".to_string(),
+            ));
+        }
+        let mut rv = Self {
+            config: ConfigType::new(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            matrices: Vec::new(),
+            attributes: Attributes::new(),
+            golden: None,
+        };
+        for (line_number, line) in lines {
+            let line_number = line_number + 1;
+            let (tag, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match tag {
+                "" => (),
+                "golden" => {
+                    let (hash, quantum) = rest.split_once(' ').ok_or_else(|| {
+                        HallrError::InvalidInputData(format!(
+                            "Invalid golden hash on line {} of the session",
+                            line_number
+                        ))
+                    })?;
+                    let hash = u64::from_str_radix(hash, 16).map_err(|_| {
+                        HallrError::InvalidInputData(format!(
+                            "Invalid golden hash on line {} of the session",
+                            line_number
+                        ))
+                    })?;
+                    rv.golden = Some((hash, parse_value(quantum, line_number)?));
+                }
+                "config" => {
+                    let (key, value) = rest.split_once('=').ok_or_else(|| {
+                        HallrError::InvalidInputData(format!(
+                            "Invalid config on line {} of the session",
+                            line_number
+                        ))
+                    })?;
+                    let _ = rv.config.insert(unescape(key), unescape(value));
+                }
+                "matrix" => {
+                    for value in rest.split_whitespace() {
+                        rv.matrices.push(parse_value(value, line_number)?);
+                    }
+                }
+                "attribute" => {
+                    // the values never contain a '=', but the name might
+                    let (name, values) = rest.rsplit_once('=').ok_or_else(|| {
+                        HallrError::InvalidInputData(format!(
+                            "Invalid attribute on line {} of the session",
+                            line_number
+                        ))
+                    })?;
+                    let values = values
+                        .split_whitespace()
+                        .map(|value| parse_value::<f32>(value, line_number))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let _ = rv.attributes.insert(unescape(name), values);
+                }
+                "vertex" => {
+                    let values = rest
+                        .split_whitespace()
+                        .map(|value| parse_value::<f32>(value, line_number))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if values.len() != 3 {
+                        return Err(HallrError::InvalidInputData(format!(
+                            "Invalid vertex on line {} of the session",
+                            line_number
+                        )));
+                    }
+                    rv.vertices
+                        .push(FFIVector3::new(values[0], values[1], values[2]));
+                }
+                "indices" => {
+                    for value in rest.split_whitespace() {
+                        rv.indices.push(parse_value(value, line_number)?);
+                    }
+                }
+                tag => {
+                    return Err(HallrError::InvalidInputData(format!(
+                        "Unknown tag \"{}\" on line {} of the session",
+                        tag, line_number
+                    )))
+                }
+            }
+        }
+        Ok(rv)
+    }
+}
+
+/// Returns a hash of the canonical form of the result. The vertices are quantized to a grid of
+/// `quantum`, and every primitive is described by its quantized coordinates. The primitives are
+/// rotated to start at their smallest corner (keeping the winding), and then sorted.
+pub(crate) fn canonical_hash(result: &CommandResult, quantum: f32) -> Result<u64, HallrError> {
+    if !quantum.is_finite() || quantum <= 0.0 {
+        return Err(HallrError::InvalidParameter(format!(
+            "The quantum must be a positive number :({})",
+            quantum
+        )));
+    }
+    let (vertices, indices, _, config) = result;
+    let quantize = |value: f32| -> i64 {
+        if value.is_finite() {
+            (value as f64 / quantum as f64).round() as i64
+        } else {
+            i64::MAX
+        }
+    };
+    let quantized: Vec<[i64; 3]> = vertices
+        .iter()
+        .map(|v| [quantize(v.x), quantize(v.y), quantize(v.z)])
+        .collect();
+    let corner = |i: &usize| -> Result<[i64; 3], HallrError> {
+        quantized.get(*i).copied().ok_or_else(|| {
+            HallrError::InternalError(format!("The index {} of the result is out of bounds", i))
+        })
+    };
+    let format = config.get_mandatory_parsed_option::<MeshFormat>("mesh.format", None)?;
+    let mut primitives: Vec<Vec<[i64; 3]>> = match format {
+        MeshFormat::Triangulated | MeshFormat::Ngons => {
+            let faces = if format == MeshFormat::Ngons {
+                mesh_format::split_ngons(indices)?
+            } else {
+                indices.chunks_exact(3).collect()
+            };
+            faces
+                .into_iter()
+                .map(|face| -> Result<Vec<[i64; 3]>, HallrError> {
+                    let mut face = face.iter().map(&corner).collect::<Result<Vec<_>, _>>()?;
+                    let first = (0..face.len()).min_by_key(|i| face[*i]).unwrap_or(0);
+                    face.rotate_left(first);
+                    Ok(face)
+                })
+                .collect::<Result<_, _>>()?
+        }
+        MeshFormat::LineChunks | MeshFormat::LineWindows => {
+            let edges: Vec<&[usize]> = if format == MeshFormat::LineChunks {
+                indices.chunks_exact(2).collect()
+            } else {
+                indices.windows(2).collect()
+            };
+            edges
+                .into_iter()
+                .map(|edge| -> Result<Vec<[i64; 3]>, HallrError> {
+                    let mut edge = edge.iter().map(&corner).collect::<Result<Vec<_>, _>>()?;
+                    edge.sort_unstable();
+                    Ok(edge)
+                })
+                .collect::<Result<_, _>>()?
+        }
+    };
+    primitives.sort_unstable();
+    let mut sorted_vertices = quantized.clone();
+    sorted_vertices.sort_unstable();
+
+    let mut hasher = Fnv1a::new();
+    hasher.write(format.as_str().as_bytes());
+    hasher.write_i64(sorted_vertices.len() as i64);
+    for v in sorted_vertices.iter().flatten() {
+        hasher.write_i64(*v);
+    }
+    hasher.write_i64(primitives.len() as i64);
+    for primitive in primitives.iter() {
+        hasher.write_i64(primitive.len() as i64);
+        for v in primitive.iter().flatten() {
+            hasher.write_i64(*v);
+        }
+    }
+    Ok(hasher.0)
+}
+
+/// Write the input, and the golden hash of the result, into a session file in the
+/// `RECORD_SESSION_DIR` directory. The recording options are not recorded.
+/// Returns the path of the session file.
+pub(crate) fn record(
+    vertices: &[FFIVector3],
+    indices: &[usize],
+    matrices: &[f32],
+    attributes: &Attributes,
+    config: &ConfigType,
+    result: &CommandResult,
+) -> Result<String, HallrError> {
+    let dir = config.get_mandatory_option(RECORD_SESSION_DIR_KEY)?;
+    let quantum = config
+        .get_mandatory_parsed_option::<f32>(RECORD_SESSION_QUANTUM_KEY, Some(DEFAULT_QUANTUM))?;
+    let mut session_config = config.clone();
+    let _ = session_config.remove(RECORD_SESSION_DIR_KEY);
+    let _ = session_config.remove(RECORD_SESSION_QUANTUM_KEY);
+    let _ = session_config.remove(result_cache::CACHE_SIZE_MB_KEY);
+    let session = Session {
+        vertices: vertices.to_vec(),
+        indices: indices.to_vec(),
+        matrices: matrices.to_vec(),
+        attributes: attributes.clone(),
+        golden: Some((canonical_hash(result, quantum)?, quantum)),
+        config: session_config,
+    };
+    let file_name = format!(
+        "{}_{:016x}.session",
+        session
+            .config
+            .get_mandatory_option("command")?
+            .replace(|c: char| !c.is_ascii_alphanumeric() && c != '_', "_"),
+        result_cache::hash_input(vertices, indices, matrices, &session.config).hash()
+    );
+    let path = Path::new(dir).join(file_name);
+    fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&path, session.to_text()))
+        .map_err(|e| {
+            HallrError::InternalError(format!("Could not record the session in {}: {}", dir, e))
+        })?;
+    Ok(path.display().to_string())
+}
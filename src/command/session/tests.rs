@@ -0,0 +1,161 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{canonical_hash, Session};
+use crate::{
+    command::{attributes::Attributes, CommandResult, ConfigType},
+    ffi::FFIVector3,
+    HallrError,
+};
+
+fn triangle_result(vertices: Vec<FFIVector3>, indices: Vec<usize>) -> CommandResult {
+    let mut config = ConfigType::new();
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    (vertices, indices, vec![], config)
+}
+
+#[test]
+fn test_session_text() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "centerline".to_string());
+    let _ = config.insert("NAME".to_string(), "a=b\nc\\d".to_string());
+    let session = Session {
+        config,
+        vertices: vec![
+            FFIVector3::new(0.1, -2.5, 3.0e-7),
+            FFIVector3::new(f32::MAX, 0.0, -0.0),
+        ],
+        indices: vec![0, 1, 1, 0],
+        matrices: (0..16).map(|i| i as f32 * 0.5).collect(),
+        attributes: [
+            ("weights".to_string(), vec![0.25, -1.0e-9, 3.0]),
+            ("a=b c".to_string(), vec![]),
+        ]
+        .into_iter()
+        .collect(),
+        golden: Some((0x0123_4567_89ab_cdef, 1e-4)),
+    };
+    let parsed = Session::from_text(&session.to_text())?;
+    assert_eq!(session, parsed);
+    assert!(Session::from_text("not a session").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_session_canonical_hash() -> Result<(), HallrError> {
+    let vertices = vec![
+        FFIVector3::new(0.0, 0.0, 0.0),
+        FFIVector3::new(1.0, 0.0, 0.0),
+        FFIVector3::new(1.0, 1.0, 0.0),
+        FFIVector3::new(0.0, 1.0, 0.5),
+    ];
+    let hash = canonical_hash(
+        &triangle_result(vertices.clone(), vec![0, 1, 2, 0, 2, 3]),
+        1e-4,
+    )?;
+
+    // the same mesh, with the vertices and triangles in another order
+    let reordered = vec![vertices[3], vertices[2], vertices[1], vertices[0]];
+    assert_eq!(
+        hash,
+        canonical_hash(&triangle_result(reordered, vec![3, 1, 0, 2, 1, 3]), 1e-4)?
+    );
+    // noise smaller than the quantum
+    let noisy: Vec<FFIVector3> = vertices
+        .iter()
+        .map(|v| FFIVector3::new(v.x + 1e-6, v.y, v.z))
+        .collect();
+    assert_eq!(
+        hash,
+        canonical_hash(&triangle_result(noisy, vec![0, 1, 2, 0, 2, 3]), 1e-4)?
+    );
+    // a flipped triangle is a different mesh
+    assert_ne!(
+        hash,
+        canonical_hash(
+            &triangle_result(vertices.clone(), vec![0, 2, 1, 0, 2, 3]),
+            1e-4
+        )?
+    );
+    assert!(canonical_hash(&triangle_result(vertices, vec![0, 1, 4]), 1e-4).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_session_record() -> Result<(), HallrError> {
+    let dir = std::env::temp_dir().join(format!("hallr_session_test_{}", std::process::id()));
+    let mut config = ConfigType::default();
+    let _ = config.insert("command".to_string(), "convex_hull_2d".to_string());
+    let _ = config.insert(
+        super::RECORD_SESSION_DIR_KEY.to_string(),
+        dir.display().to_string(),
+    );
+    let vertices = vec![
+        FFIVector3::new(0.0, 0.0, 0.0),
+        FFIVector3::new(1.0, 0.0, 0.0),
+        FFIVector3::new(1.0, 1.0, 0.0),
+    ];
+    let result = triangle_result(vertices.clone(), vec![0, 1, 2]);
+    let path = super::record(
+        &vertices,
+        &[0, 1, 2],
+        &[0.0; 16],
+        &Attributes::new(),
+        &config,
+        &result,
+    )?;
+
+    let session = Session::from_text(&std::fs::read_to_string(&path).unwrap())?;
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(!session.config.contains_key(super::RECORD_SESSION_DIR_KEY));
+    assert_eq!(vertices, session.vertices);
+    assert_eq!(Some((canonical_hash(&result, 1e-4)?, 1e-4)), session.golden);
+    Ok(())
+}
+
+/// Replay every recorded session of the corpus directory, and compare the output against the
+/// golden hash
+#[cfg(feature = "corpus_tests")]
+#[test]
+fn test_session_corpus() -> Result<(), HallrError> {
+    let corpus = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+    let mut paths: Vec<_> = std::fs::read_dir(&corpus)
+        .map_err(|e| HallrError::InternalError(format!("{}: {}", corpus.display(), e)))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "session"))
+        .collect();
+    paths.sort();
+
+    let mut failures = Vec::<String>::new();
+    for path in paths.iter() {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| HallrError::InternalError(format!("{}: {}", path.display(), e)))?;
+        let session = Session::from_text(&text)?;
+        let Some((golden, quantum)) = session.golden else {
+            failures.push(format!("{}: no golden hash", path.display()));
+            continue;
+        };
+        match crate::command::process_command(
+            &session.vertices,
+            &session.indices,
+            &session.matrices,
+            &session.attributes,
+            session.config.clone(),
+        )
+        .and_then(|(result, _)| canonical_hash(&result, quantum))
+        {
+            Ok(hash) if hash == golden => (),
+            Ok(hash) => failures.push(format!(
+                "{}: the output hash {:016x} does not match the golden hash {:016x}",
+                path.display(),
+                hash,
+                golden
+            )),
+            Err(err) => failures.push(format!("{}: {}", path.display(), err)),
+        }
+    }
+    info!("Session corpus: replayed {} sessions", paths.len());
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+    Ok(())
+}
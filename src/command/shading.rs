@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! Vertex normals and UVs through the binary attribute channel.
+//!
+//! The addon sends the custom normals as the `NORMALS` channel (x, y, z per vertex) and the UVs as
+//! the `UVS` channel (u, v per vertex). When the first input model is triangulated and the command
+//! does not return the channel itself, it is carried over to the output: every output vertex
+//! gets the value at the closest point of the input triangles, interpolated from the corners.
+//! The normals are normalized after the interpolation. Vertices that did not move keep their
+//! value, so commands that change the topology (remeshing, offsets) keep the shading as well.
+
+#[cfg(test)]
+mod tests;
+
+use super::{attributes::Attributes, CommandResult, ConfigType, Options};
+use crate::{ffi::FFIVector3, utils::bvh::Bvh, HallrError};
+use rayon::prelude::*;
+use vector_traits::glam::{dvec3, DVec3};
+
+/// The per vertex normal channel, three values per vertex
+pub(crate) const NORMALS_KEY: &str = "NORMALS";
+/// The per vertex UV channel, two values per vertex
+pub(crate) const UVS_KEY: &str = "UVS";
+
+/// The shading channels and their number of values per vertex
+const CHANNELS: [(&str, usize); 2] = [(NORMALS_KEY, 3), (UVS_KEY, 2)];
+
+/// Returns the weights of the corners of the triangle at `p`, which is on the triangle
+fn barycentric(p: DVec3, t: &[DVec3; 3]) -> [f64; 3] {
+    let (v0, v1, v2) = (t[1] - t[0], t[2] - t[0], p - t[0]);
+    let (d00, d01, d11) = (v0.dot(v0), v0.dot(v1), v1.dot(v1));
+    let (d20, d21) = (v2.dot(v0), v2.dot(v1));
+    let denominator = d00 * d11 - d01 * d01;
+    if denominator.abs() <= f64::EPSILON * d00 * d11 {
+        // a degenerate triangle, use the closest corner
+        let closest = (0..3)
+            .min_by(|a, b| {
+                p.distance_squared(t[*a])
+                    .total_cmp(&p.distance_squared(t[*b]))
+            })
+            .unwrap_or(0);
+        let mut rv = [0.0; 3];
+        rv[closest] = 1.0;
+        return rv;
+    }
+    let v = (d11 * d20 - d01 * d21) / denominator;
+    let w = (d00 * d21 - d01 * d20) / denominator;
+    [1.0 - v - w, v, w]
+}
+
+/// Carries the `NORMALS` and `UVS` channels of the input over to the vertices of the result,
+/// unless the command returned them. `vertices`, `indices` and `config` are the input of the
+/// command.
+pub(crate) fn transfer_shading(
+    vertices: &[FFIVector3],
+    indices: &[usize],
+    config: &ConfigType,
+    attributes: &Attributes,
+    result: &CommandResult,
+    output_attributes: &mut Attributes,
+) -> Result<(), HallrError> {
+    let channels: Vec<(&str, usize, &Vec<f32>)> = CHANNELS
+        .iter()
+        .filter(|(key, _)| !output_attributes.contains_key(*key))
+        .filter_map(|(key, size)| attributes.get(*key).map(|values| (*key, *size, values)))
+        .collect();
+    if channels.is_empty()
+        || result.0.is_empty()
+        || config.get("mesh.format").map(|f| f.as_str()) != Some("triangulated")
+    {
+        return Ok(());
+    }
+    for (key, size, values) in channels.iter() {
+        if values.len() != size * vertices.len() {
+            return Err(HallrError::InvalidInputData(format!(
+                "The {} channel holds {} values, expected {} for {} vertices",
+                key,
+                values.len(),
+                size * vertices.len(),
+                vertices.len()
+            )));
+        }
+    }
+    // the triangles of the first model, without the non-finite vertices
+    let end = config
+        .get_parsed_option::<usize>("first_index_model_1")?
+        .unwrap_or(indices.len());
+    let triangles: Vec<[usize; 3]> = indices
+        .get(..end)
+        .ok_or_else(|| {
+            HallrError::InvalidInputData("first_index_model_1 is out of bounds".to_string())
+        })?
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+    if let Some(index) = triangles.iter().flatten().find(|i| **i >= vertices.len()) {
+        return Err(HallrError::InvalidInputData(format!(
+            "The index {} is out of bounds",
+            index
+        )));
+    }
+    let triangles: Vec<[usize; 3]> = triangles
+        .into_iter()
+        .filter(|t| {
+            t.iter().all(|i| {
+                let v = &vertices[*i];
+                v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+            })
+        })
+        .collect();
+    let to_dvec3 = |v: &FFIVector3| dvec3(v.x as f64, v.y as f64, v.z as f64);
+    let bvh = Bvh::new(
+        triangles
+            .iter()
+            .map(|t| {
+                [
+                    to_dvec3(&vertices[t[0]]),
+                    to_dvec3(&vertices[t[1]]),
+                    to_dvec3(&vertices[t[2]]),
+                ]
+            })
+            .collect(),
+    );
+    // the input corners and their weights, for every output vertex
+    let weights: Vec<Option<([usize; 3], [f64; 3])>> = result
+        .0
+        .par_iter()
+        .map(|v| {
+            bvh.closest_id(to_dvec3(v)).map(|(closest, id)| {
+                let t = triangles[id];
+                let corners = [
+                    to_dvec3(&vertices[t[0]]),
+                    to_dvec3(&vertices[t[1]]),
+                    to_dvec3(&vertices[t[2]]),
+                ];
+                (t, barycentric(closest, &corners))
+            })
+        })
+        .collect();
+    if weights.iter().any(|w| w.is_none()) {
+        // no input triangles
+        return Ok(());
+    }
+    for (key, size, values) in channels.into_iter() {
+        let mut output = Vec::<f32>::with_capacity(size * weights.len());
+        for (corners, w) in weights.iter().flatten() {
+            let mut value = [0.0_f64; 3];
+            for (corner, weight) in corners.iter().zip(w.iter()) {
+                for (c, v) in value
+                    .iter_mut()
+                    .zip(&values[corner * size..(corner + 1) * size])
+                {
+                    *c += *v as f64 * weight;
+                }
+            }
+            if key == NORMALS_KEY {
+                let n = DVec3::from(value).normalize_or_zero();
+                value = n.to_array();
+            }
+            output.extend(value[..size].iter().map(|c| *c as f32));
+        }
+        info!(
+            "Rust: transferred the {} of {} vertices",
+            key,
+            weights.len()
+        );
+        let _ = output_attributes.insert(key.to_string(), output);
+    }
+    Ok(())
+}
@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{barycentric, transfer_shading, NORMALS_KEY, UVS_KEY};
+use crate::{
+    command::{attributes::Attributes, fixtures, ConfigType},
+    ffi::FFIVector3,
+    HallrError,
+};
+use vector_traits::glam::dvec3;
+
+/// A unit square of two triangles, with the UVs at the xy coordinates and the normals tilted
+/// towards +x on the right side
+fn square() -> (Vec<FFIVector3>, Vec<usize>, ConfigType, Attributes) {
+    let model = fixtures::rectangle_faces([0.0, 0.0], [1.0, 1.0], 0.0);
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let mut attributes = Attributes::new();
+    let _ = attributes.insert(
+        UVS_KEY.to_string(),
+        vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0],
+    );
+    let _ = attributes.insert(
+        NORMALS_KEY.to_string(),
+        vec![0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+    );
+    (model.vertices, model.indices, config, attributes)
+}
+
+#[test]
+fn test_barycentric() {
+    let t = [
+        dvec3(0.0, 0.0, 0.0),
+        dvec3(2.0, 0.0, 0.0),
+        dvec3(0.0, 2.0, 0.0),
+    ];
+    assert_eq!([0.5, 0.25, 0.25], barycentric(dvec3(0.5, 0.5, 0.0), &t));
+    assert_eq!([0.0, 1.0, 0.0], barycentric(dvec3(2.0, 0.0, 0.0), &t));
+    // a degenerate triangle uses the closest corner
+    let line = [
+        dvec3(0.0, 0.0, 0.0),
+        dvec3(1.0, 0.0, 0.0),
+        dvec3(2.0, 0.0, 0.0),
+    ];
+    assert_eq!([0.0, 0.0, 1.0], barycentric(dvec3(1.9, 0.0, 0.0), &line));
+}
+
+#[test]
+fn test_transfer_shading() -> Result<(), HallrError> {
+    let (vertices, indices, config, attributes) = square();
+    // a vertex in the middle of the bottom edge, a moved corner and a point above the square
+    let result = (
+        vec![
+            (0.5, 0.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (0.25, 0.75, 3.0).into(),
+        ],
+        vec![0, 1, 2],
+        vec![],
+        ConfigType::default(),
+    );
+    let mut output_attributes = Attributes::new();
+    transfer_shading(
+        &vertices,
+        &indices,
+        &config,
+        &attributes,
+        &result,
+        &mut output_attributes,
+    )?;
+    let uvs = output_attributes.get(UVS_KEY).unwrap();
+    assert_eq!(6, uvs.len());
+    for (expected, uv) in [0.5, 0.0, 1.0, 1.0, 0.25, 0.75].iter().zip(uvs.iter()) {
+        assert!((expected - uv).abs() < 1e-6, "{:?}", uvs);
+    }
+    let normals = output_attributes.get(NORMALS_KEY).unwrap();
+    let half = std::f32::consts::FRAC_1_SQRT_2;
+    for (expected, n) in [half, 0.0, half, 1.0, 0.0, 0.0].iter().zip(normals.iter()) {
+        assert!((expected - n).abs() < 1e-6, "{:?}", normals);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_transfer_shading_skipped() -> Result<(), HallrError> {
+    let (vertices, indices, mut config, attributes) = square();
+    let result = (
+        vec![(0.5, 0.0, 0.0).into()],
+        vec![],
+        vec![],
+        ConfigType::default(),
+    );
+    // the channels returned by the command are kept
+    let mut output_attributes = Attributes::new();
+    let _ = output_attributes.insert(UVS_KEY.to_string(), vec![7.0, 7.0]);
+    transfer_shading(
+        &vertices,
+        &indices,
+        &config,
+        &attributes,
+        &result,
+        &mut output_attributes,
+    )?;
+    assert_eq!(vec![7.0, 7.0], *output_attributes.get(UVS_KEY).unwrap());
+    assert!(output_attributes.contains_key(NORMALS_KEY));
+
+    // only triangulated input is transferred
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let mut output_attributes = Attributes::new();
+    transfer_shading(
+        &vertices,
+        &indices,
+        &config,
+        &attributes,
+        &result,
+        &mut output_attributes,
+    )?;
+    assert!(output_attributes.is_empty());
+
+    // the channels must match the vertices
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    assert!(transfer_shading(
+        &vertices[..3],
+        &indices[..3],
+        &config,
+        &attributes,
+        &result,
+        &mut output_attributes,
+    )
+    .is_err());
+    Ok(())
+}
@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! Central handling of the document units.
+//!
+//! Blender scenes are modelled in meters or in millimeters, but the commands assume one unit.
+//! `UNIT_SCALE` is the length of one document unit in the canonical unit (the meter, the Blender
+//! unit at a scene unit scale of 1.0), e.g. 0.001 for a scene in millimeters. Before the command
+//! runs, the input vertices, the translations of the world matrices and the length parameters
+//! (given in document units) are scaled into the canonical unit. The output vertices, matrices
+//! and returned lengths are scaled back, and the applied scale is returned as `UNIT_SCALE`.
+//! Without the option, or at 1.0, nothing is changed.
+
+#[cfg(test)]
+mod tests;
+
+use super::{CommandResult, ConfigType, Options};
+use crate::{ffi::FFIVector3, HallrError};
+
+/// The option selecting the unit scale, it is also returned when a scale was applied
+pub(crate) const UNIT_SCALE_KEY: &str = "UNIT_SCALE";
+
+/// The options holding lengths (or comma separated lists of lengths) in document units
+const LENGTH_PARAMETERS: &[&str] = &[
+    "probe_radius",
+    "probe_minor_radius",
+    "probe_sample_step",
+    "blade_offset",
+    "minimum_z",
+    "step",
+    "step_x",
+    "step_y",
+    "return_z",
+    "FLOOR_Z",
+    "BEZIER_TOLERANCE",
+    "MEDIAL_AXIS_MIN_CLEARANCE",
+    "MULTI_OFFSETS",
+    "HATCH_SPACING",
+    "CHAMFER_WIDTH",
+    "TIP_OFFSET",
+    "ROUNDOVER_RADIUS",
+    "BEARING_RADIUS",
+    "THICKNESS",
+    "SOLIDIFY_OFFSET",
+    "FILLET_RADIUS",
+    "TOOL_DIAMETER",
+    "SYMMETRY_TOLERANCE",
+    "SDF_OFFSET",
+    "FIT_TOLERANCE",
+    "CVT_EDGE_LENGTH",
+    "OFFSET_DISTANCE",
+    "POCKET_STEPOVER",
+    "POCKET_DEPTH",
+    "POCKET_DEPTH_PER_PASS",
+    "POCKET_TOOL_RADIUS",
+    "POCKET_RETURN_Z",
+    "SAFE_Z",
+    "max_chord_error",
+    "min_step",
+    "VORONOI_SNAP_GRID",
+    "CORRIDOR_OFFSET",
+    "PROJECT_MAX_DISTANCE",
+    "PROJECT_OFFSET",
+    "ORIGIN_SHIFT",
+];
+
+/// The returned keys holding lengths (or comma separated lists of lengths)
+const LENGTH_RESULTS: &[&str] = &[
+    "grid.min_x",
+    "grid.min_y",
+    "grid.step",
+    "lattice.step_x",
+    "lattice.step_y",
+    "DEVIATIONS",
+    "DEVIATION_MIN",
+    "DEVIATION_MAX",
+    "DEVIATION_MEAN",
+    "DISTANCES",
+    "DISTANCE_MAX",
+    "DISTANCE_MEAN",
+    "DISTANCE_RMS",
+    "CHAMFER_DEPTH",
+    "CHAMFER_OFFSET",
+    "FEATURE_CHECK_STEP",
+    "PEN_UP_TRAVEL",
+    "TRAVEL_BEFORE",
+    "TRAVEL_AFTER",
+    "SDF_VOXEL_SIZE",
+    "SDF_VOXEL_SIZE_X",
+    "SDF_VOXEL_SIZE_Y",
+    "SDF_VOXEL_SIZE_Z",
+    "VOXEL_SIZE",
+    "SYMMETRY_TOLERANCE",
+    "FIT_TOLERANCE",
+    "CVT_EDGE_LENGTH",
+    "POCKET_CUT_LENGTH",
+    "VORONOI_GRID_RESOLUTION",
+];
+
+/// The returned keys holding areas
+const AREA_RESULTS: &[&str] = &["SURFACE_AREA"];
+
+/// The returned keys holding volumes
+const VOLUME_RESULTS: &[&str] = &["VOLUME"];
+
+/// Returns the unit scale, or None if nothing should be scaled
+pub(crate) fn unit_scale(config: &ConfigType) -> Result<Option<f64>, HallrError> {
+    match config.get_parsed_option::<f64>(UNIT_SCALE_KEY)? {
+        Some(scale) if !scale.is_finite() || scale <= 0.0 => Err(HallrError::InvalidParameter(
+            format!("{} must be a positive number :({})", UNIT_SCALE_KEY, scale),
+        )),
+        Some(scale) if scale != 1.0 => Ok(Some(scale)),
+        _ => Ok(None),
+    }
+}
+
+/// Returns the comma separated numbers of `value` multiplied by `scale`
+fn scale_value(key: &str, value: &str, scale: f64) -> Result<String, HallrError> {
+    Ok(value
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f64>()
+                .map(|v| (v * scale).to_string())
+                .map_err(|_| {
+                    HallrError::InvalidParameter(format!("Could not parse \"{}\" of {}", v, key))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .join(","))
+}
+
+/// Scales the values of the `keys` present in the config
+fn scale_keys(config: &mut ConfigType, keys: &[&str], scale: f64) -> Result<(), HallrError> {
+    for key in keys.iter() {
+        if let Some(value) = config.get(*key) {
+            let scaled = scale_value(key, value, scale)?;
+            let _ = config.insert(key.to_string(), scaled);
+        }
+    }
+    Ok(())
+}
+
+/// Scales the row major world matrices, only the translations depend on the unit
+fn scale_matrices(matrices: &mut [f32], scale: f64) {
+    for matrix in matrices.chunks_exact_mut(16) {
+        for i in [3, 7, 11] {
+            matrix[i] = (matrix[i] as f64 * scale) as f32;
+        }
+    }
+}
+
+fn scale_vertex(v: &FFIVector3, scale: f64) -> FFIVector3 {
+    FFIVector3::new(
+        (v.x as f64 * scale) as f32,
+        (v.y as f64 * scale) as f32,
+        (v.z as f64 * scale) as f32,
+    )
+}
+
+/// Returns the vertices and matrices scaled into the canonical unit. The length parameters of the
+/// config are scaled in place.
+pub(crate) fn scale_input(
+    vertices: &[FFIVector3],
+    matrices: &[f32],
+    config: &mut ConfigType,
+    scale: f64,
+) -> Result<(Vec<FFIVector3>, Vec<f32>), HallrError> {
+    scale_keys(config, LENGTH_PARAMETERS, scale)?;
+    let mut matrices = matrices.to_vec();
+    scale_matrices(&mut matrices, scale);
+    Ok((
+        vertices.iter().map(|v| scale_vertex(v, scale)).collect(),
+        matrices,
+    ))
+}
+
+/// Scales the result of the command back into document units, and reports the applied scale
+pub(crate) fn scale_output(rv: &mut CommandResult, scale: f64) -> Result<(), HallrError> {
+    let inverse = 1.0 / scale;
+    for v in rv.0.iter_mut() {
+        *v = scale_vertex(v, inverse);
+    }
+    scale_matrices(&mut rv.2, inverse);
+    scale_keys(&mut rv.3, LENGTH_RESULTS, inverse)?;
+    scale_keys(&mut rv.3, AREA_RESULTS, inverse * inverse)?;
+    scale_keys(&mut rv.3, VOLUME_RESULTS, inverse * inverse * inverse)?;
+    let _ = rv.3.insert(UNIT_SCALE_KEY.to_string(), scale.to_string());
+    Ok(())
+}
@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{scale_input, scale_output, unit_scale, UNIT_SCALE_KEY};
+use crate::{
+    command::{ConfigType, OwnedModel},
+    ffi::FFIVector3,
+    HallrError,
+};
+
+/// A row major matrix with the translation `translation`
+fn matrix(translation: [f32; 3]) -> Vec<f32> {
+    let mut rv = OwnedModel::identity_matrix().to_vec();
+    rv[3] = translation[0];
+    rv[7] = translation[1];
+    rv[11] = translation[2];
+    rv
+}
+
+#[test]
+fn test_unit_scale_option() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    assert_eq!(None, unit_scale(&config)?);
+    let _ = config.insert(UNIT_SCALE_KEY.to_string(), "1.0".to_string());
+    assert_eq!(None, unit_scale(&config)?);
+    let _ = config.insert(UNIT_SCALE_KEY.to_string(), "0.001".to_string());
+    assert_eq!(Some(0.001), unit_scale(&config)?);
+    let _ = config.insert(UNIT_SCALE_KEY.to_string(), "-1.0".to_string());
+    assert!(unit_scale(&config).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_unit_scale_round_trip() -> Result<(), HallrError> {
+    let vertices: Vec<FFIVector3> = vec![(1000.0, -2000.0, 500.0).into()];
+    let mut config = ConfigType::default();
+    let _ = config.insert("probe_radius".to_string(), "2.0".to_string());
+    let _ = config.insert("MULTI_OFFSETS".to_string(), "1.0, -3.0".to_string());
+    let _ = config.insert("pattern".to_string(), "MEANDER".to_string());
+
+    // a scene in millimeters
+    let (scaled_vertices, scaled_matrices) =
+        scale_input(&vertices, &matrix([100.0, 0.0, 0.0]), &mut config, 0.001)?;
+    assert_eq!(FFIVector3::new(1.0, -2.0, 0.5), scaled_vertices[0]);
+    assert!((scaled_matrices[3] - 0.1).abs() < 1e-7);
+    assert_eq!(1.0, scaled_matrices[0]);
+    assert_eq!("0.002", config.get("probe_radius").unwrap());
+    assert_eq!("0.001,-0.003", config.get("MULTI_OFFSETS").unwrap());
+    // not a length
+    assert_eq!("MEANDER", config.get("pattern").unwrap());
+
+    let mut return_config = ConfigType::default();
+    let _ = return_config.insert("DISTANCE_MAX".to_string(), "0.5".to_string());
+    let _ = return_config.insert("DEVIATIONS".to_string(), "0.25,NaN".to_string());
+    let mut rv = (scaled_vertices, vec![0], scaled_matrices, return_config);
+    scale_output(&mut rv, 0.001)?;
+    assert!((rv.0[0].x - 1000.0).abs() < 1e-3);
+    assert!((rv.0[0].z - 500.0).abs() < 1e-3);
+    assert!((rv.2[3] - 100.0).abs() < 1e-4);
+    assert_eq!("500", rv.3.get("DISTANCE_MAX").unwrap());
+    assert_eq!("250,NaN", rv.3.get("DEVIATIONS").unwrap());
+    assert_eq!("0.001", rv.3.get(UNIT_SCALE_KEY).unwrap());
+    Ok(())
+}
@@ -0,0 +1,212 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! Welding of coincident output vertices. Commands returning duplicated vertices ask the addon to
+//! `REMOVE_DOUBLES`, with `WELD_IN_RUST=true` the vertices are welded here instead, before the
+//! result is returned.
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    mesh_format::{self, MeshFormat},
+    output_stats, CommandResult, Options,
+};
+use crate::{ffi::FFIVector3, HallrError};
+use ahash::AHashMap;
+
+/// The option requesting the vertices to be welded in Rust instead of by the addon
+pub(crate) const WELD_IN_RUST_KEY: &str = "WELD_IN_RUST";
+/// The key a command sets when the addon should remove doubles
+const REMOVE_DOUBLES_KEY: &str = "REMOVE_DOUBLES";
+/// The merge distance of the remove doubles, set by the command
+const REMOVE_DOUBLES_THRESHOLD_KEY: &str = "REMOVE_DOUBLES_THRESHOLD";
+/// The merge distance the addon uses when the command sets none
+const DEFAULT_THRESHOLD: f32 = 0.0001;
+/// The number of removed vertices, in the returned config
+pub(crate) const WELDED_VERTICES_KEY: &str = "WELDED_VERTICES";
+
+/// Returns the new index of every vertex when the vertices within `threshold` of an earlier vertex
+/// are merged into it, and the number of remaining vertices. The remaining vertices keep their
+/// order.
+pub(crate) fn weld_map(vertices: &[FFIVector3], threshold: f32) -> (Vec<usize>, usize) {
+    let mut map = Vec::<usize>::with_capacity(vertices.len());
+    let mut kept = Vec::<usize>::new();
+    if threshold <= 0.0 {
+        // only exact duplicates
+        let mut seen = AHashMap::<(u32, u32, u32), usize>::new();
+        for v in vertices.iter() {
+            let key = (v.x.to_bits(), v.y.to_bits(), v.z.to_bits());
+            let next = seen.len();
+            map.push(*seen.entry(key).or_insert(next));
+        }
+        return (map, seen.len());
+    }
+    // the kept vertices in cells of the threshold size, a merge candidate is in a neighbouring cell
+    let mut grid = AHashMap::<(i64, i64, i64), Vec<usize>>::new();
+    let cell = |v: &FFIVector3| {
+        (
+            (v.x / threshold).floor() as i64,
+            (v.y / threshold).floor() as i64,
+            (v.z / threshold).floor() as i64,
+        )
+    };
+    let threshold_squared = threshold * threshold;
+    for v in vertices.iter() {
+        let (x, y, z) = cell(v);
+        let mut existing = None;
+        'search: for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    for k in grid.get(&(x + dx, y + dy, z + dz)).into_iter().flatten() {
+                        let other = &vertices[kept[*k]];
+                        let (ex, ey, ez) = (v.x - other.x, v.y - other.y, v.z - other.z);
+                        if ex * ex + ey * ey + ez * ez <= threshold_squared {
+                            existing = Some(*k);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+        match existing {
+            Some(k) => map.push(k),
+            None => {
+                map.push(kept.len());
+                grid.entry((x, y, z)).or_default().push(kept.len());
+                kept.push(map.len() - 1);
+            }
+        }
+    }
+    (map, kept.len())
+}
+
+/// Returns the indices with the vertices replaced by `map`, dropping the edges and faces that
+/// collapsed
+fn weld_indices(
+    indices: &[usize],
+    format: MeshFormat,
+    map: impl Fn(usize) -> Result<usize, HallrError>,
+) -> Result<Vec<usize>, HallrError> {
+    let mut rv = Vec::<usize>::with_capacity(indices.len());
+    match format {
+        MeshFormat::Triangulated => {
+            for t in indices.chunks_exact(3) {
+                let (a, b, c) = (map(t[0])?, map(t[1])?, map(t[2])?);
+                if a != b && b != c && c != a {
+                    rv.extend([a, b, c]);
+                }
+            }
+        }
+        MeshFormat::LineChunks => {
+            for e in indices.chunks_exact(2) {
+                let (a, b) = (map(e[0])?, map(e[1])?);
+                if a != b {
+                    rv.extend([a, b]);
+                }
+            }
+        }
+        MeshFormat::LineWindows => {
+            for i in indices.iter() {
+                rv.push(map(*i)?);
+            }
+            rv.dedup();
+        }
+        MeshFormat::Ngons => {
+            let mut faces = Vec::<Vec<usize>>::new();
+            for face in mesh_format::split_ngons(indices)? {
+                let mut welded = face
+                    .iter()
+                    .map(|i| map(*i))
+                    .collect::<Result<Vec<_>, _>>()?;
+                welded.dedup();
+                while welded.len() > 1 && welded.first() == welded.last() {
+                    let _ = welded.pop();
+                }
+                if welded.len() >= 3 {
+                    faces.push(welded);
+                }
+            }
+            rv = mesh_format::faces_to_ngons(&faces);
+        }
+    }
+    Ok(rv)
+}
+
+/// Weld the vertices of every segment of the result, if the command requested `REMOVE_DOUBLES`.
+/// The segments are welded separately, and the addon is told not to remove doubles again.
+pub(crate) fn weld_result(result: &mut CommandResult) -> Result<(), HallrError> {
+    if result.3.get(REMOVE_DOUBLES_KEY).map(|v| v.as_str()) != Some("true") {
+        return Ok(());
+    }
+    weld_segments(result, None)
+}
+
+/// Weld the vertices of every segment of the result within `threshold`, or the threshold
+/// requested by the command when `None`. The addon is told not to remove doubles again.
+pub(crate) fn weld_segments(
+    result: &mut CommandResult,
+    threshold: Option<f32>,
+) -> Result<(), HallrError> {
+    let threshold = match threshold {
+        Some(threshold) => threshold,
+        None => result
+            .3
+            .get_parsed_option::<f32>(REMOVE_DOUBLES_THRESHOLD_KEY)?
+            .unwrap_or(DEFAULT_THRESHOLD),
+    };
+    let mut vertices = Vec::<FFIVector3>::with_capacity(result.0.len());
+    let mut indices = Vec::<usize>::with_capacity(result.1.len());
+    for (n, (format, vertex_range, index_range)) in
+        output_stats::segments(result)?.into_iter().enumerate()
+    {
+        if n > 0 {
+            let _ = result.3.insert(
+                format!("first_vertex_model_{}", n),
+                vertices.len().to_string(),
+            );
+            let _ = result.3.insert(
+                format!("first_index_model_{}", n),
+                indices.len().to_string(),
+            );
+        }
+        let segment_vertices = result.0.get(vertex_range.clone()).ok_or_else(|| {
+            HallrError::InternalError(format!("The segment {} is out of bounds", n))
+        })?;
+        let segment_indices = result.1.get(index_range).ok_or_else(|| {
+            HallrError::InternalError(format!("The segment {} is out of bounds", n))
+        })?;
+        let (map, _) = weld_map(segment_vertices, threshold);
+        let offset = vertices.len();
+        let mut kept = vec![false; segment_vertices.len()];
+        for (i, v) in segment_vertices.iter().enumerate() {
+            if !kept[map[i]] {
+                kept[map[i]] = true;
+                vertices.push(*v);
+            }
+        }
+        let format = format.parse::<MeshFormat>()?;
+        indices.append(&mut weld_indices(segment_indices, format, |i| {
+            if vertex_range.contains(&i) {
+                Ok(offset + map[i - vertex_range.start])
+            } else {
+                Err(HallrError::InternalError(format!(
+                    "The index {} is outside of the segment {}",
+                    i, n
+                )))
+            }
+        })?);
+    }
+    let welded = result.0.len() - vertices.len();
+    info!("Rust: welded {} vertices", welded);
+    result.0 = vertices;
+    result.1 = indices;
+    let _ = result
+        .3
+        .insert(REMOVE_DOUBLES_KEY.to_string(), "false".to_string());
+    let _ = result
+        .3
+        .insert(WELDED_VERTICES_KEY.to_string(), welded.to_string());
+    Ok(())
+}
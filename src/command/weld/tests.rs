@@ -0,0 +1,99 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{weld_map, weld_result, WELDED_VERTICES_KEY};
+use crate::{command::ConfigType, ffi::FFIVector3, HallrError};
+
+#[test]
+fn test_weld_map() {
+    let vertices: Vec<FFIVector3> = vec![
+        (0.0, 0.0, 0.0).into(),
+        (1.0, 0.0, 0.0).into(),
+        (0.00005, 0.0, 0.0).into(),
+        (1.0, 0.0, 0.0).into(),
+        (0.0002, 0.0, 0.0).into(),
+    ];
+    assert_eq!((vec![0, 1, 0, 1, 2], 3), weld_map(&vertices, 0.0001));
+    // only the exact duplicates
+    assert_eq!((vec![0, 1, 2, 1, 3], 4), weld_map(&vertices, 0.0));
+}
+
+#[test]
+fn test_weld_triangles() -> Result<(), HallrError> {
+    // two triangles with vertices of their own, and a triangle that collapses
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "triangulated".to_string());
+    let _ = config.insert("REMOVE_DOUBLES".to_string(), "true".to_string());
+    let mut result = (
+        vec![
+            (0.0, 0.0, 0.0).into(),
+            (1.0, 0.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (0.0, 0.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (0.0, 1.0, 0.0).into(),
+            (0.0, 1.00001, 0.0).into(),
+        ],
+        vec![0, 1, 2, 3, 4, 5, 5, 6, 0],
+        vec![],
+        config,
+    );
+    weld_result(&mut result)?;
+    assert_eq!(4, result.0.len());
+    assert_eq!(vec![0, 1, 2, 0, 2, 3], result.1);
+    assert_eq!("false", result.3.get("REMOVE_DOUBLES").unwrap());
+    assert_eq!("3", result.3.get(WELDED_VERTICES_KEY).unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_weld_segments() -> Result<(), HallrError> {
+    // a line_windows segment and an ngons segment, the segments are welded separately
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_windows".to_string());
+    let _ = config.insert("REMOVE_DOUBLES".to_string(), "true".to_string());
+    let _ = config.insert("REMOVE_DOUBLES_THRESHOLD".to_string(), "0.1".to_string());
+    let _ = config.insert("first_vertex_model_1".to_string(), "3".to_string());
+    let _ = config.insert("first_index_model_1".to_string(), "3".to_string());
+    let _ = config.insert("mesh.format_model_1".to_string(), "ngons".to_string());
+    let mut result = (
+        vec![
+            (0.0, 0.0, 0.0).into(),
+            (0.05, 0.0, 0.0).into(),
+            (1.0, 0.0, 0.0).into(),
+            // the ngons
+            (0.0, 0.0, 0.0).into(),
+            (1.0, 0.0, 0.0).into(),
+            (1.0, 1.0, 0.0).into(),
+            (0.0, 1.0, 0.0).into(),
+            (0.0, 1.05, 0.0).into(),
+        ],
+        vec![0, 1, 2, 5, 3, 4, 5, 6, 7, 3, 3, 6, 7],
+        vec![],
+        config,
+    );
+    weld_result(&mut result)?;
+    assert_eq!(2 + 4, result.0.len());
+    assert_eq!("2", result.3.get("first_vertex_model_1").unwrap());
+    assert_eq!("2", result.3.get("first_index_model_1").unwrap());
+    // the pentagon becomes a quad, the triangle collapses
+    assert_eq!(vec![0, 1, 4, 2, 3, 4, 5], result.1);
+    Ok(())
+}
+
+#[test]
+fn test_weld_not_requested() -> Result<(), HallrError> {
+    let mut config = ConfigType::default();
+    let _ = config.insert("mesh.format".to_string(), "line_chunks".to_string());
+    let mut result = (
+        vec![(0.0, 0.0, 0.0).into(), (0.0, 0.0, 0.0).into()],
+        vec![0, 1],
+        vec![],
+        config,
+    );
+    weld_result(&mut result)?;
+    assert_eq!(2, result.0.len());
+    assert!(!result.3.contains_key(WELDED_VERTICES_KEY));
+    Ok(())
+}
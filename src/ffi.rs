@@ -3,14 +3,26 @@
 // This file is part of the hallr crate.
 
 //! This module contains the Rust to Python (or rather CTypes) interface
+mod buffers;
 mod impls;
+mod jobs;
+mod stream;
 
+use crate::{
+    command::{
+        attributes, dispatch, origin_shift,
+        plugin::{Plugin, PluginCommandFn, PluginReleaseFn},
+        progress::{self, ProgressCallback},
+    },
+    logging::{self, LogCallback},
+    HallrError,
+};
 use std::{
     collections::HashMap,
-    ffi::{CStr, CString},
+    ffi::{c_void, CStr, CString},
     iter::successors,
     slice,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 /// A simple 3D vector struct for FFI (Foreign Function Interface) usage.
@@ -43,6 +55,24 @@ impl FFIVector3 {
     }
 }
 
+/// The double precision variant of `FFIVector3`, used by `process_geometry_f64`.
+///
+/// The commands still run in single precision, but the coordinates are moved close to the origin
+/// in double precision first, so models far away from the origin do not lose their detail.
+#[derive(PartialEq, PartialOrd, Copy, Clone, Default)]
+#[repr(C)]
+pub struct FFIVector3d {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl FFIVector3d {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+}
+
 /// A struct representing the geometry output for FFI (Foreign Function Interface) usage.
 ///
 /// This struct is used to return geometry-related data from Rust to other programming languages
@@ -88,6 +118,71 @@ impl GeometryOutput {
     }
 }
 
+/// The double precision variant of `GeometryOutput`, returned by `process_geometry_f64`.
+///
+/// # Fields
+///
+/// * `vertices`: A pointer to an array of `FFIVector3d` representing vertices.
+/// * `vertex_count`: The number of vertices in the geometry.
+/// * `indices`: A pointer to an array of `usize` representing indices.
+/// * `indices_count`: The number of indices in the geometry.
+/// * `matrices`: A pointer to an array of `f64` representing world orientation (matrix)
+/// * `matrices_count`: The number of elements (f64) in `matrices`,
+#[repr(C)]
+pub struct GeometryOutputF64 {
+    vertices: *mut FFIVector3d,
+    vertex_count: usize,
+    indices: *mut usize,
+    indices_count: usize,
+    matrices: *mut f64,
+    matrices_count: usize,
+}
+
+impl GeometryOutputF64 {
+    /// Deallocates the memory associated with the `GeometryOutputF64` vertices and indices.
+    ///
+    /// # Safety
+    /// This function uses unsafe Rust code to deallocate memory. It should only be
+    /// called in situations where you are certain that the memory can be safely
+    /// released.
+    fn free(&self) {
+        unsafe {
+            let _ = Vec::from_raw_parts(self.vertices, self.vertex_count, self.vertex_count);
+            let _ = Vec::from_raw_parts(self.indices, self.indices_count, self.indices_count);
+            let _ = Vec::from_raw_parts(self.matrices, self.matrices_count, self.matrices_count);
+        }
+    }
+}
+
+/// A struct representing a blob of bytes for FFI (Foreign Function Interface) usage.
+///
+/// This struct is used to return the binary attribute channels (see `process_geometry`) from Rust
+/// to other programming languages like C or Python via FFI.
+///
+/// # Fields
+///
+/// * `data`: A pointer to the bytes of the blob.
+/// * `size`: The number of bytes in the blob, zero when there are no attributes.
+#[repr(C)]
+pub struct AttributeOutput {
+    data: *mut u8,
+    size: usize,
+}
+
+impl AttributeOutput {
+    /// Deallocates the memory associated with the `AttributeOutput` blob.
+    ///
+    /// # Safety
+    /// This function uses unsafe Rust code to deallocate memory. It should only be
+    /// called in situations where you are certain that the memory can be safely
+    /// released.
+    fn free(&self) {
+        unsafe {
+            let _ = Vec::from_raw_parts(self.data, self.size, self.size);
+        }
+    }
+}
+
 /// A struct representing a map of strings for FFI (Foreign Function Interface) usage.
 ///
 /// This struct is used to pass a map of strings between Rust and other programming languages
@@ -141,11 +236,74 @@ impl StringMap {
 ///
 /// * `geometry`: The geometry output of the process, typically containing vertices and indices.
 /// * `map`: A string map with key-value pairs that store additional information about the process.
+/// * `attributes`: The binary attribute channels returned by the process.
 ///
 #[repr(C)]
 pub struct ProcessResult {
     pub geometry: GeometryOutput,
     pub map: StringMap,
+    pub attributes: AttributeOutput,
+}
+
+/// The double precision variant of `ProcessResult`, returned by `process_geometry_f64`.
+#[repr(C)]
+pub struct ProcessResultF64 {
+    pub geometry: GeometryOutputF64,
+    pub map: StringMap,
+    pub attributes: AttributeOutput,
+}
+
+/// The output buffers provided by the caller of `process_geometry_into()`. A pointer may be null
+/// when its capacity is zero.
+///
+/// # Fields
+///
+/// * `vertices`, `vertex_capacity`: Room for `vertex_capacity` vertices.
+/// * `indices`, `indices_capacity`: Room for `indices_capacity` indices.
+/// * `matrices`, `matrices_capacity`: Room for `matrices_capacity` matrix elements.
+/// * `attributes`, `attributes_capacity`: Room for an attribute blob of `attributes_capacity`
+///   bytes, see the `attributes` module for the format.
+/// * `config`, `config_capacity`: Room for the returned config of `config_capacity` bytes. The
+///   config is written as null-terminated strings, alternating between the keys and the values.
+#[repr(C)]
+pub struct OutputBuffers {
+    pub vertices: *mut FFIVector3,
+    pub vertex_capacity: usize,
+    pub indices: *mut usize,
+    pub indices_capacity: usize,
+    pub matrices: *mut f32,
+    pub matrices_capacity: usize,
+    pub attributes: *mut u8,
+    pub attributes_capacity: usize,
+    pub config: *mut u8,
+    pub config_capacity: usize,
+}
+
+/// The sizes of the output of `process_geometry_into()`, the required capacities of the
+/// `OutputBuffers` when they were too small.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OutputSizes {
+    pub vertex_count: usize,
+    pub indices_count: usize,
+    pub matrices_count: usize,
+    pub attributes_size: usize,
+    pub config_size: usize,
+}
+
+/// Logs the error and its causes, and returns it as the "ERROR" of the python side response.
+/// The numeric `HallrErrorCode` of the error is returned as "ERROR_CODE", and the message
+/// without the description of the variant as "ERROR_DETAIL".
+fn error_config(err: HallrError) -> HashMap<String, String> {
+    error!("{:?}", err);
+    for cause in successors(Some(&err as &(dyn std::error::Error)), |e| e.source()) {
+        error!("Caused by: {:?}", cause);
+    }
+    let mut config = HashMap::new();
+    let _ = config.insert("ERROR".to_string(), err.to_string());
+    let _ = config.insert("ERROR_CODE".to_string(), (err.code() as u32).to_string());
+    let _ = config.insert("ERROR_DETAIL".to_string(), err.detail());
+    config
 }
 
 /// Converts any Err object into a python side response.
@@ -153,38 +311,80 @@ fn process_command_error_handler(
     vertices: &[FFIVector3],
     indices: &[usize],
     matrix: &[f32],
+    attribute_blob: &[u8],
     config: HashMap<String, String>,
 ) -> (
     Vec<FFIVector3>,
     Vec<usize>,
     Vec<f32>,
     HashMap<String, String>,
+    Vec<u8>,
 ) {
     let start = Instant::now();
-    let rv = match crate::command::process_command(vertices, indices, matrix, config) {
-        Ok(rv) => rv,
-        Err(err) => {
-            eprintln!("{:?}", err);
-            for cause in successors(Some(&err as &(dyn std::error::Error)), |e| e.source()) {
-                eprintln!("Caused by: {:?}", cause);
-            }
-            let mut config = HashMap::new();
-            let _ = config.insert("ERROR".to_string(), err.to_string());
-            (vec![], vec![], vec![], config)
-        }
+    let rv = match attributes::decode(attribute_blob).and_then(|input_attributes| {
+        crate::command::process_command(vertices, indices, matrix, &input_attributes, config)
+    }) {
+        Ok(((vertices, indices, matrix, config), output_attributes)) => (
+            vertices,
+            indices,
+            matrix,
+            config,
+            attributes::encode(&output_attributes),
+        ),
+        Err(err) => (vec![], vec![], vec![], error_config(err), vec![]),
+    };
+    let duration = start.elapsed();
+    info!("Rust: Time elapsed in process_command() was {:?}", duration);
+    rv
+}
+
+/// The double precision variant of `process_command_error_handler()`
+fn process_command_f64_error_handler(
+    vertices: &[FFIVector3d],
+    indices: &[usize],
+    matrix: &[f64],
+    attribute_blob: &[u8],
+    config: HashMap<String, String>,
+) -> (
+    Vec<FFIVector3d>,
+    Vec<usize>,
+    Vec<f64>,
+    HashMap<String, String>,
+    Vec<u8>,
+) {
+    let start = Instant::now();
+    let rv = match attributes::decode(attribute_blob).and_then(|input_attributes| {
+        origin_shift::process_command_f64(vertices, indices, matrix, &input_attributes, config)
+    }) {
+        Ok(((vertices, indices, matrix, config), output_attributes)) => (
+            vertices,
+            indices,
+            matrix,
+            config,
+            attributes::encode(&output_attributes),
+        ),
+        Err(err) => (vec![], vec![], vec![], error_config(err), vec![]),
     };
     let duration = start.elapsed();
-    println!("Rust: Time elapsed in process_command() was {:?}", duration);
+    info!(
+        "Rust: Time elapsed in process_command_f64() was {:?}",
+        duration
+    );
     rv
 }
 
 /// Processes the provided geometry (vertices and edges).
 ///
+/// Large numeric side channels can be passed in `input_attributes`, a blob of named `f32` arrays
+/// (see the `attributes` module for the format). The channels produced by the command are returned
+/// in the same format in `ProcessResult::attributes`.
+///
 /// # Safety
 ///
 /// This function is marked `unsafe` because it:
 /// - Dereferences raw pointers that are passed in.
 /// - Assumes the memory blocks pointed to by `input_vertices` and `input_edges` are valid and have sizes at least `vertex_count` and `edge_count` respectively.
+/// - Assumes the memory block pointed to by `input_attributes` is valid and has a size of at least `attributes_size` bytes. The pointer may be null when `attributes_size` is zero.
 /// - It's the caller's responsibility to ensure that the memory blocks are valid and can safely be accessed.
 ///
 /// Furthermore, after using this function, you MUST NOT use the passed memory blocks from the caller's side until you're done with them in Rust, to avoid data races and undefined behavior.
@@ -199,13 +399,424 @@ pub unsafe extern "C" fn process_geometry(
     input_ffi_matrix: *const f32,
     matrix_count: usize,
     config: *const StringMap,
+    input_attributes: *const u8,
+    attributes_size: usize,
+) -> ProcessResult {
+    let input = read_input(
+        input_ffi_vertices,
+        vertex_count,
+        input_ffi_indices,
+        indices_count,
+        input_ffi_matrix,
+        matrix_count,
+        config,
+        input_attributes,
+        attributes_size,
+    );
+    progress::clear_cancel();
+    into_process_result(process_command_error_handler(
+        input.vertices,
+        input.indices,
+        input.matrix,
+        input.attributes,
+        input.config,
+    ))
+}
+
+/// Processes the provided geometry like `process_geometry()`, but with double precision vertices
+/// and matrices. The input is moved close to the origin before the command runs in single
+/// precision, and the result is moved back. The applied offset is returned as `ORIGIN_SHIFT`.
+/// The memory of the result must be released with `free_process_results_f64()`.
+///
+/// # Safety
+///
+/// The same requirements as for `process_geometry()` apply to the passed memory blocks.
+#[no_mangle]
+pub unsafe extern "C" fn process_geometry_f64(
+    input_ffi_vertices: *const FFIVector3d,
+    vertex_count: usize,
+    input_ffi_indices: *const usize,
+    indices_count: usize,
+    input_ffi_matrix: *const f64,
+    matrix_count: usize,
+    config: *const StringMap,
+    input_attributes: *const u8,
+    attributes_size: usize,
+) -> ProcessResultF64 {
+    let input = read_input(
+        input_ffi_vertices,
+        vertex_count,
+        input_ffi_indices,
+        indices_count,
+        input_ffi_matrix,
+        matrix_count,
+        config,
+        input_attributes,
+        attributes_size,
+    );
+    progress::clear_cancel();
+    let (output_vertices, output_indices, output_matrix, output_config, output_attributes) =
+        process_command_f64_error_handler(
+            input.vertices,
+            input.indices,
+            input.matrix,
+            input.attributes,
+            input.config,
+        );
+    debug!(
+        "Rust returning: vertices:{}, indices:{}, matrices:{}/16, attributes:{} bytes, config:{:?}",
+        output_vertices.len(),
+        output_indices.len(),
+        output_matrix.len(),
+        output_attributes.len(),
+        output_config
+    );
+    // Vecs without spare capacity, so that they can be rebuilt from the pointers and sizes
+    let output_vertices = output_vertices.into_boxed_slice().into_vec();
+    let output_indices = output_indices.into_boxed_slice().into_vec();
+    let output_matrix = output_matrix.into_boxed_slice().into_vec();
+    let rv = ProcessResultF64 {
+        geometry: GeometryOutputF64 {
+            vertices: output_vertices.as_ptr() as *mut FFIVector3d,
+            vertex_count: output_vertices.len(),
+            indices: output_indices.as_ptr() as *mut usize,
+            indices_count: output_indices.len(),
+            matrices: output_matrix.as_ptr() as *mut f64,
+            matrices_count: output_matrix.len(),
+        },
+        map: into_string_map(output_config),
+        attributes: into_attribute_output(output_attributes),
+    };
+    // The memory is released by free_process_results_f64()
+    std::mem::forget(output_vertices);
+    std::mem::forget(output_indices);
+    std::mem::forget(output_matrix);
+    rv
+}
+
+/// Starts the command in the background and returns the token of the job, see
+/// `process_geometry_poll()`. The arguments are the same as for `process_geometry()`, but the
+/// input is copied, so the caller may release it as soon as this function returns.
+///
+/// Only one job runs at a time, 0 is returned while another job is running. Jobs share the cancel
+/// request, the log level and the progress callback with the synchronous calls, so
+/// `process_geometry()` should not be called meanwhile either.
+///
+/// # Safety
+///
+/// The same requirements as for `process_geometry()` apply to the passed memory blocks.
+#[no_mangle]
+pub unsafe extern "C" fn process_geometry_start(
+    input_ffi_vertices: *const FFIVector3,
+    vertex_count: usize,
+    input_ffi_indices: *const usize,
+    indices_count: usize,
+    input_ffi_matrix: *const f32,
+    matrix_count: usize,
+    config: *const StringMap,
+    input_attributes: *const u8,
+    attributes_size: usize,
+) -> u64 {
+    let input = read_input(
+        input_ffi_vertices,
+        vertex_count,
+        input_ffi_indices,
+        indices_count,
+        input_ffi_matrix,
+        matrix_count,
+        config,
+        input_attributes,
+        attributes_size,
+    );
+    let vertices = input.vertices.to_vec();
+    let indices = input.indices.to_vec();
+    let matrix = input.matrix.to_vec();
+    let attributes = input.attributes.to_vec();
+    let config = input.config;
+    jobs::start(move || {
+        process_command_error_handler(&vertices, &indices, &matrix, &attributes, config)
+    })
+    .unwrap_or_else(|| {
+        error!("Rust: process_geometry_start(): another job is running");
+        0
+    })
+}
+
+/// Waits at most `max_milliseconds` for the job started by `process_geometry_start()` to finish.
+///
+/// Returns 0 if the job is still running, and 1 if it finished, the result is then written to
+/// `result` and must be released with `free_process_results()`. The result of a job can only be
+/// fetched once, -1 is returned for unknown tokens.
+///
+/// # Safety
+///
+/// `result` must point to a writable `ProcessResult`.
+#[no_mangle]
+pub unsafe extern "C" fn process_geometry_poll(
+    token: u64,
+    max_milliseconds: u32,
+    result: *mut ProcessResult,
+) -> i32 {
+    assert!(
+        !result.is_null(),
+        "Rust: process_geometry_poll(): result ptr was null"
+    );
+    match jobs::poll(token, Duration::from_millis(max_milliseconds as u64)) {
+        jobs::JobState::Running => 0,
+        jobs::JobState::Finished(output) => {
+            result.write(into_process_result(output));
+            1
+        }
+        jobs::JobState::Unknown => -1,
+    }
+}
+
+/// Processes the provided geometry like `process_geometry()`, but writes the result into the
+/// buffers of the caller, so there is nothing to release afterwards. The sizes of the result are
+/// always written to `sizes`.
+///
+/// Returns 0 when the result was written, and 1 when a buffer was too small. Nothing is written
+/// to the buffers then, the result is kept and can be fetched with `fetch_pending_output()`
+/// once the buffers are large enough. Errors are returned as the `ERROR` key of the config.
+///
+/// # Safety
+///
+/// The same requirements as for `process_geometry()` apply to the passed input memory blocks.
+/// `buffers` must describe writable memory blocks of at least the given capacities, and `sizes`
+/// must point to a writable `OutputSizes`.
+#[no_mangle]
+pub unsafe extern "C" fn process_geometry_into(
+    input_ffi_vertices: *const FFIVector3,
+    vertex_count: usize,
+    input_ffi_indices: *const usize,
+    indices_count: usize,
+    input_ffi_matrix: *const f32,
+    matrix_count: usize,
+    config: *const StringMap,
+    input_attributes: *const u8,
+    attributes_size: usize,
+    buffers: *const OutputBuffers,
+    sizes: *mut OutputSizes,
+) -> i32 {
+    assert!(
+        !buffers.is_null() && !sizes.is_null(),
+        "Rust: process_geometry_into(): buffers or sizes ptr was null"
+    );
+    let input = read_input(
+        input_ffi_vertices,
+        vertex_count,
+        input_ffi_indices,
+        indices_count,
+        input_ffi_matrix,
+        matrix_count,
+        config,
+        input_attributes,
+        attributes_size,
+    );
+    progress::clear_cancel();
+    let output = process_command_error_handler(
+        input.vertices,
+        input.indices,
+        input.matrix,
+        input.attributes,
+        input.config,
+    );
+    buffers::deliver(output, &*buffers, &mut *sizes)
+}
+
+/// Writes the result kept by the last `process_geometry_into()` call with too small buffers.
+///
+/// Returns 0 when the result was written, 1 when a buffer is still too small (the result is
+/// kept) and -1 when there is no kept result. The sizes of the result are written to `sizes`.
+///
+/// # Safety
+///
+/// See `process_geometry_into()`
+#[no_mangle]
+pub unsafe extern "C" fn fetch_pending_output(
+    buffers: *const OutputBuffers,
+    sizes: *mut OutputSizes,
+) -> i32 {
+    assert!(
+        !buffers.is_null() && !sizes.is_null(),
+        "Rust: fetch_pending_output(): buffers or sizes ptr was null"
+    );
+    buffers::deliver_pending(&*buffers, &mut *sizes)
+}
+
+/// Opens a geometry stream and returns its token. Huge meshes can be sent in chunks with
+/// `push_vertices()` and `push_indices()`, instead of in one contiguous buffer, and then be
+/// processed with `finish_and_process()`. The capacities are the expected total number of
+/// vertices and indices, they are only used to reserve the memory up front and may be zero.
+#[no_mangle]
+pub extern "C" fn begin_geometry_stream(vertex_capacity: usize, indices_capacity: usize) -> u64 {
+    stream::begin(vertex_capacity, indices_capacity)
+}
+
+/// Appends `vertex_count` vertices to the stream. Returns 0 on success and -1 for unknown
+/// tokens. The chunk is copied, so the caller may reuse the buffer as soon as this returns.
+///
+/// # Safety
+///
+/// `vertices` must point to at least `vertex_count` vertices, it may be null when
+/// `vertex_count` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn push_vertices(
+    token: u64,
+    vertices: *const FFIVector3,
+    vertex_count: usize,
+) -> i32 {
+    let vertices = if vertex_count == 0 {
+        &[]
+    } else {
+        assert!(
+            !vertices.is_null(),
+            "Rust: push_vertices(): vertices ptr was null"
+        );
+        slice::from_raw_parts(vertices, vertex_count)
+    };
+    if stream::push_vertices(token, vertices) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// Appends `indices_count` indices to the stream. Returns 0 on success and -1 for unknown
+/// tokens. The indices refer to the whole stream, not to the vertices of a chunk.
+///
+/// # Safety
+///
+/// `indices` must point to at least `indices_count` indices, it may be null when
+/// `indices_count` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn push_indices(
+    token: u64,
+    indices: *const usize,
+    indices_count: usize,
+) -> i32 {
+    let indices = if indices_count == 0 {
+        &[]
+    } else {
+        assert!(
+            !indices.is_null(),
+            "Rust: push_indices(): indices ptr was null"
+        );
+        slice::from_raw_parts(indices, indices_count)
+    };
+    if stream::push_indices(token, indices) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// Closes the stream and processes the collected geometry like `process_geometry()`, with the
+/// given matrices, config and attributes. The result must be released with
+/// `free_process_results()`, an unknown token is returned as an `ERROR`.
+///
+/// # Safety
+///
+/// The same requirements as for `process_geometry()` apply to the passed memory blocks.
+#[no_mangle]
+pub unsafe extern "C" fn finish_and_process(
+    token: u64,
+    input_ffi_matrix: *const f32,
+    matrix_count: usize,
+    config: *const StringMap,
+    input_attributes: *const u8,
+    attributes_size: usize,
 ) -> ProcessResult {
+    let input_config = read_config(config);
+    let input_matrix = slice::from_raw_parts(input_ffi_matrix, matrix_count);
+    let input_attributes = read_attributes(input_attributes, attributes_size);
+    let Some((vertices, indices)) = stream::finish(token) else {
+        let err = HallrError::InvalidInputData(format!("Unknown geometry stream: {}", token));
+        return into_process_result((vec![], vec![], vec![], error_config(err), vec![]));
+    };
+    debug!(
+        "Rust:received {} vertices and {} indices from the stream",
+        vertices.len(),
+        indices.len()
+    );
+    progress::clear_cancel();
+    into_process_result(process_command_error_handler(
+        &vertices,
+        &indices,
+        input_matrix,
+        input_attributes,
+        input_config,
+    ))
+}
+
+/// Closes the stream without processing it, e.g. when the caller ran into an error. Returns 0
+/// on success and -1 for unknown tokens.
+#[no_mangle]
+pub extern "C" fn abort_geometry_stream(token: u64) -> i32 {
+    match stream::finish(token) {
+        Some(_) => 0,
+        None => -1,
+    }
+}
+
+/// The input of `process_geometry`, borrowed from the caller. The vertices and matrices are
+/// `FFIVector3d` and `f64` for `process_geometry_f64`.
+struct Input<'a, V = FFIVector3, M = f32> {
+    vertices: &'a [V],
+    indices: &'a [usize],
+    matrix: &'a [M],
+    attributes: &'a [u8],
+    config: HashMap<String, String>,
+}
+
+/// Reads the raw input of `process_geometry`, the config strings are copied.
+///
+/// # Safety
+///
+/// See `process_geometry`
+#[allow(clippy::too_many_arguments)]
+unsafe fn read_input<'a, V, M>(
+    input_ffi_vertices: *const V,
+    vertex_count: usize,
+    input_ffi_indices: *const usize,
+    indices_count: usize,
+    input_ffi_matrix: *const M,
+    matrix_count: usize,
+    config: *const StringMap,
+    input_attributes: *const u8,
+    attributes_size: usize,
+) -> Input<'a, V, M> {
+    let input_config = read_config(config);
+
+    let input_vertices = slice::from_raw_parts(input_ffi_vertices, vertex_count);
+    let input_indices = slice::from_raw_parts(input_ffi_indices, indices_count);
+    let input_matrix = slice::from_raw_parts(input_ffi_matrix, matrix_count);
+    debug!("Rust:received {} vertices", input_vertices.len());
+    debug!("Rust:received {} indices", input_indices.len());
+    let input_attributes = read_attributes(input_attributes, attributes_size);
+    debug!("Rust:received {} matrix", input_matrix.len());
+
+    Input {
+        vertices: input_vertices,
+        indices: input_indices,
+        matrix: input_matrix,
+        attributes: input_attributes,
+        config: input_config,
+    }
+}
+
+/// Copies the config strings of the `StringMap`
+///
+/// # Safety
+///
+/// See `process_geometry`
+unsafe fn read_config(config: *const StringMap) -> HashMap<String, String> {
     assert!(
         !config.is_null(),
         "Rust: process_geometry(): Config ptr was null"
     );
     let count = (*config).count;
-    println!("Rust:Received config of size:{:?}", count);
+    debug!("Rust:Received config of size:{:?}", count);
     assert!(
         (*config).count < 1000,
         "Rust: process_geometry(): Number of configuration parameters was too large: {} (limit is 999)",
@@ -229,24 +840,48 @@ pub unsafe extern "C" fn process_geometry(
         //println!("Rust:Received Key: {}, Value: {}", key, value);
         let _ = input_config.insert(key, value);
     }
-    println!("Rust:Received config:{:?}", input_config);
+    debug!("Rust:Received config:{:?}", input_config);
+    input_config
+}
 
-    let input_vertices = slice::from_raw_parts(input_ffi_vertices, vertex_count);
-    let input_indices = slice::from_raw_parts(input_ffi_indices, indices_count);
-    let input_matrix = slice::from_raw_parts(input_ffi_matrix, matrix_count);
-    println!("Rust:received {} vertices", input_vertices.len());
-    println!("Rust:received {} indices", input_indices.len());
-    println!("Rust:received {} matrix", input_matrix.len());
-
-    let (output_vertices, output_indices, output_matrix, output_config) =
-        process_command_error_handler(input_vertices, input_indices, input_matrix, input_config);
-    println!(
-        "Rust returning: vertices:{}, indices:{}, matrices:{}/16, config:{:?}",
+/// Borrows the attribute blob, the pointer may be null when `attributes_size` is zero
+///
+/// # Safety
+///
+/// See `process_geometry`
+unsafe fn read_attributes<'a>(input_attributes: *const u8, attributes_size: usize) -> &'a [u8] {
+    let input_attributes = if attributes_size == 0 {
+        &[]
+    } else {
+        assert!(
+            !input_attributes.is_null(),
+            "Rust: process_geometry(): Attributes ptr was null"
+        );
+        slice::from_raw_parts(input_attributes, attributes_size)
+    };
+    debug!(
+        "Rust:received {} bytes of attributes",
+        input_attributes.len()
+    );
+    input_attributes
+}
+
+/// Moves the output of a command into a `ProcessResult`, the memory is released by
+/// `free_process_results()`
+fn into_process_result(output: jobs::JobOutput) -> ProcessResult {
+    let (output_vertices, output_indices, output_matrix, output_config, output_attributes) = output;
+    debug!(
+        "Rust returning: vertices:{}, indices:{}, matrices:{}/16, attributes:{} bytes, config:{:?}",
         output_vertices.len(),
         output_indices.len(),
         output_matrix.len(),
+        output_attributes.len(),
         output_config
     );
+    // Vecs without spare capacity, so that they can be rebuilt from the pointers and sizes
+    let output_vertices = output_vertices.into_boxed_slice().into_vec();
+    let output_indices = output_indices.into_boxed_slice().into_vec();
+    let output_matrix = output_matrix.into_boxed_slice().into_vec();
     let rv_g = GeometryOutput {
         vertices: output_vertices.as_ptr() as *mut FFIVector3,
         vertex_count: output_vertices.len(),
@@ -256,6 +891,23 @@ pub unsafe extern "C" fn process_geometry(
         matrices_count: output_matrix.len(),
     };
 
+    let rv = ProcessResult {
+        geometry: rv_g,
+        map: into_string_map(output_config),
+        attributes: into_attribute_output(output_attributes),
+    };
+
+    // Prevent the vectors from being deallocated. Their memory is now allocated until caller
+    // calls free_process_results() on the vectors.
+    std::mem::forget(output_vertices);
+    std::mem::forget(output_indices);
+    std::mem::forget(output_matrix);
+
+    rv
+}
+
+/// Moves the config into a `StringMap`, the memory is released by `StringMap::free()`
+fn into_string_map(output_config: HashMap<String, String>) -> StringMap {
     // Convert the HashMap into two vectors of *mut c_char
     let mut output_keys = Vec::with_capacity(output_config.len());
     let mut output_values = Vec::with_capacity(output_config.len());
@@ -264,27 +916,30 @@ pub unsafe extern "C" fn process_geometry(
         output_keys.push(CString::new(k.clone()).unwrap().into_raw());
         output_values.push(CString::new(v.clone()).unwrap().into_raw());
     }
+    // Vecs without spare capacity, so that they can be rebuilt from the pointers and count
+    let output_keys = output_keys.into_boxed_slice().into_vec();
+    let output_values = output_values.into_boxed_slice().into_vec();
 
-    // Create the return map
-    let rv_s = StringMap {
+    let rv = StringMap {
         keys: output_keys.as_ptr() as *mut *mut std::os::raw::c_char,
         values: output_values.as_ptr() as *mut *mut std::os::raw::c_char,
         count: output_config.len(),
     };
-
-    let rv = ProcessResult {
-        geometry: rv_g,
-        map: rv_s,
-    };
-
-    // Prevent the vectors from being deallocated. Their memory is now allocated until caller
-    // calls free_process_results() on the vectors.
-    std::mem::forget(output_vertices);
-    std::mem::forget(output_indices);
-    std::mem::forget(output_matrix);
     std::mem::forget(output_keys);
     std::mem::forget(output_values);
+    rv
+}
 
+/// Moves the attribute blob into an `AttributeOutput`, the memory is released by
+/// `AttributeOutput::free()`
+fn into_attribute_output(output_attributes: Vec<u8>) -> AttributeOutput {
+    // a Vec without spare capacity, so that it can be rebuilt from the pointer and size
+    let output_attributes = output_attributes.into_boxed_slice().into_vec();
+    let rv = AttributeOutput {
+        data: output_attributes.as_ptr() as *mut u8,
+        size: output_attributes.len(),
+    };
+    std::mem::forget(output_attributes);
     rv
 }
 
@@ -317,4 +972,117 @@ pub unsafe extern "C" fn free_process_results(result: *mut ProcessResult) {
     );*/
     (*result).geometry.free();
     (*result).map.free();
+    (*result).attributes.free();
+}
+
+/// Frees the memory associated with a `ProcessResultF64`, returned by `process_geometry_f64()`.
+///
+/// # Safety
+/// This function should only be called with a valid pointer to a `ProcessResultF64` created
+/// by the Rust code. Using it with an invalid or NULL pointer may lead to memory issues.
+#[no_mangle]
+pub unsafe extern "C" fn free_process_results_f64(result: *mut ProcessResultF64) {
+    assert!(
+        !result.is_null(),
+        "Rust: free_process_results_f64(): result ptr was null"
+    );
+    (*result).geometry.free();
+    (*result).map.free();
+    (*result).attributes.free();
+}
+
+/// Drops every result stored by the opt-in result cache (see the `CACHE_SIZE_MB` option).
+#[no_mangle]
+pub extern "C" fn clear_result_cache() {
+    crate::command::result_cache::clear();
+}
+
+/// Returns the commands, their versions, accepted mesh formats and parameters. This is the config
+/// returned by the `list_commands` command, see `api::list_commands()` for the typed variant.
+/// The map must be released with `free_string_map()`.
+#[no_mangle]
+pub extern "C" fn hallr_list_commands() -> StringMap {
+    into_string_map(crate::command::registry::list_commands().3)
+}
+
+/// Registers the command `name` of a plugin, implemented by the `command` callback. The command
+/// is run like the built in commands, with the input of every model in one `PluginGeometry`, see
+/// the `plugin` module. `release` is called with the output once it has been copied, and
+/// `user_data` is passed to both callbacks.
+///
+/// Returns 0 on success, -1 if the name is taken by another command or is invalid.
+///
+/// # Safety
+/// `name` must be a valid null-terminated string. The callbacks may be invoked from worker
+/// threads for the lifetime of the process, together with `user_data`.
+#[no_mangle]
+pub unsafe extern "C" fn hallr_register_command(
+    name: *const std::os::raw::c_char,
+    command: Option<PluginCommandFn>,
+    release: Option<PluginReleaseFn>,
+    user_data: *mut c_void,
+) -> i32 {
+    let Some(command) = command else {
+        error!("Rust: hallr_register_command(): the command callback was null");
+        return -1;
+    };
+    if name.is_null() {
+        error!("Rust: hallr_register_command(): the name was null");
+        return -1;
+    }
+    let name = CStr::from_ptr(name).to_string_lossy();
+    match dispatch::register(Box::new(Plugin::new(&name, command, release, user_data))) {
+        Ok(()) => 0,
+        Err(err) => {
+            error!("{}", err);
+            -1
+        }
+    }
+}
+
+/// Frees the memory of a `StringMap` returned by `hallr_list_commands()`.
+///
+/// # Safety
+/// This function should only be called with a valid pointer to a `StringMap` created
+/// by the Rust code. Using it with an invalid or NULL pointer may lead to memory issues.
+#[no_mangle]
+pub unsafe extern "C" fn free_string_map(map: *mut StringMap) {
+    assert!(!map.is_null(), "Rust: free_string_map(): map ptr was null");
+    (*map).free();
+}
+
+/// Registers a callback receiving the percent completed (0..=100) of the running command, a null
+/// pointer removes it. The long running commands (sdf_mesh, sdf_mesh_2_5, surface_scan and
+/// voronoi_mesh) report their progress through it.
+///
+/// The callback may be invoked from worker threads, it should only store the value for the UI
+/// thread to read.
+#[no_mangle]
+pub extern "C" fn hallr_set_progress_callback(callback: Option<ProgressCallback>) {
+    progress::set_callback(callback);
+}
+
+/// Asks the running commands to stop. The SDF meshing commands check the request between their
+/// chunks and return the error "The command was cancelled". The request is cleared when the next
+/// command starts.
+#[no_mangle]
+pub extern "C" fn hallr_request_cancel() {
+    progress::request_cancel();
+}
+
+/// Registers a callback receiving the log messages of the crate, together with their level
+/// (1 = error, 2 = warn, 3 = info, 4 = debug). A null pointer restores the console output.
+///
+/// The callback may be invoked from worker threads, and the message is only valid during the
+/// call.
+#[no_mangle]
+pub extern "C" fn hallr_set_log_callback(callback: Option<LogCallback>) {
+    logging::set_callback(callback);
+}
+
+/// Sets the log level, the messages more verbose than it are dropped (0 = off, 1 = error,
+/// 2 = warn, 3 = info, 4 = debug). The `LOG_LEVEL` option of a command overrides it.
+#[no_mangle]
+pub extern "C" fn hallr_set_log_level(level: u32) {
+    logging::set_level(logging::Level::from_u32(level));
 }
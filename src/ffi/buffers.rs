@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! The output of `process_geometry_into`, written into the buffers of the caller.
+//!
+//! The result is copied into the `OutputBuffers` when every part of it fits, otherwise only the
+//! required `OutputSizes` are returned. The result is then kept, so that the caller can grow the
+//! buffers and fetch it with `fetch_pending_output` without running the command again.
+
+#[cfg(test)]
+mod tests;
+
+use super::{jobs::JobOutput, OutputBuffers, OutputSizes};
+use std::{collections::HashMap, ptr, sync::Mutex};
+
+/// The result that did not fit into the buffers of the last call, and its encoded config
+static PENDING: Mutex<Option<(JobOutput, Vec<u8>)>> = Mutex::new(None);
+
+/// Encodes the config as null-terminated strings, alternating between the keys and the values.
+/// The keys are sorted.
+pub(super) fn encode_config(config: &HashMap<String, String>) -> Vec<u8> {
+    let mut keys: Vec<&String> = config.keys().collect();
+    keys.sort();
+    let mut rv = Vec::new();
+    for key in keys {
+        for string in [key, &config[key]] {
+            // a string can not hold a null byte
+            rv.extend(string.bytes().filter(|b| *b != 0));
+            rv.push(0);
+        }
+    }
+    rv
+}
+
+/// Returns the sizes of the output
+fn sizes(output: &JobOutput, config: &[u8]) -> OutputSizes {
+    OutputSizes {
+        vertex_count: output.0.len(),
+        indices_count: output.1.len(),
+        matrices_count: output.2.len(),
+        attributes_size: output.4.len(),
+        config_size: config.len(),
+    }
+}
+
+/// Returns true if the output of `sizes` fits into the buffers
+fn fits(buffers: &OutputBuffers, sizes: &OutputSizes) -> bool {
+    sizes.vertex_count <= buffers.vertex_capacity
+        && sizes.indices_count <= buffers.indices_capacity
+        && sizes.matrices_count <= buffers.matrices_capacity
+        && sizes.attributes_size <= buffers.attributes_capacity
+        && sizes.config_size <= buffers.config_capacity
+}
+
+/// Copies `source` to `destination`, which may be null when `source` is empty
+///
+/// # Safety
+///
+/// `destination` must be writable for `source.len()` elements
+unsafe fn copy<T: Copy>(source: &[T], destination: *mut T) {
+    if !source.is_empty() {
+        assert!(
+            !destination.is_null(),
+            "Rust: process_geometry_into(): an output buffer ptr was null"
+        );
+        ptr::copy_nonoverlapping(source.as_ptr(), destination, source.len());
+    }
+}
+
+/// Writes the output into the buffers and returns true if it fits. The sizes of the output are
+/// written to `output_sizes` in any case.
+///
+/// # Safety
+///
+/// See `process_geometry_into`
+unsafe fn write(
+    output: &JobOutput,
+    config: &[u8],
+    buffers: &OutputBuffers,
+    output_sizes: &mut OutputSizes,
+) -> bool {
+    *output_sizes = sizes(output, config);
+    if !fits(buffers, output_sizes) {
+        return false;
+    }
+    copy(&output.0, buffers.vertices);
+    copy(&output.1, buffers.indices);
+    copy(&output.2, buffers.matrices);
+    copy(&output.4, buffers.attributes);
+    copy(config, buffers.config);
+    true
+}
+
+/// Writes the output into the buffers, or keeps it until `deliver_pending` when it does not
+/// fit. Returns 0 when the output was written and 1 when it was kept.
+///
+/// # Safety
+///
+/// See `process_geometry_into`
+pub(super) unsafe fn deliver(
+    output: JobOutput,
+    buffers: &OutputBuffers,
+    output_sizes: &mut OutputSizes,
+) -> i32 {
+    let config = encode_config(&output.3);
+    let mut pending = PENDING.lock().unwrap();
+    if write(&output, &config, buffers, output_sizes) {
+        // an older result is never fetched once a new one was delivered
+        *pending = None;
+        0
+    } else {
+        info!(
+            "Rust: the output buffers are too small, keeping the result {:?}",
+            output_sizes
+        );
+        *pending = Some((output, config));
+        1
+    }
+}
+
+/// Writes the kept output into the buffers. Returns 0 when the output was written, 1 when it
+/// still does not fit and -1 when there is no kept output.
+///
+/// # Safety
+///
+/// See `process_geometry_into`
+pub(super) unsafe fn deliver_pending(
+    buffers: &OutputBuffers,
+    output_sizes: &mut OutputSizes,
+) -> i32 {
+    let mut pending = PENDING.lock().unwrap();
+    let Some((output, config)) = pending.as_ref() else {
+        return -1;
+    };
+    if write(output, config, buffers, output_sizes) {
+        *pending = None;
+        0
+    } else {
+        1
+    }
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{deliver, deliver_pending, encode_config};
+use crate::ffi::{FFIVector3, OutputBuffers, OutputSizes};
+use std::{collections::HashMap, ptr};
+
+#[test]
+fn test_encode_config() {
+    let mut config = HashMap::new();
+    let _ = config.insert("b".to_string(), "2".to_string());
+    let _ = config.insert("a".to_string(), "".to_string());
+    assert_eq!(b"a\0\0b\x002\0".to_vec(), encode_config(&config));
+    assert!(encode_config(&HashMap::new()).is_empty());
+}
+
+#[test]
+fn test_deliver() {
+    let mut config = HashMap::new();
+    let _ = config.insert("k".to_string(), "v".to_string());
+    let output = (
+        vec![FFIVector3::new(1.0, 2.0, 3.0)],
+        vec![0, 0],
+        vec![1.0; 16],
+        config,
+        vec![7_u8; 5],
+    );
+    // only ask for the sizes
+    let empty = OutputBuffers {
+        vertices: ptr::null_mut(),
+        vertex_capacity: 0,
+        indices: ptr::null_mut(),
+        indices_capacity: 0,
+        matrices: ptr::null_mut(),
+        matrices_capacity: 0,
+        attributes: ptr::null_mut(),
+        attributes_capacity: 0,
+        config: ptr::null_mut(),
+        config_capacity: 0,
+    };
+    let mut sizes = OutputSizes::default();
+    assert_eq!(1, unsafe { deliver(output, &empty, &mut sizes) });
+    let expected = OutputSizes {
+        vertex_count: 1,
+        indices_count: 2,
+        matrices_count: 16,
+        attributes_size: 5,
+        config_size: 4,
+    };
+    assert_eq!(expected, sizes);
+    // the kept result still does not fit
+    assert_eq!(1, unsafe { deliver_pending(&empty, &mut sizes) });
+
+    let mut vertices = vec![FFIVector3::default(); sizes.vertex_count];
+    let mut indices = vec![1_usize; sizes.indices_count];
+    let mut matrices = vec![0.0_f32; sizes.matrices_count];
+    let mut attributes = vec![0_u8; sizes.attributes_size];
+    let mut config = vec![0_u8; sizes.config_size];
+    let buffers = OutputBuffers {
+        vertices: vertices.as_mut_ptr(),
+        vertex_capacity: vertices.len(),
+        indices: indices.as_mut_ptr(),
+        indices_capacity: indices.len(),
+        matrices: matrices.as_mut_ptr(),
+        matrices_capacity: matrices.len(),
+        attributes: attributes.as_mut_ptr(),
+        attributes_capacity: attributes.len(),
+        config: config.as_mut_ptr(),
+        config_capacity: config.len(),
+    };
+    assert_eq!(0, unsafe { deliver_pending(&buffers, &mut sizes) });
+    assert_eq!(expected, sizes);
+    assert_eq!(vec![FFIVector3::new(1.0, 2.0, 3.0)], vertices);
+    assert_eq!(vec![0, 0], indices);
+    assert_eq!(vec![1.0; 16], matrices);
+    assert_eq!(vec![7; 5], attributes);
+    assert_eq!(b"k\0v\0".to_vec(), config);
+    // the kept result can only be fetched once
+    assert_eq!(-1, unsafe { deliver_pending(&buffers, &mut sizes) });
+}
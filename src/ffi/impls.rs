@@ -4,7 +4,7 @@
 
 //! A module containing boiler-plate implementations of standard traits such as Default, From etc etc
 
-use super::FFIVector3;
+use super::{FFIVector3, FFIVector3d};
 use hronn::prelude::ConvertTo;
 use std::fmt;
 use vector_traits::{
@@ -33,6 +33,45 @@ impl fmt::Debug for FFIVector3 {
     }
 }
 
+impl fmt::Debug for FFIVector3d {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({},{},{})", self.x, self.y, self.z)
+    }
+}
+
+impl From<(f64, f64, f64)> for FFIVector3d {
+    #[inline(always)]
+    fn from(v: (f64, f64, f64)) -> Self {
+        Self {
+            x: v.0,
+            y: v.1,
+            z: v.2,
+        }
+    }
+}
+
+impl From<DVec3> for FFIVector3d {
+    #[inline(always)]
+    fn from(v: DVec3) -> Self {
+        Self {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+        }
+    }
+}
+
+impl From<FFIVector3d> for DVec3 {
+    #[inline(always)]
+    fn from(v: FFIVector3d) -> Self {
+        Self {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+        }
+    }
+}
+
 impl HasXY for FFIVector3 {
     type Scalar = f32;
     fn new_2d(x: Self::Scalar, y: Self::Scalar) -> Self {
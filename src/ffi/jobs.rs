@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! The background jobs of the time-sliced execution mode.
+//!
+//! `process_geometry_start` copies the input and runs the command on a worker thread, the caller
+//! then drives it with `process_geometry_poll`, which never blocks for longer than the requested
+//! time slice. The Python side can keep the Blender UI alive between the polls.
+//!
+//! Only one job runs at a time: the cancel request, the log level and the progress callback are
+//! shared by all the commands, a second job would clear the cancel request and change the log
+//! level of the first one. `start` refuses a job while another one is running.
+
+#[cfg(test)]
+mod tests;
+
+use super::FFIVector3;
+use crate::{command::progress, HallrErrorCode};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// The output of a command: vertices, indices, matrices, config and the attribute blob
+pub(super) type JobOutput = (
+    Vec<FFIVector3>,
+    Vec<usize>,
+    Vec<f32>,
+    HashMap<String, String>,
+    Vec<u8>,
+);
+
+/// The running (or finished but not yet polled) jobs, by token
+static JOBS: Mutex<BTreeMap<u64, Receiver<JobOutput>>> = Mutex::new(BTreeMap::new());
+/// The next token, zero is never used
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+/// True while a job runs
+static JOB_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Clears `JOB_RUNNING` when dropped, also when the job panics
+struct Running;
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        JOB_RUNNING.store(false, Ordering::Release);
+    }
+}
+
+/// The state of a job
+pub(super) enum JobState {
+    Running,
+    Finished(JobOutput),
+    /// The token was never returned by `start`, or the result was already fetched
+    Unknown,
+}
+
+/// Run `job` on a new thread, and return the token used to poll it. Returns `None` if another
+/// job is running.
+pub(super) fn start<F>(job: F) -> Option<u64>
+where
+    F: FnOnce() -> JobOutput + Send + 'static,
+{
+    if JOB_RUNNING
+        .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        return None;
+    }
+    // cleared here and not on the worker thread, a cancel request made after `start` returns
+    // reaches the job
+    progress::clear_cancel();
+    let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+    let (sender, receiver) = mpsc::channel();
+    let _ = thread::spawn(move || {
+        // the job is done before its output can be polled, so the next job can start then
+        let output = {
+            let _running = Running;
+            job()
+        };
+        // the receiver is only gone if the job was abandoned
+        let _ = sender.send(output);
+    });
+    let _ = JOBS.lock().unwrap().insert(token, receiver);
+    Some(token)
+}
+
+/// Wait at most `timeout` for the job to finish. A finished job is forgotten, its output can
+/// only be fetched once.
+pub(super) fn poll(token: u64, timeout: Duration) -> JobState {
+    // the lock is not held while waiting, so that other jobs can be polled meanwhile
+    let Some(receiver) = JOBS.lock().unwrap().remove(&token) else {
+        return JobState::Unknown;
+    };
+    match receiver.recv_timeout(timeout) {
+        Ok(output) => JobState::Finished(output),
+        Err(RecvTimeoutError::Timeout) => {
+            let _ = JOBS.lock().unwrap().insert(token, receiver);
+            JobState::Running
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            let detail = "The command panicked, see the console for details".to_string();
+            let mut config = HashMap::new();
+            let _ = config.insert("ERROR".to_string(), detail.clone());
+            let _ = config.insert(
+                "ERROR_CODE".to_string(),
+                (HallrErrorCode::InternalError as u32).to_string(),
+            );
+            let _ = config.insert("ERROR_DETAIL".to_string(), detail);
+            JobState::Finished((vec![], vec![], vec![], config, vec![]))
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{poll, start, JobState};
+use crate::{ffi::error_config, HallrError};
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Mutex},
+    time::Duration,
+};
+
+/// Only one job runs at a time, the tests starting jobs take turns
+static SERIAL: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_jobs_poll() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let (sender, receiver) = mpsc::channel::<()>();
+    let token = start(move || {
+        // wait until the test has seen the job running
+        let _ = receiver.recv();
+        (vec![], vec![1, 2], vec![], HashMap::new(), vec![])
+    })
+    .unwrap();
+    assert!(matches!(poll(token, Duration::ZERO), JobState::Running));
+    // a second job is refused while the first one runs
+    assert!(start(|| (vec![], vec![], vec![], HashMap::new(), vec![])).is_none());
+    sender.send(()).unwrap();
+    match poll(token, Duration::from_secs(10)) {
+        JobState::Finished((_, indices, _, _, _)) => assert_eq!(vec![1, 2], indices),
+        _ => panic!("the job did not finish"),
+    }
+    // the output can only be fetched once
+    assert!(matches!(poll(token, Duration::ZERO), JobState::Unknown));
+    // the next job can start once the output was fetched
+    let token = start(|| (vec![], vec![], vec![], HashMap::new(), vec![])).unwrap();
+    assert!(matches!(
+        poll(token, Duration::from_secs(10)),
+        JobState::Finished(_)
+    ));
+}
+
+#[test]
+fn test_jobs_panic() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let token = start(|| panic!("expected panic")).unwrap();
+    match poll(token, Duration::from_secs(10)) {
+        JobState::Finished((_, _, _, config, _)) => {
+            assert!(config.contains_key("ERROR"));
+            assert_eq!(Some("14"), config.get("ERROR_CODE").map(|c| c.as_str()));
+        }
+        _ => panic!("the job did not finish"),
+    }
+    // the panicked job does not block the next one
+    let token = start(|| (vec![], vec![], vec![], HashMap::new(), vec![])).unwrap();
+    assert!(matches!(
+        poll(token, Duration::from_secs(10)),
+        JobState::Finished(_)
+    ));
+}
+
+#[test]
+fn test_error_config() {
+    let config = error_config(HallrError::MissingParameter("SIZE".to_string()));
+    assert_eq!("Missing parameter: SIZE", config["ERROR"]);
+    assert_eq!("12", config["ERROR_CODE"]);
+    assert_eq!("SIZE", config["ERROR_DETAIL"]);
+}
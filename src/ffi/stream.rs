@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! The geometry streams of the chunked transfer mode.
+//!
+//! `begin_geometry_stream` returns a token, the caller then appends the vertices and indices in
+//! chunks with `push_vertices` and `push_indices`, and runs the command on the collected geometry
+//! with `finish_and_process`. The caller never has to hold the whole mesh in one FFI buffer.
+
+#[cfg(test)]
+mod tests;
+
+use super::FFIVector3;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+/// The geometry collected by a stream
+#[derive(Default)]
+struct Stream {
+    vertices: Vec<FFIVector3>,
+    indices: Vec<usize>,
+}
+
+/// The open streams, by token
+static STREAMS: Mutex<BTreeMap<u64, Stream>> = Mutex::new(BTreeMap::new());
+/// The next token, zero is never used
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+
+/// Open a new stream and return its token. The capacities are hints, the memory is reserved up
+/// front when it is available.
+pub(super) fn begin(vertex_capacity: usize, index_capacity: usize) -> u64 {
+    let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+    let mut stream = Stream::default();
+    // a failed reservation is not an error, the Vecs grow as the chunks arrive
+    let _ = stream.vertices.try_reserve_exact(vertex_capacity);
+    let _ = stream.indices.try_reserve_exact(index_capacity);
+    let _ = STREAMS.lock().unwrap().insert(token, stream);
+    token
+}
+
+/// Append the vertices to the stream, returns false for unknown tokens
+pub(super) fn push_vertices(token: u64, vertices: &[FFIVector3]) -> bool {
+    match STREAMS.lock().unwrap().get_mut(&token) {
+        Some(stream) => {
+            stream.vertices.extend_from_slice(vertices);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Append the indices to the stream, returns false for unknown tokens
+pub(super) fn push_indices(token: u64, indices: &[usize]) -> bool {
+    match STREAMS.lock().unwrap().get_mut(&token) {
+        Some(stream) => {
+            stream.indices.extend_from_slice(indices);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Close the stream and return the collected vertices and indices, None for unknown tokens
+pub(super) fn finish(token: u64) -> Option<(Vec<FFIVector3>, Vec<usize>)> {
+    STREAMS
+        .lock()
+        .unwrap()
+        .remove(&token)
+        .map(|stream| (stream.vertices, stream.indices))
+}
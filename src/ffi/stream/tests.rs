@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{begin, finish, push_indices, push_vertices};
+use crate::ffi::FFIVector3;
+
+#[test]
+fn test_stream_chunks() {
+    let token = begin(3, 3);
+    let vertices: Vec<FFIVector3> = vec![
+        (0.0, 0.0, 0.0).into(),
+        (1.0, 0.0, 0.0).into(),
+        (0.0, 1.0, 0.0).into(),
+    ];
+    assert!(push_vertices(token, &vertices[..2]));
+    assert!(push_indices(token, &[0, 1]));
+    assert!(push_vertices(token, &vertices[2..]));
+    assert!(push_indices(token, &[2]));
+    let (streamed_vertices, streamed_indices) = finish(token).unwrap();
+    assert_eq!(vertices, streamed_vertices);
+    assert_eq!(vec![0, 1, 2], streamed_indices);
+    // the stream is closed
+    assert!(!push_indices(token, &[3]));
+    assert!(finish(token).is_none());
+}
+
+#[test]
+fn test_stream_capacity_hint() {
+    // an impossible reservation is ignored
+    let token = begin(usize::MAX, 0);
+    assert!(push_indices(token, &[1]));
+    assert_eq!((vec![], vec![1]), finish(token).unwrap());
+}
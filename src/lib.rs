@@ -27,6 +27,9 @@
 //! memory leaks and dangling pointers. For the same reason, the API is stateless, ensuring that
 //! everything needed for a specific operation is contained within that operation.
 
+#[macro_use]
+pub(crate) mod logging;
+pub mod api;
 pub mod command;
 pub mod ffi;
 pub(crate) mod utils;
@@ -35,8 +38,17 @@ use hronn::HronnError;
 
 pub mod prelude {
     pub use crate::{
-        ffi::{free_process_results, process_geometry, FFIVector3, GeometryOutput, StringMap},
-        HallrError,
+        command::plugin::PluginGeometry,
+        ffi::{
+            abort_geometry_stream, begin_geometry_stream, clear_result_cache, fetch_pending_output,
+            finish_and_process, free_process_results, free_process_results_f64, free_string_map,
+            hallr_list_commands, hallr_register_command, hallr_request_cancel,
+            hallr_set_log_callback, hallr_set_log_level, hallr_set_progress_callback,
+            process_geometry, process_geometry_f64, process_geometry_into, process_geometry_poll,
+            process_geometry_start, push_indices, push_vertices, AttributeOutput, FFIVector3,
+            FFIVector3d, GeometryOutput, GeometryOutputF64, OutputBuffers, OutputSizes, StringMap,
+        },
+        HallrError, HallrErrorCode,
     };
 }
 
@@ -83,4 +95,75 @@ pub enum HallrError {
 
     #[error("Unknown error: {0}")]
     InternalError(String),
+
+    #[error("The command was cancelled")]
+    Cancelled,
+
+    #[error("Plugin error: {0}")]
+    PluginError(String),
+}
+
+/// The stable numeric codes of the `HallrError` variants, returned as `ERROR_CODE` through the
+/// FFI. New variants get new numbers, existing numbers never change.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HallrErrorCode {
+    EarcutrError = 1,
+    BoostVoronoiError = 2,
+    CenterlineError = 3,
+    HronnErr = 4,
+    LinestringError = 5,
+    Overflow = 6,
+    FloatNotFinite = 7,
+    InvalidParameter = 8,
+    InputNotPLane = 9,
+    InvalidInputData = 10,
+    NoData = 11,
+    MissingParameter = 12,
+    ModelContainsFaces = 13,
+    InternalError = 14,
+    Cancelled = 15,
+    PluginError = 16,
+}
+
+impl HallrError {
+    /// Returns the stable numeric code of the variant
+    pub fn code(&self) -> HallrErrorCode {
+        match self {
+            Self::EarcutrError(_) => HallrErrorCode::EarcutrError,
+            Self::BoostVoronoiError(_) => HallrErrorCode::BoostVoronoiError,
+            Self::CenterlineError(_) => HallrErrorCode::CenterlineError,
+            Self::HronnErr(_) => HallrErrorCode::HronnErr,
+            Self::LinestringError(_) => HallrErrorCode::LinestringError,
+            Self::Overflow(_) => HallrErrorCode::Overflow,
+            Self::FloatNotFinite(_) => HallrErrorCode::FloatNotFinite,
+            Self::InvalidParameter(_) => HallrErrorCode::InvalidParameter,
+            Self::InputNotPLane(_) => HallrErrorCode::InputNotPLane,
+            Self::InvalidInputData(_) => HallrErrorCode::InvalidInputData,
+            Self::NoData(_) => HallrErrorCode::NoData,
+            Self::MissingParameter(_) => HallrErrorCode::MissingParameter,
+            Self::ModelContainsFaces(_) => HallrErrorCode::ModelContainsFaces,
+            Self::InternalError(_) => HallrErrorCode::InternalError,
+            Self::Cancelled => HallrErrorCode::Cancelled,
+            Self::PluginError(_) => HallrErrorCode::PluginError,
+        }
+    }
+
+    /// Returns the message of the error without the description of the variant, e.g. the name
+    /// of the parameter of a `MissingParameter`
+    pub fn detail(&self) -> String {
+        match self {
+            Self::Overflow(detail)
+            | Self::FloatNotFinite(detail)
+            | Self::InvalidParameter(detail)
+            | Self::InputNotPLane(detail)
+            | Self::InvalidInputData(detail)
+            | Self::NoData(detail)
+            | Self::MissingParameter(detail)
+            | Self::ModelContainsFaces(detail)
+            | Self::InternalError(detail)
+            | Self::PluginError(detail) => detail.clone(),
+            _ => self.to_string(),
+        }
+    }
 }
@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! The diagnostic messages of the crate.
+//!
+//! The messages are written with the `error!`, `warn!`, `info!` and `debug!` macros. They go to
+//! the console (errors to stderr) unless the caller registered a callback with
+//! `hallr_set_log_callback`, which then receives every message with its level. The callback may
+//! be invoked from the rayon worker threads, so it must be thread safe.
+//!
+//! Messages more verbose than the log level are dropped. The level is set with
+//! `hallr_set_log_level` (`Info` by default), and can be overridden for a single command with the
+//! `LOG_LEVEL` option (`OFF`, `ERROR`, `WARN`, `INFO` or `DEBUG`). The override lasts until the
+//! next command starts.
+
+/// Log a message at the `Error` level, with the arguments of `format!`
+macro_rules! error {
+    ($($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Error, format_args!($($arg)+))
+    };
+}
+
+/// Log a message at the `Warn` level, with the arguments of `format!`
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Warn, format_args!($($arg)+))
+    };
+}
+
+/// Log a message at the `Info` level, with the arguments of `format!`
+macro_rules! info {
+    ($($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Info, format_args!($($arg)+))
+    };
+}
+
+/// Log a message at the `Debug` level, with the arguments of `format!`
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Debug, format_args!($($arg)+))
+    };
+}
+
+#[cfg(test)]
+mod tests;
+
+use crate::HallrError;
+use std::{
+    ffi::CString,
+    fmt,
+    os::raw::c_char,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+/// The option overriding the log level of a command
+pub(crate) const LOG_LEVEL_KEY: &str = "LOG_LEVEL";
+
+/// The signature of the log callback, the arguments are the `Level` as a number and the
+/// null-terminated message. The message is only valid during the call.
+pub type LogCallback = extern "C" fn(level: u32, message: *const c_char);
+
+/// The verbosity of a message, and the log level
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// As a log level: nothing is logged
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+}
+
+impl Level {
+    /// The level of the number, numbers above `Debug` are `Debug`
+    pub(crate) fn from_u32(level: u32) -> Self {
+        match level {
+            0 => Self::Off,
+            1 => Self::Error,
+            2 => Self::Warn,
+            3 => Self::Info,
+            _ => Self::Debug,
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = HallrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "OFF" => Ok(Self::Off),
+            "ERROR" => Ok(Self::Error),
+            "WARN" => Ok(Self::Warn),
+            "INFO" => Ok(Self::Info),
+            "DEBUG" => Ok(Self::Debug),
+            _ => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"{}\" parameter",
+                s, LOG_LEVEL_KEY
+            ))),
+        }
+    }
+}
+
+static LOG_CALLBACK: Mutex<Option<LogCallback>> = Mutex::new(None);
+static LOG_LEVEL: AtomicU32 = AtomicU32::new(Level::Info as u32);
+/// The `LOG_LEVEL` of the running command, `NO_LEVEL` when the command did not set one
+static COMMAND_LOG_LEVEL: AtomicU32 = AtomicU32::new(NO_LEVEL);
+const NO_LEVEL: u32 = u32::MAX;
+
+/// Register the log callback, `None` restores the console output
+pub(crate) fn set_callback(callback: Option<LogCallback>) {
+    *LOG_CALLBACK.lock().unwrap() = callback;
+}
+
+/// Set the log level of the commands without a `LOG_LEVEL` option
+pub(crate) fn set_level(level: Level) {
+    LOG_LEVEL.store(level as u32, Ordering::Relaxed);
+}
+
+/// Set the log level of the command that starts, `None` uses the level of `set_level`
+pub(crate) fn set_command_level(level: Option<Level>) {
+    COMMAND_LOG_LEVEL.store(level.map_or(NO_LEVEL, |l| l as u32), Ordering::Relaxed);
+}
+
+/// Returns the current log level
+pub(crate) fn level() -> Level {
+    match COMMAND_LOG_LEVEL.load(Ordering::Relaxed) {
+        NO_LEVEL => Level::from_u32(LOG_LEVEL.load(Ordering::Relaxed)),
+        level => Level::from_u32(level),
+    }
+}
+
+/// Write the message to the callback or the console, if the log level allows it. Use the macros
+/// instead.
+pub(crate) fn log(level: Level, message: fmt::Arguments<'_>) {
+    if level == Level::Off || level > self::level() {
+        return;
+    }
+    // the lock is not held while the callback runs
+    let callback = *LOG_CALLBACK.lock().unwrap();
+    match callback {
+        Some(callback) => {
+            // a C string can not hold a null byte
+            let message = CString::new(message.to_string().replace('\0', "")).unwrap_or_default();
+            callback(level as u32, message.as_ptr());
+        }
+        None if level == Level::Error => eprintln!("{}", message),
+        None => println!("{}", message),
+    }
+}
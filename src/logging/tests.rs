@@ -0,0 +1,41 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+use super::{set_callback, Level};
+use std::{ffi::CStr, os::raw::c_char, sync::Mutex};
+
+static LOGGED: Mutex<Vec<(u32, String)>> = Mutex::new(Vec::new());
+
+extern "C" fn record(level: u32, message: *const c_char) {
+    let message = unsafe { CStr::from_ptr(message) }
+        .to_string_lossy()
+        .to_string();
+    LOGGED.lock().unwrap().push((level, message));
+}
+
+#[test]
+fn test_level() {
+    assert_eq!(Level::Warn, "WARN".parse::<Level>().unwrap());
+    assert!("warn".parse::<Level>().is_err());
+    assert!(Level::Debug > Level::Info);
+    assert_eq!(Level::Off, Level::from_u32(0));
+    assert_eq!(Level::Debug, Level::from_u32(17));
+}
+
+#[test]
+fn test_log_callback() {
+    set_callback(Some(record));
+    error!("test_log_callback {}", "\0marker");
+    // the warnings are logged at the default level
+    warn!("test_log_callback warning");
+    set_callback(None);
+    // other tests may log meanwhile
+    let logged = LOGGED.lock().unwrap();
+    assert!(logged
+        .iter()
+        .any(|(level, message)| *level == 1 && message == "test_log_callback marker"));
+    assert!(logged
+        .iter()
+        .any(|(level, message)| *level == 2 && message == "test_log_callback warning"));
+}
@@ -2,18 +2,20 @@
 // Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
 // This file is part of the hallr crate.
 
+pub(crate) mod bvh;
 mod impls;
 #[cfg(test)]
 mod tests;
 pub(crate) mod voronoi_utils;
 
-use crate::HallrError;
+use crate::{ffi::FFIVector3, HallrError};
 use ahash::{AHashMap, AHashSet};
 use hronn::prelude::MaximumTracker;
 use smallvec::SmallVec;
 use std::cmp::Reverse;
 use vector_traits::{
-    num_traits::float::FloatCore, GenericScalar, GenericVector2, GenericVector3, HasXYZ,
+    approx::ulps_eq, num_traits::float::FloatCore, GenericScalar, GenericVector2, GenericVector3,
+    HasXYZ,
 };
 
 pub(crate) trait GrowingVob {
@@ -328,3 +330,35 @@ pub fn reconstruct_from_unordered_edges(edges: &[usize]) -> Result<Vec<usize>, H
 
     Ok(reconstructed)
 }
+
+/// Detects if the vertices are located in an axis aligned plane that does not cross origin,
+/// e.g. `z=c`. Returns the offset of that plane, or a zero vector if the data already crosses origin
+/// (or is not axis aligned at all).
+/// The offset should be subtracted from the input and then added back to the output.
+pub(crate) fn axis_aligned_plane_offset(vertices: &[FFIVector3]) -> FFIVector3 {
+    let mut offset = FFIVector3::default();
+    if vertices.is_empty() {
+        return offset;
+    }
+    let mut low = FFIVector3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut high = FFIVector3::new(f32::MIN, f32::MIN, f32::MIN);
+    for v in vertices {
+        low = FFIVector3::new(low.x.min(v.x), low.y.min(v.y), low.z.min(v.z));
+        high = FFIVector3::new(high.x.max(v.x), high.y.max(v.y), high.z.max(v.z));
+    }
+    let center = (low + high) / 2.0;
+    // XY is the most common plane, so it is tested first
+    if ulps_eq!(low.z, high.z) {
+        offset.z = center.z;
+    } else if ulps_eq!(low.y, high.y) {
+        offset.y = center.y;
+    } else if ulps_eq!(low.x, high.x) {
+        offset.x = center.x;
+    }
+    offset
+}
+
+/// Returns a copy of the vertices, translated by `-offset`
+pub(crate) fn translate_vertices(vertices: &[FFIVector3], offset: FFIVector3) -> Vec<FFIVector3> {
+    vertices.iter().map(|v| *v - offset).collect()
+}
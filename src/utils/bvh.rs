@@ -0,0 +1,395 @@
+// SPDX-License-Identifier: AGPL-3.0-or-later
+// Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
+// This file is part of the hallr crate.
+
+//! A bounding volume hierarchy over the triangles of a mesh, and the triangle queries it is built
+//! on.
+
+use vector_traits::glam::DVec3;
+
+/// The maximum number of triangles in a BVH leaf
+const LEAF_SIZE: usize = 4;
+
+struct BvhNode {
+    min: DVec3,
+    max: DVec3,
+    /// Leaf: the range of triangles. Inner node: the range holds the indices of the two children.
+    start: usize,
+    end: usize,
+    is_leaf: bool,
+}
+
+impl BvhNode {
+    /// Returns the squared distance from `p` to the bounding box, zero if `p` is inside
+    fn distance_squared(&self, p: DVec3) -> f64 {
+        let d = (self.min - p).max(p - self.max).max(DVec3::ZERO);
+        d.length_squared()
+    }
+
+    /// Returns where the ray enters the bounding box, in multiples of the ray direction. Zero if
+    /// the origin is inside, None if the ray misses the box.
+    fn ray_entry(&self, origin: DVec3, inverse_direction: DVec3) -> Option<f64> {
+        let (mut near, mut far) = (0.0_f64, f64::MAX);
+        for axis in 0..3 {
+            if inverse_direction[axis].is_infinite() {
+                // parallel to the slab
+                if origin[axis] < self.min[axis] || origin[axis] > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let t1 = (self.min[axis] - origin[axis]) * inverse_direction[axis];
+            let t2 = (self.max[axis] - origin[axis]) * inverse_direction[axis];
+            near = near.max(t1.min(t2));
+            far = far.min(t1.max(t2));
+        }
+        (far >= near).then_some(near)
+    }
+}
+
+/// A bounding volume hierarchy over the triangles of a mesh, used for closest point and overlap
+/// queries
+pub(crate) struct Bvh {
+    nodes: Vec<BvhNode>,
+    triangles: Vec<[DVec3; 3]>,
+    /// The original index of every triangle
+    ids: Vec<usize>,
+}
+
+/// Returns the bounding box of the triangle
+pub(crate) fn triangle_bounds(t: &[DVec3; 3]) -> (DVec3, DVec3) {
+    (t[0].min(t[1]).min(t[2]), t[0].max(t[1]).max(t[2]))
+}
+
+impl Bvh {
+    pub(crate) fn new(triangles: Vec<[DVec3; 3]>) -> Self {
+        let mut nodes = Vec::<BvhNode>::with_capacity(2 * triangles.len() / LEAF_SIZE + 1);
+        let mut ids: Vec<usize> = (0..triangles.len()).collect();
+        if !triangles.is_empty() {
+            let len = triangles.len();
+            let _ = Self::build(&mut nodes, &triangles, &mut ids, 0, len);
+        }
+        let triangles = ids.iter().map(|i| triangles[*i]).collect();
+        Self {
+            nodes,
+            triangles,
+            ids,
+        }
+    }
+
+    /// Recursively builds the node of the `start..end` triangles, splitting at the median of the
+    /// longest axis. Returns the index of the node.
+    fn build(
+        nodes: &mut Vec<BvhNode>,
+        triangles: &[[DVec3; 3]],
+        ids: &mut [usize],
+        start: usize,
+        end: usize,
+    ) -> usize {
+        let (min, max) = ids[start..end]
+            .iter()
+            .flat_map(|i| triangles[*i].iter())
+            .fold(
+                (DVec3::splat(f64::MAX), DVec3::splat(f64::MIN)),
+                |(min, max), v| (min.min(*v), max.max(*v)),
+            );
+        let index = nodes.len();
+        nodes.push(BvhNode {
+            min,
+            max,
+            start,
+            end,
+            is_leaf: true,
+        });
+        if end - start <= LEAF_SIZE {
+            return index;
+        }
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let centroid = |i: &usize| {
+            let t = &triangles[*i];
+            (t[0][axis] + t[1][axis] + t[2][axis]) / 3.0
+        };
+        let mid = (start + end) / 2;
+        let _ = ids[start..end].select_nth_unstable_by(mid - start, |a, b| {
+            centroid(a).partial_cmp(&centroid(b)).unwrap()
+        });
+        let left = Self::build(nodes, triangles, ids, start, mid);
+        let right = Self::build(nodes, triangles, ids, mid, end);
+        let node = &mut nodes[index];
+        node.start = left;
+        node.end = right;
+        node.is_leaf = false;
+        index
+    }
+
+    /// Returns the distance from `p` to the closest point of the mesh, or None if the mesh is
+    /// empty
+    pub(crate) fn distance(&self, p: DVec3) -> Option<f64> {
+        self.closest(p).map(|(closest, _)| closest.distance(p))
+    }
+
+    /// Returns the closest point of the mesh and the triangle it is on, or None if the mesh is
+    /// empty
+    pub(crate) fn closest(&self, p: DVec3) -> Option<(DVec3, &[DVec3; 3])> {
+        self.closest_position(p)
+            .map(|(closest, i)| (closest, &self.triangles[i]))
+    }
+
+    /// Returns the closest point of the mesh and the original index of its triangle, or None if
+    /// the mesh is empty
+    pub(crate) fn closest_id(&self, p: DVec3) -> Option<(DVec3, usize)> {
+        self.closest_position(p)
+            .map(|(closest, i)| (closest, self.ids[i]))
+    }
+
+    /// Returns the closest point and the position of its triangle in `self.triangles`
+    fn closest_position(&self, p: DVec3) -> Option<(DVec3, usize)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut best = f64::MAX;
+        let mut rv = None;
+        let mut stack = vec![0_usize];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.distance_squared(p) >= best {
+                continue;
+            }
+            if node.is_leaf {
+                for i in node.start..node.end {
+                    let closest = closest_point_on_triangle(p, &self.triangles[i]);
+                    let distance_squared = closest.distance_squared(p);
+                    if distance_squared < best {
+                        best = distance_squared;
+                        rv = Some((closest, i));
+                    }
+                }
+            } else {
+                // visit the closest child first
+                let (near, far) = if self.nodes[node.start].distance_squared(p)
+                    <= self.nodes[node.end].distance_squared(p)
+                {
+                    (node.start, node.end)
+                } else {
+                    (node.end, node.start)
+                };
+                stack.push(far);
+                stack.push(near);
+            }
+        }
+        rv
+    }
+
+    /// Returns the original indices of the triangles with a bounding box overlapping the box from
+    /// `min` to `max`
+    pub(crate) fn overlapping(&self, min: DVec3, max: DVec3) -> Vec<usize> {
+        let mut rv = Vec::new();
+        if self.nodes.is_empty() {
+            return rv;
+        }
+        let mut stack = vec![0_usize];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.min.cmpgt(max).any() || min.cmpgt(node.max).any() {
+                continue;
+            }
+            if node.is_leaf {
+                for i in node.start..node.end {
+                    let (low, high) = triangle_bounds(&self.triangles[i]);
+                    if !(low.cmpgt(max).any() || min.cmpgt(high).any()) {
+                        rv.push(self.ids[i]);
+                    }
+                }
+            } else {
+                stack.push(node.start);
+                stack.push(node.end);
+            }
+        }
+        rv
+    }
+
+    /// Returns the closest hit of the ray from `origin` along `direction`, in multiples of
+    /// `direction`, or None if no triangle is hit within `max_distance`
+    pub(crate) fn raycast(
+        &self,
+        origin: DVec3,
+        direction: DVec3,
+        max_distance: f64,
+    ) -> Option<f64> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let inverse_direction = direction.recip();
+        let mut rv: Option<f64> = None;
+        let mut stack = vec![0_usize];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            match node.ray_entry(origin, inverse_direction) {
+                Some(entry) if entry <= rv.unwrap_or(max_distance) => (),
+                _ => continue,
+            }
+            if node.is_leaf {
+                for t in self.triangles[node.start..node.end].iter() {
+                    if let Some(hit) = ray_triangle_intersection(origin, direction, t) {
+                        if hit <= rv.unwrap_or(max_distance) {
+                            rv = Some(hit);
+                        }
+                    }
+                }
+            } else {
+                stack.push(node.start);
+                stack.push(node.end);
+            }
+        }
+        rv
+    }
+
+    /// Returns the highest Z of the mesh straight above or below (`x`, `y`), or None if no
+    /// triangle covers the point
+    pub(crate) fn highest_z(&self, x: f64, y: f64) -> Option<f64> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut rv: Option<f64> = None;
+        let mut stack = vec![0_usize];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if x < node.min.x || x > node.max.x || y < node.min.y || y > node.max.y {
+                continue;
+            }
+            if matches!(rv, Some(z) if node.max.z <= z) {
+                // nothing in this node is higher
+                continue;
+            }
+            if node.is_leaf {
+                for t in self.triangles[node.start..node.end].iter() {
+                    if let Some(z) = z_on_triangle(x, y, t) {
+                        rv = Some(rv.map_or(z, |rv| rv.max(z)));
+                    }
+                }
+            } else {
+                stack.push(node.start);
+                stack.push(node.end);
+            }
+        }
+        rv
+    }
+}
+
+/// Returns the Z of the triangle at (`x`, `y`), or None if the point is outside of the triangle
+/// in the XY plane. Vertical triangles are ignored.
+pub(crate) fn z_on_triangle(x: f64, y: f64, t: &[DVec3; 3]) -> Option<f64> {
+    let (a, b, c) = (t[0], t[1], t[2]);
+    let denominator = (b.y - c.y) * (a.x - c.x) + (c.x - b.x) * (a.y - c.y);
+    if denominator.abs() < f64::EPSILON {
+        return None;
+    }
+    let wa = ((b.y - c.y) * (x - c.x) + (c.x - b.x) * (y - c.y)) / denominator;
+    let wb = ((c.y - a.y) * (x - c.x) + (a.x - c.x) * (y - c.y)) / denominator;
+    let wc = 1.0 - wa - wb;
+    // a small tolerance, so that points on shared edges are not lost
+    if wa < -1e-9 || wb < -1e-9 || wc < -1e-9 {
+        return None;
+    }
+    Some(wa * a.z + wb * b.z + wc * c.z)
+}
+
+/// Returns where the segment `p`-`q` crosses the triangle, as the fraction of the way from `p`
+/// to `q`. Segments in the plane of the triangle are never crossing.
+pub(crate) fn segment_triangle_intersection(p: DVec3, q: DVec3, t: &[DVec3; 3]) -> Option<f64> {
+    ray_triangle_intersection(p, q - p, t).filter(|f| *f <= 1.0)
+}
+
+/// Returns where the ray from `origin` along `direction` hits the triangle, in multiples of
+/// `direction`. Rays in the plane of the triangle never hit.
+/// From "Fast, Minimum Storage Ray/Triangle Intersection" by Möller and Trumbore.
+pub(crate) fn ray_triangle_intersection(
+    origin: DVec3,
+    direction: DVec3,
+    t: &[DVec3; 3],
+) -> Option<f64> {
+    let (e1, e2) = (t[1] - t[0], t[2] - t[0]);
+    let h = direction.cross(e2);
+    let det = e1.dot(h);
+    if det.abs() <= f64::EPSILON * e1.length_squared().max(e2.length_squared()) {
+        return None;
+    }
+    let s = origin - t[0];
+    let u = s.dot(h) / det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let k = s.cross(e1);
+    let v = direction.dot(k) / det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some(e2.dot(k) / det).filter(|f| *f >= 0.0)
+}
+
+/// Returns the point of the triangle closest to `p`.
+/// From "Real-Time Collision Detection" by Christer Ericson.
+pub(crate) fn closest_point_on_triangle(p: DVec3, t: &[DVec3; 3]) -> DVec3 {
+    let (a, b, c) = (t[0], t[1], t[2]);
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denominator = va + vb + vc;
+    if denominator.abs() < f64::EPSILON {
+        // degenerate triangle, use the closest of the edges
+        return [(a, b), (b, c), (c, a)]
+            .iter()
+            .map(|(s, e)| {
+                let se = *e - *s;
+                let h = if se.length_squared() > 0.0 {
+                    ((p - *s).dot(se) / se.length_squared()).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                *s + se * h
+            })
+            .min_by(|x, y| {
+                x.distance_squared(p)
+                    .partial_cmp(&y.distance_squared(p))
+                    .unwrap()
+            })
+            .unwrap();
+    }
+    a + ab * (vb / denominator) + ac * (vc / denominator)
+}
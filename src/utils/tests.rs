@@ -2,8 +2,9 @@
 // Copyright (c) 2023 lacklustr@protonmail.com https://github.com/eadf
 // This file is part of the hallr crate.
 
+use super::bvh;
 use std::fmt::Debug;
-use vector_traits::glam::{DVec2, DVec3};
+use vector_traits::glam::{dvec3, DVec2, DVec3};
 
 #[allow(dead_code)]
 const EPSILON: f64 = 1e-10; // or some small value that's appropriate for your use case
@@ -35,3 +36,162 @@ impl SillyApproxEq for DVec3 {
             && (self.z - other.z).abs() <= epsilon
     }
 }
+
+#[test]
+fn test_remove_degenerate_input() {
+    use super::voronoi_utils::{remove_degenerate_input, DegenerateInputCount};
+    use boostvoronoi as BV;
+
+    let points = vec![
+        BV::Point { x: 5, y: 5 },
+        BV::Point { x: 5, y: 5 },
+        BV::Point { x: 0, y: 0 },
+        BV::Point { x: 7, y: 3 },
+    ];
+    let segments = vec![
+        BV::Line::new(BV::Point { x: 0, y: 0 }, BV::Point { x: 10, y: 0 }),
+        BV::Line::new(BV::Point { x: 10, y: 0 }, BV::Point { x: 0, y: 0 }),
+        BV::Line::new(BV::Point { x: 3, y: 3 }, BV::Point { x: 3, y: 3 }),
+        BV::Line::new(BV::Point { x: 10, y: 0 }, BV::Point { x: 10, y: 10 }),
+    ];
+    let (points, segments, count) = remove_degenerate_input(points, segments);
+    assert_eq!(2, points.len());
+    assert_eq!(2, segments.len());
+    assert_eq!(
+        DegenerateInputCount {
+            duplicated_points: 2,
+            zero_length_segments: 1,
+            duplicated_segments: 1,
+        },
+        count
+    );
+}
+
+#[test]
+fn test_voronoi_snapping() -> Result<(), crate::HallrError> {
+    use super::voronoi_utils::VoronoiSnapping;
+    use crate::command::ConfigType;
+
+    let mut config = ConfigType::default();
+    assert_eq!(
+        VoronoiSnapping::Round,
+        VoronoiSnapping::from_config(&config)?
+    );
+    let _ = config.insert("VORONOI_SNAPPING".to_string(), "GRID".to_string());
+    // the grid size is mandatory
+    assert!(VoronoiSnapping::from_config(&config).is_err());
+    let _ = config.insert("VORONOI_SNAP_GRID".to_string(), "0.25".to_string());
+    let snapping = VoronoiSnapping::from_config(&config)?;
+    assert_eq!(VoronoiSnapping::Grid(0.25), snapping);
+
+    // 100 integer units per model unit, the grid is 25 integer units
+    let mapping = snapping.integer_mapping(100.0);
+    assert_eq!(25, mapping.to_integer(12.6));
+    assert_eq!(-25, mapping.to_integer(-13.0));
+    assert_eq!(0.25, mapping.resolution());
+
+    let mapping = VoronoiSnapping::Floor.integer_mapping(100.0);
+    assert_eq!(-2, mapping.to_integer(-1.5));
+    assert_eq!(1, mapping.to_integer(1.9));
+    assert_eq!(0.01, mapping.resolution());
+    let mapping = VoronoiSnapping::Round.integer_mapping(100.0);
+    assert_eq!(-2, mapping.to_integer(-1.5));
+    assert_eq!(2, mapping.to_integer(1.5));
+
+    // a grid finer than the integer units is widened to one unit
+    assert_eq!(
+        1.0,
+        VoronoiSnapping::Grid(0.001).integer_mapping(100.0).step
+    );
+
+    let _ = config.insert("VORONOI_SNAPPING".to_string(), "CEIL".to_string());
+    assert!(VoronoiSnapping::from_config(&config).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_closest_point_on_triangle() {
+    let t = [
+        dvec3(0.0, 0.0, 0.0),
+        dvec3(1.0, 0.0, 0.0),
+        dvec3(0.0, 1.0, 0.0),
+    ];
+    let closest = |p: DVec3| bvh::closest_point_on_triangle(p, &t);
+    assert_eq!(dvec3(0.25, 0.25, 0.0), closest(dvec3(0.25, 0.25, 1.0)));
+    assert_eq!(dvec3(0.0, 0.0, 0.0), closest(dvec3(-1.0, -1.0, 0.0)));
+    assert_eq!(dvec3(0.5, 0.0, 0.0), closest(dvec3(0.5, -2.0, 0.0)));
+    assert_eq!(dvec3(0.5, 0.5, 0.0), closest(dvec3(1.0, 1.0, 0.0)));
+}
+
+#[test]
+fn test_bvh_distance() {
+    // a row of triangles, enough to create several BVH levels
+    let triangles: Vec<[DVec3; 3]> = (0..100)
+        .map(|i| {
+            let x = i as f64;
+            [
+                dvec3(x, 0.0, 0.0),
+                dvec3(x + 1.0, 0.0, 0.0),
+                dvec3(x, 1.0, 0.0),
+            ]
+        })
+        .collect();
+    let bvh = bvh::Bvh::new(triangles);
+    assert!((bvh.distance(dvec3(50.2, 0.2, 3.0)).unwrap() - 3.0).abs() < 1e-9);
+    assert!((bvh.distance(dvec3(-2.0, 0.0, 0.0)).unwrap() - 2.0).abs() < 1e-9);
+    assert!((bvh.distance(dvec3(102.0, 0.0, 0.0)).unwrap() - 2.0).abs() < 1e-9);
+    let (closest, id) = bvh.closest_id(dvec3(50.2, 0.2, 3.0)).unwrap();
+    assert_eq!(50, id);
+    assert!(closest.distance(dvec3(50.2, 0.2, 0.0)) < 1e-9);
+    assert!(bvh::Bvh::new(Vec::default())
+        .distance(DVec3::ZERO)
+        .is_none());
+}
+
+#[test]
+fn test_bvh_overlapping() {
+    let triangles: Vec<[DVec3; 3]> = (0..100)
+        .map(|i| {
+            let x = i as f64;
+            [
+                dvec3(x, 0.0, 0.0),
+                dvec3(x + 0.5, 0.0, 0.0),
+                dvec3(x, 1.0, 0.0),
+            ]
+        })
+        .collect();
+    let bvh = bvh::Bvh::new(triangles);
+    let mut overlapping = bvh.overlapping(dvec3(10.2, 0.5, -1.0), dvec3(12.7, 0.6, 1.0));
+    overlapping.sort_unstable();
+    // the original indices are returned
+    assert_eq!(vec![10, 11, 12], overlapping);
+    assert!(bvh
+        .overlapping(dvec3(10.6, 0.0, 0.0), dvec3(10.9, 1.0, 0.0))
+        .is_empty());
+}
+
+#[test]
+fn test_bvh_raycast() {
+    let triangles: Vec<[DVec3; 3]> = (0..10)
+        .map(|i| {
+            let z = i as f64;
+            [dvec3(0.0, 0.0, z), dvec3(1.0, 0.0, z), dvec3(0.0, 1.0, z)]
+        })
+        .collect();
+    let bvh = bvh::Bvh::new(triangles);
+    // the closest hit is returned
+    let hit = bvh.raycast(dvec3(0.25, 0.25, 4.5), DVec3::NEG_Z, f64::MAX);
+    assert!((hit.unwrap() - 0.5).abs() < 1e-9, "{:?}", hit);
+    let hit = bvh.raycast(dvec3(0.25, 0.25, -2.0), DVec3::Z, f64::MAX);
+    assert!((hit.unwrap() - 2.0).abs() < 1e-9, "{:?}", hit);
+    assert!(bvh
+        .raycast(dvec3(0.25, 0.25, -2.0), DVec3::Z, 1.5)
+        .is_none());
+    assert!(bvh
+        .raycast(dvec3(0.75, 0.75, 20.0), DVec3::NEG_Z, f64::MAX)
+        .is_none());
+    // parallel to the triangles
+    assert!(bvh
+        .raycast(dvec3(-1.0, 0.25, 3.0), DVec3::X, f64::MAX)
+        .is_none());
+}
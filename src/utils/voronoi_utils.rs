@@ -3,18 +3,237 @@
 // This file is part of the hallr crate.
 
 use super::{GrowingVob, HallrError, VertexDeduplicator3D};
-use crate::ffi::FFIVector3;
+use crate::{
+    command::{ConfigType, Options},
+    ffi::FFIVector3,
+};
+use ahash::AHashSet;
 use boostvoronoi as BV;
 use centerline::{HasMatrix4, Matrix4};
 use hronn::prelude::ConvertTo;
 use itertools::Itertools;
 use linestring::linestring_2d::VoronoiParabolicArc;
-use std::collections::VecDeque;
+use std::{collections::VecDeque, ops::AddAssign};
 use vector_traits::{
-    num_traits::{AsPrimitive, Float},
-    GenericScalar, GenericVector2, GenericVector3, HasXY,
+    num_traits::{AsPrimitive, Float, ToPrimitive},
+    GenericScalar, GenericVector2, GenericVector3, HasXY, HasXYZ,
 };
 
+/// The number of degenerate input primitives removed by `remove_degenerate_input()`
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DegenerateInputCount {
+    pub(crate) duplicated_points: usize,
+    pub(crate) zero_length_segments: usize,
+    pub(crate) duplicated_segments: usize,
+}
+
+impl DegenerateInputCount {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.duplicated_points == 0
+            && self.zero_length_segments == 0
+            && self.duplicated_segments == 0
+    }
+
+    /// Print the counts as a warning and add them to the `WARNING` key of the return config
+    pub(crate) fn report(&self, command_name: &str, return_config: &mut ConfigType) {
+        if self.is_empty() {
+            return;
+        }
+        let warning = format!(
+            "{}: ignored {} duplicated points, {} zero length segments and {} duplicated segments",
+            command_name,
+            self.duplicated_points,
+            self.zero_length_segments,
+            self.duplicated_segments
+        );
+        warn!("Warning: {}", warning);
+        let _ = return_config.insert("WARNING".to_string(), warning);
+    }
+}
+
+impl AddAssign for DegenerateInputCount {
+    fn add_assign(&mut self, rhs: Self) {
+        self.duplicated_points += rhs.duplicated_points;
+        self.zero_length_segments += rhs.zero_length_segments;
+        self.duplicated_segments += rhs.duplicated_segments;
+    }
+}
+
+/// How the transformed input coordinates are mapped to the integer coordinates of boost voronoi,
+/// parsed from `VORONOI_SNAPPING`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum VoronoiSnapping {
+    /// Round towards negative infinity
+    Floor,
+    /// Round to the nearest integer, halfway cases away from zero
+    Round,
+    /// Round to the nearest multiple of a grid size, in model units
+    Grid(f64),
+}
+
+impl VoronoiSnapping {
+    /// Parse `VORONOI_SNAPPING` (`ROUND` by default, `FLOOR` or `GRID`) and `VORONOI_SNAP_GRID`,
+    /// the grid size in model units required by `GRID`
+    pub(crate) fn from_config(config: &ConfigType) -> Result<Self, HallrError> {
+        match config
+            .get_mandatory_parsed_option::<String>("VORONOI_SNAPPING", Some("ROUND".to_string()))?
+            .as_str()
+        {
+            "FLOOR" => Ok(Self::Floor),
+            "ROUND" => Ok(Self::Round),
+            "GRID" => {
+                let grid = config.get_mandatory_parsed_option::<f64>("VORONOI_SNAP_GRID", None)?;
+                if !grid.is_finite() || grid <= 0.0 {
+                    return Err(HallrError::InvalidParameter(format!(
+                        "VORONOI_SNAP_GRID must be a positive number :({})",
+                        grid
+                    )));
+                }
+                Ok(Self::Grid(grid))
+            }
+            snapping => Err(HallrError::InvalidParameter(format!(
+                "{} is not a valid \"VORONOI_SNAPPING\" parameter",
+                snapping
+            ))),
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Floor => "FLOOR",
+            Self::Round => "ROUND",
+            Self::Grid(_) => "GRID",
+        }
+    }
+
+    /// The integer mapping of a transform that scales the model units by `scale`. A grid smaller
+    /// than one integer unit is widened to one unit.
+    pub(crate) fn integer_mapping(self, scale: f64) -> IntegerMapping {
+        let step = match self {
+            Self::Grid(grid) => (grid * scale).round().max(1.0),
+            _ => 1.0,
+        };
+        IntegerMapping {
+            snapping: self,
+            step,
+            scale,
+        }
+    }
+}
+
+/// Maps the transformed coordinates to integers, the same way in every voronoi command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct IntegerMapping {
+    pub(crate) snapping: VoronoiSnapping,
+    /// The distance between the snapped values, in integer units
+    pub(crate) step: f64,
+    /// Integer units per model unit
+    pub(crate) scale: f64,
+}
+
+impl IntegerMapping {
+    /// The mapping of the uniformly scaling `transform`
+    pub(crate) fn new<T: GenericVector3 + HasMatrix4>(
+        snapping: VoronoiSnapping,
+        transform: &T::Matrix4Type,
+    ) -> Self {
+        let zero: T::Scalar = 0.0.into();
+        let one: T::Scalar = 1.0.into();
+        let origin = transform.transform_point3(T::new_3d(zero, zero, zero));
+        let unit = transform.transform_point3(T::new_3d(one, zero, zero)) - origin;
+        let scale = (unit.x() * unit.x() + unit.y() * unit.y() + unit.z() * unit.z()).sqrt();
+        snapping.integer_mapping(scale.to_f64().unwrap_or(1.0))
+    }
+
+    /// The snapped value, it is always an integer
+    pub(crate) fn snap(&self, value: f64) -> f64 {
+        match self.snapping {
+            VoronoiSnapping::Floor => value.floor(),
+            VoronoiSnapping::Round => value.round(),
+            VoronoiSnapping::Grid(_) => (value / self.step).round() * self.step,
+        }
+    }
+
+    pub(crate) fn to_integer(&self, value: f64) -> i64 {
+        self.snap(value) as i64
+    }
+
+    /// The transformed 2d point as a boost voronoi point
+    pub(crate) fn point<V: GenericVector2>(&self, p: V) -> BV::Point<i64> {
+        BV::Point {
+            x: self.to_integer(p.x().to_f64().unwrap_or_default()),
+            y: self.to_integer(p.y().to_f64().unwrap_or_default()),
+        }
+    }
+
+    /// The distance between the snapped coordinates, in model units
+    pub(crate) fn resolution(&self) -> f64 {
+        self.step / self.scale
+    }
+
+    /// Add `VORONOI_SNAPPING` and the effective `VORONOI_GRID_RESOLUTION` to the return config
+    pub(crate) fn report(&self, return_config: &mut ConfigType) {
+        let _ = return_config.insert(
+            "VORONOI_SNAPPING".to_string(),
+            self.snapping.as_str().to_string(),
+        );
+        let _ = return_config.insert(
+            "VORONOI_GRID_RESOLUTION".to_string(),
+            self.resolution().to_string(),
+        );
+    }
+}
+
+/// Removes the input that boost voronoi can't handle: duplicated points, points on top of a
+/// segment end point, zero length segments and duplicated segments (in any direction).
+/// The comparisons are done on the integer coordinates, i.e. after the voronoi transformation.
+pub(crate) fn remove_degenerate_input(
+    points: Vec<BV::Point<i64>>,
+    segments: Vec<BV::Line<i64>>,
+) -> (
+    Vec<BV::Point<i64>>,
+    Vec<BV::Line<i64>>,
+    DegenerateInputCount,
+) {
+    let mut count = DegenerateInputCount::default();
+    let mut segment_set = AHashSet::<((i64, i64), (i64, i64))>::with_capacity(segments.len());
+    let mut end_points = AHashSet::<(i64, i64)>::with_capacity(segments.len() * 2);
+    let segments: Vec<BV::Line<i64>> = segments
+        .into_iter()
+        .filter(|s| {
+            let (start, end) = ((s.start.x, s.start.y), (s.end.x, s.end.y));
+            if start == end {
+                count.zero_length_segments += 1;
+                return false;
+            }
+            let key = if start < end {
+                (start, end)
+            } else {
+                (end, start)
+            };
+            if !segment_set.insert(key) {
+                count.duplicated_segments += 1;
+                return false;
+            }
+            let _ = end_points.insert(start);
+            let _ = end_points.insert(end);
+            true
+        })
+        .collect();
+    let points: Vec<BV::Point<i64>> = points
+        .into_iter()
+        .filter(|p| {
+            if end_points.insert((p.x, p.y)) {
+                true
+            } else {
+                count.duplicated_points += 1;
+                false
+            }
+        })
+        .collect();
+    (points, segments, count)
+}
+
 /// Mark infinite edges and their adjacent edges as EXTERNAL.
 pub(crate) fn reject_external_edges<T: GenericVector3>(
     diagram: &BV::Diagram<T::Scalar>,
@@ -180,7 +399,7 @@ where
 {
     /// transform the voronoi Point into a PB point. Perform duplication checks
     #[inline(always)]
-    fn place_new_vertex_dup_check(&mut self, vertex: T) -> Result<usize, HallrError> {
+    pub(crate) fn place_new_vertex_dup_check(&mut self, vertex: T) -> Result<usize, HallrError> {
         let rv = self.vertex_map.get_index_or_insert(vertex)? as usize;
         Ok(rv)
     }
@@ -556,13 +775,15 @@ where
         Ok(None)
     }
 
-    /// Iterate over each cell, generate mesh
+    /// Iterate over each cell, generate mesh. The id of the cell of every triangle is returned
+    /// in a third vector.
     pub(crate) fn generate_mesh_from_cells(
         &self,
         mut dhrw: DiagramHelperRw<T>,
         edge_map: ahash::AHashMap<usize, Vec<usize>>,
-    ) -> Result<(Vec<usize>, Vec<T>), HallrError> {
+    ) -> Result<(Vec<usize>, Vec<T>, Vec<usize>), HallrError> {
         let mut return_indices = Vec::<usize>::new();
+        let mut cell_ids = Vec::<usize>::new();
 
         for cell in self.diagram.cells().iter() {
             let cell = cell.get();
@@ -678,6 +899,7 @@ where
                     triangulate_face(&mut return_indices, &dhrw.vertex_map.vertices, &new_face)?;
                 }
             }
+            cell_ids.resize(return_indices.len() / 3, cell_id.0);
         }
         //println!("indices:{:?}", return_indices);
         //println!("vertices:{:?}", dhrw.vertex_map.vertices);
@@ -687,7 +909,7 @@ where
             .into_iter()
             .map(|v| self.inverted_transform.transform_point3(v))
             .collect();
-        Ok((return_indices, vertices))
+        Ok((return_indices, vertices, cell_ids))
     }
 
     /// Iterate over each cell, generate edges in "chunk" format